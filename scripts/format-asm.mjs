@@ -63,6 +63,7 @@ const DIRECTIVES = new Set([
   "asciz",
   "ascii",
   "fail",
+  "assert",
   "opt",
   "end",
   "litstr",
@@ -225,6 +225,7 @@ ready:
 
 ; Enable break interrupt for serial loader
                  move.b     #%00000100,IER(a1)
+                 move.b     #(1<<INTC_UART),INTC+INTC_ENABLE                    ; Unmask the UART at the controller
                  move.w     #$2000,sr                                           ; Enable interrupts
 
 ; Try to mount filesystem
@@ -3,14 +3,100 @@
 //! A complete M68000 assembler with Motorola syntax support. This module provides:
 //! - Full M68000 instruction set encoding
 //! - Macro processor with REPT, IF/ELSE/ENDIF, and parameterized macros
-//! - Expression evaluator for complex constant expressions
+//! - Expression evaluator for complex constant expressions, with `LO`/`HI`
+//!   (bytes of the low word), `LOWORD`/`HIWORD`, `STRLEN("...")` and
+//!   character constants of up to 4 characters (`'AB'` is $4142)
+//! - Numbers in decimal, hex (`$1F` or `0x1F`) and binary (`%1010` or
+//!   `0b1010`), with `_` between digits as a separator (`%1010_0101`)
 //! - Include file handling
 //! - Two-pass assembly for forward reference resolution
 //!
 //! The assembler produces raw binary output suitable for direct execution
 //! on the Flux32 emulator or real M68K hardware.
-
-use std::collections::HashMap;
+//!
+//! A statement continues on the next line when its line ends in `\`, as in
+//! a long `DC.B` table or a macro call with many arguments. Errors still
+//! point at the line and column they're on. A comment runs to the end of
+//! its line, so a `\` in one doesn't continue the line, and a comment after
+//! the `\` is an error.
+//!
+//! An `Assembler` with `incremental` set keeps what it can between calls
+//! for an editor to assemble on every change: the expansion of the source
+//! and its includes, the tokens of unchanged lines and the sizes and
+//! symbols pass 1 settled on. An edit to plain lines is patched into the
+//! expansion rather than preprocessed again, and one that only changes
+//! what instructions encode, not their sizes, skips pass 1. The output is always what a fresh assembler would produce;
+//! `timings` tells what was reused and how long each stage took.
+//!
+//! `ORG` sets the address the following code is assembled for. Without one,
+//! code is assembled from 0 and the loader places it at the app load
+//! address. A file may hold several ORG sections: the flat binary pads the
+//! gaps between them with zeros, so each ORG must stay at or above the code
+//! before it, while `assemble_chunks` returns each section separately with
+//! its origin and lets them go in any order.
+//!
+//! `SECTION name[,type]` switches to a section with its own location
+//! counter; code goes in `text` until the first one. A type is `code`,
+//! `data` or `bss` (a section named after one has it). Code sections come
+//! first, then data, then BSS, each word aligned after the one before.
+//! BSS only reserves space with `DS`: it takes none in the flat binary,
+//! and the loader clears it (see `bss_space` and `assemble_sections`).
+//!
+//! `ROMSIZE size[,fill[,CHECKSUM]]` (or the `rom` option) pads the flat
+//! binary to an EPROM image, optionally ending in a 16-bit checksum of the
+//! bytes before it; a program that doesn't fit is an error.
+//!
+//! Instructions and word or long `DS` space must start on even addresses;
+//! `EVEN` or `ALIGN n[,fill]` pads after odd-length data. Word or long `DC`
+//! data, a branch, JMP or JSR to an odd address and an odd `ORG` in a code
+//! section get a warning, since the 68000 faults on them when the code
+//! runs, or an error with `strict_alignment`.
+//!
+//! A branch without a size suffix is short when its target is in reach and
+//! a word otherwise; `.s` and `.w` force the form, and a target out of the
+//! form's reach is an error. Likewise an absolute address without `.W` or
+//! `.L` is a word when it's in $0000-$7FFF or $FFFF8000-$FFFFFFFF, which
+//! the CPU sign-extends, and a long otherwise.
+//!
+//! With `optimize` set, `MOVE.L #n,Dn` becomes `MOVEQ` and `ADD`/`SUB #1-8`
+//! to a register `ADDQ`/`SUBQ` when the value fits; mnemonics written out,
+//! like `ADDI`, are left alone.
+//!
+//! `OPT` sets options from the line after it: `O+`/`O-` quick forms,
+//! `W+`/`W-` warnings, `L+`/`L-` the listing, `C+`/`C-` case-sensitive
+//! symbols, and `NOWARN=n[-m]` turns off warnings by number (`WARN_DIRECTIVE`,
+//! `WARN_CASE`, `WARN_OPTION`, `WARN_ALIGN`). `OPT PUSH` saves them and `OPT POP` brings
+//! them back. An option it doesn't know is a warning.
+//!
+//! `ASSERT expr[,"message"]` is an error when the expression is zero, as in
+//! `ASSERT table_end-table<=256`. It's checked in pass 2, so labels after
+//! it count, and the error gives the values of the symbols it uses. `FAIL
+//! "message"` is an error wherever it's reached, as in an IF block.
+//!
+//! `assemble_object` makes a relocatable object for the linker instead (see
+//! the `object` module). Each section starts at 0 and BSS is zeros;
+//! `XREF` names symbols other objects define and `XDEF` the ones this one
+//! exports. Addresses of labels and imports must
+//! be longs, or PC-relative words.
+//!
+//! `assemble_listing` lists each source line with its address and bytes.
+//! A macro call or REPT shows all its bytes on one line, or with
+//! `list_expansions` the lines it expands to follow it, marked `+depth`;
+//! a REPT lists its first `LISTED_REPT_ITERATIONS` iterations and
+//! collapses the rest.
+//!
+//! Symbols are case-sensitive, and two that differ only in case get a
+//! warning; with `ignore_case` they're folded to lower case. Mnemonics,
+//! registers, directives and size suffixes are case-insensitive either way.
+//!
+//! `SP` and `SSP` name A7 and `FP` names A6 (`USP` stays the user stack
+//! pointer of `MOVE USP`). `name EQUR Rn` names a register for the lines
+//! after it, anywhere a register can go, but it has no value.
+
+use crate::debugger::similar_names;
+use crate::object::{Export, ObjectFile, ObjectSection, RelocKind, RelocTarget, Relocation};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
 // ============================================================================
@@ -106,6 +192,97 @@ pub enum Token {
 pub struct LocatedToken {
     pub token: Token,
     pub loc: SourceLoc,
+    /// Length of the token's text in columns.
+    pub len: usize,
+}
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Where an INCLUDE was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IncludeOrigin {
+    /// A file the caller passed in memory (see `Assembler::virtual_files`)
+    Virtual,
+    /// A file on disk
+    Disk,
+}
+
+/// An INCLUDE the preprocessor resolved.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ResolvedInclude {
+    /// File containing the INCLUDE line.
+    pub from: String,
+    /// File name as written on the INCLUDE line.
+    pub name: String,
+    /// Virtual file name or disk path the include was read from.
+    pub path: String,
+    /// Whether it was read from memory or from disk.
+    pub origin: IncludeOrigin,
+}
+
+/// A line of an assembly listing, before the code is filled in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    /// Source text, as written or as a macro expanded it.
+    pub text: String,
+    /// How many macro calls and REPT blocks the line is inside.
+    pub depth: usize,
+    /// Preprocessed lines (0-based) the line became: one for an ordinary
+    /// line, the whole expansion for a macro call or REPT.
+    pub lines: std::ops::Range<usize>,
+}
+
+/// An error or warning about a span of the source.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// Length of the span in columns.
+    pub length: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Creates an error about `length` columns starting at `loc`.
+    pub fn error(loc: &SourceLoc, length: usize, message: impl Into<String>) -> Self {
+        Self::new(loc, length, Severity::Error, message.into())
+    }
+
+    /// Creates a warning about `length` columns starting at `loc`.
+    pub fn warning(loc: &SourceLoc, length: usize, message: impl Into<String>) -> Self {
+        Self::new(loc, length, Severity::Warning, message.into())
+    }
+
+    fn new(loc: &SourceLoc, length: usize, severity: Severity, message: String) -> Self {
+        Self {
+            file: loc.file.clone(),
+            line: loc.line,
+            column: loc.column,
+            length,
+            severity,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "{}:{}: {}", self.file, self.line, self.message),
+            Severity::Warning => {
+                write!(f, "{}:{}: warning: {}", self.file, self.line, self.message)
+            }
+        }
+    }
 }
 
 // ============================================================================
@@ -113,69 +290,92 @@ pub struct LocatedToken {
 // ============================================================================
 
 /// Lexer for M68K assembly source code.
+///
+/// Columns count characters, not bytes, so a tab is one column and so is
+/// each UTF-8 character in a string or comment.
 pub struct Lexer<'a> {
-    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
     file: String,
     line: usize,
-    line_start: usize,
-    current_pos: usize,
+    /// Column of the next character to be read (1-based).
+    column: usize,
+    /// Characters read so far.
+    read: usize,
+    /// Where the token being read starts.
+    start: SourceLoc,
 }
 
 impl<'a> Lexer<'a> {
     /// Creates a new lexer for the given source code.
     pub fn new(source: &'a str, file: impl Into<String>) -> Self {
         Self {
-            chars: source.char_indices().peekable(),
+            chars: source.chars().peekable(),
             file: file.into(),
             line: 1,
-            line_start: 0,
-            current_pos: 0,
+            column: 1,
+            read: 0,
+            start: SourceLoc::default(),
         }
     }
 
     /// Returns the current source location (of the next character to be read).
     fn loc(&self) -> SourceLoc {
-        // Get the position of the next character to be read
-        let next_pos = self
-            .chars
-            .clone()
-            .next()
-            .map_or(self.current_pos + 1, |(p, _)| p);
         SourceLoc {
             file: self.file.clone(),
             line: self.line,
-            column: next_pos.saturating_sub(self.line_start) + 1,
+            column: self.column,
         }
     }
 
+    /// Returns where the last token read starts, which is where the error
+    /// is when reading it failed.
+    pub const fn location(&self) -> &SourceLoc {
+        &self.start
+    }
+
     /// Peeks at the next character without consuming it.
     fn peek_char(&mut self) -> Option<char> {
-        self.chars.peek().map(|(_, c)| *c)
+        self.chars.peek().copied()
     }
 
     /// Consumes and returns the next character.
     fn next_char(&mut self) -> Option<char> {
-        if let Some((pos, c)) = self.chars.next() {
-            self.current_pos = pos;
-            if c == '\n' {
-                self.line += 1;
-                self.line_start = pos + 1;
-            }
-            Some(c)
+        let c = self.chars.next()?;
+        self.read += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
         } else {
-            None
+            self.column += 1;
         }
+        Some(c)
     }
 
-    /// Skips whitespace (but not newlines).
-    fn skip_whitespace(&mut self) {
+    /// Skips whitespace (but not newlines) and line continuations,
+    /// returning true if it went on to another line.
+    fn skip_whitespace(&mut self) -> bool {
+        let mut continued = false;
         while let Some(c) = self.peek_char() {
             if c == ' ' || c == '\t' || c == '\r' {
                 self.next_char();
+            } else if c == '\\' && self.continues() {
+                while self.next_char().is_some_and(|c| c != '\n') {}
+                continued = true;
             } else {
                 break;
             }
         }
+        continued
+    }
+
+    /// Returns true if the next character, a backslash, is a line
+    /// continuation: only spaces follow it on the line.
+    fn continues(&self) -> bool {
+        self.chars
+            .clone()
+            .skip(1)
+            .find(|c| !matches!(c, ' ' | '\t' | '\r'))
+            .is_none_or(|c| c == '\n')
     }
 
     /// Skips a comment (from ; or * to end of line).
@@ -203,60 +403,98 @@ fn read_ident(&mut self, first: char) -> String {
         s
     }
 
-    /// Reads a decimal number.
+    /// Reads digits for which `is_digit` holds onto `digits`, with any `_`
+    /// among them. Returns false if a `_` isn't a separator between two
+    /// digits.
+    fn read_digits(&mut self, digits: &mut String, is_digit: impl Fn(char) -> bool) -> bool {
+        while let Some(c) = self.peek_char().filter(|&c| is_digit(c) || c == '_') {
+            digits.push(c);
+            self.next_char();
+        }
+        !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__")
+    }
+
+    /// Reads a decimal number, or a hex or binary one written `0x1F` or
+    /// `0b1010`.
     fn read_decimal(&mut self, first: char) -> Result<i64, String> {
-        let mut s = String::new();
-        s.push(first);
-        while let Some(c) = self.peek_char() {
-            if c.is_ascii_digit() {
-                s.push(c);
-                self.next_char();
-            } else {
-                break;
+        if first == '0' {
+            match self.peek_char() {
+                Some(prefix @ ('x' | 'X')) => {
+                    self.next_char();
+                    return self.read_prefixed(prefix, 16);
+                }
+                Some(prefix @ ('b' | 'B')) => {
+                    self.next_char();
+                    return self.read_prefixed(prefix, 2);
+                }
+                _ => {}
             }
         }
-        s.parse::<i64>()
-            .map_err(|_| format!("invalid decimal number: {s}"))
+        let mut s = first.to_string();
+        let separated = self.read_digits(&mut s, |c| c.is_ascii_digit());
+        let error = || format!("invalid decimal number: {s}");
+        if !separated {
+            return Err(error());
+        }
+        s.replace('_', "").parse::<i64>().map_err(|_| error())
+    }
+
+    /// Reads the digits of a number after its `0x` or `0b` prefix, which
+    /// must run to the end of the word.
+    fn read_prefixed(&mut self, prefix: char, radix: u32) -> Result<i64, String> {
+        let mut s = String::new();
+        let separated = self.read_digits(&mut s, |c| c.is_digit(radix));
+        let base = if radix == 16 { "hex" } else { "binary" };
+        while let Some(c) = self
+            .peek_char()
+            .filter(|&c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            s.push(c);
+            self.next_char();
+        }
+        let error = || format!("invalid {base} number: 0{prefix}{s}");
+        if !separated {
+            return Err(error());
+        }
+        i64::from_str_radix(&s.replace('_', ""), radix).map_err(|_| error())
     }
 
     /// Reads a hexadecimal number (after $ prefix).
     fn read_hex(&mut self) -> Result<i64, String> {
         let mut s = String::new();
-        while let Some(c) = self.peek_char() {
-            if c.is_ascii_hexdigit() {
-                s.push(c);
-                self.next_char();
-            } else {
-                break;
-            }
-        }
+        let separated = self.read_digits(&mut s, |c| c.is_ascii_hexdigit());
         if s.is_empty() {
             return Err("expected hexadecimal digits after $".to_string());
         }
-        i64::from_str_radix(&s, 16).map_err(|_| format!("invalid hex number: ${s}"))
+        let error = || format!("invalid hex number: ${s}");
+        if !separated {
+            return Err(error());
+        }
+        i64::from_str_radix(&s.replace('_', ""), 16).map_err(|_| error())
     }
 
     /// Reads a binary number (after % prefix).
     fn read_binary(&mut self) -> Result<i64, String> {
         let mut s = String::new();
-        while let Some(c) = self.peek_char() {
-            if c == '0' || c == '1' {
-                s.push(c);
-                self.next_char();
-            } else {
-                break;
-            }
-        }
+        let separated = self.read_digits(&mut s, |c| c == '0' || c == '1');
         if s.is_empty() {
             return Err("expected binary digits after %".to_string());
         }
-        i64::from_str_radix(&s, 2).map_err(|_| format!("invalid binary number: %{s}"))
+        let error = || format!("invalid binary number: %{s}");
+        if !separated {
+            return Err(error());
+        }
+        i64::from_str_radix(&s.replace('_', ""), 2).map_err(|_| error())
     }
 
     /// Reads a string literal (after opening quote).
     fn read_string(&mut self, quote: char) -> Result<String, String> {
         let mut s = String::new();
         loop {
+            // Leave the newline, so the next line still lexes
+            if matches!(self.peek_char(), None | Some('\n')) {
+                return Err("unterminated string literal".to_string());
+            }
             match self.next_char() {
                 None => return Err("unterminated string literal".to_string()),
                 Some(c) if c == quote => break,
@@ -281,17 +519,23 @@ fn read_string(&mut self, quote: char) -> Result<String, String> {
 
     /// Reads the next token from the source.
     pub fn next_token(&mut self) -> Result<LocatedToken, String> {
-        self.skip_whitespace();
-
-        let loc = self.loc();
-        let c = match self.next_char() {
-            None => {
-                return Ok(LocatedToken {
-                    token: Token::Eof,
-                    loc,
-                })
-            }
-            Some(c) => c,
+        let continued = self.skip_whitespace();
+
+        self.start = self.loc();
+        let read = self.read;
+        let token = self.read_token(self.start.column == 1 && !continued)?;
+        Ok(LocatedToken {
+            token,
+            loc: self.start.clone(),
+            len: self.read - read,
+        })
+    }
+
+    /// Reads the next token, which starts a line if `line_start` (rather
+    /// than continuing one).
+    fn read_token(&mut self, line_start: bool) -> Result<Token, String> {
+        let Some(c) = self.next_char() else {
+            return Ok(Token::Eof);
         };
 
         let token = match c {
@@ -313,16 +557,10 @@ pub fn next_token(&mut self) -> Result<LocatedToken, String> {
                         let lower = rest.to_ascii_lowercase();
                         if lower == "b" || lower == "w" || lower == "l" || lower == "s" {
                             // Return it as .X identifier - parser will decide
-                            return Ok(LocatedToken {
-                                token: Token::Ident(format!(".{rest}")),
-                                loc,
-                            });
+                            return Ok(Token::Ident(format!(".{rest}")));
                         }
                         // Multi-character or starts with digit: local label
-                        return Ok(LocatedToken {
-                            token: Token::Ident(format!(".{rest}")),
-                            loc,
-                        });
+                        return Ok(Token::Ident(format!(".{rest}")));
                     }
                 }
                 Token::Dot
@@ -333,7 +571,7 @@ pub fn next_token(&mut self) -> Result<LocatedToken, String> {
             '-' => Token::Minus,
             '*' => {
                 // Could be comment at start of line or multiply
-                if loc.column == 1 {
+                if line_start {
                     self.skip_comment();
                     Token::Newline
                 } else {
@@ -381,7 +619,13 @@ pub fn next_token(&mut self) -> Result<LocatedToken, String> {
                 let n = self.read_hex()?;
                 Token::Number(n)
             }
-            '\\' => Token::Backslash,
+            '\\' => {
+                let mut rest = self.chars.clone().skip_while(|c| matches!(c, ' ' | '\t'));
+                if rest.next() == Some(';') {
+                    return Err("a comment can't follow a line continuation (\\)".to_string());
+                }
+                Token::Backslash
+            }
             '@' => Token::At,
             ';' => {
                 self.skip_comment();
@@ -411,7 +655,7 @@ pub fn next_token(&mut self) -> Result<LocatedToken, String> {
             c => return Err(format!("unexpected character: '{c}'")),
         };
 
-        Ok(LocatedToken { token, loc })
+        Ok(token)
     }
 
     /// Tokenizes the entire source into a vector of tokens.
@@ -427,6 +671,141 @@ pub fn tokenize(&mut self) -> Result<Vec<LocatedToken>, String> {
         }
         Ok(tokens)
     }
+
+    /// Tokenizes the entire source, skipping the rest of any line that
+    /// fails to lex. Returns the tokens and an error for each such line.
+    pub fn tokenize_recovering(&mut self) -> (Vec<LocatedToken>, Vec<Diagnostic>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            match self.next_token() {
+                Ok(tok) => {
+                    let is_eof = tok.token == Token::Eof;
+                    tokens.push(tok);
+                    if is_eof {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    errors.push(Diagnostic::error(&self.start, 1, e));
+                    self.skip_comment();
+                }
+            }
+        }
+        (tokens, errors)
+    }
+}
+
+/// The tokens of the source the last assembly lexed, so an incremental
+/// assembly only lexes the lines that changed: those between the lines at
+/// the start and at the end that are the same as last time, widened to
+/// whole statements where lines end in a continuation.
+#[derive(Default)]
+pub struct LexCache {
+    text: String,
+    /// Tokens of `text`, while `keep` has given them back.
+    tokens: Vec<LocatedToken>,
+    errors: Vec<Diagnostic>,
+    /// Lines (1-based) lexed again by the last assembly, when it had as
+    /// many lines as the one before.
+    changed: Option<std::ops::Range<usize>>,
+    /// Lines reused by the last assembly.
+    hits: usize,
+}
+
+impl LexCache {
+    /// Tokenizes `source` like `Lexer::tokenize_recovering`, lexing only the
+    /// lines that changed since the last call. The tokens are the caller's
+    /// until `keep` gives them back.
+    fn tokenize(&mut self, source: &str, file: &str) -> (Vec<LocatedToken>, Vec<Diagnostic>) {
+        let old_text = std::mem::replace(&mut self.text, source.to_string());
+        let mut tokens = std::mem::take(&mut self.tokens);
+        let mut errors = std::mem::take(&mut self.errors);
+        self.changed = None;
+        self.hits = 0;
+        if tokens.is_empty() {
+            let (tokens, errors) = Lexer::new(source, file).tokenize_recovering();
+            self.errors.clone_from(&errors);
+            return (tokens, errors);
+        }
+        let old: Vec<&str> = old_text.split_inclusive('\n').collect();
+        let new: Vec<&str> = source.split_inclusive('\n').collect();
+        let continues = |line: &str| line.trim_end().ends_with('\\');
+        let mut prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        while prefix > 0 && continues(new[prefix - 1]) {
+            prefix -= 1;
+        }
+        let most = old.len().min(new.len()) - prefix;
+        let mut suffix = old
+            .iter()
+            .rev()
+            .zip(new.iter().rev())
+            .take(most)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let continued = |lines: &[&str], suffix: usize| {
+            let first = lines.len() - suffix;
+            first > 0 && continues(lines[first - 1])
+        };
+        while suffix > 0 && (continued(&old, suffix) || continued(&new, suffix)) {
+            suffix -= 1;
+        }
+
+        // Lex the lines between, then move the ones after to where they are
+        let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+        let middle: String = new[prefix..new_end].concat();
+        let (mut lexed, mut lexed_errors) = Lexer::new(&middle, file).tokenize_recovering();
+        if suffix > 0 {
+            // The source's end of file token is the last assembly's
+            lexed.pop();
+        }
+        for token in &mut lexed {
+            token.loc.line += prefix;
+        }
+        for error in &mut lexed_errors {
+            error.line += prefix;
+        }
+        let moved = |line: usize| line + new.len() - old.len();
+        let through = (suffix > 0).then_some(old_end);
+        let range = on_lines(&tokens, |token| token.loc.line, prefix, through);
+        let after = range.start + lexed.len();
+        drop(tokens.splice(range, lexed));
+        for token in &mut tokens[after..] {
+            token.loc.line = moved(token.loc.line);
+        }
+        let range = on_lines(&errors, |error| error.line, prefix, through);
+        let after = range.start + lexed_errors.len();
+        drop(errors.splice(range, lexed_errors));
+        for error in &mut errors[after..] {
+            error.line = moved(error.line);
+        }
+
+        self.changed = (old.len() == new.len()).then_some(prefix + 1..new_end + 1);
+        self.hits = prefix + suffix;
+        self.errors.clone_from(&errors);
+        (tokens, errors)
+    }
+
+    /// Gives back the tokens `tokenize` returned, for the next call to
+    /// start from.
+    fn keep(&mut self, tokens: Vec<LocatedToken>) {
+        self.tokens = tokens;
+    }
+}
+
+/// Returns the range of `items`, which are in line order, on the lines
+/// after `after` through `through`, or to the end without one.
+fn on_lines<T>(
+    items: &[T],
+    line: impl Fn(&T) -> usize,
+    after: usize,
+    through: Option<usize>,
+) -> std::ops::Range<usize> {
+    let start = items.partition_point(|item| line(item) <= after);
+    let end = through.map_or(items.len(), |through| {
+        items.partition_point(|item| line(item) <= through)
+    });
+    start..end
 }
 
 // ============================================================================
@@ -448,6 +827,60 @@ pub enum Expr {
     Not(Box<Self>),
     /// Binary operation
     BinOp(Box<Self>, BinOp, Box<Self>),
+    /// Function call (only parsed for names the parser was given)
+    Call(String, Box<Self>),
+}
+
+impl Expr {
+    /// Returns true if the expression uses any of the symbols.
+    fn mentions(&self, names: &HashSet<String>) -> bool {
+        match self {
+            Self::Symbol(name) => names.contains(name),
+            Self::Neg(e) | Self::Not(e) | Self::Call(_, e) => e.mentions(names),
+            Self::BinOp(l, _, r) => l.mentions(names) || r.mentions(names),
+            Self::Number(_) | Self::CurrentPc => false,
+        }
+    }
+
+    /// Returns the symbol an address is written relative to: `name`,
+    /// `name+n`, `n+name` or `name-n`, where `n` uses no symbols.
+    fn base_symbol(&self) -> Option<&str> {
+        let constant = |e: &Self| !e.mentions_any();
+        match self {
+            Self::Symbol(name) => Some(name),
+            Self::BinOp(l, BinOp::Add | BinOp::Sub, r) if constant(r) => l.base_symbol(),
+            Self::BinOp(l, BinOp::Add, r) if constant(l) => r.base_symbol(),
+            _ => None,
+        }
+    }
+
+    /// Adds the symbols the expression uses to `names`, each once, in the
+    /// order they're written.
+    fn symbol_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Self::Symbol(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            Self::Neg(e) | Self::Not(e) | Self::Call(_, e) => e.symbol_names(names),
+            Self::BinOp(l, _, r) => {
+                l.symbol_names(names);
+                r.symbol_names(names);
+            }
+            Self::Number(_) | Self::CurrentPc => {}
+        }
+    }
+
+    /// Returns true if the expression uses a symbol or `*`.
+    fn mentions_any(&self) -> bool {
+        match self {
+            Self::Symbol(_) | Self::CurrentPc => true,
+            Self::Neg(e) | Self::Not(e) | Self::Call(_, e) => e.mentions_any(),
+            Self::BinOp(l, _, r) => l.mentions_any() || r.mentions_any(),
+            Self::Number(_) => false,
+        }
+    }
 }
 
 /// Binary operators in expressions.
@@ -486,15 +919,47 @@ const fn precedence(self) -> u8 {
     }
 }
 
+/// Functions assembler expressions can call: the low and high byte of the
+/// low word, the low and high word, and a string's length in bytes.
+const BUILTIN_FUNCTIONS: [&str; 5] = ["lo", "hi", "loword", "hiword", "strlen"];
+
 /// Expression parser operating on a token slice.
 pub struct ExprParser<'a> {
     tokens: &'a [LocatedToken],
     pos: usize,
+    /// Names parsed as one-argument function calls when followed by '('
+    functions: &'a [&'a str],
 }
 
 impl<'a> ExprParser<'a> {
+    /// Creates a parser for assembler expressions, which may call the
+    /// built-in functions (`LO`, `HI`, `LOWORD`, `HIWORD`, `STRLEN`)
     pub const fn new(tokens: &'a [LocatedToken]) -> Self {
-        Self { tokens, pos: 0 }
+        Self {
+            tokens,
+            pos: 0,
+            functions: &BUILTIN_FUNCTIONS,
+        }
+    }
+
+    /// Creates a parser that accepts calls to the given functions instead
+    /// of the built-in ones (matched case-insensitively)
+    pub const fn with_functions(tokens: &'a [LocatedToken], functions: &'a [&'a str]) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            functions,
+        }
+    }
+
+    /// Returns the location of the next unparsed token
+    pub fn location(&self) -> SourceLoc {
+        self.current_loc()
+    }
+
+    /// Returns true if every token up to the end of the line was parsed
+    pub fn at_end(&self) -> bool {
+        matches!(self.peek(), None | Some(Token::Newline | Token::Eof))
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -511,6 +976,25 @@ fn advance(&mut self) -> Option<&Token> {
         }
     }
 
+    /// Returns the span of the next unparsed token, or of the column just
+    /// past the last one when they're all parsed
+    pub fn span(&self) -> (SourceLoc, usize) {
+        match (self.tokens.get(self.pos), self.tokens.last()) {
+            (Some(t), _) => (t.loc.clone(), t.len.max(1)),
+            (None, Some(last)) => {
+                let column = last.loc.column + last.len;
+                (
+                    SourceLoc {
+                        column,
+                        ..last.loc.clone()
+                    },
+                    1,
+                )
+            }
+            (None, None) => (SourceLoc::default(), 0),
+        }
+    }
+
     fn current_loc(&self) -> SourceLoc {
         self.tokens
             .get(self.pos)
@@ -586,9 +1070,34 @@ fn parse_primary(&mut self) -> Result<Expr, String> {
                 self.advance();
                 Ok(Expr::Number(c as i64))
             }
+            Some(Token::String(text)) => {
+                self.advance();
+                character_constant(&text).map(Expr::Number)
+            }
             Some(Token::Ident(s)) => {
                 self.advance();
-                Ok(Expr::Symbol(s))
+                let is_call = self.peek() == Some(&Token::LParen)
+                    && self.functions.iter().any(|f| f.eq_ignore_ascii_case(&s));
+                if !is_call {
+                    return Ok(Expr::Symbol(s));
+                }
+                self.advance();
+                let name = s.to_ascii_lowercase();
+                let arg = if name == "strlen" {
+                    let length = match self.advance() {
+                        Some(Token::String(text)) => text.len(),
+                        Some(Token::Char(c)) => c.len_utf8(),
+                        _ => return Err("strlen needs a string".to_string()),
+                    };
+                    Expr::Number(length as i64)
+                } else {
+                    self.parse_expr()?
+                };
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(format!("expected ')' after {s} argument"));
+                }
+                self.advance();
+                Ok(Expr::Call(name, Box::new(arg)))
             }
             Some(Token::Star) => {
                 self.advance();
@@ -603,10 +1112,7 @@ fn parse_primary(&mut self) -> Result<Expr, String> {
                 self.advance();
                 Ok(expr)
             }
-            _ => Err(format!(
-                "unexpected token in expression at {}",
-                self.current_loc()
-            )),
+            _ => Err("unexpected token in expression".to_string()),
         }
     }
 }
@@ -676,6 +1182,17 @@ pub fn eval_expr_scoped(
                 BinOp::Ge => i64::from(lv >= rv),
             })
         }
+        Expr::Call(name, arg) => {
+            let value = eval_expr_scoped(arg, symbols, pc, scope)?;
+            match name.as_str() {
+                "lo" => Ok(value & 0xFF),
+                "hi" => Ok((value >> 8) & 0xFF),
+                "loword" => Ok(value & 0xFFFF),
+                "hiword" => Ok((value >> 16) & 0xFFFF),
+                "strlen" => Ok(value),
+                _ => Err(format!("unknown function: {name}")),
+            }
+        }
     }
 }
 
@@ -684,10 +1201,21 @@ pub fn eval_expr_scoped(
 // ============================================================================
 
 /// Symbol table for the assembler.
+///
+/// Labels and EQU constants take one value. SET symbols are variables: each
+/// SET assigns a new value that later lines see, and they start each pass
+/// undefined, so both passes see the same values. A name can't be both.
 #[derive(Debug, Default)]
 pub struct SymbolTable {
-    /// Symbol values (labels, EQU constants).
+    /// Symbol values (labels, EQU constants, SET variables).
     symbols: HashMap<String, i64>,
+    /// Names assigned with SET.
+    variables: HashSet<String>,
+    /// Names defined this pass.
+    defined: HashSet<String>,
+    /// Names by their lower case form, for finding ones that differ only
+    /// in case.
+    folded: HashMap<String, Vec<String>>,
     /// Current local label scope (most recent global label).
     local_scope: String,
 }
@@ -722,16 +1250,84 @@ pub fn define(&mut self, name: &str, value: i64) -> Result<(), String> {
             name.to_string()
         };
 
+        if self.variables.contains(&full_name) {
+            return Err(format!("{full_name} is a SET symbol"));
+        }
         // In two-pass assembly, pass 2 redefines all symbols - allow this
         // The pass 2 values are the correct ones
-        self.symbols.insert(full_name, value);
+        self.insert(full_name, value);
+        Ok(())
+    }
+
+    /// Sets a symbol's value, noting that it was defined this pass.
+    fn insert(&mut self, name: String, value: i64) {
+        if let Some(slot) = self.symbols.get_mut(&name) {
+            *slot = value;
+        } else {
+            let folded = self.folded.entry(name.to_ascii_lowercase()).or_default();
+            folded.push(name.clone());
+            self.symbols.insert(name.clone(), value);
+        }
+        self.defined.insert(name);
+    }
+
+    /// Returns a symbol whose name differs from `name` only in case.
+    pub fn case_clash(&self, name: &str) -> Option<&str> {
+        let folded = self.folded.get(&name.to_ascii_lowercase())?;
+        folded
+            .iter()
+            .map(String::as_str)
+            .find(|other| *other != name)
+    }
+
+    /// Returns true if `name` was defined this pass, rather than left from
+    /// an earlier one.
+    fn defined_this_pass(&self, name: &str) -> bool {
+        self.defined.contains(name)
+    }
+
+    /// Defines a symbol another object exports as 0, its offset from
+    /// itself. Unlike a label, it doesn't start a local label scope.
+    pub fn import(&mut self, name: &str) {
+        self.insert(name.to_string(), 0);
+    }
+
+    /// Assigns a SET symbol. Returns error if the name is a label or EQU.
+    pub fn assign(&mut self, name: &str, value: i64) -> Result<(), String> {
+        if self.symbols.contains_key(name) && !self.variables.contains(name) {
+            return Err(format!("{name} is already defined and can't be SET"));
+        }
+        self.variables.insert(name.to_string());
+        self.insert(name.to_string(), value);
         Ok(())
     }
 
+    /// Returns true if a symbol left from an earlier pass (or assembly)
+    /// wasn't defined again this pass.
+    fn has_stale(&self) -> bool {
+        self.symbols.keys().any(|name| !self.defined.contains(name))
+    }
+
+    /// Forgets the SET symbols' values at the start of a pass.
+    pub fn begin_pass(&mut self) {
+        self.defined.clear();
+        for name in self.variables.drain() {
+            self.symbols.remove(&name);
+            if let Some(folded) = self.folded.get_mut(&name.to_ascii_lowercase()) {
+                folded.retain(|other| *other != name);
+            }
+        }
+    }
+
     /// Returns reference to inner `HashMap` for expression evaluation.
     pub const fn as_map(&self) -> &HashMap<String, i64> {
         &self.symbols
     }
+
+    /// Returns true if the name was assigned with SET.
+    pub fn is_variable(&self, name: &str) -> bool {
+        self.variables.contains(name)
+    }
 }
 
 // ============================================================================
@@ -754,7 +1350,7 @@ pub fn from_suffix(s: &str) -> Option<Self> {
             "B" => Some(Self::Byte),
             "W" => Some(Self::Word),
             "L" => Some(Self::Long),
-            "S" => Some(Self::Word), // Short is word for branches
+            "S" => Some(Self::Byte), // Short: a byte branch displacement
             _ => None,
         }
     }
@@ -767,6 +1363,22 @@ pub const fn bytes(self) -> usize {
             Self::Long => 4,
         }
     }
+
+    /// Returns the size's name for messages.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Byte => "byte",
+            Self::Word => "word",
+            Self::Long => "long",
+        }
+    }
+
+    /// Returns the values that fit: from the smallest signed one to the
+    /// largest unsigned one.
+    pub const fn range(self) -> std::ops::RangeInclusive<i64> {
+        let bits = self.bytes() as u32 * 8;
+        -(1 << (bits - 1))..=(1 << bits) - 1
+    }
 }
 
 // ============================================================================
@@ -811,9 +1423,12 @@ pub enum AddrMode {
 /// Parses a register name, returns (register number, `is_address_reg`).
 fn parse_register(name: &str) -> Option<(u8, bool)> {
     let upper = name.to_ascii_uppercase();
-    if upper == "SP" || upper == "A7" {
+    if upper == "SP" || upper == "SSP" || upper == "A7" {
         return Some((7, true));
     }
+    if upper == "FP" {
+        return Some((6, true));
+    }
     if upper.len() == 2 {
         let reg_num = upper.chars().nth(1)?.to_digit(10)? as u8;
         if reg_num > 7 {
@@ -829,22 +1444,57 @@ fn parse_register(name: &str) -> Option<(u8, bool)> {
     }
 }
 
-/// Parses a register list like "d0-d3/a0-a2" into a bitmask.
+/// Whether a MOVEM operand is written as a register list: registers joined
+/// by `-` and `/`. A lone symbol isn't one; it may name a mask.
+fn looks_like_register_list(tokens: &[LocatedToken]) -> bool {
+    let mut registers = false;
+    for t in tokens {
+        match &t.token {
+            Token::Ident(name) => registers |= parse_register(name).is_some(),
+            Token::Minus | Token::Slash => {}
+            _ => return false,
+        }
+    }
+    registers
+}
+
+/// How surely a MOVEM operand that isn't a register list is the mask: 2 for
+/// `#value`, 1 for a bare symbol (which could also be an address), 0 for
+/// anything else.
+const fn movem_mask_rank(mode: &AddrMode) -> u8 {
+    match mode {
+        AddrMode::Immediate(_) => 2,
+        AddrMode::AbsShort(_) | AddrMode::AbsLong(_) => 1,
+        _ => 0,
+    }
+}
+
+/// Parses a register list like "d0-d3/a0-a2" into a bitmask, bit 0 for D0
+/// up to bit 15 for A7. Ranges stay within one register type and run low to
+/// high.
 fn parse_register_list(s: &str) -> Result<u16, String> {
     let mut mask = 0u16;
     for part in s.split('/') {
         let part = part.trim();
         if part.contains('-') {
             // Range like d0-d3
-            let mut iter = part.split('-');
-            let start = iter.next().ok_or("invalid register range")?;
-            let end = iter.next().ok_or("invalid register range")?;
+            let (start, end) = part
+                .split_once('-')
+                .filter(|(_, end)| !end.contains('-'))
+                .ok_or_else(|| format!("invalid register range: {part}"))?;
             let (start_num, start_is_addr) =
                 parse_register(start).ok_or_else(|| format!("invalid register: {start}"))?;
             let (end_num, end_is_addr) =
                 parse_register(end).ok_or_else(|| format!("invalid register: {end}"))?;
             if start_is_addr != end_is_addr {
-                return Err("register range must be same type".to_string());
+                return Err(format!(
+                    "register range {part} mixes data and address registers"
+                ));
+            }
+            if end_num < start_num {
+                return Err(format!(
+                    "register range {part} runs backwards (write it low to high)"
+                ));
             }
             let base = if start_is_addr { 8 } else { 0 };
             for i in start_num..=end_num {
@@ -885,6 +1535,25 @@ fn try_eval_expr(
     }
 }
 
+/// Checks in pass 2 (when forward references have resolved) that a value
+/// fits `range`, naming it in the error, e.g. "quick value 9 is out of
+/// range (1 to 8)".
+fn check_range(
+    pass: u8,
+    what: &str,
+    value: i64,
+    range: std::ops::RangeInclusive<i64>,
+) -> Result<i64, String> {
+    if pass == 2 && !range.contains(&value) {
+        return Err(format!(
+            "{what} {value} is out of range ({} to {})",
+            range.start(),
+            range.end()
+        ));
+    }
+    Ok(value)
+}
+
 /// Encodes an effective address into mode/reg fields and extension words.
 pub fn encode_ea(
     mode: &AddrMode,
@@ -900,18 +1569,21 @@ pub fn encode_ea(
         AddrMode::PostInc(r) => Ok((0b011, *r, vec![])),
         AddrMode::PreDec(r) => Ok((0b100, *r, vec![])),
         AddrMode::Disp(expr, r) => {
-            let disp = try_eval_expr(expr, symbols, pc, pass, scope)? as i16;
+            let disp = try_eval_expr(expr, symbols, pc, pass, scope)?;
+            let disp = check_range(pass, "16-bit displacement", disp, -32768..=32767)? as i16;
             Ok((0b101, *r, vec![disp as u16]))
         }
         AddrMode::Index(expr, an, xn, sz, is_addr) => {
-            let disp = try_eval_expr(expr, symbols, pc, pass, scope)? as i8;
+            let disp = try_eval_expr(expr, symbols, pc, pass, scope)?;
+            let disp = check_range(pass, "8-bit displacement", disp, -128..=127)? as i8;
             let xr = if *is_addr { 0x8000 } else { 0 };
             let xs = if *sz == Size::Long { 0x0800 } else { 0 };
             let ext = xr | xs | (u16::from(*xn) << 12) | u16::from(disp as u8);
             Ok((0b110, *an, vec![ext]))
         }
         AddrMode::AbsShort(expr) => {
-            let addr = try_eval_expr(expr, symbols, pc, pass, scope)? as i16;
+            let addr = sign_extended_address(try_eval_expr(expr, symbols, pc, pass, scope)?);
+            let addr = check_range(pass, "absolute short address", addr, -32768..=32767)? as i16;
             Ok((0b111, 0b000, vec![addr as u16]))
         }
         AddrMode::AbsLong(expr) => {
@@ -921,12 +1593,14 @@ pub fn encode_ea(
         AddrMode::PcDisp(expr) => {
             let target = try_eval_expr(expr, symbols, pc, pass, scope)?;
             // pc is already the extension word address (callers pass self.pc + 2)
-            let disp = (target - i64::from(pc)) as i16;
+            let disp = target - i64::from(pc);
+            let disp = check_range(pass, "16-bit displacement", disp, -32768..=32767)? as i16;
             Ok((0b111, 0b010, vec![disp as u16]))
         }
         AddrMode::PcIndex(expr, xn, sz, is_addr) => {
             let target = try_eval_expr(expr, symbols, pc, pass, scope)?;
-            let disp = (target - i64::from(pc)) as i8;
+            let disp = target - i64::from(pc);
+            let disp = check_range(pass, "8-bit displacement", disp, -128..=127)? as i8;
             let xr = if *is_addr { 0x8000 } else { 0 };
             let xs = if *sz == Size::Long { 0x0800 } else { 0 };
             let ext = xr | xs | (u16::from(*xn) << 12) | u16::from(disp as u8);
@@ -998,6 +1672,20 @@ pub fn parse_operand(
         }
     }
 
+    // Check for an absolute address with its size written out: addr.W,
+    // addr.L or (addr).L
+    if let Some((size, addr)) = abs_size_suffix(tokens) {
+        let mut parser = ExprParser::new(addr);
+        let expr = parser.parse_expr()?;
+        if !parser.at_end() {
+            return Err("invalid absolute address".to_string());
+        }
+        return Ok(match size {
+            Size::Word => AddrMode::AbsShort(expr),
+            _ => AddrMode::AbsLong(expr),
+        });
+    }
+
     // Check for (An), (An)+, d(An), d(An,Xn), (PC), d(PC), d(PC,Xn)
     if tokens[0].token == Token::LParen {
         return parse_indirect(tokens);
@@ -1005,27 +1693,94 @@ pub fn parse_operand(
 
     // Check for expression possibly followed by (An) or (PC)
     // e.g., 4(a0) or label(pc)
-    let paren_pos = tokens.iter().position(|t| t.token == Token::LParen);
-    if let Some(pp) = paren_pos {
-        if pp > 0 {
-            // Expression before the parenthesis
-            let mut parser = ExprParser::new(&tokens[..pp]);
-            let disp_expr = parser.parse_expr()?;
-            return parse_indirect_with_disp(&tokens[pp..], disp_expr);
+    if let Some(pp) = tokens.iter().position(|t| t.token == Token::LParen) {
+        match register_group(tokens) {
+            Some(group) if group > 0 => {
+                // Expression before the parenthesis
+                let mut parser = ExprParser::new(&tokens[..group]);
+                let disp_expr = parser.parse_expr()?;
+                return parse_indirect_with_disp(&tokens[group..], disp_expr);
+            }
+            // The parentheses belong to the expression, e.g. hi(label)
+            None if pp > 0 => {
+                let mut parser = ExprParser::new(tokens);
+                let expr = parser.parse_expr()?;
+                if !parser.at_end() {
+                    return Err("invalid indexed addressing".to_string());
+                }
+                return Ok(abs_mode(expr, symbols));
+            }
+            _ => {}
         }
     }
 
     // Default: absolute address (expression)
     let mut parser = ExprParser::new(tokens);
     let expr = parser.parse_expr()?;
+    Ok(abs_mode(expr, symbols))
+}
+
+/// Returns an absolute address mode, short or long based on the value.
+fn abs_mode(expr: Expr, symbols: &HashMap<String, i64>) -> AddrMode {
+    match eval_expr(&expr, symbols, 0) {
+        Ok(val) if fits_abs_short(val) => AddrMode::AbsShort(expr),
+        _ => AddrMode::AbsLong(expr),
+    }
+}
+
+/// Returns an address in the top 32K ($FFFF8000-$FFFFFFFF) as the negative
+/// word the CPU sign-extends to it.
+const fn sign_extended_address(addr: i64) -> i64 {
+    if addr >= 0xFFFF_8000 && addr <= 0xFFFF_FFFF {
+        addr - 0x1_0000_0000
+    } else {
+        addr
+    }
+}
 
-    // Try to determine if short or long based on value
-    if let Ok(val) = eval_expr(&expr, symbols, 0) {
-        if (-32768..=32767).contains(&val) {
-            return Ok(AddrMode::AbsShort(expr));
+/// Returns true if an absolute address can be written as a word: it's in
+/// $0000-$7FFF or $FFFF8000-$FFFFFFFF.
+const fn fits_abs_short(addr: i64) -> bool {
+    let addr = sign_extended_address(addr);
+    addr >= -0x8000 && addr <= 0x7FFF
+}
+
+/// Splits the `.W` or `.L` off an absolute address that has one, as in
+/// `label.L` or `(label).W`.
+fn abs_size_suffix(tokens: &[LocatedToken]) -> Option<(Size, &[LocatedToken])> {
+    let (last, addr) = tokens.split_last()?;
+    let Token::Ident(suffix) = &last.token else {
+        return None;
+    };
+    let size = match suffix.to_ascii_lowercase().as_str() {
+        ".w" => Size::Word,
+        ".l" => Size::Long,
+        _ => return None,
+    };
+    (!addr.is_empty()).then_some((size, addr))
+}
+
+/// Returns where an operand's closing `(An...)` or `(PC...)` group starts,
+/// if it ends with one.
+fn register_group(tokens: &[LocatedToken]) -> Option<usize> {
+    if tokens.last()?.token != Token::RParen {
+        return None;
+    }
+    let mut depth = 0;
+    let start = tokens.iter().rposition(|t| {
+        match t.token {
+            Token::RParen => depth += 1,
+            Token::LParen => depth -= 1,
+            _ => {}
         }
+        depth == 0
+    })?;
+    match &tokens.get(start + 1)?.token {
+        Token::Ident(name) if name.eq_ignore_ascii_case("pc") || parse_register(name).is_some() => {
+            Some(start)
+        }
+        _ => None,
     }
-    Ok(AddrMode::AbsLong(expr))
 }
 
 fn parse_indirect(tokens: &[LocatedToken]) -> Result<AddrMode, String> {
@@ -1044,7 +1799,10 @@ fn parse_indirect(tokens: &[LocatedToken]) -> Result<AddrMode, String> {
                 let upper = reg.to_ascii_uppercase();
                 // Parse the expression before the comma
                 let expr_tokens = &tokens[1..comma_idx];
-                if !expr_tokens.is_empty() {
+                // (An,Xn) has no displacement, so leave it to the checks below
+                let base_reg = matches!(expr_tokens, [LocatedToken { token: Token::Ident(name), .. }]
+                    if parse_register(name).is_some());
+                if !expr_tokens.is_empty() && !base_reg {
                     let mut parser = ExprParser::new(expr_tokens);
                     if let Ok(disp_expr) = parser.parse_expr() {
                         // Check for PC-relative forms
@@ -1059,10 +1817,8 @@ fn parse_indirect(tokens: &[LocatedToken]) -> Result<AddrMode, String> {
                             if tokens.len() > comma_idx + 4
                                 && tokens[comma_idx + 2].token == Token::Comma
                             {
-                                if let Token::Ident(ref xreg) = tokens[comma_idx + 3].token {
-                                    let (xn, is_addr, sz) = parse_index_reg(xreg)?;
-                                    return Ok(AddrMode::PcIndex(disp_expr, xn, sz, is_addr));
-                                }
+                                let (xn, is_addr, sz) = parse_index(&tokens[comma_idx + 3..])?;
+                                return Ok(AddrMode::PcIndex(disp_expr, xn, sz, is_addr));
                             }
                         }
                         // Check for (expr,An) - displacement mode
@@ -1076,10 +1832,8 @@ fn parse_indirect(tokens: &[LocatedToken]) -> Result<AddrMode, String> {
                             if tokens.len() > comma_idx + 4
                                 && tokens[comma_idx + 2].token == Token::Comma
                             {
-                                if let Token::Ident(ref xreg) = tokens[comma_idx + 3].token {
-                                    let (xn, is_addr, sz) = parse_index_reg(xreg)?;
-                                    return Ok(AddrMode::Index(disp_expr, an, xn, sz, is_addr));
-                                }
+                                let (xn, is_addr, sz) = parse_index(&tokens[comma_idx + 3..])?;
+                                return Ok(AddrMode::Index(disp_expr, an, xn, sz, is_addr));
                             }
                         }
                     }
@@ -1091,9 +1845,9 @@ fn parse_indirect(tokens: &[LocatedToken]) -> Result<AddrMode, String> {
     if let Token::Ident(ref reg) = tokens[1].token {
         let upper = reg.to_ascii_uppercase();
 
-        // Check for (PC)
+        // Check for (PC), which addresses its own extension word
         if upper == "PC" && tokens.len() >= 3 && tokens[2].token == Token::RParen {
-            return Ok(AddrMode::PcDisp(Expr::Number(0)));
+            return Ok(AddrMode::PcDisp(Expr::CurrentPc));
         }
         // (PC,Xn) - parse index register
         // For now, simplified
@@ -1111,10 +1865,8 @@ fn parse_indirect(tokens: &[LocatedToken]) -> Result<AddrMode, String> {
 
             // Check for (An,Xn) - index mode with 0 displacement
             if tokens.len() >= 5 && tokens[2].token == Token::Comma {
-                if let Token::Ident(ref xreg) = tokens[3].token {
-                    let (xn, is_addr, sz) = parse_index_reg(xreg)?;
-                    return Ok(AddrMode::Index(Expr::Number(0), n, xn, sz, is_addr));
-                }
+                let (xn, is_addr, sz) = parse_index(&tokens[3..])?;
+                return Ok(AddrMode::Index(Expr::Number(0), n, xn, sz, is_addr));
             }
         }
     }
@@ -1138,10 +1890,8 @@ fn parse_indirect_with_disp(tokens: &[LocatedToken], disp: Expr) -> Result<AddrM
             }
             // d(PC,Xn)
             if tokens.len() >= 5 && tokens[2].token == Token::Comma {
-                if let Token::Ident(ref xreg) = tokens[3].token {
-                    let (xn, is_addr, sz) = parse_index_reg(xreg)?;
-                    return Ok(AddrMode::PcIndex(disp, xn, sz, is_addr));
-                }
+                let (xn, is_addr, sz) = parse_index(&tokens[3..])?;
+                return Ok(AddrMode::PcIndex(disp, xn, sz, is_addr));
             }
         }
 
@@ -1152,10 +1902,8 @@ fn parse_indirect_with_disp(tokens: &[LocatedToken], disp: Expr) -> Result<AddrM
             }
             // d(An,Xn)
             if tokens.len() >= 5 && tokens[2].token == Token::Comma {
-                if let Token::Ident(ref xreg) = tokens[3].token {
-                    let (xn, is_addr, sz) = parse_index_reg(xreg)?;
-                    return Ok(AddrMode::Index(disp, n, xn, sz, is_addr));
-                }
+                let (xn, is_addr, sz) = parse_index(&tokens[3..])?;
+                return Ok(AddrMode::Index(disp, n, xn, sz, is_addr));
             }
         }
     }
@@ -1163,23 +1911,100 @@ fn parse_indirect_with_disp(tokens: &[LocatedToken], disp: Expr) -> Result<AddrM
     Err("invalid indexed addressing".to_string())
 }
 
-fn parse_index_reg(name: &str) -> Result<(u8, bool, Size), String> {
-    // Parse register like d0, a0, d0.w, a0.l
-    let name = name.trim();
-    let (reg_part, size) = if let Some(dot_pos) = name.find('.') {
-        let sz = Size::from_suffix(&name[dot_pos + 1..]).unwrap_or(Size::Word);
-        (&name[..dot_pos], sz)
-    } else {
-        (name, Size::Word)
+/// Parses the index register that ends an indexed operand, `Xn)` or
+/// `Xn.W)` or `Xn.L)`, where Xn is any data or address register. The index
+/// is sign-extended from its low word unless it is `.L`.
+fn parse_index(tokens: &[LocatedToken]) -> Result<(u8, bool, Size), String> {
+    let Some(Token::Ident(name)) = tokens.first().map(|t| &t.token) else {
+        return Err("expected an index register".to_string());
     };
-
-    if let Some((n, is_addr)) = parse_register(reg_part) {
-        Ok((n, is_addr, size))
-    } else {
-        Err(format!("invalid index register: {name}"))
+    let Some((n, is_addr)) = parse_register(name) else {
+        return Err(format!("invalid index register: {name}"));
+    };
+    let (size, rest) = match tokens.get(1).map(|t| &t.token) {
+        Some(Token::Ident(suffix)) if suffix.starts_with('.') => {
+            let size = match suffix.to_ascii_lowercase().as_str() {
+                ".w" => Size::Word,
+                ".l" => Size::Long,
+                _ => {
+                    return Err(format!(
+                        "index register size must be .W or .L: {name}{suffix}"
+                    ))
+                }
+            };
+            (size, &tokens[2..])
+        }
+        _ => (Size::Word, &tokens[1..]),
+    };
+    match rest {
+        [LocatedToken {
+            token: Token::RParen,
+            ..
+        }] => Ok((n, is_addr, size)),
+        _ => Err("expected ) after the index register".to_string()),
     }
 }
 
+/// Returns the value of a character constant of up to 4 characters: its
+/// bytes big-endian, so `'AB'` is $4142 and `'FORM'` $464F524D. Three
+/// characters are right-aligned, `'ABC'` being $00414243.
+fn character_constant(text: &str) -> Result<i64, String> {
+    if text.is_empty() || text.len() > 4 {
+        return Err(format!(
+            "'{text}' isn't a value (a character constant has 1 to 4 characters)"
+        ));
+    }
+    Ok(text
+        .bytes()
+        .fold(0, |value, byte| value << 8 | i64::from(byte)))
+}
+
+/// Returns the span from the first token to the end of the last on its
+/// line, if any.
+fn token_span(tokens: &[LocatedToken]) -> Option<(SourceLoc, usize)> {
+    let first = tokens.first()?;
+    let last = tokens.iter().rfind(|t| t.loc.line == first.loc.line)?;
+    let length = (last.loc.column + last.len).saturating_sub(first.loc.column);
+    Some((first.loc.clone(), length))
+}
+
+/// Returns the name an "undefined symbol" error is about, less any
+/// suggestions.
+fn undefined_symbol(message: &str) -> Option<&str> {
+    let (_, name) = message.rsplit_once("undefined symbol: ")?;
+    Some(
+        name.split_once(" (did you mean ")
+            .map_or(name, |(name, _)| name),
+    )
+}
+
+/// Returns the span of the first use of the symbol an "undefined symbol"
+/// error names.
+fn undefined_symbol_span(tokens: &[LocatedToken], message: &str) -> Option<(SourceLoc, usize)> {
+    let name = undefined_symbol(message)?;
+    tokens
+        .iter()
+        .find(|t| matches!(&t.token, Token::Ident(s) if s == name))
+        .map(|t| (t.loc.clone(), t.len))
+}
+
+/// Returns the names an XDEF or XREF lists, separated by commas.
+fn symbol_names(directive: &str, operands: &[LocatedToken]) -> Result<Vec<String>, String> {
+    let ops = split_operands(operands);
+    if ops.is_empty() {
+        return Err(format!("{directive} requires a symbol name"));
+    }
+    ops.iter()
+        .map(|tokens| match tokens {
+            [LocatedToken {
+                token: Token::Ident(name),
+                ..
+            }] if !name.starts_with('.') => Ok(name.clone()),
+            _ => Err(format!("{directive} takes global symbol names")),
+        })
+        .collect()
+}
+
 /// Splits operands by comma, respecting parentheses.
 /// Filters out Eof and Newline tokens.
 pub fn split_operands(tokens: &[LocatedToken]) -> Vec<&[LocatedToken]> {
@@ -1268,6 +2093,584 @@ pub fn from_name(name: &str) -> Option<Self> {
 // ASSEMBLER STATE
 // ============================================================================
 
+/// A section of assembled code starting at an ORG address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// Address of the first byte.
+    pub origin: u32,
+    /// Machine code.
+    pub data: Vec<u8>,
+}
+
+/// Section code goes in until a SECTION directive.
+const DEFAULT_SECTION: &str = "text";
+
+/// What a section holds, which decides where it's placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SectionKind {
+    /// Code (`CODE` or `TEXT`)
+    Code,
+    /// Initialized data
+    Data,
+    /// Space that's cleared when the program is loaded, and takes none in
+    /// the binary
+    Bss,
+}
+
+impl SectionKind {
+    /// Parses a SECTION type.
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "CODE" | "TEXT" => Some(Self::Code),
+            "DATA" => Some(Self::Data),
+            "BSS" => Some(Self::Bss),
+            _ => None,
+        }
+    }
+
+    /// The kind a section without a type has: the one its name is, or code.
+    fn of_section(name: &str) -> Self {
+        Self::from_name(name).unwrap_or(Self::Code)
+    }
+}
+
+/// A section being assembled.
+#[derive(Debug, Clone)]
+struct SectionState {
+    name: String,
+    kind: SectionKind,
+    /// Chunks its code went in, one per ORG, the last being current.
+    chunks: Vec<usize>,
+    /// Address it starts at.
+    start: u32,
+    /// Where it got to, while another section is being assembled.
+    pc: u32,
+    /// Placed by ORG (or the origin) rather than after the section before.
+    org: bool,
+}
+
+/// A section of a program assembled with `Assembler::assemble_sections`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Section {
+    pub name: String,
+    pub kind: SectionKind,
+    /// Address of its first byte.
+    pub origin: u32,
+    /// Its bytes (none for BSS).
+    pub data: Vec<u8>,
+    /// Its size in bytes, which for BSS is the space to clear.
+    pub size: u32,
+}
+
+/// Imports and exports of an object being assembled (see
+/// `Assembler::assemble_object`).
+#[derive(Debug, Default)]
+struct ObjectState {
+    /// Names declared with XREF, kept across passes.
+    imports: HashSet<String>,
+    /// Names declared with XDEF this pass.
+    exports: Vec<String>,
+    /// Relocations found in pass 2.
+    relocations: Vec<Relocation>,
+}
+
+impl ObjectState {
+    /// Starts a pass.
+    fn begin_pass(&mut self) {
+        self.exports.clear();
+        self.relocations.clear();
+    }
+}
+
+/// What a symbol in a symbol map is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    /// Code or data label.
+    Label,
+    /// EQU or RS constant.
+    Constant,
+    /// SET variable, with its last value.
+    Variable,
+}
+
+/// A symbol from the last assembly (see `Assembler::symbol_map`).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct MapSymbol {
+    pub name: String,
+    pub value: i64,
+    pub kind: SymbolKind,
+    /// Section a label is in.
+    pub section: Option<String>,
+}
+
+/// How a symbol map is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapFormat {
+    /// A table of every symbol with its value and kind, for reading.
+    #[default]
+    Listing,
+    /// The labels as `name $value` lines, the format the debugger's map
+    /// file importer reads.
+    Plain,
+}
+
+impl MapFormat {
+    /// Writes a symbol map in this format.
+    pub fn render(self, symbols: &[MapSymbol]) -> String {
+        use std::fmt::Write;
+        let mut text = String::new();
+        match self {
+            Self::Listing => {
+                let width = symbols
+                    .iter()
+                    .map(|symbol| symbol.name.len())
+                    .max()
+                    .unwrap_or(0)
+                    .max(4);
+                let _ = writeln!(text, "{:<width$}  Value      Kind", "Name");
+                for symbol in symbols {
+                    let kind = match symbol.kind {
+                        SymbolKind::Label => "label",
+                        SymbolKind::Constant => "constant",
+                        SymbolKind::Variable => "variable",
+                    };
+                    let _ = writeln!(
+                        text,
+                        "{:<width$}  ${:08X}  {kind}",
+                        symbol.name, symbol.value as u32
+                    );
+                }
+            }
+            Self::Plain => {
+                for symbol in symbols.iter().filter(|s| s.kind == SymbolKind::Label) {
+                    let _ = writeln!(text, "{} ${:X}", symbol.name, symbol.value as u32);
+                }
+            }
+        }
+        text
+    }
+}
+
+/// Fill byte for ROM padding unless one is given (erased EPROM).
+pub const DEFAULT_ROM_FILL: u8 = 0xFF;
+
+/// Largest ROM image, the 68000's whole 16 MB address space.
+pub const MAX_ROM_SIZE: u32 = 0x0100_0000;
+
+/// Size of a ROM image the flat binary is padded to (see
+/// `Assembler::rom`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RomImage {
+    /// Image size in bytes.
+    pub size: u32,
+    /// Byte the space after the code is filled with.
+    #[serde(default = "default_rom_fill")]
+    pub fill: u8,
+    /// Store the 16-bit sum of the other bytes in the last two, big-endian.
+    #[serde(default)]
+    pub checksum: bool,
+}
+
+const fn default_rom_fill() -> u8 {
+    DEFAULT_ROM_FILL
+}
+
+impl RomImage {
+    /// Checks the size is from 1 (2 with a checksum) to `MAX_ROM_SIZE`.
+    pub fn validate(self) -> Result<Self, String> {
+        let least = if self.checksum { 2 } else { 1 };
+        if (least..=MAX_ROM_SIZE).contains(&self.size) {
+            Ok(self)
+        } else {
+            Err(format!(
+                "rom size must be from {least} to ${MAX_ROM_SIZE:X}: {}",
+                self.size
+            ))
+        }
+    }
+
+    /// Pads `binary` to the image size and adds the checksum, or explains
+    /// why it doesn't fit.
+    fn pad(self, binary: &mut Vec<u8>) -> Result<(), String> {
+        let room = self.size as usize - if self.checksum { 2 } else { 0 };
+        if binary.len() > room {
+            let what = if self.checksum {
+                " (less the checksum)"
+            } else {
+                ""
+            };
+            return Err(format!(
+                "program is ${:X} bytes, more than the ${:X} byte ROM{what}",
+                binary.len(),
+                self.size
+            ));
+        }
+        binary.resize(room, self.fill);
+        if self.checksum {
+            let sum = binary
+                .iter()
+                .fold(0u16, |sum, &b| sum.wrapping_add(u16::from(b)));
+            binary.extend(sum.to_be_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Data bytes per S-record unless asked otherwise.
+pub const DEFAULT_SRECORD_LENGTH: usize = 16;
+
+/// Most data bytes an S-record holds (with a 4-byte address, the byte
+/// count can't go higher).
+pub const MAX_SRECORD_LENGTH: usize = 250;
+
+/// Writes chunks as Motorola S-records: an S0 header holding `header`, a
+/// data record per `record_length` bytes (clamped to 1 to
+/// `MAX_SRECORD_LENGTH`), and a termination record with `entry`.
+///
+/// The address width is the smallest that reaches every byte and the
+/// entry: S1/S9 up to $FFFF, S2/S8 up to $FFFFFF, else S3/S7.
+pub fn write_srecords(chunks: &[Chunk], entry: u32, header: &[u8], record_length: usize) -> String {
+    let record_length = record_length.clamp(1, MAX_SRECORD_LENGTH);
+    let highest = chunks
+        .iter()
+        .filter(|chunk| !chunk.data.is_empty())
+        .map(|chunk| chunk.origin.wrapping_add(chunk.data.len() as u32 - 1))
+        .fold(entry, u32::max);
+    let (data_kind, end_kind, width) = match highest {
+        0..=0xFFFF => (1, 9, 2),
+        0x1_0000..=0xFF_FFFF => (2, 8, 3),
+        _ => (3, 7, 4),
+    };
+
+    let mut text = String::new();
+    let header = &header[..header.len().min(MAX_SRECORD_LENGTH)];
+    push_srecord(&mut text, 0, 0, 2, header);
+    for chunk in chunks {
+        for (address, data) in (chunk.origin..)
+            .step_by(record_length)
+            .zip(chunk.data.chunks(record_length))
+        {
+            push_srecord(&mut text, data_kind, address, width, data);
+        }
+    }
+    push_srecord(&mut text, end_kind, entry, width, &[]);
+    text
+}
+
+/// Appends one S-record line: type, byte count, address of `width` bytes,
+/// data and checksum.
+fn push_srecord(text: &mut String, kind: u8, address: u32, width: usize, data: &[u8]) {
+    use std::fmt::Write;
+    let mut bytes = vec![(width + data.len() + 1) as u8];
+    bytes.extend_from_slice(&address.to_be_bytes()[4 - width..]);
+    bytes.extend_from_slice(data);
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes.push(!sum);
+    let _ = write!(text, "S{kind}");
+    for byte in bytes {
+        let _ = write!(text, "{byte:02X}");
+    }
+    text.push('\n');
+}
+
+/// REPT iterations a listing shows before the rest collapse to one line.
+pub const LISTED_REPT_ITERATIONS: usize = 4;
+
+/// Bytes a listing line shows; more are marked with `...`.
+const LISTED_BYTES: usize = 8;
+
+/// Code one preprocessed line assembled to, for the listing.
+#[derive(Debug, Clone, Default)]
+struct ListedCode {
+    /// Address the line starts at, if it was assembled.
+    address: Option<u32>,
+    /// Its first bytes, up to `LISTED_BYTES`.
+    bytes: Vec<u8>,
+    /// Its size in bytes.
+    size: usize,
+}
+
+/// Writes a listing: each line's address, bytes and source text.
+///
+/// With `expand`, lines inside macro calls and REPT blocks follow the line
+/// they came from, marked `+depth`, and the call itself has only its
+/// address. Without, the call has its whole expansion's bytes.
+fn write_listing(lines: &[ListingLine], code: &[ListedCode], expand: bool) -> String {
+    use std::fmt::Write;
+    let mut text = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.depth > 0 && !expand {
+            continue;
+        }
+        let covered = code.get(line.lines.clone()).unwrap_or_default();
+        let address = covered.iter().find_map(|code| code.address);
+        // An expanded call's bytes are on the lines after it
+        let expanded = expand && lines.get(i + 1).is_some_and(|next| next.depth > line.depth);
+        let (mut bytes, mut size) = (Vec::new(), 0);
+        if !expanded {
+            for code in covered {
+                if bytes.len() == size {
+                    bytes.extend_from_slice(&code.bytes);
+                }
+                size += code.size;
+            }
+        }
+        let address = address.map_or_else(String::new, |address| format!("{address:06X}"));
+        let mut hex = String::new();
+        for (i, byte) in bytes.iter().take(LISTED_BYTES).enumerate() {
+            if i > 0 && i % 2 == 0 {
+                hex.push(' ');
+            }
+            let _ = write!(hex, "{byte:02X}");
+        }
+        if size > LISTED_BYTES {
+            hex.push_str(" ...");
+        }
+        let marker = if line.depth > 0 {
+            format!("+{} ", line.depth)
+        } else {
+            String::new()
+        };
+        let row = format!("{address:<6}  {hex:<23}  {marker}{}", line.text);
+        text.push_str(row.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+/// Most errors one assembly reports before it stops.
+pub const MAX_ERRORS: usize = 100;
+
+/// Most symbols an undefined one's error suggests.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The errors an assembly has found so far.
+///
+/// Each line reports at most one error, and an error that only follows
+/// from an earlier one (a symbol a failed line should have defined) isn't
+/// reported at all.
+#[derive(Clone, Default)]
+struct ErrorLog {
+    diagnostics: Vec<Diagnostic>,
+    /// Lines with an error, by file and line number.
+    lines: HashSet<(String, usize)>,
+    /// Symbols that lines with errors should have defined.
+    poisoned: HashSet<String>,
+}
+
+impl ErrorLog {
+    /// Records an error on a line, which defines `label` if it has one.
+    /// Past `MAX_ERRORS`, records a last "too many errors" error instead.
+    fn push(&mut self, diagnostic: Diagnostic, label: Option<&str>) {
+        if let Some(label) = label {
+            self.poisoned.insert(label.to_string());
+        }
+        if self.is_full()
+            || !self
+                .lines
+                .insert((diagnostic.file.clone(), diagnostic.line))
+            || self.follows_earlier(&diagnostic.message)
+        {
+            return;
+        }
+        if self.diagnostics.len() == MAX_ERRORS {
+            self.diagnostics.push(Diagnostic {
+                message: format!("too many errors (more than {MAX_ERRORS}), stopping"),
+                ..diagnostic
+            });
+        } else {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Returns true if an error is about using a symbol whose line failed.
+    fn follows_earlier(&self, message: &str) -> bool {
+        undefined_symbol(message).is_some_and(|name| self.poisoned.contains(name))
+    }
+
+    /// Returns true once the "too many errors" error is in.
+    const fn is_full(&self) -> bool {
+        self.diagnostics.len() > MAX_ERRORS
+    }
+
+    /// Returns the errors in line order, less any that turned out to follow
+    /// from others, with "too many errors" last.
+    fn finish(mut self) -> Vec<Diagnostic> {
+        let last = self.is_full().then(|| self.diagnostics.pop()).flatten();
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.retain(|d| !self.follows_earlier(&d.message));
+        diagnostics.sort_by_key(|d| d.line);
+        diagnostics.extend(last);
+        diagnostics
+    }
+}
+
+/// Returns true if a displacement fits a short branch (0 marks a word one).
+fn fits_short(disp: i64) -> bool {
+    (-128..=127).contains(&disp) && disp != 0
+}
+
+/// Returns the error for a branch displacement beyond 16 bits, if it is.
+fn check_word_branch(name: &str, disp: i64) -> Result<(), String> {
+    if (-32768..=32767).contains(&disp) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{name} target is {disp} bytes away, out of range (-32768 to 32767)"
+        ))
+    }
+}
+
+/// Returns true if `MOVE #n` behaves the same as `MOVEQ`: a long move to a
+/// data register (a word or byte one keeps the upper bits).
+fn moveq_destination(size: Size, dst: &AddrMode) -> bool {
+    size == Size::Long && matches!(dst, AddrMode::DataReg(_))
+}
+
+/// Returns true if `ADD #n` or `SUB #n` behaves the same as `ADDQ`/`SUBQ`:
+/// to a data register, or a word or long to an address register, where
+/// both change all 32 bits and no flags.
+fn addq_destination(size: Size, dst: &AddrMode) -> bool {
+    match dst {
+        AddrMode::DataReg(_) => true,
+        AddrMode::AddrReg(_) => size != Size::Byte,
+        _ => false,
+    }
+}
+
+/// Returns the microseconds since `start`, for `AssemblyTimings`.
+fn elapsed_us(start: std::time::Instant) -> u64 {
+    u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX)
+}
+
+/// Most times pass 1 runs before pass 2 goes ahead with the addresses it
+/// has, which then reports the labels that moved as phase errors.
+const MAX_SIZING_PASSES: usize = 32;
+
+/// How long the last assembly's stages took, in microseconds, and what it
+/// reused from the one before (see `Assembler::incremental`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssemblyTimings {
+    pub preprocess_us: u64,
+    pub lex_us: u64,
+    /// Both passes, including each time pass 1 ran again.
+    pub passes_us: u64,
+    pub total_us: u64,
+    /// Times pass 1 ran: none when the lines that changed only hold
+    /// instructions that kept their size (see `assemble_checked`).
+    pub sizing_passes: usize,
+    /// Includes whose expansion was reused.
+    pub cached_includes: usize,
+    /// The source's expansion was patched where it changed rather than
+    /// preprocessed again.
+    pub patched_source: bool,
+    /// Lines whose tokens were reused.
+    pub cached_lines: usize,
+    /// Pass 1 was skipped, or started from the last assembly's sizes and
+    /// addresses, and they held.
+    pub reused_sizes: bool,
+}
+
+/// What pass 1 settled on, for the next incremental assembly to start
+/// from.
+struct SizeSeed {
+    grown: Vec<bool>,
+    site_pcs: Vec<u32>,
+    reaches: Vec<Reach>,
+    barriers: Vec<u32>,
+    layout_symbols: HashSet<String>,
+    labels: Vec<(String, u32)>,
+    code_labels: HashSet<String>,
+    section_bases: Vec<u32>,
+    origin: u32,
+    rs_counter: u32,
+    option_changes: Vec<(usize, Options)>,
+    /// What each line assembled to (see `Assembler::line_sizes`).
+    line_sizes: Vec<Option<LineSize>>,
+}
+
+/// What a line that changed nothing else assembled to: its code's size,
+/// after its label if it has one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct LineSize {
+    label: Option<String>,
+    size: u32,
+}
+
+/// What decides whether an instruction with a short and a long form fits
+/// the short one, for telling whether sizes kept from the last assembly are
+/// still the ones a cold assembly would choose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reach {
+    /// A value that doesn't depend on where code is.
+    Fixed,
+    /// A branch's short displacement to `target`, a code label.
+    Branch { disp: i64, target: u32 },
+    /// A PC-relative displacement to `target`, a code label.
+    PcRelative { disp: i64, target: u32 },
+    /// The address of a code label.
+    Address(u32),
+    /// A value that depends on where code is some other way.
+    Unknown,
+}
+
+impl Reach {
+    /// Returns the value whose distance from zero decides the size, and
+    /// the code address it reaches, if it depends only on that.
+    fn span(self) -> Option<(i64, u32)> {
+        match self {
+            Self::Branch { disp, target } | Self::PcRelative { disp, target } => {
+                Some((disp, target))
+            }
+            Self::Address(address) => Some((sign_extended_address(address.into()), address)),
+            Self::Fixed | Self::Unknown => None,
+        }
+    }
+}
+
+/// Returns true if `now` is at least as far from zero as `then`, on the
+/// same side.
+const fn no_nearer(then: i64, now: i64) -> bool {
+    if then > 0 {
+        now >= then
+    } else if then < 0 {
+        now <= then
+    } else {
+        now == 0
+    }
+}
+
+/// What an incremental assembly keeps for the next one.
+#[derive(Default)]
+struct AssemblyCache {
+    includes: IncludeCache,
+    lines: LexCache,
+    sizes: Option<SizeSeed>,
+    /// The origin and RS counter the last assembly started from, and
+    /// where the source left them.
+    start: Option<((u32, u32), (u32, u32))>,
+}
+
+/// Largest boundary ALIGN and CNOP accept.
+const MAX_ALIGN: u32 = 0x1_0000;
+
+/// Checks an ALIGN or CNOP boundary, named `what` in the error: a power of
+/// two up to `MAX_ALIGN`.
+fn alignment(what: &str, value: i64) -> Result<u32, String> {
+    if (1..=i64::from(MAX_ALIGN)).contains(&value) && value & (value - 1) == 0 {
+        Ok(value as u32)
+    } else {
+        Err(format!(
+            "{what} must be a power of two up to {MAX_ALIGN}: {value}"
+        ))
+    }
+}
+
 /// Main assembler state.
 pub struct Assembler {
     /// Symbol table.
@@ -1278,19 +2681,158 @@ pub struct Assembler {
     pub origin: u32,
     /// Output buffer.
     pub output: Vec<u8>,
+    /// ORG sections of the current assembly, when assembling chunks.
+    chunks: Vec<Chunk>,
+    /// Keep ORG sections apart (see `assemble_chunks`).
+    chunked: bool,
+    /// Highest address assembled so far, which a flat binary's ORGs can't
+    /// go back below.
+    end: u32,
+    /// Byte DS fills the space it reserves with.
+    pub space_fill: u8,
     /// RS counter for structure definitions.
     pub rs_counter: u32,
     /// Include paths for resolving includes.
     pub include_paths: Vec<PathBuf>,
+    /// In-memory files by name, which INCLUDE tries before the disk.
+    pub virtual_files: HashMap<String, String>,
+    /// How deep includes may nest.
+    pub max_include_depth: usize,
+    /// INCLUDEs resolved by the last assembly, in the order they were read.
+    pub includes: Vec<ResolvedInclude>,
     /// Current pass (1 or 2).
     pub pass: u8,
     /// Current file being assembled (reserved for future error reporting).
     #[allow(dead_code)]
     pub current_file: PathBuf,
     /// Pending EQU definitions with forward references.
-    pending_equs: Vec<(String, Expr)>,
+    pending_equs: Vec<(String, Expr, SourceLoc)>,
     /// Current global label scope for local labels.
     current_scope: String,
+    /// Code labels and their addresses, in definition order (EQU and RS
+    /// symbols are excluded).
+    pub labels: Vec<(String, u32)>,
+    /// Warnings from the last assembly.
+    pub warnings: Vec<Diagnostic>,
+    /// Entry point named by END, if any.
+    pub entry: Option<u32>,
+    /// END was reached, so the rest of the source is skipped.
+    ended: bool,
+    /// Where the current line's error is, when it's about one operand
+    /// rather than the whole statement.
+    error_span: Option<(SourceLoc, usize)>,
+    /// Whether each instruction with a short and a long form (branches
+    /// and absolute addresses without a size suffix, quick and PC-relative
+    /// rewrites) has grown to the long one, in source order.
+    grown: Vec<bool>,
+    /// Where each of those instructions was the last time it was encoded.
+    site_pcs: Vec<u32>,
+    /// What each of those instructions had to reach to be short the last
+    /// time it was encoded.
+    reaches: Vec<Reach>,
+    /// EQU and SET symbols whose values depend on where code is.
+    layout_symbols: HashSet<String>,
+    /// Addresses this pass where code after the first of those
+    /// instructions starts again at a fixed address (an ORG, or a DS or DCB
+    /// that pads up to one), however long the code before it is.
+    barriers: Vec<u32>,
+    /// Something after the first of those instructions this pass moves
+    /// code some other way as they change size: an ALIGN or CNOP beyond a
+    /// word, or a DS or DCB whose length depends on where code is.
+    layout_shifts: bool,
+    /// Instructions with a short and a long form encoded so far this pass.
+    sized_count: usize,
+    /// An instruction grew (or a value it depends on wasn't known yet) this
+    /// pass, so pass 1 runs again with the new label addresses.
+    resize: bool,
+    /// Code labels and their addresses at the end of pass 1, which pass 2
+    /// must give them too.
+    pass1_labels: Vec<(String, u32)>,
+    /// The RS counter at the end of pass 1, which pass 2 starts from.
+    pass1_rs_counter: u32,
+    /// A phase error was reported this assembly, or a line failed in pass
+    /// 2 and left out its code; the phase errors after that follow from
+    /// it, so they aren't.
+    phase_error: bool,
+    /// Rewrite `MOVE.L #n,Dn` to `MOVEQ` and `ADD`/`SUB #1-8` to
+    /// `ADDQ`/`SUBQ` where the flags come out the same (off by default, so
+    /// the output matches the source).
+    pub optimize: bool,
+    /// Rewrite absolute source operands that address a label within
+    /// 32 KB as `label(PC)`, for code that runs wherever it's loaded (off
+    /// by default). A size written out, as in `label.L`, keeps one
+    /// absolute.
+    pub pc_relative: bool,
+    /// Fold symbol names to lower case, so `Start` and `START` are one
+    /// symbol. Off by default: symbols are case-sensitive, and defining two
+    /// that differ only in case is warned about.
+    pub ignore_case: bool,
+    /// Names (lower case) a case clash was warned about this assembly.
+    case_warned: HashSet<String>,
+    /// Report word data, branch and jump targets and code origins at odd
+    /// addresses as errors rather than warnings (off by default).
+    pub strict_alignment: bool,
+    /// What the line being assembled has at odd addresses, reported after
+    /// it (see `note_odd`).
+    odd_addresses: Vec<String>,
+    /// Options in effect on the line being assembled, which OPT changes.
+    options: Options,
+    /// Options each OPT set, with the first line they apply to.
+    option_changes: Vec<(usize, Options)>,
+    /// Pad the flat binary to a ROM image. A ROMSIZE directive does the same,
+    /// but this takes precedence.
+    pub rom: Option<RomImage>,
+    /// The last ROMSIZE directive, with where it is.
+    rom_directive: Option<(RomImage, SourceLoc, usize)>,
+    /// Names defined as code labels this assembly, kept across passes so
+    /// a forward reference to one can be told from a constant.
+    code_labels: HashSet<String>,
+    /// Sections declared this pass, starting with the default one.
+    sections: Vec<SectionState>,
+    /// Section being assembled.
+    current_section: usize,
+    /// Where each section starts, placed after the one before by the last
+    /// pass.
+    section_bases: Vec<u32>,
+    /// The section each code label is in, kept across passes.
+    label_sections: HashMap<String, usize>,
+    /// Code or data was emitted in a BSS section on this line.
+    bss_written: bool,
+    /// Imports, exports and relocations, when assembling an object.
+    object: Option<ObjectState>,
+    /// Show the lines macro calls and REPT blocks expand to in listings,
+    /// not just the call (off by default).
+    pub list_expansions: bool,
+    /// Source lines for the listing, from the preprocessor.
+    listing: Vec<ListingLine>,
+    /// Code each preprocessed line assembled to in pass 2, when making a
+    /// listing.
+    listed: Option<Vec<ListedCode>>,
+    /// Preprocessed line (0-based) being assembled.
+    listed_line: usize,
+    /// Register names defined with EQUR so far this pass, each mapped to
+    /// the register it names.
+    register_aliases: HashMap<String, String>,
+    /// Keep what each assembly learns for the next one, for an editor that
+    /// assembles the same source after every change (off by default; see
+    /// `assemble_checked`).
+    pub incremental: bool,
+    /// What the last incremental assembly kept.
+    cache: AssemblyCache,
+    /// The line being assembled is an instruction rather than a directive.
+    encoded: bool,
+    /// What each preprocessed line (by number) assembled to in pass 2,
+    /// when it changed nothing but that: a line without a statement, or an
+    /// instruction without an error or a form that depends on where code
+    /// is. Kept when assembling incrementally, so an edit to such lines
+    /// that keeps their labels and sizes doesn't need pass 1 again.
+    line_sizes: Vec<Option<LineSize>>,
+    /// An instruction's form this pass wasn't confirmed by what's known:
+    /// it's long but would fit the short one, or depends on a value that
+    /// isn't known yet.
+    unsettled: bool,
+    /// How long the last assembly took.
+    pub timings: AssemblyTimings,
 }
 
 impl Assembler {
@@ -1300,26 +2842,383 @@ pub fn new() -> Self {
             pc: 0,
             origin: 0,
             output: Vec::new(),
+            chunks: Vec::new(),
+            chunked: false,
+            end: 0,
+            space_fill: 0,
             rs_counter: 0,
             include_paths: vec![],
+            virtual_files: HashMap::new(),
+            max_include_depth: MAX_INCLUDE_DEPTH,
+            includes: Vec::new(),
             pass: 1,
             current_file: PathBuf::new(),
             pending_equs: Vec::new(),
             current_scope: String::new(),
+            labels: Vec::new(),
+            warnings: Vec::new(),
+            entry: None,
+            ended: false,
+            error_span: None,
+            grown: Vec::new(),
+            site_pcs: Vec::new(),
+            reaches: Vec::new(),
+            layout_symbols: HashSet::new(),
+            barriers: Vec::new(),
+            layout_shifts: false,
+            sized_count: 0,
+            resize: false,
+            pass1_labels: Vec::new(),
+            pass1_rs_counter: 0,
+            phase_error: false,
+            optimize: false,
+            pc_relative: false,
+            ignore_case: false,
+            case_warned: HashSet::new(),
+            strict_alignment: false,
+            odd_addresses: Vec::new(),
+            options: Options::new(false, false),
+            option_changes: Vec::new(),
+            rom: None,
+            rom_directive: None,
+            code_labels: HashSet::new(),
+            sections: Vec::new(),
+            current_section: 0,
+            section_bases: Vec::new(),
+            label_sections: HashMap::new(),
+            bss_written: false,
+            object: None,
+            list_expansions: false,
+            listing: Vec::new(),
+            listed: None,
+            listed_line: 0,
+            register_aliases: HashMap::new(),
+            incremental: false,
+            cache: AssemblyCache::default(),
+            encoded: false,
+            line_sizes: Vec::new(),
+            unsettled: false,
+            timings: AssemblyTimings::default(),
+        }
+    }
+
+    /// Parses an instruction operand, pointing any error at it.
+    fn operand(&mut self, tokens: &[LocatedToken]) -> Result<AddrMode, String> {
+        let mode = parse_operand(&self.with_registers(tokens), self.symbols.as_map())
+            .inspect_err(|_| self.error_span = token_span(tokens))?;
+        if abs_size_suffix(tokens).is_some() {
+            return Ok(mode);
+        }
+        let (AddrMode::AbsShort(expr) | AddrMode::AbsLong(expr)) = mode else {
+            return Ok(mode);
+        };
+        // Without a size, an address is short if it fits once labels
+        // settle. In an object, addresses aren't known until linking, so
+        // they're long.
+        let (fits, reach) = if self.relocation_target(&expr)?.is_some() {
+            (Some(false), Reach::Fixed)
+        } else {
+            let drift = self.forward_drift(&expr);
+            let address = eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope())
+                .ok()
+                .map(|address| address + drift);
+            let reach = match address {
+                Some(address) if self.on_code_label(&expr) => Reach::Address(address as u32),
+                _ => self.reach_of(&expr),
+            };
+            (address.map(fits_abs_short), reach)
+        };
+        Ok(if self.keep_short(fits, reach) {
+            AddrMode::AbsShort(expr)
+        } else {
+            AddrMode::AbsLong(expr)
+        })
+    }
+
+    /// Encodes an effective address whose extension words start at
+    /// `ext_pc`, noting the relocation an object needs for it.
+    fn ea(&mut self, mode: &AddrMode, ext_pc: u32) -> Result<(u8, u8, Vec<u16>), String> {
+        match mode {
+            AddrMode::AbsShort(expr) => self.relocate_value(expr, Size::Word, ext_pc)?,
+            AddrMode::AbsLong(expr) => self.relocate_value(expr, Size::Long, ext_pc)?,
+            AddrMode::PcDisp(expr) if self.relocate_displacement(expr, ext_pc)? => {
+                return Ok((0b111, 0b010, vec![0]));
+            }
+            AddrMode::PcIndex(expr, ..) if self.external_target(expr)?.is_some() => {
+                return Err(
+                    "an indexed PC-relative address can't reach another section or an import"
+                        .to_string(),
+                );
+            }
+            _ => {}
+        }
+        encode_ea(mode, self.symbols.as_map(), ext_pc, self.pass, self.scope())
+    }
+
+    /// In an object, returns what the value of `expr` depends on: the
+    /// section of the label it's written relative to, or an import.
+    fn relocation_target(&self, expr: &Expr) -> Result<Option<RelocTarget>, String> {
+        let Some(object) = &self.object else {
+            return Ok(None);
+        };
+        if let Some(base) = expr.base_symbol() {
+            let name = self.expand_local_label(base);
+            if object.imports.contains(&name) {
+                return Ok(Some(RelocTarget::Symbol(name)));
+            }
+            return Ok(self
+                .label_sections
+                .get(&name)
+                .map(|&section| RelocTarget::Section(section)));
+        }
+        if expr.mentions(&object.imports) {
+            return Err("an import can only be used as name, name+n or name-n".to_string());
+        }
+        Ok(None)
+    }
+
+    /// Like `relocation_target`, but only for targets outside the current
+    /// section, whose distance only the linker knows.
+    fn external_target(&self, expr: &Expr) -> Result<Option<RelocTarget>, String> {
+        let current = Some(self.current_section);
+        Ok(self
+            .relocation_target(expr)?
+            .filter(|target| !matches!(target, RelocTarget::Section(s) if Some(*s) == current)))
+    }
+
+    /// In an object, notes the relocation a `size` value of `expr` at `at`
+    /// needs if it's an address, which only a long can hold.
+    fn relocate_value(&mut self, expr: &Expr, size: Size, at: u32) -> Result<(), String> {
+        let Some(target) = self.relocation_target(expr)? else {
+            return Ok(());
+        };
+        if size != Size::Long {
+            return Err(format!(
+                "an address in an object needs a long, not a {}, to be relocated",
+                size.name()
+            ));
         }
+        self.add_relocation(RelocKind::Abs32, at, target, expr)
+    }
+
+    /// In an object, notes the relocation the 16-bit PC-relative field at
+    /// `at` needs if `expr` is outside the current section, and returns
+    /// whether it did.
+    fn relocate_displacement(&mut self, expr: &Expr, at: u32) -> Result<bool, String> {
+        let Some(target) = self.external_target(expr)? else {
+            return Ok(false);
+        };
+        self.add_relocation(RelocKind::Pc16, at, target, expr)?;
+        Ok(true)
+    }
+
+    /// Records in pass 2 a relocation of the field at `at` in the current
+    /// section, the value of `expr` being the addend.
+    fn add_relocation(
+        &mut self,
+        kind: RelocKind,
+        at: u32,
+        target: RelocTarget,
+        expr: &Expr,
+    ) -> Result<(), String> {
+        if self.pass != 2 {
+            return Ok(());
+        }
+        let addend = eval_expr_scoped(expr, self.symbols.as_map(), self.pc, self.scope())?;
+        if let Some(object) = &mut self.object {
+            object.relocations.push(Relocation {
+                section: self.current_section,
+                offset: at,
+                kind,
+                target,
+                addend,
+            });
+        }
+        Ok(())
+    }
+
+    /// Parses a source operand of an instruction that can read through
+    /// `d16(PC)`, whose extension word is at `ext_pc`. With `pc_relative`
+    /// on, an absolute address written relative to a code label becomes
+    /// PC-relative if it's in range; constants and addresses with a size
+    /// written out stay absolute.
+    fn source_operand(&mut self, tokens: &[LocatedToken], ext_pc: u32) -> Result<AddrMode, String> {
+        let mode = self.operand(tokens)?;
+        if !self.pc_relative || abs_size_suffix(tokens).is_some() {
+            return Ok(mode);
+        }
+        let (AddrMode::AbsShort(expr) | AddrMode::AbsLong(expr)) = &mode else {
+            return Ok(mode);
+        };
+        let Some(base) = expr.base_symbol() else {
+            return Ok(mode);
+        };
+        let is_label = self.code_labels.contains(&self.expand_local_label(base));
+        let drift = self.forward_drift(expr);
+        let target = eval_expr_scoped(expr, self.symbols.as_map(), self.pc, self.scope())
+            .ok()
+            .map(|target| target + drift);
+        let disp = target.map(|target| target - i64::from(ext_pc));
+        let fits = disp.map(|disp| is_label && (-32768..=32767).contains(&disp));
+        let reach = match (target, disp) {
+            (Some(target), Some(disp)) if is_label => Reach::PcRelative {
+                disp,
+                target: target as u32,
+            },
+            (Some(_), _) => Reach::Fixed,
+            _ => Reach::Unknown,
+        };
+        Ok(if self.keep_short(fits, reach) {
+            AddrMode::PcDisp(expr.clone())
+        } else {
+            mode
+        })
+    }
+
+    /// Replaces EQUR names where an operand takes a register (alone, in a
+    /// register list, or after `(` or `,`) with the register's own name.
+    /// Elsewhere they stay symbols and fail to evaluate.
+    fn with_registers<'a>(&self, tokens: &'a [LocatedToken]) -> Cow<'a, [LocatedToken]> {
+        if self.register_aliases.is_empty() {
+            return Cow::Borrowed(tokens);
+        }
+        let list = tokens
+            .iter()
+            .all(|t| matches!(t.token, Token::Ident(_) | Token::Minus | Token::Slash));
+        let mut resolved = Cow::Borrowed(tokens);
+        for (i, t) in tokens.iter().enumerate() {
+            let Token::Ident(name) = &t.token else {
+                continue;
+            };
+            let in_register_slot =
+                list || i > 0 && matches!(tokens[i - 1].token, Token::LParen | Token::Comma);
+            // An index register may carry a size, as in `(a0,idx.w)`
+            let (base, suffix) = name
+                .split_once('.')
+                .map_or((name.as_str(), ""), |(b, _)| (b, &name[b.len()..]));
+            if let Some(register) = self.register_aliases.get(base).filter(|_| in_register_slot) {
+                resolved.to_mut()[i].token = Token::Ident(format!("{register}{suffix}"));
+            }
+        }
+        resolved
+    }
+
+    /// Processes an EQUR directive, naming a register for the lines after
+    /// it.
+    fn handle_equr(&mut self, label: &str, operands: &[LocatedToken]) -> Result<(), String> {
+        let register = match operands {
+            [LocatedToken {
+                token: Token::Ident(name),
+                ..
+            }] => self.register_aliases.get(name).cloned().or_else(|| {
+                parse_register(name)
+                    .map(|(n, is_addr)| format!("{}{n}", if is_addr { 'a' } else { 'd' }))
+            }),
+            _ => None,
+        };
+        let Some(register) = register else {
+            self.error_span = token_span(operands);
+            return Err("equr needs a data or address register".to_string());
+        };
+        if parse_register(label).is_some() {
+            return Err(format!("{label} is already a register"));
+        }
+        if self.symbols.as_map().contains_key(label) {
+            return Err(format!("{label} is already a symbol"));
+        }
+        if self.register_aliases.contains_key(label) {
+            return Err(format!("register alias {label} is already defined"));
+        }
+        self.register_aliases.insert(label.to_string(), register);
+        Ok(())
+    }
+
+    /// Returns the EQUR name an "undefined symbol" error is about, if it's
+    /// one: the name was used where a value was wanted.
+    fn register_alias_in<'a>(&self, message: &'a str) -> Option<&'a str> {
+        let name = undefined_symbol(message)?;
+        self.register_aliases.contains_key(name).then_some(name)
+    }
+
+    /// Explains an "undefined symbol" error about an EQUR name.
+    fn explain_register_alias(&self, message: String) -> String {
+        match self.register_alias_in(&message) {
+            Some(name) => format!("{name} is a register (EQUR), not a value"),
+            None => message,
+        }
+    }
+
+    /// Adds the symbols an undefined one might be a typo of to its error,
+    /// up to `MAX_SUGGESTIONS`. A local label is only compared with the
+    /// ones in its scope, and a global one with other globals.
+    fn suggest_symbols(&self, message: String) -> String {
+        let Some(name) = undefined_symbol(&message) else {
+            return message;
+        };
+        let symbols = self.symbols.as_map().keys();
+        let suggestions = if name.starts_with('.') {
+            let scope = self.current_scope.as_str();
+            let locals = symbols.filter_map(|symbol| {
+                symbol
+                    .strip_prefix(scope)
+                    .filter(|local| local.starts_with('.'))
+            });
+            similar_names(name, locals, MAX_SUGGESTIONS)
+        } else {
+            let globals = symbols
+                .map(String::as_str)
+                .filter(|symbol| !symbol.contains('.'));
+            similar_names(name, globals, MAX_SUGGESTIONS)
+        };
+        if suggestions.is_empty() {
+            message
+        } else {
+            format!("{message} (did you mean {}?)", suggestions.join(", "))
+        }
+    }
+
+    /// Parses an expression operand, pointing any error at the token where
+    /// parsing stopped.
+    fn parse_expr_at(&mut self, tokens: &[LocatedToken]) -> Result<Expr, String> {
+        let mut parser = ExprParser::new(tokens);
+        parser
+            .parse_expr()
+            .inspect_err(|_| self.error_span = Some(parser.span()))
     }
 
     /// Emits a byte to the output.
     pub fn emit_byte(&mut self, b: u8) {
-        if self.pass == 2 {
+        if self.in_bss() {
+            self.bss_written = true;
+        } else if self.pass == 2 {
+            let chunk = self
+                .sections
+                .get(self.current_section)
+                .and_then(|section| section.chunks.last().copied())
+                .unwrap_or(self.chunks.len().saturating_sub(1));
+            let (origin, output) = match self.chunks.get_mut(chunk) {
+                Some(chunk) if self.chunked => (chunk.origin, &mut chunk.data),
+                _ => (self.origin, &mut self.output),
+            };
             // Extend output if needed
-            let offset = (self.pc - self.origin) as usize;
-            if offset >= self.output.len() {
-                self.output.resize(offset + 1, 0);
+            let offset = (self.pc - origin) as usize;
+            if offset >= output.len() {
+                output.resize(offset + 1, 0);
+            }
+            output[offset] = b;
+            if let Some(code) = self
+                .listed
+                .as_mut()
+                .and_then(|l| l.get_mut(self.listed_line))
+            {
+                if code.bytes.len() < LISTED_BYTES {
+                    code.bytes.push(b);
+                }
+                code.size += 1;
             }
-            self.output[offset] = b;
         }
         self.pc += 1;
+        self.end = self.end.max(self.pc);
     }
 
     /// Emits a word (big-endian) to the output.
@@ -1337,10 +3236,265 @@ pub fn emit_long(&mut self, l: u32) {
     /// Aligns PC to word boundary.
     pub fn align_word(&mut self) {
         if self.pc & 1 != 0 {
-            self.emit_byte(0);
+            self.reserve(1, 0);
         }
     }
 
+    /// Returns true if code is going in a BSS section, which takes no
+    /// space in the binary. In an object it's zeros like any other data.
+    fn in_bss(&self) -> bool {
+        self.object.is_none()
+            && self
+                .sections
+                .get(self.current_section)
+                .is_some_and(|section| section.kind == SectionKind::Bss)
+    }
+
+    /// Reserves `count` bytes of space: filled with `fill`, or in a BSS
+    /// section left for the loader to clear.
+    fn reserve(&mut self, count: usize, fill: u8) {
+        if self.in_bss() {
+            self.pc += count as u32;
+            self.end = self.end.max(self.pc);
+        } else {
+            for _ in 0..count {
+                self.emit_byte(fill);
+            }
+        }
+    }
+
+    /// Notes in pass 2 that something the 68000 reads as words, or jumps
+    /// to, is at an odd address, where it faults. The line gets a warning,
+    /// or an error with `strict_alignment`.
+    fn note_odd(&mut self, address: i64, message: impl FnOnce() -> String) {
+        if self.pass == 2
+            && address & 1 != 0
+            && (self.strict_alignment || self.options.warns(WARN_ALIGN))
+        {
+            self.odd_addresses.push(message());
+        }
+    }
+
+    /// Fails a line that put code or data in a BSS section.
+    fn check_bss(&mut self) -> Result<(), String> {
+        if !std::mem::take(&mut self.bss_written) {
+            return Ok(());
+        }
+        let name = &self.sections[self.current_section].name;
+        Err(format!(
+            "{name} is a BSS section, which can only reserve space (DS)"
+        ))
+    }
+
+    /// Returns the BSS sections of the last assembly as (start, size in
+    /// bytes) pairs, the space a loader clears.
+    pub fn bss_space(&self) -> Vec<(u32, u32)> {
+        self.sections
+            .iter()
+            .filter(|section| section.kind == SectionKind::Bss && section.pc > section.start)
+            .map(|section| (section.start, section.pc - section.start))
+            .collect()
+    }
+
+    /// Assembles source code into its ORG sections, each with its origin,
+    /// or returns every error found.
+    ///
+    /// Unlike `assemble_checked`, an ORG may go back below code already
+    /// assembled. Sections without code are left out.
+    pub fn assemble_chunks(
+        &mut self,
+        source: &str,
+        file: &std::path::Path,
+    ) -> Result<Vec<Chunk>, Vec<Diagnostic>> {
+        self.chunked = true;
+        let result = self.assemble_checked(source, file);
+        self.chunked = false;
+        result?;
+        let mut chunks = std::mem::take(&mut self.chunks);
+        chunks.retain(|chunk| !chunk.data.is_empty());
+        Ok(chunks)
+    }
+
+    /// Assembles source code into its SECTIONs, in the order they're
+    /// placed, or returns every error found.
+    ///
+    /// A code or data section has an entry for each ORG in it that has
+    /// code. A BSS section has one with its size and no data, and is left
+    /// out if it's empty.
+    pub fn assemble_sections(
+        &mut self,
+        source: &str,
+        file: &std::path::Path,
+    ) -> Result<Vec<Section>, Vec<Diagnostic>> {
+        self.chunked = true;
+        let result = self.assemble_checked(source, file);
+        self.chunked = false;
+        result?;
+        let mut placed: Vec<&SectionState> = self.sections.iter().collect();
+        placed.sort_by_key(|section| section.kind);
+        let mut sections = Vec::new();
+        for section in placed {
+            if section.kind == SectionKind::Bss {
+                if section.pc > section.start {
+                    sections.push(Section {
+                        name: section.name.clone(),
+                        kind: section.kind,
+                        origin: section.start,
+                        data: Vec::new(),
+                        size: section.pc - section.start,
+                    });
+                }
+                continue;
+            }
+            for &chunk in &section.chunks {
+                let chunk = &mut self.chunks[chunk];
+                if !chunk.data.is_empty() {
+                    sections.push(Section {
+                        name: section.name.clone(),
+                        kind: section.kind,
+                        origin: chunk.origin,
+                        size: chunk.data.len() as u32,
+                        data: std::mem::take(&mut chunk.data),
+                    });
+                }
+            }
+        }
+        Ok(sections)
+    }
+
+    /// Saves where the current section got to and places each section
+    /// after the one before, word aligned: code, then data, then BSS, each
+    /// in the order declared. The next pass puts them there. Returns true
+    /// if that moves any.
+    fn place_sections(&mut self) -> bool {
+        if let Some(section) = self.sections.get_mut(self.current_section) {
+            section.pc = self.pc;
+        }
+        if self.object.is_some() {
+            return false;
+        }
+        let previous = std::mem::take(&mut self.section_bases);
+        let mut order: Vec<usize> = (0..self.sections.len()).collect();
+        order.sort_by_key(|&index| self.sections[index].kind);
+        self.section_bases = vec![0; self.sections.len()];
+        let mut next = self.origin;
+        for index in order {
+            let section = &self.sections[index];
+            let base = if section.org {
+                section.start
+            } else {
+                (next + 1) & !1
+            };
+            self.section_bases[index] = base;
+            next = next.max(base + section.pc.saturating_sub(section.start));
+        }
+        self.section_bases != previous
+    }
+
+    /// Assembles source code into a relocatable object named after the
+    /// file, or returns every error found.
+    ///
+    /// Each section starts at 0 and ORG isn't allowed; `link` places the
+    /// sections and fills in the addresses the relocations mark.
+    pub fn assemble_object(
+        &mut self,
+        source: &str,
+        file: &std::path::Path,
+    ) -> Result<ObjectFile, Vec<Diagnostic>> {
+        self.origin = 0;
+        self.object = Some(ObjectState::default());
+        self.chunked = true;
+        let result = self.assemble_checked(source, file);
+        self.chunked = false;
+        let object = self.object.take().unwrap_or_default();
+        result?;
+
+        let sections = std::mem::take(&mut self.sections)
+            .into_iter()
+            .map(|section| ObjectSection {
+                data: std::mem::take(&mut self.chunks[section.chunks[0]].data),
+                name: section.name,
+            })
+            .collect();
+        let mut exports: Vec<Export> = Vec::new();
+        for name in object.exports {
+            if exports.iter().all(|export| export.name != name) {
+                exports.push(Export {
+                    section: self.label_sections.get(&name).copied(),
+                    value: self.symbols.as_map().get(&name).copied().unwrap_or(0),
+                    name,
+                });
+            }
+        }
+        let mut imports: Vec<String> = object.imports.into_iter().collect();
+        imports.sort();
+        Ok(ObjectFile {
+            name: file.to_string_lossy().into_owned(),
+            sections,
+            exports,
+            imports,
+            relocations: object.relocations,
+        })
+    }
+
+    /// Assembles source code into a listing, or returns every error found.
+    ///
+    /// Each source line has the address it starts at and the bytes it
+    /// assembled to (see `list_expansions` for macro calls and REPT
+    /// blocks).
+    pub fn assemble_listing(
+        &mut self,
+        source: &str,
+        file: &std::path::Path,
+    ) -> Result<String, Vec<Diagnostic>> {
+        self.listed = Some(Vec::new());
+        let result = self.assemble_checked(source, file);
+        let code = self.listed.take().unwrap_or_default();
+        result?;
+        Ok(write_listing(&self.listing, &code, self.list_expansions))
+    }
+
+    /// Notes in pass 2 that the preprocessed line `line` (1-based) starts
+    /// here, for the listing.
+    fn list_line(&mut self, line: usize) {
+        self.listed_line = line.saturating_sub(1);
+        if self.pass != 2 {
+            return;
+        }
+        if let Some(code) = self
+            .listed
+            .as_mut()
+            .and_then(|l| l.get_mut(self.listed_line))
+        {
+            code.address = Some(self.pc);
+        }
+    }
+
+    /// Assembles source code into Motorola S-records, `record_length` data
+    /// bytes to a record, or returns every error found.
+    ///
+    /// The records follow the ORG sections (see `assemble_chunks`), after
+    /// an S0 header with the file's name. The termination record carries
+    /// the END address, or the origin without one.
+    pub fn assemble_srecords(
+        &mut self,
+        source: &str,
+        file: &std::path::Path,
+        record_length: usize,
+    ) -> Result<String, Vec<Diagnostic>> {
+        let chunks = self.assemble_chunks(source, file)?;
+        let header = file
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let entry = self.entry.unwrap_or(self.origin);
+        Ok(write_srecords(
+            &chunks,
+            entry,
+            header.as_bytes(),
+            record_length,
+        ))
+    }
+
     /// Returns the current scope for local label resolution.
     fn scope(&self) -> Option<&str> {
         if self.current_scope.is_empty() {
@@ -1384,37 +3538,528 @@ pub fn assemble_source(
         source: &str,
         file: &std::path::Path,
     ) -> Result<Vec<u8>, String> {
+        self.assemble_checked(source, file).map_err(|errors| {
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+
+    /// Assembles source code and returns the binary output, or every error
+    /// found. Warnings are left in `warnings`.
+    ///
+    /// A line with an error is skipped and assembly carries on, through
+    /// both passes, so one run reports every independent error, up to
+    /// `MAX_ERRORS`. Each line reports at most one, and uses of a symbol
+    /// that a failed line should have defined aren't reported.
+    ///
+    /// With `incremental` set, each assembly starts from what the last one
+    /// kept: includes whose text and context are unchanged aren't read or
+    /// preprocessed again, and neither is the source when the lines that
+    /// changed define nothing and hold no directive the preprocessor acts
+    /// on: they're patched into its expansion. Unchanged lines aren't
+    /// lexed again. If the changed lines are instructions that keep their
+    /// labels and sizes, pass 1 is skipped and pass 2 runs from where the
+    /// last one left off (see `skip_sizing`). Otherwise pass 1 starts from
+    /// the sizes, symbols and addresses it settled on, so a small edit
+    /// usually needs one run of it rather than several. Each assembly
+    /// starts from the origin and RS counter the last one did, unless
+    /// they've been set since. The
+    /// result is checked to be the one a cold assembly would settle on
+    /// (see `seed_held`): every instruction's form must be confirmed by the
+    /// final addresses, no long one may have come nearer what it reaches,
+    /// no symbol may be left over from the last assembly, and there must be
+    /// no errors, or it assembles again from scratch. A listing, sections
+    /// or an object always size from scratch, and each assembly starts from
+    /// an empty symbol table. `timings` shows what was reused.
+    pub fn assemble_checked(
+        &mut self,
+        source: &str,
+        file: &std::path::Path,
+    ) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let started = std::time::Instant::now();
+        let cached = self.incremental && self.listed.is_none();
+        self.timings = AssemblyTimings::default();
+        // ORG and RS leave the origin and RS counter where the source put
+        // them, so assemble from where the last assembly started, unless
+        // they've been set since
+        if let Some((start, end)) = self.cache.start.filter(|_| cached) {
+            if (self.origin, self.rs_counter) == end {
+                (self.origin, self.rs_counter) = start;
+            }
+        }
+        let start = (self.origin, self.rs_counter);
+
         // Preprocess
         let mut pp = Preprocessor::new();
         for inc_path in &self.include_paths {
             pp.add_include_path(inc_path.clone());
         }
-        let processed = pp.preprocess(source, file)?;
-
-        // Two-pass assembly
-        for pass in 1..=2 {
-            self.pass = pass;
-            self.pc = self.origin;
-            self.output.clear();
-
-            // Tokenize and parse
-            let mut lexer = Lexer::new(&processed, file.to_string_lossy().as_ref());
-            let tokens = lexer.tokenize()?;
-            let lines = split_lines(&tokens);
+        pp.virtual_files.clone_from(&self.virtual_files);
+        pp.max_include_depth = self.max_include_depth;
+        pp.options = Options::new(self.optimize, self.ignore_case);
+        pp.listing = self.listed.is_some().then(Vec::new);
+        if cached {
+            self.cache.includes.hits = 0;
+            self.cache.includes.patched = false;
+            pp.cache = Some(std::mem::take(&mut self.cache.includes));
+        }
+        let processed = pp.preprocess(source, file);
+        if let Some(includes) = pp.cache.take() {
+            self.timings.cached_includes = includes.hits;
+            self.timings.patched_source = includes.patched;
+            self.cache.includes = includes;
+        }
+        let processed = processed.map_err(|e| vec![e])?;
+        self.warnings = pp.warnings;
+        self.option_changes = pp.option_changes;
+        self.includes = pp.includes;
+        if let Some(listing) = pp.listing {
+            self.listing = listing;
+            self.listed = Some(vec![ListedCode::default(); processed.lines().count()]);
+        }
+        self.timings.preprocess_us = elapsed_us(started);
+
+        // Tokenize and parse
+        let lexing = std::time::Instant::now();
+        let mut log = ErrorLog::default();
+        let file_name = file.to_string_lossy();
+        let (mut tokens, lex_errors) = if cached {
+            let lexed = self.cache.lines.tokenize(&processed, &file_name);
+            self.timings.cached_lines = self.cache.lines.hits;
+            lexed
+        } else {
+            Lexer::new(&processed, file_name.as_ref()).tokenize_recovering()
+        };
+        // Fold symbol names where OPT C- (or `ignore_case`) says to.
+        // Mnemonics, registers and directives don't mind, so every name is.
+        // The cache keeps them as they were lexed.
+        let folds = self.ignore_case || self.option_changes.iter().any(|(_, o)| o.ignore_case);
+        if cached && folds {
+            self.cache.lines.keep(tokens.clone());
+        }
+        let mut fold = self.ignore_case;
+        let mut changes = self.option_changes.iter().peekable();
+        for token in &mut tokens {
+            while let Some((_, options)) = changes.next_if(|(line, _)| *line <= token.loc.line) {
+                fold = options.ignore_case;
+            }
+            if let Token::Ident(name) = &mut token.token {
+                if fold {
+                    name.make_ascii_lowercase();
+                }
+            }
+        }
+        for error in lex_errors {
+            log.push(error, None);
+        }
+        let unlexed = log.lines.clone();
+        let lines = split_lines(&tokens);
+        self.timings.lex_us = elapsed_us(lexing);
 
-            for line_tokens in lines {
-                if let Some(parsed) = parse_line(line_tokens) {
-                    self.process_line(&parsed)?;
+        // Two-pass assembly, from the last assembly's sizes if it kept them,
+        // or just pass 2 if the lines that changed allow
+        let passes = std::time::Instant::now();
+        let seed = self.cache.sizes.take().filter(|_| cached);
+        let changed = self.cache.lines.changed.clone();
+        let reused = seed.is_some_and(|seed| {
+            let (cold_log, warnings) = (log.clone(), self.warnings.len());
+            if let Some(changed) = changed {
+                if self.skip_sizing(&lines, &unlexed, &mut log, &seed, changed) {
+                    return true;
                 }
+                log.clone_from(&cold_log);
+                self.warnings.truncate(warnings);
+                (self.origin, self.rs_counter) = start;
             }
+            let settled = self.run_passes(&lines, &unlexed, &mut log, Some(seed));
+            if !settled {
+                log = cold_log;
+                self.warnings.truncate(warnings);
+            }
+            settled
+        });
+        if !reused {
+            if cached {
+                self.symbols = SymbolTable::new();
+                (self.origin, self.rs_counter) = start;
+            }
+            self.run_passes(&lines, &unlexed, &mut log, None);
+        }
+        self.timings.reused_sizes = reused;
+        self.timings.passes_us = elapsed_us(passes);
+        if cached {
+            self.cache.start = Some((start, (self.origin, self.rs_counter)));
+        }
+        let keep_sizes = self.sections.len() == 1 && self.object.is_none();
+        if cached && keep_sizes && log.diagnostics.is_empty() {
+            self.cache.sizes = Some(SizeSeed {
+                grown: self.grown.clone(),
+                site_pcs: self.site_pcs.clone(),
+                reaches: self.reaches.clone(),
+                barriers: self.barriers.clone(),
+                layout_symbols: self.layout_symbols.clone(),
+                labels: self.pass1_labels.clone(),
+                code_labels: self.code_labels.clone(),
+                section_bases: self.section_bases.clone(),
+                origin: self.origin,
+                rs_counter: self.pass1_rs_counter,
+                option_changes: self.option_changes.clone(),
+                line_sizes: std::mem::take(&mut self.line_sizes),
+            });
+        }
+        drop(lines);
+        if cached && !folds {
+            self.cache.lines.keep(tokens);
+        }
+
+        if log.diagnostics.is_empty() {
+            self.pad_rom(file, &mut log);
+        }
+        self.timings.total_us = elapsed_us(started);
+        if log.diagnostics.is_empty() {
+            Ok(std::mem::take(&mut self.output))
+        } else {
+            Err(log.finish())
+        }
+    }
+
+    /// Runs pass 1 until instructions stop growing and labels stop moving,
+    /// each run using the label addresses of the one before, then pass 2.
+    ///
+    /// Given a `seed`, pass 1 starts from its sizes and addresses and the
+    /// symbols left from the last assembly. Returns false, without running
+    /// pass 2, if what it settles on isn't sure to be what starting from
+    /// scratch would (see `assemble_checked`).
+    fn run_passes(
+        &mut self,
+        lines: &[&[LocatedToken]],
+        unlexed: &HashSet<(String, usize)>,
+        log: &mut ErrorLog,
+        seed: Option<SizeSeed>,
+    ) -> bool {
+        self.grown.clear();
+        self.site_pcs.clear();
+        self.reaches.clear();
+        self.layout_symbols.clear();
+        self.code_labels.clear();
+        self.label_sections.clear();
+        self.section_bases.clear();
+        self.rom_directive = None;
+        self.case_warned.clear();
+        self.phase_error = false;
+        let mut previous_labels = Vec::new();
+        let mut seed = seed;
+        if let Some(seed) = &mut seed {
+            self.grown.clone_from(&seed.grown);
+            self.site_pcs.clone_from(&seed.site_pcs);
+            self.reaches.clone_from(&seed.reaches);
+            self.layout_symbols = std::mem::take(&mut seed.layout_symbols);
+            self.code_labels = std::mem::take(&mut seed.code_labels);
+            self.section_bases = std::mem::take(&mut seed.section_bases);
+            previous_labels = std::mem::take(&mut seed.labels);
+        }
+        let (mut pass, mut sized) = (1, 0);
+        while pass <= 2 {
+            self.pass = pass;
+            let placed = self.run_pass(lines, unlexed, log);
 
             // After pass 1, resolve any pending EQUs with forward references
             if pass == 1 {
-                self.resolve_pending_equs()?;
+                self.resolve_pending_equs(log);
+                self.timings.sizing_passes += 1;
+                sized += 1;
+                let moved = self.labels != previous_labels || placed;
+                if (self.resize || moved) && sized < MAX_SIZING_PASSES && !log.is_full() {
+                    previous_labels = std::mem::take(&mut self.labels);
+                    continue;
+                }
+                if let Some(seed) = &seed {
+                    if !self.seed_held(log, seed) {
+                        return false;
+                    }
+                }
+                self.pass1_labels.clone_from(&self.labels);
+                self.pass1_rs_counter = self.rs_counter;
+            }
+            pass += 1;
+        }
+        true
+    }
+
+    /// Runs pass 2 alone from where the last assembly's pass 1 left off,
+    /// for a source whose only `changed` lines held instructions that
+    /// changed nothing but their code and labels, as `line_sizes` tells.
+    /// Returns false unless they still do, with the same labels and sizes,
+    /// and there are no errors: then a cold assembly's pass 1 would end the
+    /// same.
+    fn skip_sizing(
+        &mut self,
+        lines: &[&[LocatedToken]],
+        unlexed: &HashSet<(String, usize)>,
+        log: &mut ErrorLog,
+        seed: &SizeSeed,
+        changed: std::ops::Range<usize>,
+    ) -> bool {
+        let sizes = |line_sizes: &[Option<LineSize>]| {
+            changed
+                .clone()
+                .map(|line| line_sizes.get(line).cloned().flatten())
+                .collect::<Option<Vec<LineSize>>>()
+        };
+        let before = sizes(&seed.line_sizes);
+        if before.is_none() || seed.option_changes != self.option_changes {
+            return false;
+        }
+        // Everything else pass 1 sets is as the last assembly left it
+        self.origin = seed.origin;
+        self.rs_counter = seed.rs_counter;
+        self.pass1_rs_counter = seed.rs_counter;
+        self.rom_directive = None;
+        self.case_warned.clear();
+        self.phase_error = false;
+        self.pass = 2;
+        self.run_pass(lines, unlexed, log);
+        log.diagnostics.is_empty() && sizes(&self.line_sizes) == before
+    }
+
+    /// Runs the current pass over `lines`, logging errors. Returns true if
+    /// placing the sections at its end moved one.
+    fn run_pass(
+        &mut self,
+        lines: &[&[LocatedToken]],
+        unlexed: &HashSet<(String, usize)>,
+        log: &mut ErrorLog,
+    ) -> bool {
+        let pass = self.pass;
+        self.sized_count = 0;
+        self.resize = false;
+        self.unsettled = false;
+        self.barriers.clear();
+        self.layout_shifts = false;
+        self.pc = self.origin;
+        self.end = self.origin;
+        self.symbols.begin_pass();
+        self.output.clear();
+        self.chunks = vec![Chunk {
+            origin: self.origin,
+            data: Vec::new(),
+        }];
+        self.sections = vec![SectionState {
+            name: DEFAULT_SECTION.to_string(),
+            kind: SectionKind::Code,
+            chunks: vec![0],
+            start: self.origin,
+            pc: self.origin,
+            org: true,
+        }];
+        self.current_section = 0;
+        if let Some(object) = &mut self.object {
+            object.begin_pass();
+        }
+        self.labels.clear();
+        self.register_aliases.clear();
+        self.entry = None;
+        self.ended = false;
+        self.options = Options::new(self.optimize, self.ignore_case);
+        let sizing = pass == 2 && self.incremental;
+        self.line_sizes.clear();
+        let mut next_change = 0;
+
+        for line_tokens in lines {
+            if self.ended || log.is_full() {
+                break;
+            }
+            let line = line_tokens[0].loc.line;
+            while let Some((_, options)) = self
+                .option_changes
+                .get(next_change)
+                .filter(|(first, _)| *first <= line)
+            {
+                self.options = options.clone();
+                next_change += 1;
+            }
+            let Some(parsed) = parse_line(line_tokens) else {
+                if sizing {
+                    self.note_line_size(line_tokens, Some(LineSize::default()));
+                }
+                continue;
+            };
+            let label = parsed.label.as_deref();
+            let (pc, sized) = (self.pc, self.sized_count);
+            self.list_line(parsed.loc.line);
+            let failed = if unlexed.contains(&(parsed.loc.file.clone(), parsed.loc.line)) {
+                // The rest of the line is missing
+                if let Some(label) = label {
+                    log.poisoned.insert(label.to_string());
+                }
+                true
+            } else if let Err(e) = self.process_line(&parsed).and_then(|()| self.check_bss()) {
+                let (loc, length) = self
+                    .error_span
+                    .take()
+                    .or_else(|| undefined_symbol_span(&parsed.operands, &e))
+                    .unwrap_or_else(|| (parsed.loc.clone(), parsed.length));
+                let e = self.suggest_symbols(self.explain_register_alias(e));
+                log.push(Diagnostic::error(&loc, length, e), label);
+                self.phase_error |= pass == 2;
+                true
+            } else {
+                false
+            };
+            for message in std::mem::take(&mut self.odd_addresses) {
+                let (loc, length) = (&parsed.loc, parsed.length);
+                if self.strict_alignment {
+                    log.push(Diagnostic::error(loc, length, message), None);
+                } else {
+                    self.warnings
+                        .push(Diagnostic::warning(loc, length, message));
+                }
             }
+            if sizing {
+                let plain = !failed && self.encoded && self.sized_count == sized;
+                let size = plain.then(|| LineSize {
+                    label: parsed.label.clone(),
+                    size: self.pc.wrapping_sub(pc),
+                });
+                self.note_line_size(line_tokens, size);
+            }
+        }
+
+        self.place_sections()
+    }
+
+    /// Notes what the lines a statement spans assembled to, for
+    /// `line_sizes`. The lines before it without one assembled to nothing.
+    fn note_line_size(&mut self, tokens: &[LocatedToken], size: Option<LineSize>) {
+        let (first, last) = (tokens[0].loc.line, tokens[tokens.len() - 1].loc.line);
+        if self.line_sizes.len() <= last {
+            self.line_sizes.resize(last + 1, Some(LineSize::default()));
+        }
+        self.line_sizes[first..=last].fill(size);
+    }
+
+    /// Returns true if the sizes pass 1 settled on from a seed are the
+    /// ones it would have from scratch.
+    ///
+    /// From scratch, instructions start short and only grow when they
+    /// don't fit, so the long ones are the fewest that can't be short
+    /// together. The seed's were, and the same ones still are if each
+    /// reaches no nearer than it did, across the same instructions, none of
+    /// them newly long: then however many of the others are short, it
+    /// still doesn't fit, and the ones that grew since grow from scratch
+    /// too. That takes reaches that only depend on where one code label
+    /// is, and code that only moves as instructions change size, or not at
+    /// all after a barrier that no branch crosses.
+    fn seed_held(&self, log: &ErrorLog, seed: &SizeSeed) -> bool {
+        let labels: HashSet<&str> = self.labels.iter().map(|(name, _)| name.as_str()).collect();
+        // How many of the instructions come before an address
+        let before = |pcs: &[u32], address: u32| pcs.partition_point(|&pc| pc < address);
+        let crosses = |from: u32, to: u32| self.barriers.iter().any(|&at| (from < at) != (to < at));
+        // Instructions long now that weren't, which must not lie between a
+        // long one and what it reaches
+        let newly: Vec<u32> = (0..self.grown.len().min(seed.grown.len()))
+            .filter(|&i| self.grown[i] && !seed.grown[i])
+            .map(|i| self.site_pcs[i])
+            .collect();
+        let held = |index: usize, now: Reach, then: Reach| {
+            if std::mem::discriminant(&now) != std::mem::discriminant(&then) {
+                return false;
+            }
+            let (Some((value, target)), Some((was, was_target))) = (now.span(), then.span()) else {
+                return now == Reach::Fixed;
+            };
+            if !matches!(now, Reach::Address(_)) && crosses(self.site_pcs[index], target) {
+                false
+            } else if seed.grown[index] {
+                let from = self.site_pcs[index];
+                no_nearer(was, value)
+                    && before(&self.site_pcs, target) == before(&seed.site_pcs, was_target)
+                    && !newly.iter().any(|&pc| (pc < target) != (pc < from))
+            } else {
+                // Short stays short as code before shrinks, unless it's an
+                // address in the top 32K
+                self.grown[index] || value >= 0 || !matches!(now, Reach::Address(_))
+            }
+        };
+        !self.unsettled
+            && !self.resize
+            && !self.layout_shifts
+            && log.diagnostics.is_empty()
+            && self.sections.len() == 1
+            && !self.symbols.has_stale()
+            && self
+                .code_labels
+                .iter()
+                .all(|name| labels.contains(name.as_str()))
+            && self.grown.len() == seed.grown.len()
+            && self.reaches.len() == seed.reaches.len()
+            && (0..self.reaches.len()).all(|i| held(i, self.reaches[i], seed.reaches[i]))
+            && self.barriers.len() == seed.barriers.len()
+            && self
+                .barriers
+                .iter()
+                .zip(&seed.barriers)
+                .all(|(&now, &then)| before(&self.site_pcs, now) == before(&seed.site_pcs, then))
+    }
+
+    /// Pads the flat binary to the ROM image asked for, if any. An image
+    /// that's too small is blamed on the ROMSIZE line, or the start of the
+    /// file when the option asked for it.
+    fn pad_rom(&mut self, file: &std::path::Path, log: &mut ErrorLog) {
+        if self.chunked {
+            return;
+        }
+        let directive = self.rom_directive.take();
+        let (rom, loc, length) = match (self.rom, directive) {
+            (Some(rom), _) => {
+                let loc = SourceLoc {
+                    file: file.display().to_string(),
+                    line: 1,
+                    column: 1,
+                };
+                (rom, loc, 0)
+            }
+            (None, Some(directive)) => directive,
+            (None, None) => return,
+        };
+        if let Err(e) = rom.pad(&mut self.output) {
+            log.push(Diagnostic::error(&loc, length, e), None);
         }
+    }
 
-        Ok(std::mem::take(&mut self.output))
+    /// Returns the symbols of the last assembly, sorted by value and then
+    /// name. Call it before the labels are taken.
+    pub fn symbol_map(&self) -> Vec<MapSymbol> {
+        let labels: HashSet<&str> = self.labels.iter().map(|(name, _)| name.as_str()).collect();
+        let mut symbols: Vec<MapSymbol> = self
+            .symbols
+            .as_map()
+            .iter()
+            .map(|(name, &value)| {
+                let kind = if labels.contains(name.as_str()) {
+                    SymbolKind::Label
+                } else if self.symbols.is_variable(name) {
+                    SymbolKind::Variable
+                } else {
+                    SymbolKind::Constant
+                };
+                MapSymbol {
+                    name: name.clone(),
+                    value,
+                    kind,
+                    section: self
+                        .label_sections
+                        .get(name)
+                        .and_then(|&index| self.sections.get(index))
+                        .map(|section| section.name.clone()),
+                }
+            })
+            .collect();
+        symbols.sort_by(|a, b| a.value.cmp(&b.value).then_with(|| a.name.cmp(&b.name)));
+        symbols
     }
 }
 
@@ -1441,6 +4086,9 @@ pub struct ParsedLine {
     pub operands: Vec<LocatedToken>,
     /// Source location of the line.
     pub loc: SourceLoc,
+    /// Length of the statement in columns, from `loc` to the end of its
+    /// last token.
+    pub length: usize,
 }
 
 /// Parses a single line from tokens.
@@ -1452,6 +4100,9 @@ pub fn parse_line(tokens: &[LocatedToken]) -> Option<ParsedLine> {
 
     let mut pos = 0;
     let loc = tokens[0].loc.clone();
+    // A statement continued on more lines is marked on its first
+    let last = tokens.iter().rfind(|t| t.loc.line == loc.line)?;
+    let length = (last.loc.column + last.len).saturating_sub(loc.column);
 
     // Skip leading newlines
     while pos < tokens.len() && tokens[pos].token == Token::Newline {
@@ -1473,11 +4124,16 @@ pub fn parse_line(tokens: &[LocatedToken]) -> Option<ParsedLine> {
             label = Some(s.clone());
             pos += 2; // Skip identifier and colon
         }
-        // Handle EQU/RS syntax: LABEL EQU VALUE (no colon)
+        // Handle EQU/SET/RS syntax: LABEL EQU VALUE (no colon)
         else if pos + 1 < tokens.len() {
             if let Token::Ident(ref next) = tokens[pos + 1].token {
                 let upper = next.to_ascii_uppercase();
-                if upper == "EQU" || upper == "RS" || upper.starts_with("RS.") {
+                if upper == "EQU"
+                    || upper == "EQUR"
+                    || upper == "SET"
+                    || upper == "RS"
+                    || upper.starts_with("RS.")
+                {
                     label = Some(s.clone());
                     pos += 1; // Skip label, mnemonic will be parsed next
                 }
@@ -1495,6 +4151,7 @@ pub fn parse_line(tokens: &[LocatedToken]) -> Option<ParsedLine> {
             size: None,
             operands: vec![],
             loc,
+            length,
         });
     }
 
@@ -1533,42 +4190,250 @@ pub fn parse_line(tokens: &[LocatedToken]) -> Option<ParsedLine> {
         size,
         operands,
         loc,
+        length,
     })
 }
 
-/// Splits tokens into lines (separated by Newline tokens).
-pub fn split_lines(tokens: &[LocatedToken]) -> Vec<&[LocatedToken]> {
-    let mut lines = vec![];
-    let mut start = 0;
+/// Splits tokens into lines (separated by Newline tokens).
+pub fn split_lines(tokens: &[LocatedToken]) -> Vec<&[LocatedToken]> {
+    let mut lines = vec![];
+    let mut start = 0;
+
+    for (i, tok) in tokens.iter().enumerate() {
+        if tok.token == Token::Newline || tok.token == Token::Eof {
+            if i > start {
+                lines.push(&tokens[start..i]);
+            }
+            start = i + 1;
+        }
+    }
+
+    lines
+}
+
+// ============================================================================
+// PREPROCESSOR
+// ============================================================================
+
+/// How deep includes may nest by default.
+pub const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// A macro parameter's name and default value.
+type MacroParam = (String, Option<String>);
+
+/// Warning from a WARN directive, for `OPT NOWARN`.
+pub const WARN_DIRECTIVE: u32 = 1;
+/// Warning about symbols that differ only in case.
+pub const WARN_CASE: u32 = 2;
+/// Warning about an OPT option that isn't known.
+pub const WARN_OPTION: u32 = 3;
+/// Warning about word data, a branch or jump target or a code origin at an
+/// odd address.
+pub const WARN_ALIGN: u32 = 4;
+
+/// What OPT sets, from the line after it on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Options {
+    /// Use quick forms (`O+`, see `Assembler::optimize`).
+    pub optimize: bool,
+    /// Report warnings (`W+`).
+    pub warnings: bool,
+    /// List lines (`L+`).
+    pub list: bool,
+    /// Fold symbol names to lower case (`C-`, see `Assembler::ignore_case`).
+    pub ignore_case: bool,
+    /// Warning numbers not reported (`NOWARN=n[-m]`).
+    pub nowarn: Vec<std::ops::RangeInclusive<u32>>,
+}
+
+impl Options {
+    /// Starts with warnings and the listing on.
+    const fn new(optimize: bool, ignore_case: bool) -> Self {
+        Self {
+            optimize,
+            warnings: true,
+            list: true,
+            ignore_case,
+            nowarn: Vec::new(),
+        }
+    }
+
+    /// Returns true if warning `number` is reported.
+    fn warns(&self, number: u32) -> bool {
+        self.warnings && !self.nowarn.iter().any(|range| range.contains(&number))
+    }
 
-    for (i, tok) in tokens.iter().enumerate() {
-        if tok.token == Token::Newline || tok.token == Token::Eof {
-            if i > start {
-                lines.push(&tokens[start..i]);
+    /// Applies one OPT option (`O+`, `PUSH`, `NOWARN=3`...), pushing or
+    /// popping `saved` for PUSH and POP. Returns false if it isn't one.
+    fn apply(&mut self, option: &str, saved: &mut Vec<Self>) -> Result<bool, String> {
+        let upper = option.to_ascii_uppercase();
+        if let Some(numbers) = upper.strip_prefix("NOWARN=") {
+            let number = |text: &str| {
+                let text = text.trim();
+                match text.strip_prefix('$') {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => text.parse(),
+                }
+                .map_err(|_| format!("opt nowarn needs warning numbers: {option}"))
+            };
+            let range = match numbers.split_once('-') {
+                Some((first, last)) => number(first)?..=number(last)?,
+                None => number(numbers)?..=number(numbers)?,
+            };
+            self.nowarn.push(range);
+            return Ok(true);
+        }
+        match upper.as_str() {
+            "PUSH" => saved.push(self.clone()),
+            "POP" => *self = saved.pop().ok_or("opt pop without an opt push")?,
+            _ => {
+                let flag = match upper.as_bytes() {
+                    [.., b'+'] => true,
+                    [.., b'-'] => false,
+                    _ => return Ok(false),
+                };
+                match &upper[..upper.len() - 1] {
+                    "O" => self.optimize = flag,
+                    "W" => self.warnings = flag,
+                    "L" => self.list = flag,
+                    "C" => self.ignore_case = !flag,
+                    _ => return Ok(false),
+                }
             }
-            start = i + 1;
         }
+        Ok(true)
     }
+}
 
-    lines
+/// A disk file an INCLUDE read, with its size and modification time.
+struct CachedFile {
+    len: u64,
+    modified: Option<std::time::SystemTime>,
+    text: String,
 }
 
-// ============================================================================
-// PREPROCESSOR
-// ============================================================================
+/// What preprocessing an include produced, with the state it left the
+/// preprocessor in.
+struct CachedExpansion {
+    /// Hash of the include's text and of the preprocessor state before it.
+    key: (u64, u64),
+    /// Includes it read in turn, with hashes of their text.
+    nested: Vec<(ResolvedInclude, u64)>,
+    text: String,
+    lines: usize,
+    state: PreprocessorState,
+    /// Warnings and OPT changes it added, the changes' lines counted from
+    /// the include's first line.
+    warnings: Vec<Diagnostic>,
+    option_changes: Vec<(usize, Options)>,
+}
+
+/// The preprocessor state an include can change.
+#[derive(Clone)]
+struct PreprocessorState {
+    macros: HashMap<String, (Vec<MacroParam>, Vec<String>)>,
+    symbols: HashMap<String, i64>,
+    defined: HashSet<String>,
+    options: Options,
+    option_stack: Vec<Options>,
+    unique_counter: u32,
+}
+
+/// The main file's last expansion, for patching where the lines that
+/// changed since go straight through the preprocessor.
+struct CachedSource {
+    /// Hash of the file's name and what else it was preprocessed with.
+    key: u64,
+    source: String,
+    text: String,
+    /// Lines of the source that went straight through, each with the
+    /// output line it became (both 0-based), in order.
+    passed: Vec<(usize, usize)>,
+    /// Macros defined by the end of the file.
+    macros: HashSet<String>,
+    /// Includes read, with hashes of their text.
+    includes: Vec<(ResolvedInclude, u64)>,
+    warnings: Vec<Diagnostic>,
+    option_changes: Vec<(usize, Options)>,
+}
+
+/// Include files and what they expanded to, kept from one assembly to the
+/// next so unchanged includes aren't read or preprocessed again.
+///
+/// A disk file is read again when its size or modification time changes.
+/// An expansion is reused when the include's text and the preprocessor
+/// state before it (macros, symbols, options, the include chain) are the
+/// same, and each include it read still resolves to the same text.
+///
+/// The main file's expansion is patched rather than preprocessed again
+/// when the only lines that changed go straight through (see
+/// `passes_through`) and define the same names, and its includes still
+/// resolve to the same text.
+#[derive(Default)]
+pub struct IncludeCache {
+    files: HashMap<PathBuf, CachedFile>,
+    expansions: HashMap<PathBuf, CachedExpansion>,
+    source: Option<CachedSource>,
+    /// Expansions reused by the last assembly.
+    hits: usize,
+    /// The last assembly patched the main file's expansion.
+    patched: bool,
+}
+
+/// Hashes a value, for cache keys.
+fn hash_of(value: &impl std::hash::Hash) -> u64 {
+    use std::hash::Hasher;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
 
 /// Preprocessor state for handling macros, includes, conditionals.
 pub struct Preprocessor {
-    /// Macro definitions: name -> (`param_names`, `body_lines`).
-    macros: HashMap<String, (Vec<String>, Vec<String>)>,
+    /// Macro definitions: name -> (parameters with their default values,
+    /// `body_lines`).
+    macros: HashMap<String, (Vec<MacroParam>, Vec<String>)>,
     /// Include search paths.
     include_paths: Vec<PathBuf>,
+    /// In-memory files by name, searched before the disk.
+    pub virtual_files: HashMap<String, String>,
+    /// INCLUDEs resolved so far, in the order they were read.
+    pub includes: Vec<ResolvedInclude>,
     /// Unique counter for local labels in macro expansions.
     unique_counter: u32,
-    /// Stack of files being processed (for detecting circular includes).
-    file_stack: Vec<PathBuf>,
+    /// Stack of files being processed, each with the line of the INCLUDE
+    /// it's reading (for detecting circular includes).
+    file_stack: Vec<(PathBuf, usize)>,
+    /// Includes may nest this deep, a backstop for cycles that go through
+    /// different names for the same file.
+    pub max_include_depth: usize,
+    /// Lines before the block being preprocessed in its file, so a kept
+    /// IF block's diagnostics have the file's line numbers.
+    line_offset: usize,
     /// Symbol table for EQU definitions (needed for REPT expressions).
     symbols: HashMap<String, i64>,
+    /// Names defined so far (labels and EQU, SET and EQUR symbols), for
+    /// IFDEF.
+    defined: HashSet<String>,
+    /// Options in effect (see OPT).
+    pub options: Options,
+    /// Options OPT PUSH saved.
+    option_stack: Vec<Options>,
+    /// Options each OPT set, with the first output line they apply to.
+    pub option_changes: Vec<(usize, Options)>,
+    /// Warnings from WARN and OPT directives.
+    pub warnings: Vec<Diagnostic>,
+    /// The source lines and what they became, when making a listing.
+    pub listing: Option<Vec<ListingLine>>,
+    /// Lines output so far.
+    output_lines: usize,
+    /// Macro calls and REPT blocks being expanded.
+    depth: usize,
+    /// Lines of the main file that went straight through, with the output
+    /// line each became (see `CachedSource`).
+    passed: Vec<(usize, usize)>,
+    /// Includes from earlier assemblies, when assembling incrementally.
+    pub cache: Option<IncludeCache>,
 }
 
 impl Preprocessor {
@@ -1576,9 +4441,46 @@ pub fn new() -> Self {
         Self {
             macros: HashMap::new(),
             include_paths: vec![],
+            virtual_files: HashMap::new(),
+            includes: vec![],
             unique_counter: 0,
             file_stack: vec![],
+            max_include_depth: MAX_INCLUDE_DEPTH,
+            line_offset: 0,
             symbols: HashMap::new(),
+            defined: HashSet::new(),
+            options: Options::new(false, false),
+            option_stack: vec![],
+            option_changes: vec![],
+            warnings: vec![],
+            listing: None,
+            output_lines: 0,
+            depth: 0,
+            passed: vec![],
+            cache: None,
+        }
+    }
+
+    /// Starts a listing line for `text`, covering the lines output from
+    /// here until `end_listed`, and returns its index.
+    fn start_listed(&mut self, text: &str) -> usize {
+        let start = self.output_lines;
+        let Some(listing) = self.listing.as_mut().filter(|_| self.options.list) else {
+            return usize::MAX;
+        };
+        listing.push(ListingLine {
+            text: text.to_string(),
+            depth: self.depth,
+            lines: start..start,
+        });
+        listing.len() - 1
+    }
+
+    /// Ends the listing line `start_listed` returned at the lines output
+    /// so far.
+    fn end_listed(&mut self, index: usize) {
+        if let Some(line) = self.listing.as_mut().and_then(|l| l.get_mut(index)) {
+            line.lines.end = self.output_lines;
         }
     }
 
@@ -1596,14 +4498,195 @@ fn unique_suffix(&mut self) -> String {
 
     /// Preprocesses source text, expanding includes, macros, rept, and conditionals.
     /// Returns the fully expanded source.
-    pub fn preprocess(&mut self, source: &str, file: &std::path::Path) -> Result<String, String> {
-        self.file_stack.push(file.to_path_buf());
+    pub fn preprocess(
+        &mut self,
+        source: &str,
+        file: &std::path::Path,
+    ) -> Result<String, Diagnostic> {
+        let main = self.file_stack.is_empty() && self.cache.is_some() && self.listing.is_none();
+        let key = self.source_key(file);
+        if main {
+            if let Some(text) = self.patch_source(source, file, key) {
+                return Ok(text);
+            }
+        }
+        self.file_stack.push((file.to_path_buf(), 0));
+        let line_offset = std::mem::take(&mut self.line_offset);
         let result = self.preprocess_lines(source, file);
+        self.line_offset = line_offset;
         self.file_stack.pop();
+        if let (true, Ok(text)) = (main, &result) {
+            self.keep_source(source, key, text);
+        }
         result
     }
 
-    fn preprocess_lines(&mut self, source: &str, file: &std::path::Path) -> Result<String, String> {
+    /// Hashes what the main file is preprocessed with besides its text:
+    /// its name, where includes are found and the options it starts with.
+    fn source_key(&self, file: &std::path::Path) -> u64 {
+        hash_of(&(
+            file,
+            &self.include_paths,
+            self.max_include_depth,
+            &self.options,
+        ))
+    }
+
+    /// Keeps the main file's expansion for `patch_source`, with the
+    /// `source_key` it started with.
+    fn keep_source(&mut self, source: &str, key: u64, text: &str) {
+        let mut includes = Vec::new();
+        for include in self.includes.clone() {
+            let path = PathBuf::from(&include.path);
+            let Ok(text) = self.include_text(&path, include.origin) else {
+                return;
+            };
+            includes.push((include, hash_of(&text)));
+        }
+        let cached = CachedSource {
+            key,
+            source: source.to_string(),
+            text: text.to_string(),
+            passed: std::mem::take(&mut self.passed),
+            macros: self.macros.keys().cloned().collect(),
+            includes,
+            warnings: self.warnings.clone(),
+            option_changes: self.option_changes.clone(),
+        };
+        if let Some(cache) = &mut self.cache {
+            cache.source = Some(cached);
+        }
+    }
+
+    /// Returns the main file's last expansion patched for the lines of
+    /// `source` that changed, if they can be (see `IncludeCache`).
+    fn patch_source(&mut self, source: &str, file: &std::path::Path, key: u64) -> Option<String> {
+        let mut cached = self.cache.as_mut()?.source.take()?;
+        if cached.key != key {
+            return None;
+        }
+        let old: Vec<&str> = cached.source.lines().collect();
+        let new: Vec<&str> = source.lines().collect();
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let most = old.len().min(new.len()) - prefix;
+        let suffix = old
+            .iter()
+            .rev()
+            .zip(new.iter().rev())
+            .take(most)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let (removed, added) = (
+            &old[prefix..old.len() - suffix],
+            &new[prefix..new.len() - suffix],
+        );
+        let names = |lines: &[&str]| -> Vec<String> {
+            lines
+                .iter()
+                .filter_map(|line| defined_name(line).map(str::to_string))
+                .collect()
+        };
+        let plain = removed
+            .iter()
+            .chain(added)
+            .all(|line| passes_through(line, &cached.macros));
+        if !plain || names(removed) != names(added) {
+            return None;
+        }
+        // An IF block's last line doesn't come through if it's empty
+        let end = new.len() - suffix;
+        let closes_block = |line: &str| {
+            let word = line.split_whitespace().next().unwrap_or_default();
+            word.eq_ignore_ascii_case("ELSE") || word.eq_ignore_ascii_case("ENDIF")
+        };
+        if end > 0 && new[end - 1].is_empty() && new.get(end).is_some_and(|line| closes_block(line))
+        {
+            return None;
+        }
+
+        // Where the lines that changed went: after the same lines as
+        // before, or for new lines, before or after one that went through
+        let first = cached.passed.partition_point(|&(line, _)| line < prefix);
+        let out = if removed.is_empty() && added.is_empty() {
+            0
+        } else if removed.is_empty() {
+            let before = first.checked_sub(1).map(|index| cached.passed[index]);
+            match (cached.passed.get(first), before) {
+                (Some(&(line, out)), _) if line == prefix => out,
+                (_, Some((line, out))) if line + 1 == prefix => out + 1,
+                _ => return None,
+            }
+        } else {
+            let run = cached.passed.get(first..first + removed.len())?;
+            if !run.iter().zip(prefix..).all(|(&(line, _), at)| line == at) {
+                return None;
+            }
+            run[0].1
+        };
+        let unchanged = cached
+            .includes
+            .iter()
+            .all(|(include, hash)| self.include_unchanged(include, *hash));
+        if !unchanged {
+            return None;
+        }
+
+        let start = match out {
+            0 => 0,
+            out => cached.text.match_indices('\n').nth(out - 1)?.0 + 1,
+        };
+        let end = start + removed.iter().map(|line| line.len() + 1).sum::<usize>();
+        let mut text = String::with_capacity(cached.text.len());
+        text.push_str(&cached.text[..start]);
+        for line in added {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text.push_str(cached.text.get(end..)?);
+
+        // Move what comes after the lines that changed
+        let moved = |line: usize| line + added.len() - removed.len();
+        let after: Vec<_> = cached.passed[first + removed.len()..]
+            .iter()
+            .map(|&(line, out)| (moved(line), moved(out)))
+            .collect();
+        cached.passed.truncate(first);
+        cached
+            .passed
+            .extend((0..added.len()).map(|n| (prefix + n, out + n)));
+        cached.passed.extend(after);
+        for (line, _) in &mut cached.option_changes {
+            if *line > out + 1 {
+                *line = moved(*line);
+            }
+        }
+        let name = file.display().to_string();
+        for warning in &mut cached.warnings {
+            if warning.file == name && warning.line > prefix {
+                warning.line = moved(warning.line);
+            }
+        }
+        self.warnings.clone_from(&cached.warnings);
+        self.option_changes.clone_from(&cached.option_changes);
+        self.includes = cached
+            .includes
+            .iter()
+            .map(|(include, _)| include.clone())
+            .collect();
+        cached.source = source.to_string();
+        cached.text.clone_from(&text);
+        let cache = self.cache.as_mut()?;
+        cache.hits = cached.includes.len();
+        cache.patched = true;
+        cache.source = Some(cached);
+        Some(text)
+    }
+
+    fn preprocess_lines(
+        &mut self,
+        source: &str,
+        file: &std::path::Path,
+    ) -> Result<String, Diagnostic> {
         let mut output = String::new();
         let lines: Vec<&str> = source.lines().collect();
         let mut i = 0;
@@ -1612,12 +4695,29 @@ fn preprocess_lines(&mut self, source: &str, file: &std::path::Path) -> Result<S
             let line = lines[i];
             let trimmed = line.trim();
             let upper = trimmed.to_ascii_uppercase();
+            // Preprocessor diagnostics cover the whole line
+            let loc = SourceLoc {
+                file: file.display().to_string(),
+                line: self.line_offset + i + 1,
+                column: line.chars().take_while(|c| c.is_whitespace()).count() + 1,
+            };
+            let width = trimmed.chars().count();
+            let error = |message| Diagnostic::error(&loc, width, message);
 
             // Check for INCLUDE
-            if upper.starts_with("INCLUDE") || trimmed.to_ascii_lowercase().starts_with("include") {
-                let included = self.handle_include(trimmed, file)?;
+            let is_word_end =
+                |rest: &str| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+            if upper.strip_prefix("INCLUDE").is_some_and(is_word_end) {
+                if let Some((_, include_line)) = self.file_stack.last_mut() {
+                    *include_line = loc.line;
+                }
+                let (path, content) = self.read_include(trimmed, file).map_err(error)?;
+                let listed = self.start_listed(line);
+                self.end_listed(listed);
+                let included = self.preprocess_include(&content, &path)?;
                 output.push_str(&included);
                 output.push('\n');
+                self.output_lines += 1;
                 i += 1;
                 continue;
             }
@@ -1625,7 +4725,10 @@ fn preprocess_lines(&mut self, source: &str, file: &std::path::Path) -> Result<S
             // Check for MACRO definition
             if let Some(macro_line) = self.try_parse_macro_start(trimmed) {
                 let (name, params) = macro_line;
-                let (body, end_idx) = self.collect_until(&lines, i + 1, "ENDM")?;
+                let (body, end_idx) = self.collect_until(&lines, i + 1, "ENDM").map_err(error)?;
+                for line in &lines[i..=end_idx] {
+                    self.start_listed(line);
+                }
                 self.macros
                     .insert(name.to_ascii_uppercase(), (params, body));
                 i = end_idx + 1;
@@ -1634,38 +4737,68 @@ fn preprocess_lines(&mut self, source: &str, file: &std::path::Path) -> Result<S
 
             // Check for REPT
             if upper.starts_with("REPT") {
-                let count = self.parse_rept_count(trimmed)?;
-                let (body, end_idx) = self.collect_until(&lines, i + 1, "ENDR")?;
-                for _ in 0..count {
-                    for body_line in &body {
-                        output.push_str(body_line);
-                        output.push('\n');
+                let (count, counter) = self.parse_rept(trimmed).map_err(error)?;
+                let (body, end_idx) = self.collect_until(&lines, i + 1, "ENDR").map_err(error)?;
+                let listed = self.start_listed(line);
+                let mut collapsed = None;
+                self.depth += 1;
+                for n in 0..count {
+                    if n == LISTED_REPT_ITERATIONS && self.options.list {
+                        let listing = self.listing.as_ref().map_or(0, Vec::len);
+                        collapsed = Some((listing, self.output_lines));
                     }
+                    // Each copy is preprocessed again for nested blocks;
+                    // errors in it are reported at the REPT
+                    let copy = rept_iteration(&body, counter.as_deref(), n);
+                    let expanded = self
+                        .preprocess_lines(&copy, file)
+                        .map_err(|diagnostic| error(diagnostic.message))?;
+                    output.push_str(&expanded);
+                }
+                self.depth -= 1;
+                if let (Some(listing), Some((len, start))) = (&mut self.listing, collapsed) {
+                    let more = count - LISTED_REPT_ITERATIONS;
+                    listing.truncate(len);
+                    listing.push(ListingLine {
+                        text: format!(
+                            "({more} more iteration{})",
+                            if more == 1 { "" } else { "s" }
+                        ),
+                        depth: self.depth + 1,
+                        lines: start..self.output_lines,
+                    });
                 }
+                self.end_listed(listed);
                 i = end_idx + 1;
                 continue;
             }
 
-            // Check for IF/ELSE/ENDIF
-            if upper.starts_with("IF")
-                && !upper.starts_with("IFDEF")
-                && !upper.starts_with("IFNDEF")
-            {
-                let (taken_body, end_idx) = self.handle_conditional(&lines, i)?;
-                for body_line in taken_body {
-                    output.push_str(&body_line);
-                    output.push('\n');
-                }
+            // Check for IF/IFDEF/IFC.../ELSE/ENDIF
+            if conditional_directive(trimmed).is_some() {
+                let (taken, end_idx) = self.handle_conditional(&lines, i).map_err(error)?;
+                let first = taken.start;
+                output.push_str(&self.preprocess_block(&lines[taken], file, first)?);
                 i = end_idx + 1;
                 continue;
             }
 
             // Check for FAIL
-            if upper.starts_with("FAIL") {
+            if upper.split_whitespace().next() == Some("FAIL") {
+                return Err(error(fail_message(&trimmed[4..])));
+            }
+
+            // Check for WARN
+            if upper.split_whitespace().next() == Some("WARN") {
                 let msg = trimmed[4..].trim();
-                return Err(format!("{}:{}: {}", file.display(), i + 1, msg));
+                if self.options.warns(WARN_DIRECTIVE) {
+                    self.warnings.push(Diagnostic::warning(&loc, width, msg));
+                }
+                i += 1;
+                continue;
             }
 
+            self.note_definition(trimmed);
+
             // Check for EQU definitions and collect symbols for REPT expressions
             // Format: NAME EQU value  OR  NAME equ value
             if upper.contains(" EQU ")
@@ -1676,23 +4809,79 @@ fn preprocess_lines(&mut self, source: &str, file: &std::path::Path) -> Result<S
                 self.try_parse_equ(trimmed);
             }
 
+            // A statement continued over several lines is one macro call,
+            // with blank lines keeping the lines after it where they were.
+            // Anything else goes through line by line for the lexer to join.
+            let continued = lines[i..]
+                .iter()
+                .take_while(|line| continued_line(line).is_some())
+                .count()
+                .min(lines.len() - i - 1);
+            let call = if continued == 0 {
+                Cow::Borrowed(line)
+            } else {
+                Cow::Owned(
+                    lines[i..=i + continued]
+                        .iter()
+                        .map(|&line| continued_line(line).unwrap_or(line))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                )
+            };
+
             // Check for macro invocation
-            if let Some(expanded) = self.try_expand_macro(trimmed)? {
+            if let Some(expanded) = self.try_expand_macro(&call).map_err(error)? {
                 output.push_str(&expanded);
                 output.push('\n');
-                i += 1;
+                output.push_str(&"\n".repeat(continued));
+                self.output_lines += 1 + continued;
+                i += 1 + continued;
                 continue;
             }
 
             // Regular line - pass through
-            output.push_str(line);
-            output.push('\n');
+            if continued == 0 && self.depth == 0 && self.file_stack.len() == 1 {
+                self.passed.push((self.line_offset + i, self.output_lines));
+            }
+            for line in &lines[i..=i + continued] {
+                let listed = self.start_listed(line);
+                output.push_str(line);
+                output.push('\n');
+                self.output_lines += 1;
+                self.end_listed(listed);
+            }
+            i += continued;
+
+            // OPT applies from the next line on; the assembler skips it
+            if upper.split_whitespace().next() == Some("OPT") {
+                self.set_options(trimmed, &loc, width).map_err(error)?;
+                self.option_changes
+                    .push((self.output_lines + 1, self.options.clone()));
+            }
             i += 1;
         }
 
         Ok(output)
     }
 
+    /// Applies the options an OPT line lists, separated by commas, warning
+    /// about ones it doesn't know.
+    fn set_options(&mut self, line: &str, loc: &SourceLoc, width: usize) -> Result<(), String> {
+        let operands = line[3..].split(';').next().unwrap_or_default();
+        for option in operands.split(',').map(str::trim) {
+            if option.is_empty() {
+                return Err("opt requires options".to_string());
+            }
+            if !self.options.apply(option, &mut self.option_stack)?
+                && self.options.warns(WARN_OPTION)
+            {
+                let message = format!("unknown opt option: {option}");
+                self.warnings.push(Diagnostic::warning(loc, width, message));
+            }
+        }
+        Ok(())
+    }
+
     /// Try to parse an EQU definition and add to preprocessor's symbol table.
     fn try_parse_equ(&mut self, line: &str) {
         // Format: NAME EQU value
@@ -1739,11 +4928,13 @@ fn try_parse_equ(&mut self, line: &str) {
         }
     }
 
-    fn handle_include(
+    /// Resolves and reads the file an INCLUDE line names, returning its path
+    /// and contents.
+    fn read_include(
         &mut self,
         line: &str,
         current_file: &std::path::Path,
-    ) -> Result<String, String> {
+    ) -> Result<(PathBuf, String), String> {
         // Parse: include "filename" or include <filename>
         let rest = line.trim();
         let rest = if rest.to_ascii_uppercase().starts_with("INCLUDE") {
@@ -1760,6 +4951,32 @@ fn handle_include(
             rest.trim_matches('"')
         };
 
+        let (path, origin) = self.resolve_include(filename, current_file);
+        self.check_include(&path)?;
+        let content = self.include_text(&path, origin)?;
+        self.record_include(current_file, filename, &path, origin);
+        Ok((path, content))
+    }
+
+    /// Finds the file an include names.
+    ///
+    /// Virtual files are tried first (relative to the including file, then
+    /// by name), then the disk.
+    fn resolve_include(
+        &self,
+        filename: &str,
+        current_file: &std::path::Path,
+    ) -> (PathBuf, IncludeOrigin) {
+        let virtual_file = current_file
+            .parent()
+            .map(|parent| parent.join(filename))
+            .into_iter()
+            .chain([PathBuf::from(filename)])
+            .find(|path| self.virtual_files.contains_key(&*path.to_string_lossy()));
+        if let Some(path) = virtual_file {
+            return (path, IncludeOrigin::Virtual);
+        }
+
         // Resolve path relative to current file
         let include_path = if let Some(parent) = current_file.parent() {
             let relative = parent.join(filename);
@@ -1776,33 +4993,245 @@ fn handle_include(
         } else {
             PathBuf::from(filename)
         };
+        (include_path, IncludeOrigin::Disk)
+    }
 
-        // Check for circular include
-        if self.file_stack.contains(&include_path) {
-            return Err(format!(
-                "circular include detected: {}",
-                include_path.display()
-            ));
+    /// Returns the text of a resolved include. A disk file whose size and
+    /// modification time haven't changed comes from the cache.
+    fn include_text(
+        &mut self,
+        path: &std::path::Path,
+        origin: IncludeOrigin,
+    ) -> Result<String, String> {
+        if origin == IncludeOrigin::Virtual {
+            return Ok(self.virtual_files[&*path.to_string_lossy()].clone());
+        }
+        let read = || {
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+        };
+        let Some(cache) = &mut self.cache else {
+            return read();
+        };
+        let metadata = std::fs::metadata(path).ok();
+        let len = metadata.as_ref().map_or(0, std::fs::Metadata::len);
+        let modified = metadata.and_then(|m| m.modified().ok());
+        if let Some(file) = cache.files.get(path) {
+            if modified.is_some() && file.len == len && file.modified == modified {
+                return Ok(file.text.clone());
+            }
+        }
+        let text = read()?;
+        cache.files.insert(
+            path.to_path_buf(),
+            CachedFile {
+                len,
+                modified,
+                text: text.clone(),
+            },
+        );
+        Ok(text)
+    }
+
+    /// Preprocesses an included file, or reuses what it expanded to last
+    /// time (see `IncludeCache`).
+    fn preprocess_include(
+        &mut self,
+        content: &str,
+        path: &std::path::Path,
+    ) -> Result<String, Diagnostic> {
+        if self.cache.is_none() || self.listing.is_some() {
+            return self.preprocess(content, path);
+        }
+        let key = (hash_of(&content), self.state_hash());
+        if let Some(text) = self.reuse_expansion(path, key) {
+            return Ok(text);
+        }
+        let (lines, warnings, includes, changes) = (
+            self.output_lines,
+            self.warnings.len(),
+            self.includes.len(),
+            self.option_changes.len(),
+        );
+        let text = self.preprocess(content, path)?;
+        let read = self.includes[includes..].to_vec();
+        let mut nested = Vec::new();
+        for include in read {
+            let origin = include.origin;
+            let path = PathBuf::from(&include.path);
+            let Ok(nested_text) = self.include_text(&path, origin) else {
+                return Ok(text);
+            };
+            nested.push((include, hash_of(&nested_text)));
+        }
+        let expansion = CachedExpansion {
+            key,
+            nested,
+            text: text.clone(),
+            lines: self.output_lines - lines,
+            state: self.state(),
+            warnings: self.warnings[warnings..].to_vec(),
+            option_changes: self.option_changes[changes..]
+                .iter()
+                .map(|(line, options)| (line - lines, options.clone()))
+                .collect(),
+        };
+        if let Some(cache) = &mut self.cache {
+            cache.expansions.insert(path.to_path_buf(), expansion);
         }
+        Ok(text)
+    }
 
-        // Read and preprocess the included file
-        let content = std::fs::read_to_string(&include_path)
-            .map_err(|e| format!("cannot read {}: {}", include_path.display(), e))?;
+    /// Replays a cached expansion of `path` if it's still good, returning
+    /// its text.
+    fn reuse_expansion(&mut self, path: &std::path::Path, key: (u64, u64)) -> Option<String> {
+        let cache = self.cache.as_mut()?;
+        let expansion = cache.expansions.remove(path)?;
+        let unchanged = expansion.key == key
+            && expansion
+                .nested
+                .iter()
+                .all(|(include, hash)| self.include_unchanged(include, *hash));
+        let result = unchanged.then(|| {
+            let start = self.output_lines;
+            self.output_lines += expansion.lines;
+            self.warnings.extend_from_slice(&expansion.warnings);
+            self.includes
+                .extend(expansion.nested.iter().map(|(include, _)| include.clone()));
+            self.option_changes.extend(
+                expansion
+                    .option_changes
+                    .iter()
+                    .map(|(line, options)| (line + start, options.clone())),
+            );
+            self.restore(expansion.state.clone());
+            expansion.text.clone()
+        });
+        let cache = self.cache.as_mut()?;
+        cache.hits += usize::from(result.is_some());
+        cache.expansions.insert(path.to_path_buf(), expansion);
+        result
+    }
+
+    /// Returns true if an include still resolves to the same file, whose
+    /// text has the hash `hash`.
+    fn include_unchanged(&mut self, include: &ResolvedInclude, hash: u64) -> bool {
+        let from = std::path::Path::new(&include.from);
+        let (path, origin) = self.resolve_include(&include.name, from);
+        path.display().to_string() == include.path
+            && origin == include.origin
+            && self
+                .include_text(&path, origin)
+                .is_ok_and(|text| hash_of(&text) == hash)
+    }
+
+    /// Returns the state an include can change.
+    fn state(&self) -> PreprocessorState {
+        PreprocessorState {
+            macros: self.macros.clone(),
+            symbols: self.symbols.clone(),
+            defined: self.defined.clone(),
+            options: self.options.clone(),
+            option_stack: self.option_stack.clone(),
+            unique_counter: self.unique_counter,
+        }
+    }
+
+    /// Puts back the state `state` saved.
+    fn restore(&mut self, state: PreprocessorState) {
+        self.macros = state.macros;
+        self.symbols = state.symbols;
+        self.defined = state.defined;
+        self.options = state.options;
+        self.option_stack = state.option_stack;
+        self.unique_counter = state.unique_counter;
+    }
+
+    /// Hashes everything that can change what an include expands to,
+    /// besides its text.
+    fn state_hash(&self) -> u64 {
+        let mut macros: Vec<_> = self.macros.iter().collect();
+        macros.sort_unstable_by_key(|(name, _)| *name);
+        let mut symbols: Vec<_> = self.symbols.iter().collect();
+        symbols.sort_unstable();
+        let mut defined: Vec<_> = self.defined.iter().collect();
+        defined.sort_unstable();
+        let files: Vec<_> = self.file_stack.iter().map(|(file, _)| file).collect();
+        hash_of(&(
+            (macros, symbols, defined),
+            (&self.options, &self.option_stack, self.unique_counter),
+            (files, &self.include_paths, self.max_include_depth),
+        ))
+    }
+
+    /// Fails if including `path` would re-enter a file still being read or
+    /// nest deeper than `max_include_depth`, showing the include chain.
+    fn check_include(&self, path: &std::path::Path) -> Result<(), String> {
+        let same_file = |open: &std::path::Path| {
+            open == path
+                || matches!(
+                    (std::fs::canonicalize(open), std::fs::canonicalize(path)),
+                    (Ok(a), Ok(b)) if a == b
+                )
+        };
+        let circular = self.file_stack.iter().any(|(open, _)| same_file(open));
+        let too_deep = self.file_stack.len() > self.max_include_depth;
+        if !circular && !too_deep {
+            return Ok(());
+        }
+        let mut chain: Vec<String> = self
+            .file_stack
+            .iter()
+            .map(|(file, line)| format!("{}:{line}", file.display()))
+            .collect();
+        chain.push(path.display().to_string());
+        let chain = chain.join(" → ");
+        if circular {
+            Err(format!("circular include: {chain}"))
+        } else {
+            Err(format!(
+                "includes nested more than {} deep: {chain}",
+                self.max_include_depth
+            ))
+        }
+    }
 
-        self.preprocess(&content, &include_path)
+    fn record_include(
+        &mut self,
+        from: &std::path::Path,
+        name: &str,
+        path: &std::path::Path,
+        origin: IncludeOrigin,
+    ) {
+        self.includes.push(ResolvedInclude {
+            from: from.display().to_string(),
+            name: name.to_string(),
+            path: path.display().to_string(),
+            origin,
+        });
     }
 
-    fn try_parse_macro_start(&self, line: &str) -> Option<(String, Vec<String>)> {
+    /// Parses `name MACRO [param[=default],...]`, returning the name and
+    /// parameters.
+    fn try_parse_macro_start(&self, line: &str) -> Option<(String, Vec<MacroParam>)> {
         // Format: name MACRO or name macro [params]
         let parts: Vec<&str> = line.split_whitespace().collect();
         if parts.len() >= 2 {
             let upper1 = parts[1].to_ascii_uppercase();
             if upper1 == "MACRO" {
                 let name = parts[0].to_string();
-                let params: Vec<String> = parts
-                    .iter()
-                    .skip(2)
-                    .map(std::string::ToString::to_string)
+                let rest = line[name.len()..].trim_start()[upper1.len()..].trim();
+                let rest = rest.split_once(';').map_or(rest, |(params, _)| params);
+                let params = rest
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|param| !param.is_empty())
+                    .map(|param| match param.split_once('=') {
+                        Some((name, default)) => {
+                            (name.trim().to_string(), Some(default.trim().to_string()))
+                        }
+                        None => (param.to_string(), None),
+                    })
                     .collect();
                 return Some((name, params));
             }
@@ -1837,8 +5266,7 @@ fn collect_until(
                 if depth == 0 {
                     return Ok((body, i));
                 }
-            }
-            if trimmed == "ENDM" || trimmed == "ENDR" || trimmed == "ENDIF" {
+            } else if trimmed == "ENDM" || trimmed == "ENDR" || trimmed == "ENDIF" {
                 depth -= 1;
                 if depth == 0 && trimmed.starts_with(end_directive) {
                     return Ok((body, i));
@@ -1852,109 +5280,146 @@ fn collect_until(
         Err(format!("unterminated {end_directive}"))
     }
 
-    fn parse_rept_count(&self, line: &str) -> Result<usize, String> {
-        // Format: REPT count (where count can be an expression)
+    /// Parses `REPT count[,counter]`, returning the count and the name of
+    /// the counter symbol if one is given. The count must be known here,
+    /// from numbers and the EQUs before it.
+    fn parse_rept(&self, line: &str) -> Result<(usize, Option<String>), String> {
         let rest = line[4..].trim();
-
-        // Try simple numeric formats first
-        if let Some(hex) = rest.strip_prefix('$') {
-            if let Ok(n) = usize::from_str_radix(hex, 16) {
-                return Ok(n);
-            }
-        } else if let Some(bin) = rest.strip_prefix('%') {
-            if let Ok(n) = usize::from_str_radix(bin, 2) {
-                return Ok(n);
+        let rest = rest.split_once(';').map_or(rest, |(rest, _)| rest).trim();
+        let (count, counter) = match rest.rsplit_once(',') {
+            Some((count, name)) => {
+                let name = name.trim();
+                let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    return Err(format!("invalid rept counter name: {name}"));
+                }
+                (count.trim(), Some(name.to_string()))
             }
-        } else if let Ok(n) = rest.parse::<usize>() {
-            return Ok(n);
+            None => (rest, None),
+        };
+        if count.is_empty() {
+            return Err("rept requires a count".to_string());
         }
 
-        // Try to evaluate as expression using preprocessor's known symbols
-        let mut lexer = Lexer::new(rest, "rept");
-        if let Ok(tokens) = lexer.tokenize() {
-            // Filter out Eof and Newline
-            let expr_tokens: Vec<_> = tokens
-                .into_iter()
-                .filter(|t| !matches!(t.token, Token::Eof | Token::Newline))
-                .collect();
-            if !expr_tokens.is_empty() {
-                let mut parser = ExprParser::new(&expr_tokens);
-                if let Ok(expr) = parser.parse_expr() {
-                    if let Ok(val) = eval_expr(&expr, &self.symbols, 0) {
-                        if val >= 0 {
-                            return Ok(val as usize);
-                        }
-                    }
-                }
-            }
+        let mut lexer = Lexer::new(count, "rept");
+        let tokens = lexer.tokenize()?;
+        let mut parser = ExprParser::new(&tokens);
+        let expr = parser.parse_expr()?;
+        if !parser.at_end() {
+            return Err(format!("invalid rept count: {count}"));
         }
+        let value = eval_expr(&expr, &self.symbols, 0)
+            .map_err(|e| format!("rept count must be known where the REPT is ({e})"))?;
+        let value = usize::try_from(value)
+            .map_err(|_| format!("rept count must not be negative: {value}"))?;
+        Ok((value, counter))
+    }
 
-        Err(format!("invalid rept count: {rest}"))
+    /// Preprocesses lines `first..` of `file`, which a directive around
+    /// them chose, keeping the file's line numbers in diagnostics.
+    fn preprocess_block(
+        &mut self,
+        lines: &[&str],
+        file: &std::path::Path,
+        first: usize,
+    ) -> Result<String, Diagnostic> {
+        let line_offset = self.line_offset;
+        self.line_offset += first;
+        let result = self.preprocess_lines(&lines.join("\n"), file);
+        self.line_offset = line_offset;
+        result
     }
 
+    /// Evaluates the conditional directive at `start` and finds its ENDIF,
+    /// returning the range of lines to keep and the ENDIF's index.
     fn handle_conditional(
         &mut self,
         lines: &[&str],
         start: usize,
-    ) -> Result<(Vec<String>, usize), String> {
-        // Parse IF expression
+    ) -> Result<(std::ops::Range<usize>, usize), String> {
         let line = lines[start].trim();
-        let upper = line.to_ascii_uppercase();
-        let condition_str = if upper.starts_with("IF ") {
-            line[3..].trim()
-        } else {
-            line[2..].trim() // Just "IF"
+        let directive = conditional_directive(line).unwrap_or_default();
+        let operand = line[directive.len()..].trim();
+
+        let condition = match directive.as_str() {
+            "IFD" | "IFDEF" => self.is_defined(operand)?,
+            "IFND" | "IFNDEF" => !self.is_defined(operand)?,
+            "IFC" | "IFNC" => {
+                let (a, b) = ifc_strings(operand)?;
+                (a == b) == (directive == "IFC")
+            }
+            // Evaluate condition (simple: just check if it's non-zero or NARG comparison)
+            _ => self.eval_simple_condition(operand)?,
         };
 
-        // Evaluate condition (simple: just check if it's non-zero or NARG comparison)
-        let condition = self.eval_simple_condition(condition_str)?;
-
-        let mut if_body = vec![];
-        let mut else_body = vec![];
-        let mut in_else = false;
+        let mut else_idx = None;
         let mut depth = 1;
-        let mut i = start + 1;
-
-        while i < lines.len() {
-            let trimmed = lines[i].trim().to_ascii_uppercase();
-
-            if trimmed.starts_with("IF")
-                && !trimmed.starts_with("IFDEF")
-                && !trimmed.starts_with("IFNDEF")
-            {
+        for (i, line) in lines.iter().enumerate().skip(start + 1) {
+            let word = line
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_ascii_uppercase();
+            if conditional_directive(line).is_some() {
                 depth += 1;
-            }
-            if trimmed == "ENDIF" {
+            } else if word == "ENDIF" {
                 depth -= 1;
                 if depth == 0 {
-                    return Ok((if condition { if_body } else { else_body }, i));
+                    let taken = match (condition, else_idx) {
+                        (true, Some(else_idx)) => start + 1..else_idx,
+                        (true, None) => start + 1..i,
+                        (false, Some(else_idx)) => else_idx + 1..i,
+                        (false, None) => i..i,
+                    };
+                    return Ok((taken, i));
                 }
+            } else if word == "ELSE" && depth == 1 {
+                else_idx = Some(i);
             }
-            if trimmed == "ELSE" && depth == 1 {
-                in_else = true;
-                i += 1;
-                continue;
-            }
+        }
 
-            if in_else {
-                else_body.push(lines[i].to_string());
-            } else {
-                if_body.push(lines[i].to_string());
-            }
-            i += 1;
+        Err(format!("unterminated {directive} (no ENDIF)"))
+    }
+
+    /// Returns whether the symbol `operand` names has been defined above
+    /// (IFDEF doesn't evaluate it).
+    fn is_defined(&self, operand: &str) -> Result<bool, String> {
+        let name = operand
+            .split(|c: char| c.is_whitespace() || c == ';')
+            .next()
+            .unwrap_or_default();
+        if name.is_empty() {
+            return Err("ifdef requires a symbol".to_string());
         }
+        let name = if self.options.ignore_case {
+            Cow::Owned(name.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(name)
+        };
+        Ok(self.defined.contains(name.as_ref())
+            || self.symbols.contains_key(&name.to_ascii_uppercase()))
+    }
 
-        Err("unterminated IF".to_string())
+    /// Records the name a line defines for IFDEF.
+    fn note_definition(&mut self, line: &str) {
+        if let Some(name) = defined_name(line) {
+            let name = if self.options.ignore_case {
+                name.to_ascii_lowercase()
+            } else {
+                name.to_string()
+            };
+            self.defined.insert(name);
+        }
     }
 
     fn eval_simple_condition(&self, expr: &str) -> Result<bool, String> {
         // Handle simple cases: NARG>1, number, symbol comparison
         let expr = expr.trim();
 
-        // NARG comparisons (used in macros - for now return false outside macro)
-        if expr.contains("NARG") {
-            // In macro expansion context, we'd have NARG value
-            // For preprocessing stage, default to false
+        // Expansions replace NARG with the argument count, so one left over
+        // is outside a macro
+        if expr.to_ascii_uppercase().contains("NARG") {
             return Ok(false);
         }
 
@@ -1968,8 +5433,14 @@ fn eval_simple_condition(&self, expr: &str) -> Result<bool, String> {
             }
         }
 
-        // For complex expressions, default to true (will be properly evaluated later)
-        Ok(true)
+        // Evaluate with the EQUs seen so far; anything else defaults to true
+        let mut lexer = Lexer::new(expr, "if");
+        let value = lexer.tokenize().ok().and_then(|tokens| {
+            let mut parser = ExprParser::new(&tokens);
+            let expr = parser.parse_expr().ok()?;
+            eval_expr(&expr, &self.symbols, 0).ok()
+        });
+        Ok(value.is_none_or(|v| v != 0))
     }
 
     fn try_expand_macro(&mut self, line: &str) -> Result<Option<String>, String> {
@@ -1991,10 +5462,10 @@ fn try_expand_macro(&mut self, line: &str) -> Result<Option<String>, String> {
         };
 
         // Check if it's a defined macro
-        if let Some((_params, body)) = self.macros.get(&name).cloned() {
+        if let Some((params, body)) = self.macros.get(&name).cloned() {
             // Parse args: join remaining parts and split by comma
             let arg_text: String = parts[arg_start_idx..].join(" ");
-            let args: Vec<&str> = if arg_text.is_empty() {
+            let mut args: Vec<&str> = if arg_text.is_empty() {
                 vec![]
             } else {
                 arg_text
@@ -2004,9 +5475,44 @@ fn try_expand_macro(&mut self, line: &str) -> Result<Option<String>, String> {
                     .collect()
             };
 
+            // A macro that names its parameters takes at most that many, and
+            // trailing ones left out take their defaults. NARG and \# count
+            // the arguments actually passed.
+            let narg = args.len();
+            if !params.is_empty() && narg > params.len() {
+                return Err(format!(
+                    "macro {} takes at most {} argument{}, got {narg}",
+                    parts[arg_start_idx - 1],
+                    params.len(),
+                    if params.len() == 1 { "" } else { "s" }
+                ));
+            }
+            args.extend(
+                params[narg.min(params.len())..]
+                    .iter()
+                    .map_while(|(_, default)| default.as_deref()),
+            );
+            // Longest names first, so \name doesn't replace part of \names
+            let mut named: Vec<(&str, &str)> = params
+                .iter()
+                .zip(&args)
+                .map(|((param, _), arg)| (param.as_str(), *arg))
+                .collect();
+            named.sort_by_key(|(param, _)| std::cmp::Reverse(param.len()));
+
             let suffix = self.unique_suffix();
             let mut output = String::new();
 
+            // Local labels the body defines get this expansion's suffix, so
+            // each expansion has its own and they don't take the scope of the
+            // global label around the call
+            let locals = macro_local_labels(&body);
+            let own_labels = |line: &str| {
+                map_idents(line, |ident| {
+                    locals.contains(ident).then(|| format!("{ident}_{suffix}"))
+                })
+            };
+
             // Preserve label if present
             if let Some(label) = label_prefix {
                 output.push_str(label);
@@ -2040,9 +5546,9 @@ fn try_expand_macro(&mut self, line: &str) -> Result<Option<String>, String> {
                     }
 
                     // Expand: for each argument, output the rept body with \+ replaced
-                    for arg in &args {
+                    for arg in &args[..narg] {
                         for rept_line in &rept_body {
-                            let mut expanded = rept_line.clone();
+                            let mut expanded = own_labels(rept_line);
                             // Replace \@ with unique suffix
                             expanded = expanded.replace("\\@", &suffix);
                             // Replace \+ with current argument
@@ -2051,8 +5557,9 @@ fn try_expand_macro(&mut self, line: &str) -> Result<Option<String>, String> {
                             for (idx, a) in args.iter().enumerate() {
                                 expanded = expanded.replace(&format!("\\{}", idx + 1), a);
                             }
-                            // Replace \# with arg count (for any remaining)
-                            expanded = expanded.replace("\\#", &args.len().to_string());
+                            // Replace \# and NARG with arg count (for any remaining)
+                            expanded = expanded.replace("\\#", &narg.to_string());
+                            expanded = replace_word(&expanded, "NARG", &narg.to_string());
                             output.push_str(&expanded);
                             output.push('\n');
                         }
@@ -2063,7 +5570,7 @@ fn try_expand_macro(&mut self, line: &str) -> Result<Option<String>, String> {
                 }
 
                 // Normal line processing
-                let mut expanded = line.clone();
+                let mut expanded = own_labels(line);
 
                 // Replace \@@ with unique suffix + @ (vasm compatibility)
                 expanded = expanded.replace("\\@@", &format!("{suffix}@"));
@@ -2084,13 +5591,17 @@ fn try_expand_macro(&mut self, line: &str) -> Result<Option<String>, String> {
                     }
                 }
 
-                // Replace \# with argument count
-                expanded = expanded.replace("\\#", &args.len().to_string());
+                // Replace \# and NARG with argument count
+                expanded = expanded.replace("\\#", &narg.to_string());
+                expanded = replace_word(&expanded, "NARG", &narg.to_string());
 
-                // Replace \1, \2, etc. with arguments
+                // Replace \1, \2, etc. and \name with arguments
                 for (idx, arg) in args.iter().enumerate() {
                     expanded = expanded.replace(&format!("\\{}", idx + 1), arg);
                 }
+                for (param, arg) in &named {
+                    expanded = expanded.replace(&format!("\\{param}"), arg);
+                }
 
                 output.push_str(&expanded);
                 output.push('\n');
@@ -2100,18 +5611,225 @@ fn try_expand_macro(&mut self, line: &str) -> Result<Option<String>, String> {
             // Recursively preprocess the expanded output for nested macro calls
             // Use a temporary path for the expansion
             let expanded_path = std::path::Path::new("macro_expansion");
-            let reprocessed = self.preprocess_lines(&output, expanded_path)?;
+            // Errors in the expansion are reported at the invocation
+            let listed = self.start_listed(line);
+            self.depth += 1;
+            let reprocessed = self
+                .preprocess_lines(&output, expanded_path)
+                .map_err(|diagnostic| diagnostic.message)?;
+            self.depth -= 1;
+            self.end_listed(listed);
             return Ok(Some(reprocessed));
         }
-
-        Ok(None)
+
+        Ok(None)
+    }
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the name a line defines for IFDEF: a label, or an EQU, SET,
+/// EQUR or RS symbol.
+fn defined_name(line: &str) -> Option<&str> {
+    let mut words = line.split_whitespace();
+    let first = words.next()?;
+    let name = match first.split_once(':') {
+        Some((label, _)) => label,
+        None => match words.next().map(str::to_ascii_uppercase) {
+            Some(word) if matches!(word.as_str(), "EQU" | "SET" | "EQUR") => first,
+            Some(word) if word == "RS" || word.starts_with("RS.") => first,
+            _ => return None,
+        },
+    };
+    (!name.is_empty() && !name.starts_with('.')).then_some(name)
+}
+
+/// Returns true if the preprocessor passes `line` straight through,
+/// changing nothing but the names IFDEF sees: it isn't an INCLUDE, a
+/// directive that starts or ends a block, FAIL, WARN, OPT or EQU (which
+/// the preprocessor evaluates), a call to one of `macros` or a
+/// continuation. Erring on the side of false is fine.
+fn passes_through(line: &str, macros: &HashSet<String>) -> bool {
+    let upper = line.trim().to_ascii_uppercase();
+    let words: Vec<&str> = upper.split_whitespace().collect();
+    let call = match words[..] {
+        [label, name, ..] if label.ends_with(':') => name,
+        [name, ..] => name,
+        [] => "",
+    };
+    !["INCLUDE", "MACRO", "REPT", "EQU"]
+        .iter()
+        .any(|word| upper.contains(word))
+        && conditional_directive(&upper).is_none()
+        && !matches!(
+            words.first().copied(),
+            Some("FAIL" | "WARN" | "OPT" | "ELSE" | "ENDIF" | "ENDM" | "ENDR")
+        )
+        && continued_line(line).is_none()
+        && !macros.contains(call)
+}
+
+/// Replaces `word` (matched case-insensitively) where it stands as a whole
+/// identifier in `text`.
+fn replace_word(text: &str, word: &str, with: &str) -> String {
+    map_idents(text, |ident| {
+        ident.eq_ignore_ascii_case(word).then(|| with.to_string())
+    })
+}
+
+/// Replaces each identifier (including local `.labels`) in `text` that `f`
+/// returns a replacement for.
+fn map_idents(text: &str, f: impl Fn(&str) -> Option<String>) -> String {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_ident) {
+        let end = rest[start..]
+            .find(|c: char| !is_ident(c))
+            .map_or(rest.len(), |n| start + n);
+        result.push_str(&rest[..start]);
+        let ident = &rest[start..end];
+        match f(ident) {
+            Some(replacement) => result.push_str(&replacement),
+            None => result.push_str(ident),
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Returns a line that ends in a line continuation (`\\`) without it, or
+/// None if it doesn't. A backslash in a string or comment doesn't continue
+/// the line.
+fn continued_line(line: &str) -> Option<&str> {
+    let statement = line.trim_end().strip_suffix('\\')?;
+    if statement.starts_with('*') {
+        return None;
+    }
+    let mut quote = None;
+    let mut chars = statement.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, ';') => return None,
+            _ => {}
+        }
+    }
+    quote.is_none().then_some(statement)
+}
+
+/// Returns the conditional directive (IF, IFD/IFDEF, IFND/IFNDEF, IFC or
+/// IFNC) a line starts with, in upper case.
+fn conditional_directive(line: &str) -> Option<String> {
+    let word = line.split_whitespace().next()?.to_ascii_uppercase();
+    matches!(
+        word.as_str(),
+        "IF" | "IFD" | "IFDEF" | "IFND" | "IFNDEF" | "IFC" | "IFNC"
+    )
+    .then_some(word)
+}
+
+/// Returns the message of a FAIL directive with `operand`: the operand,
+/// without its quotes if it's a quoted string.
+fn fail_message(operand: &str) -> String {
+    let operand = operand.trim();
+    let quoted = ifc_string(operand).filter(|(_, rest)| {
+        let rest = rest.trim_start();
+        rest.is_empty() || rest.starts_with(';')
+    });
+    match quoted {
+        Some((message, _)) if operand.starts_with(['\'', '"']) => message,
+        _ if operand.is_empty() => "fail directive reached".to_string(),
+        _ => operand.to_string(),
+    }
+}
+
+/// Parses IFC's operands, two strings quoted with `'` or `"` (or bare, up
+/// to the comma) separated by a comma.
+fn ifc_strings(operand: &str) -> Result<(String, String), String> {
+    let error = || format!("ifc needs two strings, as in 'a','b': {operand}");
+    let (first, rest) = ifc_string(operand).ok_or_else(error)?;
+    let rest = rest.trim_start().strip_prefix(',').ok_or_else(error)?;
+    let (second, rest) = ifc_string(rest).ok_or_else(error)?;
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with(';') {
+        return Err(error());
+    }
+    Ok((first, second))
+}
+
+/// Reads one of IFC's strings, returning it and the text after it.
+fn ifc_string(text: &str) -> Option<(String, &str)> {
+    let text = text.trim_start();
+    match text.chars().next() {
+        Some(quote @ ('\'' | '"')) => {
+            let end = text[1..].find(quote)? + 1;
+            Some((text[1..end].to_string(), &text[end + 1..]))
+        }
+        _ => {
+            let end = text.find([',', ';']).unwrap_or(text.len());
+            Some((text[..end].trim_end().to_string(), &text[end..]))
+        }
+    }
+}
+
+/// Returns REPT iteration `n`'s copy of `body`: `REPTN`, and the counter
+/// if the REPT names one, read as `n`, except inside nested REPTs that
+/// bind the same name.
+fn rept_iteration(body: &[String], counter: Option<&str>, n: usize) -> String {
+    let names: Vec<&str> = std::iter::once("REPTN").chain(counter).collect();
+    // The names each enclosing nested REPT binds
+    let mut nested: Vec<[String; 2]> = Vec::new();
+    let mut copy = String::new();
+    for line in body {
+        let upper = line.trim().to_ascii_uppercase();
+        let directive = upper.split_whitespace().next().unwrap_or_default();
+        // A nested REPT's own counter name isn't replaced
+        let inner_counter = if directive == "REPT" {
+            let code = line.split_once(';').map_or(line.as_str(), |(code, _)| code);
+            code.rsplit_once(',')
+                .map(|(_, name)| name.trim().to_string())
+        } else {
+            None
+        };
+        let replaced = map_idents(line, |ident| {
+            let shadowed = inner_counter.as_deref() == Some(ident)
+                || nested
+                    .iter()
+                    .flatten()
+                    .any(|name| name.eq_ignore_ascii_case(ident));
+            let bound = names.iter().any(|name| name.eq_ignore_ascii_case(ident));
+            (bound && !shadowed).then(|| n.to_string())
+        });
+        if directive == "REPT" {
+            nested.push(["REPTN".to_string(), inner_counter.unwrap_or_default()]);
+        } else if directive == "ENDR" {
+            nested.pop();
+        }
+        copy.push_str(&replaced);
+        copy.push('\n');
     }
+    copy
 }
 
-impl Default for Preprocessor {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Returns the local labels (`.name:`) a macro body defines.
+fn macro_local_labels(body: &[String]) -> HashSet<String> {
+    body.iter()
+        .filter_map(|line| {
+            let (label, _) = line.split_whitespace().next()?.split_once(':')?;
+            (label.starts_with('.') && !label.contains('\\')).then(|| label.to_string())
+        })
+        .collect()
 }
 
 // ============================================================================
@@ -2120,16 +5838,180 @@ fn default() -> Self {
 
 impl Assembler {
     /// Processes an ORG directive.
-    pub fn handle_org(&mut self, operands: &[LocatedToken], loc: &SourceLoc) -> Result<(), String> {
+    pub fn handle_org(&mut self, operands: &[LocatedToken]) -> Result<(), String> {
         if operands.is_empty() {
-            return Err(format!("{loc}: org requires an address"));
+            return Err("org requires an address".to_string());
         }
-        let mut parser = ExprParser::new(operands);
-        let expr = parser.parse_expr()?;
-        let addr = eval_expr(&expr, self.symbols.as_map(), self.pc)?;
-        self.pc = addr as u32;
-        if self.pass == 1 && self.origin == 0 {
-            self.origin = self.pc;
+        if self.object.is_some() {
+            return Err(
+                "org can't be used in an object (the linker places its sections)".to_string(),
+            );
+        }
+        let expr = self.parse_expr_at(operands)?;
+        let addr = eval_expr(&expr, self.symbols.as_map(), self.pc)? as u32;
+        if self.sized_count > 0 {
+            self.barriers.push(addr);
+            self.layout_shifts |= self.layout_dependent(&expr);
+        }
+        let section = self.sections.get(self.current_section);
+        if section.is_none_or(|section| section.kind == SectionKind::Code) {
+            self.note_odd(addr.into(), || {
+                format!("org ${addr:X} is odd, so code after it faults (use an even address)")
+            });
+        }
+        if self.current_section > 0 {
+            return self.org_section(addr);
+        }
+        // The first ORG sets the origin, unless code came before it
+        if self.pass == 1 && self.origin == 0 && self.end == 0 {
+            self.origin = addr;
+            self.end = addr;
+            if let Some(section) = self.sections.first_mut() {
+                section.start = addr;
+            }
+        }
+        if !self.chunked && addr < self.end {
+            return Err(format!(
+                "org ${addr:X} is below code already assembled (up to ${:X})",
+                self.end
+            ));
+        }
+        self.pc = addr;
+        if self.chunked {
+            self.chunks.push(Chunk {
+                origin: addr,
+                data: Vec::new(),
+            });
+            if let Some(section) = self.sections.first_mut() {
+                section.chunks.push(self.chunks.len() - 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Places the current section, other than the default one, at `addr`
+    /// instead of after the section before. It has to come before the
+    /// section's code.
+    fn org_section(&mut self, addr: u32) -> Result<(), String> {
+        let section = &mut self.sections[self.current_section];
+        if self.pc != section.start {
+            return Err(format!(
+                "org in section {} has to come before its code",
+                section.name
+            ));
+        }
+        if !self.chunked && addr < self.origin {
+            return Err(format!(
+                "org ${addr:X} is below the origin (${:X})",
+                self.origin
+            ));
+        }
+        section.start = addr;
+        section.org = true;
+        if let Some(&chunk) = section.chunks.last() {
+            self.chunks[chunk].origin = addr;
+        }
+        self.pc = addr;
+        Ok(())
+    }
+
+    /// Processes a SECTION directive, which switches to the named section
+    /// (creating it) and carries on where that section got to. A type,
+    /// `code` (or `text`), `data` or `bss`, may follow the name; without
+    /// one, a section named after a type has that type, and any other is
+    /// code.
+    pub fn handle_section(&mut self, operands: &[LocatedToken]) -> Result<(), String> {
+        let ops = split_operands(operands);
+        let name_of = |tokens: &[LocatedToken]| match tokens {
+            [LocatedToken {
+                token: Token::Ident(name) | Token::String(name),
+                ..
+            }] => Some(name.clone()),
+            _ => None,
+        };
+        let (Some(name), true) = (
+            ops.first().and_then(|tokens| name_of(tokens)),
+            ops.len() <= 2,
+        ) else {
+            return Err("section takes a name and an optional type".to_string());
+        };
+        let kind = match ops.get(1) {
+            Some(tokens) => {
+                let kind = name_of(tokens).and_then(|kind| SectionKind::from_name(&kind));
+                Some(kind.ok_or("section type must be code, data or bss")?)
+            }
+            None => None,
+        };
+
+        self.sections[self.current_section].pc = self.pc;
+        self.current_section = match self.sections.iter().position(|s| s.name == name) {
+            Some(index) => {
+                if kind.is_some_and(|kind| kind != self.sections[index].kind) {
+                    return Err(format!("section {name} was declared with another type"));
+                }
+                index
+            }
+            None => {
+                let start = if self.object.is_some() {
+                    0
+                } else {
+                    let index = self.sections.len();
+                    self.section_bases
+                        .get(index)
+                        .copied()
+                        .unwrap_or((self.end + 1) & !1)
+                };
+                self.chunks.push(Chunk {
+                    origin: start,
+                    data: Vec::new(),
+                });
+                self.sections.push(SectionState {
+                    kind: kind.unwrap_or_else(|| SectionKind::of_section(&name)),
+                    name,
+                    chunks: vec![self.chunks.len() - 1],
+                    start,
+                    pc: start,
+                    org: false,
+                });
+                self.sections.len() - 1
+            }
+        };
+        self.pc = self.sections[self.current_section].pc;
+        Ok(())
+    }
+
+    /// Processes an XDEF directive, which exports labels or EQU symbols
+    /// from an object. Outside one there's nothing to export to.
+    pub fn handle_xdef(&mut self, operands: &[LocatedToken]) -> Result<(), String> {
+        for name in symbol_names("xdef", operands)? {
+            let Some(object) = &mut self.object else {
+                continue;
+            };
+            if self.pass == 2 {
+                if object.imports.contains(&name) {
+                    return Err(format!("{name} is imported (XREF) and can't be exported"));
+                }
+                if !self.symbols.as_map().contains_key(&name) {
+                    return Err(format!("undefined symbol: {name}"));
+                }
+            }
+            object.exports.push(name);
+        }
+        Ok(())
+    }
+
+    /// Processes an XREF directive, which names symbols another object
+    /// exports. Each is 0 here, the linker adding its address.
+    pub fn handle_xref(&mut self, operands: &[LocatedToken]) -> Result<(), String> {
+        for name in symbol_names("xref", operands)? {
+            let Some(object) = &mut self.object else {
+                return Err(format!("{name} can only be imported into an object"));
+            };
+            if self.code_labels.contains(&name) {
+                return Err(format!("{name} is defined here and can't be imported"));
+            }
+            object.imports.insert(name.clone());
+            self.symbols.import(&name);
         }
         Ok(())
     }
@@ -2144,73 +6026,282 @@ pub fn handle_equ(
         loc: &SourceLoc,
     ) -> Result<(), String> {
         if operands.is_empty() {
-            return Err(format!("{loc}: equ requires a value"));
+            return Err("equ requires a value".to_string());
+        }
+        let expr = self.parse_expr_at(operands)?;
+        if self.layout_dependent(&expr) {
+            self.layout_symbols.insert(label.to_string());
         }
-        let mut parser = ExprParser::new(operands);
-        let expr = parser.parse_expr()?;
 
         match eval_expr(&expr, self.symbols.as_map(), self.pc) {
             Ok(value) => {
                 self.symbols.define(label, value)?;
             }
-            Err(_) if self.pass == 1 => {
+            Err(e) if self.pass == 1 && self.register_alias_in(&e).is_none() => {
                 // Forward reference - store as pending
-                self.pending_equs.push((label.to_string(), expr));
+                self.pending_equs
+                    .push((label.to_string(), expr, loc.clone()));
             }
             Err(e) => return Err(e),
         }
         Ok(())
     }
 
-    /// Resolves pending EQU definitions that had forward references.
-    fn resolve_pending_equs(&mut self) -> Result<(), String> {
+    /// Warns in pass 2, once per name, when a symbol being defined differs
+    /// only in case from another one, which with case-sensitive symbols is
+    /// likely a mistake.
+    fn warn_case_clash(&mut self, name: &str, loc: &SourceLoc, length: usize) {
+        if self.pass != 2 || self.options.ignore_case || !self.options.warns(WARN_CASE) {
+            return;
+        }
+        if let Some(other) = self.symbols.case_clash(name) {
+            if self.case_warned.insert(name.to_ascii_lowercase()) {
+                let message = format!("{name} and {other} differ only in case");
+                self.warnings
+                    .push(Diagnostic::warning(loc, length, message));
+            }
+        }
+    }
+
+    /// Checks in pass 2 that the code label about to be defined here gets
+    /// the address it had at the end of pass 1, which the code before it
+    /// was assembled with. Labels are matched by definition order, as a
+    /// name may be defined more than once.
+    fn check_phase(&mut self, name: &str) -> Result<(), String> {
+        if self.pass != 2 || self.phase_error {
+            return Ok(());
+        }
+        match self.pass1_labels.get(self.labels.len()) {
+            Some(&(_, before)) if before != self.pc => {
+                self.phase_error = true;
+                Err(format!(
+                    "phase error: {name} is ${:X} in pass 2 but was ${before:X} in pass 1 (an operand's size depends on a later symbol)",
+                    self.pc
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolves pending EQU definitions that had forward references,
+    /// logging the ones that can't be.
+    fn resolve_pending_equs(&mut self, log: &mut ErrorLog) {
         let mut made_progress = true;
         while made_progress && !self.pending_equs.is_empty() {
             made_progress = false;
             let pending = std::mem::take(&mut self.pending_equs);
-            for (label, expr) in pending {
+            for (label, expr, loc) in pending {
                 match eval_expr(&expr, self.symbols.as_map(), self.pc) {
                     Ok(value) => {
-                        self.symbols.define(&label, value)?;
+                        if let Err(e) = self.symbols.define(&label, value) {
+                            log.push(Diagnostic::error(&loc, label.len(), e), Some(&label));
+                        }
                         made_progress = true;
                     }
                     Err(_) => {
                         // Still can't resolve, put back
-                        self.pending_equs.push((label, expr));
+                        self.pending_equs.push((label, expr, loc));
                     }
                 }
             }
         }
 
-        // If any are still pending, that's an error
-        if !self.pending_equs.is_empty() {
-            let labels: Vec<_> = self.pending_equs.iter().map(|(l, _)| l.as_str()).collect();
-            return Err(format!("unresolved symbols: {}", labels.join(", ")));
+        // Any still pending are errors, unless they use a symbol that is
+        // missing because of an earlier one
+        for (label, expr, loc) in std::mem::take(&mut self.pending_equs) {
+            if expr.mentions(&log.poisoned) {
+                log.poisoned.insert(label);
+            } else {
+                let message = format!("unresolved symbol: {label}");
+                log.push(Diagnostic::error(&loc, label.len(), message), Some(&label));
+            }
+        }
+    }
+
+    /// Processes an END directive: the source ends here, and the optional
+    /// operand names the entry point.
+    pub fn handle_end(&mut self, operands: &[LocatedToken]) -> Result<(), String> {
+        if !operands.is_empty() {
+            let expr = self.parse_expr_at(operands)?;
+            // Use tolerant evaluation in pass 1 for forward references
+            let entry = if self.pass == 1 {
+                eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope()).unwrap_or(0)
+            } else {
+                eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope())?
+            };
+            self.entry = Some(entry as u32);
         }
+        self.ended = true;
         Ok(())
     }
 
+    /// Processes a SET directive, assigning a reassignable symbol.
+    pub fn handle_set(&mut self, label: &str, operands: &[LocatedToken]) -> Result<(), String> {
+        if operands.is_empty() {
+            return Err("set requires a value".to_string());
+        }
+        let expr = self.parse_expr_at(operands)?;
+        if self.layout_dependent(&expr) {
+            self.layout_symbols.insert(label.to_string());
+        }
+        // Use tolerant evaluation in pass 1 for forward references
+        let value = if self.pass == 1 {
+            eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope()).unwrap_or(0)
+        } else {
+            eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope())?
+        };
+        self.symbols.assign(label, value)
+    }
+
     /// Processes an EVEN directive.
     pub fn handle_even(&mut self) {
         self.align_word();
     }
 
+    /// Processes an ALIGN directive: `ALIGN n[,fill]` pads with the fill
+    /// byte (0 by default) up to a multiple of `n`, a power of two.
+    ///
+    /// Both values must be known when the line is reached, so the padding
+    /// is the same in both passes.
+    pub fn handle_align(&mut self, operands: &[LocatedToken]) -> Result<(), String> {
+        if operands.is_empty() {
+            return Err("align requires a boundary".to_string());
+        }
+        let ops = split_operands(operands);
+        if ops.len() > 2 {
+            return Err("align takes a boundary and an optional fill byte".to_string());
+        }
+        let boundary = alignment("align boundary", self.known_value(ops[0])?)?;
+        let fill = match ops.get(1) {
+            Some(tokens) => {
+                let fill = self.known_value(tokens)?;
+                u8::try_from(fill)
+                    .or_else(|_| i8::try_from(fill).map(|fill| fill as u8))
+                    .map_err(|_| format!("align fill must be a byte: {fill}"))?
+            }
+            None => 0,
+        };
+        self.pad_to(0, boundary, fill);
+        Ok(())
+    }
+
+    /// Processes a CNOP directive: `CNOP offset,n` pads with zero bytes up
+    /// to the next address that is `offset` past a multiple of `n`, so
+    /// `CNOP 0,n` is `ALIGN n`.
+    ///
+    /// Like ALIGN, the values must be known when the line is reached. The
+    /// padding depends only on them and the current address, so once pass 1
+    /// has settled every instruction's size both passes pad the same.
+    pub fn handle_cnop(&mut self, operands: &[LocatedToken]) -> Result<(), String> {
+        let ops = split_operands(operands);
+        if ops.len() != 2 || ops.iter().any(|tokens| tokens.is_empty()) {
+            return Err("cnop takes an offset and an alignment".to_string());
+        }
+        let offset = self.known_value(ops[0])?;
+        let boundary = alignment("cnop alignment", self.known_value(ops[1])?)?;
+        if !(0..i64::from(boundary)).contains(&offset) {
+            return Err(format!(
+                "cnop offset must be from 0 to {}: {offset}",
+                boundary - 1
+            ));
+        }
+        self.pad_to(offset as u32, boundary, 0);
+        Ok(())
+    }
+
+    /// Evaluates an operand that must be known where it is written.
+    fn known_value(&self, tokens: &[LocatedToken]) -> Result<i64, String> {
+        let mut parser = ExprParser::new(tokens);
+        let expr = parser.parse_expr()?;
+        eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope())
+    }
+
+    /// Processes ASSERT expr[,message], an error when the expression is
+    /// zero. It's checked in pass 2, when every label is known, and the
+    /// error gives the values of the symbols the expression uses.
+    pub fn handle_assert(&mut self, operands: &[LocatedToken]) -> Result<(), String> {
+        let ops = split_operands(operands);
+        let message = match ops.as_slice() {
+            [_] => None,
+            [_, message] => match message {
+                [LocatedToken {
+                    token: Token::String(message),
+                    ..
+                }] => Some(message.clone()),
+                _ => return Err("assert message must be in quotes".to_string()),
+            },
+            _ => return Err("assert takes an expression and a message".to_string()),
+        };
+        let expr = self.parse_expr_at(ops[0])?;
+        if self.pass != 2 {
+            return Ok(());
+        }
+        let symbols = self.symbols.as_map();
+        if eval_expr_scoped(&expr, symbols, self.pc, self.scope())? != 0 {
+            return Ok(());
+        }
+        self.error_span = token_span(ops[0]);
+        let mut names = Vec::new();
+        expr.symbol_names(&mut names);
+        let values: Vec<_> = names
+            .iter()
+            .filter_map(|&name| {
+                let value = Expr::Symbol(name.to_string());
+                let value = eval_expr_scoped(&value, symbols, self.pc, self.scope()).ok()?;
+                Some(if value < 0 {
+                    format!("{name} = -${:X}", value.unsigned_abs())
+                } else {
+                    format!("{name} = ${value:X}")
+                })
+            })
+            .collect();
+        let message = message.unwrap_or_else(|| "assertion failed".to_string());
+        if values.is_empty() {
+            Err(message)
+        } else {
+            Err(format!("{message} ({})", values.join(", ")))
+        }
+    }
+
+    /// Emits fill bytes until the address is `offset` past a multiple of
+    /// `boundary`.
+    fn pad_to(&mut self, offset: u32, boundary: u32, fill: u8) {
+        // Every instruction's long form is an even number of bytes longer
+        self.layout_shifts |= boundary > 2 && self.sized_count > 0;
+        let count = (offset + boundary - self.pc % boundary) % boundary;
+        self.reserve(count as usize, fill);
+    }
+
     /// Processes DC.B/W/L directive.
-    pub fn handle_dc(
-        &mut self,
-        size: Size,
-        operands: &[LocatedToken],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    pub fn handle_dc(&mut self, size: Size, operands: &[LocatedToken]) -> Result<(), String> {
         if operands.is_empty() {
-            return Err(format!("{loc}: dc requires data"));
+            return Err("dc requires data".to_string());
+        }
+        if size != Size::Byte {
+            let pc = self.pc;
+            self.note_odd(pc.into(), || {
+                format!(
+                    "dc.{} at odd address ${pc:X} (add EVEN before it)",
+                    &size.name()[..1]
+                )
+            });
         }
 
         // Parse comma-separated values
         let mut pos = 0;
         while pos < operands.len() {
-            // Check for string literal
-            if let Token::String(ref s) = operands[pos].token {
+            // A string on its own is its characters, one to an element,
+            // unless it's a character constant that fits one word or long
+            let alone = operands
+                .get(pos + 1)
+                .is_none_or(|next| next.token == Token::Comma);
+            let string = match &operands[pos].token {
+                Token::String(s) if alone && (size == Size::Byte || s.len() > size.bytes()) => {
+                    Some(s)
+                }
+                _ => None,
+            };
+            if let Some(s) = string {
                 for byte in s.bytes() {
                     match size {
                         Size::Byte => self.emit_byte(byte),
@@ -2230,8 +6321,8 @@ pub fn handle_dc(
                     pos += 1;
                     continue;
                 }
-                let mut parser = ExprParser::new(&expr_tokens);
-                let expr = parser.parse_expr()?;
+                let expr = self.parse_expr_at(&expr_tokens)?;
+                self.relocate_value(&expr, size, self.pc)?;
                 // Use tolerant evaluation in pass 1 for forward references
                 let value = if self.pass == 1 {
                     eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope())
@@ -2239,11 +6330,7 @@ pub fn handle_dc(
                 } else {
                     eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope())?
                 };
-                match size {
-                    Size::Byte => self.emit_byte(value as u8),
-                    Size::Word => self.emit_word(value as u16),
-                    Size::Long => self.emit_long(value as u32),
-                }
+                self.emit_data(size, value)?;
                 pos += expr_tokens.len();
             }
 
@@ -2256,84 +6343,162 @@ pub fn handle_dc(
     }
 
     /// Processes DCB.B/W/L directive (define constant block - fill with repeated value).
-    pub fn handle_dcb(
-        &mut self,
-        size: Size,
-        operands: &[LocatedToken],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    pub fn handle_dcb(&mut self, size: Size, operands: &[LocatedToken]) -> Result<(), String> {
         if operands.is_empty() {
-            return Err(format!("{loc}: dcb requires count"));
+            return Err("dcb requires count".to_string());
+        }
+        if size != Size::Byte {
+            let pc = self.pc;
+            self.note_odd(pc.into(), || {
+                format!(
+                    "dcb.{} at odd address ${pc:X} (add EVEN before it)",
+                    &size.name()[..1]
+                )
+            });
         }
 
         // Parse count,value (comma-separated) or just count (fills with 0)
         let ops = split_operands(operands);
 
-        // Parse count expression
-        let mut parser = ExprParser::new(ops[0]);
-        let count_expr = parser.parse_expr()?;
-        let count = if self.pass == 1 {
-            eval_expr_scoped(&count_expr, self.symbols.as_map(), self.pc, self.scope()).unwrap_or(0)
-        } else {
-            eval_expr_scoped(&count_expr, self.symbols.as_map(), self.pc, self.scope())?
-        } as usize;
+        // Parse count expression, which must be known in pass 1 so labels
+        // after the block don't move
+        let count_expr = self.parse_expr_at(ops[0])?;
+        let count = eval_expr_scoped(&count_expr, self.symbols.as_map(), self.pc, self.scope())?;
+        let count =
+            usize::try_from(count).map_err(|_| format!("dcb count can't be negative: {count}"))?;
+        self.note_block(&count_expr, size.bytes(), count * size.bytes());
 
         // Parse value expression (optional, defaults to 0)
-        let value = if ops.len() >= 2 {
-            let mut parser = ExprParser::new(ops[1]);
-            let value_expr = parser.parse_expr()?;
+        let value_expr = match ops.get(1) {
+            Some(tokens) => Some(self.parse_expr_at(tokens)?),
+            None => None,
+        };
+        let value = if let Some(value_expr) = &value_expr {
             if self.pass == 1 {
-                eval_expr_scoped(&value_expr, self.symbols.as_map(), self.pc, self.scope())
+                eval_expr_scoped(value_expr, self.symbols.as_map(), self.pc, self.scope())
                     .unwrap_or(0)
             } else {
-                eval_expr_scoped(&value_expr, self.symbols.as_map(), self.pc, self.scope())?
+                eval_expr_scoped(value_expr, self.symbols.as_map(), self.pc, self.scope())?
             }
         } else {
             0
         };
 
-        // Emit the repeated value
-        for _ in 0..count {
-            match size {
-                Size::Byte => self.emit_byte(value as u8),
-                Size::Word => self.emit_word(value as u16),
-                Size::Long => self.emit_long(value as u32),
+        // Emit the repeated value. Pass 1 only needs the space after the
+        // first one.
+        let emitted = if self.pass == 1 && self.object.is_none() {
+            count.min(1)
+        } else {
+            count
+        };
+        for _ in 0..emitted {
+            if let Some(value_expr) = &value_expr {
+                self.relocate_value(value_expr, size, self.pc)?;
             }
+            self.emit_data(size, value)?;
         }
+        self.pc += ((count - emitted) * size.bytes()) as u32;
+        self.end = self.end.max(self.pc);
         Ok(())
     }
 
-    /// Processes DS.B/W/L directive (reserve space).
-    pub fn handle_ds(
+    /// Emits a DC or DCB value at `size`, checking in pass 2 (when forward
+    /// references have resolved) that it fits.
+    fn emit_data(&mut self, size: Size, value: i64) -> Result<(), String> {
+        let range = size.range();
+        if self.pass == 2 && !range.contains(&value) {
+            return Err(format!(
+                "value {value} is out of range for a {} ({} to {})",
+                size.name(),
+                range.start(),
+                range.end()
+            ));
+        }
+        match size {
+            Size::Byte => self.emit_byte(value as u8),
+            Size::Word => self.emit_word(value as u16),
+            Size::Long => self.emit_long(value as u32),
+        }
+        Ok(())
+    }
+
+    /// Processes a ROMSIZE directive: `ROMSIZE size[,fill[,CHECKSUM]]` pads
+    /// the flat binary to `size` bytes of `fill` (`DEFAULT_ROM_FILL` if
+    /// left out), with the checksum in the last two when asked for.
+    pub fn handle_romsize(
         &mut self,
-        size: Size,
         operands: &[LocatedToken],
         loc: &SourceLoc,
+        length: usize,
     ) -> Result<(), String> {
+        let ops = split_operands(operands);
+        if ops.is_empty() || ops.len() > 3 || ops.iter().any(|tokens| tokens.is_empty()) {
+            return Err("romsize takes a size, an optional fill byte and CHECKSUM".to_string());
+        }
+        let size = self.known_value(ops[0])?;
+        let size =
+            u32::try_from(size).map_err(|_| format!("rom size can't be negative: {size}"))?;
+        let fill = match ops.get(1) {
+            Some(tokens) => {
+                let fill = self.known_value(tokens)?;
+                u8::try_from(fill)
+                    .or_else(|_| i8::try_from(fill).map(|fill| fill as u8))
+                    .map_err(|_| format!("romsize fill must be a byte: {fill}"))?
+            }
+            None => DEFAULT_ROM_FILL,
+        };
+        let checksum = match ops.get(2).map(|tokens| &tokens[..]) {
+            Some(
+                [LocatedToken {
+                    token: Token::Ident(word),
+                    ..
+                }],
+            ) if word.eq_ignore_ascii_case("CHECKSUM") => true,
+            Some(_) => return Err("romsize's third operand can only be CHECKSUM".to_string()),
+            None => false,
+        };
+        let rom = RomImage {
+            size,
+            fill,
+            checksum,
+        }
+        .validate()?;
+        self.rom_directive = Some((rom, loc.clone(), length));
+        Ok(())
+    }
+
+    /// Processes DS.B/W/L directive (reserve space).
+    ///
+    /// The count may only use symbols defined before the line, since the
+    /// space it reserves moves every label after it. The flat binary holds
+    /// `space_fill` bytes there, except in a BSS section, which takes none.
+    pub fn handle_ds(&mut self, size: Size, operands: &[LocatedToken]) -> Result<(), String> {
         if operands.is_empty() {
-            return Err(format!("{loc}: ds requires a count"));
+            return Err("ds requires a count".to_string());
         }
-        let mut parser = ExprParser::new(operands);
-        let expr = parser.parse_expr()?;
-        let count = eval_expr(&expr, self.symbols.as_map(), self.pc)? as usize;
-        let bytes = count * size.bytes();
-        for _ in 0..bytes {
-            self.emit_byte(0);
+        if size != Size::Byte && self.pc & 1 != 0 {
+            return Err(format!(
+                "ds.{} at odd address ${:X} (add EVEN before it)",
+                &size.name()[..1],
+                self.pc
+            ));
         }
+        let expr = self.parse_expr_at(operands)?;
+        let count = eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope())
+            .map_err(|e| format!("ds count must only use symbols defined before it: {e}"))?;
+        let count =
+            usize::try_from(count).map_err(|_| format!("ds count can't be negative: {count}"))?;
+        self.note_block(&expr, size.bytes(), count * size.bytes());
+        self.reserve(count * size.bytes(), self.space_fill);
         Ok(())
     }
 
     /// Processes RSSET directive.
-    pub fn handle_rsset(
-        &mut self,
-        operands: &[LocatedToken],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    pub fn handle_rsset(&mut self, operands: &[LocatedToken]) -> Result<(), String> {
         if operands.is_empty() {
-            return Err(format!("{loc}: rsset requires an address"));
+            return Err("rsset requires an address".to_string());
         }
-        let mut parser = ExprParser::new(operands);
-        let expr = parser.parse_expr()?;
+        let expr = self.parse_expr_at(operands)?;
         let value = eval_expr(&expr, self.symbols.as_map(), self.pc)?;
         self.rs_counter = value as u32;
         Ok(())
@@ -2345,7 +6510,6 @@ pub fn handle_rs(
         size: Size,
         label: &str,
         operands: &[LocatedToken],
-        _loc: &SourceLoc,
     ) -> Result<(), String> {
         // RS returns current RS counter, then advances it
         let current = i64::from(self.rs_counter);
@@ -2354,8 +6518,7 @@ pub fn handle_rs(
         let count = if operands.is_empty() {
             1
         } else {
-            let mut parser = ExprParser::new(operands);
-            let expr = parser.parse_expr()?;
+            let expr = self.parse_expr_at(operands)?;
             eval_expr(&expr, self.symbols.as_map(), self.pc)? as usize
         };
         self.rs_counter += (count * size.bytes()) as u32;
@@ -2364,6 +6527,8 @@ pub fn handle_rs(
 
     /// Processes a single parsed line.
     pub fn process_line(&mut self, line: &ParsedLine) -> Result<(), String> {
+        self.error_span = None;
+        self.encoded = false;
         // Handle label
         if let Some(ref label) = line.label {
             // Update scope: global labels set new scope, local labels use current scope
@@ -2374,13 +6539,24 @@ pub fn process_line(&mut self, line: &ParsedLine) -> Result<(), String> {
                 self.current_scope = label.clone();
                 label.clone()
             };
+            self.warn_case_clash(&full_label, &line.loc, label.len());
 
-            // Don't define label for EQU (it's handled specially)
+            // Don't define label for EQU or SET (they're handled specially)
             let mnemonic = line.mnemonic.as_ref().map(|s| s.to_ascii_uppercase());
-            if mnemonic.as_deref() != Some("EQU")
+            if mnemonic.as_deref() != Some("EQUR")
+                && self.register_aliases.contains_key(&full_label)
+            {
+                return Err(format!("{full_label} is a register (EQUR)"));
+            }
+            if !matches!(mnemonic.as_deref(), Some("EQU" | "EQUR" | "SET"))
                 && !mnemonic.as_ref().is_some_and(|s| s.starts_with("RS"))
             {
+                self.check_phase(&full_label)?;
                 self.symbols.define(&full_label, i64::from(self.pc))?;
+                self.code_labels.insert(full_label.clone());
+                self.label_sections
+                    .insert(full_label.clone(), self.current_section);
+                self.labels.push((full_label, self.pc));
             }
         }
 
@@ -2393,130 +6569,170 @@ pub fn process_line(&mut self, line: &ParsedLine) -> Result<(), String> {
         let size = line.size.unwrap_or(Size::Word);
 
         match mnemonic.as_str() {
-            "ORG" => self.handle_org(&line.operands, &line.loc),
+            "ORG" => self.handle_org(&line.operands),
+            "SECTION" => self.handle_section(&line.operands),
+            "XDEF" => self.handle_xdef(&line.operands),
+            "XREF" => self.handle_xref(&line.operands),
             "EQU" => {
-                let label = line
-                    .label
-                    .as_ref()
-                    .ok_or_else(|| format!("{}: equ requires a label", line.loc))?;
+                let label = line.label.as_ref().ok_or("equ requires a label")?;
                 self.handle_equ(label, &line.operands, &line.loc)
             }
+            "EQUR" => {
+                let label = line.label.as_ref().ok_or("equr requires a label")?;
+                self.handle_equr(label, &line.operands)
+            }
+            "END" => self.handle_end(&line.operands),
+            "SET" => {
+                let label = line.label.as_ref().ok_or("set requires a label")?;
+                self.handle_set(label, &line.operands)
+            }
             "EVEN" => {
                 self.handle_even();
                 Ok(())
             }
-            "DC" => self.handle_dc(size, &line.operands, &line.loc),
-            "DCB" => self.handle_dcb(size, &line.operands, &line.loc),
-            "DS" => self.handle_ds(size, &line.operands, &line.loc),
-            "RSSET" => self.handle_rsset(&line.operands, &line.loc),
+            "ALIGN" => self.handle_align(&line.operands),
+            "CNOP" => self.handle_cnop(&line.operands),
+            "ROMSIZE" => self.handle_romsize(&line.operands, &line.loc, line.length),
+            "ASSERT" => self.handle_assert(&line.operands),
+            // The preprocessor stops at a FAIL at the start of a line
+            "FAIL" => Err(match line.operands.as_slice() {
+                [] => "fail directive reached".to_string(),
+                [LocatedToken {
+                    token: Token::String(message),
+                    ..
+                }] => message.clone(),
+                _ => "fail takes a message in quotes".to_string(),
+            }),
+            "DC" => self.handle_dc(size, &line.operands),
+            "DCB" => self.handle_dcb(size, &line.operands),
+            "DS" => self.handle_ds(size, &line.operands),
+            "RSSET" => self.handle_rsset(&line.operands),
             "RS" => {
-                let label = line
-                    .label
-                    .as_ref()
-                    .ok_or_else(|| format!("{}: rs requires a label", line.loc))?;
-                self.handle_rs(size, label, &line.operands, &line.loc)
+                let label = line.label.as_ref().ok_or("rs requires a label")?;
+                self.handle_rs(size, label, &line.operands)
             }
+            // The preprocessor applies OPT
+            "OPT" => Ok(()),
             // VASM diagnostic directives - ignore
-            "PRINTT" | "PRINTV" | "PRINTI" | "ECHO" | "FAIL" | "WARN" => Ok(()),
+            "PRINTT" | "PRINTV" | "PRINTI" | "ECHO" | "WARN" => Ok(()),
             // Instructions
-            _ => self.encode_instruction(&mnemonic, size, &line.operands, &line.loc),
+            _ if self.pc & 1 != 0 => Err(format!(
+                "instruction at odd address ${:X} (add EVEN before it)",
+                self.pc
+            )),
+            _ => {
+                self.encoded = true;
+                self.encode_instruction(&mnemonic, line.size, &line.operands)
+            }
         }
     }
 
-    /// Encodes a single M68K instruction.
+    /// Encodes a single M68K instruction, `explicit` being its size suffix
+    /// if it has one.
     fn encode_instruction(
         &mut self,
         mnemonic: &str,
-        size: Size,
+        explicit: Option<Size>,
         operands: &[LocatedToken],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
+        let size = explicit.unwrap_or(Size::Word);
         let ops = split_operands(operands);
 
         match mnemonic {
             // Data movement
-            "MOVE" => self.encode_move(size, &ops, loc),
-            "MOVEA" => self.encode_movea(size, &ops, loc),
-            "MOVEQ" => self.encode_moveq(&ops, loc),
-            "LEA" => self.encode_lea(&ops, loc),
-            "PEA" => self.encode_pea(&ops, loc),
-            "CLR" => self.encode_clr(size, &ops, loc),
-            "EXG" => self.encode_exg(&ops, loc),
-            "SWAP" => self.encode_swap(&ops, loc),
+            "MOVE" if self.use_quick(size, &ops, -128..=127, moveq_destination) => {
+                self.encode_moveq(&ops, true)
+            }
+            "MOVE" => self.encode_move(size, &ops),
+            "MOVEA" => self.encode_movea(size, &ops),
+            "MOVEQ" => self.encode_moveq(&ops, false),
+            "LEA" => self.encode_lea(&ops),
+            "PEA" => self.encode_pea(&ops),
+            "CLR" => self.encode_clr(size, &ops),
+            "EXG" => self.encode_exg(&ops),
+            "SWAP" => self.encode_swap(&ops),
 
             // Arithmetic
-            "ADD" => self.encode_add(size, &ops, loc),
-            "ADDA" => self.encode_adda(size, &ops, loc),
-            "ADDI" => self.encode_addi(size, &ops, loc),
-            "ADDQ" => self.encode_addq(size, &ops, loc),
-            "ADDX" => self.encode_addx(size, &ops, loc),
-            "SUB" => self.encode_sub(size, &ops, loc),
-            "SUBA" => self.encode_suba(size, &ops, loc),
-            "SUBI" => self.encode_subi(size, &ops, loc),
-            "SUBQ" => self.encode_subq(size, &ops, loc),
-            "SUBX" => self.encode_subx(size, &ops, loc),
-            "NEG" => self.encode_neg(size, &ops, loc),
-            "NEGX" => self.encode_negx(size, &ops, loc),
-            "EXT" => self.encode_ext(size, &ops, loc),
-            "MULU" => self.encode_mulu(&ops, loc),
-            "MULS" => self.encode_muls(&ops, loc),
-            "DIVU" => self.encode_divu(&ops, loc),
-            "DIVS" => self.encode_divs(&ops, loc),
-            "CMP" => self.encode_cmp(size, &ops, loc),
-            "CMPA" => self.encode_cmpa(size, &ops, loc),
-            "CMPI" => self.encode_cmpi(size, &ops, loc),
-            "CMPM" => self.encode_cmpm(size, &ops, loc),
-            "TST" => self.encode_tst(size, &ops, loc),
+            "ADD" if self.use_quick(size, &ops, 1..=8, addq_destination) => {
+                self.encode_addq(size, &ops)
+            }
+            "ADD" => self.encode_add(size, &ops),
+            "ADDA" => self.encode_adda(size, &ops),
+            "ADDI" => self.encode_addi(size, &ops),
+            "ADDQ" => self.encode_addq(size, &ops),
+            "ADDX" => self.encode_addx(size, &ops),
+            "SUB" if self.use_quick(size, &ops, 1..=8, addq_destination) => {
+                self.encode_subq(size, &ops)
+            }
+            "SUB" => self.encode_sub(size, &ops),
+            "SUBA" => self.encode_suba(size, &ops),
+            "SUBI" => self.encode_subi(size, &ops),
+            "SUBQ" => self.encode_subq(size, &ops),
+            "SUBX" => self.encode_subx(size, &ops),
+            "NEG" => self.encode_neg(size, &ops),
+            "NEGX" => self.encode_negx(size, &ops),
+            "EXT" => self.encode_ext(size, &ops),
+            "MULU" => self.encode_mulu(&ops),
+            "MULS" => self.encode_muls(&ops),
+            "DIVU" => self.encode_divu(&ops),
+            "DIVS" => self.encode_divs(&ops),
+            "CMP" => self.encode_cmp(size, &ops),
+            "CMPA" => self.encode_cmpa(size, &ops),
+            "CMPI" => self.encode_cmpi(size, &ops),
+            "CMPM" => self.encode_cmpm(size, &ops),
+            "TST" => self.encode_tst(size, &ops),
 
             // Logical
-            "AND" => self.encode_and(size, &ops, loc),
-            "ANDI" => self.encode_andi(size, &ops, loc),
-            "OR" => self.encode_or(size, &ops, loc),
-            "ORI" => self.encode_ori(size, &ops, loc),
-            "EOR" => self.encode_eor(size, &ops, loc),
-            "EORI" => self.encode_eori(size, &ops, loc),
-            "NOT" => self.encode_not(size, &ops, loc),
+            "AND" => self.encode_and(size, &ops),
+            "ANDI" => self.encode_andi(size, &ops),
+            "OR" => self.encode_or(size, &ops),
+            "ORI" => self.encode_ori(size, &ops),
+            "EOR" => self.encode_eor(size, &ops),
+            "EORI" => self.encode_eori(size, &ops),
+            "NOT" => self.encode_not(size, &ops),
 
             // Shifts and rotates
-            "ASL" => self.encode_shift(size, &ops, loc, 0b100, true),
-            "ASR" => self.encode_shift(size, &ops, loc, 0b000, true),
-            "LSL" => self.encode_shift(size, &ops, loc, 0b101, true),
-            "LSR" => self.encode_shift(size, &ops, loc, 0b001, true),
-            "ROL" => self.encode_shift(size, &ops, loc, 0b111, false),
-            "ROR" => self.encode_shift(size, &ops, loc, 0b011, false),
-            "ROXL" => self.encode_shift(size, &ops, loc, 0b110, false),
-            "ROXR" => self.encode_shift(size, &ops, loc, 0b010, false),
+            "ASL" => self.encode_shift(size, &ops, 0b100, true),
+            "ASR" => self.encode_shift(size, &ops, 0b000, true),
+            "LSL" => self.encode_shift(size, &ops, 0b101, true),
+            "LSR" => self.encode_shift(size, &ops, 0b001, true),
+            "ROL" => self.encode_shift(size, &ops, 0b111, false),
+            "ROR" => self.encode_shift(size, &ops, 0b011, false),
+            "ROXL" => self.encode_shift(size, &ops, 0b110, false),
+            "ROXR" => self.encode_shift(size, &ops, 0b010, false),
 
             // Bit manipulation
-            "BTST" => self.encode_bit(0b00, &ops, loc),
-            "BCHG" => self.encode_bit(0b01, &ops, loc),
-            "BCLR" => self.encode_bit(0b10, &ops, loc),
-            "BSET" => self.encode_bit(0b11, &ops, loc),
+            "BTST" => self.encode_bit(0b00, &ops),
+            "BCHG" => self.encode_bit(0b01, &ops),
+            "BCLR" => self.encode_bit(0b10, &ops),
+            "BSET" => self.encode_bit(0b11, &ops),
 
             // BCD
-            "ABCD" => self.encode_bcd(0xC100, &ops, loc),
-            "SBCD" => self.encode_bcd(0x8100, &ops, loc),
-            "NBCD" => self.encode_nbcd(&ops, loc),
+            "ABCD" => self.encode_bcd(0xC100, &ops),
+            "SBCD" => self.encode_bcd(0x8100, &ops),
+            "NBCD" => self.encode_nbcd(&ops),
 
             // Branches
-            "BRA" => self.encode_bra(&ops, loc),
-            "BSR" => self.encode_bsr(&ops, loc),
+            "BRA" => self.encode_bra(explicit, &ops),
+            "BSR" => self.encode_bsr(explicit, &ops),
             "BHI" | "BLS" | "BCC" | "BHS" | "BCS" | "BLO" | "BNE" | "BEQ" | "BVC" | "BVS"
-            | "BPL" | "BMI" | "BGE" | "BLT" | "BGT" | "BLE" => self.encode_bcc(mnemonic, &ops, loc),
+            | "BPL" | "BMI" | "BGE" | "BLT" | "BGT" | "BLE" => {
+                self.encode_bcc(mnemonic, explicit, &ops)
+            }
 
             // DBcc
             "DBRA" | "DBT" | "DBF" | "DBHI" | "DBLS" | "DBCC" | "DBCS" | "DBNE" | "DBEQ"
             | "DBVC" | "DBVS" | "DBPL" | "DBMI" | "DBGE" | "DBLT" | "DBGT" | "DBLE" => {
-                self.encode_dbcc(mnemonic, &ops, loc)
+                self.encode_dbcc(mnemonic, &ops)
             }
 
             // Scc
             "ST" | "SF" | "SHI" | "SLS" | "SCC" | "SCS" | "SNE" | "SEQ" | "SVC" | "SVS" | "SPL"
-            | "SMI" | "SGE" | "SLT" | "SGT" | "SLE" => self.encode_scc(mnemonic, &ops, loc),
+            | "SMI" | "SGE" | "SLT" | "SGT" | "SLE" => self.encode_scc(mnemonic, &ops),
 
             // Control
-            "JMP" => self.encode_jmp(&ops, loc),
-            "JSR" => self.encode_jsr(&ops, loc),
+            "JMP" => self.encode_jmp(&ops),
+            "JSR" => self.encode_jsr(&ops),
             "RTS" => self.encode_simple(0x4E75),
             "RTE" => self.encode_simple(0x4E73),
             "RTR" => self.encode_simple(0x4E77),
@@ -2524,16 +6740,16 @@ fn encode_instruction(
             "RESET" => self.encode_simple(0x4E70),
             "TRAPV" => self.encode_simple(0x4E76),
             "ILLEGAL" => self.encode_simple(0x4AFC),
-            "TRAP" => self.encode_trap(&ops, loc),
-            "CHK" => self.encode_chk(&ops, loc),
-            "LINK" => self.encode_link(&ops, loc),
-            "UNLK" => self.encode_unlk(&ops, loc),
-            "STOP" => self.encode_stop(&ops, loc),
-            "TAS" => self.encode_tas(&ops, loc),
-            "MOVEM" => self.encode_movem(size, &ops, loc),
-            "MOVEP" => self.encode_movep(size, &ops, loc),
+            "TRAP" => self.encode_trap(&ops),
+            "CHK" => self.encode_chk(&ops),
+            "LINK" => self.encode_link(&ops),
+            "UNLK" => self.encode_unlk(&ops),
+            "STOP" => self.encode_stop(&ops),
+            "TAS" => self.encode_tas(&ops),
+            "MOVEM" => self.encode_movem(size, &ops),
+            "MOVEP" => self.encode_movep(size, &ops),
 
-            _ => Err(format!("{loc}: unknown instruction: {mnemonic}")),
+            _ => Err(format!("unknown instruction: {mnemonic}")),
         }
     }
 
@@ -2544,35 +6760,30 @@ fn encode_simple(&mut self, opcode: u16) -> Result<(), String> {
     }
 
     // Encode MOVE instruction
-    fn encode_move(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_move(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: move requires 2 operands"));
+            return Err("move requires 2 operands".to_string());
         }
 
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let dst = self.operand(ops[1])?;
 
         // Check for MOVE to/from SR, CCR
         if matches!(dst, AddrMode::Sr) {
-            return self.encode_move_to_sr(&src, loc);
+            return self.encode_move_to_sr(&src);
         }
         if matches!(src, AddrMode::Sr) {
-            return self.encode_move_from_sr(&dst, loc);
+            return self.encode_move_from_sr(&dst);
         }
         if matches!(dst, AddrMode::Ccr) {
-            return self.encode_move_to_ccr(&src, loc);
+            return self.encode_move_to_ccr(&src);
         }
         // Check for MOVE to/from USP
         if matches!(dst, AddrMode::Usp) {
-            return self.encode_move_to_usp(&src, loc);
+            return self.encode_move_to_usp(&src);
         }
         if matches!(src, AddrMode::Usp) {
-            return self.encode_move_from_usp(&dst, loc);
+            return self.encode_move_from_usp(&dst);
         }
 
         let sz_bits = match size {
@@ -2582,13 +6793,8 @@ fn encode_move(
         };
 
         let (src_mode, src_reg, src_ext) = self.encode_ea_with_imm(&src, size)?;
-        let (dst_mode, dst_reg, dst_ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2 + (src_ext.len() * 2) as u32,
-            self.pass,
-            self.scope(),
-        )?;
+        let (dst_mode, dst_reg, dst_ext) =
+            self.ea(&dst, self.pc + 2 + (src_ext.len() * 2) as u32)?;
 
         // MOVE encoding: 00 size dst_reg dst_mode src_mode src_reg
         let opcode = (sz_bits << 12)
@@ -2606,33 +6812,37 @@ fn encode_move(
         Ok(())
     }
 
-    fn encode_movea(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_movea(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: movea requires 2 operands"));
+            return Err("movea requires 2 operands".to_string());
         }
         // MOVEA is just MOVE with address register destination
-        self.encode_move(size, ops, loc)
+        self.encode_move(size, ops)
     }
 
-    fn encode_moveq(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    /// Encodes `MOVEQ`, or a `MOVE.L #n,Dn` rewritten to it when `from_move`
+    /// is set, whose 32-bit immediate `$FFFFFF80-$FFFFFFFF` is -128 to -1
+    fn encode_moveq(&mut self, ops: &[&[LocatedToken]], from_move: bool) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: moveq requires 2 operands"));
+            return Err("moveq requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
+        let dst = self.operand(ops[1])?;
 
         let imm = match src {
-            AddrMode::Immediate(ref expr) => eval_expr(expr, self.symbols.as_map(), self.pc)? as i8,
-            _ => return Err(format!("{loc}: moveq source must be immediate")),
+            AddrMode::Immediate(ref expr) => {
+                let (symbols, scope) = (self.symbols.as_map(), self.scope());
+                let mut value = try_eval_expr(expr, symbols, self.pc, self.pass, scope)?;
+                if from_move && Size::Long.range().contains(&value) {
+                    value = i64::from(value as u32 as i32);
+                }
+                check_range(self.pass, "moveq value", value, -128..=127)? as i8
+            }
+            _ => return Err("moveq source must be immediate".to_string()),
         };
         let dreg = match dst {
             AddrMode::DataReg(r) => r,
-            _ => return Err(format!("{loc}: moveq destination must be data register")),
+            _ => return Err("moveq destination must be data register".to_string()),
         };
 
         let opcode = 0x7000 | (u16::from(dreg) << 9) | u16::from(imm as u8);
@@ -2640,25 +6850,19 @@ fn encode_moveq(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(
         Ok(())
     }
 
-    fn encode_lea(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_lea(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: lea requires 2 operands"));
+            return Err("lea requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let dst = self.operand(ops[1])?;
 
         let areg = match dst {
             AddrMode::AddrReg(r) => r,
-            _ => return Err(format!("{loc}: lea destination must be address register")),
+            _ => return Err("lea destination must be address register".to_string()),
         };
 
-        let (mode, reg, ext) = encode_ea(
-            &src,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+        let (mode, reg, ext) = self.ea(&src, self.pc + 2)?;
         let opcode = 0x41C0 | (u16::from(areg) << 9) | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -2667,18 +6871,12 @@ fn encode_lea(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(),
         Ok(())
     }
 
-    fn encode_pea(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_pea(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: pea requires 1 operand"));
-        }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let (mode, reg, ext) = encode_ea(
-            &src,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+            return Err("pea requires 1 operand".to_string());
+        }
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let (mode, reg, ext) = self.ea(&src, self.pc + 2)?;
         let opcode = 0x4840 | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -2687,28 +6885,17 @@ fn encode_pea(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(),
         Ok(())
     }
 
-    fn encode_clr(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_clr(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: clr requires 1 operand"));
+            return Err("clr requires 1 operand".to_string());
         }
-        let dst = parse_operand(ops[0], self.symbols.as_map())?;
+        let dst = self.operand(ops[0])?;
         let sz = match size {
             Size::Byte => 0,
             Size::Word => 1,
             Size::Long => 2,
         };
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
         let opcode = 0x4200 | (sz << 6) | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -2717,19 +6904,19 @@ fn encode_clr(
         Ok(())
     }
 
-    fn encode_exg(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_exg(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: exg requires 2 operands"));
+            return Err("exg requires 2 operands".to_string());
         }
-        let a = parse_operand(ops[0], self.symbols.as_map())?;
-        let b = parse_operand(ops[1], self.symbols.as_map())?;
+        let a = self.operand(ops[0])?;
+        let b = self.operand(ops[1])?;
 
         let (rx, ry, mode) = match (&a, &b) {
             (AddrMode::DataReg(x), AddrMode::DataReg(y)) => (*x, *y, 0b01000),
             (AddrMode::AddrReg(x), AddrMode::AddrReg(y)) => (*x, *y, 0b01001),
             (AddrMode::DataReg(x), AddrMode::AddrReg(y)) => (*x, *y, 0b10001),
             (AddrMode::AddrReg(x), AddrMode::DataReg(y)) => (*y, *x, 0b10001),
-            _ => return Err(format!("{loc}: exg requires register operands")),
+            _ => return Err("exg requires register operands".to_string()),
         };
 
         let opcode = 0xC100 | (u16::from(rx) << 9) | (mode << 3) | u16::from(ry);
@@ -2737,14 +6924,14 @@ fn encode_exg(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(),
         Ok(())
     }
 
-    fn encode_swap(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_swap(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: swap requires 1 operand"));
+            return Err("swap requires 1 operand".to_string());
         }
-        let dst = parse_operand(ops[0], self.symbols.as_map())?;
+        let dst = self.operand(ops[0])?;
         let dreg = match dst {
             AddrMode::DataReg(r) => r,
-            _ => return Err(format!("{loc}: swap requires data register")),
+            _ => return Err("swap requires data register".to_string()),
         };
         self.emit_word(0x4840 | u16::from(dreg));
         Ok(())
@@ -2752,12 +6939,13 @@ fn encode_swap(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<()
 
     // Helper to encode EA with immediate handling
     fn encode_ea_with_imm(
-        &self,
+        &mut self,
         mode: &AddrMode,
         size: Size,
     ) -> Result<(u8, u8, Vec<u16>), String> {
         match mode {
             AddrMode::Immediate(ref expr) => {
+                self.relocate_value(expr, size, self.pc + 2)?;
                 // In pass 1, use 0 for undefined symbols; in pass 2, require resolution
                 let val = try_eval_expr(
                     expr,
@@ -2766,6 +6954,8 @@ fn encode_ea_with_imm(
                     self.pass,
                     self.scope(),
                 )?;
+                let what = format!("{} immediate", size.name());
+                let val = check_range(self.pass, &what, val, size.range())?;
                 let ext = match size {
                     Size::Byte => vec![(val & 0xFF) as u16],
                     Size::Word => vec![val as u16],
@@ -2773,47 +6963,31 @@ fn encode_ea_with_imm(
                 };
                 Ok((0b111, 0b100, ext))
             }
-            _ => encode_ea(
-                mode,
-                self.symbols.as_map(),
-                self.pc + 2,
-                self.pass,
-                self.scope(),
-            ),
+            _ => self.ea(mode, self.pc + 2),
         }
     }
 
     // Arithmetic encoders
-    fn encode_add(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_add(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         // Check if destination is address register - use ADDA
         if ops.len() == 2 {
-            let dst = parse_operand(ops[1], self.symbols.as_map())?;
+            let dst = self.operand(ops[1])?;
             if matches!(dst, AddrMode::AddrReg(_)) {
-                return self.encode_adda(size, ops, loc);
+                return self.encode_adda(size, ops);
             }
         }
-        self.encode_arith_op(0xD000, size, ops, loc)
+        self.encode_arith_op(0xD000, size, ops)
     }
 
-    fn encode_sub(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_sub(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         // Check if destination is address register - use SUBA
         if ops.len() == 2 {
-            let dst = parse_operand(ops[1], self.symbols.as_map())?;
+            let dst = self.operand(ops[1])?;
             if matches!(dst, AddrMode::AddrReg(_)) {
-                return self.encode_suba(size, ops, loc);
+                return self.encode_suba(size, ops);
             }
         }
-        self.encode_arith_op(0x9000, size, ops, loc)
+        self.encode_arith_op(0x9000, size, ops)
     }
 
     fn encode_arith_op(
@@ -2821,13 +6995,12 @@ fn encode_arith_op(
         base: u16,
         size: Size,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: requires 2 operands"));
+            return Err("requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let dst = self.operand(ops[1])?;
 
         let sz = match size {
             Size::Byte => 0,
@@ -2851,13 +7024,7 @@ fn encode_arith_op(
             }
             (AddrMode::DataReg(dreg), _) => {
                 // Dn,<ea>
-                let (mode, reg, ext) = encode_ea(
-                    &dst,
-                    self.symbols.as_map(),
-                    self.pc + 2,
-                    self.pass,
-                    self.scope(),
-                )?;
+                let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
                 let opmode = sz | 0b100;
                 let opcode = base
                     | (u16::from(*dreg) << 9)
@@ -2869,27 +7036,17 @@ fn encode_arith_op(
                     self.emit_word(e);
                 }
             }
-            _ => return Err(format!("{loc}: invalid operand combination")),
+            _ => return Err("invalid operand combination".to_string()),
         }
         Ok(())
     }
 
-    fn encode_adda(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_adda_suba(0xD0C0, size, ops, loc)
+    fn encode_adda(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_adda_suba(0xD0C0, size, ops)
     }
 
-    fn encode_suba(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_adda_suba(0x90C0, size, ops, loc)
+    fn encode_suba(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_adda_suba(0x90C0, size, ops)
     }
 
     fn encode_adda_suba(
@@ -2897,17 +7054,16 @@ fn encode_adda_suba(
         base: u16,
         size: Size,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: requires 2 operands"));
+            return Err("requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let dst = self.operand(ops[1])?;
 
         let areg = match dst {
             AddrMode::AddrReg(r) => r,
-            _ => return Err(format!("{loc}: destination must be address register")),
+            _ => return Err("destination must be address register".to_string()),
         };
 
         let opmode = if size == Size::Long { 0b111 } else { 0b011 };
@@ -2924,22 +7080,12 @@ fn encode_adda_suba(
         Ok(())
     }
 
-    fn encode_addi(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_imm_op(0x0600, size, ops, loc)
+    fn encode_addi(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_imm_op(0x0600, size, ops)
     }
 
-    fn encode_subi(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_imm_op(0x0400, size, ops, loc)
+    fn encode_subi(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_imm_op(0x0400, size, ops)
     }
 
     fn encode_imm_op(
@@ -2947,31 +7093,32 @@ fn encode_imm_op(
         base: u16,
         size: Size,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: requires 2 operands"));
+            return Err("requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
+        let dst = self.operand(ops[1])?;
 
         let imm = match src {
-            AddrMode::Immediate(ref expr) => eval_expr(expr, self.symbols.as_map(), self.pc)?,
-            _ => return Err(format!("{loc}: source must be immediate")),
+            AddrMode::Immediate(ref expr) => {
+                self.relocate_value(expr, size, self.pc + 2)?;
+                let (symbols, scope) = (self.symbols.as_map(), self.scope());
+                try_eval_expr(expr, symbols, self.pc, self.pass, scope)?
+            }
+            _ => return Err("source must be immediate".to_string()),
         };
+        let what = format!("{} immediate", size.name());
+        let imm = check_range(self.pass, &what, imm, size.range())?;
 
         let sz = match size {
             Size::Byte => 0,
             Size::Word => 1,
             Size::Long => 2,
         };
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 4,
-            self.pass,
-            self.scope(),
-        )?;
+        // The destination's extension words follow the immediate
+        let imm_bytes = if size == Size::Long { 4 } else { 2 };
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2 + imm_bytes)?;
         let opcode = base | (sz << 6) | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
 
@@ -2985,22 +7132,12 @@ fn encode_imm_op(
         Ok(())
     }
 
-    fn encode_addq(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_quick(0x5000, size, ops, loc)
+    fn encode_addq(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_quick(0x5000, size, ops)
     }
-
-    fn encode_subq(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_quick(0x5100, size, ops, loc)
+
+    fn encode_subq(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_quick(0x5100, size, ops)
     }
 
     fn encode_quick(
@@ -3008,17 +7145,20 @@ fn encode_quick(
         base: u16,
         size: Size,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: requires 2 operands"));
+            return Err("requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
+        let dst = self.operand(ops[1])?;
 
         let imm = match src {
-            AddrMode::Immediate(ref expr) => eval_expr(expr, self.symbols.as_map(), self.pc)? as u8,
-            _ => return Err(format!("{loc}: source must be immediate 1-8")),
+            AddrMode::Immediate(ref expr) => {
+                let (symbols, scope) = (self.symbols.as_map(), self.scope());
+                let value = try_eval_expr(expr, symbols, self.pc, self.pass, scope)?;
+                check_range(self.pass, "quick value", value, 1..=8)? as u8
+            }
+            _ => return Err("source must be immediate 1-8".to_string()),
         };
         let data = if imm == 8 { 0 } else { imm };
 
@@ -3027,13 +7167,7 @@ fn encode_quick(
             Size::Word => 1,
             Size::Long => 2,
         };
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
         let opcode =
             base | (u16::from(data) << 9) | (sz << 6) | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
@@ -3043,22 +7177,12 @@ fn encode_quick(
         Ok(())
     }
 
-    fn encode_addx(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_x_op(0xD100, size, ops, loc)
+    fn encode_addx(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_x_op(0xD100, size, ops)
     }
 
-    fn encode_subx(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_x_op(0x9100, size, ops, loc)
+    fn encode_subx(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_x_op(0x9100, size, ops)
     }
 
     fn encode_x_op(
@@ -3066,13 +7190,12 @@ fn encode_x_op(
         base: u16,
         size: Size,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: requires 2 operands"));
+            return Err("requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
+        let dst = self.operand(ops[1])?;
 
         let sz = match size {
             Size::Byte => 0,
@@ -3089,45 +7212,25 @@ fn encode_x_op(
                 let opcode = base | (u16::from(*ry) << 9) | (sz << 6) | 0x08 | u16::from(*rx);
                 self.emit_word(opcode);
             }
-            _ => return Err(format!("{loc}: invalid operand combination for addx/subx")),
+            _ => return Err("invalid operand combination for addx/subx".to_string()),
         }
         Ok(())
     }
 
-    fn encode_neg(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_unary(0x4400, size, ops, loc)
+    fn encode_neg(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_unary(0x4400, size, ops)
     }
 
-    fn encode_negx(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_unary(0x4000, size, ops, loc)
+    fn encode_negx(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_unary(0x4000, size, ops)
     }
 
-    fn encode_not(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_unary(0x4600, size, ops, loc)
+    fn encode_not(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_unary(0x4600, size, ops)
     }
 
-    fn encode_tst(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_unary(0x4A00, size, ops, loc)
+    fn encode_tst(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_unary(0x4A00, size, ops)
     }
 
     fn encode_unary(
@@ -3135,24 +7238,17 @@ fn encode_unary(
         base: u16,
         size: Size,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: requires 1 operand"));
+            return Err("requires 1 operand".to_string());
         }
-        let dst = parse_operand(ops[0], self.symbols.as_map())?;
+        let dst = self.operand(ops[0])?;
         let sz = match size {
             Size::Byte => 0,
             Size::Word => 1,
             Size::Long => 2,
         };
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
         let opcode = base | (sz << 6) | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -3161,56 +7257,46 @@ fn encode_unary(
         Ok(())
     }
 
-    fn encode_ext(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_ext(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: ext requires 1 operand"));
+            return Err("ext requires 1 operand".to_string());
         }
-        let dst = parse_operand(ops[0], self.symbols.as_map())?;
+        let dst = self.operand(ops[0])?;
         let dreg = match dst {
             AddrMode::DataReg(r) => r,
-            _ => return Err(format!("{loc}: ext requires data register")),
+            _ => return Err("ext requires data register".to_string()),
         };
         let opmode = if size == Size::Long { 0b011 } else { 0b010 };
         self.emit_word(0x4800 | (opmode << 6) | u16::from(dreg));
         Ok(())
     }
 
-    fn encode_mulu(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
-        self.encode_mul_div(0xC0C0, ops, loc)
+    fn encode_mulu(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_mul_div(0xC0C0, ops)
     }
 
-    fn encode_muls(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
-        self.encode_mul_div(0xC1C0, ops, loc)
+    fn encode_muls(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_mul_div(0xC1C0, ops)
     }
 
-    fn encode_divu(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
-        self.encode_mul_div(0x80C0, ops, loc)
+    fn encode_divu(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_mul_div(0x80C0, ops)
     }
 
-    fn encode_divs(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
-        self.encode_mul_div(0x81C0, ops, loc)
+    fn encode_divs(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_mul_div(0x81C0, ops)
     }
 
-    fn encode_mul_div(
-        &mut self,
-        base: u16,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_mul_div(&mut self, base: u16, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: requires 2 operands"));
+            return Err("requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let dst = self.operand(ops[1])?;
 
         let dreg = match dst {
             AddrMode::DataReg(r) => r,
-            _ => return Err(format!("{loc}: destination must be data register")),
+            _ => return Err("destination must be data register".to_string()),
         };
 
         let (mode, reg, ext) = self.encode_ea_with_imm(&src, Size::Word)?;
@@ -3222,17 +7308,12 @@ fn encode_mul_div(
         Ok(())
     }
 
-    fn encode_cmp(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_cmp(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: cmp requires 2 operands"));
+            return Err("cmp requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let dst = self.operand(ops[1])?;
 
         // Auto-promote to CMPA if destination is address register
         if let AddrMode::AddrReg(areg) = dst {
@@ -3253,12 +7334,12 @@ fn encode_cmp(
         // Auto-promote to CMPI if source is immediate and destination is not a register
         if matches!(src, AddrMode::Immediate(_)) && !matches!(dst, AddrMode::DataReg(_)) {
             // CMPI #imm,<ea>
-            return self.encode_cmpi(size, ops, loc);
+            return self.encode_cmpi(size, ops);
         }
 
         let dreg = match dst {
             AddrMode::DataReg(r) => r,
-            _ => return Err(format!("{loc}: cmp destination must be data register")),
+            _ => return Err("cmp destination must be data register".to_string()),
         };
 
         let sz = match size {
@@ -3276,21 +7357,16 @@ fn encode_cmp(
         Ok(())
     }
 
-    fn encode_cmpa(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_cmpa(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: cmpa requires 2 operands"));
+            return Err("cmpa requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let dst = self.operand(ops[1])?;
 
         let areg = match dst {
             AddrMode::AddrReg(r) => r,
-            _ => return Err(format!("{loc}: cmpa destination must be address register")),
+            _ => return Err("cmpa destination must be address register".to_string()),
         };
 
         let opmode = if size == Size::Long { 0b111 } else { 0b011 };
@@ -3307,30 +7383,20 @@ fn encode_cmpa(
         Ok(())
     }
 
-    fn encode_cmpi(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        self.encode_imm_op(0x0C00, size, ops, loc)
+    fn encode_cmpi(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_imm_op(0x0C00, size, ops)
     }
 
-    fn encode_cmpm(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_cmpm(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: cmpm requires 2 operands"));
+            return Err("cmpm requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
+        let dst = self.operand(ops[1])?;
 
         let (ax, ay) = match (&src, &dst) {
             (AddrMode::PostInc(x), AddrMode::PostInc(y)) => (*x, *y),
-            _ => return Err(format!("{loc}: cmpm requires (An)+,(An)+ operands")),
+            _ => return Err("cmpm requires (An)+,(An)+ operands".to_string()),
         };
 
         let sz = match size {
@@ -3344,55 +7410,40 @@ fn encode_cmpm(
     }
 
     // Logical operations
-    fn encode_and(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_and(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         // Auto-promote to ANDI if source is immediate and destination is not a data register
         if ops.len() == 2 {
-            let src = parse_operand(ops[0], self.symbols.as_map())?;
-            let dst = parse_operand(ops[1], self.symbols.as_map())?;
+            let src = self.operand(ops[0])?;
+            let dst = self.operand(ops[1])?;
             if matches!(src, AddrMode::Immediate(_)) && !matches!(dst, AddrMode::DataReg(_)) {
-                return self.encode_andi(size, ops, loc);
+                return self.encode_andi(size, ops);
             }
         }
-        self.encode_logical(0xC000, size, ops, loc)
+        self.encode_logical(0xC000, size, ops)
     }
 
-    fn encode_or(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_or(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         // Auto-promote to ORI if source is immediate and destination is not a data register
         if ops.len() == 2 {
-            let src = parse_operand(ops[0], self.symbols.as_map())?;
-            let dst = parse_operand(ops[1], self.symbols.as_map())?;
+            let src = self.operand(ops[0])?;
+            let dst = self.operand(ops[1])?;
             if matches!(src, AddrMode::Immediate(_)) && !matches!(dst, AddrMode::DataReg(_)) {
-                return self.encode_ori(size, ops, loc);
+                return self.encode_ori(size, ops);
             }
         }
-        self.encode_logical(0x8000, size, ops, loc)
+        self.encode_logical(0x8000, size, ops)
     }
 
-    fn encode_eor(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_eor(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: eor requires 2 operands"));
+            return Err("eor requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
+        let dst = self.operand(ops[1])?;
 
         let dreg = match src {
             AddrMode::DataReg(r) => r,
-            _ => return Err(format!("{loc}: eor source must be data register")),
+            _ => return Err("eor source must be data register".to_string()),
         };
 
         let sz = match size {
@@ -3400,13 +7451,7 @@ fn encode_eor(
             Size::Word => 1,
             Size::Long => 2,
         };
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
         let opmode = sz | 0b100;
         let opcode = 0xB000
             | (u16::from(dreg) << 9)
@@ -3425,13 +7470,12 @@ fn encode_logical(
         base: u16,
         size: Size,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: requires 2 operands"));
+            return Err("requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let dst = self.operand(ops[1])?;
 
         let sz = match size {
             Size::Byte => 0,
@@ -3453,13 +7497,7 @@ fn encode_logical(
                 }
             }
             (AddrMode::DataReg(dreg), _) => {
-                let (mode, reg, ext) = encode_ea(
-                    &dst,
-                    self.symbols.as_map(),
-                    self.pc + 2,
-                    self.pass,
-                    self.scope(),
-                )?;
+                let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
                 let opmode = sz | 0b100;
                 let opcode = base
                     | (u16::from(*dreg) << 9)
@@ -3471,96 +7509,71 @@ fn encode_logical(
                     self.emit_word(e);
                 }
             }
-            _ => return Err(format!("{loc}: invalid operand combination")),
+            _ => return Err("invalid operand combination".to_string()),
         }
         Ok(())
     }
 
-    fn encode_andi(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_andi(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         // Check for ANDI to CCR/SR
         if ops.len() == 2 {
-            let dst = parse_operand(ops[1], self.symbols.as_map())?;
+            let dst = self.operand(ops[1])?;
             if matches!(dst, AddrMode::Ccr) {
-                return self.encode_imm_to_ccr(0x023C, ops, loc);
+                return self.encode_imm_to_ccr(0x023C, ops);
             }
             if matches!(dst, AddrMode::Sr) {
-                return self.encode_imm_to_sr(0x027C, ops, loc);
+                return self.encode_imm_to_sr(0x027C, ops);
             }
         }
-        self.encode_imm_op(0x0200, size, ops, loc)
+        self.encode_imm_op(0x0200, size, ops)
     }
 
-    fn encode_ori(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_ori(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() == 2 {
-            let dst = parse_operand(ops[1], self.symbols.as_map())?;
+            let dst = self.operand(ops[1])?;
             if matches!(dst, AddrMode::Ccr) {
-                return self.encode_imm_to_ccr(0x003C, ops, loc);
+                return self.encode_imm_to_ccr(0x003C, ops);
             }
             if matches!(dst, AddrMode::Sr) {
-                return self.encode_imm_to_sr(0x007C, ops, loc);
+                return self.encode_imm_to_sr(0x007C, ops);
             }
         }
-        self.encode_imm_op(0x0000, size, ops, loc)
+        self.encode_imm_op(0x0000, size, ops)
     }
 
-    fn encode_eori(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_eori(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() == 2 {
-            let dst = parse_operand(ops[1], self.symbols.as_map())?;
+            let dst = self.operand(ops[1])?;
             if matches!(dst, AddrMode::Ccr) {
-                return self.encode_imm_to_ccr(0x0A3C, ops, loc);
+                return self.encode_imm_to_ccr(0x0A3C, ops);
             }
             if matches!(dst, AddrMode::Sr) {
-                return self.encode_imm_to_sr(0x0A7C, ops, loc);
+                return self.encode_imm_to_sr(0x0A7C, ops);
             }
         }
-        self.encode_imm_op(0x0A00, size, ops, loc)
+        self.encode_imm_op(0x0A00, size, ops)
     }
 
-    fn encode_imm_to_ccr(
-        &mut self,
-        opcode: u16,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
+    fn encode_imm_to_ccr(&mut self, opcode: u16, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        let src = self.operand(ops[0])?;
         let imm = match src {
             AddrMode::Immediate(ref expr) => {
                 eval_expr(expr, self.symbols.as_map(), self.pc)? as u16
             }
-            _ => return Err(format!("{loc}: source must be immediate")),
+            _ => return Err("source must be immediate".to_string()),
         };
         self.emit_word(opcode);
         self.emit_word(imm & 0xFF);
         Ok(())
     }
 
-    fn encode_imm_to_sr(
-        &mut self,
-        opcode: u16,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
+    fn encode_imm_to_sr(&mut self, opcode: u16, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        let src = self.operand(ops[0])?;
         let imm = match src {
             AddrMode::Immediate(ref expr) => {
                 eval_expr(expr, self.symbols.as_map(), self.pc)? as u16
             }
-            _ => return Err(format!("{loc}: source must be immediate")),
+            _ => return Err("source must be immediate".to_string()),
         };
         self.emit_word(opcode);
         self.emit_word(imm);
@@ -3572,32 +7585,25 @@ fn encode_shift(
         &mut self,
         size: Size,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
         kind: u16,
         _is_arith: bool,
     ) -> Result<(), String> {
         if ops.len() == 1 {
             // Memory shift
-            let dst = parse_operand(ops[0], self.symbols.as_map())?;
-            let (mode, reg, ext) = encode_ea(
-                &dst,
-                self.symbols.as_map(),
-                self.pc + 2,
-                self.pass,
-                self.scope(),
-            )?;
+            let dst = self.operand(ops[0])?;
+            let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
             let opcode = 0xE0C0 | (kind << 9) | (u16::from(mode) << 3) | u16::from(reg);
             self.emit_word(opcode);
             for e in ext {
                 self.emit_word(e);
             }
         } else if ops.len() == 2 {
-            let src = parse_operand(ops[0], self.symbols.as_map())?;
-            let dst = parse_operand(ops[1], self.symbols.as_map())?;
+            let src = self.operand(ops[0])?;
+            let dst = self.operand(ops[1])?;
 
             let dreg = match dst {
                 AddrMode::DataReg(r) => r,
-                _ => return Err(format!("{loc}: shift destination must be data register")),
+                _ => return Err("shift destination must be data register".to_string()),
             };
 
             let sz = match size {
@@ -3619,7 +7625,9 @@ fn encode_shift(
                     self.emit_word(opcode);
                 }
                 AddrMode::Immediate(ref expr) => {
-                    let count = eval_expr(expr, self.symbols.as_map(), self.pc)? as u16;
+                    let (symbols, scope) = (self.symbols.as_map(), self.scope());
+                    let count = try_eval_expr(expr, symbols, self.pc, self.pass, scope)?;
+                    let count = check_range(self.pass, "shift count", count, 1..=8)? as u16;
                     let count = if count == 8 { 0 } else { count & 7 };
                     let opcode = 0xE000
                         | (count << 9)
@@ -3629,41 +7637,26 @@ fn encode_shift(
                         | u16::from(dreg);
                     self.emit_word(opcode);
                 }
-                _ => {
-                    return Err(format!(
-                        "{loc}: shift count must be immediate or data register"
-                    ))
-                }
+                _ => return Err("shift count must be immediate or data register".to_string()),
             }
         } else {
-            return Err(format!("{loc}: shift requires 1 or 2 operands"));
+            return Err("shift requires 1 or 2 operands".to_string());
         }
         Ok(())
     }
 
     // Bit operations
-    fn encode_bit(
-        &mut self,
-        op: u16,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_bit(&mut self, op: u16, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: bit operation requires 2 operands"));
+            return Err("bit operation requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
+        let dst = self.operand(ops[1])?;
 
         match src {
             AddrMode::DataReg(dreg) => {
                 // Dynamic bit
-                let (mode, reg, ext) = encode_ea(
-                    &dst,
-                    self.symbols.as_map(),
-                    self.pc + 2,
-                    self.pass,
-                    self.scope(),
-                )?;
+                let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
                 let opcode = 0x0100
                     | (u16::from(dreg) << 9)
                     | (op << 6)
@@ -3677,13 +7670,7 @@ fn encode_bit(
             AddrMode::Immediate(ref expr) => {
                 // Static bit
                 let bit_num = eval_expr(expr, self.symbols.as_map(), self.pc)? as u16;
-                let (mode, reg, ext) = encode_ea(
-                    &dst,
-                    self.symbols.as_map(),
-                    self.pc + 4,
-                    self.pass,
-                    self.scope(),
-                )?;
+                let (mode, reg, ext) = self.ea(&dst, self.pc + 4)?;
                 let opcode = 0x0800 | (op << 6) | (u16::from(mode) << 3) | u16::from(reg);
                 self.emit_word(opcode);
                 self.emit_word(bit_num);
@@ -3691,27 +7678,18 @@ fn encode_bit(
                     self.emit_word(e);
                 }
             }
-            _ => {
-                return Err(format!(
-                    "{loc}: bit number must be immediate or data register"
-                ))
-            }
+            _ => return Err("bit number must be immediate or data register".to_string()),
         }
         Ok(())
     }
 
     // BCD operations
-    fn encode_bcd(
-        &mut self,
-        base: u16,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_bcd(&mut self, base: u16, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: requires 2 operands"));
+            return Err("requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
+        let dst = self.operand(ops[1])?;
 
         match (&src, &dst) {
             (AddrMode::DataReg(rx), AddrMode::DataReg(ry)) => {
@@ -3722,23 +7700,17 @@ fn encode_bcd(
                 let opcode = base | (u16::from(*ry) << 9) | 0x08 | u16::from(*rx);
                 self.emit_word(opcode);
             }
-            _ => return Err(format!("{loc}: invalid operand combination for BCD")),
+            _ => return Err("invalid operand combination for BCD".to_string()),
         }
         Ok(())
     }
 
-    fn encode_nbcd(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_nbcd(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: nbcd requires 1 operand"));
-        }
-        let dst = parse_operand(ops[0], self.symbols.as_map())?;
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+            return Err("nbcd requires 1 operand".to_string());
+        }
+        let dst = self.operand(ops[0])?;
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
         let opcode = 0x4800 | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -3748,83 +7720,308 @@ fn encode_nbcd(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<()
     }
 
     // Branch instructions
-    fn encode_bra(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
-        self.encode_branch(0x6000, ops, loc)
+    fn encode_bra(&mut self, size: Option<Size>, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_branch(0x6000, size, ops)
     }
 
-    fn encode_bsr(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
-        self.encode_branch(0x6100, ops, loc)
+    fn encode_bsr(&mut self, size: Option<Size>, ops: &[&[LocatedToken]]) -> Result<(), String> {
+        self.encode_branch(0x6100, size, ops)
     }
 
     fn encode_bcc(
         &mut self,
         mnemonic: &str,
+        size: Option<Size>,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
-        let cc = Condition::from_name(&mnemonic[1..])
-            .ok_or_else(|| format!("{loc}: unknown condition"))?;
+        let cc =
+            Condition::from_name(&mnemonic[1..]).ok_or_else(|| "unknown condition".to_string())?;
         let base = 0x6000 | ((cc as u16) << 8);
-        self.encode_branch(base, ops, loc)
+        self.encode_branch(base, size, ops)
     }
 
+    /// Encodes a branch. `.s` forces the short form and `.w` the word
+    /// form; without a suffix the branch is short when the target is close
+    /// enough, which pass 1 settles by running until no branch grows.
     fn encode_branch(
         &mut self,
         base: u16,
+        size: Option<Size>,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
     ) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: branch requires 1 operand"));
+            return Err("branch requires 1 operand".to_string());
         }
 
-        let mut parser = ExprParser::new(ops[0]);
-        let expr = parser.parse_expr()?;
+        let expr = self.parse_expr_at(ops[0])?;
 
-        // In pass 1, we may have forward references. Use placeholder.
+        // In pass 1, we may have forward references
         let target = match eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope()) {
-            Ok(t) => t,
-            Err(_) if self.pass == 1 => i64::from(self.pc), // Placeholder
+            Ok(t) => Some(t),
+            Err(_) if self.pass == 1 => None,
             Err(e) => return Err(e),
         };
-        let disp = target - (i64::from(self.pc) + 2);
+        let disp = target.map(|t| t - (i64::from(self.pc) + 2));
+        // Only the linker knows how far another section or an import is
+        let external = self.external_target(&expr)?.is_some();
+        if let Some(target) = target.filter(|_| !external) {
+            self.note_odd_target("branch", target);
+        }
+        let drift = self.forward_drift(&expr);
+        // Shrinking a long branch brings a target after it 2 bytes nearer
+        let long = self.grown.get(self.sized_count) == Some(&true);
+
+        // The displacement the short form would have
+        let short_disp = disp.map(|disp| {
+            let disp = disp + drift;
+            if long && disp > 0 {
+                disp - 2
+            } else {
+                disp
+            }
+        });
 
-        if (-128..=127).contains(&disp) && disp != 0 {
-            // Short branch
-            let opcode = base | u16::from(disp as u8);
-            self.emit_word(opcode);
+        let short = match size {
+            Some(Size::Byte) => true,
+            Some(Size::Word) => false,
+            Some(Size::Long) => return Err("branches can't be .l on the 68000".to_string()),
+            None if external => self.keep_short(Some(false), Reach::Fixed),
+            None => {
+                let reach = match (short_disp, target) {
+                    (Some(disp), Some(target)) if self.on_code_label(&expr) => Reach::Branch {
+                        disp,
+                        target: (target + drift) as u32,
+                    },
+                    _ => Reach::Unknown,
+                };
+                self.keep_short(short_disp.map(fits_short), reach)
+            }
+        };
+
+        let disp = disp.unwrap_or(0);
+        if short {
+            if external {
+                return Err("a branch out of the section needs a word displacement".to_string());
+            }
+            if self.pass == 2 && !fits_short(disp) {
+                return Err(if disp == 0 {
+                    "a short branch can't target the next instruction".to_string()
+                } else {
+                    format!("branch target is {disp} bytes away, out of range for .s (-128 to 127)")
+                });
+            }
+            self.emit_word(base | u16::from(disp as u8));
+        } else if self.relocate_displacement(&expr, self.pc + 2)? {
+            self.emit_word(base);
+            self.emit_word(0);
         } else {
-            // Word displacement
+            if self.pass == 2 {
+                check_word_branch("branch", disp)?;
+            }
             self.emit_word(base);
             self.emit_word(disp as u16);
         }
         Ok(())
     }
 
-    fn encode_dbcc(
+    /// Chooses whether the next instruction with a short and a long form
+    /// takes the short one, given whether it fits if that's known yet.
+    ///
+    /// Instructions start short and grow once they don't fit, and never
+    /// shrink back, so the label addresses settle. `reach` is what decides
+    /// whether it fits (see `seed_held`).
+    fn keep_short(&mut self, fits: Option<bool>, reach: Reach) -> bool {
+        let index = self.sized_count;
+        self.sized_count += 1;
+        let long = match self.grown.get(index) {
+            // Pass 2 keeps what pass 1 settled on
+            Some(&long) if long || self.pass == 2 => long,
+            _ => fits == Some(false),
+        };
+        self.unsettled |= fits.is_none() || long && fits == Some(true);
+        match self.site_pcs.get_mut(index) {
+            Some(pc) => *pc = self.pc,
+            None => self.site_pcs.push(self.pc),
+        }
+        match self.reaches.get_mut(index) {
+            Some(old) => *old = reach,
+            None => self.reaches.push(reach),
+        }
+        if let Some(grown) = self.grown.get_mut(index) {
+            self.resize |= long != *grown;
+            *grown = long;
+        } else {
+            // What isn't known yet may not fit
+            self.resize |= fits.is_none();
+            self.grown.push(long);
+        }
+        !long
+    }
+
+    /// Returns how far the code before the next instruction with a short
+    /// and a long form has moved since it was last encoded, if `expr`
+    /// refers forward to a code label. The label's address is still the
+    /// one from then, so adding this measures how far away it is as the
+    /// code was laid out then: the code between can only have grown since,
+    /// so an instruction never grows for a label that only seems out of
+    /// reach because its address is stale.
+    fn forward_drift(&self, expr: &Expr) -> i64 {
+        let Some(&previous) = self.site_pcs.get(self.sized_count) else {
+            return 0;
+        };
+        let forward = expr.base_symbol().is_some_and(|base| {
+            let name = self.expand_local_label(base);
+            self.code_labels.contains(&name) && !self.symbols.defined_this_pass(&name)
+        });
+        if forward {
+            i64::from(self.pc) - i64::from(previous)
+        } else {
+            0
+        }
+    }
+
+    /// Returns true if an expression's value depends on where code is: it
+    /// uses `*`, a code label, or a symbol defined from one.
+    fn layout_dependent(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::CurrentPc => true,
+            Expr::Symbol(name) => {
+                let name = self.expand_local_label(name);
+                self.code_labels.contains(&name) || self.layout_symbols.contains(&name)
+            }
+            Expr::Neg(e) | Expr::Not(e) | Expr::Call(_, e) => self.layout_dependent(e),
+            Expr::BinOp(l, _, r) => self.layout_dependent(l) || self.layout_dependent(r),
+            Expr::Number(_) => false,
+        }
+    }
+
+    /// Returns true if an expression is a code label plus or minus a
+    /// number.
+    fn on_code_label(&self, expr: &Expr) -> bool {
+        expr.base_symbol()
+            .is_some_and(|base| self.code_labels.contains(&self.expand_local_label(base)))
+    }
+
+    /// Returns the reach of a value that isn't a distance or an address:
+    /// fixed unless it depends on where code is.
+    fn reach_of(&self, expr: &Expr) -> Reach {
+        if self.layout_dependent(expr) {
+            Reach::Unknown
+        } else {
+            Reach::Fixed
+        }
+    }
+
+    /// Returns how much an expression's value changes when all code moves
+    /// up a byte, if it's linear in where code is.
+    fn shift_weight(&self, expr: &Expr) -> Option<i64> {
+        match expr {
+            Expr::Number(_) => Some(0),
+            Expr::CurrentPc => Some(1),
+            Expr::Symbol(name) => {
+                let name = self.expand_local_label(name);
+                if self.code_labels.contains(&name) {
+                    Some(1)
+                } else {
+                    (!self.layout_symbols.contains(&name)).then_some(0)
+                }
+            }
+            Expr::Neg(e) => self.shift_weight(e).map(|weight| -weight),
+            Expr::BinOp(l, op, r) => {
+                let (l, r) = (self.shift_weight(l)?, self.shift_weight(r)?);
+                match op {
+                    BinOp::Add => Some(l + r),
+                    BinOp::Sub => Some(l - r),
+                    _ => (l == 0 && r == 0).then_some(0),
+                }
+            }
+            Expr::Not(e) | Expr::Call(_, e) => (self.shift_weight(e)? == 0).then_some(0),
+        }
+    }
+
+    /// Notes where a DS or DCB block `length` bytes long, of `count` units
+    /// of `unit` bytes, leaves the code after it, when the count depends on
+    /// where code is: it's only fixed or moves with the code before it when
+    /// the code labels it uses all come after the last instruction with a
+    /// short and a long form (see `seed_held`).
+    fn note_block(&mut self, count: &Expr, unit: usize, length: usize) {
+        if !self.layout_dependent(count) {
+            return;
+        }
+        let mut names = Vec::new();
+        count.symbol_names(&mut names);
+        let last_site = self.site_pcs[..self.sized_count].last().copied();
+        let pc = i64::from(self.pc);
+        let local = names.iter().all(|name| {
+            let name = self.expand_local_label(name);
+            !self.code_labels.contains(&name)
+                || self.symbols.as_map().get(&name).is_some_and(|&address| {
+                    address <= pc && last_site.is_none_or(|site| i64::from(site) < address)
+                })
+        });
+        let end = self
+            .shift_weight(count)
+            .filter(|_| local)
+            .map(|weight| 1 + weight * unit as i64);
+        match end {
+            Some(1) => {}
+            Some(0) if self.sized_count > 0 => self.barriers.push(self.pc + length as u32),
+            Some(0) => {}
+            _ => self.layout_shifts = true,
+        }
+    }
+
+    /// Returns true if an instruction with an immediate source and a quick
+    /// form should take it: optimizing is on, `quick_dst` accepts the size
+    /// and destination, and the value, taken as a 32-bit immediate, is in
+    /// `range`.
+    fn use_quick(
         &mut self,
-        mnemonic: &str,
+        size: Size,
         ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+        range: std::ops::RangeInclusive<i64>,
+        quick_dst: fn(Size, &AddrMode) -> bool,
+    ) -> bool {
+        if !self.options.optimize || ops.len() != 2 {
+            return false;
+        }
+        let symbols = self.symbols.as_map();
+        let (Ok(AddrMode::Immediate(expr)), Ok(dst)) = (
+            parse_operand(ops[0], symbols),
+            parse_operand(&self.with_registers(ops[1]), symbols),
+        ) else {
+            return false;
+        };
+        if !quick_dst(size, &dst) {
+            return false;
+        }
+        let value = eval_expr_scoped(&expr, symbols, self.pc, self.scope()).ok();
+        let reach = self.reach_of(&expr);
+        // The immediate is as wide as the operation, so a long $FFFFFFFF is -1
+        let fits = value.map(|value| {
+            size.range().contains(&value) && range.contains(&i64::from(value as u32 as i32))
+        });
+        self.keep_short(fits, reach)
+    }
+
+    fn encode_dbcc(&mut self, mnemonic: &str, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: dbcc requires 2 operands"));
+            return Err("dbcc requires 2 operands".to_string());
         }
 
         let cc = if mnemonic == "DBRA" {
             Condition::False
         } else {
             let cc_name = &mnemonic[2..];
-            Condition::from_name(cc_name).ok_or_else(|| format!("{loc}: unknown condition"))?
+            Condition::from_name(cc_name).ok_or_else(|| "unknown condition".to_string())?
         };
 
-        let dreg = match parse_operand(ops[0], self.symbols.as_map())? {
+        let dreg = match self.operand(ops[0])? {
             AddrMode::DataReg(r) => r,
-            _ => return Err(format!("{loc}: dbcc requires data register")),
+            _ => return Err("dbcc requires data register".to_string()),
         };
 
-        let mut parser = ExprParser::new(ops[1]);
-        let expr = parser.parse_expr()?;
+        let expr = self.parse_expr_at(ops[1])?;
         // In pass 1, we may have forward references. Use placeholder.
         let target = match eval_expr_scoped(&expr, self.symbols.as_map(), self.pc, self.scope()) {
             Ok(t) => t,
@@ -3832,33 +8029,34 @@ fn encode_dbcc(
             Err(e) => return Err(e),
         };
         let disp = target - (i64::from(self.pc) + 2);
+        if self.external_target(&expr)?.is_none() {
+            self.note_odd_target("dbcc", target);
+        }
 
         let opcode = 0x50C8 | ((cc as u16) << 8) | u16::from(dreg);
+        if self.relocate_displacement(&expr, self.pc + 2)? {
+            self.emit_word(opcode);
+            self.emit_word(0);
+            return Ok(());
+        }
+        if self.pass == 2 {
+            check_word_branch("dbcc", disp)?;
+        }
+
         self.emit_word(opcode);
         self.emit_word(disp as u16);
         Ok(())
     }
 
-    fn encode_scc(
-        &mut self,
-        mnemonic: &str,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_scc(&mut self, mnemonic: &str, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: scc requires 1 operand"));
-        }
-
-        let cc = Condition::from_name(&mnemonic[1..])
-            .ok_or_else(|| format!("{loc}: unknown condition"))?;
-        let dst = parse_operand(ops[0], self.symbols.as_map())?;
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+            return Err("scc requires 1 operand".to_string());
+        }
+
+        let cc =
+            Condition::from_name(&mnemonic[1..]).ok_or_else(|| "unknown condition".to_string())?;
+        let dst = self.operand(ops[0])?;
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
         let opcode = 0x50C0 | ((cc as u16) << 8) | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -3867,19 +8065,46 @@ fn encode_scc(
         Ok(())
     }
 
+    /// Notes a branch to an odd address, naming the label it's at when it
+    /// has one.
+    fn note_odd_target(&mut self, what: &str, target: i64) {
+        if target & 1 == 0 {
+            return;
+        }
+        let label = self
+            .pass1_labels
+            .iter()
+            .find(|&&(_, address)| i64::from(address) == target);
+        let message = match label {
+            Some((name, _)) => {
+                format!("{what} target {name} is at odd address ${target:X} (add EVEN before it)")
+            }
+            None => format!("{what} target ${target:X} is odd"),
+        };
+        self.note_odd(target, || message);
+    }
+
+    /// Notes a JMP or JSR to an absolute or PC-relative odd address.
+    fn note_odd_jump(&mut self, what: &str, dst: &AddrMode) -> Result<(), String> {
+        let (AddrMode::AbsShort(expr) | AddrMode::AbsLong(expr) | AddrMode::PcDisp(expr)) = dst
+        else {
+            return Ok(());
+        };
+        if self.pass == 2 && self.external_target(expr)?.is_none() {
+            let target = eval_expr_scoped(expr, self.symbols.as_map(), self.pc, self.scope())?;
+            self.note_odd_target(what, target);
+        }
+        Ok(())
+    }
+
     // Control instructions
-    fn encode_jmp(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_jmp(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: jmp requires 1 operand"));
-        }
-        let dst = parse_operand(ops[0], self.symbols.as_map())?;
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+            return Err("jmp requires 1 operand".to_string());
+        }
+        let dst = self.source_operand(ops[0], self.pc + 2)?;
+        self.note_odd_jump("jmp", &dst)?;
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
         let opcode = 0x4EC0 | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -3888,18 +8113,13 @@ fn encode_jmp(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(),
         Ok(())
     }
 
-    fn encode_jsr(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_jsr(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: jsr requires 1 operand"));
-        }
-        let dst = parse_operand(ops[0], self.symbols.as_map())?;
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+            return Err("jsr requires 1 operand".to_string());
+        }
+        let dst = self.source_operand(ops[0], self.pc + 2)?;
+        self.note_odd_jump("jsr", &dst)?;
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
         let opcode = 0x4E80 | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -3908,34 +8128,34 @@ fn encode_jsr(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(),
         Ok(())
     }
 
-    fn encode_trap(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_trap(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: trap requires 1 operand"));
+            return Err("trap requires 1 operand".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
         let vector = match src {
             AddrMode::Immediate(ref expr) => {
                 eval_expr(expr, self.symbols.as_map(), self.pc)? as u16
             }
-            _ => return Err(format!("{loc}: trap requires immediate vector")),
+            _ => return Err("trap requires immediate vector".to_string()),
         };
         if vector > 15 {
-            return Err(format!("{loc}: trap vector must be 0-15"));
+            return Err("trap vector must be 0-15".to_string());
         }
         self.emit_word(0x4E40 | vector);
         Ok(())
     }
 
-    fn encode_chk(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_chk(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: chk requires 2 operands"));
+            return Err("chk requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.source_operand(ops[0], self.pc + 2)?;
+        let dst = self.operand(ops[1])?;
 
         let dreg = match dst {
             AddrMode::DataReg(r) => r,
-            _ => return Err(format!("{loc}: chk destination must be data register")),
+            _ => return Err("chk destination must be data register".to_string()),
         };
 
         let (mode, reg, ext) = self.encode_ea_with_imm(&src, Size::Word)?;
@@ -3947,22 +8167,22 @@ fn encode_chk(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(),
         Ok(())
     }
 
-    fn encode_link(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_link(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: link requires 2 operands"));
+            return Err("link requires 2 operands".to_string());
         }
-        let areg_mode = parse_operand(ops[0], self.symbols.as_map())?;
-        let disp_mode = parse_operand(ops[1], self.symbols.as_map())?;
+        let areg_mode = self.operand(ops[0])?;
+        let disp_mode = self.operand(ops[1])?;
 
         let areg = match areg_mode {
             AddrMode::AddrReg(r) => r,
-            _ => return Err(format!("{loc}: link requires address register")),
+            _ => return Err("link requires address register".to_string()),
         };
         let disp = match disp_mode {
             AddrMode::Immediate(ref expr) => {
                 eval_expr(expr, self.symbols.as_map(), self.pc)? as i16
             }
-            _ => return Err(format!("{loc}: link requires immediate displacement")),
+            _ => return Err("link requires immediate displacement".to_string()),
         };
 
         self.emit_word(0x4E50 | u16::from(areg));
@@ -3970,47 +8190,41 @@ fn encode_link(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<()
         Ok(())
     }
 
-    fn encode_unlk(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_unlk(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: unlk requires 1 operand"));
+            return Err("unlk requires 1 operand".to_string());
         }
-        let areg_mode = parse_operand(ops[0], self.symbols.as_map())?;
+        let areg_mode = self.operand(ops[0])?;
         let areg = match areg_mode {
             AddrMode::AddrReg(r) => r,
-            _ => return Err(format!("{loc}: unlk requires address register")),
+            _ => return Err("unlk requires address register".to_string()),
         };
         self.emit_word(0x4E58 | u16::from(areg));
         Ok(())
     }
 
-    fn encode_stop(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_stop(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: stop requires 1 operand"));
+            return Err("stop requires 1 operand".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
         let imm = match src {
             AddrMode::Immediate(ref expr) => {
                 eval_expr(expr, self.symbols.as_map(), self.pc)? as u16
             }
-            _ => return Err(format!("{loc}: stop requires immediate")),
+            _ => return Err("stop requires immediate".to_string()),
         };
         self.emit_word(0x4E72);
         self.emit_word(imm);
         Ok(())
     }
 
-    fn encode_tas(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(), String> {
+    fn encode_tas(&mut self, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 1 {
-            return Err(format!("{loc}: tas requires 1 operand"));
-        }
-        let dst = parse_operand(ops[0], self.symbols.as_map())?;
-        let (mode, reg, ext) = encode_ea(
-            &dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+            return Err("tas requires 1 operand".to_string());
+        }
+        let dst = self.operand(ops[0])?;
+        let (mode, reg, ext) = self.ea(&dst, self.pc + 2)?;
         let opcode = 0x4AC0 | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -4019,86 +8233,97 @@ fn encode_tas(&mut self, ops: &[&[LocatedToken]], loc: &SourceLoc) -> Result<(),
         Ok(())
     }
 
-    fn encode_movem(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    fn encode_movem(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: movem requires 2 operands"));
+            return Err("movem requires 2 operands".to_string());
         }
 
-        // Determine direction: register list to memory, or memory to register list
-        // A register list contains only identifiers, minus (for ranges), and slash (for lists)
-        // If first operand has parentheses or other tokens, it's a memory address
-        let first_is_reglist = ops[0]
-            .iter()
-            .all(|t| matches!(t.token, Token::Ident(_) | Token::Minus | Token::Slash));
-
-        let sz = u16::from(size == Size::Long);
-
-        if first_is_reglist {
-            // Try parsing first operand as register list
-            let first_str: String = ops[0]
-                .iter()
-                .filter_map(|t| match &t.token {
-                    Token::Ident(s) => Some(s.clone()),
-                    Token::Minus => Some("-".to_string()),
-                    Token::Slash => Some("/".to_string()),
-                    _ => None,
-                })
-                .collect();
-
-            if let Ok(mask) = parse_register_list(&first_str) {
-                // Register list to memory
-                let dst = parse_operand(ops[1], self.symbols.as_map())?;
-                let (mode, reg, ext) = encode_ea(
-                    &dst,
-                    self.symbols.as_map(),
-                    self.pc + 4,
-                    self.pass,
-                    self.scope(),
-                )?;
-
-                // For pre-decrement, reverse the mask
-                let mask = if matches!(dst, AddrMode::PreDec(_)) {
-                    reverse_bits_16(mask)
-                } else {
-                    mask
-                };
+        // Direction: the register list (or mask) is the source when storing
+        // and the destination when loading
+        let lists = [ops[0], ops[1]].map(|op| looks_like_register_list(&self.with_registers(op)));
+        let to_memory = match lists {
+            [true, true] => {
+                self.error_span = token_span(ops[1]);
+                return Err(
+                    "movem moves registers to or from memory, not between lists".to_string()
+                );
+            }
+            [true, false] => true,
+            [false, true] => false,
+            [false, false] => {
+                let first = movem_mask_rank(&self.operand(ops[0])?);
+                let second = movem_mask_rank(&self.operand(ops[1])?);
+                if first == second {
+                    return Err(
+                        "movem needs a register list (like d0-d3/a0) or #mask in one operand"
+                            .to_string(),
+                    );
+                }
+                first > second
+            }
+        };
+        let (list, ea) = if to_memory {
+            (ops[0], ops[1])
+        } else {
+            (ops[1], ops[0])
+        };
 
-                let opcode = 0x4880 | (sz << 6) | (u16::from(mode) << 3) | u16::from(reg);
-                self.emit_word(opcode);
-                self.emit_word(mask);
-                for e in ext {
-                    self.emit_word(e);
+        let mask = self.movem_mask(list)?;
+        let ea = if to_memory {
+            self.operand(ea)?
+        } else {
+            self.source_operand(ea, self.pc + 4)?
+        };
+        let allowed = if to_memory {
+            matches!(
+                ea,
+                AddrMode::AddrInd(_)
+                    | AddrMode::PreDec(_)
+                    | AddrMode::Disp(..)
+                    | AddrMode::Index(..)
+                    | AddrMode::AbsShort(_)
+                    | AddrMode::AbsLong(_)
+            )
+        } else {
+            matches!(
+                ea,
+                AddrMode::AddrInd(_)
+                    | AddrMode::PostInc(_)
+                    | AddrMode::Disp(..)
+                    | AddrMode::Index(..)
+                    | AddrMode::AbsShort(_)
+                    | AddrMode::AbsLong(_)
+                    | AddrMode::PcDisp(_)
+                    | AddrMode::PcIndex(..)
+            )
+        };
+        if !allowed {
+            self.error_span = token_span(list);
+            return Err(match ea {
+                AddrMode::PostInc(_) => {
+                    "movem stores registers through -(An); (An)+ loads them, list second"
                 }
-                return Ok(());
+                AddrMode::PreDec(_) => {
+                    "movem loads registers from (An)+; -(An) stores them, list first"
+                }
+                _ if to_memory => "movem can't store a register list to that operand",
+                _ => "movem can't load a register list from that operand",
             }
+            .to_string());
         }
 
-        // Memory to register list
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let second_str: String = ops[1]
-            .iter()
-            .filter_map(|t| match &t.token {
-                Token::Ident(s) => Some(s.clone()),
-                Token::Minus => Some("-".to_string()),
-                Token::Slash => Some("/".to_string()),
-                _ => None,
-            })
-            .collect();
-        let mask = parse_register_list(&second_str)?;
-
-        let (mode, reg, ext) = encode_ea(
-            &src,
-            self.symbols.as_map(),
-            self.pc + 4,
-            self.pass,
-            self.scope(),
-        )?;
-        let opcode = 0x4C80 | (sz << 6) | (u16::from(mode) << 3) | u16::from(reg);
+        let (mode, reg, ext) = self.ea(&ea, self.pc + 4)?;
+
+        // Pre-decrement stores A7 first, so its mask runs backwards
+        let mask = if matches!(ea, AddrMode::PreDec(_)) {
+            reverse_bits_16(mask)
+        } else {
+            mask
+        };
+
+        let sz = u16::from(size == Size::Long);
+        let base = if to_memory { 0x4880 } else { 0x4C80 };
+        let opcode = base | (sz << 6) | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         self.emit_word(mask);
         for e in ext {
@@ -4107,17 +8332,41 @@ fn encode_movem(
         Ok(())
     }
 
-    fn encode_movep(
-        &mut self,
-        size: Size,
-        ops: &[&[LocatedToken]],
-        loc: &SourceLoc,
-    ) -> Result<(), String> {
+    /// Evaluates a MOVEM register list, or a mask given as `#value` or a
+    /// symbol, in D0-first order (bit 0 is D0, bit 15 is A7).
+    fn movem_mask(&mut self, tokens: &[LocatedToken]) -> Result<u16, String> {
+        let resolved = self.with_registers(tokens);
+        let result = if looks_like_register_list(&resolved) {
+            let text: String = resolved
+                .iter()
+                .map(|t| match &t.token {
+                    Token::Ident(s) => s.as_str(),
+                    Token::Minus => "-",
+                    _ => "/",
+                })
+                .collect();
+            parse_register_list(&text)
+        } else {
+            let expr = match self.operand(tokens)? {
+                AddrMode::Immediate(expr) | AddrMode::AbsShort(expr) | AddrMode::AbsLong(expr) => {
+                    expr
+                }
+                _ => return Err("movem mask must be a register list or #value".to_string()),
+            };
+            let (symbols, scope) = (self.symbols.as_map(), self.scope());
+            try_eval_expr(&expr, symbols, self.pc, self.pass, scope)
+                .and_then(|value| check_range(self.pass, "movem mask", value, 0..=0xFFFF))
+                .map(|value| value as u16)
+        };
+        result.inspect_err(|_| self.error_span = token_span(tokens))
+    }
+
+    fn encode_movep(&mut self, size: Size, ops: &[&[LocatedToken]]) -> Result<(), String> {
         if ops.len() != 2 {
-            return Err(format!("{loc}: movep requires 2 operands"));
+            return Err("movep requires 2 operands".to_string());
         }
-        let src = parse_operand(ops[0], self.symbols.as_map())?;
-        let dst = parse_operand(ops[1], self.symbols.as_map())?;
+        let src = self.operand(ops[0])?;
+        let dst = self.operand(ops[1])?;
 
         let opmode = if size == Size::Long { 0b11 } else { 0b10 };
 
@@ -4136,12 +8385,12 @@ fn encode_movep(
                 self.emit_word(opcode);
                 self.emit_word(disp);
             }
-            _ => return Err(format!("{loc}: movep requires d(An),Dn or Dn,d(An)")),
+            _ => return Err("movep requires d(An),Dn or Dn,d(An)".to_string()),
         }
         Ok(())
     }
 
-    fn encode_move_to_sr(&mut self, src: &AddrMode, _loc: &SourceLoc) -> Result<(), String> {
+    fn encode_move_to_sr(&mut self, src: &AddrMode) -> Result<(), String> {
         let (mode, reg, ext) = self.encode_ea_with_imm(src, Size::Word)?;
         let opcode = 0x46C0 | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
@@ -4151,14 +8400,8 @@ fn encode_move_to_sr(&mut self, src: &AddrMode, _loc: &SourceLoc) -> Result<(),
         Ok(())
     }
 
-    fn encode_move_from_sr(&mut self, dst: &AddrMode, _loc: &SourceLoc) -> Result<(), String> {
-        let (mode, reg, ext) = encode_ea(
-            dst,
-            self.symbols.as_map(),
-            self.pc + 2,
-            self.pass,
-            self.scope(),
-        )?;
+    fn encode_move_from_sr(&mut self, dst: &AddrMode) -> Result<(), String> {
+        let (mode, reg, ext) = self.ea(dst, self.pc + 2)?;
         let opcode = 0x40C0 | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
         for e in ext {
@@ -4167,7 +8410,7 @@ fn encode_move_from_sr(&mut self, dst: &AddrMode, _loc: &SourceLoc) -> Result<()
         Ok(())
     }
 
-    fn encode_move_to_ccr(&mut self, src: &AddrMode, _loc: &SourceLoc) -> Result<(), String> {
+    fn encode_move_to_ccr(&mut self, src: &AddrMode) -> Result<(), String> {
         let (mode, reg, ext) = self.encode_ea_with_imm(src, Size::Word)?;
         let opcode = 0x44C0 | (u16::from(mode) << 3) | u16::from(reg);
         self.emit_word(opcode);
@@ -4177,30 +8420,22 @@ fn encode_move_to_ccr(&mut self, src: &AddrMode, _loc: &SourceLoc) -> Result<(),
         Ok(())
     }
 
-    fn encode_move_to_usp(&mut self, src: &AddrMode, loc: &SourceLoc) -> Result<(), String> {
+    fn encode_move_to_usp(&mut self, src: &AddrMode) -> Result<(), String> {
         // MOVE An,USP: 0100 1110 0110 0 An
         let areg = match src {
             AddrMode::AddrReg(r) => *r,
-            _ => {
-                return Err(format!(
-                    "{loc}: move to USP source must be address register"
-                ))
-            }
+            _ => return Err("move to USP source must be address register".to_string()),
         };
         let opcode = 0x4E60 | u16::from(areg);
         self.emit_word(opcode);
         Ok(())
     }
 
-    fn encode_move_from_usp(&mut self, dst: &AddrMode, loc: &SourceLoc) -> Result<(), String> {
+    fn encode_move_from_usp(&mut self, dst: &AddrMode) -> Result<(), String> {
         // MOVE USP,An: 0100 1110 0110 1 An
         let areg = match dst {
             AddrMode::AddrReg(r) => *r,
-            _ => {
-                return Err(format!(
-                    "{loc}: move from USP destination must be address register"
-                ))
-            }
+            _ => return Err("move from USP destination must be address register".to_string()),
         };
         let opcode = 0x4E68 | u16::from(areg);
         self.emit_word(opcode);
@@ -4266,6 +8501,47 @@ fn test_lexer_binary_numbers() {
         assert!(matches!(tokens[1].token, Token::Number(0b11110000)));
     }
 
+    #[test]
+    fn test_lexer_c_style_numbers() {
+        let numbers = |source: &str| {
+            let tokens = Lexer::new(source, "test.asm").tokenize().unwrap();
+            tokens
+                .into_iter()
+                .map(|t| t.token)
+                .filter(|t| *t != Token::Eof)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            numbers("0x1F 0XdeadBEEF 0b1010 0B11"),
+            [0x1F, 0xDEAD_BEEF, 0b1010, 0b11].map(Token::Number)
+        );
+        assert_eq!(
+            numbers("1_000 $FF_FF %1010_0101 0xFF_00 0b1_1"),
+            [1000, 0xFFFF, 0b1010_0101, 0xFF00, 0b11].map(Token::Number)
+        );
+
+        let error = |source: &str| Lexer::new(source, "test.asm").tokenize().unwrap_err();
+        assert_eq!(error("0b"), "invalid binary number: 0b");
+        assert_eq!(error("0x"), "invalid hex number: 0x");
+        assert_eq!(error("0b2"), "invalid binary number: 0b2");
+        assert_eq!(error("0x_1"), "invalid hex number: 0x_1");
+        assert_eq!(error("1__0"), "invalid decimal number: 1__0");
+        assert_eq!(error("$1__2"), "invalid hex number: $1__2");
+        assert_eq!(error("1_"), "invalid decimal number: 1_");
+        assert_eq!(error("%_1"), "invalid binary number: %_1");
+        assert_eq!(error("0xZZ"), "invalid hex number: 0xZZ");
+        assert_eq!(error("0x1G"), "invalid hex number: 0x1G");
+        assert_eq!(error("0b102"), "invalid binary number: 0b102");
+
+        let binary = Assembler::new()
+            .assemble_checked(
+                "        move.w  #0x7F_FF,d0\n        dc.b    0b1000_0001\n",
+                std::path::Path::new("test.asm"),
+            )
+            .unwrap();
+        assert_eq!(binary, [0x30, 0x3C, 0x7F, 0xFF, 0x81]);
+    }
+
     #[test]
     fn test_lexer_strings() {
         let source = r#""hello" "world\n""#;
@@ -4343,6 +8619,38 @@ fn test_lexer_char_literal() {
         assert!(matches!(tokens[1].token, Token::Char('x')));
     }
 
+    #[test]
+    fn test_lexer_columns_count_characters() {
+        // Tabs and UTF-8 characters are one column each, and CRLF ends a line
+        let source = "\tdc.b\t\"é\",1 ; ü\r\n  nop\r\n";
+        let mut lexer = Lexer::new(source, "test.asm");
+        let tokens = lexer.tokenize().unwrap();
+        let spans: Vec<_> = tokens
+            .iter()
+            .filter(|t| t.token != Token::Newline && t.token != Token::Eof)
+            .map(|t| (t.loc.line, t.loc.column, t.len))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (1, 2, 2),
+                (1, 4, 2),
+                (1, 7, 3),
+                (1, 10, 1),
+                (1, 11, 1),
+                (2, 3, 3)
+            ]
+        );
+
+        // A string can't run on past the end of its line
+        let (tokens, errors) =
+            Lexer::new("\tdc.b \"oops\n\tnop\n", "test.asm").tokenize_recovering();
+        assert_eq!((errors[0].line, errors[0].column), (1, 7));
+        assert!(tokens
+            .iter()
+            .any(|t| matches!(&t.token, Token::Ident(s) if s == "nop") && t.loc.line == 2));
+    }
+
     // ------------------------------------------------------------------------
     // Expression parser tests
     // ------------------------------------------------------------------------
@@ -4461,6 +8769,44 @@ fn test_expr_comparison() {
         assert_eq!(result, 1);
     }
 
+    #[test]
+    fn test_multi_character_constants() {
+        let source = "        move.w  #'AB',d0
+        cmp.l   #'FORM',d1
+        move.l  #'ABC',d2
+        dc.w    'AB','CD'+1
+        dc.l    'HDR1'
+        dc.b    'HDR1',0
+        dc.w    'xyz'
+";
+        let binary = Assembler::new()
+            .assemble_source(source, std::path::Path::new("test.asm"))
+            .unwrap();
+        assert_eq!(
+            binary,
+            [
+                0x30, 0x3C, 0x41, 0x42, // move.w #$4142,d0
+                0xB2, 0xBC, 0x46, 0x4F, 0x52, 0x4D, // cmp.l #$464F524D,d1
+                0x24, 0x3C, 0x00, 0x41, 0x42, 0x43, // move.l #$00414243,d2
+                0x41, 0x42, 0x43, 0x45, // dc.w $4142,$4345
+                0x48, 0x44, 0x52, 0x31, // dc.l $48445231
+                b'H', b'D', b'R', b'1', 0, // dc.b
+                0, b'x', 0, b'y', 0, b'z', // longer than a word: one to a word
+            ]
+        );
+
+        let errors = Assembler::new()
+            .assemble_checked(
+                "        move.l  #'HELLO',d0\n",
+                std::path::Path::new("test.asm"),
+            )
+            .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "'HELLO' isn't a value (a character constant has 1 to 4 characters)"
+        );
+    }
+
     // ------------------------------------------------------------------------
     // Symbol table tests
     // ------------------------------------------------------------------------
@@ -4512,6 +8858,55 @@ fn test_symbol_table_redefine_allowed() {
         assert_eq!(st.get("FOO"), Some(200));
     }
 
+    #[test]
+    fn test_symbol_table_set_variables() {
+        let mut st = SymbolTable::new();
+        st.assign("n", 1).unwrap();
+        st.assign("n", 2).unwrap();
+        assert_eq!(st.get("n"), Some(2));
+        assert_eq!(st.define("n", 3), Err("n is a SET symbol".to_string()));
+
+        st.define("FOO", 100).unwrap();
+        assert_eq!(
+            st.assign("FOO", 1),
+            Err("FOO is already defined and can't be SET".to_string())
+        );
+
+        st.begin_pass();
+        assert_eq!(st.get("n"), None);
+        assert_eq!(st.get("FOO"), Some(100));
+    }
+
+    #[test]
+    fn test_assemble_set_counter_in_rept() {
+        let source = "
+            org $1000
+index       set     0
+table:
+            rept    4
+            dc.w    handlers+index*6-table
+index       set     index+1
+            endr
+count       equ     index
+handlers:   nop
+";
+        let path = std::path::Path::new("test.asm");
+        let mut asm = Assembler::new();
+        let output = asm.assemble_source(source, path).unwrap();
+        // The offsets use the forward label and each pass's counter
+        assert_eq!(output[..8], [0, 8, 0, 14, 0, 20, 0, 26]);
+        assert_eq!(asm.symbols.get("count"), Some(4));
+        assert_eq!(asm.symbols.get("index"), Some(4));
+
+        let errors = Assembler::new()
+            .assemble_checked("size equ 4\nsize set 5\n", path)
+            .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "size is already defined and can't be SET"
+        );
+    }
+
     // ------------------------------------------------------------------------
     // Register parsing tests
     // ------------------------------------------------------------------------
@@ -4540,6 +8935,9 @@ fn test_parse_register_address() {
     fn test_parse_register_sp() {
         assert_eq!(parse_register("sp"), Some((7, true)));
         assert_eq!(parse_register("SP"), Some((7, true)));
+        assert_eq!(parse_register("ssp"), Some((7, true)));
+        assert_eq!(parse_register("fp"), Some((6, true)));
+        assert_eq!(parse_register("usp"), None);
     }
 
     #[test]
@@ -4586,7 +8984,7 @@ fn test_size_from_suffix() {
         assert_eq!(Size::from_suffix("W"), Some(Size::Word));
         assert_eq!(Size::from_suffix("l"), Some(Size::Long));
         assert_eq!(Size::from_suffix("L"), Some(Size::Long));
-        assert_eq!(Size::from_suffix("s"), Some(Size::Word));
+        assert_eq!(Size::from_suffix("s"), Some(Size::Byte));
         assert_eq!(Size::from_suffix("x"), None);
     }
 
@@ -4845,6 +9243,48 @@ fn test_directive_dc_string() {
         assert_eq!(asm.output, vec![b'H', b'i', 0]);
     }
 
+    #[test]
+    fn test_directive_dc_mixed_and_dcb() {
+        let source = "
+            dc.b    'Hello',13,10,0,'!'
+            dc.b    \"a\\tb\",-1
+            even
+size:       dc.w    table_end-table_start
+table_start:
+            dcb.l   16,$DEADBEEF
+table_end:
+";
+        let path = std::path::Path::new("test.asm");
+        let mut asm = Assembler::new();
+        let output = asm.assemble_source(source, path).unwrap();
+        assert_eq!(output[..13], *b"Hello\r\n\0!a\tb\xFF");
+        assert_eq!(asm.symbols.get("size"), Some(14));
+        assert_eq!(output[14..16], [0, 64]);
+        assert_eq!(output.len(), 16 + 64);
+        assert!(output[16..]
+            .chunks(4)
+            .all(|long| long == [0xDE, 0xAD, 0xBE, 0xEF]));
+
+        let error = |source: &str| {
+            let errors = Assembler::new().assemble_checked(source, path).unwrap_err();
+            errors[0].message.clone()
+        };
+        assert_eq!(
+            error("    dc.b 'A',256\n"),
+            "value 256 is out of range for a byte (-128 to 255)"
+        );
+        // Forward references are checked once they resolve
+        assert_eq!(
+            error("    dc.w far\nfar equ $10000\n"),
+            "value 65536 is out of range for a word (-32768 to 65535)"
+        );
+        assert_eq!(
+            error("    dcb.b 2,-129\n"),
+            "value -129 is out of range for a byte (-128 to 255)"
+        );
+        assert_eq!(error("    dcb.w -1,0\n"), "dcb count can't be negative: -1");
+    }
+
     #[test]
     fn test_directive_ds() {
         let source = "  ds.b 10";
@@ -4862,6 +9302,41 @@ fn test_directive_ds() {
         assert_eq!(asm.pc, 10);
     }
 
+    #[test]
+    fn test_directive_ds_reserves_space() {
+        let source = "
+            org $1000
+before:     dc.w    1
+buffer:     ds.b    256
+after:      dc.w    2
+longs:      ds.l    2
+";
+        let path = std::path::Path::new("test.asm");
+        let mut asm = Assembler::new();
+        asm.space_fill = 0xEE;
+        let output = asm.assemble_source(source, path).unwrap();
+        assert_eq!(asm.symbols.get("buffer"), Some(0x1002));
+        assert_eq!(asm.symbols.get("after"), Some(0x1102));
+        assert_eq!(asm.symbols.get("longs"), Some(0x1104));
+        assert!(output[2..0x102].iter().all(|&b| b == 0xEE));
+        assert_eq!(output[0x102..0x104], [0, 2]);
+        assert_eq!(output.len(), 0x10C);
+
+        let error = |source: &str| {
+            let errors = Assembler::new().assemble_checked(source, path).unwrap_err();
+            errors[0].message.clone()
+        };
+        assert_eq!(
+            error("    ds.b size\nsize equ 4\n"),
+            "ds count must only use symbols defined before it: undefined symbol: size"
+        );
+        assert_eq!(
+            error("    dc.b 1\n    ds.w 1\n"),
+            "ds.w at odd address $1 (add EVEN before it)"
+        );
+        assert_eq!(error("    ds.l -2\n"), "ds count can't be negative: -2");
+    }
+
     #[test]
     fn test_directive_rsset_and_rs() {
         let mut asm = Assembler::new();
@@ -4916,6 +9391,79 @@ fn test_directive_even() {
     // Preprocessor tests
     // ------------------------------------------------------------------------
 
+    #[test]
+    fn test_assemble_virtual_includes() {
+        let mut asm = Assembler::new();
+        asm.virtual_files.insert(
+            "defs.inc".to_string(),
+            "include \"lib/consts.inc\"\nVALUE equ BASE+1\n".to_string(),
+        );
+        asm.virtual_files
+            .insert("lib/consts.inc".to_string(), "BASE equ $40\n".to_string());
+        let source = "  include \"defs.inc\"\n  moveq #VALUE,d0\n";
+        let output = asm
+            .assemble_source(source, std::path::Path::new("<editor>"))
+            .unwrap();
+        assert_eq!(output, [0x70, 0x41]);
+
+        let includes: Vec<_> = asm
+            .includes
+            .iter()
+            .map(|i| (i.from.as_str(), i.name.as_str(), i.path.as_str(), i.origin))
+            .collect();
+        assert_eq!(
+            includes,
+            [
+                ("<editor>", "defs.inc", "defs.inc", IncludeOrigin::Virtual),
+                (
+                    "defs.inc",
+                    "lib/consts.inc",
+                    "lib/consts.inc",
+                    IncludeOrigin::Virtual
+                ),
+            ]
+        );
+
+        // A virtual file that includes itself is caught like a disk one
+        asm.virtual_files
+            .insert("loop.inc".to_string(), "include \"loop.inc\"\n".to_string());
+        let err = asm
+            .assemble_source("  include \"loop.inc\"\n", std::path::Path::new("<editor>"))
+            .unwrap_err();
+        assert!(err.contains("circular include"), "{err}");
+    }
+
+    #[test]
+    fn test_assemble_include_cycle_shows_the_chain() {
+        let dir = std::env::temp_dir().join(format!("flux32-include-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.inc"), "; a\n  include \"b.inc\"\n").unwrap();
+        std::fs::write(dir.join("b.inc"), "  include \"a.inc\"\n").unwrap();
+        let main = dir.join("main.asm");
+
+        let err = Assembler::new()
+            .assemble_source("  nop\n  include \"a.inc\"\n", &main)
+            .unwrap_err();
+        let a = dir.join("a.inc").display().to_string();
+        let b = dir.join("b.inc").display().to_string();
+        let chain = format!("{}:2 → {a}:2 → {b}:1 → {a}", main.display());
+        assert!(err.contains(&format!("circular include: {chain}")), "{err}");
+
+        // The depth limit stops a chain before it loops
+        let mut asm = Assembler::new();
+        asm.max_include_depth = 1;
+        let err = asm
+            .assemble_source("  include \"a.inc\"\n", &main)
+            .unwrap_err();
+        let chain = format!("{}:1 → {a}:2 → {b}", main.display());
+        assert!(
+            err.contains(&format!("includes nested more than 1 deep: {chain}")),
+            "{err}"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_preprocessor_rept() {
         let source = "rept 3\n  nop\nendr";
@@ -4929,6 +9477,35 @@ fn test_preprocessor_rept() {
         assert_eq!(lines.len(), 3);
     }
 
+    #[test]
+    fn test_assemble_rept_counters() {
+        let assemble = |source: &str| {
+            Assembler::new().assemble_source(source, std::path::Path::new("test.asm"))
+        };
+
+        // A named counter
+        let ramp = assemble("  rept 16,i\n  dc.w i*4\n  endr\n").unwrap();
+        let expected: Vec<u8> = (0..16u16).flat_map(|i| (i * 4).to_be_bytes()).collect();
+        assert_eq!(ramp, expected);
+
+        // Nested, each with its own counter
+        let matrix = assemble("  rept 4,row\n  rept 4,col\n  dc.b row*4+col\n  endr\n  endr\n");
+        assert_eq!(matrix.unwrap(), (0..16).collect::<Vec<u8>>());
+        let reptn = assemble("  rept 2\n  dc.b REPTN\n  rept 2\n  dc.b reptn+10\n  endr\n  endr\n");
+        assert_eq!(reptn.unwrap(), [0, 10, 11, 1, 10, 11]);
+
+        let negative = assemble("  rept 1-2\n  nop\n  endr\n").unwrap_err();
+        assert!(
+            negative.contains("rept count must not be negative: -1"),
+            "{negative}"
+        );
+        let unknown = assemble("  rept later\n  nop\n  endr\nlater equ 2\n").unwrap_err();
+        assert!(
+            unknown.contains("rept count must be known where the REPT is"),
+            "{unknown}"
+        );
+    }
+
     #[test]
     fn test_preprocessor_macro_simple() {
         let source = "mymacro macro\n  move.l d0,d1\nendm\n  mymacro";
@@ -5000,6 +9577,210 @@ fn test_preprocessor_if_else() {
         assert!(result.contains("included"));
     }
 
+    #[test]
+    fn test_preprocessor_macro_narg_and_defaults() {
+        let source = "print macro msg,newline=0 ; newline flag is optional
+  lea \\msg,a0
+  moveq #\\2,d1
+  if NARG>1
+  bsr newline
+  endif
+  bsr puts
+  endm
+  print hello
+  print bye,1
+";
+        let mut pp = Preprocessor::new();
+        let result = pp
+            .preprocess(source, std::path::Path::new("test.asm"))
+            .unwrap();
+        let lines: Vec<_> = result
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "lea hello,a0",
+                "moveq #0,d1",
+                "bsr puts",
+                "lea bye,a0",
+                "moveq #1,d1",
+                "bsr newline",
+                "bsr puts",
+            ]
+        );
+
+        // Too many arguments is an error at the call
+        let source = format!("{source}  print a,b,c\n");
+        let error = Preprocessor::new()
+            .preprocess(&source, std::path::Path::new("test.asm"))
+            .unwrap_err();
+        assert_eq!(error.line, 11);
+        assert_eq!(
+            error.message,
+            "macro print takes at most 2 arguments, got 3"
+        );
+    }
+
+    #[test]
+    fn test_assemble_macro_labels_per_expansion() {
+        let source = "        org $1000
+delay   macro
+        move.w #\\1,d0
+.loop:  subq.w #1,d0
+        bne .loop
+        endm
+spin    macro
+wait\\@: tst.b d1
+        beq wait\\@
+        delay 3
+        endm
+start:  delay 1
+        delay 2
+        spin
+        spin
+        rts
+";
+        let output = Assembler::new()
+            .assemble_source(source, std::path::Path::new("test.asm"))
+            .unwrap();
+        let words: Vec<u16> = output
+            .chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], w[1]]))
+            .collect();
+        // Every branch goes back 4 bytes, to its own expansion's label
+        let delay = |n| [0x303C, n, 0x5340, 0x66FC];
+        let spin = [[0x4A01, 0x67FC].as_slice(), &delay(3)].concat();
+        let expected = [&delay(1)[..], &delay(2), &spin, &spin, &[0x4E75]].concat();
+        assert_eq!(words, expected);
+    }
+
+    #[test]
+    fn test_assemble_symbol_and_string_conditionals() {
+        let assemble = |source: &str| {
+            Assembler::new().assemble_checked(source, std::path::Path::new("test.asm"))
+        };
+
+        // IFC compares text exactly, so only SP itself takes the first branch
+        let source = "pushreg macro
+        ifc '\\1','SP'
+        pea 4(sp)
+        else
+        move.l \\1,-(sp)
+        endif
+        endm
+        pushreg SP
+        pushreg d0
+        pushreg sp
+";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x48, 0x6F, 0x00, 0x04, 0x2F, 0x00, 0x2F, 0x0F]
+        );
+
+        let source = "DEBUG   equ 1
+        ifdef DEBUG
+        ifndef TRACE
+        dc.b 1
+        else
+        dc.b 2
+        endif
+        ifnc \"a\",\"b\" ; different
+        dc.b 3
+        endif
+        else
+        dc.b 4
+        endif
+        ifd missing
+        dc.b 5
+        endif
+start:
+        ifd start
+        dc.b 7
+        endif
+";
+        assert_eq!(assemble(source).unwrap(), [1, 3, 7]);
+
+        // A missing ENDIF is reported at the directive, and errors inside a
+        // block keep their own line
+        let errors = assemble("        nop\n        ifdef X\n        nop\n").unwrap_err();
+        assert_eq!(
+            (errors[0].line, errors[0].message.as_str()),
+            (2, "unterminated IFDEF (no ENDIF)")
+        );
+        let errors = assemble("        if 1\n        fail boom\n        endif\n").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].message.as_str()), (2, "boom"));
+    }
+
+    #[test]
+    fn test_assert_and_fail() {
+        let path = std::path::Path::new("test.asm");
+        let source = |limit: u32| {
+            format!(
+                "limit   equ     {limit}
+        org     $1000
+        bra     done
+table:  dcb.b   200,$FF
+table_end:
+        assert  table_end-table<=limit,\"table too big\"
+done:   assert  done<$1100
+        rts
+"
+            )
+        };
+        let binary = Assembler::new()
+            .assemble_checked(&source(256), path)
+            .unwrap();
+        assert_eq!(binary.len(), 206);
+
+        // Labels after the assertion count, and the error gives the values
+        // of the symbols in it
+        let errors = Assembler::new()
+            .assemble_checked(&source(100), path)
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "table too big (table_end = $10CC, table = $1004, limit = $64)"
+        );
+        assert_eq!((errors[0].line, errors[0].column), (6, 17));
+
+        let errors = Assembler::new()
+            .assemble_checked("        assert  *>$1000\n", path)
+            .unwrap_err();
+        assert_eq!(errors[0].message, "assertion failed");
+        let errors = Assembler::new()
+            .assemble_checked("        assert  1,oops\n", path)
+            .unwrap_err();
+        assert_eq!(errors[0].message, "assert message must be in quotes");
+
+        // FAIL stops the assembly with its message wherever it's reached
+        let fail = |source: &str| {
+            let errors = Assembler::new().assemble_checked(source, path).unwrap_err();
+            (errors[0].line, errors[0].message.clone())
+        };
+        let source = "size    equ     300
+        if      size>256
+        fail    \"size is over 256\"
+        endif
+";
+        assert_eq!(fail(source), (3, "size is over 256".to_string()));
+        assert_eq!(
+            fail("        nop\nstop:   fail    'stopped'\n"),
+            (2, "stopped".to_string())
+        );
+        assert_eq!(
+            fail("        fail\n"),
+            (1, "fail directive reached".to_string())
+        );
+        let binary = Assembler::new()
+            .assemble_checked("failed  equ     1\n        dc.b    failed\n", path)
+            .unwrap();
+        assert_eq!(binary, [1]);
+    }
+
     // ------------------------------------------------------------------------
     // Operand parsing tests
     // ------------------------------------------------------------------------
@@ -5234,15 +10015,336 @@ fn test_assemble_simple_program() {
             rts
 "#;
         let mut asm = Assembler::new();
-        let output = asm
-            .assemble_source(source, std::path::Path::new("test.asm"))
-            .unwrap();
+        let output = asm
+            .assemble_source(source, std::path::Path::new("test.asm"))
+            .unwrap();
+
+        // moveq #0,d0 = 0x7000
+        // nop = 0x4E71
+        // rts = 0x4E75
+        assert_eq!(output, vec![0x70, 0x00, 0x4E, 0x71, 0x4E, 0x75]);
+        assert_eq!(asm.symbols.get("start"), Some(0x1000));
+    }
+
+    #[test]
+    fn test_assemble_org_sections() {
+        let source = "
+            org $1000
+start:      nop
+            rts
+            org $1010
+table:      dc.l    start,table
+";
+        let path = std::path::Path::new("test.asm");
+        let mut asm = Assembler::new();
+        let output = asm.assemble_source(source, path).unwrap();
+        assert_eq!(asm.symbols.get("start"), Some(0x1000));
+        assert_eq!(asm.symbols.get("table"), Some(0x1010));
+        // NOP and RTS, padding up to $1010, then the table
+        let mut expected = vec![0x4E, 0x71, 0x4E, 0x75];
+        expected.resize(0x10, 0);
+        expected.extend([0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x10, 0x10]);
+        assert_eq!(output, expected);
+
+        let chunks = Assembler::new().assemble_chunks(source, path).unwrap();
+        assert_eq!(
+            chunks,
+            [
+                Chunk {
+                    origin: 0x1000,
+                    data: expected[..4].to_vec(),
+                },
+                Chunk {
+                    origin: 0x1010,
+                    data: expected[0x10..].to_vec(),
+                },
+            ]
+        );
+
+        // Going back is only allowed for chunks
+        let backwards = "
+            org $2000
+            dc.w    1,2
+            org $2002
+            dc.w    3
+";
+        let errors = Assembler::new()
+            .assemble_checked(backwards, path)
+            .unwrap_err();
+        assert_eq!(errors[0].line, 4);
+        assert_eq!(
+            errors[0].message,
+            "org $2002 is below code already assembled (up to $2004)"
+        );
+        let chunks = Assembler::new().assemble_chunks(backwards, path).unwrap();
+        assert_eq!(chunks[1].origin, 0x2002);
+        assert_eq!(chunks[1].data, [0, 3]);
+
+        // Code before the first ORG keeps the default origin
+        let mut asm = Assembler::new();
+        let output = asm
+            .assemble_source("    dc.w 1\n    org $8\n    dc.w 2\n", path)
+            .unwrap();
+        assert_eq!(asm.origin, 0);
+        assert_eq!(output, [0, 1, 0, 0, 0, 0, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn test_assemble_even_and_align() {
+        let source = "
+            org $1000
+text:       dc.b    \"abc\"
+            even
+count:      dc.w    $1234
+            dc.b    1
+            align   8,$FF
+table:      dc.l    text
+";
+        let path = std::path::Path::new("test.asm");
+        let mut asm = Assembler::new();
+        let output = asm.assemble_source(source, path).unwrap();
+        assert_eq!(asm.symbols.get("count"), Some(0x1004));
+        assert_eq!(asm.symbols.get("table"), Some(0x1008));
+        assert_eq!(output[..6], [b'a', b'b', b'c', 0, 0x12, 0x34]);
+        assert_eq!(output[6..8], [1, 0xFF]);
+
+        let error = |source: &str| {
+            let errors = Assembler::new().assemble_checked(source, path).unwrap_err();
+            errors[0].message.clone()
+        };
+        assert_eq!(
+            error("    align 3\n"),
+            "align boundary must be a power of two up to 65536: 3"
+        );
+        assert_eq!(
+            error("    align $20000\n"),
+            "align boundary must be a power of two up to 65536: 131072"
+        );
+        assert_eq!(error("    align 4,256\n"), "align fill must be a byte: 256");
+        assert_eq!(
+            error("    dc.b 1\n    nop\n"),
+            "instruction at odd address $1 (add EVEN before it)"
+        );
+    }
+
+    #[test]
+    fn test_odd_address_warnings() {
+        let path = std::path::Path::new("test.asm");
+        let source = "        org     $1001
+        even
+start:  bsr     sub
+        jsr     sub
+        rts
+        dc.b    1
+        dc.w    2
+        dc.l    3
+        dcb.w   2,0
+sub:    dc.b    0
+";
+        let expected = [
+            (
+                1,
+                "org $1001 is odd, so code after it faults (use an even address)",
+            ),
+            (
+                3,
+                "branch target sub is at odd address $1015 (add EVEN before it)",
+            ),
+            (
+                4,
+                "jsr target sub is at odd address $1015 (add EVEN before it)",
+            ),
+            (7, "dc.w at odd address $100B (add EVEN before it)"),
+            (8, "dc.l at odd address $100D (add EVEN before it)"),
+            (9, "dcb.w at odd address $1011 (add EVEN before it)"),
+        ];
+        let found = |diagnostics: &[Diagnostic]| {
+            diagnostics
+                .iter()
+                .map(|d| (d.line, d.message.clone()))
+                .collect::<Vec<_>>()
+        };
+        let expected = expected.map(|(line, message)| (line, message.to_string()));
+
+        let mut asm = Assembler::new();
+        asm.assemble_checked(source, path).unwrap();
+        assert_eq!(found(&asm.warnings), expected);
+
+        let mut asm = Assembler::new();
+        let quiet = format!("        opt     nowarn=4\n{source}");
+        asm.assemble_checked(&quiet, path).unwrap();
+        assert!(asm.warnings.is_empty());
+
+        // Strict, they're errors
+        let mut asm = Assembler::new();
+        asm.strict_alignment = true;
+        let errors = asm.assemble_checked(source, path).unwrap_err();
+        assert_eq!(found(&errors), expected);
+        assert!(asm.warnings.is_empty());
+
+        // Numeric targets and data ORGs
+        let mut asm = Assembler::new();
+        let source = "        section data
+        org     $2001
+        dc.b    1
+        section code
+        jmp     $1235
+";
+        asm.assemble_checked(source, path).unwrap();
+        assert_eq!(
+            found(&asm.warnings),
+            [(5, "jmp target $1235 is odd".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_assemble_cnop() {
+        // The forward BRA is sized in pass 1 before its target is known, so
+        // the padding after it must come out the same once it is settled.
+        let source = "
+            org $1000
+            bra     done
+            dcb.b   $40,$AA
+            cnop    2,256
+entry:      nop
+done:       rts
+            cnop    0,4
+after:      dc.b    1
+";
+        let path = std::path::Path::new("test.asm");
+        let mut asm = Assembler::new();
+        let output = asm.assemble_source(source, path).unwrap();
+        assert_eq!(asm.symbols.get("entry"), Some(0x1102));
+        assert_eq!(asm.symbols.get("done"), Some(0x1104));
+        assert_eq!(asm.symbols.get("after"), Some(0x1108));
+        assert_eq!(output.len(), 0x109);
+        assert!(output[0x44..0x102].iter().all(|&b| b == 0));
+        assert_eq!(output[0x102..0x106], [0x4E, 0x71, 0x4E, 0x75]);
+
+        let error = |source: &str| {
+            let errors = Assembler::new().assemble_checked(source, path).unwrap_err();
+            errors[0].message.clone()
+        };
+        assert_eq!(
+            error("    cnop 0,6\n"),
+            "cnop alignment must be a power of two up to 65536: 6"
+        );
+        assert_eq!(
+            error("    cnop 4,4\n"),
+            "cnop offset must be from 0 to 3: 4"
+        );
+        assert_eq!(
+            error("    cnop 2\n"),
+            "cnop takes an offset and an alignment"
+        );
+    }
+
+    #[test]
+    fn test_symbol_map_round_trip() {
+        let source = "
+            org $E01000
+COUNT       equ     3
+n           set     1
+n           set     n+1
+start:      moveq   #COUNT,d0
+.loop:      dbra    d0,.loop
+            rts
+table:      dc.w    n
+";
+        let mut asm = Assembler::new();
+        asm.assemble_source(source, std::path::Path::new("test.asm"))
+            .unwrap();
+        let symbols = asm.symbol_map();
+        let entries: Vec<_> = symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.value, s.kind))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("n", 2, SymbolKind::Variable),
+                ("COUNT", 3, SymbolKind::Constant),
+                ("start", 0xE0_1000, SymbolKind::Label),
+                ("start.loop", 0xE0_1002, SymbolKind::Label),
+                ("table", 0xE0_1008, SymbolKind::Label),
+            ]
+        );
+
+        let listing = MapFormat::Listing.render(&symbols);
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(lines[0], "Name        Value      Kind");
+        assert_eq!(lines[2], "COUNT       $00000003  constant");
+        assert_eq!(lines[4], "start.loop  $00E01002  label");
+
+        let plain = MapFormat::Plain.render(&symbols);
+        assert_eq!(plain, "start $E01000\nstart.loop $E01002\ntable $E01008\n");
+        let mut debugger = crate::debugger::Debugger::new();
+        assert_eq!(debugger.load_symbol_map(&plain), Ok(3));
+        for (name, value, _) in &entries[2..] {
+            assert_eq!(debugger.address_of(name), Some(*value as u32));
+        }
+    }
+
+    #[test]
+    fn test_assemble_srecords() {
+        let source = "
+            org $E00100
+start:      lea     message(pc),a0
+            bra     start
+            org $E00200
+message:    dc.b    \"Hello, S-records!\",0
+            end     start
+            dc.b    1
+";
+        let path = std::path::Path::new("dir/demo.asm");
+        let mut asm = Assembler::new();
+        let text = asm.assemble_srecords(source, path, 8).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        // demo.asm in the header; 24-bit addresses, so S2 data and an S8 end
+        assert_eq!(lines[0], "S00B000064656D6F2E61736DE0");
+        assert!(lines[1..lines.len() - 1]
+            .iter()
+            .all(|l| l.starts_with("S2")));
+        assert_eq!(*lines.last().unwrap(), "S804E001001A");
+
+        // Every record's count and checksum add up
+        for line in &lines {
+            let bytes: Vec<u8> = (2..line.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&line[i..i + 2], 16).unwrap())
+                .collect();
+            assert_eq!(usize::from(bytes[0]), bytes.len() - 1, "{line}");
+            let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+            assert_eq!(sum, 0xFF, "{line}");
+        }
 
-        // moveq #0,d0 = 0x7000
-        // nop = 0x4E71
-        // rts = 0x4E75
-        assert_eq!(output, vec![0x70, 0x00, 0x4E, 0x71, 0x4E, 0x75]);
-        assert_eq!(asm.symbols.get("start"), Some(0x1000));
+        let image = crate::loader::parse_srecords(&text).unwrap();
+        let expected = asm
+            .assemble_chunks(source, path)
+            .unwrap()
+            .into_iter()
+            .map(|chunk| crate::loader::Segment {
+                address: chunk.origin,
+                data: chunk.data,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(image.segments, expected);
+        assert_eq!(image.segments[0].address, 0xE0_0100);
+        assert_eq!(image.segments[1].address, 0xE0_0200);
+        assert_eq!(image.segments[1].data.len(), 18);
+        assert_eq!(image.entry, Some(0xE0_0100));
+
+        // Small programs get 16-bit records, ending at the origin
+        let text = write_srecords(
+            &[Chunk {
+                origin: 0x1000,
+                data: vec![0x4E, 0x75],
+            }],
+            0x1000,
+            b"",
+            16,
+        );
+        assert_eq!(text, "S0030000FC\nS10510004E7527\nS9031000EC\n");
     }
 
     #[test]
@@ -5450,6 +10552,1088 @@ fn test_assemble_with_dc() {
         assert_eq!(output, vec![0x48, 0x69, 0x00, 0x00, 0x12, 0x34]);
     }
 
+    #[test]
+    fn test_assemble_checked_reports_every_error() {
+        let source = "            org $1000
+            moveq #1,a0
+loop:       frob d0
+            warn \"check this\"
+            bra loop
+";
+        let mut asm = Assembler::new();
+        let errors = asm
+            .assemble_checked(source, std::path::Path::new("test.asm"))
+            .unwrap_err();
+        let spans: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.column, e.length, e.severity))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![(2, 13, 11, Severity::Error), (3, 1, 19, Severity::Error)]
+        );
+        assert_eq!(errors[0].file, "test.asm");
+        assert_eq!(errors[0].message, "moveq destination must be data register");
+        assert_eq!(errors[1].message, "unknown instruction: FROB");
+
+        // Warnings don't stop assembly
+        let source = source
+            .replace("moveq #1,a0", "moveq #1,d0")
+            .replace("frob", "not");
+        let output = asm
+            .assemble_checked(&source, std::path::Path::new("test.asm"))
+            .unwrap();
+        assert_eq!(output.len(), 6);
+        assert_eq!(asm.warnings.len(), 1);
+        assert_eq!(asm.warnings[0].severity, Severity::Warning);
+        assert_eq!(asm.warnings[0].line, 4);
+        assert_eq!(asm.warnings[0].message, "\"check this\"");
+    }
+
+    #[test]
+    fn test_assemble_checked_carries_on_past_errors() {
+        let source = "            org $1000
+start:      move.l #1,d0
+            dc.b \"oops
+table       equ (1+
+            moveq #table,d1
+            bra nowhere
+size        equ missing
+half        equ size/2
+            moveq #half,d2
+";
+        let path = std::path::Path::new("test.asm");
+        let errors = Assembler::new().assemble_checked(source, path).unwrap_err();
+        // Lines 5, 8 and 9 only fail because table and size are missing
+        let reported: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (3, "unterminated string literal"),
+                (4, "unexpected token in expression"),
+                (6, "undefined symbol: nowhere"),
+                (7, "unresolved symbol: size"),
+            ]
+        );
+
+        // Past the cap, one last error says assembly stopped there
+        let source = "            frob d0\n".repeat(MAX_ERRORS + 10);
+        let errors = Assembler::new()
+            .assemble_checked(&source, path)
+            .unwrap_err();
+        assert_eq!(errors.len(), MAX_ERRORS + 1);
+        assert_eq!(errors[MAX_ERRORS].line, MAX_ERRORS + 1);
+        assert_eq!(
+            errors[MAX_ERRORS].message,
+            format!("too many errors (more than {MAX_ERRORS}), stopping")
+        );
+    }
+
+    #[test]
+    fn test_assemble_branch_sizing() {
+        let path = std::path::Path::new("test.asm");
+        let assemble = |source: &str| Assembler::new().assemble_checked(source, path);
+
+        // -128 is as far back as a short branch goes
+        let code = assemble("top:    ds.w 63\n        bra top\n").unwrap();
+        assert_eq!(code[126..], [0x60, 0x80]);
+        let code = assemble("top:    ds.w 64\n        bra top\n").unwrap();
+        assert_eq!(code[128..], [0x60, 0x00, 0xFF, 0x7E]);
+
+        // And 126 ahead as far forward (127 would be odd)
+        let code = assemble("        bra next\n        ds.w 63\nnext:   nop\n").unwrap();
+        assert_eq!(code[..2], [0x60, 0x7E]);
+        let code = assemble("        bra next\n        ds.w 64\nnext:   nop\n").unwrap();
+        assert_eq!(code[..4], [0x60, 0x00, 0x00, 0x82]);
+
+        // The second branch only grows once its target is known, which
+        // pushes next out of the first one's reach
+        let source = "        bra next
+        bra far
+        ds.w 62
+next:   nop
+        ds.w 100
+far:    nop
+";
+        let code = assemble(source).unwrap();
+        assert_eq!(code[..8], [0x60, 0x00, 0x00, 0x82, 0x60, 0x00, 0x01, 0x48]);
+        assert_eq!(code.len(), 336);
+
+        let message = |source: &str| assemble(source).unwrap_err()[0].message.clone();
+        assert_eq!(
+            message("        bra.s next\n        ds.w 64\nnext:   nop\n"),
+            "branch target is 128 bytes away, out of range for .s (-128 to 127)"
+        );
+        assert_eq!(
+            message("        bne.s next\nnext:   nop\n"),
+            "a short branch can't target the next instruction"
+        );
+        assert_eq!(
+            message("        bsr.w far\n        ds.b 40000\nfar:    rts\n"),
+            "branch target is 40002 bytes away, out of range (-32768 to 32767)"
+        );
+        assert_eq!(
+            message("        dbra d0,far\n        ds.b 40000\nfar:    rts\n"),
+            "dbcc target is 40002 bytes away, out of range (-32768 to 32767)"
+        );
+        assert_eq!(
+            message("        bra.l far\nfar:    rts\n"),
+            "branches can't be .l on the 68000"
+        );
+    }
+
+    #[test]
+    fn test_assemble_range_checks() {
+        let path = std::path::Path::new("test.asm");
+        let check = |source: &str| {
+            let source = source.replace(';', "\n        ");
+            match Assembler::new().assemble_checked(&format!("        {source}\n"), path) {
+                Ok(_) => String::new(),
+                Err(errors) => errors[0].message.clone(),
+            }
+        };
+
+        // Each context's last value in range, then the first one out
+        let cases = [
+            ("move.b #255,d0", ""),
+            (
+                "move.b #256,d0",
+                "byte immediate 256 is out of range (-128 to 255)",
+            ),
+            ("move.b #-128,d0", ""),
+            (
+                "move.b #-129,d0",
+                "byte immediate -129 is out of range (-128 to 255)",
+            ),
+            ("addi.w #65535,d0", ""),
+            (
+                "addi.w #65536,d0",
+                "word immediate 65536 is out of range (-32768 to 65535)",
+            ),
+            ("addq #8,d0", ""),
+            ("addq #9,d0", "quick value 9 is out of range (1 to 8)"),
+            ("subq #0,d0", "quick value 0 is out of range (1 to 8)"),
+            ("lsl.w #9,d0", "shift count 9 is out of range (1 to 8)"),
+            ("moveq #-128,d0", ""),
+            (
+                "moveq #128,d0",
+                "moveq value 128 is out of range (-128 to 127)",
+            ),
+            ("move.w 127(a0,d0.w),d1", ""),
+            (
+                "move.w 128(a0,d0.w),d1",
+                "8-bit displacement 128 is out of range (-128 to 127)",
+            ),
+            ("move.w -32768(a0),d1", ""),
+            (
+                "move.w -32769(a0),d1",
+                "16-bit displacement -32769 is out of range (-32768 to 32767)",
+            ),
+            ("move.w far(pc,d0.w),d1;ds.b 124;far: nop", ""),
+            (
+                "move.w far(pc,d0.w),d1;ds.b 126;far: nop",
+                "8-bit displacement 128 is out of range (-128 to 127)",
+            ),
+            ("lea far(pc),a0;ds.b 32764;far: nop", ""),
+            (
+                "lea far(pc),a0;ds.b 32766;far: nop",
+                "16-bit displacement 32768 is out of range (-32768 to 32767)",
+            ),
+        ];
+        for (source, error) in cases {
+            assert_eq!(check(source), error, "{source}");
+        }
+
+        // Absolute short addresses are only chosen when they fit, so the
+        // check guards the encoder
+        let short = |address| {
+            let mode = AddrMode::AbsShort(Expr::Number(address));
+            encode_ea(&mode, &HashMap::new(), 0, 2, None)
+        };
+        assert_eq!(short(-32768).unwrap().2, vec![0x8000]);
+        assert_eq!(
+            short(32768).unwrap_err(),
+            "absolute short address 32768 is out of range (-32768 to 32767)"
+        );
+    }
+
+    #[test]
+    fn test_assemble_quick_rewrites() {
+        let source = "        org $1000
+        move.l #3,d0
+        move.l #-128,d1
+        move.l #200,d2
+        move.l #$FFFFFFFF,d1
+        move.l #$FFFFFF80,d1
+        move.l #$FFFFFF7F,d2
+        move.w #3,d3
+        move.l #3,(a0)
+        add.w #8,d4
+        sub.l #1,a5
+        add.l #9,d5
+        sub.w #count,d6
+        move.l #tail,d7
+        moveq #3,d7
+        addi.w #1,d0
+count   equ 2
+tail:   rts
+";
+        // Only long moves to data registers and 1-8 adds and subtracts to
+        // registers change, including a constant defined later and 32-bit
+        // immediates that are -128 to -1; a label too far for MOVEQ grows
+        // back to MOVE.L
+        let quick = source
+            .replace("move.l #3,d0", "moveq #3,d0")
+            .replace("move.l #-128,d1", "moveq #-128,d1")
+            .replace("move.l #$FFFFFFFF,d1", "moveq #-1,d1")
+            .replace("move.l #$FFFFFF80,d1", "moveq #-128,d1")
+            .replace("add.w #8,d4", "addq.w #8,d4")
+            .replace("sub.l #1,a5", "subq.l #1,a5")
+            .replace("sub.w #count,d6", "subq.w #count,d6");
+        let path = std::path::Path::new("test.asm");
+        let plain = Assembler::new().assemble_checked(source, path).unwrap();
+        let expected = Assembler::new().assemble_checked(&quick, path).unwrap();
+
+        let mut asm = Assembler::new();
+        asm.optimize = true;
+        let optimized = asm.assemble_checked(source, path).unwrap();
+        assert_eq!(optimized, expected);
+        assert_eq!(plain.len() - optimized.len(), 24);
+    }
+
+    #[test]
+    fn test_assemble_checked_points_at_the_operand() {
+        let path = std::path::Path::new("test.asm");
+        let span = |source: &str| {
+            let errors = Assembler::new().assemble_checked(source, path).unwrap_err();
+            (errors[0].line, errors[0].column, errors[0].length)
+        };
+
+        // A parse error in the third operand, after a tab and a UTF-8 string
+        assert_eq!(span("label:\tdc.b\t\"é\",2,3 + )\n"), (1, 23, 1));
+        // An undefined symbol in the third operand
+        assert_eq!(span("\tdc.w\t1,2,3+nowhere\n"), (1, 13, 7));
+        // A bad addressing mode covers the operand
+        assert_eq!(span("\tmove.l\td0,(a0\n"), (1, 12, 3));
+        // Other errors cover the statement
+        assert_eq!(span("\tmoveq\t#1,a0\n"), (1, 2, 11));
+    }
+
+    #[test]
+    fn test_assemble_movem_lists() {
+        let path = std::path::Path::new("test.asm");
+        let source = "saved   equ $7fff
+        movem.l d0-d7/a0-a6,-(sp)
+        movem.l (sp)+,d0-d7/a0-a6
+        movem.w d0-d3/d7/a0-a2/a6,(a0)
+        movem.w 4(a1),A6/a0-A2/d7/D0-d3
+        movem.l saved,-(sp)
+        movem.l (sp)+,#saved
+";
+        let output = Assembler::new().assemble_checked(source, path).unwrap();
+        let words: Vec<u16> = output
+            .chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], w[1]]))
+            .collect();
+        assert_eq!(
+            words,
+            [
+                0x48E7, 0xFFFE, // reversed for -(sp): A7 down to D0 is bit 15 to 0
+                0x4CDF, 0x7FFF, //
+                0x4890, 0x478F, //
+                0x4CA9, 0x478F, 0x0004, // order and case don't matter
+                0x48E7, 0xFFFE, // a symbol's mask is reversed like a list
+                0x4CDF, 0x7FFF,
+            ]
+        );
+
+        let error = |source: &str| {
+            let errors = Assembler::new().assemble_checked(source, path).unwrap_err();
+            let e = &errors[0];
+            (e.message.clone(), e.column, e.length)
+        };
+        assert_eq!(
+            error("\tmovem.l\td3-d0,-(sp)\n"),
+            (
+                "register range d3-d0 runs backwards (write it low to high)".to_string(),
+                10,
+                5
+            )
+        );
+        assert_eq!(
+            error("\tmovem.l\td0-a2,-(sp)\n"),
+            (
+                "register range d0-a2 mixes data and address registers".to_string(),
+                10,
+                5
+            )
+        );
+        assert_eq!(
+            error("\tmovem.l\t-(sp),d0-d3\n"),
+            (
+                "movem loads registers from (An)+; -(An) stores them, list first".to_string(),
+                16,
+                5
+            )
+        );
+        assert_eq!(
+            error("\tmovem.l\td0-d3,(sp)+\n").0,
+            "movem stores registers through -(An); (An)+ loads them, list second"
+        );
+        assert_eq!(
+            error("\tmovem.l\t#$10000,(a0)\n").0,
+            "movem mask 65536 is out of range (0 to 65535)"
+        );
+    }
+
+    #[test]
+    fn test_assemble_register_aliases() {
+        let path = std::path::Path::new("test.asm");
+        let source = "ptr     equr a3
+cnt     equr D2
+        move.l (ptr)+,d0
+        movem.l d0/cnt/ptr-a4,-(sp)
+        lea 8(ptr,cnt.w),a0
+        move.l fp,d1
+";
+        let output = Assembler::new().assemble_checked(source, path).unwrap();
+        let words: Vec<u16> = output
+            .chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], w[1]]))
+            .collect();
+        assert_eq!(words, [0x201B, 0x48E7, 0xA018, 0x41F3, 0x2008, 0x220E]);
+
+        let error = |line: &str| {
+            let source = format!("ptr     equr a3\n        {line}\n");
+            let errors = Assembler::new()
+                .assemble_checked(&source, path)
+                .unwrap_err();
+            errors[0].message.clone()
+        };
+        assert_eq!(error("dc.w ptr"), "ptr is a register (EQUR), not a value");
+        assert_eq!(
+            error("move.l #ptr,d0"),
+            "ptr is a register (EQUR), not a value"
+        );
+        assert_eq!(
+            error("x equ ptr+1"),
+            "ptr is a register (EQUR), not a value"
+        );
+        assert_eq!(error("ptr: nop"), "ptr is a register (EQUR)");
+        assert_eq!(
+            error("ptr equr a4"),
+            "register alias ptr is already defined"
+        );
+        assert_eq!(error("a0 equr d1"), "a0 is already a register");
+        assert_eq!(error("p2 equr 5"), "equr needs a data or address register");
+    }
+
+    #[test]
+    fn test_assemble_builtin_functions() {
+        // A vector table split into words and bytes of labels defined later
+        let source = "        org $20000
+vectors:
+        dc.w hiword(reset),loword(reset)
+        dc.w HIWORD(irq),LoWord(irq)
+        dc.b hi(irq),lo(irq),strlen(\"abc\"),strlen('x')
+        move.w lo(irq)(a0),d0
+        move.w hiword(vectors),d1
+reset:  nop
+irq:    rte
+";
+        let output = Assembler::new()
+            .assemble_checked(source, std::path::Path::new("test.asm"))
+            .unwrap();
+        assert_eq!(
+            output,
+            [
+                0x00, 0x02, 0x00, 0x14, 0x00, 0x02, 0x00, 0x16, // vectors
+                0x00, 0x16, 0x03, 0x01, // bytes and lengths
+                0x30, 0x28, 0x00, 0x16, // lo(irq)(a0)
+                0x32, 0x38, 0x00, 0x02, // hiword(vectors).w
+                0x4E, 0x71, 0x4E, 0x73,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_pc_relative() {
+        let source = "
+            org     $E00100
+port        equ     $F00000
+            lea     table,a0
+            move.w  (table).l,d0
+            move.w  table.l,d1
+            jsr     sub
+            move.b  port,d2
+            lea     (pc),a1
+            add.w   d0,table
+sub:        rts
+table:      dc.w    1
+";
+        let path = std::path::Path::new("test.asm");
+        let plain = Assembler::new().assemble_checked(source, path).unwrap();
+        let mut asm = Assembler::new();
+        asm.pc_relative = true;
+        let output = asm.assemble_checked(source, path).unwrap();
+        assert_eq!(
+            output,
+            [
+                0x41, 0xFA, 0x00, 0x24, // lea table(pc),a0
+                0x30, 0x39, 0x00, 0xE0, 0x01, 0x26, // (table).l stays absolute
+                0x32, 0x39, 0x00, 0xE0, 0x01, 0x26, // and so does table.l
+                0x4E, 0xBA, 0x00, 0x12, // jsr sub(pc)
+                0x14, 0x39, 0x00, 0xF0, 0x00, 0x00, // a constant isn't a label
+                0x43, 0xFA, 0x00, 0x00, // (pc) is the extension word
+                0xD1, 0x79, 0x00, 0xE0, 0x01, 0x26, // destinations stay absolute
+                0x4E, 0x75, 0x00, 0x01,
+            ]
+        );
+        assert_eq!(plain.len() - output.len(), 4);
+        assert_eq!(plain[..2], [0x41, 0xF9]);
+
+        // A label out of reach of a 16-bit displacement stays absolute
+        let far = "    lea far,a0\n    ds.b 40000\nfar: nop\n";
+        let output = asm.assemble_checked(far, path).unwrap();
+        assert_eq!(output[..2], [0x41, 0xF9]);
+    }
+
+    #[test]
+    fn test_assemble_phase_errors() {
+        let path = std::path::Path::new("test.asm");
+        // The forward reference is short until pass 1 runs again and sees
+        // whether the label fits a word
+        let output = Assembler::new()
+            .assemble_checked("    move.w fwd,d0\nfwd: nop\n", path)
+            .unwrap();
+        assert_eq!(output, [0x30, 0x38, 0x00, 0x04, 0x4E, 0x71]);
+
+        // Here the address only fits a word while fwd is 6, which it isn't
+        // once it does. The operand grows and stays long, so the passes
+        // agree.
+        let source = "    move.w fwd*$10000-$60000,d0\nfwd: nop\nafter: nop\n";
+        let output = Assembler::new().assemble_checked(source, path).unwrap();
+        assert_eq!(output[..6], [0x30, 0x39, 0x00, 0x00, 0x00, 0x00]);
+
+        // A count that depends on a later label still can't settle
+        let errors = Assembler::new()
+            .assemble_checked("    ds.b 8-fwd\nfwd: nop\n", path)
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].message.starts_with("phase error: fwd is $")
+                && errors[0].message.contains("in pass 2 but was $"),
+            "{}",
+            errors[0].message
+        );
+    }
+
+    #[test]
+    fn test_assemble_listing_expansions() {
+        let source = "        org     $1000
+push    macro
+        move.l  \\1,-(sp)
+        nop
+        endm
+start:  nop
+        push    d0
+        rept    6
+        dc.w    1
+        endr
+        rts
+";
+        let path = std::path::Path::new("test.asm");
+        let row = |address: &str, bytes: &str, text: &str| {
+            format!("{address:<6}  {bytes:<23}  {text}")
+                .trim_end()
+                .to_string()
+        };
+
+        let mut asm = Assembler::new();
+        let listing = asm.assemble_listing(source, path).unwrap();
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[1], row("", "", "push    macro"));
+        assert_eq!(lines[6], row("001002", "2F00 4E71", "        push    d0"));
+        assert_eq!(
+            lines[7],
+            row("001006", "0001 0001 0001 0001 ...", "        rept    6")
+        );
+        assert_eq!(lines[8], row("001012", "4E75", "        rts"));
+
+        asm.list_expansions = true;
+        let listing = asm.assemble_listing(source, path).unwrap();
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(
+            lines[6..],
+            [
+                row("001002", "", "        push    d0"),
+                row("001002", "2F00", "+1         move.l  d0,-(sp)"),
+                row("001004", "4E71", "+1         nop"),
+                row("001006", "", "        rept    6"),
+                row("001006", "0001", "+1         dc.w    1"),
+                row("001008", "0001", "+1         dc.w    1"),
+                row("00100A", "0001", "+1         dc.w    1"),
+                row("00100C", "0001", "+1         dc.w    1"),
+                row("00100E", "0001 0001", "+1 (2 more iterations)"),
+                row("001012", "4E75", "        rts"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_sections() {
+        let source = "        org     $1000
+start:  move.l  #message,a0
+        move.w  count,d0
+        section bss
+buffer: ds.b    6
+count:  ds.w    1
+        section data
+message:
+        dc.b    \"hi\",0
+        section text
+        rts
+";
+        let path = std::path::Path::new("test.asm");
+        let mut asm = Assembler::new();
+        let binary = asm.assemble_checked(source, path).unwrap();
+        // Code, then data, then BSS, which the binary leaves out
+        assert_eq!(binary.len(), 0x0F);
+        assert_eq!(&binary[..6], &[0x20, 0x7C, 0x00, 0x00, 0x10, 0x0C]);
+        assert_eq!(&binary[0x0C..], b"hi\0");
+        assert_eq!(asm.symbols.get("buffer"), Some(0x1010));
+        assert_eq!(asm.symbols.get("count"), Some(0x1016));
+        assert_eq!(asm.bss_space(), [(0x1010, 8)]);
+        let map = asm.symbol_map();
+        let message = map.iter().find(|symbol| symbol.name == "message");
+        assert_eq!(message.unwrap().section.as_deref(), Some("data"));
+
+        let sections = asm.assemble_sections(source, path).unwrap();
+        let placed: Vec<_> = sections
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind, s.origin, s.size, s.data.len()))
+            .collect();
+        assert_eq!(
+            placed,
+            [
+                ("text", SectionKind::Code, 0x1000, 12, 12),
+                ("data", SectionKind::Data, 0x100C, 3, 3),
+                ("bss", SectionKind::Bss, 0x1010, 8, 0),
+            ]
+        );
+
+        let errors = Assembler::new()
+            .assemble_checked("        section vars,bss\n        dc.w 1\n", path)
+            .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "vars is a BSS section, which can only reserve space (DS)"
+        );
+        let errors = Assembler::new()
+            .assemble_checked(
+                "        section vars,bss\n        section vars,data\n",
+                path,
+            )
+            .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "section vars was declared with another type"
+        );
+    }
+
+    #[test]
+    fn test_assemble_romsize() {
+        let path = std::path::Path::new("test.asm");
+        let source = "        org     $1000
+start:  moveq   #1,d0
+        rts
+        romsize $20,$FF,checksum
+";
+        let binary = Assembler::new().assemble_checked(source, path).unwrap();
+        assert_eq!(binary.len(), 0x20);
+        assert_eq!(&binary[..4], &[0x70, 0x01, 0x4E, 0x75]);
+        assert!(binary[4..0x1E].iter().all(|&b| b == 0xFF));
+        // $70 + $01 + $4E + $75 + 26 * $FF
+        assert_eq!(&binary[0x1E..], &[0x1B, 0x1A]);
+
+        // The option beats the directive
+        let mut asm = Assembler::new();
+        asm.rom = Some(RomImage {
+            size: 8,
+            fill: 0,
+            checksum: false,
+        });
+        let binary = asm.assemble_checked(source, path).unwrap();
+        assert_eq!(binary, [0x70, 0x01, 0x4E, 0x75, 0, 0, 0, 0]);
+
+        let errors = Assembler::new()
+            .assemble_checked("        dc.l    1,2\n        romsize 8,0,checksum\n", path)
+            .unwrap_err();
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(
+            errors[0].message,
+            "program is $8 bytes, more than the $8 byte ROM (less the checksum)"
+        );
+        let errors = Assembler::new()
+            .assemble_checked("        romsize 0\n", path)
+            .unwrap_err();
+        assert!(errors[0].message.starts_with("rom size must be from 1"));
+    }
+
+    #[test]
+    fn test_undefined_symbol_suggestions() {
+        let source = "printstr:
+        rts
+count   equ     3
+start:  bsr     pritnstr
+        move.w  #cuont,d0
+.loop:  dbra    d0,.lopp
+        bsr     prnt
+        rts
+print   macro
+        nop
+        endm
+";
+        let errors = Assembler::new()
+            .assemble_checked(source, std::path::Path::new("test.asm"))
+            .unwrap_err();
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (4, "undefined symbol: pritnstr (did you mean printstr?)"),
+                (5, "undefined symbol: cuont (did you mean count?)"),
+                (6, "undefined symbol: .lopp (did you mean .loop?)"),
+                // Macros aren't values
+                (7, "undefined symbol: prnt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_symbol_case() {
+        let path = std::path::Path::new("test.asm");
+        let source = "Start:  Move.W  #1,D0
+START:  bra.S   Start
+        DC.b    1
+        Even
+        ifd     start
+        nop
+        endif
+";
+        // Case-sensitive: two symbols, and a warning about them
+        let mut asm = Assembler::new();
+        let binary = asm.assemble_checked(source, path).unwrap();
+        assert_eq!(binary, [0x30, 0x3C, 0x00, 0x01, 0x60, 0xFA, 0x01, 0x00]);
+        assert_eq!(asm.symbols.get("Start"), Some(0));
+        assert_eq!(asm.symbols.get("START"), Some(4));
+        let warnings: Vec<_> = asm
+            .warnings
+            .iter()
+            .map(|w| (w.line, w.message.as_str()))
+            .collect();
+        assert_eq!(warnings, [(1, "Start and START differ only in case")]);
+
+        // Folded: start and Start are one symbol
+        let mut asm = Assembler::new();
+        asm.ignore_case = true;
+        let source = source.replacen("START:", "       ", 1);
+        let binary = asm.assemble_checked(&source, path).unwrap();
+        assert_eq!(
+            binary,
+            [0x30, 0x3C, 0x00, 0x01, 0x60, 0xFA, 0x01, 0x00, 0x4E, 0x71]
+        );
+        assert_eq!(asm.symbols.get("start"), Some(0));
+        assert_eq!(asm.symbols.get("Start"), None);
+        assert!(asm.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_opt_directive() {
+        let path = std::path::Path::new("test.asm");
+        let source = "        move.l  #1,d0
+        opt     o+
+        move.l  #1,d0
+        opt     push,o-,w-
+        move.l  #1,d0
+        warn    not reported
+        opt     pop
+        move.l  #1,d0
+        warn    reported
+        opt     frob
+        opt     nowarn=1-2,c-
+        warn    not reported either
+Loop:   bra.s   LOOP
+";
+        let mut asm = Assembler::new();
+        let binary = asm.assemble_checked(source, path).unwrap();
+        assert_eq!(
+            binary,
+            [
+                0x20, 0x3C, 0, 0, 0, 1, // move.l #1,d0
+                0x70, 0x01, // moveq #1,d0
+                0x20, 0x3C, 0, 0, 0, 1, // move.l #1,d0
+                0x70, 0x01, // moveq #1,d0
+                0x60, 0xFE, // bra.s loop
+            ]
+        );
+        let warnings: Vec<_> = asm
+            .warnings
+            .iter()
+            .map(|w| (w.line, w.message.as_str()))
+            .collect();
+        assert_eq!(
+            warnings,
+            [(9, "reported"), (10, "unknown opt option: frob")]
+        );
+
+        let listing = asm
+            .assemble_listing(
+                "        nop\n        opt     l-\n        rts\n        opt     l+\n        nop\n",
+                path,
+            )
+            .unwrap();
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].ends_with("opt     l-"));
+        assert!(lines[2].starts_with("000004  4E71"));
+
+        let errors = Assembler::new()
+            .assemble_checked("        opt     pop\n", path)
+            .unwrap_err();
+        assert_eq!(errors[0].message, "opt pop without an opt push");
+    }
+
+    #[test]
+    fn test_index_register_sizes() {
+        let path = std::path::Path::new("test.asm");
+        let source = "        org     $1000
+        move.w  4(a0,d1.w),d2
+        move.w  -2(a1,d3.l),d2
+        move.w  (a1,a2),d2
+        move.w  (127,a1,a7.l),d2
+        move.w  table(pc,d0.l),d2
+        move.w  (table,pc,a0.w),d2
+table:  dc.w    0
+";
+        let binary = Assembler::new().assemble_checked(source, path).unwrap();
+        let words: Vec<u16> = binary
+            .chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], w[1]]))
+            .collect();
+        assert_eq!(
+            words,
+            [
+                0x3430, 0x1004, // D1.W
+                0x3431, 0x38FE, // D3.L
+                0x3431, 0xA000, // A2, .W by default
+                0x3431, 0xF87F, // A7.L
+                0x343B, 0x0806, // D0.L, from the extension word at $1012
+                0x343B, 0x8002, // A0.W, from $1016
+                0x0000,
+            ]
+        );
+
+        for (operand, message) in [
+            (
+                "128(a0,d0)",
+                "8-bit displacement 128 is out of range (-128 to 127)",
+            ),
+            ("(a0,d0.b)", "index register size must be .W or .L: d0.b"),
+            ("(a0,pc)", "invalid index register: pc"),
+        ] {
+            let errors = Assembler::new()
+                .assemble_checked(&format!("        move.w  {operand},d2\n"), path)
+                .unwrap_err();
+            assert_eq!(errors[0].message, message, "{operand}");
+        }
+
+        // The CPU sign-extends a .W index and takes all of a .L one
+        let source = "        org     $E00100
+start:  lea     table+2,a0
+        move.l  #$0000FFFE,d1
+        move.w  (a0,d1.w),d2
+        moveq   #2,d3
+        move.w  (table,pc,d3.l),d4
+        stop    #$2700
+table:  dc.w    $1234,$5678
+";
+        let binary = Assembler::new().assemble_checked(source, path).unwrap();
+        let mut sbc = crate::sbc::Sbc::new();
+        sbc.load_app(&binary, None).unwrap();
+        sbc.run_app(None);
+        sbc.run(1_000);
+        assert!(sbc.is_halted());
+        assert_eq!(sbc.registers().d(2) as u16, 0x1234);
+        assert_eq!(sbc.registers().d(4) as u16, 0x5678);
+    }
+
+    #[test]
+    fn test_absolute_size_selection() {
+        let path = std::path::Path::new("test.asm");
+        let source = "        org     $1000
+        move.l  vectors+8,d0
+        move.w  d0,screen
+        tst.b   io
+        jmp     later
+        move.l  $7FFF.l,d1
+later:  nop
+vectors equ     0
+screen  equ     $E00000
+io      equ     $FFFF8000
+";
+        let binary = Assembler::new().assemble_checked(source, path).unwrap();
+        assert_eq!(
+            binary,
+            [
+                0x20, 0x38, 0x00, 0x08, // low memory is short
+                0x33, 0xC0, 0x00, 0xE0, 0x00, 0x00, // high memory is long
+                0x4A, 0x38, 0x80, 0x00, // $FFFF8000 sign-extends from $8000
+                0x4E, 0xF8, 0x10, 0x18, // a forward label that fits
+                0x22, 0x39, 0x00, 0x00, 0x7F, 0xFF, // .L is kept
+                0x4E, 0x71,
+            ]
+        );
+
+        let errors = Assembler::new()
+            .assemble_checked("        tst.b   $8000.w\n", path)
+            .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "absolute short address 32768 is out of range (-32768 to 32767)"
+        );
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        let path = std::path::Path::new("test.asm");
+        let source = "        org     $1000
+table:  dc.b    1,2,3,\\
+                4,5,6, \\
+                7,8 ; the last row
+pair    macro
+        dc.b    \\1,\\2
+        endm
+        pair    9,\\
+                10
+        dc.b    'a\\\\' ; not continued \\
+        dc.b    11
+";
+        let binary = Assembler::new().assemble_checked(source, path).unwrap();
+        assert_eq!(binary, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, b'a', b'\\', 11]);
+
+        // Errors point at the line and column they're on
+        let source = "        nop
+        dc.b    1,2,\\
+                3,4,\\
+                5,bad
+";
+        let errors = Assembler::new().assemble_checked(source, path).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "undefined symbol: bad");
+        assert_eq!((errors[0].line, errors[0].column), (4, 19));
+
+        let errors = Assembler::new()
+            .assemble_checked("        dc.b    1,\\ ; more\n        dc.b    2\n", path)
+            .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "a comment can't follow a line continuation (\\)"
+        );
+        assert_eq!((errors[0].line, errors[0].column), (1, 19));
+    }
+
+    #[test]
+    fn test_incremental_assembly() {
+        let path = std::path::Path::new("main.asm");
+        let lib = "twice   macro
+        \\1
+        \\1
+        endm
+";
+        let source = "        include \"lib.inc\"
+        org     $1000
+start:  moveq   #1,d0
+        bne     done
+        twice   nop
+        bra     start
+        ds.b    100
+done:   rts
+";
+        let assembler = |incremental, lib: &str| {
+            let mut asm = Assembler::new();
+            asm.virtual_files.insert("lib.inc".into(), lib.into());
+            asm.incremental = incremental;
+            asm
+        };
+        let mut incremental = assembler(true, lib);
+        let mut check = |source: &str, lib: &str| {
+            incremental
+                .virtual_files
+                .insert("lib.inc".into(), lib.into());
+            let cold = assembler(false, lib).assemble_checked(source, path);
+            assert_eq!(incremental.assemble_checked(source, path), cold);
+            incremental.timings.clone()
+        };
+
+        let timings = check(source, lib);
+        assert!(!timings.reused_sizes);
+        assert_eq!(timings.cached_includes, 0);
+
+        // Changing an operand reuses everything but that line, and pass 1
+        let edited = source.replace("#1,d0", "#2,d0");
+        let timings = check(&edited, lib);
+        assert!(timings.reused_sizes);
+        assert!(timings.patched_source);
+        assert_eq!(timings.sizing_passes, 0);
+        assert_eq!(timings.cached_includes, 1);
+        assert!(timings.cached_lines > 0);
+
+        // One that changes the instruction's size runs pass 1 again
+        let timings = check(&source.replace("moveq   #1,d0", "move.l  #1,d0"), lib);
+        assert!(timings.reused_sizes);
+        assert_ne!(timings.sizing_passes, 0);
+
+        // A branch that has to grow still starts from the old sizes
+        let grown = source.replace("ds.b    100", "ds.b    200");
+        assert!(check(&grown, lib).reused_sizes);
+
+        // One that could shrink again assembles from scratch
+        assert!(!check(source, lib).reused_sizes);
+
+        // So does a reference to a label that went away
+        let timings = check(&source.replace("done:   rts", "        rts"), lib);
+        assert!(!timings.reused_sizes);
+
+        // Editing an include expands it again
+        let lib = lib.replace("\\1\n        endm", "\\1\n        \\1\n        endm");
+        let timings = check(source, &lib);
+        assert_eq!(timings.cached_includes, 0);
+    }
+
+    #[test]
+    fn test_incremental_patches_match_cold() {
+        let path = std::path::Path::new("main.asm");
+        let source = "        include \"lib.inc\"
+        org     $1000
+COUNT   equ     3
+        opt     o+
+start:  moveq   #1,d0
+        ifdef   start
+        move.l  #1,d1
+        else
+        nop
+        endif
+        if      COUNT>2
+        nop
+        warn    \"big count\"
+        endif
+        rept    COUNT
+        addq    #1,d0
+        endr
+        twice   nop
+        dc.b    1,2,\\
+                3,4
+        even
+        bra     start
+done:   rts
+";
+        let assembler = |incremental| {
+            let mut asm = Assembler::new();
+            asm.virtual_files
+                .insert("lib.inc".into(), "twice macro\n \\1\n \\1\n endm\n".into());
+            asm.incremental = incremental;
+            asm
+        };
+        let mut incremental = assembler(true);
+        incremental.assemble_checked(source, path).unwrap();
+        let edits = [
+            // Lines that go straight through are patched in
+            ("#1,d0", "#2,d0", true),
+            ("move.l  #1,d1", "move.l  #2,d1", true),
+            ("        warn", "        nop\n        warn", true),
+            ("        opt", "        nop\n        opt", true),
+            ("done:   rts", "done:   rts\n        nop", true),
+            ("        nop\n        warn", "        warn", true),
+            // Anything else is preprocessed again
+            ("start:  moveq", "begin:  moveq", false),
+            (
+                "        nop\n        endif",
+                "        rts\n        endif",
+                false,
+            ),
+            ("3,4", "3,4,5", false),
+            ("twice   nop", "twice   rts", false),
+            ("COUNT   equ     3", "COUNT   equ     1", false),
+            ("        opt     o+", "        opt     o-", false),
+            ("        even", "        endif", false),
+        ];
+        for (from, to, patched) in edits {
+            incremental.assemble_checked(source, path).unwrap();
+            let edited = source.replacen(from, to, 1);
+            let mut cold = assembler(false);
+            let output = cold.assemble_checked(&edited, path);
+            assert_eq!(
+                incremental.assemble_checked(&edited, path),
+                output,
+                "after changing {from:?} to {to:?}"
+            );
+            assert_eq!(incremental.warnings, cold.warnings);
+            assert_eq!(
+                incremental.timings.patched_source, patched,
+                "{from:?} to {to:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_incremental_mutual_branches() {
+        let path = std::path::Path::new("main.asm");
+        // Each branch only needs its long form while the other has it
+        let source = "        org     $1000
+back:   nop
+        bne     ahead
+        ds.b    122
+        bne     back
+ahead:  nop
+";
+        let mut incremental = Assembler::new();
+        incremental.incremental = true;
+        for gap in ["122", "124", "122", "120", "124", "122"] {
+            let edited = source.replace("122", gap);
+            let cold = Assembler::new().assemble_checked(&edited, path).unwrap();
+            let output = incremental.assemble_checked(&edited, path).unwrap();
+            assert_eq!(output, cold, "with a gap of {gap}");
+        }
+        assert!(!incremental.timings.reused_sizes);
+    }
+
+    #[test]
+    fn test_forward_branch_sizing() {
+        let path = std::path::Path::new("test.asm");
+        // Growing the branches ahead of `bra near` must not make it look
+        // like its target is out of reach
+        let source = "        org     $1000
+        rept    70
+        bra     far
+        endr
+        bra     near
+        nop
+near:   nop
+        ds.b    200
+far:    nop
+";
+        let binary = Assembler::new().assemble_checked(source, path).unwrap();
+        assert_eq!(binary[70 * 4..70 * 4 + 2], [0x60, 0x02]);
+    }
+
     #[test]
     #[ignore] // Enable when ready to test real files
     fn test_assemble_hello_asm() {
@@ -5530,6 +11714,75 @@ fn test_assemble_fizzbuzz_asm() {
         }
     }
 
+    #[test]
+    fn test_incremental_rom_asm() {
+        let path = std::path::Path::new("rom/rom.asm");
+        let source = std::fs::read_to_string(path).unwrap();
+        let assembler = |incremental| {
+            let mut asm = Assembler::new();
+            asm.include_paths.push(path.parent().unwrap().to_path_buf());
+            asm.incremental = incremental;
+            asm
+        };
+        let mut incremental = assembler(true);
+        let edits = [
+            ("moveq      #7,d1", "moveq      #7,d1"),
+            ("bra        ready", "bra        ready\n ds.b 200"),
+            ("bra        ready", "bra        ready\n ds.b 20000"),
+            ("moveq      #7,d1", "moveq      #6,d1"),
+            ("moveq      #7,d1", "move.l     #7,d1"),
+            ("moveq      #0,d0", "moveq      #0,d0\n nop"),
+            ("moveq      #0,d0", "moveq      #0,d0 ; zero"),
+            ("moveq      #0,d0", ""),
+            ("moveq      #7,d1", "moveq      #6,d1"),
+        ];
+        for (from, to) in edits {
+            let edited = source.replacen(from, to, 1);
+            let mut cold = assembler(false);
+            let output = cold.assemble_checked(&edited, path).unwrap();
+            let patched = incremental.assemble_checked(&edited, path).unwrap();
+            assert_eq!(patched, output, "after changing {from:?} to {to:?}");
+            assert_eq!(incremental.warnings, cold.warnings);
+        }
+        assert!(incremental.timings.reused_sizes);
+    }
+
+    #[test]
+    fn test_incremental_rom_asm_is_faster() {
+        let path = std::path::Path::new("rom/rom.asm");
+        let source = std::fs::read_to_string(path).unwrap();
+        let assembler = |incremental| {
+            let mut asm = Assembler::new();
+            asm.include_paths.push(path.parent().unwrap().to_path_buf());
+            asm.incremental = incremental;
+            asm
+        };
+        // The fastest of a few runs, so other tests running don't decide it
+        let cold = (0..3)
+            .map(|_| {
+                let mut asm = assembler(false);
+                asm.assemble_checked(&source, path).unwrap();
+                asm.timings.total_us
+            })
+            .min()
+            .unwrap();
+        let mut incremental = assembler(true);
+        incremental.assemble_checked(&source, path).unwrap();
+        let warm = ["#6,d1", "#7,d1", "#5,d1"]
+            .into_iter()
+            .map(|operand| {
+                let edited = source.replacen("#7,d1", operand, 1);
+                incremental.assemble_checked(&edited, path).unwrap();
+                let timings = &incremental.timings;
+                assert!(timings.patched_source && timings.reused_sizes);
+                assert_eq!(timings.sizing_passes, 0);
+                timings.total_us
+            })
+            .min()
+            .unwrap();
+        assert!(warm * 4 <= cold, "{warm}us warm, {cold}us cold");
+    }
+
     #[test]
     #[ignore] // Enable when ready to test real files
     fn test_assemble_rom_asm() {
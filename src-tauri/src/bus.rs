@@ -320,7 +320,8 @@ fn decode_address(&self, addr: u32) -> AddressRegion {
         } else if uart_sel {
             AddressRegion::Uart(addr & 0xF)
         } else {
-            AddressRegion::CfCard(addr & 0xF)
+            // A4 selects the CS1 register block (alternate status / device control)
+            AddressRegion::CfCard(addr & 0x1F)
         }
     }
 
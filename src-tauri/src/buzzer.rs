@@ -0,0 +1,288 @@
+//! Buzzer Emulation
+//!
+//! This module emulates a programmable-tone buzzer on the expansion bus. The
+//! guest sets a square-wave period and gates the tone on and off; each tone
+//! is recorded with its start and end cycle so the host can play it back.
+//!
+//! ## Register Map (offsets from $8000C0)
+//!
+//! | Offset | Read          | Write         |
+//! |--------|---------------|---------------|
+//! | 0      | Period (high) | Period (high) |
+//! | 2      | Period (low)  | Period (low)  |
+//! | 4      | Gate          | Gate (bit 0)  |
+//!
+//! The period is in microseconds, so the tone frequency is 1 MHz / period.
+//! A period of 0 is silent.
+//!
+//! ## Tone Events
+//!
+//! A tone starts when the gate opens with a non-zero period and ends when
+//! the gate closes or the period changes. The bell (a fixed short beep, e.g.
+//! for BEL sent to the UART) is recorded the same way without touching the
+//! gate.
+
+use std::collections::VecDeque;
+
+use crate::scheduler::Clocked;
+
+/// Sample rate of rendered audio in Hz
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Amplitude of rendered audio
+pub const AMPLITUDE: i16 = 8192;
+
+/// Bell frequency in Hz
+pub const BELL_FREQUENCY: u32 = 800;
+
+/// Bell duration in milliseconds
+pub const BELL_DURATION_MS: u32 = 100;
+
+/// CPU cycles per microsecond at the default 12 MHz clock
+const CYCLES_PER_US: u64 = 12;
+
+/// Maximum number of queued tone events
+const MAX_EVENTS: usize = 256;
+
+/// Buzzer register offsets
+pub mod regs {
+    /// Period, high byte
+    pub const PERIOD_HI: u32 = 0;
+    /// Period, low byte
+    pub const PERIOD_LO: u32 = 2;
+    /// Gate (bit 0 = tone on)
+    pub const GATE: u32 = 4;
+}
+
+/// A tone the buzzer played
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToneEvent {
+    /// Frequency in Hz
+    pub frequency: u32,
+    /// Duration in milliseconds (rounded up)
+    pub duration_ms: u32,
+    /// Buzzer cycle count when the tone started
+    pub start_cycle: u64,
+    /// Buzzer cycle count when the tone ended
+    pub end_cycle: u64,
+}
+
+/// Programmable-tone buzzer peripheral
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Buzzer {
+    /// Square-wave period in microseconds (0 = silent)
+    period: u16,
+    /// Gate register
+    gate: bool,
+    /// Cycles elapsed since power-on (not reset by a machine reset)
+    now: u64,
+    /// Start cycle of the tone currently playing
+    tone_start: Option<u64>,
+    /// Tone events not yet collected by the host
+    events: VecDeque<ToneEvent>,
+    /// CPU cycles per microsecond
+    cycles_per_us: u64,
+}
+
+impl Buzzer {
+    /// Creates a silent buzzer
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            period: 0,
+            gate: false,
+            now: 0,
+            tone_start: None,
+            events: VecDeque::new(),
+            cycles_per_us: CYCLES_PER_US,
+        }
+    }
+
+    /// Sets the CPU clock tone durations are measured against
+    pub const fn set_cpu_clock(&mut self, clock_hz: u32) {
+        self.cycles_per_us = (clock_hz / 1_000_000) as u64;
+    }
+
+    /// Closes the gate and clears the period, ending any tone
+    pub fn reset(&mut self) {
+        self.end_tone();
+        self.gate = false;
+        self.period = 0;
+    }
+
+    /// Reads a buzzer register
+    #[must_use]
+    pub const fn read(&self, offset: u32) -> u8 {
+        match offset {
+            regs::PERIOD_HI => (self.period >> 8) as u8,
+            regs::PERIOD_LO => self.period as u8,
+            regs::GATE => self.gate as u8,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes a buzzer register
+    pub fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            regs::PERIOD_HI => self.set_period((self.period & 0x00FF) | (u16::from(value) << 8)),
+            regs::PERIOD_LO => self.set_period((self.period & 0xFF00) | u16::from(value)),
+            regs::GATE => {
+                let gate = value & 0x01 != 0;
+                if gate != self.gate {
+                    self.end_tone();
+                    self.gate = gate;
+                    self.start_tone();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances the buzzer's clock by the given number of CPU cycles
+    pub const fn tick(&mut self, cycles: u64) {
+        self.now += cycles;
+    }
+
+    /// Returns the current tone frequency in Hz (0 = silent)
+    #[must_use]
+    pub const fn frequency(&self) -> u32 {
+        if self.gate && self.period != 0 {
+            1_000_000 / self.period as u32
+        } else {
+            0
+        }
+    }
+
+    /// Records a bell beep starting now
+    pub fn bell(&mut self) {
+        let duration = u64::from(BELL_DURATION_MS) * 1000 * self.cycles_per_us;
+        self.push_event(ToneEvent {
+            frequency: BELL_FREQUENCY,
+            duration_ms: BELL_DURATION_MS,
+            start_cycle: self.now,
+            end_cycle: self.now + duration,
+        });
+    }
+
+    /// Returns and clears the queued tone events
+    pub fn take_events(&mut self) -> Vec<ToneEvent> {
+        self.events.drain(..).collect()
+    }
+
+    /// Renders the current output as a square wave at [`SAMPLE_RATE`]
+    ///
+    /// Silence renders as zeros.
+    #[must_use]
+    pub fn render(&self, ms: u32) -> Vec<i16> {
+        let count = (u64::from(SAMPLE_RATE) * u64::from(ms) / 1000) as usize;
+        if self.frequency() == 0 {
+            return vec![0; count];
+        }
+        let period = u64::from(self.period);
+        (0..count as u64)
+            .map(|i| {
+                let us = i * 1_000_000 / u64::from(SAMPLE_RATE);
+                if us % period < period / 2 {
+                    AMPLITUDE
+                } else {
+                    -AMPLITUDE
+                }
+            })
+            .collect()
+    }
+
+    /// Changes the period, splitting the current tone if one is playing
+    fn set_period(&mut self, period: u16) {
+        self.end_tone();
+        self.period = period;
+        self.start_tone();
+    }
+
+    /// Starts a tone if the gate is open and the period is non-zero
+    const fn start_tone(&mut self) {
+        if self.frequency() != 0 {
+            self.tone_start = Some(self.now);
+        }
+    }
+
+    /// Ends the tone currently playing, recording it
+    fn end_tone(&mut self) {
+        let Some(start) = self.tone_start.take() else {
+            return;
+        };
+        if self.now == start {
+            return;
+        }
+        let duration_us = (self.now - start) / self.cycles_per_us;
+        self.push_event(ToneEvent {
+            frequency: self.frequency(),
+            duration_ms: duration_us.div_ceil(1000) as u32,
+            start_cycle: start,
+            end_cycle: self.now,
+        });
+    }
+
+    /// Queues a tone event, dropping the oldest if the queue is full
+    fn push_event(&mut self, event: ToneEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+impl Clocked for Buzzer {
+    fn tick(&mut self, cycles: u64) {
+        Self::tick(self, cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buzzer_records_tones() {
+        let mut buzzer = Buzzer::new();
+        buzzer.write(regs::PERIOD_HI, 0x03);
+        buzzer.write(regs::PERIOD_LO, 0xE8);
+        buzzer.tick(100);
+        buzzer.write(regs::GATE, 1);
+        assert_eq!(buzzer.frequency(), 1000);
+        buzzer.tick(24_000);
+        buzzer.write(regs::PERIOD_LO, 0xF4);
+        buzzer.tick(12_000);
+        buzzer.write(regs::GATE, 0);
+
+        let events = buzzer.take_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0],
+            ToneEvent {
+                frequency: 1000,
+                duration_ms: 2,
+                start_cycle: 100,
+                end_cycle: 24_100,
+            }
+        );
+        assert_eq!(events[1].frequency, 1_000_000 / 0x03F4);
+        assert_eq!(events[1].duration_ms, 1);
+    }
+
+    #[test]
+    fn test_buzzer_render_square_wave() {
+        let mut buzzer = Buzzer::new();
+        assert_eq!(buzzer.render(1), vec![0; 48]);
+
+        // 2 kHz: 24 samples per period at 48 kHz
+        buzzer.write(regs::PERIOD_HI, 0x01);
+        buzzer.write(regs::PERIOD_LO, 0xF4);
+        buzzer.write(regs::GATE, 1);
+        let samples = buzzer.render(1);
+        assert_eq!(samples.len(), 48);
+        assert!(samples[..12].iter().all(|&s| s == AMPLITUDE));
+        assert!(samples[12..24].iter().all(|&s| s == -AMPLITUDE));
+        assert_eq!(samples[24..], samples[..24]);
+    }
+}
@@ -18,23 +18,93 @@
 //! | 11     | LBA2          | LBA2          |
 //! | 13     | Drive/Head    | Drive/Head    |
 //! | 15     | Status        | Command       |
+//! | 29     | Alt Status    | Device Control|
+//!
+//! The alternate status / device control register lives in the CS1 block,
+//! which the board selects with A4.
 //!
 //! ## Supported Commands
 //!
 //! - $EC: Identify Device (returns 512-byte identification block)
 //! - $20: Read Sector(s) (reads from loaded disk image)
+//! - $30: Write Sector(s) (writes through the sector cache)
+//! - $E7: Flush Cache (writes all dirty sectors to the backing file)
+//! - $EF: Set Features (01h/81h enable/disable 8-bit data transfers)
+//!
+//! ## Data Transfer Width
+//!
+//! In the default 16-bit mode each sector is 256 words: a word access to the
+//! data register returns the high byte at offset 0 and the low byte at
+//! offset 1. After SET FEATURES 01h the card transfers one byte per access
+//! on the low data lane (offset 1), so a sector takes 512 byte accesses and
+//! the high lane (offset 0) floats.
+//!
+//! ## Command Timing
+//!
+//! After a command is written, BSY stays set for a configurable number of
+//! emulated CPU cycles (see [`CfTiming`]) before DRDY/DRQ appear. Read and
+//! write commands add a per-sector cost on top of the base command latency.
+//! The card is advanced by calling [`CfCard::tick`] with elapsed cycles.
+//! [`CfTiming::INSTANT`] disables the delay entirely for fast test runs.
+//!
+//! ## Sector Cache
+//!
+//! Sector reads and writes go through a small LRU write-back cache. Written
+//! sectors stay dirty in the cache until they are evicted, the guest issues
+//! FLUSH CACHE, or the card is ejected. Only then are they written to the
+//! image: the backing file for images loaded from a file, or memory otherwise.
+//!
+//! ## Interrupts
+//!
+//! When the nIEN bit of the device control register is clear, the card
+//! asserts INTRQ when a command completes and at each DRQ block boundary of
+//! a multi-sector PIO transfer. Reading the status register clears INTRQ;
+//! reading the alternate status register does not. The emulated card powers
+//! up with nIEN set so polled drivers never see unexpected interrupts.
+//!
+//! ## Copy-on-Write Overlay
+//!
+//! With the overlay enabled, sectors leaving the cache are stored in a sparse
+//! in-memory overlay keyed by LBA instead of the base image, and reads check
+//! the overlay first. The base image file stays untouched until the overlay
+//! is committed. The overlay can also be discarded, or exported as a delta
+//! file: the magic `F32DELTA`, a big-endian sector count, then one record per
+//! sector (big-endian LBA followed by 512 data bytes).
+//!
+//! ## Hot Swap
+//!
+//! [`CfCard::eject`] and [`CfCard::insert`] simulate removing and inserting
+//! the card while the system runs. With no card, every register read floats
+//! to $FF (the board's open-bus value), writes are ignored, and any command
+//! in progress is abandoned. Only complete sectors already accepted by the
+//! card are flushed; a partially transferred sector is discarded.
+//!
+//! ## Addressing
+//!
+//! Sectors are addressed with 28-bit LBAs: LBA0-LBA2 supply bits 0-23 and
+//! the low nibble of the drive/head register supplies bits 24-27. CHS
+//! addressing is not supported, so the LBA bit of drive/head is ignored.
+//! Every access is checked against the image size and fails with IDNF past
+//! the last sector. The 48-bit (EXT) commands are rejected with ABRT.
 //!
 //! ## Disk Image Format
 //!
 //! The emulator loads raw disk images (typically FAT16 formatted).
-//! Each sector is 512 bytes. The image should be a multiple of 512 bytes.
+//! Each sector is 512 bytes; a trailing partial sector reads as zero-padded.
+//! Images loaded from a file are streamed one sector at a time rather than
+//! read into memory, so images up to the 28-bit LBA limit (128 GB) work.
 
 // Allow dead code - this module is exercised through the CLI
 #![allow(dead_code)]
 
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{self, Read};
-use std::path::Path;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::panel::Peripheral;
+use crate::scheduler::Clocked;
 
 /// Base address of the CF card in the system memory map
 pub const CF_BASE: u32 = 0x0090_0000;
@@ -42,8 +112,45 @@
 /// Sector size in bytes
 pub const SECTOR_SIZE: usize = 512;
 
-/// Number of status reads to keep the card busy after a command.
-const BUSY_READS: u8 = 2;
+/// Default number of sectors held in the write-back cache
+pub const DEFAULT_CACHE_SECTORS: usize = 16;
+
+/// Number of sectors addressable with a 28-bit LBA (128 GB)
+pub const MAX_SECTORS: u64 = 1 << 28;
+
+/// Magic bytes at the start of an exported overlay delta file
+pub const OVERLAY_MAGIC: &[u8; 8] = b"F32DELTA";
+
+/// Command latency configuration for the CF card.
+///
+/// All values are in emulated CPU cycles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CfTiming {
+    /// Cycles BSY stays set after any command is written
+    pub command_cycles: u64,
+    /// Additional BSY cycles per sector for read/write commands
+    pub sector_cycles: u64,
+}
+
+impl CfTiming {
+    /// Commands complete immediately; BSY is never observed
+    pub const INSTANT: Self = Self {
+        command_cycles: 0,
+        sector_cycles: 0,
+    };
+
+    /// Roughly 10us command overhead and 50us per sector at 12MHz
+    pub const DEFAULT: Self = Self {
+        command_cycles: 120,
+        sector_cycles: 600,
+    };
+}
+
+impl Default for CfTiming {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
 
 /// CF card register offsets (byte offsets)
 pub mod regs {
@@ -63,6 +170,16 @@ pub mod regs {
     pub const DRIVE_HEAD: u32 = 13;
     /// Status register (read) / Command register (write)
     pub const STATUS_COMMAND: u32 = 15;
+    /// Alternate status (read) / Device control (write), CS1 block
+    pub const ALT_STATUS_CONTROL: u32 = 29;
+}
+
+/// Device control register bits
+pub mod control {
+    /// Interrupt disable (INTRQ is not asserted while set)
+    pub const NIEN: u8 = 0x02;
+    /// Software reset
+    pub const SRST: u8 = 0x04;
 }
 
 /// Status register bits
@@ -117,18 +234,277 @@ pub mod commands {
     pub const WRITE_SECTORS: u8 = 0x30;
     /// Write Sector(s) without retry
     pub const WRITE_SECTORS_NR: u8 = 0x31;
+    /// Flush Cache - write all cached data to the medium
+    pub const FLUSH_CACHE: u8 = 0xE7;
+    /// Set Features - subcommand in the feature register
+    pub const SET_FEATURES: u8 = 0xEF;
+
+    /// Read Sector(s) EXT (48-bit, unsupported)
+    pub const READ_SECTORS_EXT: u8 = 0x24;
+    /// Read DMA EXT (48-bit, unsupported)
+    pub const READ_DMA_EXT: u8 = 0x25;
+    /// Read Native Max Address EXT (48-bit, unsupported)
+    pub const READ_NATIVE_MAX_EXT: u8 = 0x27;
+    /// Read Multiple EXT (48-bit, unsupported)
+    pub const READ_MULTIPLE_EXT: u8 = 0x29;
+    /// Write Sector(s) EXT (48-bit, unsupported)
+    pub const WRITE_SECTORS_EXT: u8 = 0x34;
+    /// Write DMA EXT (48-bit, unsupported)
+    pub const WRITE_DMA_EXT: u8 = 0x35;
+    /// Set Max Address EXT (48-bit, unsupported)
+    pub const SET_MAX_EXT: u8 = 0x37;
+    /// Write Multiple EXT (48-bit, unsupported)
+    pub const WRITE_MULTIPLE_EXT: u8 = 0x39;
+    /// Read Verify Sector(s) EXT (48-bit, unsupported)
+    pub const READ_VERIFY_EXT: u8 = 0x42;
+    /// Flush Cache EXT (48-bit, unsupported)
+    pub const FLUSH_CACHE_EXT: u8 = 0xEA;
 }
 
-/// `CompactFlash` card emulation
+/// SET FEATURES subcommands (feature register values)
+pub mod features {
+    /// Enable 8-bit data transfers
+    pub const ENABLE_8BIT: u8 = 0x01;
+    /// Disable 8-bit data transfers (back to 16-bit)
+    pub const DISABLE_8BIT: u8 = 0x81;
+}
+
+/// Disk image source for [`CfCard::insert`]
+#[derive(Clone, Debug)]
+pub enum CfImage {
+    /// Raw image bytes held in memory only
+    Bytes(Vec<u8>),
+    /// Raw image file; flushed writes go back to this file
+    File(PathBuf),
+}
+
+/// Sector cache statistics (for debugging)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct CacheStats {
+    /// Sector lookups served from the cache
+    pub hits: u64,
+    /// Sector lookups that had to go to the image
+    pub misses: u64,
+    /// Sectors written by the guest but not yet flushed
+    pub dirty: usize,
+}
+
+/// A cached sector
 #[derive(Clone)]
-pub struct CfCard {
-    /// Disk image data
+struct CacheEntry {
+    /// Sector address
+    lba: u32,
+    /// Sector contents
     data: Vec<u8>,
+    /// True if the sector differs from the image
+    dirty: bool,
+    /// Access stamp used for LRU eviction
+    last_used: u64,
+}
+
+/// Small LRU write-back sector cache
+#[derive(Clone)]
+struct SectorCache {
+    /// Cached sectors (at most `capacity`)
+    entries: Vec<CacheEntry>,
+    /// Maximum number of cached sectors (0 disables caching)
+    capacity: usize,
+    /// Monotonic access counter
+    clock: u64,
+    /// Number of cache hits
+    hits: u64,
+    /// Number of cache misses
+    misses: u64,
+}
+
+impl Default for SectorCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_SECTORS)
+    }
+}
+
+impl SectorCache {
+    const fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            capacity,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Looks up a sector, updating LRU order and hit/miss counters
+    fn lookup(&mut self, lba: u32) -> Option<&CacheEntry> {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.lba == lba) {
+            self.hits += 1;
+            entry.last_used = clock;
+            Some(entry)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    /// Inserts or replaces a sector, returning an evicted dirty entry if any
+    fn insert(&mut self, lba: u32, data: &[u8], dirty: bool) -> Option<CacheEntry> {
+        self.clock += 1;
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.lba == lba) {
+            entry.data.copy_from_slice(data);
+            entry.dirty |= dirty;
+            entry.last_used = self.clock;
+            return None;
+        }
+
+        let mut evicted = None;
+        if self.entries.len() >= self.capacity {
+            let lru = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(i, _)| i)?;
+            let entry = self.entries.swap_remove(lru);
+            if entry.dirty {
+                evicted = Some(entry);
+            }
+        }
+
+        self.entries.push(CacheEntry {
+            lba,
+            data: data.to_vec(),
+            dirty,
+            last_used: self.clock,
+        });
+        evicted
+    }
+
+    /// Returns the number of dirty sectors
+    fn dirty_count(&self) -> usize {
+        self.entries.iter().filter(|e| e.dirty).count()
+    }
+
+    /// Returns a copy of the first dirty sector, if any
+    fn next_dirty(&self) -> Option<(u32, Vec<u8>)> {
+        self.entries
+            .iter()
+            .find(|e| e.dirty)
+            .map(|e| (e.lba, e.data.clone()))
+    }
+
+    /// Marks a sector as matching the image once it has been written back
+    fn mark_clean(&mut self, lba: u32) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.lba == lba) {
+            entry.dirty = false;
+        }
+    }
+
+    /// Drops all cached sectors (dirty data is lost)
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Backing store for the disk image
+#[derive(Clone)]
+enum Storage {
+    /// Image held entirely in memory
+    Memory(Vec<u8>),
+    /// Image streamed from a file one sector at a time
+    File {
+        /// Open image file (shared by clones of the card)
+        file: Arc<fs::File>,
+        /// Path the image was loaded from
+        path: PathBuf,
+        /// Image length in bytes
+        len: u64,
+    },
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::Memory(Vec::new())
+    }
+}
+
+impl Storage {
+    /// Reads a sector, zero-padding past the end of the image
+    fn read_sector(&self, lba: u32, sector: &mut [u8]) -> io::Result<()> {
+        let offset = u64::from(lba) * SECTOR_SIZE as u64;
+        sector.fill(0);
+        match self {
+            Self::Memory(data) => {
+                let start = (offset as usize).min(data.len());
+                let end = (start + SECTOR_SIZE).min(data.len());
+                sector[..end - start].copy_from_slice(&data[start..end]);
+            }
+            Self::File { file, len, .. } => {
+                let count = len.saturating_sub(offset).min(SECTOR_SIZE as u64) as usize;
+                let mut file = file.as_ref();
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(&mut sector[..count])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a sector, growing a trailing partial sector to full size
+    fn write_sector(&mut self, lba: u32, sector: &[u8]) -> io::Result<()> {
+        let offset = u64::from(lba) * SECTOR_SIZE as u64;
+        match self {
+            Self::Memory(data) => {
+                let start = offset as usize;
+                if start >= data.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("LBA {lba} is outside the image"),
+                    ));
+                }
+                if data.len() < start + SECTOR_SIZE {
+                    data.resize(start + SECTOR_SIZE, 0);
+                }
+                data[start..start + SECTOR_SIZE].copy_from_slice(sector);
+            }
+            Self::File { file, len, .. } => {
+                if offset >= *len {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("LBA {lba} is outside the image"),
+                    ));
+                }
+                let mut file = file.as_ref();
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(sector)?;
+                *len = (*len).max(offset + SECTOR_SIZE as u64);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `CompactFlash` card emulation
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct CfCard {
+    // The medium isn't saved in snapshots (see `restore_registers`)
+    /// Disk image backing store
+    #[serde(skip)]
+    storage: Storage,
+    /// Write-back sector cache
+    #[serde(skip)]
+    cache: SectorCache,
+    /// Copy-on-write overlay (None = writes go to the base image)
+    #[serde(skip)]
+    overlay: Option<BTreeMap<u32, Vec<u8>>>,
     /// Total number of sectors
+    #[serde(skip)]
     total_sectors: u32,
     /// Whether a card is inserted
+    #[serde(skip)]
     inserted: bool,
     /// Volume label (11 chars, space-padded)
+    #[serde(skip)]
     label: [u8; 11],
 
     // Task file registers
@@ -148,8 +524,18 @@ pub struct CfCard {
     drive_head: u8,
     /// Status register
     status: u8,
-    /// Busy countdown (status reads remaining)
-    busy_reads_remaining: u8,
+    /// True if SET FEATURES enabled 8-bit data transfers
+    eight_bit: bool,
+    /// Device control register
+    device_control: u8,
+    /// Interrupt request line (before nIEN gating)
+    intrq: bool,
+    /// Raise INTRQ once the current command stops being busy
+    irq_on_ready: bool,
+    /// Busy countdown (emulated cycles remaining)
+    busy_cycles: u64,
+    /// Command latency configuration
+    timing: CfTiming,
 
     /// Data transfer buffer (512 bytes for sector data)
     buffer: Vec<u8>,
@@ -157,6 +543,8 @@ pub struct CfCard {
     buffer_pos: usize,
     /// Number of bytes remaining in the buffer
     buffer_remaining: usize,
+    /// True if the buffer is being filled by the host (write command)
+    writing: bool,
 }
 
 impl Default for CfCard {
@@ -170,7 +558,9 @@ impl CfCard {
     #[must_use]
     pub fn new() -> Self {
         Self {
-            data: Vec::new(),
+            storage: Storage::Memory(Vec::new()),
+            cache: SectorCache::new(DEFAULT_CACHE_SECTORS),
+            overlay: None,
             total_sectors: 0,
             inserted: false,
             label: *b"NO NAME    ",
@@ -182,34 +572,50 @@ pub fn new() -> Self {
             lba2: 0,
             drive_head: 0,
             status: 0,
-            busy_reads_remaining: 0,
+            eight_bit: false,
+            device_control: control::NIEN,
+            intrq: false,
+            irq_on_ready: false,
+            busy_cycles: 0,
+            timing: CfTiming::DEFAULT,
             buffer: vec![0; SECTOR_SIZE],
             buffer_pos: 0,
             buffer_remaining: 0,
+            writing: false,
         }
     }
 
     /// Loads a disk image from a file
     ///
     /// The image should be a raw disk image (e.g., created with `dd`).
-    /// FAT16 images are typically 16MB-2GB in size.
+    /// FAT16 images are typically 16MB-2GB in size. The file is kept open
+    /// and sectors are read on demand; a read-only file gives a card whose
+    /// writes fail. Only the first 128 GB (the 28-bit LBA limit) is used.
     pub fn load_image(&mut self, path: &Path) -> io::Result<()> {
-        let mut file = fs::File::open(path)?;
-        self.data.clear();
-        file.read_to_end(&mut self.data)?;
-
-        // Ensure size is a multiple of sector size
-        let remainder = self.data.len() % SECTOR_SIZE;
-        if remainder != 0 {
-            self.data
-                .resize(self.data.len() + SECTOR_SIZE - remainder, 0);
-        }
-
-        self.total_sectors = (self.data.len() / SECTOR_SIZE) as u32;
+        self.flush()?;
+        let file = match fs::OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => fs::File::open(path)?,
+            Err(e) => return Err(e),
+        };
+        let len = file.metadata()?.len();
+        self.cache.clear();
+        self.clear_overlay();
+        self.storage = Storage::File {
+            file: Arc::new(file),
+            path: path.to_path_buf(),
+            len,
+        };
+
+        self.total_sectors = len.div_ceil(SECTOR_SIZE as u64).min(MAX_SECTORS) as u32;
         self.inserted = true;
         self.status = status::DRDY | status::DSC;
         self.error = 0;
-        self.busy_reads_remaining = 0;
+        self.busy_cycles = 0;
+        self.intrq = false;
+        self.irq_on_ready = false;
+        self.device_control = control::NIEN;
+        self.eight_bit = false;
 
         // Try to read volume label from FAT16 BPB
         self.read_volume_label();
@@ -218,34 +624,311 @@ pub fn load_image(&mut self, path: &Path) -> io::Result<()> {
     }
 
     /// Loads a disk image from bytes
+    ///
+    /// Any dirty sectors of the previous image are discarded; call
+    /// [`CfCard::flush`] first to keep them.
     pub fn load_bytes(&mut self, data: &[u8]) {
-        self.data = data.to_vec();
+        self.cache.clear();
+        self.clear_overlay();
+        let mut data = data.to_vec();
 
         // Ensure size is a multiple of sector size
-        let remainder = self.data.len() % SECTOR_SIZE;
+        let remainder = data.len() % SECTOR_SIZE;
         if remainder != 0 {
-            self.data
-                .resize(self.data.len() + SECTOR_SIZE - remainder, 0);
+            data.resize(data.len() + SECTOR_SIZE - remainder, 0);
         }
 
-        self.total_sectors = (self.data.len() / SECTOR_SIZE) as u32;
+        self.total_sectors = (data.len() / SECTOR_SIZE).min(MAX_SECTORS as usize) as u32;
+        self.storage = Storage::Memory(data);
         self.inserted = true;
         self.status = status::DRDY | status::DSC;
         self.error = 0;
-        self.busy_reads_remaining = 0;
+        self.busy_cycles = 0;
+        self.intrq = false;
+        self.irq_on_ready = false;
+        self.device_control = control::NIEN;
+        self.eight_bit = false;
 
         self.read_volume_label();
     }
 
+    /// Inserts a card with the given disk image
+    pub fn insert(&mut self, image: CfImage) -> io::Result<()> {
+        match image {
+            CfImage::Bytes(data) => {
+                self.load_bytes(&data);
+                Ok(())
+            }
+            CfImage::File(path) => self.load_image(&path),
+        }
+    }
+
+    /// Copies the register and transfer state for a snapshot, without the
+    /// medium
+    #[must_use]
+    pub fn save_registers(&self) -> Self {
+        Self {
+            storage: Storage::default(),
+            cache: SectorCache::default(),
+            overlay: None,
+            buffer: self.buffer.clone(),
+            ..*self
+        }
+    }
+
+    /// Takes the register and transfer state from a snapshot's card,
+    /// keeping this card's medium (image, cache, overlay and label)
+    pub fn restore_registers(&mut self, mut saved: Self) {
+        std::mem::swap(&mut saved.storage, &mut self.storage);
+        std::mem::swap(&mut saved.cache, &mut self.cache);
+        std::mem::swap(&mut saved.overlay, &mut self.overlay);
+        saved.total_sectors = self.total_sectors;
+        saved.inserted = self.inserted;
+        saved.label = self.label;
+        *self = saved;
+    }
+
     /// Ejects the current disk image
-    pub fn eject(&mut self) {
-        self.data.clear();
+    ///
+    /// Any command in progress is aborted: a partially transferred sector is
+    /// dropped, while dirty sectors already in the cache are flushed to the
+    /// backing file.
+    pub fn eject(&mut self) -> io::Result<()> {
+        self.writing = false;
+        self.buffer_remaining = 0;
+        let result = self.flush();
+        self.cache.clear();
+        self.clear_overlay();
+        self.storage = Storage::Memory(Vec::new());
         self.total_sectors = 0;
         self.inserted = false;
         self.status = 0;
         self.error = 0;
         self.buffer_remaining = 0;
-        self.busy_reads_remaining = 0;
+        self.writing = false;
+        self.busy_cycles = 0;
+        self.intrq = false;
+        self.irq_on_ready = false;
+        self.device_control = control::NIEN;
+        self.eight_bit = false;
+        result
+    }
+
+    /// Handles a hardware reset of the host system
+    ///
+    /// Any command in progress is aborted and the task file returns to its
+    /// power-on state; the inserted image and cached sectors are kept.
+    pub const fn reset(&mut self) {
+        self.writing = false;
+        self.buffer_remaining = 0;
+        self.buffer_pos = 0;
+        self.status = if self.inserted {
+            status::DRDY | status::DSC
+        } else {
+            0
+        };
+        self.error = 0;
+        self.feature = 0;
+        self.sector_count = 0;
+        self.lba0 = 0;
+        self.lba1 = 0;
+        self.lba2 = 0;
+        self.drive_head = 0;
+        self.busy_cycles = 0;
+        self.intrq = false;
+        self.irq_on_ready = false;
+        self.device_control = control::NIEN;
+        self.eight_bit = false;
+    }
+
+    /// Writes all dirty cached sectors to the image (or the overlay)
+    ///
+    /// A sector stays dirty until it has been written, so after a failed
+    /// write the sectors not yet written are still cached for another try.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while let Some((lba, data)) = self.cache.next_dirty() {
+            self.write_back(lba, &data)?;
+            self.cache.mark_clean(lba);
+        }
+        Ok(())
+    }
+
+    /// Sets the number of sectors held in the write-back cache
+    ///
+    /// The cache is flushed and emptied first. A capacity of 0 makes every
+    /// sector write go straight to the image.
+    pub fn set_cache_capacity(&mut self, sectors: usize) -> io::Result<()> {
+        self.flush()?;
+        self.cache = SectorCache::new(sectors);
+        Ok(())
+    }
+
+    /// Returns sector cache statistics
+    #[must_use]
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.cache.hits,
+            misses: self.cache.misses,
+            dirty: self.cache.dirty_count(),
+        }
+    }
+
+    /// Enables or disables the copy-on-write overlay
+    ///
+    /// Cached writes are flushed to the current target first. Disabling the
+    /// overlay discards its contents; commit it first to keep them.
+    pub fn set_overlay_enabled(&mut self, enabled: bool) -> io::Result<()> {
+        self.flush()?;
+        if enabled {
+            if self.overlay.is_none() {
+                self.overlay = Some(BTreeMap::new());
+            }
+        } else if self.overlay.take().is_some() {
+            // Clean cache entries may hold overlay data
+            self.cache.clear();
+        }
+        Ok(())
+    }
+
+    /// Returns true if the copy-on-write overlay is enabled
+    #[must_use]
+    pub const fn overlay_enabled(&self) -> bool {
+        self.overlay.is_some()
+    }
+
+    /// Returns the number of sectors stored in the overlay
+    #[must_use]
+    pub fn overlay_sectors(&self) -> usize {
+        self.overlay.as_ref().map_or(0, BTreeMap::len)
+    }
+
+    /// Drops all guest writes held in the overlay and the sector cache
+    pub fn discard_overlay(&mut self) {
+        if self.overlay.is_some() {
+            self.cache.clear();
+            self.clear_overlay();
+        }
+    }
+
+    /// Writes all overlay sectors into the base image and empties the overlay
+    ///
+    /// A sector leaves the overlay once it has been written, so after a
+    /// failed write the overlay still holds the sectors not yet committed.
+    pub fn commit_overlay(&mut self) -> io::Result<()> {
+        self.flush()?;
+        while let Some((lba, sector)) = self.overlay.as_mut().and_then(BTreeMap::pop_first) {
+            if let Err(e) = self.write_base(lba, &sector) {
+                if let Some(overlay) = &mut self.overlay {
+                    overlay.insert(lba, sector);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Exports the overlay (including cached writes) as a delta file
+    pub fn export_overlay(&mut self, path: &Path) -> io::Result<()> {
+        self.flush()?;
+        let empty = BTreeMap::new();
+        let overlay = self.overlay.as_ref().unwrap_or(&empty);
+
+        let mut out = Vec::with_capacity(12 + overlay.len() * (4 + SECTOR_SIZE));
+        out.extend_from_slice(OVERLAY_MAGIC);
+        out.extend_from_slice(&(overlay.len() as u32).to_be_bytes());
+        for (lba, sector) in overlay {
+            out.extend_from_slice(&lba.to_be_bytes());
+            out.extend_from_slice(sector);
+        }
+        fs::write(path, out)
+    }
+
+    /// Empties the overlay, keeping overlay mode enabled
+    fn clear_overlay(&mut self) {
+        if let Some(overlay) = &mut self.overlay {
+            overlay.clear();
+        }
+    }
+
+    /// Writes a sector to the overlay, or to the base image if there is none
+    fn write_back(&mut self, lba: u32, sector: &[u8]) -> io::Result<()> {
+        if let Some(overlay) = &mut self.overlay {
+            overlay.insert(lba, sector.to_vec());
+            return Ok(());
+        }
+        self.write_base(lba, sector)
+    }
+
+    /// Writes a sector to the base image (in memory or the backing file)
+    fn write_base(&mut self, lba: u32, sector: &[u8]) -> io::Result<()> {
+        if lba >= self.total_sectors {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("LBA {lba} is outside the image"),
+            ));
+        }
+        self.storage.write_sector(lba, sector)
+    }
+
+    /// Sets the command latency configuration
+    pub const fn set_timing(&mut self, timing: CfTiming) {
+        self.timing = timing;
+    }
+
+    /// Returns the command latency configuration
+    #[must_use]
+    pub const fn timing(&self) -> CfTiming {
+        self.timing
+    }
+
+    /// Returns true if a command is still in progress (BSY set)
+    #[must_use]
+    pub const fn is_busy(&self) -> bool {
+        self.busy_cycles > 0
+    }
+
+    /// Advances the card by the given number of emulated CPU cycles
+    ///
+    /// Once the busy countdown reaches zero, BSY clears and the completion
+    /// status (DRDY/DRQ/ERR) of the last command becomes visible.
+    pub const fn tick(&mut self, cycles: u64) {
+        if self.busy_cycles == 0 {
+            return;
+        }
+        self.busy_cycles = self.busy_cycles.saturating_sub(cycles);
+        if self.busy_cycles == 0 {
+            self.command_ready();
+        }
+    }
+
+    /// Returns true if 8-bit data transfers are enabled
+    #[must_use]
+    pub const fn eight_bit_mode(&self) -> bool {
+        self.eight_bit
+    }
+
+    /// Returns true if the card is asserting its interrupt line
+    #[must_use]
+    pub const fn interrupt_pending(&self) -> bool {
+        self.intrq && self.device_control & control::NIEN == 0
+    }
+
+    /// Raises INTRQ if the finished command asked for it
+    const fn command_ready(&mut self) {
+        if self.irq_on_ready {
+            self.irq_on_ready = false;
+            self.intrq = true;
+        }
+    }
+
+    /// Returns the status register value without side effects
+    const fn current_status(&self) -> u8 {
+        // Only BSY is valid while a command is in progress
+        if self.busy_cycles > 0 {
+            status::BSY
+        } else {
+            self.status
+        }
     }
 
     /// Returns true if a card is inserted
@@ -257,7 +940,16 @@ pub const fn is_inserted(&self) -> bool {
     /// Returns the total capacity in bytes
     #[must_use]
     pub const fn capacity(&self) -> u64 {
-        self.data.len() as u64
+        self.total_sectors as u64 * SECTOR_SIZE as u64
+    }
+
+    /// Returns the file the image is streamed from, if any
+    #[must_use]
+    pub fn image_path(&self) -> Option<&Path> {
+        match &self.storage {
+            Storage::File { path, .. } => Some(path),
+            Storage::Memory(_) => None,
+        }
     }
 
     /// Returns the total number of sectors
@@ -273,15 +965,28 @@ pub fn volume_label(&self) -> &str {
     }
 
     /// Attempts to read the FAT16 volume label from the BPB
+    ///
+    /// The BPB is the first sector of superfloppy images, or the first sector
+    /// of the first partition when sector 0 holds an MBR.
     fn read_volume_label(&mut self) {
-        // Volume label is at offset 0x2B in the BPB (first sector)
-        if self.data.len() >= SECTOR_SIZE {
-            let label_offset = 0x2B;
-            if self.data.len() > label_offset + 11 {
-                self.label
-                    .copy_from_slice(&self.data[label_offset..label_offset + 11]);
+        let mut sector = [0u8; SECTOR_SIZE];
+        if self.total_sectors == 0 || self.storage.read_sector(0, &mut sector).is_err() {
+            return;
+        }
+
+        if !matches!(sector[0], 0xEB | 0xE9)
+            && sector[510..512] == [0x55, 0xAA]
+            && matches!(sector[0x1BE + 4], 0x04 | 0x06 | 0x0E)
+        {
+            let start = u32::from_le_bytes(sector[0x1C6..0x1CA].try_into().unwrap());
+            if start >= self.total_sectors || self.storage.read_sector(start, &mut sector).is_err()
+            {
+                return;
             }
         }
+
+        // Volume label is at offset 0x2B in the BPB
+        self.label.copy_from_slice(&sector[0x2B..0x2B + 11]);
     }
 
     /// Reads from a CF card register
@@ -292,7 +997,11 @@ pub fn read(&mut self, offset: u32) -> u8 {
             return 0xFF; // No card - open bus
         }
 
-        match offset & 0xF {
+        match offset & 0x1F {
+            0 if self.eight_bit => {
+                // High data lane is not driven in 8-bit mode
+                0xFF
+            }
             0 | 1 => {
                 // Data register (16-bit, but we handle byte-by-byte)
                 self.read_data()
@@ -322,14 +1031,13 @@ pub fn read(&mut self, offset: u32) -> u8 {
                 self.drive_head
             }
             15 => {
-                // Status
-                let mut status = self.status;
-                if self.busy_reads_remaining > 0 {
-                    status |= status::BSY;
-                    status &= !status::DRQ;
-                    self.busy_reads_remaining -= 1;
-                }
-                status
+                // Status (acknowledges the interrupt)
+                self.intrq = false;
+                self.current_status()
+            }
+            29 => {
+                // Alternate status (does not acknowledge the interrupt)
+                self.current_status()
             }
             _ => 0xFF,
         }
@@ -343,9 +1051,13 @@ pub fn write(&mut self, offset: u32, value: u8) {
             return; // No card
         }
 
-        match offset & 0xF {
+        match offset & 0x1F {
+            0 if self.eight_bit => {
+                // High data lane is ignored in 8-bit mode
+            }
             0 | 1 => {
-                // Data register - ignore writes for now (read-only disk)
+                // Data register
+                self.write_data(value);
             }
             3 => {
                 // Feature register
@@ -375,6 +1087,10 @@ pub fn write(&mut self, offset: u32, value: u8) {
                 // Command register
                 self.execute_command(value);
             }
+            29 => {
+                // Device control
+                self.device_control = value;
+            }
             _ => {}
         }
     }
@@ -386,9 +1102,46 @@ pub fn read_data_word(&mut self) -> u16 {
         (hi << 8) | lo
     }
 
+    /// Writes a byte to the data buffer during a write command
+    fn write_data(&mut self, value: u8) {
+        if !self.writing || self.buffer_remaining == 0 || self.busy_cycles > 0 {
+            return;
+        }
+
+        self.buffer[self.buffer_pos] = value;
+        self.buffer_pos += 1;
+        self.buffer_remaining -= 1;
+
+        if self.buffer_remaining == 0 {
+            self.status &= !status::DRQ;
+            let lba = self.get_lba();
+            if self.commit_sector(lba).is_err() {
+                self.writing = false;
+                self.error = error::UNC;
+                self.status = status::DRDY | status::ERR;
+                return;
+            }
+
+            // Each completed sector interrupts (next DRQ or command done)
+            self.intrq = true;
+
+            // If more sectors to write, request the next one
+            if self.sector_count > 0 {
+                self.sector_count -= 1;
+            }
+            if self.sector_count > 0 {
+                let lba = lba + 1;
+                self.set_lba(lba);
+                self.setup_write_sector(lba);
+            } else {
+                self.writing = false;
+            }
+        }
+    }
+
     /// Reads a byte from the data buffer
     fn read_data(&mut self) -> u8 {
-        if self.buffer_remaining == 0 {
+        if self.writing || self.buffer_remaining == 0 || self.busy_cycles > 0 {
             return 0;
         }
 
@@ -408,6 +1161,8 @@ fn read_data(&mut self) -> u8 {
                     let lba = self.get_lba() + 1;
                     self.set_lba(lba);
                     self.setup_read_sector(lba);
+                    // DRQ block boundary
+                    self.intrq = true;
                 }
             }
         }
@@ -435,7 +1190,11 @@ const fn set_lba(&mut self, lba: u32) {
     /// Executes an ATA command
     fn execute_command(&mut self, cmd: u8) {
         self.error = 0;
-        self.busy_reads_remaining = BUSY_READS;
+        self.writing = false;
+        self.intrq = false;
+        self.busy_cycles = self.command_latency(cmd);
+        // PIO writes don't interrupt before the first sector is transferred
+        self.irq_on_ready = !matches!(cmd, commands::WRITE_SECTORS | commands::WRITE_SECTORS_NR);
 
         match cmd {
             commands::IDENTIFY => {
@@ -446,7 +1205,31 @@ fn execute_command(&mut self, cmd: u8) {
                 self.setup_read_sector(lba);
             }
             commands::WRITE_SECTORS | commands::WRITE_SECTORS_NR => {
-                // Write is not supported (read-only disk)
+                let lba = self.get_lba();
+                self.setup_write_sector(lba);
+            }
+            commands::FLUSH_CACHE => {
+                if self.flush().is_ok() {
+                    self.status = status::DRDY | status::DSC;
+                } else {
+                    self.error = error::ABRT;
+                    self.status = status::DRDY | status::ERR;
+                }
+            }
+            commands::SET_FEATURES => {
+                self.execute_set_features();
+            }
+            commands::READ_SECTORS_EXT
+            | commands::READ_DMA_EXT
+            | commands::READ_NATIVE_MAX_EXT
+            | commands::READ_MULTIPLE_EXT
+            | commands::WRITE_SECTORS_EXT
+            | commands::WRITE_DMA_EXT
+            | commands::SET_MAX_EXT
+            | commands::WRITE_MULTIPLE_EXT
+            | commands::READ_VERIFY_EXT
+            | commands::FLUSH_CACHE_EXT => {
+                // 48-bit addressing is not supported
                 self.error = error::ABRT;
                 self.status = status::DRDY | status::ERR;
             }
@@ -456,6 +1239,51 @@ fn execute_command(&mut self, cmd: u8) {
                 self.status = status::DRDY | status::ERR;
             }
         }
+
+        // A command that fails completes immediately with an interrupt
+        if self.status & status::ERR != 0 {
+            self.irq_on_ready = true;
+        }
+        if self.busy_cycles == 0 {
+            self.command_ready();
+        }
+    }
+
+    /// Returns the BSY duration for a command, scaled by sector count for transfers
+    fn command_latency(&self, cmd: u8) -> u64 {
+        let sectors = match cmd {
+            commands::READ_SECTORS
+            | commands::READ_SECTORS_NR
+            | commands::WRITE_SECTORS
+            | commands::WRITE_SECTORS_NR => {
+                // A sector count of 0 means 256 sectors in ATA
+                if self.sector_count == 0 {
+                    256
+                } else {
+                    self.sector_count as u64
+                }
+            }
+            // BSY stays set while dirty sectors are written out
+            commands::FLUSH_CACHE => self.cache.dirty_count() as u64,
+            _ => 0,
+        };
+        self.timing
+            .command_cycles
+            .saturating_add(self.timing.sector_cycles.saturating_mul(sectors))
+    }
+
+    /// Executes the SET FEATURES command using the feature register
+    const fn execute_set_features(&mut self) {
+        match self.feature {
+            features::ENABLE_8BIT => self.eight_bit = true,
+            features::DISABLE_8BIT => self.eight_bit = false,
+            _ => {
+                self.error = error::ABRT;
+                self.status = status::DRDY | status::ERR;
+                return;
+            }
+        }
+        self.status = status::DRDY | status::DSC;
     }
 
     /// Executes the IDENTIFY DEVICE command
@@ -492,10 +1320,18 @@ fn execute_identify(&mut self) {
         let model = b"FLUX32 Virtual CompactFlash Card        ";
         self.buffer[54..94].copy_from_slice(model);
 
+        // Word 48: 8-bit data transfers supported (SET FEATURES 01h)
+        self.buffer[96] = 0x00;
+        self.buffer[97] = 0x01;
+
         // Word 49: Capabilities
         self.buffer[98] = 0x00;
         self.buffer[99] = 0x02; // LBA supported
 
+        // Word 83: Command sets supported (FLUSH CACHE)
+        self.buffer[166] = 0x50;
+        self.buffer[167] = 0x00;
+
         // Words 60-61: Total addressable sectors (LBA)
         self.buffer[120] = self.total_sectors as u8;
         self.buffer[121] = (self.total_sectors >> 8) as u8;
@@ -517,13 +1353,101 @@ fn setup_read_sector(&mut self, lba: u32) {
         }
 
         // Copy sector data to buffer
-        let offset = (lba as usize) * SECTOR_SIZE;
-        self.buffer
-            .copy_from_slice(&self.data[offset..offset + SECTOR_SIZE]);
+        if let Some(entry) = self.cache.lookup(lba) {
+            self.buffer.copy_from_slice(&entry.data);
+        } else {
+            if let Some(sector) = self.overlay.as_ref().and_then(|o| o.get(&lba)) {
+                self.buffer.copy_from_slice(sector);
+            } else if self.storage.read_sector(lba, &mut self.buffer).is_err() {
+                self.error = error::UNC;
+                self.status = status::DRDY | status::ERR;
+                return;
+            }
+            if self.cache.capacity > 0 {
+                let sector = self.buffer.clone();
+                if let Some(evicted) = self.cache.insert(lba, &sector, false) {
+                    if self.write_back(evicted.lba, &evicted.data).is_err() {
+                        self.error = error::UNC;
+                        self.status = status::DRDY | status::ERR;
+                        return;
+                    }
+                }
+            }
+        }
+        self.buffer_pos = 0;
+        self.buffer_remaining = SECTOR_SIZE;
+        self.status = status::DRDY | status::DRQ | status::DSC;
+    }
+
+    /// Sets up a sector write operation (waits for the host to fill the buffer)
+    const fn setup_write_sector(&mut self, lba: u32) {
+        if lba >= self.total_sectors {
+            self.error = error::IDNF;
+            self.status = status::DRDY | status::ERR;
+            return;
+        }
+
+        self.writing = true;
         self.buffer_pos = 0;
         self.buffer_remaining = SECTOR_SIZE;
         self.status = status::DRDY | status::DRQ | status::DSC;
     }
+
+    /// Stores the buffer as a dirty sector, writing back any evicted sector
+    fn commit_sector(&mut self, lba: u32) -> io::Result<()> {
+        let sector = self.buffer.clone();
+        if self.cache.capacity == 0 {
+            return self.write_back(lba, &sector);
+        }
+        if let Some(evicted) = self.cache.insert(lba, &sector, true) {
+            self.write_back(evicted.lba, &evicted.data)?;
+        }
+        Ok(())
+    }
+}
+
+impl Clocked for CfCard {
+    fn tick(&mut self, cycles: u64) {
+        Self::tick(self, cycles);
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        (self.busy_cycles > 0).then_some(self.busy_cycles)
+    }
+}
+
+impl Peripheral for CfCard {
+    fn ui_state(&self) -> serde_json::Value {
+        serde_json::json!({
+            "inserted": self.inserted,
+            "image": self.image_path().map(|path| path.display().to_string()),
+            "label": self.inserted.then(|| self.volume_label().trim_end().to_string()),
+            "busy": self.is_busy(),
+            "error": self.error,
+        })
+    }
+
+    fn debug_dump(&self) -> serde_json::Value {
+        serde_json::json!({
+            "error": self.error,
+            "feature": self.feature,
+            "sectorCount": self.sector_count,
+            "lba0": self.lba0,
+            "lba1": self.lba1,
+            "lba2": self.lba2,
+            "driveHead": self.drive_head,
+            "status": self.current_status(),
+            "deviceControl": self.device_control,
+            "lba": self.get_lba(),
+            "intrq": self.intrq,
+            "busyCycles": self.busy_cycles,
+            "eightBit": self.eight_bit,
+            "writing": self.writing,
+            "bufferPos": self.buffer_pos,
+            "bufferRemaining": self.buffer_remaining,
+            "cache": self.cache_stats(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -531,11 +1455,12 @@ mod tests {
     use super::*;
 
     fn read_status_ready(cf: &mut CfCard) -> u8 {
-        for _ in 0..4 {
+        for _ in 0..1000 {
             let status = cf.read(regs::STATUS_COMMAND);
             if status & status::BSY == 0 {
                 return status;
             }
+            cf.tick(1000);
         }
         cf.read(regs::STATUS_COMMAND)
     }
@@ -613,9 +1538,70 @@ fn test_cfcard_read_sector() {
         // Read sector 1
         cf.write(regs::LBA0, 1);
         cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        assert!(read_status_ready(&mut cf) & status::DRQ != 0);
         assert_eq!(cf.read(regs::DATA), 0xBB);
     }
 
+    #[test]
+    fn test_cfcard_read_busy_until_ticked() {
+        let mut cf = CfCard::new();
+        let mut data = vec![0u8; SECTOR_SIZE * 4];
+        data[0] = 0xAA;
+        cf.load_bytes(&data);
+        cf.set_timing(CfTiming {
+            command_cycles: 100,
+            sector_cycles: 50,
+        });
+
+        cf.write(regs::SECTOR_COUNT, 2);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+
+        // Polled immediately: only BSY is visible, data is not yet available
+        assert_eq!(cf.read(regs::STATUS_COMMAND), status::BSY);
+        assert_eq!(cf.read(regs::DATA), 0);
+
+        // Latency is 100 + 2 * 50 cycles
+        cf.tick(199);
+        assert!(cf.is_busy());
+        cf.tick(1);
+        assert!(!cf.is_busy());
+
+        let status = cf.read(regs::STATUS_COMMAND);
+        assert_eq!(status & status::BSY, 0);
+        assert!(status & status::DRQ != 0);
+        assert_eq!(cf.read(regs::DATA), 0xAA);
+    }
+
+    #[test]
+    fn test_cfcard_huge_timing_saturates() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE * 4]);
+        cf.set_timing(CfTiming {
+            command_cycles: u64::MAX - 1,
+            sector_cycles: u64::MAX / 2,
+        });
+
+        // 256 sectors would overflow; the latency stops at u64::MAX
+        cf.write(regs::SECTOR_COUNT, 0);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        cf.tick(u64::MAX - 1);
+        assert!(cf.is_busy());
+        cf.tick(1);
+        assert!(!cf.is_busy());
+    }
+
+    #[test]
+    fn test_cfcard_instant_timing() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE * 4]);
+        cf.set_timing(CfTiming::INSTANT);
+
+        cf.write(regs::STATUS_COMMAND, commands::IDENTIFY);
+        let status = cf.read(regs::STATUS_COMMAND);
+        assert_eq!(status & status::BSY, 0);
+        assert!(status & status::DRQ != 0);
+    }
+
     #[test]
     fn test_cfcard_invalid_sector() {
         let mut cf = CfCard::new();
@@ -639,4 +1625,479 @@ fn test_cfcard_no_card() {
         assert_eq!(cf.read(regs::STATUS_COMMAND), 0xFF);
         assert_eq!(cf.read(regs::DATA), 0xFF);
     }
+
+    /// Creates a zeroed image file unique to the calling test
+    fn temp_image(name: &str, sectors: usize) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("flux32-cfcard-{}-{}.img", name, std::process::id()));
+        fs::write(&path, vec![0u8; SECTOR_SIZE * sectors]).unwrap();
+        path
+    }
+
+    fn write_sectors(cf: &mut CfCard, lba: u8, sectors: &[u8]) {
+        cf.write(regs::LBA0, lba);
+        cf.write(regs::SECTOR_COUNT, sectors.len() as u8);
+        cf.write(regs::STATUS_COMMAND, commands::WRITE_SECTORS);
+        for &fill in sectors {
+            assert!(read_status_ready(cf) & status::DRQ != 0);
+            for _ in 0..SECTOR_SIZE {
+                cf.write(regs::DATA, fill);
+            }
+        }
+        assert_eq!(read_status_ready(cf) & (status::DRQ | status::ERR), 0);
+    }
+
+    #[test]
+    fn test_cfcard_write_then_read_back() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE * 4]);
+
+        write_sectors(&mut cf, 1, &[0x11, 0x22]);
+
+        cf.write(regs::LBA0, 2);
+        cf.write(regs::SECTOR_COUNT, 1);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        assert!(read_status_ready(&mut cf) & status::DRQ != 0);
+        assert_eq!(cf.read(regs::DATA), 0x22);
+        assert_eq!(cf.cache_stats().dirty, 2);
+    }
+
+    #[test]
+    fn test_cfcard_flush_cache_writes_backing_file() {
+        let path = temp_image("flush", 8);
+        let mut cf = CfCard::new();
+        cf.load_image(&path).unwrap();
+
+        write_sectors(&mut cf, 0, &[0xA1, 0xA2, 0xA3]);
+
+        // Dirty sectors are only in the cache
+        assert!(fs::read(&path).unwrap().iter().all(|&b| b == 0));
+        assert_eq!(cf.cache_stats().dirty, 3);
+
+        cf.write(regs::STATUS_COMMAND, commands::FLUSH_CACHE);
+        assert_eq!(cf.read(regs::STATUS_COMMAND), status::BSY);
+        let status = read_status_ready(&mut cf);
+        assert_eq!(status & status::ERR, 0);
+        assert_eq!(cf.cache_stats().dirty, 0);
+
+        let file = fs::read(&path).unwrap();
+        assert!(file[..SECTOR_SIZE].iter().all(|&b| b == 0xA1));
+        assert!(file[SECTOR_SIZE..SECTOR_SIZE * 2]
+            .iter()
+            .all(|&b| b == 0xA2));
+        assert!(file[SECTOR_SIZE * 2..SECTOR_SIZE * 3]
+            .iter()
+            .all(|&b| b == 0xA3));
+        assert!(file[SECTOR_SIZE * 3..].iter().all(|&b| b == 0));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cfcard_failed_flush_keeps_dirty_sectors() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE * 4]);
+        write_sectors(&mut cf, 0, &[0x11]);
+        write_sectors(&mut cf, 3, &[0x33]);
+
+        // The image shrinks under the card, so sector 3 can't be written
+        let Storage::Memory(data) = &mut cf.storage else {
+            unreachable!()
+        };
+        data.truncate(SECTOR_SIZE * 2);
+        assert!(cf.flush().is_err());
+        assert_eq!(cf.cache_stats().dirty, 1);
+
+        // Once the image can take it, the next flush writes it
+        let Storage::Memory(data) = &mut cf.storage else {
+            unreachable!()
+        };
+        data.resize(SECTOR_SIZE * 4, 0);
+        cf.flush().unwrap();
+        assert_eq!(cf.cache_stats().dirty, 0);
+        let Storage::Memory(data) = &cf.storage else {
+            unreachable!()
+        };
+        assert!(data[..SECTOR_SIZE].iter().all(|&b| b == 0x11));
+        assert!(data[SECTOR_SIZE * 3..].iter().all(|&b| b == 0x33));
+    }
+
+    #[test]
+    fn test_cfcard_eject_flushes() {
+        let path = temp_image("eject", 4);
+        let mut cf = CfCard::new();
+        cf.load_image(&path).unwrap();
+
+        write_sectors(&mut cf, 3, &[0x5A]);
+        assert!(fs::read(&path).unwrap().iter().all(|&b| b == 0));
+
+        cf.eject().unwrap();
+        let file = fs::read(&path).unwrap();
+        assert!(file[SECTOR_SIZE * 3..].iter().all(|&b| b == 0x5A));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cfcard_cache_lru_eviction() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE * 8]);
+        cf.set_cache_capacity(2).unwrap();
+
+        // Third sector evicts the least recently used dirty sector
+        write_sectors(&mut cf, 0, &[0x01, 0x02, 0x03]);
+        assert_eq!(cf.cache_stats().dirty, 2);
+
+        cf.write(regs::LBA0, 0);
+        cf.write(regs::SECTOR_COUNT, 1);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        read_status_ready(&mut cf);
+        assert_eq!(cf.read(regs::DATA), 0x01);
+
+        cf.write(regs::LBA0, 2);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        read_status_ready(&mut cf);
+        assert_eq!(cf.read(regs::DATA), 0x03);
+
+        let stats = cf.cache_stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.hits, 1);
+    }
+
+    #[test]
+    fn test_cfcard_eject_aborts_partial_write() {
+        let path = temp_image("abort", 4);
+        let mut cf = CfCard::new();
+        cf.load_image(&path).unwrap();
+
+        cf.write(regs::LBA0, 1);
+        cf.write(regs::SECTOR_COUNT, 1);
+        cf.write(regs::STATUS_COMMAND, commands::WRITE_SECTORS);
+        read_status_ready(&mut cf);
+        for _ in 0..SECTOR_SIZE / 2 {
+            cf.write(regs::DATA, 0xEE);
+        }
+
+        cf.eject().unwrap();
+        assert!(!cf.is_inserted());
+        assert_eq!(cf.read(regs::STATUS_COMMAND), 0xFF);
+        assert!(fs::read(&path).unwrap().iter().all(|&b| b == 0));
+
+        // Reinserting restores normal operation
+        cf.insert(CfImage::File(path.clone())).unwrap();
+        cf.write(regs::STATUS_COMMAND, commands::IDENTIFY);
+        assert!(read_status_ready(&mut cf) & status::DRQ != 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cfcard_interrupt_gated_by_nien() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE * 4]);
+
+        // nIEN is set at power-up: no interrupt
+        cf.write(regs::STATUS_COMMAND, commands::IDENTIFY);
+        read_status_ready(&mut cf);
+        assert!(!cf.interrupt_pending());
+
+        cf.write(regs::ALT_STATUS_CONTROL, 0);
+        cf.write(regs::STATUS_COMMAND, commands::IDENTIFY);
+        assert!(!cf.interrupt_pending());
+        while cf.is_busy() {
+            cf.tick(100);
+        }
+        assert!(cf.interrupt_pending());
+
+        // Alternate status doesn't acknowledge, status does
+        assert!(cf.read(regs::ALT_STATUS_CONTROL) & status::DRQ != 0);
+        assert!(cf.interrupt_pending());
+        cf.read(regs::STATUS_COMMAND);
+        assert!(!cf.interrupt_pending());
+    }
+
+    #[test]
+    fn test_cfcard_interrupt_per_read_block() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE * 4]);
+        cf.set_timing(CfTiming::INSTANT);
+        cf.write(regs::ALT_STATUS_CONTROL, 0);
+
+        cf.write(regs::SECTOR_COUNT, 2);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        assert!(cf.interrupt_pending());
+        cf.read(regs::STATUS_COMMAND);
+
+        for _ in 0..SECTOR_SIZE {
+            cf.read(regs::DATA);
+        }
+        assert!(cf.interrupt_pending());
+        cf.read(regs::STATUS_COMMAND);
+
+        // No interrupt after the final block has been read
+        for _ in 0..SECTOR_SIZE {
+            cf.read(regs::DATA);
+        }
+        assert!(!cf.interrupt_pending());
+    }
+
+    fn start_read(cf: &mut CfCard, lba: u8) {
+        cf.write(regs::LBA0, lba);
+        cf.write(regs::SECTOR_COUNT, 1);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        assert!(read_status_ready(cf) & status::DRQ != 0);
+    }
+
+    fn set_features(cf: &mut CfCard, subcommand: u8) -> u8 {
+        cf.write(regs::ERROR_FEATURE, subcommand);
+        cf.write(regs::STATUS_COMMAND, commands::SET_FEATURES);
+        read_status_ready(cf)
+    }
+
+    #[test]
+    fn test_cfcard_8bit_and_16bit_reads_match() {
+        let mut cf = CfCard::new();
+        let mut data = vec![0u8; SECTOR_SIZE * 2];
+        for (i, byte) in data[SECTOR_SIZE..].iter_mut().enumerate() {
+            *byte = (i * 7) as u8;
+        }
+        cf.load_bytes(&data);
+
+        // 16-bit mode: 256 word accesses (high lane, then low lane)
+        start_read(&mut cf, 1);
+        let mut words = Vec::new();
+        for _ in 0..SECTOR_SIZE / 2 {
+            words.push(cf.read(regs::DATA));
+            words.push(cf.read(regs::DATA + 1));
+        }
+        assert_eq!(cf.read(regs::STATUS_COMMAND) & status::DRQ, 0);
+
+        // 8-bit mode: 512 byte accesses on the low lane
+        assert_eq!(
+            set_features(&mut cf, features::ENABLE_8BIT) & status::ERR,
+            0
+        );
+        assert!(cf.eight_bit_mode());
+        start_read(&mut cf, 1);
+        assert_eq!(cf.read(regs::DATA), 0xFF);
+        let mut bytes = Vec::new();
+        for _ in 0..SECTOR_SIZE {
+            bytes.push(cf.read(regs::DATA + 1));
+        }
+        assert_eq!(cf.read(regs::STATUS_COMMAND) & status::DRQ, 0);
+
+        assert_eq!(words, bytes);
+        assert_eq!(bytes, &data[SECTOR_SIZE..]);
+
+        assert_eq!(
+            set_features(&mut cf, features::DISABLE_8BIT) & status::ERR,
+            0
+        );
+        assert!(!cf.eight_bit_mode());
+    }
+
+    #[test]
+    fn test_cfcard_set_features_unknown_aborts() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE]);
+
+        assert!(set_features(&mut cf, 0x55) & status::ERR != 0);
+        assert_eq!(cf.read(regs::ERROR_FEATURE), error::ABRT);
+        assert!(!cf.eight_bit_mode());
+    }
+
+    #[test]
+    fn test_cfcard_identify_advertises_8bit() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE]);
+        cf.write(regs::STATUS_COMMAND, commands::IDENTIFY);
+        read_status_ready(&mut cf);
+
+        let identify: Vec<u8> = (0..SECTOR_SIZE).map(|_| cf.read(regs::DATA)).collect();
+        assert_eq!(identify[97] & 0x01, 0x01);
+    }
+
+    fn file_hash(path: &Path) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        fs::read(path).unwrap().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn read_first_byte(cf: &mut CfCard, lba: u8) -> u8 {
+        start_read(cf, lba);
+        cf.read(regs::DATA)
+    }
+
+    #[test]
+    fn test_cfcard_overlay_keeps_base_pristine() {
+        let path = temp_image("overlay", 4);
+        let base_hash = file_hash(&path);
+        let mut cf = CfCard::new();
+        cf.load_image(&path).unwrap();
+        cf.set_overlay_enabled(true).unwrap();
+
+        write_sectors(&mut cf, 2, &[0x77]);
+        cf.write(regs::STATUS_COMMAND, commands::FLUSH_CACHE);
+        read_status_ready(&mut cf);
+        assert_eq!(cf.overlay_sectors(), 1);
+        assert_eq!(read_first_byte(&mut cf, 2), 0x77);
+        assert_eq!(file_hash(&path), base_hash);
+
+        cf.discard_overlay();
+        assert_eq!(cf.overlay_sectors(), 0);
+        assert_eq!(read_first_byte(&mut cf, 2), 0x00);
+        assert_eq!(file_hash(&path), base_hash);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cfcard_failed_commit_keeps_overlay_sectors() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE * 4]);
+        cf.set_overlay_enabled(true).unwrap();
+        write_sectors(&mut cf, 0, &[0x11]);
+        write_sectors(&mut cf, 3, &[0x33]);
+
+        // The image shrinks under the card, so sector 3 can't be written
+        let Storage::Memory(data) = &mut cf.storage else {
+            unreachable!()
+        };
+        data.truncate(SECTOR_SIZE * 2);
+        assert!(cf.commit_overlay().is_err());
+        assert_eq!(cf.overlay_sectors(), 1);
+        assert_eq!(read_first_byte(&mut cf, 3), 0x33);
+
+        let Storage::Memory(data) = &mut cf.storage else {
+            unreachable!()
+        };
+        data.resize(SECTOR_SIZE * 4, 0);
+        cf.commit_overlay().unwrap();
+        assert_eq!(cf.overlay_sectors(), 0);
+        let Storage::Memory(data) = &cf.storage else {
+            unreachable!()
+        };
+        assert!(data[..SECTOR_SIZE].iter().all(|&b| b == 0x11));
+        assert!(data[SECTOR_SIZE * 3..].iter().all(|&b| b == 0x33));
+    }
+
+    #[test]
+    fn test_cfcard_overlay_commit_and_export() {
+        let path = temp_image("commit", 4);
+        let delta =
+            std::env::temp_dir().join(format!("flux32-cfcard-delta-{}.bin", std::process::id()));
+        let mut cf = CfCard::new();
+        cf.load_image(&path).unwrap();
+        cf.set_overlay_enabled(true).unwrap();
+
+        write_sectors(&mut cf, 1, &[0x42]);
+        cf.export_overlay(&delta).unwrap();
+        let exported = fs::read(&delta).unwrap();
+        assert_eq!(&exported[..8], OVERLAY_MAGIC);
+        assert_eq!(&exported[8..12], &1u32.to_be_bytes());
+        assert_eq!(&exported[12..16], &1u32.to_be_bytes());
+        assert!(exported[16..].iter().all(|&b| b == 0x42));
+        assert!(fs::read(&path).unwrap().iter().all(|&b| b == 0));
+
+        cf.commit_overlay().unwrap();
+        assert_eq!(cf.overlay_sectors(), 0);
+        let file = fs::read(&path).unwrap();
+        assert!(file[SECTOR_SIZE..SECTOR_SIZE * 2]
+            .iter()
+            .all(|&b| b == 0x42));
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&delta).unwrap();
+    }
+
+    #[test]
+    fn test_cfcard_large_sparse_image_streams() {
+        // 8 GB plus a few sectors, so the last LBA needs drive/head bits 24-27
+        let path =
+            std::env::temp_dir().join(format!("flux32-cfcard-sparse-{}.img", std::process::id()));
+        let sectors = (1u64 << 24) + 4;
+        let last = (sectors - 1) as u32;
+        {
+            let mut file = fs::File::create(&path).unwrap();
+            file.set_len(sectors * SECTOR_SIZE as u64).unwrap();
+            file.seek(SeekFrom::Start(u64::from(last) * SECTOR_SIZE as u64))
+                .unwrap();
+            file.write_all(&[0x5A; SECTOR_SIZE]).unwrap();
+        }
+
+        let mut cf = CfCard::new();
+        cf.load_image(&path).unwrap();
+        assert_eq!(cf.sector_count(), last + 1);
+        assert_eq!(cf.capacity(), sectors * SECTOR_SIZE as u64);
+
+        cf.write(regs::LBA0, last as u8);
+        cf.write(regs::LBA1, (last >> 8) as u8);
+        cf.write(regs::LBA2, (last >> 16) as u8);
+        cf.write(regs::DRIVE_HEAD, 0xE0 | (last >> 24) as u8);
+        cf.write(regs::SECTOR_COUNT, 1);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        assert!(read_status_ready(&mut cf) & status::DRQ != 0);
+        assert!((0..SECTOR_SIZE).all(|_| cf.read(regs::DATA) == 0x5A));
+
+        // Without the drive/head bits the LBA is 16M sectors lower
+        cf.write(regs::DRIVE_HEAD, 0xE0);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        assert!(read_status_ready(&mut cf) & status::DRQ != 0);
+        assert_eq!(cf.read(regs::DATA), 0x00);
+
+        // One past the end fails
+        cf.write(regs::LBA0, (last + 1) as u8);
+        cf.write(regs::DRIVE_HEAD, 0xE0 | ((last + 1) >> 24) as u8);
+        cf.write(regs::STATUS_COMMAND, commands::READ_SECTORS);
+        assert!(read_status_ready(&mut cf) & status::ERR != 0);
+        assert_eq!(cf.read(regs::ERROR_FEATURE), error::IDNF);
+        drop(cf);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cfcard_lba48_commands_abort() {
+        let mut cf = CfCard::new();
+        cf.load_bytes(&vec![0u8; SECTOR_SIZE * 4]);
+
+        for cmd in [
+            commands::READ_SECTORS_EXT,
+            commands::WRITE_SECTORS_EXT,
+            commands::FLUSH_CACHE_EXT,
+        ] {
+            cf.write(regs::SECTOR_COUNT, 1);
+            cf.write(regs::STATUS_COMMAND, cmd);
+            let status = read_status_ready(&mut cf);
+            assert!(status & status::ERR != 0);
+            assert_eq!(status & status::DRQ, 0);
+            assert_eq!(cf.read(regs::ERROR_FEATURE), error::ABRT);
+        }
+    }
+
+    #[test]
+    fn test_cfcard_write_past_end_fails() {
+        let path = temp_image("pastend", 2);
+        let mut cf = CfCard::new();
+        cf.load_image(&path).unwrap();
+
+        cf.write(regs::LBA0, 2);
+        cf.write(regs::SECTOR_COUNT, 1);
+        cf.write(regs::STATUS_COMMAND, commands::WRITE_SECTORS);
+        assert!(read_status_ready(&mut cf) & status::ERR != 0);
+        assert_eq!(cf.read(regs::ERROR_FEATURE), error::IDNF);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 2 * SECTOR_SIZE as u64);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cfcard_partial_trailing_sector() {
+        let path =
+            std::env::temp_dir().join(format!("flux32-cfcard-partial-{}.img", std::process::id()));
+        fs::write(&path, vec![0x11; SECTOR_SIZE + 10]).unwrap();
+        let mut cf = CfCard::new();
+        cf.load_image(&path).unwrap();
+        assert_eq!(cf.sector_count(), 2);
+
+        start_read(&mut cf, 1);
+        let sector: Vec<u8> = (0..SECTOR_SIZE).map(|_| cf.read(regs::DATA)).collect();
+        assert!(sector[..10].iter().all(|&b| b == 0x11));
+        assert!(sector[10..].iter().all(|&b| b == 0));
+        fs::remove_file(&path).unwrap();
+    }
 }
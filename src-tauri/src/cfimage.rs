@@ -0,0 +1,396 @@
+//! Blank `CompactFlash` Image Creation
+//!
+//! This module creates raw disk images for the emulated CF card, so a new
+//! card can be prepared without external tools.
+//!
+//! ## Layout
+//!
+//! Every image starts with an MBR (boot signature $55AA). When formatting is
+//! requested, the MBR holds a single FAT16 partition starting at LBA 63:
+//!
+//! ```text
+//! LBA 0          MBR with one partition entry (type $04 or $06)
+//! LBA 63         FAT16 boot sector (BPB)
+//! LBA 64         FAT #1, followed by FAT #2
+//! ...            Root directory (512 entries, volume label first)
+//! ...            Data region
+//! ```
+//!
+//! The cluster size follows the usual FAT16 table for the partition size,
+//! which keeps the cluster count inside the FAT16 range (4085-65524).
+//! Everything past the metadata is zero; the file is extended with
+//! `set_len`, so large images stay sparse on filesystems that support it.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::cfcard::SECTOR_SIZE;
+
+/// Smallest image that can be created, formatted or not
+pub const MIN_IMAGE_MB: u32 = 1;
+
+/// Largest image that can be created (the 28-bit LBA limit)
+pub const MAX_IMAGE_MB: u32 = 128 * 1024;
+
+/// Smallest image that can hold a FAT16 filesystem
+pub const MIN_FAT16_MB: u32 = 5;
+
+/// Largest image that can hold a FAT16 filesystem
+pub const MAX_FAT16_MB: u32 = 2047;
+
+/// First sector of the FAT16 partition
+pub const PARTITION_START: u32 = 63;
+
+/// Volume label written to newly formatted images
+pub const DEFAULT_LABEL: &[u8; 11] = b"FLUX32     ";
+
+/// Sectors reserved before the first FAT (just the boot sector)
+const RESERVED_SECTORS: u16 = 1;
+
+/// Number of FAT copies
+const FAT_COPIES: u8 = 2;
+
+/// Root directory entries (32 sectors)
+const ROOT_ENTRIES: u16 = 512;
+
+/// Media descriptor for fixed disks
+const MEDIA_FIXED: u8 = 0xF8;
+
+/// Errors that can occur while creating an image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CfImageError {
+    /// The requested size is outside the supported range
+    InvalidSize {
+        /// The size that was asked for, in megabytes
+        size_mb: u32,
+        /// The smallest size allowed, in megabytes
+        min_mb: u32,
+        /// The largest size allowed, in megabytes
+        max_mb: u32,
+    },
+    /// The requested size yields a cluster count outside the FAT16 range
+    InvalidClusterCount(u32),
+    /// Writing the image file failed
+    Io(io::ErrorKind, String),
+}
+
+impl fmt::Display for CfImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSize {
+                size_mb,
+                min_mb,
+                max_mb,
+            } => write!(
+                f,
+                "Invalid image size: {size_mb} MB (must be {min_mb}-{max_mb} MB)"
+            ),
+            Self::InvalidClusterCount(count) => {
+                write!(f, "Invalid FAT16 cluster count: {count}")
+            }
+            Self::Io(_, message) => write!(f, "I/O error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for CfImageError {}
+
+impl From<io::Error> for CfImageError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error.kind(), error.to_string())
+    }
+}
+
+/// FAT16 filesystem geometry for a partition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fat16Geometry {
+    /// Sectors in the partition
+    pub total_sectors: u32,
+    /// Sectors per cluster
+    pub sectors_per_cluster: u8,
+    /// Sectors per FAT copy
+    pub fat_sectors: u16,
+    /// Number of data clusters
+    pub clusters: u32,
+}
+
+impl Fat16Geometry {
+    /// Computes the geometry for a partition of `total_sectors` sectors
+    pub fn new(total_sectors: u32) -> Result<Self, CfImageError> {
+        let sectors_per_cluster = match total_sectors {
+            0..=32_680 => 2,
+            32_681..=262_144 => 4,
+            262_145..=524_288 => 8,
+            524_289..=1_048_576 => 16,
+            1_048_577..=2_097_152 => 32,
+            _ => 64,
+        };
+
+        // Standard FAT size calculation (slightly overestimates, never under)
+        let root_sectors = u32::from(ROOT_ENTRIES) * 32 / SECTOR_SIZE as u32;
+        let tmp1 = total_sectors.saturating_sub(u32::from(RESERVED_SECTORS) + root_sectors);
+        let tmp2 = 256 * sectors_per_cluster + u32::from(FAT_COPIES);
+        let fat_sectors = tmp1.div_ceil(tmp2);
+
+        let data_start =
+            u32::from(RESERVED_SECTORS) + u32::from(FAT_COPIES) * fat_sectors + root_sectors;
+        let clusters = total_sectors.saturating_sub(data_start) / sectors_per_cluster;
+        if !(4085..=65524).contains(&clusters) {
+            return Err(CfImageError::InvalidClusterCount(clusters));
+        }
+
+        Ok(Self {
+            total_sectors,
+            sectors_per_cluster: sectors_per_cluster as u8,
+            fat_sectors: fat_sectors as u16,
+            clusters,
+        })
+    }
+
+    /// Returns the first sector of the root directory, relative to the partition
+    #[must_use]
+    pub fn root_dir_sector(&self) -> u32 {
+        u32::from(RESERVED_SECTORS) + u32::from(FAT_COPIES) * u32::from(self.fat_sectors)
+    }
+}
+
+/// Creates a zero-filled image of `size_mb` megabytes at `path`
+///
+/// The image always gets an MBR. With `format` set, it also gets a single
+/// FAT16 partition with a boot sector, two empty FATs, and a root directory
+/// holding the volume label.
+pub fn create_image(path: &Path, size_mb: u32, format: bool) -> Result<(), CfImageError> {
+    let (min_mb, max_mb) = if format {
+        (MIN_FAT16_MB, MAX_FAT16_MB)
+    } else {
+        (MIN_IMAGE_MB, MAX_IMAGE_MB)
+    };
+    if !(min_mb..=max_mb).contains(&size_mb) {
+        return Err(CfImageError::InvalidSize {
+            size_mb,
+            min_mb,
+            max_mb,
+        });
+    }
+
+    let total_sectors = size_mb * 2048;
+    let geometry = if format {
+        Some(Fat16Geometry::new(total_sectors - PARTITION_START)?)
+    } else {
+        None
+    };
+
+    let mut file = fs::File::create(path)?;
+    file.set_len(u64::from(total_sectors) * SECTOR_SIZE as u64)?;
+    file.write_all(&build_mbr(geometry.as_ref()))?;
+
+    if let Some(geometry) = geometry {
+        let base = u64::from(PARTITION_START);
+        write_sector(&mut file, base, &build_boot_sector(&geometry, volume_id()))?;
+
+        // First FAT sector of each copy holds the reserved entries 0 and 1
+        let mut fat = [0u8; SECTOR_SIZE];
+        fat[..4].copy_from_slice(&[MEDIA_FIXED, 0xFF, 0xFF, 0xFF]);
+        for copy in 0..u64::from(FAT_COPIES) {
+            let lba = base + u64::from(RESERVED_SECTORS) + copy * u64::from(geometry.fat_sectors);
+            write_sector(&mut file, lba, &fat)?;
+        }
+
+        let mut root = [0u8; SECTOR_SIZE];
+        root[..11].copy_from_slice(DEFAULT_LABEL);
+        root[11] = 0x08; // Volume label attribute
+        write_sector(
+            &mut file,
+            base + u64::from(geometry.root_dir_sector()),
+            &root,
+        )?;
+    }
+
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes one sector at the given LBA
+fn write_sector(file: &mut fs::File, lba: u64, sector: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(lba * SECTOR_SIZE as u64))?;
+    file.write_all(sector)
+}
+
+/// Builds the MBR, with a partition entry if a filesystem is present
+fn build_mbr(geometry: Option<&Fat16Geometry>) -> [u8; SECTOR_SIZE] {
+    let mut mbr = [0u8; SECTOR_SIZE];
+    mbr[0x1B8..0x1BC].copy_from_slice(&volume_id().to_le_bytes());
+
+    if let Some(geometry) = geometry {
+        let end = PARTITION_START + geometry.total_sectors - 1;
+        let entry = &mut mbr[0x1BE..0x1CE];
+        entry[0] = 0x00; // Not bootable
+        entry[1..4].copy_from_slice(&chs(PARTITION_START));
+        // Partitions below 32MB use the small FAT16 type
+        entry[4] = if geometry.total_sectors < 65536 {
+            0x04
+        } else {
+            0x06
+        };
+        entry[5..8].copy_from_slice(&chs(end));
+        entry[8..12].copy_from_slice(&PARTITION_START.to_le_bytes());
+        entry[12..16].copy_from_slice(&geometry.total_sectors.to_le_bytes());
+    }
+
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+    mbr
+}
+
+/// Builds the FAT16 boot sector with its BIOS Parameter Block
+fn build_boot_sector(geometry: &Fat16Geometry, volume_id: u32) -> [u8; SECTOR_SIZE] {
+    let mut bs = [0u8; SECTOR_SIZE];
+    bs[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    bs[3..11].copy_from_slice(b"FLUX32  ");
+    bs[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+    bs[13] = geometry.sectors_per_cluster;
+    bs[14..16].copy_from_slice(&RESERVED_SECTORS.to_le_bytes());
+    bs[16] = FAT_COPIES;
+    bs[17..19].copy_from_slice(&ROOT_ENTRIES.to_le_bytes());
+    if let Ok(small) = u16::try_from(geometry.total_sectors) {
+        bs[19..21].copy_from_slice(&small.to_le_bytes());
+    } else {
+        bs[32..36].copy_from_slice(&geometry.total_sectors.to_le_bytes());
+    }
+    bs[21] = MEDIA_FIXED;
+    bs[22..24].copy_from_slice(&geometry.fat_sectors.to_le_bytes());
+    bs[24..26].copy_from_slice(&63u16.to_le_bytes()); // Sectors per track
+    bs[26..28].copy_from_slice(&255u16.to_le_bytes()); // Heads
+    bs[28..32].copy_from_slice(&PARTITION_START.to_le_bytes()); // Hidden sectors
+    bs[36] = 0x80; // Drive number
+    bs[38] = 0x29; // Extended boot signature
+    bs[39..43].copy_from_slice(&volume_id.to_le_bytes());
+    bs[43..54].copy_from_slice(DEFAULT_LABEL);
+    bs[54..62].copy_from_slice(b"FAT16   ");
+    bs[510] = 0x55;
+    bs[511] = 0xAA;
+    bs
+}
+
+/// Converts an LBA to a packed CHS triple (255 heads, 63 sectors per track)
+const fn chs(lba: u32) -> [u8; 3] {
+    let cylinder = lba / (255 * 63);
+    if cylinder > 1023 {
+        return [0xFE, 0xFF, 0xFF];
+    }
+    let head = (lba / 63) % 255;
+    let sector = lba % 63 + 1;
+    [
+        head as u8,
+        (sector as u8) | ((cylinder >> 2) as u8 & 0xC0),
+        cylinder as u8,
+    ]
+}
+
+/// Derives a volume serial number from the current time
+fn volume_id() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("flux32-cfimage-{name}-{}.img", std::process::id()))
+    }
+
+    fn le16(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn le32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_create_formatted_image_bpb() {
+        let path = temp_path("fat16");
+        create_image(&path, 8, true).unwrap();
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), 8 * 1024 * 1024);
+
+        // MBR partition entry
+        assert_eq!(&data[510..512], &[0x55, 0xAA]);
+        assert_eq!(data[0x1BE + 4], 0x04);
+        assert_eq!(le32(&data, 0x1BE + 8), PARTITION_START);
+        assert_eq!(le32(&data, 0x1BE + 12), 8 * 2048 - PARTITION_START);
+
+        // BPB
+        let bs = &data[PARTITION_START as usize * SECTOR_SIZE..][..SECTOR_SIZE];
+        assert_eq!(le16(bs, 11), 512);
+        assert_eq!(bs[13], 2);
+        assert_eq!(le16(bs, 14), 1);
+        assert_eq!(bs[16], 2);
+        assert_eq!(le16(bs, 17), 512);
+        assert_eq!(le16(bs, 19), 16321);
+        assert_eq!(bs[21], 0xF8);
+        assert_eq!(&bs[43..54], DEFAULT_LABEL);
+        assert_eq!(&bs[54..62], b"FAT16   ");
+        assert_eq!(&bs[510..512], &[0x55, 0xAA]);
+
+        // FAT #1 and FAT #2 start with the media descriptor
+        let geometry = Fat16Geometry::new(16321).unwrap();
+        assert_eq!(le16(bs, 22), geometry.fat_sectors);
+        for copy in 0..2 {
+            let lba = PARTITION_START as usize + 1 + copy * geometry.fat_sectors as usize;
+            assert_eq!(&data[lba * SECTOR_SIZE..][..4], &[0xF8, 0xFF, 0xFF, 0xFF]);
+        }
+
+        // Root directory holds the volume label
+        let root = (PARTITION_START + geometry.root_dir_sector()) as usize * SECTOR_SIZE;
+        assert_eq!(&data[root..root + 11], DEFAULT_LABEL);
+        assert_eq!(data[root + 11], 0x08);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_create_unformatted_image() {
+        let path = temp_path("raw");
+        create_image(&path, 1, false).unwrap();
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), 1024 * 1024);
+        assert_eq!(&data[510..512], &[0x55, 0xAA]);
+        assert!(data[0x1BE..0x1FE].iter().all(|&b| b == 0));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_create_image_size_limits() {
+        let path = temp_path("limits");
+        assert_eq!(
+            create_image(&path, 4, true),
+            Err(CfImageError::InvalidSize {
+                size_mb: 4,
+                min_mb: MIN_FAT16_MB,
+                max_mb: MAX_FAT16_MB,
+            })
+        );
+        assert!(matches!(
+            create_image(&path, 4096, true),
+            Err(CfImageError::InvalidSize { .. })
+        ));
+        assert!(matches!(
+            create_image(&path, 0, false),
+            Err(CfImageError::InvalidSize { .. })
+        ));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_fat16_geometry_range() {
+        for size_mb in [MIN_FAT16_MB, 16, 32, 128, 512, 1024, MAX_FAT16_MB] {
+            let geometry = Fat16Geometry::new(size_mb * 2048 - PARTITION_START).unwrap();
+            assert!((4085..=65524).contains(&geometry.clusters), "{size_mb} MB");
+        }
+    }
+}
@@ -0,0 +1,392 @@
+//! Machine Configuration
+//!
+//! This module defines `SbcConfig`, the options an SBC is built with. It
+//! deserializes from JSON (camelCase keys) and every missing field takes its
+//! default, so `{}` describes the stock board:
+//!
+//! | Field          | Default       | Meaning                                       |
+//! |----------------|---------------|-----------------------------------------------|
+//! | `clockHz`      | 12000000      | CPU clock; whole MHz from 1 to 50             |
+//! | `ramSize`      | 1048576       | Populated RAM; a power of two, 64KB to 1MB    |
+//! | `romPath`      | none          | ROM image to boot instead of the embedded one |
+//! | `resetVectors` | none          | SSP and PC used instead of the ROM's vectors  |
+//! | `uart`         | 4KB queue     | UART wiring and input queue (`UartConfig`)    |
+//! | `peripherals`  | all enabled   | Timer, RTC and GPIO enables                   |
+//! | `cf`           | empty slot    | `CompactFlash` card (see `CfConfig`)          |
+//! | `dipSwitch`    | 0             | DIP switch positions (bit n = switch n + 1)   |
+//! | `clrRead`      | false         | CLR reads its destination before clearing it  |
+//! | `prefetch`     | false         | Emulate the 68000 prefetch                    |
+//! | `openBus`      | `high`        | Value unmapped reads return                   |
+//!
+//! The clock only changes how emulated time maps to CPU cycles: the timer,
+//! buzzer and RTC keep counting in microseconds and milliseconds. RAM beyond
+//! `ramSize` is unpopulated, so it reads as open bus and ignores writes. A
+//! disabled peripheral leaves its expansion slot open.
+
+use std::fmt;
+
+use crate::sbc::{ResetVectors, CLOCK_HZ, DEFAULT_CF_IRQ_LEVEL, RAM_SIZE};
+
+/// Slowest supported CPU clock in Hz
+pub const MIN_CLOCK_HZ: u32 = 1_000_000;
+
+/// Fastest supported CPU clock in Hz
+pub const MAX_CLOCK_HZ: u32 = 50_000_000;
+
+/// Smallest supported RAM size in bytes
+pub const MIN_RAM_SIZE: u32 = 64 * 1024;
+
+/// Default size of the host input queue in bytes
+pub const DEFAULT_INPUT_QUEUE: u32 = 4096;
+
+/// Largest supported host input queue in bytes
+pub const MAX_INPUT_QUEUE: u32 = 1024 * 1024;
+
+/// Value unmapped reads return
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OpenBusPolicy {
+    /// The data bus floats high ($FF), as with the board's pull-ups
+    #[default]
+    High,
+    /// The data bus reads low ($00)
+    Low,
+}
+
+impl OpenBusPolicy {
+    /// Returns the byte an unmapped read returns
+    #[must_use]
+    pub const fn value(self) -> u8 {
+        match self {
+            Self::High => 0xFF,
+            Self::Low => 0x00,
+        }
+    }
+}
+
+/// How pasted text's line endings reach the UART
+///
+/// CR, LF and CR LF in the paste each count as one line ending.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// Bytes are sent as they are
+    #[default]
+    Unchanged,
+    /// Line endings are sent as CR, like the Enter key
+    Cr,
+    /// Line endings are sent as LF
+    Lf,
+    /// Line endings are sent as CR LF
+    CrLf,
+}
+
+impl LineEnding {
+    /// Returns the bytes a line ending is sent as, or `None` to send it
+    /// unchanged
+    #[must_use]
+    pub const fn bytes(self) -> Option<&'static [u8]> {
+        match self {
+            Self::Unchanged => None,
+            Self::Cr => Some(b"\r"),
+            Self::Lf => Some(b"\n"),
+            Self::CrLf => Some(b"\r\n"),
+        }
+    }
+}
+
+/// UART wiring options
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UartConfig {
+    /// BEL (0x07) sent to the UART sounds the buzzer's bell
+    pub bell: bool,
+    /// The CF card-detect signal is wired to the UART's CTS input
+    pub card_detect: bool,
+    /// Ctrl-C from the terminal raises an NMI instead of reaching the UART
+    pub ctrl_c_nmi: bool,
+    /// Bytes the host input queue in front of the RX FIFO holds
+    pub input_queue: u32,
+    /// Queued input only reaches the RX FIFO while the guest asserts RTS
+    pub flow_control: bool,
+    /// Line endings in pasted text are translated to this
+    pub line_ending: LineEnding,
+}
+
+impl Default for UartConfig {
+    fn default() -> Self {
+        Self {
+            bell: false,
+            card_detect: false,
+            ctrl_c_nmi: false,
+            input_queue: DEFAULT_INPUT_QUEUE,
+            flow_control: false,
+            line_ending: LineEnding::Unchanged,
+        }
+    }
+}
+
+/// Expansion peripherals that can be left off the bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PeripheralConfig {
+    /// Interval timer
+    pub timer: bool,
+    /// Real-time clock
+    pub rtc: bool,
+    /// GPIO port
+    pub gpio: bool,
+}
+
+impl Default for PeripheralConfig {
+    fn default() -> Self {
+        Self {
+            timer: true,
+            rtc: true,
+            gpio: true,
+        }
+    }
+}
+
+/// `CompactFlash` card options
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CfConfig {
+    /// Disk image inserted at power-on (none = empty slot)
+    pub image: Option<String>,
+    /// Writes go to a copy-on-write overlay instead of the image
+    pub overlay: bool,
+    /// Priority of the CF INTRQ line (0 disconnects it)
+    pub irq_level: u8,
+}
+
+impl Default for CfConfig {
+    fn default() -> Self {
+        Self {
+            image: None,
+            overlay: false,
+            irq_level: DEFAULT_CF_IRQ_LEVEL,
+        }
+    }
+}
+
+/// Options an SBC is built with
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SbcConfig {
+    /// CPU clock in Hz
+    pub clock_hz: u32,
+    /// Populated RAM in bytes
+    pub ram_size: u32,
+    /// ROM image to boot instead of the embedded ROM
+    pub rom_path: Option<String>,
+    /// Reset vectors used instead of the ROM's vector table
+    pub reset_vectors: Option<ResetVectors>,
+    /// UART wiring
+    pub uart: UartConfig,
+    /// Expansion peripheral enables
+    pub peripherals: PeripheralConfig,
+    /// `CompactFlash` card
+    pub cf: CfConfig,
+    /// DIP switch positions (bit n = switch n + 1, 1 = ON)
+    pub dip_switch: u8,
+    /// CLR reads its memory destination before clearing it
+    pub clr_read: bool,
+    /// Emulate the 68000 prefetch (see `Cpu::set_prefetch`)
+    pub prefetch: bool,
+    /// Value unmapped reads return
+    pub open_bus: OpenBusPolicy,
+}
+
+impl Default for SbcConfig {
+    fn default() -> Self {
+        Self {
+            clock_hz: CLOCK_HZ,
+            ram_size: RAM_SIZE as u32,
+            rom_path: None,
+            reset_vectors: None,
+            uart: UartConfig::default(),
+            peripherals: PeripheralConfig::default(),
+            cf: CfConfig::default(),
+            dip_switch: 0,
+            clr_read: false,
+            prefetch: false,
+            open_bus: OpenBusPolicy::default(),
+        }
+    }
+}
+
+impl SbcConfig {
+    /// Checks every field, naming the first invalid one
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(MIN_CLOCK_HZ..=MAX_CLOCK_HZ).contains(&self.clock_hz)
+            || !self.clock_hz.is_multiple_of(1_000_000)
+        {
+            return Err(ConfigError::new(
+                "clockHz",
+                format!(
+                    "{} Hz is not a whole number of MHz from {} to {} MHz",
+                    self.clock_hz,
+                    MIN_CLOCK_HZ / 1_000_000,
+                    MAX_CLOCK_HZ / 1_000_000
+                ),
+            ));
+        }
+        if !(MIN_RAM_SIZE..=RAM_SIZE as u32).contains(&self.ram_size)
+            || !self.ram_size.is_power_of_two()
+        {
+            return Err(ConfigError::new(
+                "ramSize",
+                format!(
+                    "{} bytes is not a power of two from {} KB to {} KB",
+                    self.ram_size,
+                    MIN_RAM_SIZE / 1024,
+                    RAM_SIZE / 1024
+                ),
+            ));
+        }
+        if self.rom_path.as_deref() == Some("") {
+            return Err(ConfigError::new("romPath", "path is empty"));
+        }
+        if let Some(vectors) = self.reset_vectors {
+            if vectors.ssp & 1 != 0 {
+                return Err(ConfigError::new("resetVectors.ssp", "must be even"));
+            }
+            if vectors.pc & 1 != 0 {
+                return Err(ConfigError::new("resetVectors.pc", "must be even"));
+            }
+        }
+        if !(1..=MAX_INPUT_QUEUE).contains(&self.uart.input_queue) {
+            return Err(ConfigError::new(
+                "uart.inputQueue",
+                format!(
+                    "{} bytes is not a size from 1 to {} bytes",
+                    self.uart.input_queue, MAX_INPUT_QUEUE
+                ),
+            ));
+        }
+        if self.cf.image.as_deref() == Some("") {
+            return Err(ConfigError::new("cf.image", "path is empty"));
+        }
+        if self.cf.irq_level > 7 {
+            return Err(ConfigError::new(
+                "cf.irqLevel",
+                format!("{} is not a priority from 0 to 7", self.cf.irq_level),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A configuration field that is invalid or couldn't be applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// JSON name of the field (e.g. `ramSize` or `cf.image`)
+    pub field: &'static str,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl ConfigError {
+    /// Creates an error for a field
+    pub fn new(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid {}: {}", self.field, self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_json_round_trip() {
+        let config = SbcConfig {
+            clock_hz: 8_000_000,
+            ram_size: 256 * 1024,
+            rom_path: Some("rom.bin".to_string()),
+            reset_vectors: Some(ResetVectors {
+                ssp: 0x00E4_0000,
+                pc: 0x0000_0400,
+            }),
+            uart: UartConfig {
+                bell: true,
+                card_detect: true,
+                ctrl_c_nmi: true,
+                input_queue: 256,
+                flow_control: true,
+                line_ending: LineEnding::CrLf,
+            },
+            peripherals: PeripheralConfig {
+                timer: false,
+                ..PeripheralConfig::default()
+            },
+            cf: CfConfig {
+                image: Some("disk.img".to_string()),
+                overlay: true,
+                irq_level: 3,
+            },
+            dip_switch: 0xA5,
+            clr_read: true,
+            prefetch: true,
+            open_bus: OpenBusPolicy::Low,
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains("\"ramSize\":262144"));
+        assert!(json.contains("\"openBus\":\"low\""));
+        assert!(json.contains("\"lineEnding\":\"crlf\""));
+        assert_eq!(serde_json::from_str::<SbcConfig>(&json).unwrap(), config);
+
+        // Missing fields take their defaults
+        let partial: SbcConfig = serde_json::from_str(r#"{"ramSize": 65536}"#).unwrap();
+        assert_eq!(
+            partial,
+            SbcConfig {
+                ram_size: 65536,
+                ..SbcConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_validation_names_field() {
+        assert_eq!(SbcConfig::default().validate(), Ok(()));
+
+        let field = |config: SbcConfig| config.validate().unwrap_err().field;
+        assert_eq!(
+            field(SbcConfig {
+                ram_size: 100_000,
+                ..SbcConfig::default()
+            }),
+            "ramSize"
+        );
+        assert_eq!(
+            field(SbcConfig {
+                clock_hz: 12_500_000,
+                ..SbcConfig::default()
+            }),
+            "clockHz"
+        );
+        let mut config = SbcConfig::default();
+        config.uart.input_queue = 0;
+        assert_eq!(field(config), "uart.inputQueue");
+        let mut config = SbcConfig::default();
+        config.cf.irq_level = 8;
+        let err = config.validate().unwrap_err();
+        assert_eq!(err.field, "cf.irqLevel");
+        assert_eq!(
+            err.to_string(),
+            "Invalid cf.irqLevel: 8 is not a priority from 0 to 7"
+        );
+    }
+}
@@ -22,6 +22,22 @@
 use crate::registers::{FlagOps, RegisterFile};
 use std::fmt;
 
+/// CPU state saved in a machine snapshot (everything except memory and
+/// the behavioral options, which come from the configuration).
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CpuSnapshot {
+    /// The register file
+    pub registers: RegisterFile,
+    /// Whether the CPU is halted
+    pub halted: bool,
+    /// Total number of cycles executed
+    pub cycles: u64,
+    /// Vector number of the last exception taken
+    pub last_exception: Option<u8>,
+    /// Prefetched opcode and its address
+    pub prefetched: Option<(u32, u16)>,
+}
+
 /// M68K CPU state.
 ///
 /// The complete CPU state including registers and memory interface.
@@ -34,6 +50,14 @@ pub struct Cpu {
     halted: bool,
     /// Total number of cycles executed.
     cycles: u64,
+    /// Vector number of the last exception taken (see `take_last_exception`).
+    last_exception: Option<u8>,
+    /// Whether CLR reads its memory destination before clearing it.
+    clr_read: bool,
+    /// Whether the next opcode is fetched before the current instruction runs.
+    prefetch: bool,
+    /// Next opcode and its address, fetched ahead while `prefetch` is on.
+    prefetched: Option<(u32, u16)>,
 }
 
 impl Default for Cpu {
@@ -60,6 +84,10 @@ pub fn with_memory_size(size: usize) -> Self {
             memory: Memory::new(size),
             halted: false,
             cycles: 0,
+            last_exception: None,
+            clr_read: false,
+            prefetch: false,
+            prefetched: None,
         }
     }
 
@@ -70,12 +98,58 @@ pub fn with_memory_size(size: usize) -> Self {
     /// - SR is set to supervisor mode (S bit = 1) as per M68K reset behavior
     /// - Memory is cleared to zero for test isolation
     pub fn reset(&mut self) {
+        self.reset_keep_memory();
+        self.memory.clear();
+    }
+
+    /// Resets the CPU like `reset`, but leaves memory as it is.
+    pub const fn reset_keep_memory(&mut self) {
         self.registers = RegisterFile::new();
         // M68K starts in supervisor mode after reset
         self.registers.set_sr(0x2000); // Set S bit (supervisor mode)
-        self.memory.clear();
         self.halted = false;
         self.cycles = 0;
+        self.last_exception = None;
+        self.prefetched = None;
+    }
+
+    /// Captures the CPU state for a snapshot.
+    #[must_use]
+    pub fn save_state(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            registers: self.registers.clone(),
+            halted: self.halted,
+            cycles: self.cycles,
+            last_exception: self.last_exception,
+            prefetched: self.prefetched,
+        }
+    }
+
+    /// Restores state captured by `save_state`.
+    pub const fn restore_state(&mut self, state: CpuSnapshot) {
+        self.registers = state.registers;
+        self.halted = state.halted;
+        self.cycles = state.cycles;
+        self.last_exception = state.last_exception;
+        self.prefetched = state.prefetched;
+    }
+
+    /// Makes CLR read its memory destination before clearing it, as the
+    /// 68000 does.
+    pub const fn set_clr_read(&mut self, enabled: bool) {
+        self.clr_read = enabled;
+    }
+
+    /// Enables prefetch emulation.
+    ///
+    /// The 68000 fetches ahead of the instruction it executes, so an
+    /// instruction that overwrites the one right after it doesn't see its
+    /// change take effect. With prefetch on, the words that follow each
+    /// opcode are fetched before it runs, and the next opcode comes from
+    /// that fetch.
+    pub const fn set_prefetch(&mut self, enabled: bool) {
+        self.prefetch = enabled;
+        self.prefetched = None;
     }
 
     /// Returns the current program counter.
@@ -164,14 +238,36 @@ pub fn step(&mut self) -> bool {
 
         // Fetch the instruction word
         let current_pc = self.registers.pc;
-        let opcode = self.memory.read_word_unchecked(current_pc);
+        let opcode = match self.prefetched.take() {
+            Some((address, word)) if address == current_pc => word,
+            _ => self.memory.read_word_unchecked(current_pc),
+        };
         let initial_pc = current_pc;
 
+        // Fetch ahead up to the longest instruction (10 bytes) past the opcode
+        let lookahead = self.prefetch.then(|| {
+            let mut words = [0u16; 5];
+            for (i, word) in words.iter_mut().enumerate() {
+                *word = self
+                    .memory
+                    .read_word_unchecked(current_pc.wrapping_add(2 + 2 * i as u32));
+            }
+            words
+        });
+
         // Dispatch to instruction handler
         let result = self.execute(opcode);
         self.registers.set_pc(result.pc);
         self.cycles += u64::from(result.cycles);
 
+        // Sequential flow executes the next opcode from the prefetch
+        if let Some(words) = lookahead {
+            let offset = result.pc.wrapping_sub(current_pc);
+            if result.exception == 0 && !result.halt && (2..=10).contains(&offset) {
+                self.prefetched = Some((result.pc, words[(offset / 2 - 1) as usize]));
+            }
+        }
+
         // Handle exceptions if triggered
         if result.exception != 0 {
             // For most exceptions, push the address of the NEXT instruction
@@ -232,6 +328,9 @@ fn trigger_exception_with_sr(
         new_sr: u16,
         ssp: u32,
     ) {
+        // Exception processing refills the prefetch queue
+        self.prefetched = None;
+
         // Set SR (switch to supervisor, clear trace, update IPL as needed)
         self.registers.set_sr(new_sr);
 
@@ -254,6 +353,13 @@ fn trigger_exception_with_sr(
         let new_pc = self.memory.read_long(vector_addr).unwrap_or(0);
 
         self.registers.set_pc(new_pc);
+        self.last_exception = Some(vector);
+    }
+
+    /// Returns and clears the vector number of the last exception taken
+    /// (including interrupts).
+    pub const fn take_last_exception(&mut self) -> Option<u8> {
+        self.last_exception.take()
     }
 
     /// Services an autovector interrupt at the given level (1-7).
@@ -267,6 +373,20 @@ pub fn service_autovector_interrupt(&mut self, level: u8) {
             return;
         }
 
+        // Autovector number is 24 + level.
+        self.service_vectored_interrupt(level, 24 + level);
+    }
+
+    /// Services an interrupt at the given level (1-7) using a vector
+    /// supplied by the interrupting device.
+    ///
+    /// This clears the halted state, updates the IPL, and jumps through the
+    /// given vector.
+    pub fn service_vectored_interrupt(&mut self, level: u8, vector: u8) {
+        if !(1..=7).contains(&level) {
+            return;
+        }
+
         let old_sr = self.registers.sr;
         let ssp = self.registers.get_ssp();
 
@@ -274,8 +394,6 @@ pub fn service_autovector_interrupt(&mut self, level: u8) {
         let mut new_sr = (old_sr | 0x2000) & !0x8000;
         new_sr = (new_sr & !0x0700) | (u16::from(level) << 8);
 
-        // Autovector number is 24 + level.
-        let vector = 24 + level;
         self.halted = false;
         self.trigger_exception_with_sr(vector, self.registers.pc, old_sr, new_sr, ssp);
     }
@@ -546,7 +664,13 @@ fn execute(&mut self, opcode: u16) -> InstructionResult {
 
             // CLR: 0100 0010 ssxx xxxx
             if (opcode & 0xFF00) == 0x4200 {
-                return Instructions::clr(&mut self.registers, &mut self.memory, opcode, pc);
+                return Instructions::clr(
+                    &mut self.registers,
+                    &mut self.memory,
+                    opcode,
+                    pc,
+                    self.clr_read,
+                );
             }
 
             // NOT: 0100 0110 ssxx xxxx (where ss != 11)
@@ -836,6 +960,10 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             .field("memory", &self.memory)
             .field("halted", &self.halted)
             .field("cycles", &self.cycles)
+            .field("last_exception", &self.last_exception)
+            .field("clr_read", &self.clr_read)
+            .field("prefetch", &self.prefetch)
+            .field("prefetched", &self.prefetched)
             .finish()
     }
 }
@@ -0,0 +1,800 @@
+//! Debugger Support
+//!
+//! This module holds the breakpoints and watchpoints `Sbc::run` checks
+//! while executing, the reasons a run can stop, and the symbol table of the
+//! loaded program.
+//!
+//! ## Breakpoints
+//!
+//! A breakpoint stops execution before the instruction at its address
+//! runs. The first instruction of a run never triggers one, so running
+//! again resumes from a breakpoint.
+//!
+//! A breakpoint can have a condition (see `expression`), checked each time
+//! execution reaches it; a condition that can't be evaluated counts as
+//! true, so the error shows up. A skip count lets that many hits pass
+//! before the breakpoint stops execution.
+//!
+//! ## Watchpoints
+//!
+//! A watchpoint stops execution after an instruction reads or writes any
+//! byte of its address range. Only data accesses count; instruction fetches
+//! and host accesses between runs don't.
+//!
+//! ## Watch Expressions
+//!
+//! A watch expression (see `expression`) is evaluated against the machine
+//! whenever the host reports its state, so a value can be followed while
+//! stepping. It never stops execution; an expression that can't be
+//! evaluated reports its error instead of a value.
+//!
+//! ## Symbols
+//!
+//! Loading an assembled program installs its labels, relocated to the load
+//! address, so views like the disassembly can name addresses.
+//!
+//! Symbols from a map file (for code assembled elsewhere, like the ROM)
+//! merge into the same table, tagged with their source. A map file holds
+//! one symbol per line as `name value`, `name = value` or `name EQU value`,
+//! the value in hex (`$E00100` or `0xE00100`) or decimal; blank lines and
+//! lines starting with `;` or `*` are skipped. Each address has one name:
+//! a map symbol doesn't replace a name already there, while a program's
+//! labels replace map symbols at their addresses.
+
+use crate::expression::{Evaluation, Expression, Machine};
+use crate::registers::RegisterFile;
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Kind of memory access a watchpoint reacts to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WatchAccess {
+    /// Data reads
+    Read,
+    /// Writes
+    Write,
+    /// Both
+    ReadWrite,
+}
+
+impl WatchAccess {
+    /// Returns true if this kind includes an access (`write` = false for a
+    /// read)
+    #[must_use]
+    pub const fn matches(self, write: bool) -> bool {
+        match self {
+            Self::Read => !write,
+            Self::Write => write,
+            Self::ReadWrite => true,
+        }
+    }
+}
+
+/// A data access made by an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// First byte accessed
+    pub address: u32,
+    /// Number of bytes accessed
+    pub length: u32,
+    /// True for a write
+    pub write: bool,
+}
+
+/// An execution breakpoint
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Breakpoint {
+    /// Breakpoint id
+    pub id: u32,
+    /// Address of the instruction to stop at
+    pub address: u32,
+    /// Condition that must hold for the breakpoint to hit
+    pub condition: Option<Expression>,
+    /// Number of hits to let pass before stopping
+    pub skip_count: u64,
+    /// Whether the breakpoint is armed
+    pub enabled: bool,
+    /// Number of times execution reached the breakpoint with its condition
+    /// holding
+    pub hits: u64,
+}
+
+/// A data watchpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct Watchpoint {
+    /// Watchpoint id
+    pub id: u32,
+    /// First watched byte
+    pub address: u32,
+    /// Number of watched bytes
+    pub length: u32,
+    /// Accesses that trigger the watchpoint
+    pub access: WatchAccess,
+    /// Whether the watchpoint is armed
+    pub enabled: bool,
+    /// Number of times the watchpoint stopped execution
+    pub hits: u64,
+}
+
+/// A watch expression
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct WatchExpression {
+    /// Watch id
+    pub id: u32,
+    /// Name to show the value under
+    pub name: String,
+    /// Expression to evaluate
+    pub expression: Expression,
+}
+
+/// The current value of a watch expression (see `Debugger::watch_values`)
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct WatchValue {
+    /// Watch id
+    pub id: u32,
+    /// Name to show the value under
+    pub name: String,
+    /// Value of the expression, if it could be evaluated
+    pub value: Option<i64>,
+    /// Why it couldn't be evaluated
+    pub error: Option<String>,
+}
+
+/// Where a symbol came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SymbolSource {
+    /// The loaded program's labels
+    Program,
+    /// A map file
+    MapFile,
+}
+
+/// An entry of the symbol table
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Symbol {
+    /// Label name
+    pub name: String,
+    /// Address the label names
+    pub address: u32,
+    /// Where the symbol came from
+    pub source: SymbolSource,
+}
+
+/// The symbol closest at or below an address (see `Debugger::lookup`)
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolMatch {
+    /// The symbol
+    #[serde(flatten)]
+    pub symbol: Symbol,
+    /// How far past the symbol the address is
+    pub offset: u32,
+    /// The address as `label` or `label+$offset`
+    pub label: String,
+}
+
+/// A map file line that isn't a symbol
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolMapError {
+    /// Line number (1-based)
+    pub line: usize,
+    /// What's wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for SymbolMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SymbolMapError {}
+
+/// Why `Sbc::run` returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum StopReason {
+    /// The cycle budget ran out
+    BudgetExhausted,
+    /// A batch of steps executed all its instructions
+    StepsCompleted,
+    /// The host paused a background run
+    Paused,
+    /// The CPU halted (STOP, or an instruction it couldn't execute)
+    Halted,
+    /// Execution reached a breakpoint
+    Breakpoint { id: u32, address: u32 },
+    /// An instruction accessed a watched address
+    Watchpoint {
+        id: u32,
+        address: u32,
+        access: WatchAccess,
+    },
+    /// An exception jumped through a vector that was never set up ($0 or
+    /// $FFFFFFFF); `pc` is where the exception was taken
+    UninitializedVector { vector: u8, pc: u32 },
+    /// The program called the Exit syscall (TRAP #0) with `code` in D0
+    Exit { code: u32 },
+}
+
+/// Outcome of `Sbc::run`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct RunResult {
+    /// Instructions executed
+    pub instructions: u64,
+    /// Cycles executed
+    pub cycles: u64,
+    /// Why execution stopped
+    pub stop: StopReason,
+    /// Program counter after the run
+    pub pc: u32,
+}
+
+/// Outcome of `Sbc::step_n`
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct StepBatch {
+    /// Instructions and cycles executed, and why the batch stopped
+    pub run: RunResult,
+    /// PCs of the executed instructions, in order (capped)
+    pub pcs: Vec<u32>,
+    /// UART output produced by the batch
+    pub output: Vec<u8>,
+}
+
+/// Breakpoint, watchpoint and symbol tables
+#[derive(Clone, Debug, Default)]
+pub struct Debugger {
+    /// Breakpoints in creation order
+    breakpoints: Vec<Breakpoint>,
+    /// Watchpoints in creation order
+    watchpoints: Vec<Watchpoint>,
+    /// Watch expressions in creation order
+    watches: Vec<WatchExpression>,
+    /// Next id to hand out (shared by all three tables)
+    next_id: u32,
+    /// Label names by address
+    symbols: BTreeMap<u32, String>,
+    /// Addresses whose symbol came from a map file
+    map_symbols: BTreeSet<u32>,
+}
+
+impl Debugger {
+    /// Creates empty tables
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            watches: Vec::new(),
+            next_id: 1,
+            symbols: BTreeMap::new(),
+            map_symbols: BTreeSet::new(),
+        }
+    }
+
+    /// Adds an enabled breakpoint, returning its id
+    #[cfg(test)]
+    pub fn add_breakpoint(&mut self, address: u32) -> u32 {
+        self.add_conditional_breakpoint(address, None, 0)
+    }
+
+    /// Adds an enabled breakpoint that stops once `condition` (if any) has
+    /// held `skip_count` times, returning its id
+    pub fn add_conditional_breakpoint(
+        &mut self,
+        address: u32,
+        condition: Option<Expression>,
+        skip_count: u64,
+    ) -> u32 {
+        let id = self.allocate_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            address,
+            condition,
+            skip_count,
+            enabled: true,
+            hits: 0,
+        });
+        id
+    }
+
+    /// Adds an enabled watchpoint, returning its id
+    // Allow dead code: no command sets watchpoints yet, only the tests.
+    #[allow(dead_code)]
+    pub fn add_watchpoint(&mut self, address: u32, length: u32, access: WatchAccess) -> u32 {
+        let id = self.allocate_id();
+        self.watchpoints.push(Watchpoint {
+            id,
+            address,
+            length: length.max(1),
+            access,
+            enabled: true,
+            hits: 0,
+        });
+        id
+    }
+
+    /// Removes a breakpoint or watchpoint, returning false if the id is
+    /// unknown
+    pub fn remove(&mut self, id: u32) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|bp| bp.id != id);
+        self.watchpoints.retain(|wp| wp.id != id);
+        self.breakpoints.len() + self.watchpoints.len() != count
+    }
+
+    /// Enables or disables a breakpoint or watchpoint, returning false if
+    /// the id is unknown
+    pub fn set_enabled(&mut self, id: u32, enabled: bool) -> bool {
+        if let Some(bp) = self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+            bp.enabled = enabled;
+            true
+        } else if let Some(wp) = self.watchpoints.iter_mut().find(|wp| wp.id == id) {
+            wp.enabled = enabled;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the breakpoints
+    #[must_use]
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Returns the watchpoints
+    #[cfg(test)]
+    #[must_use]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns true if any watchpoint is enabled
+    #[must_use]
+    pub fn watching(&self) -> bool {
+        self.watchpoints.iter().any(|wp| wp.enabled)
+    }
+
+    /// Checks the enabled breakpoints at `pc`, counting the hits, and
+    /// returns the first one to stop execution
+    pub fn check_breakpoint(
+        &mut self,
+        pc: u32,
+        registers: &RegisterFile,
+        peek: &dyn Fn(u32) -> Option<u8>,
+    ) -> Option<StopReason> {
+        let machine = Machine {
+            registers,
+            peek,
+            symbols: &self.symbols,
+        };
+        let mut stop = None;
+        for bp in &mut self.breakpoints {
+            if !bp.enabled || bp.address != pc {
+                continue;
+            }
+            let holds = bp
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.evaluate(&machine) != Ok(0));
+            if holds {
+                bp.hits += 1;
+                if bp.hits > bp.skip_count && stop.is_none() {
+                    stop = Some(StopReason::Breakpoint {
+                        id: bp.id,
+                        address: bp.address,
+                    });
+                }
+            }
+        }
+        stop
+    }
+
+    /// Checks an instruction's data accesses against the enabled
+    /// watchpoints, counting the hit
+    ///
+    /// The reported address is the first watched byte accessed.
+    pub fn check_watchpoints(&mut self, accesses: &[MemoryAccess]) -> Option<StopReason> {
+        for access in accesses {
+            for wp in self.watchpoints.iter_mut().filter(|wp| wp.enabled) {
+                if !wp.access.matches(access.write) {
+                    continue;
+                }
+                let start = access.address.max(wp.address);
+                let end = (access.address + access.length).min(wp.address + wp.length);
+                if start < end {
+                    wp.hits += 1;
+                    return Some(StopReason::Watchpoint {
+                        id: wp.id,
+                        address: start,
+                        access: if access.write {
+                            WatchAccess::Write
+                        } else {
+                            WatchAccess::Read
+                        },
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Adds a watch expression, returning its id
+    pub fn add_watch(&mut self, name: String, expression: Expression) -> u32 {
+        let id = self.allocate_id();
+        self.watches.push(WatchExpression {
+            id,
+            name,
+            expression,
+        });
+        id
+    }
+
+    /// Removes a watch expression, returning false if the id is unknown
+    pub fn remove_watch(&mut self, id: u32) -> bool {
+        let count = self.watches.len();
+        self.watches.retain(|watch| watch.id != id);
+        self.watches.len() != count
+    }
+
+    /// Returns the watch expressions in creation order
+    #[must_use]
+    pub fn watches(&self) -> &[WatchExpression] {
+        &self.watches
+    }
+
+    /// Evaluates every watch expression against the machine
+    #[must_use]
+    pub fn watch_values(
+        &self,
+        registers: &RegisterFile,
+        peek: &dyn Fn(u32) -> Option<u8>,
+    ) -> Vec<WatchValue> {
+        let machine = Machine {
+            registers,
+            peek,
+            symbols: &self.symbols,
+        };
+        self.watches
+            .iter()
+            .map(|watch| {
+                let result = watch.expression.evaluate(&machine);
+                WatchValue {
+                    id: watch.id,
+                    name: watch.name.clone(),
+                    value: result.as_ref().ok().copied(),
+                    error: result.err(),
+                }
+            })
+            .collect()
+    }
+
+    /// Evaluates an expression once against the machine
+    ///
+    /// # Errors
+    /// Fails like `Expression::evaluate`.
+    pub fn evaluate(
+        &self,
+        expression: &Expression,
+        registers: &RegisterFile,
+        peek: &dyn Fn(u32) -> Option<u8>,
+    ) -> Result<Evaluation, String> {
+        expression.evaluation(&Machine {
+            registers,
+            peek,
+            symbols: &self.symbols,
+        })
+    }
+
+    /// Returns up to `limit` symbol names that look like `name` (a typo or
+    /// different case of it, or containing it), closest first
+    #[must_use]
+    pub fn similar_symbols(&self, name: &str, limit: usize) -> Vec<String> {
+        similar_names(name, self.symbols.values().map(String::as_str), limit)
+    }
+
+    /// Replaces the program's symbols with `(name, address)` pairs, keeping
+    /// map file symbols at other addresses
+    ///
+    /// When several labels share an address, the first one names it.
+    pub fn set_symbols(&mut self, symbols: impl IntoIterator<Item = (String, u32)>) {
+        let map_symbols = &self.map_symbols;
+        self.symbols
+            .retain(|address, _| map_symbols.contains(address));
+        let mut placed = BTreeSet::new();
+        for (name, address) in symbols {
+            if placed.insert(address) {
+                self.map_symbols.remove(&address);
+                self.symbols.insert(address, name);
+            }
+        }
+    }
+
+    /// Merges the symbols of a map file (see the module docs), returning
+    /// how many were added
+    ///
+    /// Nothing is added if any line is malformed.
+    pub fn load_symbol_map(&mut self, text: &str) -> Result<usize, SymbolMapError> {
+        let symbols = parse_symbol_map(text)?;
+        let mut added = 0;
+        for (name, address) in symbols {
+            if let Entry::Vacant(entry) = self.symbols.entry(address) {
+                entry.insert(name);
+                self.map_symbols.insert(address);
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Returns the symbols whose names contain `filter` (ignoring case), or
+    /// all of them, in address order
+    #[must_use]
+    pub fn symbols(&self, filter: Option<&str>) -> Vec<Symbol> {
+        let filter = filter.map(str::to_ascii_lowercase);
+        self.symbols
+            .iter()
+            .filter(|(_, name)| {
+                filter
+                    .as_deref()
+                    .is_none_or(|filter| name.to_ascii_lowercase().contains(filter))
+            })
+            .map(|(&address, name)| self.symbol(address, name))
+            .collect()
+    }
+
+    /// Returns the closest symbol at or below `address`, with the offset
+    #[must_use]
+    pub fn lookup(&self, address: u32) -> Option<SymbolMatch> {
+        let (&at, name) = self.symbols.range(..=address).next_back()?;
+        Some(SymbolMatch {
+            symbol: self.symbol(at, name),
+            offset: address - at,
+            label: self.describe(address)?,
+        })
+    }
+
+    /// Builds the table entry for the symbol at `address`
+    fn symbol(&self, address: u32, name: &str) -> Symbol {
+        let source = if self.map_symbols.contains(&address) {
+            SymbolSource::MapFile
+        } else {
+            SymbolSource::Program
+        };
+        Symbol {
+            name: name.to_string(),
+            address,
+            source,
+        }
+    }
+
+    /// Returns the label at `address`, if any
+    #[must_use]
+    pub fn symbol_at(&self, address: u32) -> Option<&str> {
+        self.symbols.get(&address).map(String::as_str)
+    }
+
+    /// Returns the address of the label `name`, if the table has it
+    #[must_use]
+    pub fn address_of(&self, name: &str) -> Option<u32> {
+        self.symbols
+            .iter()
+            .find(|(_, symbol)| *symbol == name)
+            .map(|(&address, _)| address)
+    }
+
+    /// Returns the closest label at or below `address` and how far past it
+    /// `address` is
+    #[must_use]
+    pub fn nearest_symbol(&self, address: u32) -> Option<(&str, u32)> {
+        self.symbols
+            .range(..=address)
+            .next_back()
+            .map(|(&at, name)| (name.as_str(), address - at))
+    }
+
+    /// Names `address` after the closest label at or below it, as `label`
+    /// or `label+$offset`
+    #[must_use]
+    pub fn describe(&self, address: u32) -> Option<String> {
+        let (name, offset) = self.nearest_symbol(address)?;
+        Some(if offset == 0 {
+            name.to_string()
+        } else {
+            format!("{name}+${offset:X}")
+        })
+    }
+
+    /// Hands out the next id
+    const fn allocate_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+/// Returns up to `limit` of `names` that look like `name` (a typo or
+/// different case of it, or containing it), closest first
+pub fn similar_names<'a>(
+    name: &str,
+    names: impl IntoIterator<Item = &'a str>,
+    limit: usize,
+) -> Vec<String> {
+    let name = name.to_ascii_lowercase();
+    // Up to a third of the name may be mistyped
+    let allowed = name.len().div_ceil(3);
+    let mut matches: Vec<(usize, &str)> = names
+        .into_iter()
+        .filter_map(|symbol| {
+            let lower = symbol.to_ascii_lowercase();
+            let distance = edit_distance(&name, &lower);
+            if distance <= allowed {
+                Some((distance, symbol))
+            } else {
+                lower.contains(&name).then_some((distance, symbol))
+            }
+        })
+        .collect();
+    matches.sort_unstable();
+    matches.dedup();
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, symbol)| symbol.to_string())
+        .collect()
+}
+
+/// Counts the single-character insertions, deletions and substitutions
+/// that turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Parses a map file into `(name, address)` pairs
+fn parse_symbol_map(text: &str) -> Result<Vec<(String, u32)>, SymbolMapError> {
+    let mut symbols = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('*') {
+            continue;
+        }
+        let error = |message: String| SymbolMapError {
+            line: index + 1,
+            message,
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (name, value) = match fields.as_slice() {
+            [name, value] => (*name, *value),
+            [name, separator, value]
+                if *separator == "=" || separator.eq_ignore_ascii_case("EQU") =>
+            {
+                (*name, *value)
+            }
+            _ => return Err(error(format!("expected 'name value': {line}"))),
+        };
+        let address = if let Some(hex) = value.strip_prefix('$') {
+            u32::from_str_radix(hex, 16)
+        } else if let Some(hex) = value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix("0X"))
+        {
+            u32::from_str_radix(hex, 16)
+        } else {
+            value.parse()
+        }
+        .map_err(|_| error(format!("invalid address: {value}")))?;
+        symbols.push((name.to_string(), address));
+    }
+    Ok(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debugger_watchpoint_ranges() {
+        let mut debugger = Debugger::new();
+        let id = debugger.add_watchpoint(0x1000, 4, WatchAccess::Write);
+        let read = MemoryAccess {
+            address: 0x1002,
+            length: 2,
+            write: false,
+        };
+        let write = MemoryAccess {
+            address: 0x0FFE,
+            length: 4,
+            write: true,
+        };
+
+        assert_eq!(debugger.check_watchpoints(&[read]), None);
+        assert_eq!(
+            debugger.check_watchpoints(&[read, write]),
+            Some(StopReason::Watchpoint {
+                id,
+                address: 0x1000,
+                access: WatchAccess::Write,
+            })
+        );
+        assert_eq!(debugger.watchpoints()[0].hits, 1);
+
+        assert!(debugger.set_enabled(id, false));
+        assert!(!debugger.watching());
+        assert_eq!(debugger.check_watchpoints(&[write]), None);
+        assert!(debugger.remove(id));
+        assert!(!debugger.remove(id));
+    }
+
+    #[test]
+    fn test_debugger_symbol_map_merges() {
+        let mut debugger = Debugger::new();
+        debugger.set_symbols([("main".to_string(), 0x00E0_0100)]);
+        let map = "; ROM entry points\noutch EQU $0400\ninch = 0x0410\nalias 14680320\n";
+        assert_eq!(debugger.load_symbol_map(map), Ok(2));
+
+        let names: Vec<_> = debugger
+            .symbols(None)
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.source))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("outch".to_string(), SymbolSource::MapFile),
+                ("inch".to_string(), SymbolSource::MapFile),
+                ("main".to_string(), SymbolSource::Program),
+            ]
+        );
+        assert_eq!(debugger.symbols(Some("CH")).len(), 2);
+        assert_eq!(debugger.lookup(0x0404).unwrap().label, "outch+$4");
+
+        // A program's labels replace its old ones and keep the map's
+        debugger.set_symbols([("start".to_string(), 0x00E0_0200)]);
+        assert_eq!(debugger.symbols(None).len(), 3);
+        assert_eq!(debugger.symbol_at(0x00E0_0100), None);
+
+        assert_eq!(
+            debugger.load_symbol_map("ok $10\nbroken\n"),
+            Err(SymbolMapError {
+                line: 2,
+                message: "expected 'name value': broken".to_string(),
+            })
+        );
+        assert_eq!(debugger.symbol_at(0x10), None);
+    }
+
+    #[test]
+    fn test_debugger_similar_symbols() {
+        let mut debugger = Debugger::new();
+        debugger.set_symbols([
+            ("start".to_string(), 0x100),
+            ("Restart".to_string(), 0x110),
+            ("stop".to_string(), 0x120),
+            ("print_string".to_string(), 0x130),
+        ]);
+        assert_eq!(debugger.similar_symbols("strat", 5), ["start"]);
+        assert_eq!(debugger.similar_symbols("START", 5), ["start", "Restart"]);
+        assert_eq!(debugger.similar_symbols("string", 5), ["print_string"]);
+        assert_eq!(debugger.similar_symbols("st", 1), ["stop"]);
+        assert!(debugger.similar_symbols("xyzzy", 5).is_empty());
+        assert_eq!(debugger.address_of("stop"), Some(0x120));
+        assert_eq!(debugger.address_of("STOP"), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
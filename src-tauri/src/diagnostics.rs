@@ -0,0 +1,135 @@
+//! Diagnostics Report
+//!
+//! This module defines the report `Sbc::diagnostics` builds for bug reports:
+//! the memory map, the expansion peripherals, the ROM's CRC-32, the CPU
+//! options, the cycle counters and latched fault information. Building it
+//! only reads machine state, so it can be called at any time.
+//!
+//! ## Faults
+//!
+//! - **Bus fault**: the last address that selected more than one device
+//!   (see the address decoding in `sbc`). The board has no bus error logic,
+//!   so such accesses silently read open bus; the address stays latched
+//!   until the machine is rebuilt.
+//! - **Watchdog reset**: the watchdog's WDRF flag, set when its timeout
+//!   reset the machine.
+
+use crate::config::SbcConfig;
+
+/// CPU model the emulator implements
+pub const CPU_MODEL: &str = "MC68HC000";
+
+/// A region of the memory map
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct MapRegion {
+    /// Region name
+    pub name: &'static str,
+    /// First address
+    pub start: u32,
+    /// Last address (inclusive)
+    pub end: u32,
+}
+
+/// An expansion bus peripheral
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct PeripheralInfo {
+    /// Peripheral name
+    pub name: &'static str,
+    /// Base address of its register slot
+    pub base: u32,
+    /// False if the configuration left its slot open
+    pub enabled: bool,
+}
+
+/// ROM image information
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RomInfo {
+    /// The embedded ROM is loaded
+    pub embedded: bool,
+    /// Image file the configuration names
+    pub path: Option<String>,
+    /// ROM size in bytes (images are padded to it)
+    pub size: u32,
+    /// CRC-32 of the whole ROM
+    pub crc32: u32,
+}
+
+/// CPU model and behavioral options
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuInfo {
+    /// CPU model
+    pub model: &'static str,
+    /// Clock in Hz
+    pub clock_hz: u32,
+    /// CLR reads its destination before clearing it
+    pub clr_read: bool,
+    /// The 68000 prefetch is emulated
+    pub prefetch: bool,
+    /// The CPU is halted
+    pub halted: bool,
+}
+
+/// Latched fault information
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultInfo {
+    /// Last address that selected more than one device
+    pub last_bus_fault: Option<u32>,
+    /// The last reset was caused by the watchdog
+    pub watchdog_reset: bool,
+}
+
+/// Machine diagnostics report
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    /// Emulator version
+    pub version: &'static str,
+    /// CPU model and options
+    pub cpu: CpuInfo,
+    /// Populated RAM in bytes
+    pub ram_size: u32,
+    /// ROM image
+    pub rom: RomInfo,
+    /// `CompactFlash` card inserted
+    pub cf_inserted: bool,
+    /// Memory map, in address order
+    pub memory_map: Vec<MapRegion>,
+    /// Expansion bus peripherals, in slot order
+    pub peripherals: Vec<PeripheralInfo>,
+    /// CPU cycles since the last reset
+    pub cycles: u64,
+    /// Master cycles since power-on (see `Sbc::master_cycles`)
+    pub master_cycles: u64,
+    /// Latched faults
+    pub faults: FaultInfo,
+    /// Active configuration
+    pub config: SbcConfig,
+}
+
+/// Computes the CRC-32 (IEEE 802.3, as used by zip) of `data`
+#[must_use]
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
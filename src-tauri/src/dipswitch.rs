@@ -0,0 +1,55 @@
+//! DIP Switch Emulation
+//!
+//! This module emulates an 8-position DIP switch on the expansion bus. The
+//! ROM reads it at boot to pick options such as the console baud rate or a
+//! self-test; the host sets the positions.
+//!
+//! ## Register Map (offsets from $800120)
+//!
+//! | Offset | Read             | Write   |
+//! |--------|------------------|---------|
+//! | 0      | Switch positions | Ignored |
+//!
+//! Bit n is switch n + 1; a set bit means the switch is ON. Reads have no
+//! side effects, and a machine reset leaves the switches where they are.
+
+/// DIP switch register offsets
+pub mod regs {
+    /// Switch positions
+    pub const SWITCHES: u32 = 0;
+}
+
+/// 8-position DIP switch
+#[derive(Clone, Copy, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct DipSwitch {
+    /// Switch positions (1 = ON)
+    value: u8,
+}
+
+impl DipSwitch {
+    /// Creates a DIP switch with the given positions
+    #[must_use]
+    pub const fn new(value: u8) -> Self {
+        Self { value }
+    }
+
+    /// Returns the switch positions
+    #[must_use]
+    pub const fn value(&self) -> u8 {
+        self.value
+    }
+
+    /// Sets the switch positions
+    pub const fn set(&mut self, value: u8) {
+        self.value = value;
+    }
+
+    /// Reads a DIP switch register
+    #[must_use]
+    pub const fn read(&self, offset: u32) -> u8 {
+        match offset {
+            regs::SWITCHES => self.value,
+            _ => 0xFF,
+        }
+    }
+}
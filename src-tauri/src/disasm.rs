@@ -0,0 +1,659 @@
+//! M68K Disassembler
+//!
+//! This module decodes 68000 machine code back into assembler syntax for
+//! the debugger's disassembly view. Output follows the style of the ROM
+//! sources: lowercase mnemonics with a size suffix, lowercase registers
+//! and `$` hex constants, so a line reads like the source it came from.
+//!
+//! Decoding follows the same opcode priority as `Cpu::execute`. Words that
+//! don't decode to a 68000 instruction (including Line-A and Line-F) are
+//! shown as `dc.w` data.
+//!
+//! The decoder reads instruction words through a caller-supplied fetch
+//! function, so it never touches device registers; a fetch that returns
+//! `None` ends decoding (see `Sbc::disassemble`).
+
+/// A decoded instruction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    /// Length in bytes, including extension words
+    pub length: u32,
+    /// Mnemonic with size suffix (e.g. `move.l`)
+    pub mnemonic: String,
+    /// Comma-separated operands (empty if none)
+    pub operands: String,
+}
+
+/// Condition code names, indexed by the 4-bit condition field
+const CONDITIONS: [&str; 16] = [
+    "t", "f", "hi", "ls", "cc", "cs", "ne", "eq", "vc", "vs", "pl", "mi", "ge", "lt", "gt", "le",
+];
+
+/// Why an instruction couldn't be decoded
+enum Fail {
+    /// A word couldn't be fetched
+    Fetch,
+    /// The bits don't encode a 68000 instruction
+    Invalid,
+}
+
+type Decode<T> = Result<T, Fail>;
+
+/// Operand size of a sized instruction
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Size {
+    Byte,
+    Word,
+    Long,
+}
+
+impl Size {
+    /// Decodes the usual 2-bit size field (00 = byte, 01 = word, 10 = long)
+    const fn from_bits(bits: u16) -> Decode<Self> {
+        match bits & 3 {
+            0 => Ok(Self::Byte),
+            1 => Ok(Self::Word),
+            2 => Ok(Self::Long),
+            _ => Err(Fail::Invalid),
+        }
+    }
+
+    const fn suffix(self) -> &'static str {
+        match self {
+            Self::Byte => ".b",
+            Self::Word => ".w",
+            Self::Long => ".l",
+        }
+    }
+}
+
+/// Formats a value as `$` hex
+fn hex(value: u32) -> String {
+    format!("${value:X}")
+}
+
+/// Formats a signed displacement as `$` hex
+fn signed_hex(value: i32) -> String {
+    if value < 0 {
+        format!("-${:X}", value.unsigned_abs())
+    } else {
+        format!("${value:X}")
+    }
+}
+
+/// Formats a register list mask (bit 0 = d0 ... bit 15 = a7) as ranges,
+/// e.g. `d0-d3/a0/a6`
+fn register_list(mask: u16) -> String {
+    let mut groups = Vec::new();
+    for (bank, prefix) in [(0, 'd'), (8, 'a')] {
+        let mut reg = 0;
+        while reg < 8 {
+            if mask & (1 << (bank + reg)) == 0 {
+                reg += 1;
+                continue;
+            }
+            let first = reg;
+            while reg < 8 && mask & (1 << (bank + reg)) != 0 {
+                reg += 1;
+            }
+            let last = reg - 1;
+            groups.push(if first == last {
+                format!("{prefix}{first}")
+            } else {
+                format!("{prefix}{first}-{prefix}{last}")
+            });
+        }
+    }
+    groups.join("/")
+}
+
+/// Decoder state: the fetch function and the address of the next word
+struct Reader<F> {
+    fetch: F,
+    next: u32,
+}
+
+impl<F: FnMut(u32) -> Option<u16>> Reader<F> {
+    fn word(&mut self) -> Decode<u16> {
+        let word = (self.fetch)(self.next).ok_or(Fail::Fetch)?;
+        self.next = self.next.wrapping_add(2);
+        Ok(word)
+    }
+
+    fn long(&mut self) -> Decode<u32> {
+        let high = self.word()?;
+        let low = self.word()?;
+        Ok(u32::from(high) << 16 | u32::from(low))
+    }
+
+    /// Formats a brief extension word's index register and displacement
+    fn index(&mut self) -> Decode<(i32, String)> {
+        let ext = self.word()?;
+        let kind = if ext & 0x8000 != 0 { 'a' } else { 'd' };
+        let size = if ext & 0x0800 != 0 { 'l' } else { 'w' };
+        let reg = (ext >> 12) & 7;
+        Ok((i32::from(ext as u8 as i8), format!("{kind}{reg}.{size}")))
+    }
+
+    /// Decodes an effective address from its mode and register fields,
+    /// reading any extension words
+    fn ea(&mut self, mode: u16, reg: u16, size: Size) -> Decode<String> {
+        Ok(match (mode & 7, reg & 7) {
+            (0, r) => format!("d{r}"),
+            (1, r) => format!("a{r}"),
+            (2, r) => format!("(a{r})"),
+            (3, r) => format!("(a{r})+"),
+            (4, r) => format!("-(a{r})"),
+            (5, r) => {
+                let disp = self.word()? as i16;
+                format!("{}(a{r})", signed_hex(i32::from(disp)))
+            }
+            (6, r) => {
+                let (disp, index) = self.index()?;
+                format!("{}(a{r},{index})", signed_hex(disp))
+            }
+            (7, 0) => format!("{}.w", hex(u32::from(self.word()?))),
+            (7, 1) => hex(self.long()?),
+            (7, 2) => {
+                let base = self.next;
+                let disp = self.word()? as i16;
+                format!("{}(pc)", hex(base.wrapping_add(disp as u32) & 0x00FF_FFFF))
+            }
+            (7, 3) => {
+                let base = self.next;
+                let (disp, index) = self.index()?;
+                format!(
+                    "{}(pc,{index})",
+                    hex(base.wrapping_add(disp as u32) & 0x00FF_FFFF)
+                )
+            }
+            (7, 4) => match size {
+                Size::Byte => format!("#{}", hex(u32::from(self.word()? & 0xFF))),
+                Size::Word => format!("#{}", hex(u32::from(self.word()?))),
+                Size::Long => format!("#{}", hex(self.long()?)),
+            },
+            _ => return Err(Fail::Invalid),
+        })
+    }
+
+    /// Decodes the effective address in the low six bits of `opcode`
+    fn src(&mut self, opcode: u16, size: Size) -> Decode<String> {
+        self.ea(opcode >> 3, opcode, size)
+    }
+
+    /// Computes a branch target from a displacement relative to `base`
+    const fn target(base: u32, disp: i32) -> u32 {
+        base.wrapping_add(disp as u32) & 0x00FF_FFFF
+    }
+}
+
+/// Decodes the instruction at `address`
+///
+/// Returns `None` if a word of the instruction couldn't be fetched.
+pub fn decode(address: u32, fetch: impl FnMut(u32) -> Option<u16>) -> Option<Instruction> {
+    let mut reader = Reader {
+        fetch,
+        next: address,
+    };
+    let opcode = reader.word().ok()?;
+    match decode_opcode(&mut reader, address, opcode) {
+        Ok((mnemonic, operands)) => Some(Instruction {
+            length: reader.next.wrapping_sub(address),
+            mnemonic,
+            operands,
+        }),
+        Err(Fail::Fetch) => None,
+        Err(Fail::Invalid) => Some(Instruction {
+            length: 2,
+            mnemonic: "dc.w".to_string(),
+            operands: hex(u32::from(opcode)),
+        }),
+    }
+}
+
+/// Builds a mnemonic/operands pair
+fn op(mnemonic: impl Into<String>, operands: impl Into<String>) -> (String, String) {
+    (mnemonic.into(), operands.into())
+}
+
+/// Decodes the instruction whose opcode word is at `address`
+fn decode_opcode<F: FnMut(u32) -> Option<u16>>(
+    r: &mut Reader<F>,
+    address: u32,
+    opcode: u16,
+) -> Decode<(String, String)> {
+    let reg_hi = (opcode >> 9) & 7;
+    let reg_lo = opcode & 7;
+    let size_bits = (opcode >> 6) & 3;
+
+    match opcode >> 12 {
+        0x0 => {
+            let immediate_ops = [(0x0000, "ori"), (0x0200, "andi"), (0x0A00, "eori")];
+            for (base, name) in immediate_ops {
+                if opcode == base | 0x003C {
+                    let imm = r.word()? & 0xFF;
+                    return Ok(op(
+                        format!("{name}.b"),
+                        format!("#{},ccr", hex(u32::from(imm))),
+                    ));
+                }
+                if opcode == base | 0x007C {
+                    let imm = r.word()?;
+                    return Ok(op(
+                        format!("{name}.w"),
+                        format!("#{},sr", hex(u32::from(imm))),
+                    ));
+                }
+            }
+            let name = match opcode & 0xFF00 {
+                0x0000 => Some("ori"),
+                0x0200 => Some("andi"),
+                0x0400 => Some("subi"),
+                0x0600 => Some("addi"),
+                0x0A00 => Some("eori"),
+                0x0C00 => Some("cmpi"),
+                _ => None,
+            };
+            if let Some(name) = name {
+                let size = Size::from_bits(size_bits)?;
+                let imm = r.ea(7, 4, size)?;
+                let dst = r.src(opcode, size)?;
+                return Ok(op(
+                    format!("{name}{}", size.suffix()),
+                    format!("{imm},{dst}"),
+                ));
+            }
+            if opcode & 0xF138 == 0x0108 {
+                let size = if opcode & 0x0040 != 0 { ".l" } else { ".w" };
+                let disp = r.word()? as i16;
+                let mem = format!("{}(a{reg_lo})", signed_hex(i32::from(disp)));
+                let operands = if opcode & 0x0080 != 0 {
+                    format!("d{reg_hi},{mem}")
+                } else {
+                    format!("{mem},d{reg_hi}")
+                };
+                return Ok(op(format!("movep{size}"), operands));
+            }
+            let bit_op = ["btst", "bchg", "bclr", "bset"][size_bits as usize];
+            if opcode & 0xF100 == 0x0100 {
+                let dst = r.src(opcode, Size::Byte)?;
+                return Ok(op(bit_op, format!("d{reg_hi},{dst}")));
+            }
+            if opcode & 0xFF00 == 0x0800 {
+                let bit = r.word()? & 0xFF;
+                let dst = r.src(opcode, Size::Byte)?;
+                return Ok(op(bit_op, format!("#{bit},{dst}")));
+            }
+            Err(Fail::Invalid)
+        }
+        0x1..=0x3 => {
+            let size = match opcode >> 12 {
+                1 => Size::Byte,
+                3 => Size::Word,
+                _ => Size::Long,
+            };
+            let dst_mode = (opcode >> 6) & 7;
+            if dst_mode == 1 && size == Size::Byte {
+                return Err(Fail::Invalid);
+            }
+            let src = r.src(opcode, size)?;
+            if dst_mode == 7 && reg_hi > 1 {
+                return Err(Fail::Invalid);
+            }
+            let dst = r.ea(dst_mode, reg_hi, size)?;
+            let name = if dst_mode == 1 { "movea" } else { "move" };
+            Ok(op(
+                format!("{name}{}", size.suffix()),
+                format!("{src},{dst}"),
+            ))
+        }
+        0x4 => decode_misc(r, opcode),
+        0x5 => {
+            let cc = CONDITIONS[((opcode >> 8) & 0xF) as usize];
+            if opcode & 0xF0F8 == 0x50C8 {
+                let base = r.next;
+                let disp = r.word()? as i16;
+                let name = if cc == "f" {
+                    "dbra".to_string()
+                } else {
+                    format!("db{cc}")
+                };
+                let target = Reader::<F>::target(base, i32::from(disp));
+                return Ok(op(name, format!("d{reg_lo},{}", hex(target))));
+            }
+            if size_bits == 3 {
+                let dst = r.src(opcode, Size::Byte)?;
+                return Ok(op(format!("s{cc}"), dst));
+            }
+            let size = Size::from_bits(size_bits)?;
+            let data = if reg_hi == 0 { 8 } else { reg_hi };
+            let name = if opcode & 0x0100 != 0 { "subq" } else { "addq" };
+            let dst = r.src(opcode, size)?;
+            Ok(op(
+                format!("{name}{}", size.suffix()),
+                format!("#{data},{dst}"),
+            ))
+        }
+        0x6 => {
+            let base = address.wrapping_add(2);
+            let name = match (opcode >> 8) & 0xF {
+                0 => "bra".to_string(),
+                1 => "bsr".to_string(),
+                cc => format!("b{}", CONDITIONS[cc as usize]),
+            };
+            let disp8 = opcode as u8 as i8;
+            let (suffix, disp) = if disp8 == 0 {
+                (".w", i32::from(r.word()? as i16))
+            } else {
+                (".s", i32::from(disp8))
+            };
+            let target = Reader::<F>::target(base, disp);
+            Ok(op(format!("{name}{suffix}"), hex(target)))
+        }
+        0x7 => {
+            if opcode & 0x0100 != 0 {
+                return Err(Fail::Invalid);
+            }
+            let data = opcode as u8 as i8;
+            Ok(op("moveq", format!("#{data},d{reg_hi}")))
+        }
+        0x8 | 0xC => {
+            let (bcd, word_op, logic) = if opcode >> 12 == 0x8 {
+                ("sbcd", ["divu", "divs"], "or")
+            } else {
+                ("abcd", ["mulu", "muls"], "and")
+            };
+            if opcode & 0xF1F0 == (opcode & 0xF000) | 0x0100 {
+                let operands = if opcode & 0x0008 != 0 {
+                    format!("-(a{reg_lo}),-(a{reg_hi})")
+                } else {
+                    format!("d{reg_lo},d{reg_hi}")
+                };
+                return Ok(op(bcd, operands));
+            }
+            if opcode >> 12 == 0xC && opcode & 0x0130 == 0x0100 {
+                let operands = match (opcode >> 3) & 0x1F {
+                    0x08 => format!("d{reg_hi},d{reg_lo}"),
+                    0x09 => format!("a{reg_hi},a{reg_lo}"),
+                    0x11 => format!("d{reg_hi},a{reg_lo}"),
+                    _ => String::new(),
+                };
+                if !operands.is_empty() {
+                    return Ok(op("exg", operands));
+                }
+            }
+            if size_bits == 3 {
+                let name = word_op[usize::from(opcode & 0x0100 != 0)];
+                let src = r.src(opcode, Size::Word)?;
+                return Ok(op(format!("{name}.w"), format!("{src},d{reg_hi}")));
+            }
+            arithmetic(r, opcode, logic)
+        }
+        0x9 | 0xD => {
+            let (name, extended) = if opcode >> 12 == 0x9 {
+                ("sub", "subx")
+            } else {
+                ("add", "addx")
+            };
+            if size_bits == 3 {
+                let size = if opcode & 0x0100 != 0 {
+                    Size::Long
+                } else {
+                    Size::Word
+                };
+                let src = r.src(opcode, size)?;
+                return Ok(op(
+                    format!("{name}a{}", size.suffix()),
+                    format!("{src},a{reg_hi}"),
+                ));
+            }
+            if opcode & 0x0130 == 0x0100 {
+                let size = Size::from_bits(size_bits)?;
+                let operands = if opcode & 0x0008 != 0 {
+                    format!("-(a{reg_lo}),-(a{reg_hi})")
+                } else {
+                    format!("d{reg_lo},d{reg_hi}")
+                };
+                return Ok(op(format!("{extended}{}", size.suffix()), operands));
+            }
+            arithmetic(r, opcode, name)
+        }
+        0xB => {
+            if size_bits == 3 {
+                let size = if opcode & 0x0100 != 0 {
+                    Size::Long
+                } else {
+                    Size::Word
+                };
+                let src = r.src(opcode, size)?;
+                return Ok(op(
+                    format!("cmpa{}", size.suffix()),
+                    format!("{src},a{reg_hi}"),
+                ));
+            }
+            let size = Size::from_bits(size_bits)?;
+            if opcode & 0xF138 == 0xB108 {
+                return Ok(op(
+                    format!("cmpm{}", size.suffix()),
+                    format!("(a{reg_lo})+,(a{reg_hi})+"),
+                ));
+            }
+            let ea = r.src(opcode, size)?;
+            if opcode & 0x0100 != 0 {
+                Ok(op(
+                    format!("eor{}", size.suffix()),
+                    format!("d{reg_hi},{ea}"),
+                ))
+            } else {
+                Ok(op(
+                    format!("cmp{}", size.suffix()),
+                    format!("{ea},d{reg_hi}"),
+                ))
+            }
+        }
+        0xE => {
+            let names = ["as", "ls", "rox", "ro"];
+            let dir = if opcode & 0x0100 != 0 { 'l' } else { 'r' };
+            if size_bits == 3 {
+                if opcode & 0x0800 != 0 {
+                    return Err(Fail::Invalid);
+                }
+                let name = names[((opcode >> 9) & 3) as usize];
+                let dst = r.src(opcode, Size::Word)?;
+                return Ok(op(format!("{name}{dir}.w"), dst));
+            }
+            let size = Size::from_bits(size_bits)?;
+            let name = names[((opcode >> 3) & 3) as usize];
+            let count = if opcode & 0x0020 != 0 {
+                format!("d{reg_hi}")
+            } else {
+                format!("#{}", if reg_hi == 0 { 8 } else { reg_hi })
+            };
+            Ok(op(
+                format!("{name}{dir}{}", size.suffix()),
+                format!("{count},d{reg_lo}"),
+            ))
+        }
+        _ => Err(Fail::Invalid),
+    }
+}
+
+/// Decodes OR/AND/SUB/ADD with a data register operand
+fn arithmetic<F: FnMut(u32) -> Option<u16>>(
+    r: &mut Reader<F>,
+    opcode: u16,
+    name: &str,
+) -> Decode<(String, String)> {
+    let size = Size::from_bits((opcode >> 6) & 3)?;
+    let reg = (opcode >> 9) & 7;
+    let ea = r.src(opcode, size)?;
+    let operands = if opcode & 0x0100 != 0 {
+        format!("d{reg},{ea}")
+    } else {
+        format!("{ea},d{reg}")
+    };
+    Ok(op(format!("{name}{}", size.suffix()), operands))
+}
+
+/// Decodes the miscellaneous instructions (opcode family 4)
+fn decode_misc<F: FnMut(u32) -> Option<u16>>(
+    r: &mut Reader<F>,
+    opcode: u16,
+) -> Decode<(String, String)> {
+    let reg_hi = (opcode >> 9) & 7;
+    let reg_lo = opcode & 7;
+    let size_bits = (opcode >> 6) & 3;
+
+    match opcode {
+        0x4AFC => return Ok(op("illegal", "")),
+        0x4E70 => return Ok(op("reset", "")),
+        0x4E71 => return Ok(op("nop", "")),
+        0x4E72 => return Ok(op("stop", format!("#{}", hex(u32::from(r.word()?))))),
+        0x4E73 => return Ok(op("rte", "")),
+        0x4E75 => return Ok(op("rts", "")),
+        0x4E76 => return Ok(op("trapv", "")),
+        0x4E77 => return Ok(op("rtr", "")),
+        _ => {}
+    }
+    match opcode & 0xFFF8 {
+        0x4E50 => {
+            let disp = r.word()? as i16;
+            return Ok(op("link", format!("a{reg_lo},#{disp}")));
+        }
+        0x4E58 => return Ok(op("unlk", format!("a{reg_lo}"))),
+        0x4840 => return Ok(op("swap", format!("d{reg_lo}"))),
+        0x4880 => return Ok(op("ext.w", format!("d{reg_lo}"))),
+        0x48C0 => return Ok(op("ext.l", format!("d{reg_lo}"))),
+        _ => {}
+    }
+    match opcode & 0xFFF0 {
+        0x4E40 => return Ok(op("trap", format!("#{}", opcode & 0xF))),
+        0x4E60 => {
+            let operands = if opcode & 0x0008 != 0 {
+                format!("usp,a{reg_lo}")
+            } else {
+                format!("a{reg_lo},usp")
+            };
+            return Ok(op("move.l", operands));
+        }
+        _ => {}
+    }
+    if opcode & 0xFB80 == 0x4880 {
+        let size = if opcode & 0x0040 != 0 {
+            Size::Long
+        } else {
+            Size::Word
+        };
+        let mut mask = r.word()?;
+        let mode = (opcode >> 3) & 7;
+        if mode == 4 {
+            // Predecrement masks run a7..d0 from bit 0
+            mask = mask.reverse_bits();
+        }
+        let ea = r.src(opcode, size)?;
+        let list = register_list(mask);
+        let operands = if opcode & 0x0400 != 0 {
+            format!("{ea},{list}")
+        } else {
+            format!("{list},{ea}")
+        };
+        return Ok(op(format!("movem{}", size.suffix()), operands));
+    }
+    match opcode & 0xF1C0 {
+        0x41C0 => {
+            let src = r.src(opcode, Size::Long)?;
+            return Ok(op("lea", format!("{src},a{reg_hi}")));
+        }
+        0x4180 => {
+            let src = r.src(opcode, Size::Word)?;
+            return Ok(op("chk.w", format!("{src},d{reg_hi}")));
+        }
+        _ => {}
+    }
+    let single =
+        |name: &str, r: &mut Reader<F>, size: Size| r.src(opcode, size).map(|ea| op(name, ea));
+    match opcode & 0xFFC0 {
+        0x4840 => return single("pea", r, Size::Long),
+        0x4800 => return single("nbcd", r, Size::Byte),
+        0x4AC0 => return single("tas", r, Size::Byte),
+        0x4EC0 => return single("jmp", r, Size::Long),
+        0x4E80 => return single("jsr", r, Size::Long),
+        0x40C0 => {
+            let dst = r.src(opcode, Size::Word)?;
+            return Ok(op("move.w", format!("sr,{dst}")));
+        }
+        0x44C0 => {
+            let src = r.src(opcode, Size::Word)?;
+            return Ok(op("move.w", format!("{src},ccr")));
+        }
+        0x46C0 => {
+            let src = r.src(opcode, Size::Word)?;
+            return Ok(op("move.w", format!("{src},sr")));
+        }
+        _ => {}
+    }
+    let name = match opcode & 0xFF00 {
+        0x4000 => "negx",
+        0x4200 => "clr",
+        0x4400 => "neg",
+        0x4600 => "not",
+        0x4A00 => "tst",
+        _ => return Err(Fail::Invalid),
+    };
+    let size = Size::from_bits(size_bits)?;
+    single(&format!("{name}{}", size.suffix()), r, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble(words: &[u16]) -> (String, u32) {
+        let fetch = |addr: u32| words.get((addr / 2) as usize).copied();
+        let insn = decode(0, fetch).unwrap();
+        let text = if insn.operands.is_empty() {
+            insn.mnemonic
+        } else {
+            format!("{} {}", insn.mnemonic, insn.operands)
+        };
+        (text, insn.length)
+    }
+
+    #[test]
+    fn test_decode_instructions() {
+        let cases: &[(&[u16], &str, u32)] = &[
+            (&[0x4E71], "nop", 2),
+            (&[0x7005], "moveq #5,d0", 2),
+            (&[0x70FF], "moveq #-1,d0", 2),
+            (&[0x203C, 0x1234, 0x5678], "move.l #$12345678,d0", 6),
+            (&[0x41F9, 0x00E0, 0x0100], "lea $E00100,a0", 6),
+            (&[0x3028, 0xFFFC], "move.w -$4(a0),d0", 4),
+            (&[0x6000, 0x0010], "bra.w $12", 4),
+            (&[0x66FE], "bne.s $0", 2),
+            (&[0x51C8, 0xFFFE], "dbra d0,$0", 4),
+            (&[0x48E7, 0xC0C0], "movem.l d0-d1/a0-a1,-(a7)", 4),
+            (&[0x4CDF, 0x0303], "movem.l (a7)+,d0-d1/a0-a1", 4),
+            (&[0xE348], "lsl.w #1,d0", 2),
+            (&[0x5281], "addq.l #1,d1", 2),
+            (&[0x4E4F], "trap #15", 2),
+            (&[0xD0C1], "adda.w d1,a0", 2),
+            (&[0xC340], "exg d1,d0", 2),
+            (&[0xA000], "dc.w $A000", 2),
+        ];
+        for &(words, text, length) in cases {
+            assert_eq!(
+                disassemble(words),
+                (text.to_string(), length),
+                "{words:04X?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_stops_at_missing_words() {
+        // move.l #imm,d0 with its immediate cut off
+        let words = [0x203C, 0x1234];
+        let fetch = |addr: u32| words.get((addr / 2) as usize).copied();
+        assert_eq!(decode(0, fetch), None);
+    }
+}
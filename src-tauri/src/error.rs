@@ -0,0 +1,315 @@
+//! Command Errors
+//!
+//! Every Tauri command fails with an `EmulatorError`. It reaches the
+//! frontend as an object rather than a bare string: `code` names the kind of
+//! failure, the variant's fields follow, and `message` is the `Display`
+//! text, for example
+//!
+//! ```json
+//! { "code": "unmapped", "address": 512, "message": "Address $000200 is not mapped" }
+//! ```
+//!
+//! so the UI can act on the failure (point at the address, list the
+//! assembler's diagnostics) and still has a message to show.
+
+use crate::assembler::Diagnostic;
+use crate::cfimage::CfImageError;
+use crate::config::ConfigError;
+use crate::instances::{InstanceId, PRIMARY_INSTANCE};
+use crate::memory::MemoryError;
+use crate::object::{LinkError, ObjectError};
+use crate::registers::RegisterError;
+use crate::sbc::{AppLoadError, BinaryLoadError};
+use crate::snapshot::SnapshotError;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::{fmt, io};
+
+/// Why a command failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    /// No instance has the id
+    NotInitialized { instance: InstanceId },
+    /// No memory answers at the address
+    Unmapped { address: u32 },
+    /// The address can't be written (ROM, unpopulated RAM or open bus)
+    WriteProtected { address: u32 },
+    /// Assembly failed, with every error found
+    AssemblyFailed { diagnostics: Vec<Diagnostic> },
+    /// Reading or writing a file failed
+    Io {
+        path: Option<String>,
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// An argument is out of range or doesn't parse
+    InvalidArgument { field: String, message: String },
+    /// An expression names a label the symbol table doesn't have, with
+    /// similar names it does
+    UndefinedSymbol {
+        name: String,
+        suggestions: Vec<String>,
+    },
+    /// Anything else
+    Failed { message: String },
+}
+
+impl EmulatorError {
+    /// Creates the error for a command on a missing instance (the primary
+    /// one by default)
+    pub fn not_initialized(instance: Option<InstanceId>) -> Self {
+        Self::NotInitialized {
+            instance: instance.unwrap_or(PRIMARY_INSTANCE),
+        }
+    }
+
+    /// Creates the error for a bad argument, named by its JSON name
+    pub fn invalid(field: &str, message: impl Into<String>) -> Self {
+        Self::InvalidArgument {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// Creates an error that fits no other variant
+    pub fn failed(message: impl Into<String>) -> Self {
+        Self::Failed {
+            message: message.into(),
+        }
+    }
+
+    /// Names the file an I/O error is about, unless it already names one
+    #[must_use]
+    pub fn with_path(mut self, file: &str) -> Self {
+        if let Self::Io { path, .. } = &mut self {
+            path.get_or_insert_with(|| file.to_string());
+        }
+        self
+    }
+
+    /// The camelCase name the frontend matches on
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::NotInitialized { .. } => "notInitialized",
+            Self::Unmapped { .. } => "unmapped",
+            Self::WriteProtected { .. } => "writeProtected",
+            Self::AssemblyFailed { .. } => "assemblyFailed",
+            Self::Io { .. } => "io",
+            Self::InvalidArgument { .. } => "invalidArgument",
+            Self::UndefinedSymbol { .. } => "undefinedSymbol",
+            Self::Failed { .. } => "failed",
+        }
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotInitialized { instance } if *instance == PRIMARY_INSTANCE => {
+                write!(f, "Emulator not initialized")
+            }
+            Self::NotInitialized { instance } => {
+                write!(f, "Emulator instance {instance} not initialized")
+            }
+            Self::Unmapped { address } => write!(f, "Address ${address:06X} is not mapped"),
+            Self::WriteProtected { address } => {
+                write!(f, "Address ${address:06X} is not writable")
+            }
+            Self::AssemblyFailed { diagnostics } => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{diagnostic}")?;
+                }
+                Ok(())
+            }
+            Self::UndefinedSymbol { name, suggestions } => {
+                write!(f, "Undefined symbol: {name}")?;
+                if !suggestions.is_empty() {
+                    write!(f, " (did you mean {}?)", suggestions.join(", "))?;
+                }
+                Ok(())
+            }
+            Self::Io { message, .. }
+            | Self::InvalidArgument { message, .. }
+            | Self::Failed { message } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+/// Returns an I/O error kind's name in camelCase (e.g. `notFound`)
+fn kind_name(kind: io::ErrorKind) -> String {
+    let name = format!("{kind:?}");
+    let mut chars = name.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_ascii_lowercase().to_string() + chars.as_str()
+    })
+}
+
+impl Serialize for EmulatorError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", self.code())?;
+        match self {
+            Self::NotInitialized { instance } => map.serialize_entry("instance", instance)?,
+            Self::Unmapped { address } | Self::WriteProtected { address } => {
+                map.serialize_entry("address", address)?;
+            }
+            Self::AssemblyFailed { diagnostics } => {
+                map.serialize_entry("diagnostics", diagnostics)?;
+            }
+            Self::Io { path, kind, .. } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("kind", &kind_name(*kind))?;
+            }
+            Self::InvalidArgument { field, .. } => map.serialize_entry("field", field)?,
+            Self::UndefinedSymbol { name, suggestions } => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("suggestions", suggestions)?;
+            }
+            Self::Failed { .. } => {}
+        }
+        map.serialize_entry("message", &self.to_string())?;
+        map.end()
+    }
+}
+
+impl From<io::Error> for EmulatorError {
+    fn from(error: io::Error) -> Self {
+        Self::Io {
+            path: None,
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<MemoryError> for EmulatorError {
+    fn from(error: MemoryError) -> Self {
+        match error {
+            MemoryError::AddressOutOfRange { address, .. } => Self::Unmapped { address },
+        }
+    }
+}
+
+impl From<Vec<Diagnostic>> for EmulatorError {
+    fn from(diagnostics: Vec<Diagnostic>) -> Self {
+        Self::AssemblyFailed { diagnostics }
+    }
+}
+
+impl From<ConfigError> for EmulatorError {
+    fn from(error: ConfigError) -> Self {
+        Self::invalid(error.field, error.to_string())
+    }
+}
+
+impl From<RegisterError> for EmulatorError {
+    fn from(error: RegisterError) -> Self {
+        let field = match error {
+            RegisterError::Unknown(_) => "name",
+            RegisterError::OddPc(_) | RegisterError::SrTooWide(_) => "value",
+        };
+        Self::invalid(field, error.to_string())
+    }
+}
+
+impl From<AppLoadError> for EmulatorError {
+    fn from(error: AppLoadError) -> Self {
+        Self::invalid("loadAddr", error.to_string())
+    }
+}
+
+impl From<BinaryLoadError> for EmulatorError {
+    fn from(error: BinaryLoadError) -> Self {
+        let message = error.to_string();
+        match error {
+            BinaryLoadError::NotFound { path } => Self::Io {
+                path: Some(path),
+                kind: io::ErrorKind::NotFound,
+                message,
+            },
+            BinaryLoadError::Io { path, kind, .. } => Self::Io {
+                path: Some(path),
+                kind,
+                message,
+            },
+            BinaryLoadError::NotWritable { addr } => Self::WriteProtected { address: addr },
+            BinaryLoadError::TooLarge { .. } => Self::invalid("path", message),
+        }
+    }
+}
+
+impl From<SnapshotError> for EmulatorError {
+    fn from(error: SnapshotError) -> Self {
+        let message = error.to_string();
+        match error {
+            SnapshotError::Io { path, kind, .. } => Self::Io {
+                path: Some(path),
+                kind,
+                message,
+            },
+            SnapshotError::Config(e) => Self::invalid(e.field, message),
+            SnapshotError::NotSnapshot
+            | SnapshotError::Version { .. }
+            | SnapshotError::Corrupt { .. } => Self::failed(message),
+        }
+    }
+}
+
+impl From<ObjectError> for EmulatorError {
+    fn from(error: ObjectError) -> Self {
+        let message = error.to_string();
+        match error {
+            ObjectError::Io { path, kind, .. } => Self::Io {
+                path: Some(path),
+                kind,
+                message,
+            },
+            ObjectError::NotObject | ObjectError::Version { .. } | ObjectError::Corrupt { .. } => {
+                Self::failed(message)
+            }
+        }
+    }
+}
+
+impl From<Vec<LinkError>> for EmulatorError {
+    fn from(errors: Vec<LinkError>) -> Self {
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        Self::failed(messages.join("\n"))
+    }
+}
+
+impl From<CfImageError> for EmulatorError {
+    fn from(error: CfImageError) -> Self {
+        let message = error.to_string();
+        match error {
+            CfImageError::InvalidSize { .. } | CfImageError::InvalidClusterCount(_) => {
+                Self::invalid("sizeMb", message)
+            }
+            CfImageError::Io(kind, _) => Self::Io {
+                path: None,
+                kind,
+                message,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_keeps_a_named_path() {
+        let error = EmulatorError::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        let error = error.with_path("a.img").with_path("b.img");
+        assert!(matches!(
+            error,
+            EmulatorError::Io { path: Some(ref path), kind: io::ErrorKind::PermissionDenied, .. }
+                if path == "a.img"
+        ));
+    }
+}
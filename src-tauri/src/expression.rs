@@ -0,0 +1,314 @@
+//! Debugger Expressions
+//!
+//! Breakpoint conditions are written in the assembler's expression syntax
+//! and parsed by its `ExprParser`, with terms for the machine state:
+//!
+//! - Register names (`d0`-`d7`, `a0`-`a7`, `sp`, `pc`, `sr`, `usp`, `ssp`),
+//!   in any case
+//! - `byte(x)`, `word(x)` and `long(x)`, the big-endian value at address `x`
+//! - Labels of the loaded program
+//!
+//! Comparisons are `=`, `<>`, `<`, `>`, `<=` and `>=` and yield 1 or 0; a
+//! condition holds when it evaluates to anything but 0.
+//!
+//! Memory terms only see ROM and RAM, so evaluating an expression never
+//! touches a peripheral register.
+//!
+//! Evaluated on its own (the UI's calculator), an expression's value counts
+//! as an address when it names a label, and as a plain number otherwise.
+
+use crate::assembler::{eval_expr, Expr, ExprParser, Lexer, Token};
+use crate::registers::{Register, RegisterFile};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Memory functions, with the number of bytes each reads
+const FUNCTIONS: [(&str, u32); 3] = [("byte", 1), ("word", 2), ("long", 4)];
+
+/// An expression that failed to parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// What went wrong
+    pub message: String,
+    /// Column (from 1) where parsing stopped
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Machine state an expression is evaluated against
+pub struct Machine<'a> {
+    /// CPU registers
+    pub registers: &'a RegisterFile,
+    /// Reads a byte of ROM or RAM, or `None` elsewhere
+    pub peek: &'a dyn Fn(u32) -> Option<u8>,
+    /// Labels of the loaded program by address
+    pub symbols: &'a BTreeMap<u32, String>,
+}
+
+/// What an evaluated value stands for (see `Expression::evaluation`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ValueKind {
+    /// The expression names a label, so the value is likely an address
+    Address,
+    /// A plain number
+    Number,
+}
+
+/// An expression's value in the forms the UI shows
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Evaluation {
+    /// The value
+    pub value: i64,
+    /// The value in hex (`$FF`), as a 32-bit two's complement number when
+    /// it fits in one
+    pub hex: String,
+    /// What the value stands for
+    pub kind: ValueKind,
+}
+
+/// A parsed debugger expression
+#[derive(Debug, Clone)]
+pub struct Expression {
+    /// Text the expression was parsed from
+    source: String,
+    /// Parsed form
+    expr: Expr,
+}
+
+impl Expression {
+    /// Parses an expression
+    ///
+    /// # Errors
+    /// Returns the parser's message and the column it stopped at.
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let names = FUNCTIONS.map(|(name, _)| name);
+        let mut lexer = Lexer::new(source, "expression");
+        let tokens = lexer.tokenize().map_err(|message| ParseError {
+            message,
+            column: lexer.location().column,
+        })?;
+        let mut parser = ExprParser::with_functions(&tokens, &names);
+        let expr = match parser.parse_expr() {
+            Ok(_) if !parser.at_end() => Err("unexpected token after expression".to_string()),
+            result => result,
+        };
+        let expr = expr.map_err(|message| ParseError {
+            message,
+            column: parser.location().column,
+        })?;
+        // Only a comment may follow the expression
+        let rest = tokens.iter().skip_while(|t| t.token != Token::Newline);
+        if rest
+            .into_iter()
+            .any(|t| !matches!(t.token, Token::Newline | Token::Eof))
+        {
+            return Err(ParseError {
+                message: "expression must be on one line".to_string(),
+                column: 1,
+            });
+        }
+        Ok(Self {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    /// Returns the text the expression was parsed from
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluates the expression
+    ///
+    /// # Errors
+    /// Returns a message for an unknown name, an unreadable address or a
+    /// division by zero.
+    pub fn evaluate(&self, machine: &Machine<'_>) -> Result<i64, String> {
+        evaluate(&self.expr, machine)
+    }
+
+    /// Evaluates the expression, with the value in hex and its kind
+    ///
+    /// # Errors
+    /// Fails like `evaluate`.
+    pub fn evaluation(&self, machine: &Machine<'_>) -> Result<Evaluation, String> {
+        let value = self.evaluate(machine)?;
+        let hex = if (i64::from(i32::MIN)..=i64::from(u32::MAX)).contains(&value) {
+            format!("${:X}", value as u32)
+        } else {
+            format!("${value:X}")
+        };
+        let kind = if self.labels().is_empty() {
+            ValueKind::Number
+        } else {
+            ValueKind::Address
+        };
+        Ok(Evaluation { value, hex, kind })
+    }
+
+    /// Returns the names in the expression that aren't registers, which
+    /// must be labels
+    #[must_use]
+    pub fn labels(&self) -> Vec<&str> {
+        let mut labels = Vec::new();
+        collect_labels(&self.expr, &mut labels);
+        labels
+    }
+}
+
+/// Adds the names in an expression tree that aren't registers to `labels`
+fn collect_labels<'a>(expr: &'a Expr, labels: &mut Vec<&'a str>) {
+    match expr {
+        Expr::Symbol(name) => {
+            if name.parse::<Register>().is_err() && !labels.contains(&name.as_str()) {
+                labels.push(name);
+            }
+        }
+        Expr::Call(_, e) | Expr::Neg(e) | Expr::Not(e) => collect_labels(e, labels),
+        Expr::BinOp(l, _, r) => {
+            collect_labels(l, labels);
+            collect_labels(r, labels);
+        }
+        Expr::CurrentPc | Expr::Number(_) => {}
+    }
+}
+
+impl serde::Serialize for Expression {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+/// Evaluates an expression tree, resolving names and memory terms first
+fn evaluate(expr: &Expr, machine: &Machine<'_>) -> Result<i64, String> {
+    match expr {
+        Expr::Symbol(name) => {
+            if let Ok(register) = name.parse::<Register>() {
+                return Ok(i64::from(machine.registers.read(register)));
+            }
+            machine
+                .symbols
+                .iter()
+                .find(|(_, symbol)| *symbol == name)
+                .map(|(&address, _)| i64::from(address))
+                .ok_or_else(|| format!("undefined symbol: {name}"))
+        }
+        Expr::CurrentPc => Ok(i64::from(machine.registers.pc)),
+        Expr::Call(name, arg) => {
+            let size = FUNCTIONS
+                .iter()
+                .find(|(function, _)| function == name)
+                .map(|&(_, size)| size)
+                .ok_or_else(|| format!("unknown function: {name}"))?;
+            let address = evaluate(arg, machine)? as u32;
+            (0..size).try_fold(0i64, |value, i| {
+                let byte = (machine.peek)(address.wrapping_add(i))
+                    .ok_or_else(|| format!("can't read ${:06X}", address.wrapping_add(i)))?;
+                Ok((value << 8) | i64::from(byte))
+            })
+        }
+        Expr::Neg(e) => Ok(evaluate(e, machine)?.wrapping_neg()),
+        Expr::Not(e) => Ok(!evaluate(e, machine)?),
+        Expr::BinOp(l, op, r) => {
+            // Fold both sides to numbers and let the assembler apply the operator
+            let folded = Expr::BinOp(
+                Box::new(Expr::Number(evaluate(l, machine)?)),
+                *op,
+                Box::new(Expr::Number(evaluate(r, machine)?)),
+            );
+            eval_expr(&folded, &HashMap::new(), machine.registers.pc)
+        }
+        Expr::Number(n) => Ok(*n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine_eval(source: &str) -> Result<i64, String> {
+        let mut registers = RegisterFile::new();
+        registers.d[0] = 21;
+        registers.a[0] = 0x1000;
+        registers.pc = 0x400;
+        let memory = [0x12, 0x34, 0x56, 0x78];
+        let peek = |addr: u32| {
+            addr.checked_sub(0x1000)
+                .and_then(|i| memory.get(i as usize).copied())
+        };
+        let symbols = BTreeMap::from([(0x1002, "table".to_string())]);
+        let machine = Machine {
+            registers: &registers,
+            peek: &peek,
+            symbols: &symbols,
+        };
+        Expression::parse(source)
+            .map_err(|e| e.to_string())?
+            .evaluate(&machine)
+    }
+
+    #[test]
+    fn test_expression_registers_and_memory() {
+        assert_eq!(machine_eval("D0*2"), Ok(42));
+        assert_eq!(machine_eval("d0 = 21"), Ok(1));
+        assert_eq!(machine_eval("word(A0)"), Ok(0x1234));
+        assert_eq!(machine_eval("long(a0)"), Ok(0x1234_5678));
+        assert_eq!(machine_eval("BYTE(table+1)"), Ok(0x78));
+        assert_eq!(machine_eval("pc+$10"), Ok(0x410));
+        assert_eq!(
+            machine_eval("word(a0+3)"),
+            Err("can't read $001004".to_string())
+        );
+        assert_eq!(
+            machine_eval("missing+1"),
+            Err("undefined symbol: missing".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expression_evaluation_kind() {
+        let evaluation = |source: &str| {
+            let registers = RegisterFile::new();
+            let symbols = BTreeMap::from([(0xE0_0100, "start".to_string())]);
+            let machine = Machine {
+                registers: &registers,
+                peek: &|_| None,
+                symbols: &symbols,
+            };
+            Expression::parse(source).unwrap().evaluation(&machine)
+        };
+        let result = evaluation("start+$10").unwrap();
+        assert_eq!(result.value, 0xE0_0110);
+        assert_eq!(result.hex, "$E00110");
+        assert_eq!(result.kind, ValueKind::Address);
+        let result = evaluation("d0-1").unwrap();
+        assert_eq!(result.value, -1);
+        assert_eq!(result.hex, "$FFFFFFFF");
+        assert_eq!(result.kind, ValueKind::Number);
+        let labels = Expression::parse("a+B*a-(d0+sp)").unwrap();
+        assert_eq!(labels.labels(), ["a", "B"]);
+    }
+
+    #[test]
+    fn test_expression_parse_errors() {
+        let err = Expression::parse("d0 = ").unwrap_err();
+        assert_eq!(err.message, "unexpected token in expression");
+        assert_eq!(err.column, 6);
+        let err = Expression::parse("d0 d1").unwrap_err();
+        assert_eq!(err.message, "unexpected token after expression");
+        assert_eq!(err.column, 4);
+        let err = Expression::parse("word(a0").unwrap_err();
+        assert_eq!(err.message, "expected ')' after word argument");
+        assert_eq!(Expression::parse(" d0 <> 3 ").unwrap().source(), "d0 <> 3");
+    }
+}
@@ -0,0 +1,287 @@
+//! GPIO Port Emulation
+//!
+//! This module emulates an 8-bit general-purpose I/O port on the expansion
+//! bus. Each pin is an input or an output; input levels are driven by the
+//! host (e.g. switches in the frontend) and output levels are reported back.
+//!
+//! ## Register Map (offsets from $800020)
+//!
+//! | Offset | Read             | Write                     |
+//! |--------|------------------|---------------------------|
+//! | 0      | Direction        | Direction (1 = output)    |
+//! | 2      | Pin levels       | Output latch              |
+//! | 4      | Rising edge mask | Rising edge mask          |
+//! | 6      | Falling edge mask| Falling edge mask         |
+//! | 8      | Edge flags       | Clear flags (write 1s)    |
+//!
+//! Reading the data register returns the output latch for output pins and
+//! the host-driven level for input pins. Undriven inputs are pulled up.
+//!
+//! ## Interrupts
+//!
+//! An edge on an input pin whose bit is set in the matching edge mask sets
+//! that pin's bit in the edge flags register. The port asserts its
+//! interrupt line while any flag is set.
+
+use std::collections::VecDeque;
+
+use crate::panel::Peripheral;
+
+/// Maximum number of queued output change events
+const MAX_EVENTS: usize = 256;
+
+/// GPIO register offsets
+pub mod regs {
+    /// Direction register (1 = output)
+    pub const DIR: u32 = 0;
+    /// Data register (pin levels / output latch)
+    pub const DATA: u32 = 2;
+    /// Rising edge interrupt mask
+    pub const RISE: u32 = 4;
+    /// Falling edge interrupt mask
+    pub const FALL: u32 = 6;
+    /// Edge flags (write 1 to clear)
+    pub const FLAGS: u32 = 8;
+}
+
+/// Snapshot of the GPIO port state
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct GpioState {
+    /// Direction register (1 = output)
+    pub direction: u8,
+    /// Levels of the output pins (input pins read as 0)
+    pub output: u8,
+    /// Host-driven input levels
+    pub input: u8,
+    /// Pin levels as the guest reads them
+    pub pins: u8,
+}
+
+/// 8-bit GPIO port peripheral
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Gpio {
+    /// Direction register (1 = output)
+    direction: u8,
+    /// Output latch
+    latch: u8,
+    /// Host-driven input levels
+    input: u8,
+    /// Rising edge interrupt mask
+    rise_mask: u8,
+    /// Falling edge interrupt mask
+    fall_mask: u8,
+    /// Edge flags
+    flags: u8,
+    /// Output levels reported by the last change event
+    last_output: u8,
+    /// Output change events not yet collected by the host
+    events: VecDeque<GpioState>,
+}
+
+impl Default for Gpio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gpio {
+    /// Creates a GPIO port with all pins as pulled-up inputs
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            direction: 0,
+            latch: 0,
+            input: 0xFF,
+            rise_mask: 0,
+            fall_mask: 0,
+            flags: 0,
+            last_output: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Resets the port registers (host-driven inputs keep their levels)
+    pub fn reset(&mut self) {
+        self.direction = 0;
+        self.latch = 0;
+        self.rise_mask = 0;
+        self.fall_mask = 0;
+        self.flags = 0;
+        self.output_changed();
+    }
+
+    /// Reads a GPIO register
+    pub const fn read(&mut self, offset: u32) -> u8 {
+        match offset {
+            regs::DIR => self.direction,
+            regs::DATA => self.pins(),
+            regs::RISE => self.rise_mask,
+            regs::FALL => self.fall_mask,
+            regs::FLAGS => self.flags,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes a GPIO register
+    pub fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            regs::DIR => {
+                self.direction = value;
+                self.output_changed();
+            }
+            regs::DATA => {
+                self.latch = value;
+                self.output_changed();
+            }
+            regs::RISE => self.rise_mask = value,
+            regs::FALL => self.fall_mask = value,
+            regs::FLAGS => self.flags &= !value,
+            _ => {}
+        }
+    }
+
+    /// Drives an input pin from the host
+    ///
+    /// The level is remembered even while the pin is an output, and takes
+    /// effect if the guest later makes it an input.
+    pub const fn set_input(&mut self, pin: u8, level: bool) {
+        let mask = 1u8 << (pin & 7);
+        let old = self.input;
+        if level {
+            self.input |= mask;
+        } else {
+            self.input &= !mask;
+        }
+
+        if self.direction & mask == 0 {
+            let rose = !old & self.input & mask;
+            let fell = old & !self.input & mask;
+            self.flags |= (rose & self.rise_mask) | (fell & self.fall_mask);
+        }
+    }
+
+    /// Returns the levels of the output pins (input pins read as 0)
+    #[must_use]
+    pub const fn output_state(&self) -> u8 {
+        self.latch & self.direction
+    }
+
+    /// Returns the full port state
+    #[must_use]
+    pub const fn state(&self) -> GpioState {
+        GpioState {
+            direction: self.direction,
+            output: self.output_state(),
+            input: self.input,
+            pins: self.pins(),
+        }
+    }
+
+    /// Returns and clears the queued output change events
+    pub fn take_events(&mut self) -> Vec<GpioState> {
+        self.events.drain(..).collect()
+    }
+
+    /// Returns true if the port is asserting its interrupt line
+    #[must_use]
+    pub const fn interrupt_pending(&self) -> bool {
+        self.flags != 0
+    }
+
+    /// Returns the pin levels as the guest reads them
+    const fn pins(&self) -> u8 {
+        (self.latch & self.direction) | (self.input & !self.direction)
+    }
+
+    /// Queues a change event if the output levels changed
+    fn output_changed(&mut self) {
+        let output = self.output_state();
+        if output == self.last_output {
+            return;
+        }
+        self.last_output = output;
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(self.state());
+    }
+}
+
+impl Peripheral for Gpio {
+    fn ui_state(&self) -> serde_json::Value {
+        serde_json::json!({
+            "direction": self.direction,
+            "output": self.output_state(),
+            "input": self.input,
+            "pins": self.pins(),
+            "interruptPending": self.interrupt_pending(),
+        })
+    }
+
+    fn debug_dump(&self) -> serde_json::Value {
+        serde_json::json!({
+            "direction": self.direction,
+            "latch": self.latch,
+            "input": self.input,
+            "riseMask": self.rise_mask,
+            "fallMask": self.fall_mask,
+            "flags": self.flags,
+            "pendingEvents": self.events.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpio_inputs_pulled_up() {
+        let mut gpio = Gpio::new();
+        assert_eq!(gpio.read(regs::DATA), 0xFF);
+        gpio.set_input(3, false);
+        assert_eq!(gpio.read(regs::DATA), 0xF7);
+    }
+
+    #[test]
+    fn test_gpio_outputs_and_events() {
+        let mut gpio = Gpio::new();
+        gpio.write(regs::DATA, 0x0F);
+        assert!(gpio.take_events().is_empty());
+
+        gpio.write(regs::DIR, 0x03);
+        assert_eq!(gpio.output_state(), 0x03);
+        assert_eq!(gpio.read(regs::DATA), 0xFF);
+
+        gpio.write(regs::DATA, 0x01);
+        gpio.write(regs::DATA, 0x01);
+        let events = gpio.take_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].output, 0x03);
+        assert_eq!(events[1].output, 0x01);
+        assert!(gpio.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_gpio_edge_interrupts() {
+        let mut gpio = Gpio::new();
+        gpio.write(regs::RISE, 0x01);
+        gpio.write(regs::FALL, 0x02);
+
+        gpio.set_input(0, false);
+        gpio.set_input(1, false);
+        assert_eq!(gpio.read(regs::FLAGS), 0x02);
+        gpio.set_input(0, true);
+        assert_eq!(gpio.read(regs::FLAGS), 0x03);
+        assert!(gpio.interrupt_pending());
+
+        gpio.write(regs::FLAGS, 0x03);
+        assert!(!gpio.interrupt_pending());
+
+        // Output pins don't raise edge flags
+        gpio.write(regs::DIR, 0x01);
+        gpio.set_input(0, false);
+        gpio.set_input(0, true);
+        assert!(!gpio.interrupt_pending());
+    }
+}
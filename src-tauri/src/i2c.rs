@@ -0,0 +1,504 @@
+//! I2C Controller Emulation
+//!
+//! This module emulates a byte-level I2C master on the expansion bus.
+//! Firmware issues START, WRITE, READ and STOP commands; the controller runs
+//! them against the devices attached to the bus, which the host registers
+//! by 7-bit address (see [`I2cDevice`]).
+//!
+//! ## Register Map (offsets from $800100)
+//!
+//! | Offset | Read          | Write                     |
+//! |--------|---------------|---------------------------|
+//! | 0      | Address       | Target address (7-bit)    |
+//! | 2      | Received byte | Byte to send              |
+//! | 4      | -             | Command                   |
+//! | 6      | Status        | Clear DONE (write 1s)     |
+//! | 8      | Control       | Control (IE)              |
+//!
+//! ## Commands
+//!
+//! A command byte combines these bits, performed in this order:
+//!
+//! - `START` ($01): (repeated) START, then the address byte. The direction
+//!   is read if `READ` is also set, write otherwise.
+//! - `WRITE` ($02): send the data register
+//! - `READ` ($04): receive a byte into the data register (unless combined
+//!   with `START`), acknowledging it unless `NACK` ($08) is set
+//! - `STOP` ($10): STOP, releasing the bus
+//!
+//! The bus runs at 100 kHz: START and STOP take one bit time and each byte
+//! nine. BUSY is set while a command runs and DONE when it finishes. NACK in
+//! the status register reports that the address or the last byte written
+//! was not acknowledged, e.g. because no device answers at that address.
+//! Clock stretching is not modeled.
+//!
+//! ## Interrupts
+//!
+//! With IE set in the control register, the controller asserts its
+//! interrupt line while DONE is set.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::scheduler::Clocked;
+
+/// CPU cycles per bit at 100 kHz
+pub const BIT_CYCLES: u64 = 120;
+
+/// I2C controller register offsets
+pub mod regs {
+    /// Target address
+    pub const ADDRESS: u32 = 0;
+    /// Data register
+    pub const DATA: u32 = 2;
+    /// Command register
+    pub const COMMAND: u32 = 4;
+    /// Status register
+    pub const STATUS: u32 = 6;
+    /// Control register
+    pub const CONTROL: u32 = 8;
+}
+
+/// Command register bits
+pub mod command {
+    /// Generate a (repeated) START and send the address byte
+    pub const START: u8 = 0x01;
+    /// Send the data register
+    pub const WRITE: u8 = 0x02;
+    /// Receive a byte (or, with START, address the device for reading)
+    pub const READ: u8 = 0x04;
+    /// Don't acknowledge the received byte
+    // Allow dead code: no device here cares whether a read is acknowledged.
+    #[allow(dead_code)]
+    pub const NACK: u8 = 0x08;
+    /// Generate a STOP
+    pub const STOP: u8 = 0x10;
+}
+
+/// Status register bits
+pub mod status {
+    /// A command is in progress
+    pub const BUSY: u8 = 0x01;
+    /// A command finished (write 1 to clear)
+    pub const DONE: u8 = 0x02;
+    /// The address or last byte written was not acknowledged
+    pub const NACK: u8 = 0x04;
+    /// The controller owns the bus (between START and STOP)
+    pub const BUS: u8 = 0x08;
+}
+
+/// Control register bits
+pub mod control {
+    /// Enable the command-done interrupt
+    pub const IE: u8 = 0x10;
+}
+
+/// A device on the I2C bus
+pub trait I2cDevice: Send {
+    /// Called when a START addresses this device; returns true to ACK
+    fn start(&mut self, read: bool) -> bool;
+
+    /// Receives a byte from the master; returns true to ACK
+    fn write(&mut self, byte: u8) -> bool;
+
+    /// Sends a byte to the master
+    fn read(&mut self) -> u8;
+
+    /// Called on STOP while this device is addressed
+    fn stop(&mut self) {}
+
+    /// Advances the device's internal timers by the given number of cycles
+    fn tick(&mut self, _cycles: u64) {}
+}
+
+/// Lets the host keep a handle on a device it attached
+impl<T: I2cDevice> I2cDevice for Arc<Mutex<T>> {
+    fn start(&mut self, read: bool) -> bool {
+        self.lock().unwrap().start(read)
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        self.lock().unwrap().write(byte)
+    }
+
+    fn read(&mut self) -> u8 {
+        self.lock().unwrap().read()
+    }
+
+    fn stop(&mut self) {
+        self.lock().unwrap().stop();
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.lock().unwrap().tick(cycles);
+    }
+}
+
+/// I2C master controller peripheral
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct I2cController {
+    /// Target address
+    address: u8,
+    /// Data register
+    data: u8,
+    /// Status register
+    status: u8,
+    /// Control register
+    control: u8,
+    /// Device addressed by the last START, if it acknowledged
+    selected: Option<u8>,
+    /// Cycles left in the current command
+    busy_cycles: u64,
+    /// Attached devices by 7-bit address (host-side, not saved in snapshots)
+    #[serde(skip)]
+    devices: BTreeMap<u8, Box<dyn I2cDevice>>,
+}
+
+impl I2cController {
+    /// Creates an idle controller with an empty bus
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copies the controller registers for a snapshot, without the attached
+    /// devices
+    #[must_use]
+    pub fn save_registers(&self) -> Self {
+        Self {
+            devices: BTreeMap::new(),
+            ..*self
+        }
+    }
+
+    /// Takes the registers from a snapshot's controller, keeping the
+    /// attached devices
+    pub fn restore_registers(&mut self, saved: Self) {
+        let devices = std::mem::take(&mut self.devices);
+        *self = Self { devices, ..saved };
+    }
+
+    /// Resets the controller registers, releasing the bus
+    ///
+    /// Attached devices stay attached.
+    pub fn reset(&mut self) {
+        self.release_bus();
+        self.address = 0;
+        self.data = 0;
+        self.status = 0;
+        self.control = 0;
+        self.busy_cycles = 0;
+    }
+
+    /// Attaches a device at a 7-bit address, returning the old one
+    pub fn attach(
+        &mut self,
+        address: u8,
+        device: Box<dyn I2cDevice>,
+    ) -> Option<Box<dyn I2cDevice>> {
+        self.devices.insert(address & 0x7F, device)
+    }
+
+    /// Reads a controller register
+    #[must_use]
+    pub const fn read(&self, offset: u32) -> u8 {
+        match offset {
+            regs::ADDRESS => self.address,
+            regs::DATA => self.data,
+            regs::STATUS => self.status,
+            regs::CONTROL => self.control,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes a controller register
+    pub fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            regs::ADDRESS => self.address = value & 0x7F,
+            regs::DATA => self.data = value,
+            regs::COMMAND => self.command(value),
+            regs::STATUS => self.status &= !(value & status::DONE),
+            regs::CONTROL => self.control = value & control::IE,
+            _ => {}
+        }
+    }
+
+    /// Advances the current command and the devices by the given cycles
+    pub fn tick(&mut self, cycles: u64) {
+        for device in self.devices.values_mut() {
+            device.tick(cycles);
+        }
+        if self.busy_cycles == 0 {
+            return;
+        }
+        self.busy_cycles = self.busy_cycles.saturating_sub(cycles);
+        if self.busy_cycles == 0 {
+            self.status = (self.status & !status::BUSY) | status::DONE;
+        }
+    }
+
+    /// Returns true if the controller is asserting its interrupt line
+    #[must_use]
+    pub const fn interrupt_pending(&self) -> bool {
+        self.control & control::IE != 0 && self.status & status::DONE != 0
+    }
+
+    /// Runs a command; the devices see its effects immediately
+    fn command(&mut self, cmd: u8) {
+        if self.status & status::BUSY != 0 {
+            return;
+        }
+        let mut bits = 0;
+
+        if cmd & command::START != 0 {
+            let read = cmd & command::READ != 0;
+            let address = self.address;
+            let ack = self
+                .devices
+                .get_mut(&address)
+                .is_some_and(|device| device.start(read));
+            self.selected = ack.then_some(address);
+            self.set_nack(!ack);
+            self.status |= status::BUS;
+            bits += 10;
+        } else {
+            if cmd & command::WRITE != 0 {
+                let data = self.data;
+                let ack = self
+                    .selected_device()
+                    .is_some_and(|device| device.write(data));
+                self.set_nack(!ack);
+                bits += 9;
+            }
+            if cmd & command::READ != 0 {
+                // Nobody drives SDA, so the master reads ones
+                self.data = self.selected_device().map_or(0xFF, |device| device.read());
+                bits += 9;
+            }
+        }
+
+        if cmd & command::STOP != 0 {
+            self.release_bus();
+            bits += 1;
+        }
+
+        if bits > 0 {
+            self.busy_cycles = bits * BIT_CYCLES;
+            self.status = (self.status & !status::DONE) | status::BUSY;
+        }
+    }
+
+    /// Returns the device addressed by the last START
+    fn selected_device(&mut self) -> Option<&mut Box<dyn I2cDevice>> {
+        let address = self.selected?;
+        self.devices.get_mut(&address)
+    }
+
+    /// Generates a STOP for the addressed device and releases the bus
+    fn release_bus(&mut self) {
+        if let Some(device) = self.selected_device() {
+            device.stop();
+        }
+        self.selected = None;
+        self.status &= !status::BUS;
+    }
+
+    /// Updates the NACK status bit
+    const fn set_nack(&mut self, nack: bool) {
+        if nack {
+            self.status |= status::NACK;
+        } else {
+            self.status &= !status::NACK;
+        }
+    }
+}
+
+/// 24C02 EEPROM write cycle time in CPU cycles (5 ms)
+pub const EEPROM_WRITE_CYCLES: u64 = 60_000;
+
+/// 24C02-style I2C EEPROM (256 bytes, 8-byte pages)
+///
+/// A write sets the address pointer with its first byte and buffers the
+/// rest into the page, wrapping within it. STOP starts the write cycle,
+/// during which the chip doesn't acknowledge its address. Reads continue
+/// from the address pointer and wrap at the end of memory.
+// Allow dead code: only the tests attach one; the app has no way to yet.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct I2cEeprom {
+    /// Memory contents
+    data: Vec<u8>,
+    /// Page size in bytes
+    page_size: usize,
+    /// Address pointer
+    pointer: usize,
+    /// True until the word address of a write has been received
+    expect_address: bool,
+    /// Bytes of the pending page write (address, value)
+    pending: Vec<(usize, u8)>,
+    /// Cycles left in the write cycle
+    busy_cycles: u64,
+}
+
+impl Default for I2cEeprom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Allow dead code: only the tests attach one; the app has no way to yet.
+#[allow(dead_code)]
+impl I2cEeprom {
+    /// Creates an erased ($FF) 24C02
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            data: vec![0xFF; 256],
+            page_size: 8,
+            pointer: 0,
+            expect_address: false,
+            pending: Vec::new(),
+            busy_cycles: 0,
+        }
+    }
+
+    /// Returns the memory contents
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns true while a write cycle is in progress
+    #[must_use]
+    pub const fn is_busy(&self) -> bool {
+        self.busy_cycles > 0
+    }
+}
+
+impl I2cDevice for I2cEeprom {
+    fn start(&mut self, read: bool) -> bool {
+        if self.is_busy() {
+            return false;
+        }
+        self.expect_address = !read;
+        self.pending.clear();
+        true
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        if self.expect_address {
+            self.pointer = usize::from(byte) % self.data.len();
+            self.expect_address = false;
+        } else {
+            self.pending.push((self.pointer, byte));
+            let page = self.pointer - self.pointer % self.page_size;
+            self.pointer = page + (self.pointer + 1) % self.page_size;
+        }
+        true
+    }
+
+    fn read(&mut self) -> u8 {
+        let value = self.data[self.pointer];
+        self.pointer = (self.pointer + 1) % self.data.len();
+        value
+    }
+
+    fn stop(&mut self) {
+        if !self.pending.is_empty() {
+            for &(address, value) in &self.pending {
+                self.data[address] = value;
+            }
+            self.pending.clear();
+            self.busy_cycles = EEPROM_WRITE_CYCLES;
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.busy_cycles = self.busy_cycles.saturating_sub(cycles);
+    }
+}
+
+impl Clocked for I2cController {
+    fn tick(&mut self, cycles: u64) {
+        Self::tick(self, cycles);
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        (self.busy_cycles > 0).then_some(self.busy_cycles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a command to completion and returns the status register
+    fn run(i2c: &mut I2cController, cmd: u8) -> u8 {
+        i2c.write(regs::COMMAND, cmd);
+        i2c.tick(11 * BIT_CYCLES);
+        i2c.read(regs::STATUS)
+    }
+
+    #[test]
+    fn test_i2c_missing_device_nacks() {
+        let mut i2c = I2cController::new();
+        i2c.write(regs::CONTROL, control::IE);
+        i2c.write(regs::ADDRESS, 0x50);
+        i2c.write(regs::COMMAND, command::START);
+        assert_eq!(i2c.read(regs::STATUS) & status::BUSY, status::BUSY);
+        i2c.tick(10 * BIT_CYCLES);
+        assert_eq!(
+            i2c.read(regs::STATUS),
+            status::DONE | status::NACK | status::BUS
+        );
+        assert!(i2c.interrupt_pending());
+
+        assert_eq!(
+            run(&mut i2c, command::READ | command::STOP) & status::BUS,
+            0
+        );
+        assert_eq!(i2c.read(regs::DATA), 0xFF);
+    }
+
+    #[test]
+    fn test_i2c_eeprom_busy_after_page_write() {
+        let eeprom = Arc::new(Mutex::new(I2cEeprom::new()));
+        let mut i2c = I2cController::new();
+        i2c.attach(0x50, Box::new(Arc::clone(&eeprom)));
+        i2c.write(regs::ADDRESS, 0x50);
+
+        assert_eq!(run(&mut i2c, command::START) & status::NACK, 0);
+        for byte in [0x06, 1, 2, 3] {
+            i2c.write(regs::DATA, byte);
+            assert_eq!(run(&mut i2c, command::WRITE) & status::NACK, 0);
+        }
+        run(&mut i2c, command::STOP);
+        // The page wraps from $07 back to $00
+        assert_eq!(
+            &eeprom.lock().unwrap().data()[..8],
+            &[3, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 1, 2]
+        );
+
+        // NACK until the write cycle ends
+        assert_ne!(
+            run(&mut i2c, command::START | command::STOP) & status::NACK,
+            0
+        );
+        i2c.tick(EEPROM_WRITE_CYCLES);
+        assert_eq!(
+            run(&mut i2c, command::START | command::STOP) & status::NACK,
+            0
+        );
+
+        // Random read: set the pointer, then a repeated START for reading
+        run(&mut i2c, command::START);
+        i2c.write(regs::DATA, 0x07);
+        run(&mut i2c, command::WRITE);
+        run(&mut i2c, command::START | command::READ);
+        run(&mut i2c, command::READ);
+        assert_eq!(i2c.read(regs::DATA), 2);
+        // Sequential reads cross page boundaries
+        run(&mut i2c, command::READ | command::NACK | command::STOP);
+        assert_eq!(i2c.read(regs::DATA), 0xFF);
+    }
+}
@@ -0,0 +1,191 @@
+//! Emulator Instances
+//!
+//! The host can run several machines side by side. Every `Sbc` routes its
+//! MMIO to its own devices, so instances share nothing but the process.
+//! `Instances` keeps them by id: instance 0 is the primary instance, which
+//! commands use when they aren't given an id, and `create` hands out the
+//! other ids without reusing them.
+
+use std::collections::BTreeMap;
+
+/// Identifies an emulator instance
+pub type InstanceId = u32;
+
+/// The instance commands use by default
+pub const PRIMARY_INSTANCE: InstanceId = 0;
+
+/// An event payload tagged with the instance that produced it
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct InstanceEvent<T> {
+    /// Instance the event comes from
+    pub instance: InstanceId,
+    /// The event itself
+    pub payload: T,
+}
+
+/// Emulator instances by id
+#[derive(Debug)]
+pub struct Instances<T> {
+    entries: BTreeMap<InstanceId, T>,
+    /// Next id `create` hands out
+    next_id: InstanceId,
+}
+
+impl<T> Default for Instances<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Instances<T> {
+    /// Creates an empty registry
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            next_id: PRIMARY_INSTANCE + 1,
+        }
+    }
+
+    /// Returns an instance
+    #[must_use]
+    pub fn get(&self, id: InstanceId) -> Option<&T> {
+        self.entries.get(&id)
+    }
+
+    /// Returns an instance for modification
+    pub fn get_mut(&mut self, id: InstanceId) -> Option<&mut T> {
+        self.entries.get_mut(&id)
+    }
+
+    /// Returns an instance, creating it with `create` if there is none
+    pub fn get_or_insert_with(&mut self, id: InstanceId, create: impl FnOnce() -> T) -> &mut T {
+        self.entries.entry(id).or_insert_with(create)
+    }
+
+    /// Stores an instance under `id`, returning the one it replaces
+    pub fn insert(&mut self, id: InstanceId, instance: T) -> Option<T> {
+        self.next_id = self.next_id.max(id.saturating_add(1));
+        self.entries.insert(id, instance)
+    }
+
+    /// Stores an instance under a new id and returns the id
+    pub fn create(&mut self, instance: T) -> InstanceId {
+        let id = self.next_id;
+        self.insert(id, instance);
+        id
+    }
+
+    /// Removes an instance, returning it
+    pub fn remove(&mut self, id: InstanceId) -> Option<T> {
+        self.entries.remove(&id)
+    }
+
+    /// Returns the ids of all instances in ascending order
+    #[must_use]
+    pub fn ids(&self) -> Vec<InstanceId> {
+        self.entries.keys().copied().collect()
+    }
+
+    /// Removes every instance
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::BackgroundRun;
+    use crate::sbc::Sbc;
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Builds a machine running a program that prints `text` `count` times
+    /// through the UART and exits
+    fn printing_machine(text: &str, count: u32) -> Arc<Mutex<Sbc>> {
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm
+            .assemble_source(
+                &format!(
+                    "
+        move.l  #{},d2
+outer:  lea     $E02000,a0
+inner:  move.b  (a0)+,d0
+        beq.s   next
+        move.b  d0,$A00000
+        bra.s   inner
+next:   subq.l  #1,d2
+        bne.s   outer
+        moveq   #0,d0
+        trap    #0
+",
+                    count
+                ),
+                Path::new("<test>"),
+            )
+            .unwrap();
+        sbc.load_app(&app, None).unwrap();
+        let mut data = text.as_bytes().to_vec();
+        data.push(0);
+        sbc.write_memory(0x00E0_2000, &data, false);
+        sbc.run_app(None);
+        Arc::new(Mutex::new(sbc))
+    }
+
+    #[test]
+    fn test_instances_ids() {
+        let mut instances = Instances::new();
+        assert_eq!(instances.insert(PRIMARY_INSTANCE, "primary"), None);
+        let first = instances.create("first");
+        let second = instances.create("second");
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(instances.remove(first), Some("first"));
+        // Ids aren't reused, even after a removal
+        assert_eq!(instances.create("third"), 3);
+        assert_eq!(instances.insert(7, "seventh"), None);
+        assert_eq!(instances.create("eighth"), 8);
+        assert_eq!(instances.ids(), vec![0, 2, 3, 7, 8]);
+        assert_eq!(instances.insert(PRIMARY_INSTANCE, "new"), Some("primary"));
+        assert_eq!(instances.get(PRIMARY_INSTANCE), Some(&"new"));
+        assert_eq!(*instances.get_or_insert_with(9, || "ninth"), "ninth");
+        instances.clear();
+        assert!(instances.ids().is_empty());
+    }
+
+    #[test]
+    fn test_instances_run_side_by_side() {
+        let mut instances = Instances::new();
+        instances.insert(PRIMARY_INSTANCE, printing_machine("ab", 300));
+        let other = instances.create(printing_machine("XYZ", 200));
+
+        // Run both at once, each on its own worker
+        let (tx, rx) = mpsc::channel();
+        let runs: Vec<_> = [PRIMARY_INSTANCE, other]
+            .into_iter()
+            .map(|id| {
+                let tx = tx.clone();
+                BackgroundRun::start(
+                    Arc::clone(instances.get(id).unwrap()),
+                    |_| {},
+                    move |stopped| tx.send(stopped.stop).unwrap(),
+                )
+            })
+            .collect();
+        for _ in &runs {
+            let stop = rx.recv_timeout(Duration::from_secs(30)).unwrap();
+            assert_eq!(stop, crate::debugger::StopReason::Exit { code: 0 });
+        }
+        drop(runs);
+
+        let output = |id| {
+            let sbc = instances.get(id).unwrap();
+            String::from_utf8(sbc.lock().unwrap().drain_output()).unwrap()
+        };
+        assert_eq!(output(PRIMARY_INSTANCE), "ab".repeat(300));
+        assert_eq!(output(other), "XYZ".repeat(200));
+    }
+}
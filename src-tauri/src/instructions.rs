@@ -529,7 +529,9 @@ pub fn ext(
     ///
     /// # Flags
     /// N and V are cleared. Z is set. C is cleared.
-    /// Edge cases: None beyond standard M68K addressing and size rules.
+    /// Edge cases: with `read_first`, a memory destination is read before it
+    /// is cleared, like the 68000's read-modify-write CLR cycle (this matters
+    /// for registers with read side effects).
     // Allow clippy::too_many_arguments: instruction handlers mirror M68K operand shapes.
     #[allow(clippy::too_many_arguments)]
     pub fn clr(
@@ -537,6 +539,7 @@ pub fn clr(
         memory: &mut Memory,
         opcode: u16,
         pc: u32,
+        read_first: bool,
     ) -> InstructionResult {
         let size = match (opcode >> 6) & 0x03 {
             0b00 => OperandSize::Byte,
@@ -558,6 +561,10 @@ pub fn clr(
 
         let (ea, new_pc) = EaResolver::resolve(addr_mode, reg, size, registers, memory, pc);
 
+        if read_first && matches!(ea, EffectiveAddress::Memory(_)) {
+            let _ = EaResolver::read_operand(ea, size, registers, memory);
+        }
+
         // Write zero to the destination
         EaResolver::write_operand(ea, size, 0, registers, memory);
 
@@ -0,0 +1,383 @@
+//! Interrupt Controller Emulation
+//!
+//! This module emulates a small interrupt controller on the expansion bus.
+//! Every peripheral's interrupt line is routed through it rather than wired
+//! straight to a fixed IPL. Each source has an enable bit and a programmable
+//! priority (the IPL it requests), and the controller can supply per-source
+//! vectors during the CPU's interrupt acknowledge cycle.
+//!
+//! ## Sources
+//!
+//! | Bit | Source       | Default priority |
+//! |-----|--------------|------------------|
+//! | 0   | UART         | 1                |
+//! | 1   | `CompactFlash` | 2              |
+//! | 2   | RTC          | 3                |
+//! | 3   | GPIO         | 4                |
+//! | 4   | Timer        | 5                |
+//! | 5   | SPI          | 2                |
+//! | 6   | I2C          | 2                |
+//!
+//! ## Register Map (offsets from $800040)
+//!
+//! | Offset | Read                       | Write                      |
+//! |--------|----------------------------|----------------------------|
+//! | 0      | Pending (request lines)    | Acknowledge (write 1s)     |
+//! | 2      | Enable                     | Enable                     |
+//! | 4      | Status (pending & enabled) | -                          |
+//! | 6      | Vector base                | Vector base                |
+//! | 8      | Last acknowledged source   | -                          |
+//! | 16-28  | Source 0-6 priority        | Source 0-6 priority (0-7)  |
+//!
+//! A priority of 0 masks the source like clearing its enable bit. Reset
+//! clears the enable register, so every source starts masked; the ROM
+//! unmasks the UART for its break-triggered serial loader.
+//!
+//! ## Pending Bits
+//!
+//! The pending register shows each source's request line. The UART latches
+//! its request: the bit clears when the CPU acknowledges the interrupt or
+//! when the guest writes a 1 to it. The other sources hold their lines until
+//! the guest clears the condition at the device (reading the CF status
+//! register, or clearing the RTC, GPIO or timer flags).
+//!
+//! ## Arbitration and Vectors
+//!
+//! The enabled source with the highest priority wins; ties go to the lowest
+//! source number. It is serviced when its priority is above the CPU's IPL,
+//! so a higher-priority source preempts a running handler. With a vector
+//! base of 0 the CPU uses the autovector for the priority level; otherwise
+//! source N supplies vector base + N.
+//!
+//! ## External Requests
+//!
+//! The host can latch one request of its own (see `Sbc::inject_interrupt`),
+//! which arbitrates as source `EXTERNAL` alongside the peripherals: it is
+//! serviced when its level is above the CPU's IPL, and ties go to the
+//! peripherals. It autovectors unless it names a vector, bypasses the
+//! enable register, and clears when the CPU acknowledges it.
+
+/// Number of interrupt sources
+pub const SOURCE_COUNT: usize = 7;
+
+/// Interrupt source numbers
+pub mod sources {
+    /// UART
+    pub const UART: u8 = 0;
+    /// `CompactFlash` INTRQ
+    pub const CF: u8 = 1;
+    /// Real-time clock
+    pub const RTC: u8 = 2;
+    /// GPIO port
+    pub const GPIO: u8 = 3;
+    /// Interval timer
+    pub const TIMER: u8 = 4;
+    /// SPI controller
+    pub const SPI: u8 = 5;
+    /// I2C controller
+    pub const I2C: u8 = 6;
+}
+
+/// Source number of the host's external request
+pub const EXTERNAL: u8 = SOURCE_COUNT as u8;
+
+/// Interrupt controller register offsets
+pub mod regs {
+    /// Pending request lines / acknowledge
+    pub const PENDING: u32 = 0;
+    /// Source enable mask
+    pub const ENABLE: u32 = 2;
+    /// Pending and enabled sources
+    pub const STATUS: u32 = 4;
+    /// Vector base (0 = autovector)
+    pub const VECTOR: u32 = 6;
+    /// Last acknowledged source ($FF = none)
+    pub const CURRENT: u32 = 8;
+    /// Priority of source 0 (source N at `PRIORITY + 2 * N`)
+    pub const PRIORITY: u32 = 16;
+}
+
+/// Default priority of each source after reset
+pub const DEFAULT_PRIORITIES: [u8; SOURCE_COUNT] = [1, 2, 3, 4, 5, 2, 2];
+
+/// An interrupt the controller is presenting to the CPU
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterruptRequest {
+    /// Source number
+    pub source: u8,
+    /// Requested IPL (1-7)
+    pub level: u8,
+    /// Vector number, or `None` to autovector
+    pub vector: Option<u8>,
+}
+
+/// Interrupt controller peripheral
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct InterruptController {
+    /// Request lines sampled from the sources
+    pending: u8,
+    /// Source enable mask
+    enable: u8,
+    /// Vector base (0 = autovector)
+    vector_base: u8,
+    /// Priority of each source
+    priorities: [u8; SOURCE_COUNT],
+    /// Last acknowledged source ($FF = none)
+    current: u8,
+    /// Sources the guest acknowledged through the pending register
+    acks: u8,
+    /// External request latched by the host: level and optional vector
+    #[serde(default)]
+    external: Option<(u8, Option<u8>)>,
+}
+
+impl Default for InterruptController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InterruptController {
+    /// Creates an interrupt controller with every source masked
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            pending: 0,
+            enable: 0,
+            vector_base: 0,
+            priorities: DEFAULT_PRIORITIES,
+            current: 0xFF,
+            acks: 0,
+            external: None,
+        }
+    }
+
+    /// Resets the controller, masking every source
+    pub const fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Reads a controller register
+    #[must_use]
+    pub const fn read(&self, offset: u32) -> u8 {
+        match offset {
+            regs::PENDING => self.pending,
+            regs::ENABLE => self.enable,
+            regs::STATUS => self.status(),
+            regs::VECTOR => self.vector_base,
+            regs::CURRENT => self.current,
+            _ => match Self::priority_index(offset) {
+                Some(index) => self.priorities[index],
+                None => 0xFF,
+            },
+        }
+    }
+
+    /// Writes a controller register
+    pub const fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            regs::PENDING => {
+                let mask = value & Self::source_mask();
+                self.acks |= mask;
+                self.pending &= !mask;
+            }
+            regs::ENABLE => self.enable = value & Self::source_mask(),
+            regs::VECTOR => self.vector_base = value,
+            _ => {
+                if let Some(index) = Self::priority_index(offset) {
+                    self.priorities[index] = value & 0x07;
+                }
+            }
+        }
+    }
+
+    /// Sets the priority of a source (host-side wiring)
+    pub const fn set_priority(&mut self, source: u8, level: u8) {
+        self.priorities[source as usize] = level & 0x07;
+    }
+
+    /// Returns the sources that are both pending and enabled
+    #[must_use]
+    pub const fn status(&self) -> u8 {
+        self.pending & self.enable
+    }
+
+    /// Updates the request lines sampled from the sources
+    pub const fn set_lines(&mut self, lines: u8) {
+        self.pending = lines & Self::source_mask();
+    }
+
+    /// Returns and clears the sources the guest acknowledged
+    pub const fn take_acks(&mut self) -> u8 {
+        let acks = self.acks;
+        self.acks = 0;
+        acks
+    }
+
+    /// Latches an external request at `level` (1-7), optionally with its
+    /// own vector
+    ///
+    /// Returns false, leaving the latch alone, if one is already waiting.
+    pub const fn assert_external(&mut self, level: u8, vector: Option<u8>) -> bool {
+        if self.external.is_some() {
+            return false;
+        }
+        self.external = Some((level & 0x07, vector));
+        true
+    }
+
+    /// Returns whether an external request is waiting to be acknowledged
+    #[must_use]
+    pub const fn external_pending(&self) -> bool {
+        self.external.is_some()
+    }
+
+    /// Returns the interrupt to present to the CPU, if any beats `ipl`
+    #[must_use]
+    pub const fn request(&self, ipl: u8) -> Option<InterruptRequest> {
+        let active = self.status();
+        let mut best: Option<InterruptRequest> = None;
+        let mut source = 0;
+        while source < SOURCE_COUNT as u8 {
+            let level = self.priorities[source as usize];
+            if active & (1 << source) != 0 && level > ipl {
+                let better = match best {
+                    Some(current) => level > current.level,
+                    None => true,
+                };
+                if better {
+                    let vector = if self.vector_base == 0 {
+                        None
+                    } else {
+                        Some(self.vector_base.wrapping_add(source))
+                    };
+                    best = Some(InterruptRequest {
+                        source,
+                        level,
+                        vector,
+                    });
+                }
+            }
+            source += 1;
+        }
+        if let Some((level, vector)) = self.external {
+            let better = match best {
+                Some(current) => level > current.level,
+                None => level > ipl,
+            };
+            if better {
+                best = Some(InterruptRequest {
+                    source: EXTERNAL,
+                    level,
+                    vector,
+                });
+            }
+        }
+        best
+    }
+
+    /// Records the CPU's acknowledge of a source's interrupt
+    ///
+    /// Returns true if the source latches its request and the caller must
+    /// clear it at the device (the UART).
+    pub const fn acknowledge(&mut self, source: u8) -> bool {
+        self.current = source;
+        if source == EXTERNAL {
+            self.external = None;
+        } else if source == sources::UART {
+            self.pending &= !(1 << sources::UART);
+            return true;
+        }
+        false
+    }
+
+    /// Mask covering every implemented source
+    const fn source_mask() -> u8 {
+        (1 << SOURCE_COUNT) - 1
+    }
+
+    /// Maps a priority register offset to its source index
+    const fn priority_index(offset: u32) -> Option<usize> {
+        if offset < regs::PRIORITY || !offset.is_multiple_of(2) {
+            return None;
+        }
+        let index = ((offset - regs::PRIORITY) / 2) as usize;
+        if index < SOURCE_COUNT {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intc_reset_masks_everything() {
+        let mut intc = InterruptController::new();
+        intc.set_lines(0x1F);
+        assert_eq!(intc.read(regs::PENDING), 0x1F);
+        assert_eq!(intc.read(regs::STATUS), 0);
+        assert_eq!(intc.request(0), None);
+    }
+
+    #[test]
+    fn test_intc_priority_and_vectors() {
+        let mut intc = InterruptController::new();
+        intc.write(regs::ENABLE, 0x11);
+        intc.write(regs::PRIORITY, 2);
+        intc.write(regs::PRIORITY + 8, 6);
+        intc.set_lines(0x11);
+
+        let req = intc.request(0).unwrap();
+        assert_eq!(
+            (req.source, req.level, req.vector),
+            (sources::TIMER, 6, None)
+        );
+        assert_eq!(intc.request(6), None);
+        assert_eq!(intc.request(1).unwrap().level, 6);
+
+        intc.write(regs::VECTOR, 64);
+        assert_eq!(intc.request(0).unwrap().vector, Some(68));
+
+        // Priority 0 masks the source
+        intc.write(regs::PRIORITY + 8, 0);
+        assert_eq!(intc.request(0).unwrap().source, sources::UART);
+    }
+
+    #[test]
+    fn test_intc_acknowledge() {
+        let mut intc = InterruptController::new();
+        intc.set_lines(0x03);
+        assert!(intc.acknowledge(sources::UART));
+        assert!(!intc.acknowledge(sources::CF));
+        assert_eq!(intc.read(regs::PENDING), 0x02);
+        assert_eq!(intc.read(regs::CURRENT), sources::CF);
+
+        intc.write(regs::PENDING, 0x03);
+        assert_eq!(intc.take_acks(), 0x03);
+        assert_eq!(intc.take_acks(), 0);
+    }
+
+    #[test]
+    fn test_intc_external_request() {
+        let mut intc = InterruptController::new();
+        assert!(intc.assert_external(3, None));
+        assert!(!intc.assert_external(6, Some(100)));
+        assert_eq!(intc.request(3), None);
+
+        // Ties go to the peripherals
+        intc.write(regs::ENABLE, 0x04);
+        intc.set_lines(0x04);
+        assert_eq!(intc.request(0).unwrap().source, sources::RTC);
+        intc.set_lines(0);
+        let req = intc.request(2).unwrap();
+        assert_eq!((req.source, req.level, req.vector), (EXTERNAL, 3, None));
+
+        assert!(!intc.acknowledge(EXTERNAL));
+        assert!(!intc.external_pending());
+        assert_eq!(intc.request(0), None);
+    }
+}
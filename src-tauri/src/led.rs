@@ -0,0 +1,132 @@
+//! LED Bar Emulation
+//!
+//! This module emulates a bank of 8 status LEDs driven by an output latch on
+//! the expansion bus. Value changes are queued so the host can show them
+//! without polling.
+//!
+//! ## Register Map (offsets from $8000A0)
+//!
+//! | Offset | Read        | Write       |
+//! |--------|-------------|-------------|
+//! | 0      | LED latch   | LED latch   |
+//!
+//! A set bit turns its LED on.
+//!
+//! ## Compatibility
+//!
+//! LED 0 is the board's original status LED, driven by the UART's RTS line
+//! (MCR bit 1). Toggling RTS changes bit 0 of the latch, and writing the
+//! latch changes LED 0, so ROMs that blink the LED through the UART still
+//! work. Whichever changed last wins.
+
+use std::collections::VecDeque;
+
+use crate::panel::Peripheral;
+
+/// Maximum number of queued change events
+const MAX_EVENTS: usize = 256;
+
+/// LED bar register offsets
+pub mod regs {
+    /// LED latch
+    pub const LATCH: u32 = 0;
+}
+
+/// 8-LED output latch
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct LedBar {
+    /// LED latch (1 = on)
+    value: u8,
+    /// Change events not yet collected by the host
+    events: VecDeque<u8>,
+}
+
+impl LedBar {
+    /// Creates an LED bar with every LED off
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            value: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Turns every LED off
+    pub fn reset(&mut self) {
+        self.set(0);
+    }
+
+    /// Reads an LED bar register
+    #[must_use]
+    pub const fn read(&self, offset: u32) -> u8 {
+        match offset {
+            regs::LATCH => self.value,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes an LED bar register
+    pub fn write(&mut self, offset: u32, value: u8) {
+        if offset == regs::LATCH {
+            self.set(value);
+        }
+    }
+
+    /// Drives LED 0 from the UART's RTS line
+    pub fn set_status_led(&mut self, on: bool) {
+        self.set((self.value & !0x01) | u8::from(on));
+    }
+
+    /// Returns the LED latch
+    #[must_use]
+    pub const fn value(&self) -> u8 {
+        self.value
+    }
+
+    /// Returns and clears the queued change events
+    pub fn take_events(&mut self) -> Vec<u8> {
+        self.events.drain(..).collect()
+    }
+
+    /// Updates the latch, queueing an event if it changed
+    fn set(&mut self, value: u8) {
+        if value == self.value {
+            return;
+        }
+        self.value = value;
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(value);
+    }
+}
+
+impl Peripheral for LedBar {
+    fn ui_state(&self) -> serde_json::Value {
+        serde_json::json!({ "value": self.value })
+    }
+
+    fn debug_dump(&self) -> serde_json::Value {
+        serde_json::json!({
+            "latch": self.value,
+            "pendingEvents": self.events.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_led_bar_events_on_change() {
+        let mut leds = LedBar::new();
+        leds.write(regs::LATCH, 0x81);
+        leds.write(regs::LATCH, 0x81);
+        leds.set_status_led(false);
+        leds.set_status_led(true);
+        assert_eq!(leds.read(regs::LATCH), 0x81);
+        assert_eq!(leds.take_events(), vec![0x81, 0x80, 0x81]);
+        assert!(leds.take_events().is_empty());
+    }
+}
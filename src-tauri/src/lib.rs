@@ -73,274 +73,1479 @@
 mod addressing;
 mod assembler;
 mod bus;
+mod buzzer;
 mod cfcard;
+mod cfimage;
+mod config;
 mod cpu;
+mod debugger;
+mod diagnostics;
+mod dipswitch;
+mod disasm;
+mod error;
+mod expression;
+mod gpio;
+mod i2c;
+mod instances;
 mod instructions;
+mod intc;
+mod led;
+mod loader;
 mod memory;
+mod object;
+mod panel;
+mod profile;
+mod program;
 mod registers;
+mod rtc;
+mod runner;
 mod sbc;
+mod scheduler;
+mod snapshot;
+mod spi;
+mod state;
 mod test_runner;
+mod throttle;
+mod timer;
+mod trace;
 mod uart;
+mod watchdog;
 
-use sbc::Sbc;
-use std::sync::{Arc, Mutex};
+use error::EmulatorError;
+use instances::{InstanceEvent, InstanceId, PRIMARY_INSTANCE};
+use program::{AssembledProgram, LoadedProgram};
+use registers::CpuState;
+use sbc::{InterruptOutcome, Sbc};
+use state::{EmulatorStatus, Flux32State};
+use tauri::{Emitter, Manager, State};
 
-/// The Flux32 emulator state - wrapped in `Arc<Mutex<>>` for thread safety
-///
-/// The SBC now uses `Arc<Mutex<>>` internally, making it `Send + Sync` automatically.
-/// `Flux32Emulator` wraps this in another `Arc<Mutex<>>` for Tauri IPC access.
-pub struct Flux32Emulator {
-    sbc: Arc<Mutex<Sbc>>,
+/// Returns a machine's CPU state as a JSON-serializable structure
+fn cpu_state(sbc: &Sbc) -> CpuState {
+    CpuState::from(sbc.registers())
 }
 
-impl Flux32Emulator {
-    fn new() -> Self {
-        Self {
-            sbc: Arc::new(Mutex::new(Sbc::new())),
-        }
-    }
+/// Host input queue occupancy
+#[derive(serde::Serialize)]
+pub struct UartQueueStatus {
+    /// Bytes waiting for room in the RX FIFO
+    queued: usize,
+    /// Queue size in bytes
+    capacity: usize,
+}
 
-    /// Execute a single instruction step
-    fn step(&self) -> Result<(), String> {
-        self.sbc.lock().unwrap().step();
-        Ok(())
-    }
+/// Emits an event tagged with the instance it comes from
+fn emit_event<T: serde::Serialize + Clone>(
+    app: &tauri::AppHandle,
+    event: &str,
+    instance: InstanceId,
+    payload: T,
+) {
+    let _ = app.emit(event, InstanceEvent { instance, payload });
+}
 
-    /// Get the CPU state as a JSON-serializable structure
-    fn get_cpu_state(&self) -> CpuState {
-        let sbc = self.sbc.lock().unwrap();
-        let regs = sbc.registers();
-        CpuState {
-            d: regs.d.to_vec(),
-            a: regs.a[0..7].to_vec(), // A0-A6 (A7 is SP)
-            pc: regs.pc,
-            sr: regs.sr,
-            usp: regs.usp(),
-            ssp: regs.get_ssp(),
-        }
+/// Emits queued peripheral events (GPIO output, LED bar changes and buzzer
+/// tones) to the frontend
+fn emit_peripheral_events(app: &tauri::AppHandle, instance: InstanceId, sbc: &mut Sbc) {
+    for state in sbc.take_gpio_events() {
+        emit_event(app, "gpio-output", instance, state);
+    }
+    for value in sbc.take_led_events() {
+        emit_event(app, "led-change", instance, value);
+    }
+    for tone in sbc.take_buzzer_events() {
+        emit_event(app, "buzzer", instance, tone);
     }
 }
 
-impl Default for Flux32Emulator {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Initialize an emulator instance (the primary one by default)
+///
+/// With `rom_path` or `config`, the instance is (re)created: `config` builds
+/// the machine (missing fields take their defaults) and `rom_path` boots
+/// from that ROM image instead of the embedded one, overriding the config's.
+/// An existing instance is torn down first, stopping its background run.
+/// Returns the configuration the instance runs with.
+#[tauri::command]
+fn emulator_init(
+    state: State<'_, Flux32State>,
+    rom_path: Option<String>,
+    config: Option<config::SbcConfig>,
+    instance: Option<InstanceId>,
+) -> Result<config::SbcConfig, EmulatorError> {
+    state.init(rom_path, config, instance)
 }
 
-/// CPU register state for serialization
-#[derive(serde::Serialize)]
-pub struct CpuState {
-    d: Vec<u32>,
-    a: Vec<u32>,
-    pc: u32,
-    sr: u16,
-    usp: u32,
-    ssp: u32,
+/// Create an emulator instance alongside the existing ones and return its
+/// id
+///
+/// `config` builds the machine as it does for `emulator_init`.
+#[tauri::command]
+fn emulator_create_instance(
+    state: State<'_, Flux32State>,
+    config: Option<config::SbcConfig>,
+) -> Result<InstanceId, EmulatorError> {
+    state.create_instance(config)
+}
+
+/// Destroy an emulator instance, stopping its background run and flushing
+/// its CF card
+#[tauri::command]
+fn emulator_destroy_instance(
+    state: State<'_, Flux32State>,
+    instance: InstanceId,
+) -> Result<(), EmulatorError> {
+    state.destroy_instance(instance)
+}
+
+/// List the ids of the emulator instances
+#[tauri::command]
+fn emulator_list_instances(state: State<'_, Flux32State>) -> Vec<InstanceId> {
+    state.ids()
 }
 
-/// Emulator status information
+/// Execute a single instruction step
+#[tauri::command]
+fn emulator_step(
+    app: tauri::AppHandle,
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<String, EmulatorError> {
+    state.with_sbc(instance, |sbc| {
+        sbc.step();
+        emit_peripheral_events(&app, instance.unwrap_or(PRIMARY_INSTANCE), sbc);
+    })?;
+    Ok("Step executed".to_string())
+}
+
+/// Execute a single instruction step and report what it did
+///
+/// Unlike `emulator_step`, returns the PCs around the step, the opcode, the
+/// cycles taken, any exception vector, and the UART output the instruction
+/// produced (which doesn't arrive through `emulator_read_uart` as well).
+#[tauri::command]
+fn emulator_step_ex(
+    app: tauri::AppHandle,
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<sbc::StepResult, EmulatorError> {
+    state.with_sbc(instance, |sbc| {
+        let step = sbc.step_detailed();
+        emit_peripheral_events(&app, instance.unwrap_or(PRIMARY_INSTANCE), sbc);
+        step
+    })
+}
+
+/// Most PCs `emulator_step_n` records
+const MAX_STEP_TRACE: usize = 10_000;
+
+/// Outcome of `emulator_step_n`, `emulator_step_over` and
+/// `emulator_step_out`
 #[derive(serde::Serialize)]
-pub struct EmulatorStatus {
-    halted: bool,
-    cycles: u64,
-    executed: u64,
+pub struct StepNResult {
+    /// Register state after the batch
+    registers: CpuState,
+    /// Instructions and cycles executed, and why the batch stopped
+    run: debugger::RunResult,
+    /// PCs of the executed instructions (only from `emulator_step_n` with
+    /// `trace`)
+    pcs: Vec<u32>,
+    /// UART output produced by the batch
+    output: Vec<u8>,
+    /// Values of the watch expressions after the batch
+    watches: Vec<debugger::WatchValue>,
 }
 
-/// Global emulator state using Mutex for thread-safe access.
+/// Execute up to `count` instructions in one round trip
 ///
-/// The Mutex provides thread-safe access to the emulator instance. All access
-/// is single-threaded through the main Tauri event loop, but Mutex ensures
-/// safety if the pattern changes in the future.
-static EMULATOR: std::sync::Mutex<Option<Flux32Emulator>> = std::sync::Mutex::new(None);
+/// The batch stops early when the CPU halts, or on a breakpoint or
+/// watchpoint with `stop_on_breakpoint`. With `trace`, the PCs of the first
+/// `MAX_STEP_TRACE` executed instructions are returned.
+#[tauri::command]
+fn emulator_step_n(
+    app: tauri::AppHandle,
+    state: State<'_, Flux32State>,
+    count: u64,
+    stop_on_breakpoint: bool,
+    trace: Option<bool>,
+    instance: Option<InstanceId>,
+) -> Result<StepNResult, EmulatorError> {
+    let sbc = state.machine(instance)?;
+    let trace_limit = if trace.unwrap_or(false) {
+        MAX_STEP_TRACE
+    } else {
+        0
+    };
+    let mut sbc = sbc.lock().unwrap();
+    let batch = sbc.step_n(count, stop_on_breakpoint, trace_limit);
+    emit_peripheral_events(&app, instance.unwrap_or(PRIMARY_INSTANCE), &mut sbc);
+    Ok(StepNResult {
+        registers: cpu_state(&sbc),
+        run: batch.run,
+        pcs: batch.pcs,
+        output: batch.output,
+        watches: sbc.watch_values(),
+    })
+}
+
+/// Most cycles `emulator_step_over` and `emulator_step_out` run unless the
+/// caller says otherwise
+const DEFAULT_STEP_CYCLES: u64 = 10_000_000;
 
-/// Initialize a new emulator instance
+/// Execute the instruction at the PC, running a JSR, BSR or TRAP until it
+/// returns
+///
+/// Stops early on a breakpoint, watchpoint or halt; after `max_cycles` the
+/// stop reason is `budgetExhausted`, and `stepsCompleted` once the call
+/// returned.
 #[tauri::command]
-fn emulator_init() -> Result<String, String> {
-    let mut emulator = EMULATOR.lock().unwrap();
-    if emulator.is_none() {
-        *emulator = Some(Flux32Emulator::new());
-    }
-    Ok("Emulator initialized".to_string())
+fn emulator_step_over(
+    app: tauri::AppHandle,
+    state: State<'_, Flux32State>,
+    max_cycles: Option<u64>,
+    instance: Option<InstanceId>,
+) -> Result<StepNResult, EmulatorError> {
+    step_until_return(&app, &state, instance, |sbc| {
+        sbc.step_over(max_cycles.unwrap_or(DEFAULT_STEP_CYCLES))
+    })
 }
 
-/// Execute a single instruction step
+/// Run until the current subroutine returns to its caller, stopping like
+/// `emulator_step_over`
 #[tauri::command]
-fn emulator_step() -> Result<String, String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        emulator.step()?;
-        Ok("Step executed".to_string())
-    } else {
-        Err("Emulator not initialized".to_string())
-    }
+fn emulator_step_out(
+    app: tauri::AppHandle,
+    state: State<'_, Flux32State>,
+    max_cycles: Option<u64>,
+    instance: Option<InstanceId>,
+) -> Result<StepNResult, EmulatorError> {
+    step_until_return(&app, &state, instance, |sbc| {
+        sbc.step_out(max_cycles.unwrap_or(DEFAULT_STEP_CYCLES))
+    })
+}
+
+/// Runs a step-over or step-out on an instance and reports it
+fn step_until_return(
+    app: &tauri::AppHandle,
+    state: &Flux32State,
+    instance: Option<InstanceId>,
+    step: impl FnOnce(&mut Sbc) -> debugger::StepBatch,
+) -> Result<StepNResult, EmulatorError> {
+    let sbc = state.machine(instance)?;
+    let mut sbc = sbc.lock().unwrap();
+    let batch = step(&mut sbc);
+    emit_peripheral_events(app, instance.unwrap_or(PRIMARY_INSTANCE), &mut sbc);
+    Ok(StepNResult {
+        registers: cpu_state(&sbc),
+        run: batch.run,
+        pcs: batch.pcs,
+        output: batch.output,
+        watches: sbc.watch_values(),
+    })
 }
 
 /// Get the current CPU register state
 #[tauri::command]
-fn emulator_get_registers() -> Result<CpuState, String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        Ok(emulator.get_cpu_state())
-    } else {
-        Err("Emulator not initialized".to_string())
-    }
+fn emulator_get_registers(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<CpuState, EmulatorError> {
+    state.registers(instance)
+}
+
+/// Write a CPU register by name and return the updated register state
+///
+/// Accepts D0-D7, A0-A7 (or SP), PC, SR, USP and SSP. An SR write that
+/// changes the S bit swaps the active stack, and PC must be even.
+#[tauri::command]
+fn emulator_write_register(
+    state: State<'_, Flux32State>,
+    name: String,
+    value: u32,
+    instance: Option<InstanceId>,
+) -> Result<CpuState, EmulatorError> {
+    let register = name.parse::<registers::Register>()?;
+    state.try_with_sbc(instance, |sbc| {
+        sbc.registers_mut().write(register, value)?;
+        Ok(cpu_state(sbc))
+    })
 }
 
 /// Read a byte from memory at the given address
+///
+/// Fails where nothing answers (unpopulated RAM or a conflicting decode).
 #[tauri::command]
-fn emulator_read_byte(address: u32) -> Result<u8, String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        let sbc = emulator.sbc.lock().unwrap();
-        sbc.cpu()
-            .memory
-            .read_byte(address)
-            .map_err(|e| e.to_string())
-    } else {
-        Err("Emulator not initialized".to_string())
-    }
+fn emulator_read_byte(
+    state: State<'_, Flux32State>,
+    address: u32,
+    instance: Option<InstanceId>,
+) -> Result<u8, EmulatorError> {
+    state.try_with_sbc(instance, |sbc| {
+        if !sbc.host_readable(address) {
+            return Err(EmulatorError::Unmapped {
+                address: address & bus::ADDR_MASK,
+            });
+        }
+        Ok(sbc.cpu().memory.read_byte(address)?)
+    })
 }
 
 /// Read a block of bytes from memory
 #[tauri::command]
-fn emulator_read_memory(address: u32, length: usize) -> Result<Vec<u8>, String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        let sbc = emulator.sbc.lock().unwrap();
+fn emulator_read_memory(
+    state: State<'_, Flux32State>,
+    address: u32,
+    length: usize,
+    instance: Option<InstanceId>,
+) -> Result<Vec<u8>, EmulatorError> {
+    state.try_with_sbc(instance, |sbc| {
         (0..length)
             .map(|i| {
                 sbc.cpu()
                     .memory
                     .read_byte(address + i as u32)
-                    .map_err(|e| e.to_string())
+                    .map_err(EmulatorError::from)
             })
             .collect()
-    } else {
-        Err("Emulator not initialized".to_string())
-    }
+    })
 }
 
 /// Write a byte to memory at the given address
+///
+/// Fails at a write-protected address (ROM, unpopulated RAM or open bus),
+/// as `emulator_write_memory` stops there.
 #[tauri::command]
-fn emulator_write_byte(address: u32, value: u8) -> Result<(), String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        let mut sbc = emulator.sbc.lock().unwrap();
-        sbc.cpu_mut()
-            .memory
-            .write_byte(address, value)
-            .map_err(|e| e.to_string())
-    } else {
-        Err("Emulator not initialized".to_string())
+fn emulator_write_byte(
+    state: State<'_, Flux32State>,
+    address: u32,
+    value: u8,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.try_with_sbc(instance, |sbc| {
+        match sbc.write_memory(address, &[value], false).failed_at {
+            Some(address) => Err(EmulatorError::WriteProtected { address }),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Write a block of bytes to memory
+///
+/// Writes stop at the first write-protected address (ROM, unpopulated RAM
+/// or open bus), which the result reports along with the bytes written.
+/// `override_rom` patches ROM bytes into the ROM image instead.
+#[tauri::command]
+fn emulator_write_memory(
+    state: State<'_, Flux32State>,
+    address: u32,
+    data: Vec<u8>,
+    override_rom: Option<bool>,
+    instance: Option<InstanceId>,
+) -> Result<sbc::MemoryWriteResult, EmulatorError> {
+    state.with_sbc(instance, |sbc| {
+        sbc.write_memory(address, &data, override_rom.unwrap_or(false))
+    })
+}
+
+/// Fill a block of memory with a byte `value`, or with `pattern` repeated
+///
+/// Like `emulator_write_memory`, the fill stops at the first
+/// write-protected address, which the result reports along with the bytes
+/// written. With `dry_run` nothing is written and the result reports what
+/// the fill would do.
+#[tauri::command]
+fn emulator_fill_memory(
+    state: State<'_, Flux32State>,
+    address: u32,
+    length: usize,
+    value: Option<u8>,
+    pattern: Option<Vec<u8>>,
+    dry_run: Option<bool>,
+    instance: Option<InstanceId>,
+) -> Result<sbc::MemoryWriteResult, EmulatorError> {
+    let pattern = match (value, pattern) {
+        (Some(value), None) => vec![value],
+        (None, Some(pattern)) if !pattern.is_empty() => pattern,
+        (None, Some(_)) => return Err(EmulatorError::invalid("pattern", "Pattern is empty")),
+        (Some(_), Some(_)) => {
+            return Err(EmulatorError::invalid(
+                "pattern",
+                "Give either a value or a pattern, not both",
+            ))
+        }
+        (None, None) => {
+            return Err(EmulatorError::invalid(
+                "value",
+                "Give a value or a pattern to fill with",
+            ))
+        }
+    };
+    if length > bus::ADDR_MASK as usize + 1 {
+        return Err(EmulatorError::invalid(
+            "length",
+            format!("Fill length {length} is larger than the address space"),
+        ));
     }
+    state.with_sbc(instance, |sbc| {
+        sbc.fill_memory(address, length, &pattern, dry_run.unwrap_or(false))
+    })
 }
 
-/// Assemble M68K assembly code and return the binary
+/// Load a raw binary file into RAM (or ROM with `override_rom`) at
+/// `address`, returning the number of bytes loaded
+///
+/// With `start`, the PC is set to `address` and a halted CPU resumes.
 #[tauri::command]
-fn emulator_assemble(code: String) -> Result<Vec<u8>, String> {
+fn emulator_load_binary(
+    state: State<'_, Flux32State>,
+    path: String,
+    address: u32,
+    start: bool,
+    override_rom: Option<bool>,
+    instance: Option<InstanceId>,
+) -> Result<usize, EmulatorError> {
+    state.try_with_sbc(instance, |sbc| {
+        let size = sbc.load_binary_file(
+            std::path::Path::new(&path),
+            address,
+            override_rom.unwrap_or(false),
+        )?;
+        if start {
+            sbc.cpu_mut().set_pc(address);
+            sbc.cpu_mut().resume();
+        }
+        Ok(size)
+    })
+}
+
+/// File name diagnostics use for editor code
+const EDITOR_FILE: &str = "<editor>";
+
+/// Creates an assembler for editor code
+fn editor_assembler() -> assembler::Assembler {
     let mut asm = assembler::Assembler::new();
     // Add the rom directory as an include path so app.inc etc. can be found
     let rom_dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("rom");
     asm.include_paths.push(rom_dir);
-    let path = std::path::Path::new("<editor>");
-    asm.assemble_source(&code, path)
+    asm
+}
+
+/// Assembles editor code
+fn assemble_program(code: &str) -> Result<AssembledProgram, EmulatorError> {
+    let mut asm = editor_assembler();
+    let binary = asm.assemble_checked(code, std::path::Path::new(EDITOR_FILE))?;
+    Ok(AssembledProgram::from_assembler(&mut asm, binary))
+}
+
+/// Checks a ROM image option
+fn rom_image(
+    rom: Option<assembler::RomImage>,
+) -> Result<Option<assembler::RomImage>, EmulatorError> {
+    rom.map(assembler::RomImage::validate)
+        .transpose()
+        .map_err(|e| EmulatorError::invalid("rom", e))
+}
+
+/// Assemble M68K assembly code and return the binary
+///
+/// With `rom`, the binary is padded to a ROM image of that size, as the
+/// ROMSIZE directive does (the option wins over one in the code).
+#[tauri::command]
+fn emulator_assemble(
+    code: String,
+    rom: Option<assembler::RomImage>,
+) -> Result<Vec<u8>, EmulatorError> {
+    let mut asm = editor_assembler();
+    asm.rom = rom_image(rom)?;
+    Ok(asm.assemble_checked(&code, std::path::Path::new(EDITOR_FILE))?)
+}
+
+/// Assembled project code and where its includes came from
+#[derive(serde::Serialize)]
+pub struct ProjectAssembly {
+    /// Machine code
+    binary: Vec<u8>,
+    /// Includes in the order they were read, with the virtual file or disk
+    /// path each resolved to
+    includes: Vec<assembler::ResolvedInclude>,
+}
+
+/// Assemble editor code with a project's own include files
+///
+/// INCLUDE looks in `files` (file name to contents) first, then in
+/// `include_dirs` on disk, then in the ROM directory.
+#[tauri::command]
+fn emulator_assemble_project(
+    code: String,
+    files: Option<std::collections::HashMap<String, String>>,
+    include_dirs: Option<Vec<String>>,
+) -> Result<ProjectAssembly, EmulatorError> {
+    let mut asm = editor_assembler();
+    let dirs = include_dirs.unwrap_or_default();
+    asm.include_paths
+        .splice(0..0, dirs.into_iter().map(std::path::PathBuf::from));
+    asm.virtual_files = files.unwrap_or_default();
+    let binary = asm.assemble_checked(&code, std::path::Path::new(EDITOR_FILE))?;
+    Ok(ProjectAssembly {
+        binary,
+        includes: std::mem::take(&mut asm.includes),
+    })
 }
 
-/// Assemble code, load it into RAM at `APP_START`, and start execution
+/// Assemble editor code and write its symbol map to `path`
+///
+/// The `listing` format (the default) lists every symbol with its value and
+/// kind; `plain` writes the labels in the format
+/// `emulator_load_symbol_map` reads.
 #[tauri::command]
-fn emulator_assemble_and_load(code: String) -> Result<String, String> {
-    let binary = emulator_assemble(code)?;
-    if binary.is_empty() {
-        return Err("Assembly produced no output".to_string());
+fn emulator_save_symbol_map(
+    code: String,
+    path: String,
+    format: Option<assembler::MapFormat>,
+) -> Result<(), EmulatorError> {
+    let mut asm = editor_assembler();
+    asm.assemble_checked(&code, std::path::Path::new(EDITOR_FILE))?;
+    let text = format.unwrap_or_default().render(&asm.symbol_map());
+    std::fs::write(&path, text).map_err(|e| EmulatorError::from(e).with_path(&path))
+}
+
+/// Assemble editor code into Motorola S-records for an EPROM programmer,
+/// `record_length` data bytes to a record (16 by default)
+///
+/// Each ORG section becomes its own run of records, and the termination
+/// record carries the END address (or the origin).
+#[tauri::command]
+fn emulator_assemble_srecords(
+    code: String,
+    record_length: Option<usize>,
+) -> Result<String, EmulatorError> {
+    let record_length = record_length.unwrap_or(assembler::DEFAULT_SRECORD_LENGTH);
+    if !(1..=assembler::MAX_SRECORD_LENGTH).contains(&record_length) {
+        return Err(EmulatorError::invalid(
+            "recordLength",
+            format!(
+                "Record length must be 1 to {} bytes",
+                assembler::MAX_SRECORD_LENGTH
+            ),
+        ));
     }
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        let mut sbc = emulator.sbc.lock().unwrap();
-        sbc.load_app(&binary);
-        sbc.run_app();
-        Ok(format!("Loaded {} bytes at $E00100", binary.len()))
-    } else {
-        Err("Emulator not initialized".to_string())
+    let mut asm = editor_assembler();
+    Ok(asm.assemble_srecords(&code, std::path::Path::new(EDITOR_FILE), record_length)?)
+}
+
+/// Assemble editor code into a listing: each line with its address and
+/// bytes
+///
+/// With `expand`, the lines macro calls and REPT blocks expand to follow
+/// the call, marked with their nesting depth.
+#[tauri::command]
+fn emulator_assemble_listing(code: String, expand: Option<bool>) -> Result<String, EmulatorError> {
+    let mut asm = editor_assembler();
+    asm.list_expansions = expand.unwrap_or(false);
+    Ok(asm.assemble_listing(&code, std::path::Path::new(EDITOR_FILE))?)
+}
+
+/// Assemble editor code into its sections, placed code first, then data,
+/// then BSS
+///
+/// A BSS section has its size and no bytes; loading the program clears it.
+#[tauri::command]
+fn emulator_assemble_sections(code: String) -> Result<Vec<assembler::Section>, EmulatorError> {
+    let mut asm = editor_assembler();
+    Ok(asm.assemble_sections(&code, std::path::Path::new(EDITOR_FILE))?)
+}
+
+/// Assemble editor code into a relocatable object and write it to `path`
+///
+/// Link errors name the object after the file it's written to.
+#[tauri::command]
+fn emulator_assemble_object(code: String, path: String) -> Result<(), EmulatorError> {
+    let mut asm = editor_assembler();
+    let path = std::path::Path::new(&path);
+    let mut object = asm.assemble_object(&code, std::path::Path::new(EDITOR_FILE))?;
+    if let Some(name) = path.file_name() {
+        object.name = name.to_string_lossy().into_owned();
     }
+    Ok(object.save(path)?)
 }
 
-/// Read UART output (drain output buffer)
+/// Link object files into one program starting at `origin` (`APP_START` by
+/// default), returning its bytes and exported symbols
+///
+/// Fails with every undefined and duplicate symbol, naming the files.
 #[tauri::command]
-fn emulator_read_uart() -> Result<Vec<u8>, String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        let mut sbc = emulator.sbc.lock().unwrap();
-        Ok(sbc.drain_output())
+fn emulator_link(
+    objects: Vec<String>,
+    origin: Option<u32>,
+) -> Result<object::LinkedProgram, EmulatorError> {
+    let objects = objects
+        .iter()
+        .map(|path| object::ObjectFile::load(std::path::Path::new(path)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(object::link(&objects, origin.unwrap_or(sbc::APP_START))?)
+}
+
+/// Assembled code and the warnings assembling it produced
+#[derive(serde::Serialize)]
+pub struct CheckedAssembly {
+    /// Machine code
+    binary: Vec<u8>,
+    /// Warnings, with their positions
+    warnings: Vec<assembler::Diagnostic>,
+}
+
+/// Assemble M68K assembly code, returning the binary and any warnings, or
+/// every error found with its position
+///
+/// With `optimize`, long moves and small adds and subtracts of immediates
+/// take their quick forms where the flags come out the same. With
+/// `pc_relative`, absolute source operands that address nearby labels become
+/// PC-relative, so the code runs wherever it's loaded. With `ignore_case`,
+/// symbol names are case-insensitive. With `rom`, the binary is padded to a
+/// ROM image as in `emulator_assemble`. With `strict_alignment`, word data
+/// and branch targets at odd addresses are errors rather than warnings.
+#[tauri::command]
+fn emulator_assemble_checked(
+    code: String,
+    optimize: Option<bool>,
+    pc_relative: Option<bool>,
+    ignore_case: Option<bool>,
+    rom: Option<assembler::RomImage>,
+    strict_alignment: Option<bool>,
+) -> Result<CheckedAssembly, EmulatorError> {
+    let mut asm = editor_assembler();
+    asm.optimize = optimize.unwrap_or(false);
+    asm.pc_relative = pc_relative.unwrap_or(false);
+    asm.ignore_case = ignore_case.unwrap_or(false);
+    asm.strict_alignment = strict_alignment.unwrap_or(false);
+    asm.rom = rom_image(rom)?;
+    let binary = asm.assemble_checked(&code, std::path::Path::new(EDITOR_FILE))?;
+    Ok(CheckedAssembly {
+        binary,
+        warnings: std::mem::take(&mut asm.warnings),
+    })
+}
+
+/// Code assembled incrementally, with how long assembling it took
+#[derive(serde::Serialize)]
+pub struct IncrementalAssembly {
+    /// Machine code
+    binary: Vec<u8>,
+    /// Warnings, with their positions
+    warnings: Vec<assembler::Diagnostic>,
+    /// Time each stage took and what was reused from the last call
+    timings: assembler::AssemblyTimings,
+}
+
+/// Assemble editor code, reusing what the last call learned
+///
+/// For assembling as the user types: includes, lines and label addresses
+/// that haven't changed since the last call aren't worked out again. The
+/// result is the same as `emulator_assemble_checked` with default options.
+#[tauri::command]
+fn emulator_assemble_incremental(
+    state: State<'_, Flux32State>,
+    code: String,
+) -> Result<IncrementalAssembly, EmulatorError> {
+    let new = || {
+        let mut asm = editor_assembler();
+        asm.incremental = true;
+        asm
+    };
+    state.with_assembler(new, |asm| {
+        let binary = asm.assemble_checked(&code, std::path::Path::new(EDITOR_FILE))?;
+        Ok(IncrementalAssembly {
+            binary,
+            warnings: std::mem::take(&mut asm.warnings),
+            timings: asm.timings.clone(),
+        })
+    })
+}
+
+/// Assemble code, load it into RAM at its ORG address (or `APP_START`), and
+/// start execution there
+///
+/// Returns where the program went and its labels' addresses there (with
+/// its EQU and RS constants too when `include_constants` is set).
+#[tauri::command]
+fn emulator_assemble_and_load(
+    state: State<'_, Flux32State>,
+    code: String,
+    include_constants: Option<bool>,
+    instance: Option<InstanceId>,
+) -> Result<LoadedProgram, EmulatorError> {
+    emulator_load_and_run(state, code, None, None, include_constants, instance)
+}
+
+/// Assemble code, load it into RAM at `load_addr`, and start execution at
+/// `entry`
+///
+/// `load_addr` defaults to the program's ORG address, or `APP_START` without
+/// one, and `entry` defaults to the load address. Returns the same report
+/// as `emulator_assemble_and_load`.
+#[tauri::command]
+fn emulator_load_and_run(
+    state: State<'_, Flux32State>,
+    code: String,
+    load_addr: Option<u32>,
+    entry: Option<u32>,
+    include_constants: Option<bool>,
+    instance: Option<InstanceId>,
+) -> Result<LoadedProgram, EmulatorError> {
+    let program = assemble_program(&code)?;
+    state.try_with_sbc(instance, |sbc| {
+        program.load(sbc, load_addr, entry, include_constants.unwrap_or(false))
+    })
+}
+
+/// Most instructions `emulator_disassemble` returns at once
+const MAX_DISASSEMBLY: u32 = 1024;
+
+/// Load a Motorola S-record or Intel HEX file, given as a path or as the
+/// file's text
+///
+/// Each record is written to its address. With `start`, the PC is set to
+/// the file's start address (if it has one) and a halted CPU resumes.
+/// Malformed records are reported with their line numbers.
+#[tauri::command]
+fn emulator_load_hex(
+    state: State<'_, Flux32State>,
+    path_or_text: String,
+    start: Option<bool>,
+    instance: Option<InstanceId>,
+) -> Result<loader::HexLoadResult, EmulatorError> {
+    let path = std::path::Path::new(&path_or_text);
+    let text = if path.is_file() {
+        std::fs::read_to_string(path).map_err(|e| EmulatorError::Io {
+            path: Some(path_or_text.clone()),
+            kind: e.kind(),
+            message: format!("Failed to read {path_or_text}: {e}"),
+        })?
     } else {
-        Err("Emulator not initialized".to_string())
-    }
+        path_or_text.clone()
+    };
+    let image =
+        loader::parse(&text).map_err(|e| EmulatorError::invalid("pathOrText", e.to_string()))?;
+
+    state.try_with_sbc(instance, |sbc| {
+        let loaded = image.load(sbc)?;
+        if let (Some(entry), true) = (loaded.entry, start.unwrap_or(false)) {
+            sbc.cpu_mut().set_pc(entry);
+            sbc.cpu_mut().resume();
+        }
+        Ok(loaded)
+    })
+}
+
+/// Disassemble `count` instructions starting at `address`, or at the PC
+/// when `follow_pc` is set (or no address is given)
+///
+/// The listing ends early with an `(unmapped)` entry if it runs off ROM
+/// and RAM.
+#[tauri::command]
+fn emulator_disassemble(
+    state: State<'_, Flux32State>,
+    address: Option<u32>,
+    count: u32,
+    follow_pc: Option<bool>,
+    instance: Option<InstanceId>,
+) -> Result<Vec<sbc::DisassemblyLine>, EmulatorError> {
+    state.with_sbc(instance, |sbc| {
+        let address = match address {
+            Some(address) if !follow_pc.unwrap_or(false) => address,
+            _ => sbc.pc(),
+        };
+        sbc.disassemble(address, count.min(MAX_DISASSEMBLY) as usize)
+    })
+}
+
+/// Most longwords `emulator_get_stack` returns at once
+const MAX_STACK_ENTRIES: u32 = 1024;
+
+/// Read `max_entries` longwords from the stack pointer upward, annotated
+/// with likely return addresses, symbols and the current LINK frame
+#[tauri::command]
+fn emulator_get_stack(
+    state: State<'_, Flux32State>,
+    max_entries: u32,
+    instance: Option<InstanceId>,
+) -> Result<Vec<sbc::StackEntry>, EmulatorError> {
+    state.with_sbc(instance, |sbc| {
+        sbc.stack(max_entries.min(MAX_STACK_ENTRIES) as usize)
+    })
+}
+
+/// Most frames `emulator_get_callstack` returns at once
+const MAX_CALL_FRAMES: u32 = 256;
+
+/// Reconstruct the call stack, the current PC first, following LINK frames
+/// and falling back to scanning the stack for return addresses
+#[tauri::command]
+fn emulator_get_callstack(
+    state: State<'_, Flux32State>,
+    max_frames: u32,
+    instance: Option<InstanceId>,
+) -> Result<Vec<sbc::CallFrame>, EmulatorError> {
+    state.with_sbc(instance, |sbc| {
+        sbc.call_stack(max_frames.min(MAX_CALL_FRAMES) as usize)
+    })
+}
+
+/// Start recording executed instructions, keeping the last `depth`
+///
+/// Restarting clears the trace.
+#[tauri::command]
+fn emulator_trace_start(
+    state: State<'_, Flux32State>,
+    depth: usize,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.start_trace(depth))
+}
+
+/// Stop recording executed instructions, keeping the trace
+#[tauri::command]
+fn emulator_trace_stop(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, Sbc::stop_trace)
+}
+
+/// Return the most recent `max_entries` traced instructions, oldest first
+///
+/// A background run only holds the machine between slices, so the entries
+/// always come from one consistent point in the run.
+#[tauri::command]
+fn emulator_get_trace(
+    state: State<'_, Flux32State>,
+    max_entries: usize,
+    disassemble: Option<bool>,
+    instance: Option<InstanceId>,
+) -> Result<Vec<trace::TraceEntry>, EmulatorError> {
+    state.with_sbc(instance, |sbc| {
+        sbc.trace(max_entries, disassemble.unwrap_or(false))
+    })
+}
+
+/// Start charging executed instructions' cycles to their addresses
+///
+/// This can join a background run: profiling starts with the run's next
+/// slice.
+#[tauri::command]
+fn emulator_profile_start(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, Sbc::start_profile)
+}
+
+/// Stop profiling, keeping the samples
+#[tauri::command]
+fn emulator_profile_stop(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, Sbc::stop_profile)
+}
+
+/// Discard the profile samples
+#[tauri::command]
+fn emulator_profile_reset(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, Sbc::reset_profile)
+}
+
+/// Report the `top_n` hottest addresses and the cycles per mnemonic
+///
+/// During a background run the report covers the slices run so far.
+#[tauri::command]
+fn emulator_profile_report(
+    state: State<'_, Flux32State>,
+    top_n: usize,
+    instance: Option<InstanceId>,
+) -> Result<profile::ProfileReport, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.profile_report(top_n))
+}
+
+/// Add a breakpoint and return its id
+///
+/// `condition` is a debugger expression (see `expression`) that must hold
+/// for the breakpoint to hit, and `skip_count` hits pass before it stops
+/// execution. A condition that doesn't parse is rejected with the column
+/// where parsing stopped.
+#[tauri::command]
+fn emulator_breakpoint_add(
+    state: State<'_, Flux32State>,
+    address: u32,
+    condition: Option<String>,
+    skip_count: Option<u64>,
+    instance: Option<InstanceId>,
+) -> Result<u32, EmulatorError> {
+    state.add_breakpoint(
+        address,
+        condition.as_deref(),
+        skip_count.unwrap_or(0),
+        instance,
+    )
+}
+
+/// Remove a breakpoint
+#[tauri::command]
+fn emulator_breakpoint_remove(
+    state: State<'_, Flux32State>,
+    id: u32,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_breakpoint(id, instance, |debugger| {
+        debugger.remove(id);
+    })
+}
+
+/// Enable or disable a breakpoint
+#[tauri::command]
+fn emulator_breakpoint_set_enabled(
+    state: State<'_, Flux32State>,
+    id: u32,
+    enabled: bool,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_breakpoint(id, instance, |debugger| {
+        debugger.set_enabled(id, enabled);
+    })
+}
+
+/// List the breakpoints with their hit counts
+#[tauri::command]
+fn emulator_breakpoint_list(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<Vec<debugger::Breakpoint>, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.debugger().breakpoints().to_vec())
+}
+
+/// List the symbol table (the loaded program's labels and any map file's
+/// symbols), keeping names that contain `filter` (ignoring case)
+#[tauri::command]
+fn emulator_get_symbols(
+    state: State<'_, Flux32State>,
+    filter: Option<String>,
+    instance: Option<InstanceId>,
+) -> Result<Vec<debugger::Symbol>, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.debugger().symbols(filter.as_deref()))
+}
+
+/// Find the closest symbol at or below an address and the offset past it
+#[tauri::command]
+fn emulator_symbol_at(
+    state: State<'_, Flux32State>,
+    address: u32,
+    instance: Option<InstanceId>,
+) -> Result<Option<debugger::SymbolMatch>, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.debugger().lookup(address))
+}
+
+/// Merge the symbols of a map file into the symbol table, returning how
+/// many were added (see `debugger` for the format)
+#[tauri::command]
+fn emulator_load_symbol_map(
+    state: State<'_, Flux32State>,
+    path: String,
+    instance: Option<InstanceId>,
+) -> Result<usize, EmulatorError> {
+    let text =
+        std::fs::read_to_string(&path).map_err(|e| EmulatorError::from(e).with_path(&path))?;
+    state.try_with_sbc(instance, |sbc| {
+        sbc.debugger_mut()
+            .load_symbol_map(&text)
+            .map_err(|e| EmulatorError::invalid("path", format!("{path}: {e}")))
+    })
+}
+
+/// Add a watch expression, returning its id
+///
+/// The expression uses the breakpoint condition syntax (registers, labels,
+/// `byte(x)`, `word(x)` and `long(x)`); its value is reported in every
+/// status and step result. An empty `name` shows it under its text.
+#[tauri::command]
+fn emulator_watch_add(
+    state: State<'_, Flux32State>,
+    name: String,
+    expression: String,
+    instance: Option<InstanceId>,
+) -> Result<u32, EmulatorError> {
+    state.add_watch(&name, &expression, instance)
+}
+
+/// Remove a watch expression
+#[tauri::command]
+fn emulator_watch_remove(
+    state: State<'_, Flux32State>,
+    id: u32,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.remove_watch(id, instance)
+}
+
+/// List the watch expressions
+#[tauri::command]
+fn emulator_watch_list(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<Vec<debugger::WatchExpression>, EmulatorError> {
+    state.watches(instance)
+}
+
+/// Evaluate an expression against the current machine state (see
+/// `expression`), with the value in hex and whether it names a label
+#[tauri::command]
+fn emulator_eval(
+    state: State<'_, Flux32State>,
+    expression: String,
+    instance: Option<InstanceId>,
+) -> Result<expression::Evaluation, EmulatorError> {
+    state.evaluate(&expression, instance)
+}
+
+/// Read UART output (drain output buffer)
+#[tauri::command]
+fn emulator_read_uart(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<Vec<u8>, EmulatorError> {
+    state.with_sbc(instance, Sbc::drain_output)
 }
 
 /// Write a character to UART RX (simulate keyboard input)
+///
+/// Returns false if the input queue is full and the character was dropped.
+/// Ctrl-C raises an NMI instead if `emulator_set_ctrl_c_nmi` enabled that.
 #[tauri::command]
-fn emulator_write_uart(byte: u8) -> Result<(), String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        let mut sbc = emulator.sbc.lock().unwrap();
-        sbc.send_char(byte);
-        Ok(())
-    } else {
-        Err("Emulator not initialized".to_string())
+fn emulator_write_uart(
+    state: State<'_, Flux32State>,
+    byte: u8,
+    instance: Option<InstanceId>,
+) -> Result<bool, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.send_char(byte))
+}
+
+/// Queue bytes for UART RX (simulate a terminal paste)
+///
+/// Line endings are translated as `emulator_set_uart_line_ending` chose.
+/// Returns how many leading bytes fit in the input queue; send the rest
+/// once `emulator_get_uart_queue` shows room.
+#[tauri::command]
+fn emulator_write_uart_bytes(
+    state: State<'_, Flux32State>,
+    data: Vec<u8>,
+    instance: Option<InstanceId>,
+) -> Result<usize, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.send_bytes(&data))
+}
+
+/// Queue text for UART RX as UTF-8 (simulate a terminal paste)
+///
+/// Like `emulator_write_uart_bytes`, returns how many leading bytes of the
+/// UTF-8 encoding fit in the input queue.
+#[tauri::command]
+fn emulator_write_uart_string(
+    state: State<'_, Flux32State>,
+    text: String,
+    instance: Option<InstanceId>,
+) -> Result<usize, EmulatorError> {
+    emulator_write_uart_bytes(state, text.into_bytes(), instance)
+}
+
+/// Set how line endings in pasted text reach the UART
+#[tauri::command]
+fn emulator_set_uart_line_ending(
+    state: State<'_, Flux32State>,
+    ending: config::LineEnding,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.set_uart_line_ending(ending))
+}
+
+/// Get the UART input queue occupancy
+#[tauri::command]
+fn emulator_get_uart_queue(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<UartQueueStatus, EmulatorError> {
+    state.with_sbc(instance, |sbc| UartQueueStatus {
+        queued: sbc.input_queued(),
+        capacity: sbc.input_queue_capacity(),
+    })
+}
+
+/// Raise a non-maskable interrupt (level 7), like a monitor's break button
+#[tauri::command]
+fn emulator_nmi(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, Sbc::raise_nmi)
+}
+
+/// Request an interrupt at `level` (1-7) through the interrupt controller,
+/// as a peripheral would, optionally with its own vector
+///
+/// Reports whether the CPU took it at once, the mask deferred it, or it was
+/// dropped because an earlier request is still waiting.
+#[tauri::command]
+fn emulator_interrupt(
+    state: State<'_, Flux32State>,
+    level: u8,
+    vector: Option<u8>,
+    instance: Option<InstanceId>,
+) -> Result<InterruptOutcome, EmulatorError> {
+    if !(1..=7).contains(&level) {
+        return Err(EmulatorError::invalid(
+            "level",
+            format!("Interrupt level must be 1-7, got {level}"),
+        ));
     }
+    state.with_sbc(instance, |sbc| sbc.inject_interrupt(level, vector))
 }
 
-/// Get the LED state
+/// Make Ctrl-C written to the UART raise an NMI instead
 #[tauri::command]
-fn emulator_get_led() -> Result<bool, String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        let sbc = emulator.sbc.lock().unwrap();
-        Ok(sbc.led_state())
-    } else {
-        Err("Emulator not initialized".to_string())
+fn emulator_set_ctrl_c_nmi(
+    state: State<'_, Flux32State>,
+    enabled: bool,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.set_ctrl_c_nmi(enabled))
+}
+
+/// Get the state of LED 0 (the UART RTS status LED)
+#[tauri::command]
+fn emulator_get_led(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<bool, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.led_state())
+}
+
+/// Get the LED bar latch (bit N = LED N)
+#[tauri::command]
+fn emulator_get_leds(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<u8, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.led_bar())
+}
+
+/// Get the state of every peripheral on the board panel in one call (LED
+/// bar, GPIO port, UART, `CompactFlash` card and timer)
+#[tauri::command]
+fn emulator_get_peripherals(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<serde_json::Value, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.panel_state())
+}
+
+/// Get one peripheral's registers and internal state without the side
+/// effects of a guest read (`leds`, `gpio`, `uart`, `cf` or `timer`)
+#[tauri::command]
+fn emulator_dump_peripheral(
+    state: State<'_, Flux32State>,
+    name: String,
+    instance: Option<InstanceId>,
+) -> Result<serde_json::Value, EmulatorError> {
+    state.try_with_sbc(instance, |sbc| {
+        sbc.dump_peripheral(&name).ok_or_else(|| {
+            EmulatorError::invalid(
+                "name",
+                format!(
+                    "Unknown peripheral '{name}' (available: {})",
+                    panel::PERIPHERALS.join(", ")
+                ),
+            )
+        })
+    })
+}
+
+/// Eject the `CompactFlash` card, flushing cached writes to its image file
+#[tauri::command]
+fn emulator_cf_eject(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.try_with_sbc(instance, |sbc| Ok(sbc.eject_cf()?))
+}
+
+/// Insert a `CompactFlash` card backed by the given image file
+#[tauri::command]
+fn emulator_cf_insert(
+    state: State<'_, Flux32State>,
+    path: String,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.try_with_sbc(instance, |sbc| {
+        sbc.insert_cf(cfcard::CfImage::File(path.clone().into()))
+            .map_err(|e| EmulatorError::from(e).with_path(&path))
+    })
+}
+
+/// Enable or disable the `CompactFlash` copy-on-write overlay
+#[tauri::command]
+fn emulator_cf_set_overlay(
+    state: State<'_, Flux32State>,
+    enabled: bool,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.try_with_sbc(instance, |sbc| Ok(sbc.set_cf_overlay_enabled(enabled)?))
+}
+
+/// Discard all `CompactFlash` writes held in the overlay
+#[tauri::command]
+fn emulator_cf_discard_overlay(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, Sbc::discard_cf_overlay)
+}
+
+/// Commit the `CompactFlash` overlay into the base image
+#[tauri::command]
+fn emulator_cf_commit_overlay(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.try_with_sbc(instance, |sbc| Ok(sbc.commit_cf_overlay()?))
+}
+
+/// Export the `CompactFlash` overlay as a delta file
+#[tauri::command]
+fn emulator_cf_export_overlay(
+    state: State<'_, Flux32State>,
+    path: String,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.try_with_sbc(instance, |sbc| {
+        sbc.export_cf_overlay(std::path::Path::new(&path))
+            .map_err(|e| EmulatorError::from(e).with_path(&path))
+    })
+}
+
+/// Create a blank `CompactFlash` image, optionally formatted as FAT16
+#[tauri::command]
+fn emulator_create_cf_image(path: String, size_mb: u32, format: bool) -> Result<(), EmulatorError> {
+    cfimage::create_image(std::path::Path::new(&path), size_mb, format)
+        .map_err(|e| EmulatorError::from(e).with_path(&path))
+}
+
+/// Get the GPIO port state
+#[tauri::command]
+fn emulator_gpio_read(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<gpio::GpioState, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.gpio_state())
+}
+
+/// Drive a GPIO input pin (0-7) high or low
+#[tauri::command]
+fn emulator_gpio_write_input(
+    state: State<'_, Flux32State>,
+    pin: u8,
+    level: bool,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    if pin > 7 {
+        return Err(EmulatorError::invalid(
+            "pin",
+            format!("Invalid GPIO pin: {pin}"),
+        ));
     }
+    state.with_sbc(instance, |sbc| sbc.gpio_set_input(pin, level))
+}
+
+/// Set the DIP switch positions (bit n = switch n + 1, 1 = ON)
+///
+/// The ROM reads the switches at boot, so a change usually shows after a
+/// reset.
+#[tauri::command]
+fn emulator_set_dipswitch(
+    state: State<'_, Flux32State>,
+    value: u8,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.set_dip_switch(value))
+}
+
+/// Enable or disable the terminal bell (BEL sent to the UART beeps)
+#[tauri::command]
+fn emulator_set_uart_bell(
+    state: State<'_, Flux32State>,
+    enabled: bool,
+    instance: Option<InstanceId>,
+) -> Result<(), EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.set_uart_bell(enabled))
 }
 
-/// Reset the emulator to initial state
+/// Reset the emulator
+///
+/// `mode` (cold by default) picks how much is reinitialized: a cold reset
+/// power-cycles the machine, clearing RAM, ejecting the CF card and
+/// dropping breakpoints; a warm reset reinitializes the CPU and every
+/// peripheral but keeps RAM; `cpu_only` just reloads SSP and PC from the
+/// reset vectors. The ROM is kept in every mode.
 #[tauri::command]
-fn emulator_reset() -> Result<String, String> {
-    let mut emulator = EMULATOR.lock().unwrap();
-    *emulator = Some(Flux32Emulator::new());
+fn emulator_reset(
+    state: State<'_, Flux32State>,
+    mode: Option<sbc::ResetMode>,
+    instance: Option<InstanceId>,
+) -> Result<String, EmulatorError> {
+    state.reset(mode, instance)?;
     Ok("Emulator reset".to_string())
 }
 
 /// Run the emulator continuously
+///
+/// The machine is locked one `runner::SLICE_CYCLES` slice at a time, so
+/// other commands are served while a long run is in progress. UART output
+/// streams as `uart-output` events every `state::OUTPUT_DRAIN_CYCLES`; the
+/// last one is emitted before the command returns.
 #[tauri::command]
-fn emulator_run(max_cycles: Option<u64>) -> Result<EmulatorStatus, String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        let cycles = max_cycles.unwrap_or(100_000);
-        let mut sbc = emulator.sbc.lock().unwrap();
-        let executed = sbc.run(cycles);
-        Ok(EmulatorStatus {
-            halted: sbc.is_halted(),
-            cycles: sbc.cycles(),
-            executed,
-        })
-    } else {
-        Err("Emulator not initialized".to_string())
-    }
+fn emulator_run(
+    app: tauri::AppHandle,
+    state: State<'_, Flux32State>,
+    max_cycles: Option<u64>,
+    instance: Option<InstanceId>,
+) -> Result<EmulatorStatus, EmulatorError> {
+    let id = instance.unwrap_or(PRIMARY_INSTANCE);
+    state.run(
+        max_cycles.unwrap_or(100_000),
+        instance,
+        |sbc| emit_peripheral_events(&app, id, sbc),
+        |output| emit_event(&app, "uart-output", id, output),
+    )
+}
+
+/// Most wall time one `emulator_run_throttled` call makes up for, in
+/// milliseconds, unless the caller says otherwise
+const DEFAULT_THROTTLE_MAX_MS: u64 = 100;
+
+/// Run for the wall time since the previous call at a target clock
+/// frequency (the configured clock by default)
+///
+/// Meant to be called from the frontend's animation frame loop: each call
+/// runs the cycles `target_hz` executes in the time since the last call
+/// (at most `duration_ms`, so a stalled loop doesn't cause a burst of
+/// catch-up) and returns right away. The status reports the achieved speed.
+#[tauri::command]
+fn emulator_run_throttled(
+    app: tauri::AppHandle,
+    state: State<'_, Flux32State>,
+    target_hz: Option<u32>,
+    duration_ms: Option<u64>,
+    instance: Option<InstanceId>,
+) -> Result<EmulatorStatus, EmulatorError> {
+    let id = instance.unwrap_or(PRIMARY_INSTANCE);
+    let max_elapsed =
+        std::time::Duration::from_millis(duration_ms.unwrap_or(DEFAULT_THROTTLE_MAX_MS));
+    state.run_throttled(target_hz, max_elapsed, instance, |sbc| {
+        emit_peripheral_events(&app, id, sbc);
+    })
+}
+
+/// Start (or resume) running the emulator on a background thread
+///
+/// The worker runs in `runner::SLICE_CYCLES` slices, so other commands are
+/// served between slices. When a breakpoint, watchpoint, halt or exit stops
+/// it (or it is paused), a `run-stopped` event carries the reason. The
+/// worker's callbacks never touch `Flux32State`, whose lock commands hold
+/// while they wait for it to pause.
+#[tauri::command]
+fn emulator_start(
+    app: tauri::AppHandle,
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<String, EmulatorError> {
+    let id = instance.unwrap_or(PRIMARY_INSTANCE);
+    let events = app.clone();
+    state.start(
+        instance,
+        move |sbc| emit_peripheral_events(&events, id, sbc),
+        move |stopped| emit_event(&app, "run-stopped", id, stopped),
+    )?;
+    Ok("Emulator running".to_string())
+}
+
+/// Pause a background run after the slice in progress (it can be resumed
+/// with `emulator_start`)
+#[tauri::command]
+fn emulator_pause(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<String, EmulatorError> {
+    state.pause(instance)?;
+    Ok("Emulator paused".to_string())
+}
+
+/// Stop a background run and end its worker thread
+#[tauri::command]
+fn emulator_stop(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<String, EmulatorError> {
+    state.stop(instance)?;
+    Ok("Emulator stopped".to_string())
 }
 
 /// Get emulator status (halted, cycles, etc.)
 #[tauri::command]
-fn emulator_get_status() -> Result<EmulatorStatus, String> {
-    let emulator = EMULATOR.lock().unwrap();
-    if let Some(emulator) = emulator.as_ref() {
-        let sbc = emulator.sbc.lock().unwrap();
-        Ok(EmulatorStatus {
-            halted: sbc.is_halted(),
-            cycles: sbc.cycles(),
-            executed: 0,
-        })
-    } else {
-        Err("Emulator not initialized".to_string())
-    }
+fn emulator_get_status(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<EmulatorStatus, EmulatorError> {
+    state.status(instance)
+}
+
+/// Get a diagnostics report (memory map, peripherals, ROM CRC, faults) for
+/// bug reports
+#[tauri::command]
+fn emulator_get_diagnostics(
+    state: State<'_, Flux32State>,
+    instance: Option<InstanceId>,
+) -> Result<diagnostics::Diagnostics, EmulatorError> {
+    state.with_sbc(instance, |sbc| sbc.diagnostics())
+}
+
+/// Save the full machine state to a snapshot file, returning its metadata
+///
+/// Cached CF card writes are flushed first, since the card image itself
+/// isn't part of the snapshot.
+#[tauri::command]
+fn emulator_save_snapshot(
+    state: State<'_, Flux32State>,
+    path: String,
+    instance: Option<InstanceId>,
+) -> Result<snapshot::SnapshotInfo, EmulatorError> {
+    state.save_snapshot(std::path::Path::new(&path), instance)
+}
+
+/// Replace the machine with one restored from a snapshot file, returning
+/// the snapshot's metadata
+///
+/// On any error the current machine is left as it was. Breakpoints and
+/// watchpoints carry over to the restored machine.
+#[tauri::command]
+fn emulator_load_snapshot(
+    state: State<'_, Flux32State>,
+    path: String,
+    instance: Option<InstanceId>,
+) -> Result<snapshot::SnapshotInfo, EmulatorError> {
+    state.load_snapshot(std::path::Path::new(&path), instance)
+}
+
+/// Read a snapshot file's metadata without loading it
+#[tauri::command]
+fn emulator_snapshot_info(path: String) -> Result<snapshot::SnapshotInfo, EmulatorError> {
+    Ok(snapshot::Snapshot::load_info(std::path::Path::new(&path))?)
 }
 
 fn prevent_default() -> tauri::plugin::TauriPlugin<tauri::Wry> {
@@ -375,22 +1580,231 @@ pub fn run() {
     builder = builder.plugin(prevent_default());
 
     builder
+        .manage(Flux32State::new())
         .invoke_handler(tauri::generate_handler![
             emulator_init,
+            emulator_create_instance,
+            emulator_destroy_instance,
+            emulator_list_instances,
             emulator_step,
+            emulator_step_ex,
+            emulator_step_n,
+            emulator_step_over,
+            emulator_step_out,
             emulator_reset,
             emulator_run,
+            emulator_run_throttled,
             emulator_get_registers,
+            emulator_write_register,
             emulator_get_status,
+            emulator_start,
+            emulator_pause,
+            emulator_stop,
+            emulator_get_diagnostics,
+            emulator_save_snapshot,
+            emulator_load_snapshot,
+            emulator_snapshot_info,
             emulator_read_byte,
             emulator_read_memory,
             emulator_write_byte,
+            emulator_write_memory,
+            emulator_fill_memory,
+            emulator_disassemble,
+            emulator_get_stack,
+            emulator_get_callstack,
+            emulator_trace_start,
+            emulator_trace_stop,
+            emulator_get_trace,
+            emulator_profile_start,
+            emulator_profile_stop,
+            emulator_profile_reset,
+            emulator_profile_report,
+            emulator_breakpoint_add,
+            emulator_breakpoint_remove,
+            emulator_breakpoint_set_enabled,
+            emulator_breakpoint_list,
+            emulator_load_binary,
+            emulator_load_hex,
             emulator_assemble,
+            emulator_assemble_checked,
+            emulator_assemble_incremental,
+            emulator_assemble_srecords,
+            emulator_assemble_listing,
+            emulator_assemble_sections,
+            emulator_assemble_object,
+            emulator_link,
+            emulator_assemble_project,
             emulator_assemble_and_load,
+            emulator_load_and_run,
+            emulator_watch_add,
+            emulator_watch_remove,
+            emulator_watch_list,
+            emulator_eval,
+            emulator_get_symbols,
+            emulator_symbol_at,
+            emulator_load_symbol_map,
+            emulator_save_symbol_map,
             emulator_read_uart,
             emulator_write_uart,
+            emulator_write_uart_bytes,
+            emulator_write_uart_string,
+            emulator_set_uart_line_ending,
+            emulator_get_uart_queue,
+            emulator_nmi,
+            emulator_interrupt,
+            emulator_set_ctrl_c_nmi,
             emulator_get_led,
+            emulator_get_leds,
+            emulator_get_peripherals,
+            emulator_dump_peripheral,
+            emulator_cf_eject,
+            emulator_cf_insert,
+            emulator_cf_set_overlay,
+            emulator_cf_discard_overlay,
+            emulator_cf_commit_overlay,
+            emulator_cf_export_overlay,
+            emulator_create_cf_image,
+            emulator_gpio_read,
+            emulator_gpio_write_input,
+            emulator_set_uart_bell,
+            emulator_set_dipswitch,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if matches!(event, tauri::RunEvent::Exit) {
+                // Dropping the emulators flushes cached CF card writes
+                app.state::<Flux32State>().clear();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Returns a command's error as the frontend receives it
+    fn error_json<T: std::fmt::Debug>(result: Result<T, EmulatorError>) -> serde_json::Value {
+        serde_json::to_value(result.unwrap_err()).unwrap()
+    }
+
+    #[test]
+    fn test_error_json_shapes() {
+        let app = tauri::test::mock_app();
+        app.manage(Flux32State::new());
+
+        assert_eq!(
+            error_json(emulator_get_registers(app.state(), Some(3))),
+            json!({
+                "code": "notInitialized",
+                "instance": 3,
+                "message": "Emulator instance 3 not initialized",
+            })
+        );
+
+        emulator_init(app.state(), None, None, None).unwrap();
+        // ROM and RAM both decode $400000
+        assert_eq!(
+            error_json(emulator_read_byte(app.state(), 0x0040_0000, None)),
+            json!({
+                "code": "unmapped",
+                "address": 0x40_0000,
+                "message": "Address $400000 is not mapped",
+            })
+        );
+        assert_eq!(
+            error_json(emulator_write_byte(app.state(), 0x1000, 0xAA, None)),
+            json!({
+                "code": "writeProtected",
+                "address": 0x1000,
+                "message": "Address $001000 is not writable",
+            })
+        );
+
+        let code = "        nop\n        bogus d0\n".to_string();
+        let json = error_json(emulator_assemble_listing(code, None));
+        assert_eq!(json["code"], "assemblyFailed");
+        assert_eq!(json["diagnostics"][0]["line"], 2);
+        assert_eq!(json["diagnostics"][0]["file"], EDITOR_FILE);
+        assert_eq!(json["message"], "<editor>:2: unknown instruction: BOGUS");
+
+        let missing = "/nonexistent/flux32.snap".to_string();
+        let json = error_json(emulator_snapshot_info(missing));
+        assert_eq!(json["code"], "io");
+        assert_eq!(json["path"], "/nonexistent/flux32.snap");
+        assert_eq!(json["kind"], "notFound");
+
+        assert_eq!(
+            error_json(emulator_gpio_write_input(app.state(), 9, true, None)),
+            json!({
+                "code": "invalidArgument",
+                "field": "pin",
+                "message": "Invalid GPIO pin: 9",
+            })
+        );
+
+        let code = "start:  nop\nstat:   nop\n".to_string();
+        emulator_assemble_and_load(app.state(), code, None, None).unwrap();
+        assert_eq!(
+            error_json(emulator_eval(app.state(), "strat".to_string(), None)),
+            json!({
+                "code": "undefinedSymbol",
+                "name": "strat",
+                "suggestions": ["stat", "start"],
+                "message": "Undefined symbol: strat (did you mean stat, start?)",
+            })
+        );
+    }
+
+    #[test]
+    fn test_commands_target_their_instance() {
+        let app = tauri::test::mock_app();
+        app.manage(Flux32State::new());
+        emulator_init(app.state(), None, None, None).unwrap();
+        let second = emulator_create_instance(app.state(), None).unwrap();
+        assert_eq!(
+            emulator_list_instances(app.state()),
+            [PRIMARY_INSTANCE, second]
+        );
+
+        let d0 = |instance| emulator_get_registers(app.state(), instance).unwrap().d[0];
+        emulator_write_register(app.state(), "d0".to_string(), 0x1234, Some(second)).unwrap();
+        assert_eq!(d0(Some(second)), 0x1234);
+        assert_eq!(d0(None), 0);
+
+        emulator_write_byte(app.state(), sbc::APP_START, 0x5A, Some(second)).unwrap();
+        assert_eq!(
+            emulator_read_byte(app.state(), sbc::APP_START, Some(second)).unwrap(),
+            0x5A
+        );
+        assert_eq!(
+            emulator_read_byte(app.state(), sbc::APP_START, None).unwrap(),
+            0
+        );
+
+        emulator_destroy_instance(app.state(), second).unwrap();
+        assert_eq!(
+            error_json(emulator_get_registers(app.state(), Some(second))),
+            json!({
+                "code": "notInitialized",
+                "instance": second,
+                "message": format!("Emulator instance {second} not initialized"),
+            })
+        );
+        assert_eq!(
+            error_json(emulator_write_byte(
+                app.state(),
+                sbc::APP_START,
+                0,
+                Some(second)
+            )),
+            json!({
+                "code": "notInitialized",
+                "instance": second,
+                "message": format!("Emulator instance {second} not initialized"),
+            })
+        );
+        assert_eq!(d0(None), 0);
+    }
 }
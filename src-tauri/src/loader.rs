@@ -0,0 +1,432 @@
+//! Hex File Loader
+//!
+//! External toolchains hand programs over as Motorola S-records (S19, S28
+//! and S37) or Intel HEX rather than raw binaries. `parse` reads either
+//! format, checking every record's checksum and type, into a `HexImage`:
+//! the data as runs of contiguous bytes plus the start address the file
+//! names, if any. `HexImage::load` then writes the runs into a machine
+//! through the debugger's bulk write path.
+//!
+//! Errors carry the 1-based line number of the record at fault.
+
+use crate::error::EmulatorError;
+use crate::sbc::Sbc;
+use std::fmt;
+
+/// A run of contiguous bytes from a hex file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// Address of the first byte
+    pub address: u32,
+    /// The bytes
+    pub data: Vec<u8>,
+}
+
+/// Where a loaded segment went
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadedSegment {
+    /// Address of the first byte
+    pub address: u32,
+    /// Size in bytes
+    pub length: u32,
+}
+
+/// What loading a hex file did
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HexLoadResult {
+    /// The regions written, in address order
+    pub segments: Vec<LoadedSegment>,
+    /// Start address from the file's S7/S8/S9 or Intel start record
+    pub entry: Option<u32>,
+}
+
+/// The contents of a hex file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HexImage {
+    /// Data records merged into contiguous runs, in address order
+    pub segments: Vec<Segment>,
+    /// Start address record, if the file has one
+    pub entry: Option<u32>,
+}
+
+/// A malformed hex file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HexError {
+    /// 1-based line of the bad record
+    pub line: usize,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl HexError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for HexError {}
+
+/// Parses an S-record or Intel HEX file, telling the formats apart by the
+/// first record
+pub fn parse(text: &str) -> Result<HexImage, HexError> {
+    let first = text
+        .lines()
+        .enumerate()
+        .find(|(_, line)| !line.trim().is_empty());
+    match first {
+        Some((_, line)) if line.trim_start().starts_with(['S', 's']) => parse_srecords(text),
+        Some((_, line)) if line.trim_start().starts_with(':') => parse_intel_hex(text),
+        Some((index, _)) => Err(HexError::new(
+            index + 1,
+            "not an S-record or Intel HEX record",
+        )),
+        None => Err(HexError::new(1, "file has no records")),
+    }
+}
+
+/// Decodes the hex digits of a record (after its type) into bytes
+fn record_bytes(hex: &str, line: usize) -> Result<Vec<u8>, HexError> {
+    if !hex.is_ascii() {
+        return Err(HexError::new(line, "invalid hex digit"));
+    }
+    if !hex.len().is_multiple_of(2) {
+        return Err(HexError::new(line, "odd number of hex digits"));
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| HexError::new(line, "invalid hex digit"))?;
+    if bytes.is_empty() {
+        return Err(HexError::new(line, "record is empty"));
+    }
+    Ok(bytes)
+}
+
+/// Parses Motorola S-records
+pub fn parse_srecords(text: &str) -> Result<HexImage, HexError> {
+    let mut builder = Builder::default();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let record = line.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let mut chars = record.chars();
+        if !matches!(chars.next(), Some('S' | 's')) {
+            return Err(HexError::new(number, "record doesn't start with 'S'"));
+        }
+        let kind = chars
+            .next()
+            .and_then(|c| c.to_digit(10))
+            .ok_or_else(|| HexError::new(number, "missing record type"))?;
+        let bytes = record_bytes(&record[2..], number)?;
+        let count = usize::from(bytes[0]);
+        if count + 1 != bytes.len() {
+            return Err(HexError::new(
+                number,
+                format!(
+                    "byte count is {count} but the record has {}",
+                    bytes.len() - 1
+                ),
+            ));
+        }
+        let sum = bytes[..count]
+            .iter()
+            .fold(0u8, |sum, &b| sum.wrapping_add(b));
+        if !sum != bytes[count] {
+            return Err(HexError::new(
+                number,
+                format!("checksum is ${:02X}, expected ${:02X}", bytes[count], !sum),
+            ));
+        }
+
+        let address_len = match kind {
+            0 | 1 | 5 | 9 => 2,
+            2 | 6 | 8 => 3,
+            3 | 7 => 4,
+            _ => {
+                return Err(HexError::new(
+                    number,
+                    format!("unknown record type S{kind}"),
+                ))
+            }
+        };
+        let payload = &bytes[1..count];
+        if payload.len() < address_len {
+            return Err(HexError::new(
+                number,
+                format!("S{kind} record is too short"),
+            ));
+        }
+        let address = payload[..address_len]
+            .iter()
+            .fold(0u32, |address, &b| address << 8 | u32::from(b));
+        let data = &payload[address_len..];
+        match kind {
+            1..=3 => builder.add(address, data),
+            7..=9 => builder.entry = Some(address),
+            // S0 is a header, S5/S6 a record count
+            _ => {}
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// Parses Intel HEX
+pub fn parse_intel_hex(text: &str) -> Result<HexImage, HexError> {
+    let mut builder = Builder::default();
+    // Added to every data address (from type 02 or 04 records)
+    let mut base = 0u32;
+    let mut ended = false;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let record = line.trim();
+        if record.is_empty() {
+            continue;
+        }
+        if ended {
+            return Err(HexError::new(number, "record after the end-of-file record"));
+        }
+        let Some(hex) = record.strip_prefix(':') else {
+            return Err(HexError::new(number, "record doesn't start with ':'"));
+        };
+        let bytes = record_bytes(hex, number)?;
+        if bytes.len() < 5 {
+            return Err(HexError::new(number, "record is too short"));
+        }
+        let count = usize::from(bytes[0]);
+        if count + 5 != bytes.len() {
+            return Err(HexError::new(
+                number,
+                format!(
+                    "byte count is {count} but the record has {}",
+                    bytes.len() - 5
+                ),
+            ));
+        }
+        let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        if sum != 0 {
+            let expected = bytes[count + 4].wrapping_sub(sum);
+            return Err(HexError::new(
+                number,
+                format!(
+                    "checksum is ${:02X}, expected ${expected:02X}",
+                    bytes[count + 4]
+                ),
+            ));
+        }
+
+        let offset = u32::from(u16::from_be_bytes([bytes[1], bytes[2]]));
+        let data = &bytes[4..4 + count];
+        let field = |len: usize| {
+            if data.len() == len {
+                Ok(data
+                    .iter()
+                    .fold(0u32, |value, &b| value << 8 | u32::from(b)))
+            } else {
+                Err(HexError::new(
+                    number,
+                    format!("record type {:02X} needs {len} data bytes", bytes[3]),
+                ))
+            }
+        };
+        match bytes[3] {
+            0x00 => builder.add(base.wrapping_add(offset), data),
+            0x01 => ended = true,
+            0x02 => base = field(2)? << 4,
+            0x03 => {
+                let start = field(4)?;
+                builder.entry = Some(((start >> 16) << 4) + (start & 0xFFFF));
+            }
+            0x04 => base = field(2)? << 16,
+            0x05 => builder.entry = Some(field(4)?),
+            kind => {
+                return Err(HexError::new(
+                    number,
+                    format!("unknown record type {kind:02X}"),
+                ))
+            }
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// Collects data records into segments
+#[derive(Default)]
+struct Builder {
+    records: Vec<(u32, Vec<u8>)>,
+    entry: Option<u32>,
+}
+
+impl Builder {
+    fn add(&mut self, address: u32, data: &[u8]) {
+        if !data.is_empty() {
+            self.records.push((address, data.to_vec()));
+        }
+    }
+
+    /// Sorts the records and merges the ones that touch
+    fn finish(mut self) -> HexImage {
+        self.records.sort_by_key(|(address, _)| *address);
+        let mut segments: Vec<Segment> = Vec::new();
+        for (address, data) in self.records {
+            match segments.last_mut() {
+                Some(last) if last.address.wrapping_add(last.data.len() as u32) == address => {
+                    last.data.extend(data);
+                }
+                _ => segments.push(Segment { address, data }),
+            }
+        }
+        HexImage {
+            segments,
+            entry: self.entry,
+        }
+    }
+}
+
+impl HexImage {
+    /// Writes every segment into `sbc`'s memory, as a debugger write would
+    ///
+    /// Fails at the first byte that isn't writable (see
+    /// `Sbc::write_memory`); segments before it stay written.
+    pub fn load(&self, sbc: &mut Sbc) -> Result<HexLoadResult, EmulatorError> {
+        let mut segments = Vec::with_capacity(self.segments.len());
+        for segment in &self.segments {
+            let result = sbc.write_memory(segment.address, &segment.data, false);
+            if let Some(address) = result.failed_at {
+                return Err(EmulatorError::WriteProtected { address });
+            }
+            segments.push(LoadedSegment {
+                address: segment.address,
+                length: segment.data.len() as u32,
+            });
+        }
+        Ok(HexLoadResult {
+            segments,
+            entry: self.entry,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two regions with a gap: $E00100-$E00107 (two records) and
+    /// $E00200-$E00203, starting at $E00100
+    const S28: &str = "\
+S00600004844521B
+S208E001004E714E7198
+S208E001044E714E7590
+S208E00200DEADBEEFDD
+S5030003F9
+S804E001001A
+";
+
+    #[test]
+    fn test_srecords_with_gap() {
+        let image = parse(S28).unwrap();
+        assert_eq!(image.entry, Some(0x00E0_0100));
+        assert_eq!(
+            image.segments,
+            [
+                Segment {
+                    address: 0x00E0_0100,
+                    data: vec![0x4E, 0x71, 0x4E, 0x71, 0x4E, 0x71, 0x4E, 0x75],
+                },
+                Segment {
+                    address: 0x00E0_0200,
+                    data: vec![0xDE, 0xAD, 0xBE, 0xEF],
+                },
+            ]
+        );
+
+        let mut sbc = Sbc::new();
+        let loaded = image.load(&mut sbc).unwrap();
+        assert_eq!(
+            loaded.segments,
+            [
+                LoadedSegment {
+                    address: 0x00E0_0100,
+                    length: 8
+                },
+                LoadedSegment {
+                    address: 0x00E0_0200,
+                    length: 4
+                },
+            ]
+        );
+        let listing = sbc.disassemble(0x00E0_0100, 4);
+        let mnemonics: Vec<_> = listing.iter().map(|l| l.mnemonic.as_str()).collect();
+        assert_eq!(mnemonics, ["nop", "nop", "nop", "rts"]);
+        assert_eq!(
+            sbc.cpu().memory.read_long(0x00E0_0200).unwrap(),
+            0xDEAD_BEEF
+        );
+        // The gap is untouched
+        assert_eq!(sbc.cpu().memory.read_long(0x00E0_0108).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_s19_and_s37() {
+        let image = parse("S1070100AABBCCDDE9\nS9030100FB\n").unwrap();
+        assert_eq!(image.segments[0].address, 0x0100);
+        assert_eq!(image.entry, Some(0x0100));
+        let image = parse("S30900E00100112233446B\nS70500E0010019\n").unwrap();
+        assert_eq!(image.segments[0].data, [0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(image.entry, Some(0x00E0_0100));
+    }
+
+    #[test]
+    fn test_intel_hex() {
+        let text = "\
+:0200000400E01A
+:0401000012345678E7
+:0400000500E0010016
+:00000001FF
+";
+        let image = parse(text).unwrap();
+        assert_eq!(
+            image.segments,
+            [Segment {
+                address: 0x00E0_0100,
+                data: vec![0x12, 0x34, 0x56, 0x78],
+            }]
+        );
+        assert_eq!(image.entry, Some(0x00E0_0100));
+    }
+
+    #[test]
+    fn test_malformed_records_report_lines() {
+        let bad_checksum = "S1070100AABBCCDDE9\n\nS1070104AABBCCDD00\n";
+        assert_eq!(
+            parse(bad_checksum).unwrap_err().to_string(),
+            "line 3: checksum is $00, expected $E5"
+        );
+        let err = parse("S1070100AABBCCDDE9\nS4030000FC\n").unwrap_err();
+        assert_eq!(
+            (err.line, err.message.as_str()),
+            (2, "unknown record type S4")
+        );
+        let err = parse("S1090100AABBCCDDE8\n").unwrap_err();
+        assert_eq!(err.line, 1);
+        assert!(err.message.contains("byte count"), "{err}");
+        let err = parse(":0400000012345678E8\n:00000001FF\n:00000001FF\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        let err = parse(":04000000123456G8E8\n").unwrap_err();
+        assert_eq!(err.message, "invalid hex digit");
+        assert!(parse("hello\n").is_err());
+    }
+}
@@ -10,6 +10,7 @@
 //! Bus errors are generated for out-of-bounds accesses.
 
 use std::fmt;
+use std::sync::Arc;
 
 /// Default memory size: 100KB for flux32
 ///
@@ -53,13 +54,14 @@ pub enum WriteHookResult {
     Unhandled,
 }
 
-/// Callback function type for write hooks.
+/// Callback type for write hooks, called with the address, value and size.
 ///
 /// Returning `Handled` prevents the write from touching the backing memory.
-pub type WriteHook = fn(address: u32, value: u32, size: OperandSize) -> WriteHookResult;
+/// Hooks are closures so each memory can reach its own devices.
+pub type WriteHook = Arc<dyn Fn(u32, u32, OperandSize) -> WriteHookResult + Send + Sync>;
 
-/// Callback function type for read hooks
-pub type ReadHook = fn(address: u32) -> Option<u8>;
+/// Callback type for read hooks, called with the address
+pub type ReadHook = Arc<dyn Fn(u32) -> Option<u8> + Send + Sync>;
 
 /// Operand size for write hooks
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -116,8 +118,11 @@ pub fn new(size: usize) -> Self {
     /// `WriteHookResult::Handled`, the write will not modify the backing memory.
     // Allow dead code: kept for tests, completeness, or CLI-only usage.
     #[allow(dead_code)]
-    pub fn set_write_hook(&mut self, hook: WriteHook) {
-        self.write_hook = Some(hook);
+    pub fn set_write_hook(
+        &mut self,
+        hook: impl Fn(u32, u32, OperandSize) -> WriteHookResult + Send + Sync + 'static,
+    ) {
+        self.write_hook = Some(Arc::new(hook));
     }
 
     /// Clears the write hook.
@@ -133,8 +138,8 @@ pub fn clear_write_hook(&mut self) {
     /// `Some(value)`, that value is used and memory is not accessed.
     // Allow dead code: kept for tests, completeness, or CLI-only usage.
     #[allow(dead_code)]
-    pub fn set_read_hook(&mut self, hook: ReadHook) {
-        self.read_hook = Some(hook);
+    pub fn set_read_hook(&mut self, hook: impl Fn(u32) -> Option<u8> + Send + Sync + 'static) {
+        self.read_hook = Some(Arc::new(hook));
     }
 
     /// Clears the read hook.
@@ -176,7 +181,7 @@ const fn check_bounds(&self, address: u32, size: usize) -> Result<(), MemoryErro
     /// Reads a single byte from memory.
     pub fn read_byte(&self, address: u32) -> Result<u8, MemoryError> {
         // Call read hook FIRST (for MMIO, even if address is out of bounds)
-        if let Some(hook) = self.read_hook {
+        if let Some(hook) = &self.read_hook {
             if let Some(value) = hook(address) {
                 return Ok(value);
             }
@@ -190,7 +195,7 @@ pub fn read_byte(&self, address: u32) -> Result<u8, MemoryError> {
     /// Writes a single byte to memory.
     pub fn write_byte(&mut self, address: u32, value: u8) -> Result<(), MemoryError> {
         // Call write hook FIRST (for MMIO, even if address is out of bounds)
-        if let Some(hook) = self.write_hook {
+        if let Some(hook) = &self.write_hook {
             if matches!(
                 hook(address, u32::from(value), OperandSize::Byte),
                 WriteHookResult::Handled
@@ -243,7 +248,7 @@ pub fn read_word(&self, address: u32) -> Result<u16, MemoryError> {
     /// The data is stored in big-endian format (Motorola convention).
     pub fn write_word(&mut self, address: u32, value: u16) -> Result<(), MemoryError> {
         // Call write hook FIRST (for MMIO, even if address is out of bounds)
-        if let Some(hook) = self.write_hook {
+        if let Some(hook) = &self.write_hook {
             if matches!(
                 hook(address, u32::from(value), OperandSize::Word),
                 WriteHookResult::Handled
@@ -302,7 +307,7 @@ pub fn read_long(&self, address: u32) -> Result<u32, MemoryError> {
     /// The data is stored in big-endian format (Motorola convention).
     pub fn write_long(&mut self, address: u32, value: u32) -> Result<(), MemoryError> {
         // Call write hook FIRST (for MMIO, even if address is out of bounds)
-        if let Some(hook) = self.write_hook {
+        if let Some(hook) = &self.write_hook {
             if matches!(
                 hook(address, value, OperandSize::Long),
                 WriteHookResult::Handled
@@ -389,6 +394,20 @@ pub fn load_binary(&mut self, address: u32, data: &[u8]) -> Result<usize, Memory
         Ok(data.len())
     }
 
+    /// Writes a block of bytes, one byte write at a time.
+    ///
+    /// Unlike `load_binary`, every byte goes through the write hook, so
+    /// memory-mapped devices see the writes. Writing stops at the first
+    /// byte that fails; the error holds its address.
+    ///
+    /// Returns the number of bytes written.
+    pub fn write_bytes(&mut self, address: u32, data: &[u8]) -> Result<usize, MemoryError> {
+        for (i, &byte) in data.iter().enumerate() {
+            self.write_byte(address.wrapping_add(i as u32), byte)?;
+        }
+        Ok(data.len())
+    }
+
     /// Dumps memory as a hex string for debugging.
     #[must_use]
     // Allow dead code: kept for tests, completeness, or CLI-only usage.
@@ -612,6 +631,23 @@ fn test_load_binary_out_of_bounds() {
         assert!(matches!(result, Err(MemoryError::AddressOutOfRange { .. })));
     }
 
+    #[test]
+    fn test_write_bytes_stops_at_first_failure() {
+        let mut mem = Memory::new(1024);
+        assert_eq!(mem.write_bytes(0x100, &[1, 2, 3]), Ok(3));
+        assert_eq!(mem.read_byte(0x102).unwrap(), 3);
+
+        let result = mem.write_bytes(0x3FE, &[0xAA; 4]);
+        assert_eq!(
+            result,
+            Err(MemoryError::AddressOutOfRange {
+                address: 0x400,
+                size: 1
+            })
+        );
+        assert_eq!(mem.read_byte(0x3FF).unwrap(), 0xAA);
+    }
+
     #[test]
     fn test_clear() {
         let mut mem = Memory::new(1024);
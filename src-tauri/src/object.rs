@@ -0,0 +1,599 @@
+//! Relocatable Objects and the Linker
+//!
+//! `Assembler::assemble_object` turns one source file into an `ObjectFile`:
+//! the code and data of each section, assembled from address 0, the symbols
+//! it exports (XDEF) and imports (XREF), and relocations for the fields that
+//! depend on where sections and imports end up. `link` places the sections
+//! of several objects, resolves each import to the export of that name and
+//! patches the fields, giving one binary.
+//!
+//! ## File Format
+//!
+//! ```text
+//! magic    8 bytes   "F32OBJ\0\0"
+//! version  u32       OBJECT_VERSION
+//! header   u32 length, then the header as JSON
+//! data     each section's bytes, in order
+//! ```
+//!
+//! Integers are big-endian. The header names the source file and lists the
+//! sections (name and size in bytes), exports, imports and relocations:
+//!
+//! ```json
+//! { "name": "main.asm",
+//!   "sections": [{ "name": "text", "size": 12 }],
+//!   "exports": [{ "name": "start", "section": 0, "value": 0 }],
+//!   "imports": ["print"],
+//!   "relocations": [{ "section": 0, "offset": 2, "kind": "abs32",
+//!                     "target": { "symbol": "print" }, "addend": 0 }] }
+//! ```
+//!
+//! A relocation's target is a section of the same object, by index, or an
+//! imported symbol. An `abs32` relocation writes the target's address plus
+//! the addend to the 32-bit field at `offset` in `section`; a `pc16` one
+//! writes that address less the field's own, the 16-bit displacement of a
+//! branch or `d16(PC)`. An export's value is an offset in its section, or a
+//! constant when it has none.
+//!
+//! ## Layout
+//!
+//! Sections with the same name are placed together, in the order the names
+//! first appear, and within a name in the order the objects are given. Each
+//! object's piece starts at an even address.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Magic bytes at the start of an object file
+pub const OBJECT_MAGIC: &[u8; 8] = b"F32OBJ\0\0";
+
+/// Object format version; files with another version are rejected
+pub const OBJECT_VERSION: u32 = 1;
+
+/// Code or data assembled into one named section
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectSection {
+    /// Section name, as given to SECTION ("text" before any)
+    pub name: String,
+    /// Bytes, assembled from address 0
+    pub data: Vec<u8>,
+}
+
+/// A symbol an object defines for others (XDEF)
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Export {
+    pub name: String,
+    /// Section the symbol is a label in, or none for a constant
+    pub section: Option<usize>,
+    /// Offset in the section, or the constant's value
+    pub value: i64,
+}
+
+/// How a relocated field is patched
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelocKind {
+    /// 32-bit absolute address
+    Abs32,
+    /// 16-bit displacement from the field's own address
+    Pc16,
+}
+
+/// What a relocated field refers to
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelocTarget {
+    /// The start of a section of the same object
+    Section(usize),
+    /// An imported symbol
+    Symbol(String),
+}
+
+/// A field whose value depends on where things are placed
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Relocation {
+    /// Section the field is in
+    pub section: usize,
+    /// Offset of the field in the section
+    pub offset: u32,
+    pub kind: RelocKind,
+    pub target: RelocTarget,
+    /// Added to the target's address
+    pub addend: i64,
+}
+
+/// One assembled source file, ready to link
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectFile {
+    /// Source file name, which link errors name
+    pub name: String,
+    pub sections: Vec<ObjectSection>,
+    pub exports: Vec<Export>,
+    /// Symbols other objects must define
+    pub imports: Vec<String>,
+    pub relocations: Vec<Relocation>,
+}
+
+/// A section as the file header lists it
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SectionHeader {
+    name: String,
+    size: u32,
+}
+
+/// Everything in an object file but the section data
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Header {
+    name: String,
+    sections: Vec<SectionHeader>,
+    exports: Vec<Export>,
+    imports: Vec<String>,
+    relocations: Vec<Relocation>,
+}
+
+/// Errors that can occur while saving or loading an object file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectError {
+    /// Reading or writing the file failed
+    Io {
+        path: String,
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// The file doesn't start with the object magic
+    NotObject,
+    /// The file has another format version
+    Version { found: u32, expected: u32 },
+    /// The file is truncated or its contents don't hold together
+    Corrupt { message: String },
+}
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, message, .. } => write!(f, "Object {path}: {message}"),
+            Self::NotObject => write!(f, "Not a Flux32 object file"),
+            Self::Version { found, expected } => write!(
+                f,
+                "Object version {found} is not supported (expected {expected})"
+            ),
+            Self::Corrupt { message } => write!(f, "Corrupt object: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjectError {}
+
+/// Maps a read or parse error to `ObjectError::Corrupt`
+fn corrupt(e: impl fmt::Display) -> ObjectError {
+    ObjectError::Corrupt {
+        message: e.to_string(),
+    }
+}
+
+impl ObjectFile {
+    /// Serializes the object in the file format
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = Header {
+            name: self.name.clone(),
+            sections: self
+                .sections
+                .iter()
+                .map(|section| SectionHeader {
+                    name: section.name.clone(),
+                    size: section.data.len() as u32,
+                })
+                .collect(),
+            exports: self.exports.clone(),
+            imports: self.imports.clone(),
+            relocations: self.relocations.clone(),
+        };
+        let header = serde_json::to_vec(&header).expect("object header serializes");
+
+        let mut out = Vec::new();
+        out.extend_from_slice(OBJECT_MAGIC);
+        out.extend_from_slice(&OBJECT_VERSION.to_be_bytes());
+        out.extend_from_slice(&(header.len() as u32).to_be_bytes());
+        out.extend_from_slice(&header);
+        for section in &self.sections {
+            out.extend_from_slice(&section.data);
+        }
+        out
+    }
+
+    /// Parses an object, checking that its relocations and exports refer
+    /// to sections and imports it has
+    pub fn read(reader: &mut impl Read) -> Result<Self, ObjectError> {
+        let mut magic = [0; 8];
+        let mut word = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| ObjectError::NotObject)?;
+        if &magic != OBJECT_MAGIC {
+            return Err(ObjectError::NotObject);
+        }
+        reader.read_exact(&mut word).map_err(corrupt)?;
+        let version = u32::from_be_bytes(word);
+        if version != OBJECT_VERSION {
+            return Err(ObjectError::Version {
+                found: version,
+                expected: OBJECT_VERSION,
+            });
+        }
+        reader.read_exact(&mut word).map_err(corrupt)?;
+        let mut header = vec![0; u32::from_be_bytes(word) as usize];
+        reader.read_exact(&mut header).map_err(corrupt)?;
+        let header: Header = serde_json::from_slice(&header).map_err(corrupt)?;
+
+        let mut sections = Vec::with_capacity(header.sections.len());
+        for section in header.sections {
+            let mut data = vec![0; section.size as usize];
+            reader.read_exact(&mut data).map_err(corrupt)?;
+            sections.push(ObjectSection {
+                name: section.name,
+                data,
+            });
+        }
+        if reader.read(&mut [0]).map_err(corrupt)? != 0 {
+            return Err(corrupt("trailing data"));
+        }
+
+        let object = Self {
+            name: header.name,
+            sections,
+            exports: header.exports,
+            imports: header.imports,
+            relocations: header.relocations,
+        };
+        object.check().map_err(corrupt)?;
+        Ok(object)
+    }
+
+    /// Checks that every relocation's field lies in its section and every
+    /// section index and imported name exists
+    fn check(&self) -> Result<(), String> {
+        let section = |index: usize| {
+            self.sections
+                .get(index)
+                .ok_or_else(|| format!("no section {index}"))
+        };
+        for export in &self.exports {
+            if let Some(index) = export.section {
+                section(index)?;
+            }
+        }
+        for relocation in &self.relocations {
+            let size = match relocation.kind {
+                RelocKind::Abs32 => 4,
+                RelocKind::Pc16 => 2,
+            };
+            let end = relocation.offset as usize + size;
+            if end > section(relocation.section)?.data.len() {
+                return Err(format!(
+                    "relocation at ${:X} runs past its section",
+                    relocation.offset
+                ));
+            }
+            match &relocation.target {
+                RelocTarget::Section(index) => {
+                    section(*index)?;
+                }
+                RelocTarget::Symbol(name) if !self.imports.contains(name) => {
+                    return Err(format!("relocation refers to {name}, which isn't imported"));
+                }
+                RelocTarget::Symbol(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the object to a file
+    pub fn save(&self, path: &Path) -> Result<(), ObjectError> {
+        std::fs::write(path, self.to_bytes()).map_err(|e| io_error(path, &e))
+    }
+
+    /// Reads an object file
+    pub fn load(path: &Path) -> Result<Self, ObjectError> {
+        let file = std::fs::File::open(path).map_err(|e| io_error(path, &e))?;
+        Self::read(&mut io::BufReader::new(file))
+    }
+}
+
+/// Maps an I/O error on an object file to `ObjectError::Io`
+fn io_error(path: &Path, e: &io::Error) -> ObjectError {
+    ObjectError::Io {
+        path: path.display().to_string(),
+        kind: e.kind(),
+        message: e.to_string(),
+    }
+}
+
+/// Why objects didn't link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    /// No object exports a symbol others import
+    Undefined {
+        name: String,
+        importers: Vec<String>,
+    },
+    /// More than one object exports a symbol
+    Duplicate {
+        name: String,
+        exporters: Vec<String>,
+    },
+    /// A PC-relative field's target is more than 32 KB away
+    OutOfRange {
+        file: String,
+        section: String,
+        offset: u32,
+        distance: i64,
+    },
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Undefined { name, importers } => write!(
+                f,
+                "Undefined symbol {name} (imported by {})",
+                importers.join(", ")
+            ),
+            Self::Duplicate { name, exporters } => write!(
+                f,
+                "Symbol {name} is exported more than once (by {})",
+                exporters.join(", ")
+            ),
+            Self::OutOfRange {
+                file,
+                section,
+                offset,
+                distance,
+            } => write!(
+                f,
+                "{file}: PC-relative reference at {section}+${offset:X} is {distance} bytes from its target (-32768 to 32767)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// Objects linked into one binary
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct LinkedProgram {
+    /// Machine code and data, from `origin` on
+    pub binary: Vec<u8>,
+    /// Address of the first byte
+    pub origin: u32,
+    /// Exported symbols and their addresses (or values), by name
+    pub symbols: BTreeMap<String, i64>,
+}
+
+/// Links objects into a binary starting at `origin`, or returns every
+/// undefined and duplicate symbol and out-of-range reference found
+pub fn link(objects: &[ObjectFile], origin: u32) -> Result<LinkedProgram, Vec<LinkError>> {
+    // Place the sections, each object's piece of a name together
+    let mut names: Vec<&str> = Vec::new();
+    for section in objects.iter().flat_map(|object| &object.sections) {
+        if !names.contains(&section.name.as_str()) {
+            names.push(&section.name);
+        }
+    }
+    let mut bases: Vec<Vec<u32>> = objects
+        .iter()
+        .map(|object| vec![0; object.sections.len()])
+        .collect();
+    let mut binary = Vec::new();
+    for name in names {
+        for (i, object) in objects.iter().enumerate() {
+            for (j, section) in object.sections.iter().enumerate() {
+                if section.name == name {
+                    binary.resize(binary.len().next_multiple_of(2), 0);
+                    bases[i][j] = origin + binary.len() as u32;
+                    binary.extend_from_slice(&section.data);
+                }
+            }
+        }
+    }
+
+    // Resolve the exports and imports
+    let mut exporters: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    let mut symbols = BTreeMap::new();
+    for (i, object) in objects.iter().enumerate() {
+        for export in &object.exports {
+            let value = export.section.map_or(export.value, |section| {
+                i64::from(bases[i][section]) + export.value
+            });
+            symbols.entry(export.name.clone()).or_insert(value);
+            exporters
+                .entry(&export.name)
+                .or_default()
+                .push(object.name.clone());
+        }
+    }
+    let mut errors: Vec<LinkError> = exporters
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|(name, exporters)| LinkError::Duplicate {
+            name: name.to_string(),
+            exporters,
+        })
+        .collect();
+    let mut importers: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for object in objects {
+        for name in &object.imports {
+            if !symbols.contains_key(name) {
+                importers.entry(name).or_default().push(object.name.clone());
+            }
+        }
+    }
+    errors.extend(
+        importers
+            .into_iter()
+            .map(|(name, importers)| LinkError::Undefined {
+                name: name.to_string(),
+                importers,
+            }),
+    );
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // Patch the fields
+    for (i, object) in objects.iter().enumerate() {
+        for relocation in &object.relocations {
+            let target = relocation.addend
+                + match &relocation.target {
+                    RelocTarget::Section(section) => i64::from(bases[i][*section]),
+                    RelocTarget::Symbol(name) => symbols[name],
+                };
+            let at = bases[i][relocation.section] + relocation.offset;
+            let field = (at - origin) as usize;
+            match relocation.kind {
+                RelocKind::Abs32 => {
+                    binary[field..field + 4].copy_from_slice(&(target as u32).to_be_bytes());
+                }
+                RelocKind::Pc16 => {
+                    let distance = target - i64::from(at);
+                    let Ok(disp) = i16::try_from(distance) else {
+                        errors.push(LinkError::OutOfRange {
+                            file: object.name.clone(),
+                            section: object.sections[relocation.section].name.clone(),
+                            offset: relocation.offset,
+                            distance,
+                        });
+                        continue;
+                    };
+                    binary[field..field + 2].copy_from_slice(&disp.to_be_bytes());
+                }
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(LinkedProgram {
+        binary,
+        origin,
+        symbols,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+    use crate::sbc::{Sbc, APP_START};
+
+    const MAIN: &str = "
+        xref    double,value
+        xdef    start
+start:  move.w  value,d0
+        bsr     double
+        movea.l table,a1
+        jsr     (a1)
+        lea     result,a0
+        move.w  d0,(a0)
+        stop    #$2700
+
+        section data
+result: dc.w    0
+table:  dc.l    double
+";
+
+    const LIB: &str = "
+        xdef    double,value
+double: add.w   d0,d0
+        rts
+
+        section data,data
+value:  dc.w    21
+";
+
+    fn object(source: &str, name: &str) -> ObjectFile {
+        Assembler::new()
+            .assemble_object(source, Path::new(name))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_link_runs_two_objects() {
+        let main = object(MAIN, "main.asm");
+        assert_eq!(main.imports, ["double", "value"]);
+        assert_eq!(main.sections.len(), 2);
+        let lib = object(LIB, "lib.asm");
+
+        let program = link(&[main, lib], APP_START).unwrap();
+        assert_eq!(program.symbols["start"], i64::from(APP_START));
+        let mut sbc = Sbc::new();
+        sbc.load_app(&program.binary, None).unwrap();
+        sbc.run_app(None);
+        sbc.run(10_000);
+        assert!(sbc.is_halted());
+        assert_eq!(sbc.registers().d(0) as u16, 84);
+        // JSR went through the address DC.L put in the data section
+        assert_eq!(i64::from(sbc.registers().a(1)), program.symbols["double"]);
+    }
+
+    #[test]
+    fn test_object_round_trips() {
+        let main = object(MAIN, "main.asm");
+        let bytes = main.to_bytes();
+        assert_eq!(&bytes[..8], OBJECT_MAGIC);
+        assert_eq!(ObjectFile::read(&mut &bytes[..]).unwrap(), main);
+
+        assert_eq!(
+            ObjectFile::read(&mut &b"F32SNAP\0"[..]).unwrap_err(),
+            ObjectError::NotObject
+        );
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(matches!(
+            ObjectFile::read(&mut &truncated[..]),
+            Err(ObjectError::Corrupt { .. })
+        ));
+    }
+
+    #[test]
+    fn test_link_reports_undefined_and_duplicate_symbols() {
+        let main = object(MAIN, "main.asm");
+        let errors = link(std::slice::from_ref(&main), APP_START).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "Undefined symbol double (imported by main.asm)",
+                "Undefined symbol value (imported by main.asm)",
+            ]
+        );
+
+        let lib = object(LIB, "lib.asm");
+        let other = object("        xdef double\ndouble: rts\n", "other.asm");
+        let errors = link(&[main, lib, other], APP_START).unwrap_err();
+        assert_eq!(
+            errors,
+            [LinkError::Duplicate {
+                name: "double".to_string(),
+                exporters: vec!["lib.asm".to_string(), "other.asm".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_object_rejects_unrelocatable_uses() {
+        let error = |source: &str| {
+            let diagnostics = Assembler::new()
+                .assemble_object(source, Path::new("bad.asm"))
+                .unwrap_err();
+            diagnostics[0].message.clone()
+        };
+        assert!(error("        xref x\n        move.w #x,d0\n").contains("needs a long"));
+        assert!(error("        xref x\n        bra.s x\n").contains("word displacement"));
+        assert!(error("        xref x\n        dc.l x*2\n").contains("name+n"));
+        assert!(error("        org $1000\n").contains("org"));
+        assert!(error("x:      nop\n        xref x\n").contains("can't be imported"));
+        assert!(error("        xdef y\n").contains("undefined symbol: y"));
+    }
+}
@@ -0,0 +1,28 @@
+//! Board Panel
+//!
+//! The host draws its board panel (the LED bar, the GPIO pins, the UART,
+//! the `CompactFlash` card and the timer) from the one JSON object
+//! `Sbc::panel_state` builds, so a refresh takes a single round trip. Each
+//! device on the panel implements `Peripheral` to describe its own section.
+//!
+//! Sections use camelCase keys. Devices the configuration leaves off the
+//! bus appear as `null`.
+//!
+//! `Sbc::dump_peripheral` returns one device's `debug_dump` instead: its
+//! registers and internal state, read without the side effects a guest read
+//! has.
+
+/// Names of the devices on the panel, as `Sbc::dump_peripheral` takes them
+pub const PERIPHERALS: [&str; 5] = ["leds", "gpio", "uart", "cf", "timer"];
+
+/// A device shown on the board panel
+pub trait Peripheral {
+    /// Returns the device's section of the panel state
+    fn ui_state(&self) -> serde_json::Value;
+
+    /// Returns the device's registers and internal state for debugging
+    ///
+    /// Unlike a guest read this has no side effects: FIFOs aren't popped
+    /// and read-to-clear flags stay set.
+    fn debug_dump(&self) -> serde_json::Value;
+}
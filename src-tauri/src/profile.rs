@@ -0,0 +1,233 @@
+//! Execution Profiler
+//!
+//! While profiling is on, `Sbc` charges the cycles of every instruction it
+//! executes to the instruction's address. A report ranks the addresses by
+//! cycles spent, names them after the nearest label, and totals the cycles
+//! by mnemonic, so the hot spots of a running program stand out.
+//!
+//! Samples accumulate across start/stop, so a profile can cover several
+//! windows of a run; `reset` clears them.
+
+use std::collections::HashMap;
+
+/// Cycles and executions charged to one address or mnemonic
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Sample {
+    cycles: u64,
+    count: u64,
+}
+
+/// An address in a profile report
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hotspot {
+    /// Instruction address
+    pub address: u32,
+    /// Closest label at or below the address (`label` or `label+$offset`)
+    pub symbol: Option<String>,
+    /// Instruction at the address (`mnemonic operands`)
+    pub instruction: String,
+    /// Cycles spent executing it
+    pub cycles: u64,
+    /// Times it executed
+    pub count: u64,
+    /// Share of the profiled cycles, in percent
+    pub percent: f64,
+}
+
+/// Cycles spent on one mnemonic in a profile report
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MnemonicTotal {
+    /// Mnemonic with size suffix (e.g. `move.l`)
+    pub mnemonic: String,
+    /// Cycles spent on instructions with this mnemonic
+    pub cycles: u64,
+    /// Instructions executed with this mnemonic
+    pub count: u64,
+    /// Share of the profiled cycles, in percent
+    pub percent: f64,
+}
+
+/// Where the profiled cycles went
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileReport {
+    /// Samples are being collected
+    pub running: bool,
+    /// Cycles executed while profiling
+    pub total_cycles: u64,
+    /// Instructions executed while profiling
+    pub instructions: u64,
+    /// Hottest addresses, most cycles first
+    pub hotspots: Vec<Hotspot>,
+    /// Cycles by mnemonic, most cycles first
+    pub mnemonics: Vec<MnemonicTotal>,
+}
+
+/// Per-address cycle counts of a profiled run
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    /// Samples by instruction address
+    samples: HashMap<u32, Sample>,
+    /// Cycles charged so far
+    total_cycles: u64,
+    /// Instructions charged so far
+    instructions: u64,
+    /// Whether instructions are being charged
+    enabled: bool,
+}
+
+impl Profiler {
+    /// Creates an empty profiler that isn't collecting
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts collecting samples
+    pub const fn start(&mut self) {
+        self.enabled = true;
+    }
+
+    /// Stops collecting samples, keeping the ones collected
+    pub const fn stop(&mut self) {
+        self.enabled = false;
+    }
+
+    /// Discards the samples (collection carries on if started)
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.total_cycles = 0;
+        self.instructions = 0;
+    }
+
+    /// Charges an executed instruction's cycles to its address
+    pub fn record(&mut self, pc: u32, cycles: u64) {
+        if !self.enabled {
+            return;
+        }
+        let sample = self.samples.entry(pc).or_default();
+        sample.cycles += cycles;
+        sample.count += 1;
+        self.total_cycles += cycles;
+        self.instructions += 1;
+    }
+
+    /// Builds a report of the `top_n` hottest addresses
+    ///
+    /// `symbol` names an address, and `instruction` disassembles the
+    /// instruction at it as `mnemonic operands`.
+    #[allow(
+        clippy::cast_precision_loss,
+        reason = "Percentages don't need all 64 bits of a cycle count"
+    )]
+    pub fn report(
+        &self,
+        top_n: usize,
+        symbol: impl Fn(u32) -> Option<String>,
+        instruction: impl Fn(u32) -> String,
+    ) -> ProfileReport {
+        let percent = |cycles: u64| {
+            if self.total_cycles == 0 {
+                0.0
+            } else {
+                cycles as f64 * 100.0 / self.total_cycles as f64
+            }
+        };
+
+        let mut addresses: Vec<_> = self.samples.iter().collect();
+        addresses.sort_by(|(a, x), (b, y)| y.cycles.cmp(&x.cycles).then(a.cmp(b)));
+
+        let mut by_mnemonic: HashMap<String, Sample> = HashMap::new();
+        let mut hotspots = Vec::new();
+        for (&address, sample) in addresses {
+            let instruction = instruction(address);
+            let mnemonic = instruction.split_whitespace().next().unwrap_or_default();
+            let total = by_mnemonic.entry(mnemonic.to_string()).or_default();
+            total.cycles += sample.cycles;
+            total.count += sample.count;
+            if hotspots.len() < top_n {
+                hotspots.push(Hotspot {
+                    address,
+                    symbol: symbol(address),
+                    instruction,
+                    cycles: sample.cycles,
+                    count: sample.count,
+                    percent: percent(sample.cycles),
+                });
+            }
+        }
+
+        let mut mnemonics: Vec<_> = by_mnemonic
+            .into_iter()
+            .map(|(mnemonic, sample)| MnemonicTotal {
+                mnemonic,
+                cycles: sample.cycles,
+                count: sample.count,
+                percent: percent(sample.cycles),
+            })
+            .collect();
+        mnemonics.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.mnemonic.cmp(&b.mnemonic)));
+
+        ProfileReport {
+            running: self.enabled,
+            total_cycles: self.total_cycles,
+            instructions: self.instructions,
+            hotspots,
+            mnemonics,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiler_report() {
+        let mut profiler = Profiler::new();
+        profiler.record(0x100, 4);
+        assert_eq!(
+            profiler.report(5, |_| None, |_| String::new()).instructions,
+            0
+        );
+
+        profiler.start();
+        for _ in 0..3 {
+            profiler.record(0x100, 4);
+            profiler.record(0x102, 10);
+        }
+        profiler.record(0x104, 10);
+        profiler.stop();
+
+        let names = |address| (address == 0x102).then(|| "loop".to_string());
+        let instructions = |address| match address {
+            0x100 => "addq.l #1,d1".to_string(),
+            _ => "bne.s $100".to_string(),
+        };
+        let report = profiler.report(2, names, instructions);
+        assert!(!report.running);
+        assert_eq!((report.total_cycles, report.instructions), (52, 7));
+        let hot: Vec<_> = report
+            .hotspots
+            .iter()
+            .map(|h| (h.address, h.cycles, h.count))
+            .collect();
+        assert_eq!(hot, [(0x102, 30, 3), (0x100, 12, 3)]);
+        assert_eq!(report.hotspots[0].symbol.as_deref(), Some("loop"));
+        assert!((report.hotspots[1].percent - 12.0 * 100.0 / 52.0).abs() < 1e-9);
+        // Mnemonic totals cover every address, not just the top ones
+        let totals: Vec<_> = report
+            .mnemonics
+            .iter()
+            .map(|m| (m.mnemonic.as_str(), m.cycles, m.count))
+            .collect();
+        assert_eq!(totals, [("bne.s", 40, 4), ("addq.l", 12, 3)]);
+
+        profiler.reset();
+        let report = profiler.report(2, |_| None, |_| String::new());
+        assert_eq!(report.total_cycles, 0);
+        assert!(report.hotspots.is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+//! Assembled Programs
+//!
+//! An `AssembledProgram` is editor code after assembly: its machine code,
+//! the ORG address it was assembled for and its symbols. `load` places it
+//! in a machine's RAM and starts it. Labels move with the program, so the
+//! symbol table `load` reports and hands to the debugger holds the
+//! addresses the labels ended up at; EQU and RS constants keep their values.
+//! BSS sections aren't in the machine code, so `load` clears their space.
+
+use crate::assembler::Assembler;
+use crate::bus::ADDR_MASK;
+use crate::error::EmulatorError;
+use crate::sbc::{Sbc, APP_START};
+use std::collections::{BTreeMap, HashSet};
+
+/// Machine code and symbols from assembling a program
+#[derive(Clone, Debug, Default)]
+pub struct AssembledProgram {
+    /// Machine code
+    pub binary: Vec<u8>,
+    /// ORG address (0 if none)
+    pub origin: u32,
+    /// Code and data labels and their assembled addresses
+    pub labels: Vec<(String, u32)>,
+    /// EQU and RS constants and their values
+    pub constants: Vec<(String, i64)>,
+    /// BSS sections as assembled address and size, past the machine code
+    pub bss: Vec<(u32, u32)>,
+}
+
+/// Where a program was loaded, and its symbols there
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadedProgram {
+    /// Address of the first byte
+    pub load_address: u32,
+    /// Size in bytes
+    pub size: u32,
+    /// Address execution started at
+    pub entry: u32,
+    /// Labels at their loaded addresses, plus the constants when asked for
+    pub symbols: BTreeMap<String, u32>,
+}
+
+impl AssembledProgram {
+    /// Takes the output of an assembly `asm` just finished
+    pub fn from_assembler(asm: &mut Assembler, binary: Vec<u8>) -> Self {
+        let labels = std::mem::take(&mut asm.labels);
+        let names: HashSet<&str> = labels.iter().map(|(name, _)| name.as_str()).collect();
+        let mut constants: Vec<_> = asm
+            .symbols
+            .as_map()
+            .iter()
+            .filter(|(name, _)| !names.contains(name.as_str()))
+            .map(|(name, &value)| (name.clone(), value))
+            .collect();
+        constants.sort();
+        Self {
+            binary,
+            origin: asm.origin,
+            labels,
+            constants,
+            bss: asm.bss_space(),
+        }
+    }
+
+    /// Loads the program into RAM at `load_addr` and starts it at `entry`,
+    /// replacing the debugger's symbols with its labels
+    ///
+    /// `load_addr` defaults to the ORG address, or `APP_START` without one,
+    /// and `entry` defaults to the load address. Constants are only listed
+    /// in the result when `include_constants` is set.
+    pub fn load(
+        &self,
+        sbc: &mut Sbc,
+        load_addr: Option<u32>,
+        entry: Option<u32>,
+        include_constants: bool,
+    ) -> Result<LoadedProgram, EmulatorError> {
+        if self.binary.is_empty() {
+            return Err(EmulatorError::failed("Assembly produced no output"));
+        }
+        let load_addr = load_addr.unwrap_or(if self.origin == 0 {
+            APP_START
+        } else {
+            self.origin
+        });
+        let entry = entry.unwrap_or(load_addr);
+        let end = load_addr.saturating_add(self.binary.len() as u32);
+        if !(load_addr..end).contains(&entry) {
+            return Err(EmulatorError::invalid(
+                "entry",
+                format!(
+                    "Entry point ${entry:06X} is outside the program (${load_addr:06X}-${:06X})",
+                    end - 1
+                ),
+            ));
+        }
+        sbc.load_app(&self.binary, Some(load_addr))?;
+        for &(start, size) in &self.bss {
+            let addr = start.wrapping_sub(self.origin).wrapping_add(load_addr);
+            sbc.load_app(&vec![0; size as usize], Some(addr & ADDR_MASK))?;
+        }
+
+        let labels: Vec<_> = self
+            .labels
+            .iter()
+            .map(|(name, addr)| {
+                let addr = addr.wrapping_sub(self.origin).wrapping_add(load_addr);
+                (name.clone(), addr & ADDR_MASK)
+            })
+            .collect();
+        sbc.debugger_mut().set_symbols(labels.iter().cloned());
+        sbc.run_app(Some(entry));
+
+        let mut symbols: BTreeMap<_, _> = labels.into_iter().collect();
+        if include_constants {
+            for (name, value) in &self.constants {
+                symbols.insert(name.clone(), *value as u32);
+            }
+        }
+        Ok(LoadedProgram {
+            load_address: load_addr,
+            size: self.binary.len() as u32,
+            entry,
+            symbols,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::SymbolSource;
+    use std::path::Path;
+
+    fn assemble(source: &str) -> AssembledProgram {
+        let mut asm = Assembler::new();
+        let binary = asm.assemble_source(source, Path::new("<test>")).unwrap();
+        AssembledProgram::from_assembler(&mut asm, binary)
+    }
+
+    #[test]
+    fn test_load_reports_relocated_symbols() {
+        let program = assemble(
+            "
+COUNT   equ     3
+start:  moveq   #COUNT,d0
+loop:   subq.l  #1,d0
+        bne.s   loop
+done:   bra.s   done
+",
+        );
+        let mut sbc = Sbc::new();
+        let loaded = program
+            .load(&mut sbc, Some(0x00E0_1000), Some(0x00E0_1002), false)
+            .unwrap();
+        assert_eq!(loaded.load_address, 0x00E0_1000);
+        assert_eq!(loaded.size, 8);
+        assert_eq!(loaded.entry, 0x00E0_1002);
+        assert_eq!(sbc.pc(), 0x00E0_1002);
+
+        // Each label names the instruction the listing shows at its address
+        let listing = sbc.disassemble(0x00E0_1000, 4);
+        let listed: Vec<_> = listing
+            .iter()
+            .map(|line| (line.mnemonic.as_str(), line.addr))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("moveq", 0x00E0_1000),
+                ("subq.l", 0x00E0_1002),
+                ("bne.s", 0x00E0_1004),
+                ("bra.s", 0x00E0_1006)
+            ]
+        );
+        let expected: BTreeMap<_, _> = [("start", 0), ("loop", 1), ("done", 3)]
+            .into_iter()
+            .map(|(name, line)| (name.to_string(), listing[line].addr))
+            .collect();
+        assert_eq!(loaded.symbols, expected);
+        for (name, addr) in &expected {
+            let line = listing.iter().find(|line| line.addr == *addr).unwrap();
+            assert_eq!(line.symbol.as_deref(), Some(name.as_str()));
+        }
+
+        // Constants are only listed when asked for
+        let loaded = program.load(&mut sbc, None, None, true).unwrap();
+        assert_eq!(loaded.load_address, APP_START);
+        assert_eq!(loaded.symbols["COUNT"], 3);
+        assert_eq!(loaded.symbols["loop"], APP_START + 2);
+    }
+
+    #[test]
+    fn test_loaded_symbols_resolve_addresses() {
+        let program = assemble(
+            "
+start:  bsr.s   fill
+done:   bra.s   done
+fill:   lea     $E02000,a0
+        moveq   #7,d0
+fill_loop:
+        clr.b   (a0)+
+        dbra    d0,fill_loop
+        rts
+",
+        );
+        let mut sbc = Sbc::new();
+        program.load(&mut sbc, None, None, false).unwrap();
+
+        let found = sbc.debugger().symbols(Some("fill"));
+        let names: Vec<_> = found.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(names, ["fill", "fill_loop"]);
+        assert_eq!(found[0].address, APP_START + 4);
+        assert_eq!(found[0].source, SymbolSource::Program);
+
+        // The MOVEQ in the middle of the routine
+        let symbol = sbc.debugger().lookup(APP_START + 10).unwrap();
+        assert_eq!((symbol.symbol.name.as_str(), symbol.offset), ("fill", 6));
+        assert_eq!(symbol.label, "fill+$6");
+        assert_eq!(sbc.debugger().lookup(APP_START - 1), None);
+    }
+
+    #[test]
+    fn test_load_clears_bss() {
+        let program = assemble(
+            "
+start:  lea     counts,a0
+        rts
+        section bss
+counts: ds.l    4
+",
+        );
+        let mut sbc = Sbc::new();
+        let counts = APP_START + 6;
+        sbc.load_app(&[0xFF; 16], Some(counts)).unwrap();
+        let loaded = program.load(&mut sbc, None, None, false).unwrap();
+        assert_eq!(loaded.size, 6);
+        assert_eq!(loaded.symbols["counts"], counts);
+        for offset in 0..16 {
+            assert_eq!(sbc.cpu().memory.read_byte(counts + offset), Ok(0));
+        }
+    }
+
+    #[test]
+    fn test_load_rejects_entry_outside_program() {
+        let program = assemble("        nop\n        rts\n");
+        let mut sbc = Sbc::new();
+        let err = program
+            .load(&mut sbc, Some(0x00E0_1000), Some(0x00E0_1004), false)
+            .unwrap_err();
+        assert!(err.to_string().contains("outside the program"), "{err}");
+        assert!(
+            matches!(err, EmulatorError::InvalidArgument { ref field, .. } if field == "entry")
+        );
+    }
+}
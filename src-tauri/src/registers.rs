@@ -23,7 +23,7 @@
 /// - S=0 (user mode): A7 = USP
 ///
 /// Internally we track both and swap A7 when the mode changes.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct RegisterFile {
     /// Data registers D0-D7 (32-bit each)
     pub d: [u32; 8],
@@ -188,6 +188,56 @@ pub const fn set_sr(&mut self, value: u16) {
         self.sr = value;
     }
 
+    /// Writes to the supervisor stack pointer (SSP).
+    /// Sets the actual SSP regardless of current mode.
+    #[inline]
+    pub const fn set_ssp(&mut self, value: u32) {
+        let supervisor = (self.sr & 0x2000) != 0;
+        if supervisor {
+            self.a[7] = value; // In supervisor mode, A7 is SSP
+        } else {
+            self.ssp = value; // In user mode, SSP is stored separately
+        }
+    }
+
+    /// Reads a register by name, as a debugger does (A7 is the active
+    /// stack pointer)
+    #[must_use]
+    pub const fn read(&self, reg: Register) -> u32 {
+        match reg {
+            Register::D(n) => self.d[n],
+            Register::A(n) => self.a[n],
+            Register::Pc => self.pc,
+            Register::Sr => self.sr as u32,
+            Register::Usp => self.usp(),
+            Register::Ssp => self.get_ssp(),
+        }
+    }
+
+    /// Writes a register by name, as a debugger does.
+    ///
+    /// A7 is the active stack pointer, so it writes USP or SSP depending on
+    /// the mode. SR writes go through `set_sr`, swapping stacks when the S
+    /// bit changes; bits the 68000 doesn't implement read back as 0.
+    ///
+    /// # Errors
+    /// Returns an error for an odd PC or an SR value wider than 16 bits.
+    pub fn write(&mut self, reg: Register, value: u32) -> Result<(), RegisterError> {
+        match reg {
+            Register::D(n) => self.set_d(n, value),
+            Register::A(n) => self.set_a(n, value),
+            Register::Pc if value & 1 != 0 => return Err(RegisterError::OddPc(value)),
+            Register::Pc => self.set_pc(value),
+            Register::Sr => {
+                let sr = u16::try_from(value).map_err(|_| RegisterError::SrTooWide(value))?;
+                self.set_sr(sr & SR_IMPLEMENTED);
+            }
+            Register::Usp => self.set_usp(value),
+            Register::Ssp => self.set_ssp(value),
+        }
+        Ok(())
+    }
+
     /// Gets the SSP (supervisor stack pointer) value.
     /// Returns the actual SSP regardless of current mode.
     #[must_use]
@@ -202,6 +252,75 @@ pub const fn get_ssp(&self) -> u32 {
     }
 }
 
+/// SR bits the 68000 implements (T, S, I2-I0, X, N, Z, V, C)
+pub const SR_IMPLEMENTED: u16 = 0xA71F;
+
+/// A register a debugger can write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    /// Data register D0-D7
+    D(usize),
+    /// Address register A0-A7 (A7 is the active stack pointer)
+    A(usize),
+    /// Program counter
+    Pc,
+    /// Status register
+    Sr,
+    /// User stack pointer
+    Usp,
+    /// Supervisor stack pointer
+    Ssp,
+}
+
+impl std::str::FromStr for Register {
+    type Err = RegisterError;
+
+    /// Parses a register name (case-insensitive); SP is an alias for A7
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let upper = name.trim().to_ascii_uppercase();
+        let numbered = |prefix: char| {
+            upper
+                .strip_prefix(prefix)
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|&n| n < 8 && upper.len() == 2)
+        };
+        match upper.as_str() {
+            "PC" => Ok(Self::Pc),
+            "SR" => Ok(Self::Sr),
+            "USP" => Ok(Self::Usp),
+            "SSP" => Ok(Self::Ssp),
+            "SP" => Ok(Self::A(7)),
+            _ => numbered('D')
+                .map(Self::D)
+                .or_else(|| numbered('A').map(Self::A))
+                .ok_or_else(|| RegisterError::Unknown(name.to_string())),
+        }
+    }
+}
+
+/// Errors from writing a register by name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegisterError {
+    /// The name isn't a register
+    Unknown(String),
+    /// PC values must be even
+    OddPc(u32),
+    /// SR is 16 bits wide
+    SrTooWide(u32),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "Unknown register: {name}"),
+            Self::OddPc(value) => write!(f, "PC must be even (got ${value:08X})"),
+            Self::SrTooWide(value) => write!(f, "SR is 16 bits (got ${value:X})"),
+        }
+    }
+}
+
+impl std::error::Error for RegisterError {}
+
 impl fmt::Display for RegisterFile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Data Registers:")?;
@@ -223,7 +342,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 ///
 /// These are the lower 5 bits of the SR (bits 0-4 of the byte
 /// accessed in user mode).
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
 // Allow dead code: kept for tests, completeness, or CLI-only usage.
 #[allow(dead_code)]
 pub struct CcrFlags {
@@ -299,6 +418,56 @@ fn default() -> Self {
     }
 }
 
+/// CPU register state as the host's register view shows it.
+///
+/// Besides the raw registers, this decodes SR and names the live stack
+/// pointer as `a7`, so a frontend needn't know the SR layout or which of
+/// USP and SSP is active.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuState {
+    /// Data registers D0-D7
+    pub d: Vec<u32>,
+    /// Address registers A0-A6
+    pub a: Vec<u32>,
+    /// Active stack pointer (USP in user mode, SSP in supervisor mode)
+    pub a7: u32,
+    /// Program counter
+    pub pc: u32,
+    /// Status register
+    pub sr: u16,
+    /// User stack pointer
+    pub usp: u32,
+    /// Supervisor stack pointer
+    pub ssp: u32,
+    /// Condition code flags
+    pub flags: CcrFlags,
+    /// Trace bit (T)
+    pub trace: bool,
+    /// Supervisor bit (S)
+    pub supervisor: bool,
+    /// Interrupt mask (I2-I0)
+    pub interrupt_mask: u8,
+}
+
+impl From<&RegisterFile> for CpuState {
+    fn from(regs: &RegisterFile) -> Self {
+        Self {
+            d: regs.d.to_vec(),
+            a: regs.a[0..7].to_vec(),
+            a7: regs.sp(),
+            pc: regs.pc,
+            sr: regs.sr,
+            usp: regs.usp(),
+            ssp: regs.get_ssp(),
+            flags: CcrFlags::from_sr(regs.sr),
+            trace: regs.sr & 0x8000 != 0,
+            supervisor: regs.sr & 0x2000 != 0,
+            interrupt_mask: ((regs.sr >> 8) & 7) as u8,
+        }
+    }
+}
+
 /// Extension trait for easy flag manipulation on `RegisterFile`.
 // Allow dead code: kept for tests, completeness, or CLI-only usage.
 #[allow(dead_code)]
@@ -673,4 +842,74 @@ fn test_ccr_roundtrip() {
         assert_eq!(flags.n, flags2.n);
         assert_eq!(flags.x, flags2.x);
     }
+
+    #[test]
+    fn test_write_register_by_name() {
+        let mut rf = RegisterFile::new();
+        rf.set_sr(0x2700);
+        rf.write("d3".parse().unwrap(), 0x1234).unwrap();
+        rf.write("SP".parse().unwrap(), 0x00E0_8000).unwrap();
+        rf.write("usp".parse().unwrap(), 0x00E0_4000).unwrap();
+        assert_eq!(rf.d(3), 0x1234);
+        assert_eq!(rf.get_ssp(), 0x00E0_8000);
+
+        // Dropping to user mode swaps A7 to the USP
+        rf.write(Register::Sr, 0x0015).unwrap();
+        assert_eq!(rf.sp(), 0x00E0_4000);
+        rf.write(Register::Ssp, 0x00E0_9000).unwrap();
+        assert_eq!(rf.get_ssp(), 0x00E0_9000);
+        rf.write(Register::Sr, 0xFFFF).unwrap();
+        assert_eq!(rf.sr(), 0xA71F);
+        assert_eq!(rf.sp(), 0x00E0_9000);
+
+        assert_eq!(
+            rf.write(Register::Pc, 0x0041),
+            Err(RegisterError::OddPc(0x0041))
+        );
+        assert_eq!(
+            rf.write(Register::Sr, 0x1_0000),
+            Err(RegisterError::SrTooWide(0x1_0000))
+        );
+        for name in ["D8", "A10", "X0", "d"] {
+            assert_eq!(
+                name.parse::<Register>(),
+                Err(RegisterError::Unknown(name.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_cpu_state_serialization() {
+        let mut rf = RegisterFile::new();
+        rf.set_sr(0x2700);
+        rf.set_sp(0x00F0_0000);
+        rf.set_usp(0x00E8_0000);
+        rf.set_a(6, 0x00EF_FFF0);
+        rf.pc = 0x00E0_0100;
+
+        // Supervisor mode: A7 is the SSP
+        let json = serde_json::to_value(CpuState::from(&rf)).unwrap();
+        assert_eq!(json["a"].as_array().unwrap().len(), 7);
+        assert_eq!(json["a"][6], 0x00EF_FFF0);
+        assert_eq!(json["a7"], 0x00F0_0000);
+        assert_eq!(json["ssp"], 0x00F0_0000);
+        assert_eq!(json["usp"], 0x00E8_0000);
+        assert_eq!(json["supervisor"], true);
+        assert_eq!(json["trace"], false);
+        assert_eq!(json["interruptMask"], 7);
+        assert_eq!(json["sr"], 0x2700);
+
+        // User mode with flags set: A7 is the USP
+        rf.set_sr(0x8019);
+        let json = serde_json::to_value(CpuState::from(&rf)).unwrap();
+        assert_eq!(json["a7"], 0x00E8_0000);
+        assert_eq!(json["ssp"], 0x00F0_0000);
+        assert_eq!(json["supervisor"], false);
+        assert_eq!(json["trace"], true);
+        assert_eq!(json["interruptMask"], 0);
+        assert_eq!(
+            json["flags"],
+            serde_json::json!({"x": true, "n": true, "z": false, "v": false, "c": true})
+        );
+    }
 }
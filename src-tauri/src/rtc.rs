@@ -0,0 +1,555 @@
+//! Real-Time Clock Emulation
+//!
+//! This module emulates a DS1307-style real-time clock on the expansion bus.
+//! The clock is backed by the host clock: reads return host time (UTC) plus
+//! an offset, and guest writes adjust that offset rather than the host clock.
+//!
+//! ## Register Map (offsets from $800000)
+//!
+//! Like the UART, the 8-bit registers sit on even addresses.
+//!
+//! | Offset | Register | Range              |
+//! |--------|----------|--------------------|
+//! | 0      | Seconds  | 00-59 (BCD)        |
+//! | 2      | Minutes  | 00-59 (BCD)        |
+//! | 4      | Hours    | 00-23 (BCD, 24h)   |
+//! | 6      | Weekday  | 1-7 (1 = Sunday)   |
+//! | 8      | Date     | 01-31 (BCD)        |
+//! | 10     | Month    | 01-12 (BCD)        |
+//! | 12     | Year     | 00-99 (BCD, 20xx)  |
+//! | 14     | Control  | HOLD, IE           |
+//! | 16     | Status   | IF (write 1 to clear) |
+//!
+//! ## Consistent Reads and Writes
+//!
+//! Setting HOLD in the control register latches the time registers, so a
+//! multi-byte read can't tear across a seconds rollover. Writes made while
+//! HOLD is set go to the latched copy and take effect together when HOLD is
+//! cleared. Without HOLD, each write takes effect immediately. The weekday is
+//! derived from the date; writes to it are ignored.
+//!
+//! ## Interrupts
+//!
+//! With IE set in the control register, the RTC sets IF in the status
+//! register at each seconds rollover and asserts its interrupt line until
+//! the guest clears IF.
+
+#[cfg(test)]
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::scheduler::Clocked;
+
+/// Emulated cycles between host clock polls at the default clock (1 ms at
+/// 12 MHz)
+const POLL_CYCLES: u64 = 12_000;
+
+/// RTC register offsets
+pub mod regs {
+    /// Seconds (BCD)
+    pub const SECONDS: u32 = 0;
+    /// Minutes (BCD)
+    pub const MINUTES: u32 = 2;
+    /// Hours (BCD, 24-hour)
+    pub const HOURS: u32 = 4;
+    /// Day of week (1 = Sunday)
+    pub const WEEKDAY: u32 = 6;
+    /// Day of month (BCD)
+    pub const DATE: u32 = 8;
+    /// Month (BCD)
+    pub const MONTH: u32 = 10;
+    /// Year within the century (BCD)
+    pub const YEAR: u32 = 12;
+    /// Control register
+    pub const CONTROL: u32 = 14;
+    /// Status register
+    pub const STATUS: u32 = 16;
+}
+
+/// Control register bits
+pub mod control {
+    /// Latch the time registers
+    pub const HOLD: u8 = 0x01;
+    /// Enable the 1 Hz interrupt
+    pub const IE: u8 = 0x10;
+}
+
+/// Status register bits
+pub mod status {
+    /// 1 Hz interrupt flag
+    pub const IF: u8 = 0x01;
+}
+
+/// Source of host wall-clock time
+pub trait ClockSource: Send + Sync {
+    /// Returns the current time in seconds since the Unix epoch
+    fn now(&self) -> i64;
+}
+
+/// Host system clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64)
+    }
+}
+
+/// Manually advanced clock for tests
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    seconds: AtomicI64,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    /// Creates a clock stopped at the given Unix time
+    #[must_use]
+    pub const fn new(seconds: i64) -> Self {
+        Self {
+            seconds: AtomicI64::new(seconds),
+        }
+    }
+
+    /// Sets the current Unix time
+    pub fn set(&self, seconds: i64) {
+        self.seconds.store(seconds, Ordering::Relaxed);
+    }
+
+    /// Advances the clock by the given number of seconds
+    pub fn advance(&self, seconds: i64) {
+        self.seconds.fetch_add(seconds, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+impl ClockSource for ManualClock {
+    fn now(&self) -> i64 {
+        self.seconds.load(Ordering::Relaxed)
+    }
+}
+
+/// Broken-down calendar time
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DateTime {
+    /// Full year (2000-2099)
+    pub year: u16,
+    /// Month (1-12)
+    pub month: u8,
+    /// Day of month (1-31)
+    pub day: u8,
+    /// Hours (0-23)
+    pub hours: u8,
+    /// Minutes (0-59)
+    pub minutes: u8,
+    /// Seconds (0-59)
+    pub seconds: u8,
+}
+
+impl DateTime {
+    /// Converts Unix time to calendar time (UTC)
+    #[must_use]
+    pub const fn from_unix(time: i64) -> Self {
+        let days = time.div_euclid(86_400);
+        let secs = time.rem_euclid(86_400);
+
+        // Civil-from-days (proleptic Gregorian)
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hours: (secs / 3600) as u8,
+            minutes: (secs / 60 % 60) as u8,
+            seconds: (secs % 60) as u8,
+        }
+    }
+
+    /// Converts calendar time (UTC) to Unix time
+    ///
+    /// Out-of-range days roll over into the next month.
+    #[must_use]
+    pub const fn to_unix(self) -> i64 {
+        // Days-from-civil (proleptic Gregorian)
+        let month = self.month as i64;
+        let year = self.year as i64 - if month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+
+        days * 86_400 + self.hours as i64 * 3600 + self.minutes as i64 * 60 + self.seconds as i64
+    }
+
+    /// Returns the day of the week (1 = Sunday)
+    #[must_use]
+    pub const fn weekday(self) -> u8 {
+        // 1970-01-01 was a Thursday
+        (self.to_unix().div_euclid(86_400) + 4).rem_euclid(7) as u8 + 1
+    }
+}
+
+/// Real-time clock peripheral
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Rtc {
+    /// Host clock (not saved in snapshots)
+    #[serde(skip, default = "system_clock")]
+    clock: Arc<dyn ClockSource>,
+    /// Guest time minus host time, in seconds
+    offset: i64,
+    /// Control register
+    control: u8,
+    /// Status register
+    status: u8,
+    /// Time registers latched by HOLD
+    latched: Option<DateTime>,
+    /// True if the latched registers were written while held
+    latched_dirty: bool,
+    /// Guest time seen at the last poll (for the 1 Hz interrupt)
+    last_time: i64,
+    /// Emulated cycles since the last host clock poll
+    poll_cycles: u64,
+    /// Emulated cycles between host clock polls
+    poll_interval: u64,
+}
+
+/// Clock a restored snapshot's RTC starts with
+fn system_clock() -> Arc<dyn ClockSource> {
+    Arc::new(SystemClock)
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rtc {
+    /// Creates an RTC backed by the host system clock
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Creates an RTC backed by the given clock source
+    #[must_use]
+    pub fn with_clock(clock: Arc<dyn ClockSource>) -> Self {
+        let last_time = clock.now();
+        Self {
+            clock,
+            offset: 0,
+            control: 0,
+            status: 0,
+            latched: None,
+            latched_dirty: false,
+            last_time,
+            poll_cycles: 0,
+            poll_interval: POLL_CYCLES,
+        }
+    }
+
+    /// Sets the CPU clock, keeping the host clock polled once per emulated
+    /// millisecond
+    pub const fn set_cpu_clock(&mut self, clock_hz: u32) {
+        self.poll_interval = (clock_hz / 1000) as u64;
+    }
+
+    /// Replaces the clock source, keeping the guest's offset
+    pub fn set_clock(&mut self, clock: Arc<dyn ClockSource>) {
+        self.clock = clock;
+        self.last_time = self.time();
+    }
+
+    /// Returns the guest's time offset from the host clock in seconds
+    #[cfg(test)]
+    #[must_use]
+    pub const fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    /// Sets the guest's time offset from the host clock in seconds
+    #[cfg(test)]
+    pub const fn set_offset(&mut self, offset: i64) {
+        self.offset = offset;
+    }
+
+    /// Returns the current guest time as Unix seconds
+    #[must_use]
+    pub fn time(&self) -> i64 {
+        self.clock.now() + self.offset
+    }
+
+    /// Returns the current guest time as calendar time
+    #[must_use]
+    pub fn date_time(&self) -> DateTime {
+        DateTime::from_unix(self.time())
+    }
+
+    /// Sets the guest time, leaving the host clock untouched
+    pub fn set_date_time(&mut self, time: &DateTime) {
+        self.offset = time.to_unix() - self.clock.now();
+        self.last_time = self.time();
+    }
+
+    /// Resets the control and status registers (the time offset survives)
+    pub fn reset(&mut self) {
+        self.control = 0;
+        self.status = 0;
+        self.latched = None;
+        self.latched_dirty = false;
+        self.last_time = self.time();
+    }
+
+    /// Reads an RTC register
+    pub fn read(&mut self, offset: u32) -> u8 {
+        match offset {
+            regs::CONTROL => self.control,
+            regs::STATUS => self.status,
+            regs::SECONDS..=regs::YEAR if offset.is_multiple_of(2) => {
+                let time = self.latched.unwrap_or_else(|| self.date_time());
+                match offset {
+                    regs::SECONDS => to_bcd(time.seconds),
+                    regs::MINUTES => to_bcd(time.minutes),
+                    regs::HOURS => to_bcd(time.hours),
+                    regs::WEEKDAY => time.weekday(),
+                    regs::DATE => to_bcd(time.day),
+                    regs::MONTH => to_bcd(time.month),
+                    _ => to_bcd((time.year % 100) as u8),
+                }
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes an RTC register
+    pub fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            regs::CONTROL => {
+                let was_held = self.control & control::HOLD != 0;
+                self.control = value & (control::HOLD | control::IE);
+                let held = self.control & control::HOLD != 0;
+                if held && !was_held {
+                    self.latched = Some(self.date_time());
+                    self.latched_dirty = false;
+                } else if !held && was_held {
+                    if let Some(time) = self.latched.take() {
+                        if self.latched_dirty {
+                            self.set_date_time(&time);
+                        }
+                    }
+                }
+            }
+            regs::STATUS => {
+                self.status &= !value;
+            }
+            regs::SECONDS..=regs::YEAR if offset.is_multiple_of(2) && offset != regs::WEEKDAY => {
+                let mut time = self.latched.unwrap_or_else(|| self.date_time());
+                let value = from_bcd(value);
+                match offset {
+                    regs::SECONDS => time.seconds = value.min(59),
+                    regs::MINUTES => time.minutes = value.min(59),
+                    regs::HOURS => time.hours = value.min(23),
+                    regs::DATE => time.day = value.clamp(1, 31),
+                    regs::MONTH => time.month = value.clamp(1, 12),
+                    _ => time.year = 2000 + u16::from(value.min(99)),
+                }
+                if self.latched.is_some() {
+                    self.latched = Some(time);
+                    self.latched_dirty = true;
+                } else {
+                    self.set_date_time(&time);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances the RTC by the given number of emulated CPU cycles
+    ///
+    /// The host clock is polled about once per emulated millisecond; each
+    /// seconds rollover sets IF when the interrupt is enabled.
+    pub fn tick(&mut self, cycles: u64) {
+        self.poll_cycles += cycles;
+        if self.poll_cycles < self.poll_interval {
+            return;
+        }
+        self.poll_cycles = 0;
+
+        let now = self.time();
+        if now != self.last_time {
+            self.last_time = now;
+            if self.control & control::IE != 0 {
+                self.status |= status::IF;
+            }
+        }
+    }
+
+    /// Returns true if the RTC is asserting its interrupt line
+    #[must_use]
+    pub const fn interrupt_pending(&self) -> bool {
+        self.control & control::IE != 0 && self.status & status::IF != 0
+    }
+}
+
+/// Converts a binary value (0-99) to BCD
+const fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Converts a BCD value to binary (invalid digits are clamped to 9)
+const fn from_bcd(value: u8) -> u8 {
+    let tens = value >> 4;
+    let ones = value & 0x0F;
+    let tens = if tens > 9 { 9 } else { tens };
+    let ones = if ones > 9 { 9 } else { ones };
+    tens * 10 + ones
+}
+
+impl Clocked for Rtc {
+    fn tick(&mut self, cycles: u64) {
+        Self::tick(self, cycles);
+    }
+
+    /// Only the polls that can raise the seconds interrupt count as events
+    fn next_event(&self) -> Option<u64> {
+        (self.control & control::IE != 0)
+            .then(|| self.poll_interval.saturating_sub(self.poll_cycles))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-02-29 23:59:58 UTC (a Thursday)
+    const LEAP_DAY: i64 = 1_709_251_198;
+
+    fn manual_rtc(time: i64) -> (Rtc, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new(time));
+        (Rtc::with_clock(clock.clone()), clock)
+    }
+
+    #[test]
+    fn test_rtc_date_time_round_trip() {
+        let time = DateTime::from_unix(LEAP_DAY);
+        assert_eq!(
+            time,
+            DateTime {
+                year: 2024,
+                month: 2,
+                day: 29,
+                hours: 23,
+                minutes: 59,
+                seconds: 58,
+            }
+        );
+        assert_eq!(time.to_unix(), LEAP_DAY);
+        assert_eq!(time.weekday(), 5);
+    }
+
+    #[test]
+    fn test_rtc_reads_host_time_in_bcd() {
+        let (mut rtc, clock) = manual_rtc(LEAP_DAY);
+        assert_eq!(rtc.read(regs::SECONDS), 0x58);
+        assert_eq!(rtc.read(regs::HOURS), 0x23);
+        assert_eq!(rtc.read(regs::DATE), 0x29);
+        assert_eq!(rtc.read(regs::MONTH), 0x02);
+        assert_eq!(rtc.read(regs::YEAR), 0x24);
+
+        clock.advance(3);
+        assert_eq!(rtc.read(regs::SECONDS), 0x01);
+        assert_eq!(rtc.read(regs::DATE), 0x01);
+        assert_eq!(rtc.read(regs::MONTH), 0x03);
+    }
+
+    #[test]
+    fn test_rtc_hold_prevents_tearing() {
+        let (mut rtc, clock) = manual_rtc(LEAP_DAY + 1);
+        rtc.write(regs::CONTROL, control::HOLD);
+        assert_eq!(rtc.read(regs::SECONDS), 0x59);
+        clock.advance(1);
+        assert_eq!(rtc.read(regs::MINUTES), 0x59);
+        assert_eq!(rtc.read(regs::HOURS), 0x23);
+        assert_eq!(rtc.read(regs::DATE), 0x29);
+
+        rtc.write(regs::CONTROL, 0);
+        assert_eq!(rtc.read(regs::MINUTES), 0x00);
+        assert_eq!(rtc.read(regs::DATE), 0x01);
+    }
+
+    #[test]
+    fn test_rtc_guest_write_sets_offset() {
+        let (mut rtc, clock) = manual_rtc(LEAP_DAY);
+        rtc.write(regs::CONTROL, control::HOLD);
+        rtc.write(regs::YEAR, 0x31);
+        rtc.write(regs::MONTH, 0x12);
+        rtc.write(regs::DATE, 0x25);
+        rtc.write(regs::HOURS, 0x08);
+        rtc.write(regs::MINUTES, 0x30);
+        rtc.write(regs::SECONDS, 0x00);
+        // Nothing changes until HOLD is released
+        clock.advance(10);
+        rtc.write(regs::CONTROL, 0);
+
+        let expected = DateTime {
+            year: 2031,
+            month: 12,
+            day: 25,
+            hours: 8,
+            minutes: 30,
+            seconds: 0,
+        };
+        assert_eq!(rtc.date_time(), expected);
+        assert_eq!(rtc.offset(), expected.to_unix() - LEAP_DAY - 10);
+
+        clock.advance(65);
+        assert_eq!(rtc.read(regs::MINUTES), 0x31);
+        assert_eq!(rtc.read(regs::SECONDS), 0x05);
+    }
+
+    #[test]
+    fn test_rtc_one_hz_interrupt() {
+        let (mut rtc, clock) = manual_rtc(LEAP_DAY);
+        rtc.tick(POLL_CYCLES);
+        clock.advance(1);
+        rtc.tick(POLL_CYCLES);
+        assert!(!rtc.interrupt_pending());
+
+        rtc.write(regs::CONTROL, control::IE);
+        clock.advance(1);
+        rtc.tick(POLL_CYCLES / 2);
+        assert!(!rtc.interrupt_pending());
+        rtc.tick(POLL_CYCLES / 2);
+        assert!(rtc.interrupt_pending());
+        assert_eq!(rtc.read(regs::STATUS), status::IF);
+
+        rtc.write(regs::STATUS, status::IF);
+        assert!(!rtc.interrupt_pending());
+    }
+
+    #[test]
+    fn test_rtc_clone_keeps_offset() {
+        let (mut rtc, _clock) = manual_rtc(LEAP_DAY);
+        rtc.write(regs::YEAR, 0x30);
+        let snapshot = rtc.clone();
+        rtc.set_offset(0);
+        assert_eq!(snapshot.date_time().year, 2030);
+        assert_eq!(rtc.date_time().year, 2024);
+    }
+}
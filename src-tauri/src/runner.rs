@@ -0,0 +1,358 @@
+//! Background Execution
+//!
+//! A `BackgroundRun` runs the machine on a worker thread in slices of
+//! `SLICE_CYCLES`, taking the `Sbc` lock for one slice at a time so other
+//! callers can inspect or change the machine between slices.
+//!
+//! The worker pauses itself when a slice stops for any reason other than
+//! its cycle budget (a breakpoint, a watchpoint, a halt or an exit) and
+//! reports the stop. `pause` and `stop` wait until the worker is between
+//! slices, so the machine is settled when they return; a paused worker
+//! resumes with `resume`, while a stopped one ends its thread.
+
+use crate::debugger::{RunResult, StopReason};
+use crate::sbc::Sbc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+/// Cycles executed per slice (under a millisecond at the default clock)
+pub const SLICE_CYCLES: u64 = 10_000;
+
+/// What the host wants the worker to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Request {
+    Run,
+    Pause,
+    Stop,
+}
+
+/// State shared between the host and the worker
+struct Control {
+    /// Latest host request
+    request: Request,
+    /// True while the worker is executing slices
+    active: bool,
+}
+
+/// Totals for one stretch of background execution, reported when it stops
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct RunStopped {
+    /// Instructions executed since the run started or resumed
+    pub instructions: u64,
+    /// Cycles executed since the run started or resumed
+    pub cycles: u64,
+    /// Why execution stopped (`Paused` when the host asked)
+    pub stop: StopReason,
+    /// Program counter after the run
+    pub pc: u32,
+}
+
+/// Runs `sbc` for up to `max_cycles` in slices of `SLICE_CYCLES`, taking
+/// the lock for one slice at a time; `on_slice` runs after each slice with
+/// the machine still locked
+///
+/// The result totals the slices, with the last slice's stop reason and PC.
+pub fn run_sliced(
+    sbc: &Mutex<Sbc>,
+    max_cycles: u64,
+    mut on_slice: impl FnMut(&mut Sbc),
+) -> RunResult {
+    let mut total = RunResult {
+        instructions: 0,
+        cycles: 0,
+        stop: StopReason::BudgetExhausted,
+        pc: 0,
+    };
+    loop {
+        let budget = SLICE_CYCLES.min(max_cycles - total.cycles.min(max_cycles));
+        let result = {
+            let mut sbc = sbc.lock().unwrap();
+            let result = if total.instructions == 0 {
+                sbc.run(budget)
+            } else {
+                sbc.run_continued(budget)
+            };
+            on_slice(&mut sbc);
+            result
+        };
+        total.instructions += result.instructions;
+        total.cycles += result.cycles;
+        total.stop = result.stop;
+        total.pc = result.pc;
+        if result.stop != StopReason::BudgetExhausted || total.cycles >= max_cycles {
+            return total;
+        }
+        std::thread::yield_now();
+    }
+}
+
+/// A worker thread running the machine
+pub struct BackgroundRun {
+    control: Arc<(Mutex<Control>, Condvar)>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl BackgroundRun {
+    /// Starts running `sbc` on a worker thread
+    ///
+    /// `on_slice` runs after every slice with the machine still locked;
+    /// `on_stop` runs (unlocked) each time execution stops.
+    pub fn start(
+        sbc: Arc<Mutex<Sbc>>,
+        mut on_slice: impl FnMut(&mut Sbc) + Send + 'static,
+        mut on_stop: impl FnMut(RunStopped) + Send + 'static,
+    ) -> Self {
+        let control = Arc::new((
+            Mutex::new(Control {
+                request: Request::Run,
+                active: true,
+            }),
+            Condvar::new(),
+        ));
+        let shared = Arc::clone(&control);
+        let worker = std::thread::spawn(move || {
+            let (lock, signal) = &*shared;
+            let (mut instructions, mut cycles) = (0, 0);
+            loop {
+                let result = {
+                    let mut sbc = sbc.lock().unwrap();
+                    // Only the first slice may resume from a breakpoint
+                    let result = if instructions == 0 {
+                        sbc.run(SLICE_CYCLES)
+                    } else {
+                        sbc.run_continued(SLICE_CYCLES)
+                    };
+                    on_slice(&mut sbc);
+                    result
+                };
+                instructions += result.instructions;
+                cycles += result.cycles;
+
+                let mut control = lock.lock().unwrap();
+                let stop = match (result.stop, control.request) {
+                    (StopReason::BudgetExhausted, Request::Run) => {
+                        drop(control);
+                        // Let waiting callers at the machine between slices
+                        std::thread::yield_now();
+                        continue;
+                    }
+                    (StopReason::BudgetExhausted, _) => StopReason::Paused,
+                    (stop, _) => stop,
+                };
+                if control.request == Request::Run {
+                    control.request = Request::Pause;
+                }
+                control.active = false;
+                signal.notify_all();
+                drop(control);
+
+                on_stop(RunStopped {
+                    instructions,
+                    cycles,
+                    stop,
+                    pc: result.pc,
+                });
+                (instructions, cycles) = (0, 0);
+
+                let mut control = lock.lock().unwrap();
+                loop {
+                    match control.request {
+                        Request::Run => break,
+                        Request::Pause => {
+                            // A pause can land between `resume` and waking up
+                            control.active = false;
+                            signal.notify_all();
+                            control = signal.wait(control).unwrap();
+                        }
+                        Request::Stop => {
+                            control.active = false;
+                            signal.notify_all();
+                            return;
+                        }
+                    }
+                }
+                control.active = true;
+            }
+        });
+        Self {
+            control,
+            worker: Some(worker),
+        }
+    }
+
+    /// Returns true while the worker is executing slices
+    pub fn is_running(&self) -> bool {
+        self.control.0.lock().unwrap().active
+    }
+
+    /// Resumes a paused worker (no effect while running)
+    pub fn resume(&self) {
+        let (lock, signal) = &*self.control;
+        let mut control = lock.lock().unwrap();
+        if control.request == Request::Pause {
+            control.request = Request::Run;
+            control.active = true;
+            signal.notify_all();
+        }
+    }
+
+    /// Pauses the worker, waiting for the slice in progress to finish
+    pub fn pause(&self) {
+        self.request(Request::Pause);
+    }
+
+    /// Ends the worker thread, waiting for it to exit
+    pub fn stop(mut self) {
+        self.shut_down();
+    }
+
+    /// Sends a pause or stop request and waits for the worker to go idle
+    fn request(&self, request: Request) {
+        let (lock, signal) = &*self.control;
+        let mut control = lock.lock().unwrap();
+        control.request = request;
+        signal.notify_all();
+        while control.active {
+            control = signal.wait(control).unwrap();
+        }
+    }
+
+    /// Stops and joins the worker
+    fn shut_down(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.request(Request::Stop);
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for BackgroundRun {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::Expression;
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    /// Counts D0 up forever, with a breakpoint once it reaches `stop_at`
+    fn counting_machine(stop_at: u32) -> (Arc<Mutex<Sbc>>, u32) {
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm
+            .assemble_source(
+                "
+        moveq   #0,d0
+loop:   addq.l  #1,d0
+        bra     loop
+",
+                Path::new("<test>"),
+            )
+            .unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        let address = sbc.pc() + 2;
+        let condition = Expression::parse(&format!("d0 = {stop_at}")).unwrap();
+        let id = sbc
+            .debugger_mut()
+            .add_conditional_breakpoint(address, Some(condition), 0);
+        (Arc::new(Mutex::new(sbc)), id)
+    }
+
+    #[test]
+    fn test_background_run_stops_at_breakpoint() {
+        let (sbc, id) = counting_machine(200_000);
+        let (tx, rx) = mpsc::channel();
+        let run = BackgroundRun::start(
+            Arc::clone(&sbc),
+            |_| {},
+            move |stopped| {
+                tx.send(stopped).unwrap();
+            },
+        );
+
+        // The machine can be read while it runs
+        let mut seen = Vec::new();
+        while seen.len() < 3 && run.is_running() {
+            seen.push(sbc.lock().unwrap().registers().d[0]);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(seen.windows(2).all(|w| w[0] <= w[1]));
+
+        let stopped = rx.recv_timeout(Duration::from_secs(30)).unwrap();
+        let address = sbc.lock().unwrap().pc();
+        assert_eq!(stopped.stop, StopReason::Breakpoint { id, address });
+        assert_eq!(stopped.pc, address);
+        assert!(!run.is_running());
+        assert_eq!(sbc.lock().unwrap().registers().d[0], 200_000);
+
+        // Resuming runs on; pausing waits for the worker and reports it
+        sbc.lock().unwrap().debugger_mut().remove(id);
+        run.resume();
+        std::thread::sleep(Duration::from_millis(5));
+        run.pause();
+        assert!(!run.is_running());
+        let stopped = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(stopped.stop, StopReason::Paused);
+        let d0 = sbc.lock().unwrap().registers().d[0];
+        assert!(d0 > 200_000);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(sbc.lock().unwrap().registers().d[0], d0);
+        run.stop();
+    }
+
+    #[test]
+    fn test_run_sliced_leaves_machine_readable() {
+        let (sbc, _) = counting_machine(u32::MAX);
+        let runner = {
+            let sbc = Arc::clone(&sbc);
+            std::thread::spawn(move || run_sliced(&sbc, 20_000_000, |_| {}))
+        };
+        while sbc.lock().unwrap().registers().d[0] == 0 {
+            std::thread::yield_now();
+        }
+
+        // Reads are served between slices while the run goes on
+        for _ in 0..10 {
+            let start = std::time::Instant::now();
+            let d0 = sbc.lock().unwrap().registers().d[0];
+            assert!(start.elapsed() < Duration::from_millis(500));
+            assert!(d0 > 0);
+        }
+        assert!(!runner.is_finished());
+
+        let result = runner.join().unwrap();
+        assert_eq!(result.stop, StopReason::BudgetExhausted);
+        assert!(result.cycles >= 20_000_000);
+        assert_eq!(
+            u64::from(sbc.lock().unwrap().registers().d[0]),
+            result.instructions / 2
+        );
+    }
+
+    #[test]
+    fn test_run_sliced_stops_at_breakpoint() {
+        let (sbc, id) = counting_machine(5_000);
+        let result = run_sliced(&sbc, 10_000_000, |_| {});
+        let address = sbc.lock().unwrap().pc();
+        assert_eq!(result.stop, StopReason::Breakpoint { id, address });
+        assert_eq!(sbc.lock().unwrap().registers().d[0], 5_000);
+    }
+
+    #[test]
+    fn test_background_run_stop_while_running() {
+        let (sbc, _) = counting_machine(u32::MAX);
+        let run = BackgroundRun::start(Arc::clone(&sbc), |_| {}, |_| {});
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(run.is_running());
+        run.stop();
+        let d0 = sbc.lock().unwrap().registers().d[0];
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(sbc.lock().unwrap().registers().d[0], d0);
+    }
+}
@@ -11,6 +11,9 @@
 //! - **UART**: 16550 at $A00000 (serial terminal at 57600 baud)
 //! - **Storage**: `CompactFlash` (True IDE mode) at $900000
 //! - **RTC**: DS3234 via SPI on UART modem control lines
+//! - **Expansion**: RTC, GPIO port, interrupt controller, timer, watchdog, LED
+//!   bar, buzzer, SPI and I2C controllers and DIP switch at $800000
+//!   (emulator extension)
 //!
 //! ## Memory Map
 //!
@@ -19,7 +22,7 @@
 //! $100000-$1FFFFF  Forbidden (ROM + CF overlap)
 //! $200000-$2FFFFF  ROM mirror (64KB repeated 16×)
 //! $300000-$7FFFFF  Forbidden (overlaps from minimal decode)
-//! $800000-$8FFFFF  Open bus (expansion)
+//! $800000-$8FFFFF  Expansion bus (see below)
 //! $900000-$9FFFFF  CompactFlash card
 //! $A00000-$AFFFFF  UART (16550)
 //! $B00000-$BFFFFF  Forbidden (UART + CF overlap)
@@ -38,6 +41,40 @@
 //! ```
 //! Overlaps select multiple devices and are treated as open bus in the emulator.
 //!
+//! ## Expansion Bus
+//!
+//! No chip select decodes $800000-$8FFFFF on the board, so the emulator uses
+//! it for extra peripherals. Each device gets a 32-byte slot with 8-bit
+//! registers on even addresses, like the UART. Unused slots read as open bus.
+//!
+//! ```text
+//! $800000-$80001F  Real-time clock (see `rtc`)
+//! $800020-$80003F  GPIO port (see `gpio`)
+//! $800040-$80005F  Interrupt controller (see `intc`)
+//! $800060-$80007F  Interval timer (see `timer`)
+//! $800080-$80009F  Watchdog timer (see `watchdog`)
+//! $8000A0-$8000BF  LED bar (see `led`; LED 0 aliases the UART RTS LED)
+//! $8000C0-$8000DF  Buzzer (see `buzzer`)
+//! $8000E0-$8000FF  SPI controller (see `spi`)
+//! $800100-$80011F  I2C controller (see `i2c`)
+//! $800120-$80013F  DIP switch (see `dipswitch`; read-only byte at $800120)
+//! ```
+//!
+//! ## Interrupts
+//!
+//! Every interrupt source is routed through the interrupt controller, which
+//! masks all sources at reset. Guest code enables sources and assigns their
+//! priorities there; see the `intc` module for the default priorities. NMIs
+//! are the exception: the watchdog and the break button (`raise_nmi`) raise
+//! level 7 directly.
+//!
+//! ## Configuration
+//!
+//! `Sbc::new` builds the stock board; `Sbc::new_with_config` takes an
+//! `SbcConfig` (see `config`) for a different clock, RAM size, ROM, CF card,
+//! peripheral set or bus behavior. Runtime setters such as `set_uart_bell`
+//! update the active configuration, which `config` returns.
+//!
 //! ## Boot Process
 //!
 //! 1. CPU reads initial SSP from $000000 and initial PC from $000004
@@ -56,12 +93,37 @@
 #![allow(dead_code)]
 
 use crate::bus::ADDR_MASK;
-use crate::cfcard::CfCard;
+use crate::buzzer::{Buzzer, ToneEvent};
+use crate::cfcard::{CacheStats, CfCard, CfImage, CfTiming};
+use crate::config::{ConfigError, LineEnding, SbcConfig};
 use crate::cpu::Cpu;
-use crate::memory::{OperandSize, WriteHookResult};
+use crate::debugger::{Debugger, MemoryAccess, RunResult, StepBatch, StopReason, WatchValue};
+use crate::diagnostics::{
+    crc32, CpuInfo, Diagnostics, FaultInfo, MapRegion, PeripheralInfo, RomInfo, CPU_MODEL,
+};
+use crate::dipswitch::DipSwitch;
+use crate::disasm;
+use crate::expression::{Evaluation, Expression};
+use crate::gpio::{Gpio, GpioState};
+use crate::i2c::{I2cController, I2cDevice};
+use crate::intc::{self, InterruptController};
+use crate::led::LedBar;
+use crate::memory::{Memory, MemoryError, OperandSize, WriteHookResult};
+use crate::panel::Peripheral;
+use crate::profile::{ProfileReport, Profiler};
+use crate::rtc::{ClockSource, Rtc};
+use crate::scheduler::{Clocked, EventSource, Scheduler};
+use crate::snapshot::{MachineState, Snapshot, SnapshotError, SnapshotInfo, SNAPSHOT_VERSION};
+use crate::spi::{SpiController, SpiDevice};
+use crate::timer::Timer;
+use crate::trace::{TraceBuffer, TraceEntry};
 use crate::uart::Uart16550;
+use crate::watchdog::{Watchdog, WatchdogAction};
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
 use std::io;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 /// SBC clock frequency in Hz (12 MHz)
@@ -70,6 +132,39 @@
 /// Default baud rate (57600)
 pub const DEFAULT_BAUD: u32 = 57600;
 
+/// Terminal interrupt character
+const CTRL_C: u8 = 0x03;
+
+/// Default `CompactFlash` INTRQ priority
+pub const DEFAULT_CF_IRQ_LEVEL: u8 = intc::DEFAULT_PRIORITIES[intc::sources::CF as usize];
+
+/// Expansion bus base address
+pub const EXPANSION_BASE: u32 = 0x0080_0000;
+
+/// Expansion bus slot numbers (32 bytes per slot)
+mod slots {
+    /// Real-time clock
+    pub const RTC: u32 = 0;
+    /// GPIO port
+    pub const GPIO: u32 = 1;
+    /// Interrupt controller
+    pub const INTC: u32 = 2;
+    /// Interval timer
+    pub const TIMER: u32 = 3;
+    /// Watchdog timer
+    pub const WATCHDOG: u32 = 4;
+    /// LED bar
+    pub const LED: u32 = 5;
+    /// Buzzer
+    pub const BUZZER: u32 = 6;
+    /// SPI controller
+    pub const SPI: u32 = 7;
+    /// I2C controller
+    pub const I2C: u32 = 8;
+    /// DIP switch
+    pub const DIPSWITCH: u32 = 9;
+}
+
 /// RAM base address
 pub const RAM_BASE: u32 = 0x00C0_0000;
 
@@ -88,6 +183,264 @@
 /// RAM size (1MB)
 pub const RAM_SIZE: usize = 1024 * 1024;
 
+/// Initial SSP and PC loaded by the reset sequence
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ResetVectors {
+    /// Initial supervisor stack pointer
+    pub ssp: u32,
+    /// Initial program counter
+    pub pc: u32,
+}
+
+/// Errors that can occur while loading a boot ROM image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    /// Reading the image file failed
+    Io { path: String, message: String },
+    /// The image doesn't fit in the ROM region
+    TooLarge { size: usize, max: usize },
+    /// The image is too short to hold the reset vectors (SSP and PC)
+    TooSmall { size: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, message } => write!(f, "Failed to read ROM {path}: {message}"),
+            Self::TooLarge { size, max } => {
+                write!(f, "ROM image too large: {size} bytes (max {max})")
+            }
+            Self::TooSmall { size } => write!(
+                f,
+                "ROM image too small: {size} bytes (need 8 for the reset vectors)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+/// Checks that a ROM image fits the ROM region and holds the reset vectors
+pub const fn validate_rom(data: &[u8]) -> Result<(), RomError> {
+    if data.len() > ROM_SIZE {
+        return Err(RomError::TooLarge {
+            size: data.len(),
+            max: ROM_SIZE,
+        });
+    }
+    if data.len() < 8 {
+        return Err(RomError::TooSmall { size: data.len() });
+    }
+    Ok(())
+}
+
+/// Reads and validates a ROM image file
+fn read_rom(path: &Path) -> Result<Vec<u8>, RomError> {
+    let data = std::fs::read(path).map_err(|e| RomError::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })?;
+    validate_rom(&data)?;
+    Ok(data)
+}
+
+/// Errors that can occur while loading an application into RAM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppLoadError {
+    /// The load address isn't in RAM
+    NotRam { addr: u32 },
+    /// The load address is in the system area (the first 256 bytes of RAM)
+    SystemArea { addr: u32 },
+    /// The binary runs past the end of RAM
+    TooLarge {
+        addr: u32,
+        size: usize,
+        available: usize,
+    },
+}
+
+impl fmt::Display for AppLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotRam { addr } => write!(f, "Load address ${addr:06X} is not in RAM"),
+            Self::SystemArea { addr } => {
+                write!(f, "Load address ${addr:06X} is in the system area")
+            }
+            Self::TooLarge {
+                addr,
+                size,
+                available,
+            } => write!(
+                f,
+                "Program too large: {size} bytes at ${addr:06X} (only {available} bytes of RAM left)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AppLoadError {}
+
+/// Errors that can occur while loading a raw binary file into memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinaryLoadError {
+    /// The file doesn't exist
+    NotFound { path: String },
+    /// Reading the file failed
+    Io {
+        path: String,
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// The load address is neither populated RAM nor (with the override) ROM
+    NotWritable { addr: u32 },
+    /// The file runs past the end of its region
+    TooLarge {
+        addr: u32,
+        size: usize,
+        available: usize,
+    },
+}
+
+impl fmt::Display for BinaryLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound { path } => write!(f, "Binary file not found: {path}"),
+            Self::Io { path, message, .. } => write!(f, "Failed to read {path}: {message}"),
+            Self::NotWritable { addr } => {
+                write!(f, "Load address ${addr:06X} is not writable memory")
+            }
+            Self::TooLarge {
+                addr,
+                size,
+                available,
+            } => write!(
+                f,
+                "Binary too large: {size} bytes at ${addr:06X} (only {available} bytes left in the region)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BinaryLoadError {}
+
+/// Outcome of `Sbc::write_memory`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryWriteResult {
+    /// Bytes written before the first failure
+    pub written: usize,
+    /// First address that couldn't be written (`None` if all were)
+    pub failed_at: Option<u32>,
+}
+
+/// Mnemonic of the entry `Sbc::disassemble` ends with when it runs off
+/// mapped memory
+pub const DISASSEMBLY_END: &str = "(unmapped)";
+
+/// A disassembled instruction (see `Sbc::disassemble`)
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisassemblyLine {
+    /// Instruction address
+    pub addr: u32,
+    /// Instruction bytes in hex
+    pub bytes_hex: String,
+    /// Mnemonic with size suffix
+    pub mnemonic: String,
+    /// Operands
+    pub operands: String,
+    /// The instruction is at the program counter
+    pub is_current_pc: bool,
+    /// Label at the address, from the loaded program's symbols
+    pub symbol: Option<String>,
+}
+
+/// How much of the machine a reset reinitializes (see `Sbc::reset_with`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetMode {
+    /// Power cycle: RAM is cleared, the CF card ejected and the debugger's
+    /// tables emptied
+    #[default]
+    Cold,
+    /// Hardware reset of the CPU and peripherals; RAM is kept
+    Warm,
+    /// The CPU only reloads SSP and PC from the reset vectors
+    CpuOnly,
+}
+
+/// A longword on the stack, with what it looks like (see `Sbc::stack`)
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackEntry {
+    /// Address of the longword
+    pub address: u32,
+    /// Value of the longword
+    pub value: u32,
+    /// The value points just after a JSR, BSR or TRAP in ROM or RAM
+    pub return_address: bool,
+    /// Closest label at or below the value (`label` or `label+$offset`),
+    /// if the value points into ROM or RAM
+    pub symbol: Option<String>,
+    /// The longword lies in the current LINK frame: between the stack
+    /// pointer and the return address above the saved A6
+    pub in_frame: bool,
+}
+
+/// A routine on the call stack (see `Sbc::call_stack`)
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    /// Where execution continues in the routine: the PC for frame 0, the
+    /// return address for its callers
+    pub return_address: u32,
+    /// The A6 the routine's LINK set up, if it has one
+    pub frame_pointer: Option<u32>,
+    /// Closest label at or below the return address
+    pub symbol: Option<String>,
+    /// How far past `symbol` the return address is
+    pub offset: Option<u32>,
+    /// The frame was found by following LINK frames; frames found by
+    /// scanning the stack for return addresses are guesses. For frame 0,
+    /// A6 points at a LINK frame.
+    pub is_link_frame: bool,
+}
+
+/// What one instruction step did (see `Sbc::step_detailed`)
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepResult {
+    /// Program counter before the step
+    pub pc_before: u32,
+    /// Program counter after the step
+    pub pc_after: u32,
+    /// First word of the executed instruction
+    pub opcode: u16,
+    /// Cycles the step took
+    pub cycles: u64,
+    /// Vector of the exception or interrupt the step took, if any
+    pub exception_vector: Option<u8>,
+    /// The CPU is halted after the step
+    pub halted: bool,
+    /// UART output the instruction produced
+    pub uart_output: Vec<u8>,
+    /// Values of the watch expressions after the step
+    pub watches: Vec<WatchValue>,
+}
+
+/// What became of an injected interrupt (see `Sbc::inject_interrupt`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InterruptOutcome {
+    /// The CPU took the interrupt right away
+    Accepted,
+    /// The CPU's mask is at or above the level; the request waits until the
+    /// mask drops below it
+    Deferred,
+    /// An earlier injected request is still waiting, so this one was lost
+    Dropped,
+}
+
 /// Embedded Flux32 system ROM
 /// This ROM provides the shell, syscalls, and peripheral drivers.
 static EMBEDDED_ROM: &[u8] = include_bytes!("../assets/rom.bin");
@@ -102,6 +455,10 @@
 #[allow(dead_code, reason = "Reserved for future ROM compatibility")]
 const SEPARATORS_VEC: u32 = 0x00E0_000C;
 
+/// Address of the TRAP handler stubs `run_app` installs; the first one is
+/// Exit (TRAP #0)
+const TRAP_STUB_BASE: u32 = 0x00E0_0080;
+
 // ROM addresses for I/O routines (determined from rom.lst)
 #[allow(dead_code, reason = "Kept for ROM debugging and future CLI tools")]
 const UART_OUTCHAR_ADDR: u32 = 0x0000_1186;
@@ -113,10 +470,35 @@
 #[allow(dead_code, reason = "Kept for ROM debugging and future CLI tools")]
 const SEPARATORS_VALUE: u32 = 0x2d3a_2c00;
 
-// Global peripheral references for MMIO hooks
-// These are Arc clones of the peripherals owned by the Sbc struct.
-static SBC_UART_PTR: Mutex<Option<Arc<Mutex<Uart16550>>>> = Mutex::new(None);
-static SBC_CFCARD_PTR: Mutex<Option<Arc<Mutex<CfCard>>>> = Mutex::new(None);
+/// The devices and bus state one machine's MMIO hooks reach
+///
+/// Each `Sbc` owns a `Bus` its hooks capture, so machines built side by
+/// side never see each other's devices. Disabled peripherals are `None`,
+/// which leaves their expansion slot open.
+struct Bus {
+    uart: Arc<Mutex<Uart16550>>,
+    cfcard: Arc<Mutex<CfCard>>,
+    rtc: Option<Arc<Mutex<Rtc>>>,
+    gpio: Option<Arc<Mutex<Gpio>>>,
+    intc: Arc<Mutex<InterruptController>>,
+    timer: Option<Arc<Mutex<Timer>>>,
+    watchdog: Arc<Mutex<Watchdog>>,
+    leds: Arc<Mutex<LedBar>>,
+    buzzer: Arc<Mutex<Buzzer>>,
+    spi: Arc<Mutex<SpiController>>,
+    i2c: Arc<Mutex<I2cController>>,
+    dipswitch: Arc<Mutex<DipSwitch>>,
+    /// Populated bytes per RAM window, from the active `SbcConfig`
+    ram_size: u32,
+    /// Value an unmapped read returns, from the active `SbcConfig`
+    unmapped: u8,
+    /// Last address that selected more than one device (see `Sbc::diagnostics`)
+    fault: Mutex<Option<u32>>,
+    /// True while `Sbc::run` has watchpoints to check
+    watch_active: AtomicBool,
+    /// Data accesses made while `watch_active` is set
+    access_log: Mutex<Vec<MemoryAccess>>,
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum SbcAddressRegion {
@@ -124,6 +506,7 @@ enum SbcAddressRegion {
     Ram(u32),
     Uart(u32),
     CfCard(u32),
+    Expansion(u32),
     OpenBus,
     Conflict,
 }
@@ -142,7 +525,7 @@ fn decode_address(address: u32) -> SbcAddressRegion {
 
     let selected = u8::from(rom_sel) + u8::from(ram_sel) + u8::from(uart_sel) + u8::from(card_sel);
     if selected == 0 {
-        return SbcAddressRegion::OpenBus;
+        return SbcAddressRegion::Expansion(addr & 0xFFFFF);
     }
     if selected > 1 {
         return SbcAddressRegion::Conflict;
@@ -157,40 +540,137 @@ fn decode_address(address: u32) -> SbcAddressRegion {
     if uart_sel {
         return SbcAddressRegion::Uart(addr & 0xF);
     }
-    SbcAddressRegion::CfCard(addr & 0xF)
+    // A4 selects the CS1 register block (alternate status / device control)
+    SbcAddressRegion::CfCard(addr & 0x1F)
 }
 
-/// MMIO read hook for SBC peripherals
-fn sbc_read_hook(address: u32) -> Option<u8> {
-    match decode_address(address) {
-        SbcAddressRegion::Uart(offset) => {
-            let uart_guard = SBC_UART_PTR.lock().unwrap();
-            if let Some(uart) = uart_guard.as_ref() {
-                Some(uart.lock().unwrap().read(offset))
-            } else {
-                Some(0xFF)
-            }
+/// Which debugger checks a run makes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DebugChecks {
+    /// Breakpoints and watchpoints are ignored
+    None,
+    /// Breakpoints count from the second instruction, so a run can resume
+    /// from one
+    SkipFirst,
+    /// Breakpoints count from the first instruction
+    All,
+}
+
+/// Reads a byte of ROM or populated RAM without going through the bus, or
+/// returns `None` for any other address
+fn peek_byte(rom: &[u8], memory: &Memory, bus: &Bus, address: u32) -> Option<u8> {
+    match decode_address(address & ADDR_MASK) {
+        SbcAddressRegion::Rom(offset) => rom.get(offset as usize).copied(),
+        SbcAddressRegion::Ram(offset) if !bus.ram_unpopulated(offset) => {
+            Some(memory.read_byte_unchecked(address))
         }
-        SbcAddressRegion::CfCard(offset) => {
-            let cf_guard = SBC_CFCARD_PTR.lock().unwrap();
-            if let Some(cf) = cf_guard.as_ref() {
-                Some(cf.lock().unwrap().read(offset))
-            } else {
-                Some(0xFF)
+        _ => None,
+    }
+}
+
+/// Runs `f` on a peripheral, or returns `default` if it is disabled
+fn with_peripheral<T, R>(
+    device: Option<&Arc<Mutex<T>>>,
+    default: R,
+    f: impl FnOnce(&mut T) -> R,
+) -> R {
+    match device {
+        Some(device) => f(&mut device.lock().unwrap()),
+        None => default,
+    }
+}
+
+impl Bus {
+    /// Returns true if a RAM window offset has no SRAM behind it
+    const fn ram_unpopulated(&self, offset: u32) -> bool {
+        offset >= self.ram_size
+    }
+
+    /// Records a data access for watchpoint checks
+    fn log_access(&self, address: u32, length: u32, write: bool) {
+        if self.watch_active.load(Ordering::Relaxed) {
+            self.access_log.lock().unwrap().push(MemoryAccess {
+                address: address & ADDR_MASK,
+                length,
+                write,
+            });
+        }
+    }
+
+    /// Records an access to an address that selects more than one device
+    fn latch_fault(&self, address: u32) {
+        *self.fault.lock().unwrap() = Some(address & ADDR_MASK);
+    }
+
+    /// Reads a byte from an expansion bus device
+    fn expansion_read(&self, offset: u32) -> u8 {
+        let reg = offset & 0x1F;
+        let open = self.unmapped;
+        match offset >> 5 {
+            slots::RTC => with_peripheral(self.rtc.as_ref(), open, |rtc| rtc.read(reg)),
+            slots::GPIO => with_peripheral(self.gpio.as_ref(), open, |gpio| gpio.read(reg)),
+            slots::INTC => with_peripheral(Some(&self.intc), open, |intc| intc.read(reg)),
+            slots::TIMER => with_peripheral(self.timer.as_ref(), open, |timer| timer.read(reg)),
+            slots::WATCHDOG => with_peripheral(Some(&self.watchdog), open, |wd| wd.read(reg)),
+            slots::LED => with_peripheral(Some(&self.leds), open, |leds| leds.read(reg)),
+            slots::BUZZER => with_peripheral(Some(&self.buzzer), open, |buzzer| buzzer.read(reg)),
+            slots::SPI => with_peripheral(Some(&self.spi), open, |spi| spi.read(reg)),
+            slots::I2C => with_peripheral(Some(&self.i2c), open, |i2c| i2c.read(reg)),
+            slots::DIPSWITCH => with_peripheral(Some(&self.dipswitch), open, |dip| dip.read(reg)),
+            _ => open,
+        }
+    }
+
+    /// Writes a byte to an expansion bus device
+    fn expansion_write(&self, offset: u32, value: u8) {
+        let reg = offset & 0x1F;
+        match offset >> 5 {
+            slots::RTC => with_peripheral(self.rtc.as_ref(), (), |rtc| rtc.write(reg, value)),
+            slots::GPIO => with_peripheral(self.gpio.as_ref(), (), |gpio| gpio.write(reg, value)),
+            slots::INTC => with_peripheral(Some(&self.intc), (), |intc| intc.write(reg, value)),
+            slots::TIMER => with_peripheral(self.timer.as_ref(), (), |timer| {
+                timer.write(reg, value);
+            }),
+            slots::WATCHDOG => with_peripheral(Some(&self.watchdog), (), |wd| wd.write(reg, value)),
+            slots::LED => with_peripheral(Some(&self.leds), (), |leds| leds.write(reg, value)),
+            slots::BUZZER => with_peripheral(Some(&self.buzzer), (), |buzzer| {
+                buzzer.write(reg, value);
+            }),
+            slots::SPI => with_peripheral(Some(&self.spi), (), |spi| spi.write(reg, value)),
+            slots::I2C => with_peripheral(Some(&self.i2c), (), |i2c| i2c.write(reg, value)),
+            _ => {}
+        }
+    }
+
+    /// MMIO read hook for SBC peripherals
+    fn read(&self, address: u32) -> Option<u8> {
+        self.log_access(address, 1, false);
+        match decode_address(address) {
+            SbcAddressRegion::Uart(offset) => Some(self.uart.lock().unwrap().read(offset)),
+            SbcAddressRegion::CfCard(offset) => Some(self.cfcard.lock().unwrap().read(offset)),
+            SbcAddressRegion::Expansion(offset) => Some(self.expansion_read(offset)),
+            SbcAddressRegion::Conflict => {
+                self.latch_fault(address);
+                Some(self.unmapped)
             }
+            SbcAddressRegion::OpenBus => Some(self.unmapped),
+            SbcAddressRegion::Ram(offset) if self.ram_unpopulated(offset) => Some(self.unmapped),
+            SbcAddressRegion::Rom(_) | SbcAddressRegion::Ram(_) => None,
         }
-        SbcAddressRegion::OpenBus | SbcAddressRegion::Conflict => Some(0xFF),
-        SbcAddressRegion::Rom(_) | SbcAddressRegion::Ram(_) => None,
     }
-}
 
-/// MMIO write hook for SBC peripherals
-fn sbc_write_hook(address: u32, value: u32, size: OperandSize) -> WriteHookResult {
-    match decode_address(address) {
-        SbcAddressRegion::Uart(offset) => {
-            let uart_guard = SBC_UART_PTR.lock().unwrap();
-            if let Some(uart) = uart_guard.as_ref() {
-                let mut uart = uart.lock().unwrap();
+    /// MMIO write hook for SBC peripherals
+    fn write(&self, address: u32, value: u32, size: OperandSize) -> WriteHookResult {
+        let length = match size {
+            OperandSize::Byte => 1,
+            OperandSize::Word => 2,
+            OperandSize::Long => 4,
+        };
+        self.log_access(address, length, true);
+        match decode_address(address) {
+            SbcAddressRegion::Uart(offset) => {
+                let mut uart = self.uart.lock().unwrap();
+                let led_was_on = uart.led_state();
                 match size {
                     OperandSize::Byte => {
                         uart.write(offset, value as u8);
@@ -206,13 +686,14 @@ fn sbc_write_hook(address: u32, value: u32, size: OperandSize) -> WriteHookResul
                         uart.write(offset + 3, value as u8);
                     }
                 }
+                // RTS drives LED 0 of the LED bar
+                if uart.led_state() != led_was_on {
+                    self.leds.lock().unwrap().set_status_led(uart.led_state());
+                }
+                WriteHookResult::Handled
             }
-            WriteHookResult::Handled
-        }
-        SbcAddressRegion::CfCard(offset) => {
-            let cf_guard = SBC_CFCARD_PTR.lock().unwrap();
-            if let Some(cf) = cf_guard.as_ref() {
-                let mut cf = cf.lock().unwrap();
+            SbcAddressRegion::CfCard(offset) => {
+                let mut cf = self.cfcard.lock().unwrap();
                 match size {
                     OperandSize::Byte => {
                         cf.write(offset, value as u8);
@@ -228,13 +709,34 @@ fn sbc_write_hook(address: u32, value: u32, size: OperandSize) -> WriteHookResul
                         cf.write(offset + 3, value as u8);
                     }
                 }
+                WriteHookResult::Handled
             }
-            WriteHookResult::Handled
-        }
-        SbcAddressRegion::Rom(_) | SbcAddressRegion::OpenBus | SbcAddressRegion::Conflict => {
-            WriteHookResult::Handled
+            SbcAddressRegion::Expansion(offset) => {
+                match size {
+                    OperandSize::Byte => self.expansion_write(offset, value as u8),
+                    OperandSize::Word => {
+                        self.expansion_write(offset, (value >> 8) as u8);
+                        self.expansion_write(offset + 1, value as u8);
+                    }
+                    OperandSize::Long => {
+                        self.expansion_write(offset, (value >> 24) as u8);
+                        self.expansion_write(offset + 1, (value >> 16) as u8);
+                        self.expansion_write(offset + 2, (value >> 8) as u8);
+                        self.expansion_write(offset + 3, value as u8);
+                    }
+                }
+                WriteHookResult::Handled
+            }
+            SbcAddressRegion::Conflict => {
+                self.latch_fault(address);
+                WriteHookResult::Handled
+            }
+            SbcAddressRegion::Rom(_) | SbcAddressRegion::OpenBus => WriteHookResult::Handled,
+            SbcAddressRegion::Ram(offset) if self.ram_unpopulated(offset) => {
+                WriteHookResult::Handled
+            }
+            SbcAddressRegion::Ram(_) => WriteHookResult::Unhandled,
         }
-        SbcAddressRegion::Ram(_) => WriteHookResult::Unhandled,
     }
 }
 
@@ -245,6 +747,7 @@ fn sbc_write_hook(address: u32, value: u32, size: OperandSize) -> WriteHookResul
 /// - RAM at $C00000/$E00000
 /// - UART at $A00000
 /// - `CompactFlash` at $900000
+/// - Expansion bus peripherals at $800000
 pub struct Sbc {
     /// The CPU core (uses 16MB flat memory for simplicity)
     cpu: Cpu,
@@ -252,10 +755,54 @@ pub struct Sbc {
     uart: Arc<Mutex<Uart16550>>,
     /// `CompactFlash` card
     cfcard: Arc<Mutex<CfCard>>,
+    /// Real-time clock
+    rtc: Arc<Mutex<Rtc>>,
+    /// GPIO port
+    gpio: Arc<Mutex<Gpio>>,
+    /// Interrupt controller
+    intc: Arc<Mutex<InterruptController>>,
+    /// Interval timer
+    timer: Arc<Mutex<Timer>>,
+    /// Watchdog timer
+    watchdog: Arc<Mutex<Watchdog>>,
+    /// LED bar
+    leds: Arc<Mutex<LedBar>>,
+    /// Buzzer
+    buzzer: Arc<Mutex<Buzzer>>,
+    /// SPI controller
+    spi: Arc<Mutex<SpiController>>,
+    /// I2C controller
+    i2c: Arc<Mutex<I2cController>>,
+    /// DIP switch
+    dipswitch: Arc<Mutex<DipSwitch>>,
+    /// Devices and bus state the MMIO hooks reach
+    bus: Arc<Bus>,
     /// ROM data (for read interception)
     rom_data: Vec<u8>,
+    /// True while the embedded ROM is loaded
+    embedded_rom: bool,
+    /// Active configuration (runtime setters keep it current)
+    config: SbcConfig,
     /// UART output buffer (auto-drained from TX FIFO)
     uart_output: Vec<u8>,
+    /// Host input waiting for room in the UART RX FIFO
+    uart_input: VecDeque<u8>,
+    /// Breakpoints and watchpoints checked by `run`
+    debugger: Debugger,
+    /// Instructions executed since the last reset
+    instructions: u64,
+    /// Why the last run or batch of steps stopped
+    last_stop: Option<StopReason>,
+    /// Most recently executed instructions, while tracing
+    trace: TraceBuffer,
+    /// Cycles spent per instruction address, while profiling
+    profiler: Profiler,
+    /// True while the app-mode TRAP stubs are installed (see `run_app`)
+    app_stubs: bool,
+    /// Master cycle count and pending peripheral events
+    scheduler: Scheduler,
+    /// Peripherals ticked by the master clock, in tick order
+    clocked: Vec<(EventSource, Arc<Mutex<dyn Clocked>>)>,
 }
 
 impl Default for Sbc {
@@ -268,23 +815,113 @@ impl Sbc {
     /// Creates a new SBC instance with embedded ROM pre-loaded
     #[must_use]
     pub fn new() -> Self {
+        Self::with_config(SbcConfig::default())
+    }
+
+    /// Creates a new SBC instance from a configuration
+    ///
+    /// The configuration is validated first, then the ROM image and CF card
+    /// it names are loaded; errors name the field at fault. The machine is
+    /// reset, so it boots from the configured ROM.
+    pub fn new_with_config(config: SbcConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        let rom = match &config.rom_path {
+            Some(path) => Some(
+                read_rom(Path::new(path))
+                    .map_err(|e| ConfigError::new("romPath", e.to_string()))?,
+            ),
+            None => None,
+        };
+        let cf = config.cf.clone();
+
+        let mut sbc = Self::with_config(config);
+        if let Some(rom) = rom {
+            sbc.load_rom(&rom);
+        }
+        if let Some(image) = &cf.image {
+            sbc.load_cf_image(Path::new(image))
+                .map_err(|e| ConfigError::new("cf.image", format!("{image}: {e}")))?;
+        }
+        sbc.set_cf_overlay_enabled(cf.overlay)
+            .map_err(|e| ConfigError::new("cf.overlay", e.to_string()))?;
+        sbc.reset();
+        Ok(sbc)
+    }
+
+    /// Builds the machine from an already validated configuration, with the
+    /// embedded ROM and no CF card
+    fn with_config(config: SbcConfig) -> Self {
         let uart = Arc::new(Mutex::new(Uart16550::new()));
         let cfcard = Arc::new(Mutex::new(CfCard::new()));
+        let rtc = Arc::new(Mutex::new(Rtc::new()));
+        let gpio = Arc::new(Mutex::new(Gpio::new()));
+        let intc = Arc::new(Mutex::new(InterruptController::new()));
+        let timer = Arc::new(Mutex::new(Timer::new()));
+        let watchdog = Arc::new(Mutex::new(Watchdog::new()));
+        let leds = Arc::new(Mutex::new(LedBar::new()));
+        let buzzer = Arc::new(Mutex::new(Buzzer::new()));
+        let spi = Arc::new(Mutex::new(SpiController::new()));
+        let i2c = Arc::new(Mutex::new(I2cController::new()));
+        let dipswitch = Arc::new(Mutex::new(DipSwitch::new(config.dip_switch)));
 
         // Create CPU with full 16MB address space
         let mut cpu = Cpu::with_memory_size(16 * 1024 * 1024);
 
-        // Install write hook for MMIO
-        cpu.memory_mut().set_write_hook(sbc_write_hook);
-        // Install read hook for MMIO
-        cpu.memory_mut().set_read_hook(sbc_read_hook);
-
-        // Register peripherals in global Arc<Mutex<>> references
-        *SBC_UART_PTR.lock().unwrap() = Some(Arc::clone(&uart));
-        *SBC_CFCARD_PTR.lock().unwrap() = Some(Arc::clone(&cfcard));
+        // Route MMIO to this machine's devices; disabled peripherals leave
+        // their expansion slot open
+        let enabled = config.peripherals;
+        let bus = Arc::new(Bus {
+            uart: Arc::clone(&uart),
+            cfcard: Arc::clone(&cfcard),
+            rtc: enabled.rtc.then(|| Arc::clone(&rtc)),
+            gpio: enabled.gpio.then(|| Arc::clone(&gpio)),
+            intc: Arc::clone(&intc),
+            timer: enabled.timer.then(|| Arc::clone(&timer)),
+            watchdog: Arc::clone(&watchdog),
+            leds: Arc::clone(&leds),
+            buzzer: Arc::clone(&buzzer),
+            spi: Arc::clone(&spi),
+            i2c: Arc::clone(&i2c),
+            dipswitch: Arc::clone(&dipswitch),
+            ram_size: config.ram_size,
+            unmapped: config.open_bus.value(),
+            fault: Mutex::new(None),
+            watch_active: AtomicBool::new(false),
+            access_log: Mutex::new(Vec::new()),
+        });
+        let hook_bus = Arc::clone(&bus);
+        cpu.memory_mut()
+            .set_write_hook(move |address, value, size| hook_bus.write(address, value, size));
+        let hook_bus = Arc::clone(&bus);
+        cpu.memory_mut()
+            .set_read_hook(move |address| hook_bus.read(address));
 
         // Initialize CPU for supervisor mode
         cpu.set_sr(0x2700); // Supervisor mode, interrupts masked
+        cpu.set_clr_read(config.clr_read);
+        cpu.set_prefetch(config.prefetch);
+
+        timer.lock().unwrap().set_cpu_clock(config.clock_hz);
+        rtc.lock().unwrap().set_cpu_clock(config.clock_hz);
+        buzzer.lock().unwrap().set_cpu_clock(config.clock_hz);
+
+        // The watchdog isn't listed: its timeout acts on the whole machine
+        let mut clocked: Vec<(EventSource, Arc<Mutex<dyn Clocked>>)> =
+            vec![(EventSource::Cf, cfcard.clone())];
+        if enabled.rtc {
+            clocked.push((EventSource::Rtc, rtc.clone()));
+        }
+        if enabled.timer {
+            clocked.push((EventSource::Timer, timer.clone()));
+        }
+        clocked.extend([
+            (
+                EventSource::Buzzer,
+                buzzer.clone() as Arc<Mutex<dyn Clocked>>,
+            ),
+            (EventSource::Spi, spi.clone()),
+            (EventSource::I2c, i2c.clone()),
+        ]);
 
         // Load embedded ROM
         let mut rom_data = vec![0xFF; ROM_SIZE];
@@ -295,44 +932,184 @@ pub fn new() -> Self {
             cpu,
             uart,
             cfcard,
+            rtc,
+            gpio,
+            intc,
+            timer,
+            watchdog,
+            leds,
+            buzzer,
+            spi,
+            i2c,
+            dipswitch,
+            bus,
             rom_data,
+            embedded_rom: true,
+            config,
             uart_output: Vec::new(),
+            uart_input: VecDeque::new(),
+            debugger: Debugger::new(),
+            instructions: 0,
+            last_stop: None,
+            trace: TraceBuffer::new(),
+            profiler: Profiler::new(),
+            app_stubs: false,
+            scheduler: Scheduler::new(),
+            clocked,
         };
 
-        // Sync ROM to memory (don't reset yet, let caller decide)
-        sbc.sync_rom_to_memory();
+        // Power-on reset: start from the ROM's vector table
+        sbc.update_card_detect();
+        sbc.reset();
 
         sbc
     }
 
+    /// Creates a new SBC instance booting from a ROM image file
+    ///
+    /// The image is loaded at $000000 (padded with $FF to the ROM size) and
+    /// the machine is reset, so the initial SSP and PC come from the image's
+    /// first two longwords like on real hardware.
+    pub fn new_with_rom(path: &Path) -> Result<Self, RomError> {
+        let data = read_rom(path)?;
+
+        let mut sbc = Self::new();
+        sbc.load_rom(&data);
+        sbc.reset();
+        Ok(sbc)
+    }
+
     /// Performs a hardware reset
     ///
     /// This simulates the CPU reset sequence:
     /// 1. Read initial SSP from $000000
     /// 2. Read initial PC from $000004
     /// 3. Set supervisor mode, mask interrupts
+    ///
+    /// Both vectors are fetched through the bus. /ROMSEL only depends on A23,
+    /// so the ROM is always mapped at $000000 and the board needs no
+    /// reset-time overlay. `set_reset_vectors` overrides the fetched values.
+    ///
+    /// Every peripheral is reinitialized. The host's reset and a watchdog
+    /// timeout both come through here.
     pub fn reset(&mut self) {
+        self.hardware_reset(false, true);
+    }
+
+    /// Performs a warm reset: the CPU and every peripheral reinitialize as
+    /// in `reset`, but RAM keeps its contents
+    pub fn reset_warm(&mut self) {
+        self.hardware_reset(false, false);
+    }
+
+    /// Re-runs only the CPU's reset vector fetch
+    ///
+    /// SSP and PC are reloaded from the vectors and SR becomes $2700. The
+    /// other registers, RAM and the peripherals are left alone.
+    pub fn reset_cpu(&mut self) {
+        let vectors = self.reset_vectors();
+        self.cpu.resume();
+        self.cpu.set_sr(0x2700);
+        self.cpu.registers.set_sp(vectors.ssp);
+        self.cpu.set_pc(vectors.pc);
+        self.last_stop = None;
+    }
+
+    /// Power-cycles the machine: a `reset` that also ejects the CF card and
+    /// clears the breakpoint, watchpoint and symbol tables, the trace and
+    /// the profile
+    ///
+    /// The machine is reset even if ejecting the card fails to flush its
+    /// cached writes; the flush error is returned.
+    pub fn reset_cold(&mut self) -> io::Result<()> {
+        let ejected = self.eject_cf();
+        self.debugger = Debugger::new();
+        self.trace = TraceBuffer::new();
+        self.profiler = Profiler::new();
+        self.reset();
+        ejected
+    }
+
+    /// Resets the machine as `mode` says (see `ResetMode`)
+    pub fn reset_with(&mut self, mode: ResetMode) -> io::Result<()> {
+        match mode {
+            ResetMode::Cold => return self.reset_cold(),
+            ResetMode::Warm => self.reset_warm(),
+            ResetMode::CpuOnly => self.reset_cpu(),
+        }
+        Ok(())
+    }
+
+    /// Returns the initial SSP and PC, from the ROM's vector table unless
+    /// `set_reset_vectors` overrode them
+    fn reset_vectors(&self) -> ResetVectors {
+        self.config.reset_vectors.unwrap_or_else(|| ResetVectors {
+            ssp: self.cpu.memory.read_long(0x0000_0000).unwrap_or(0),
+            pc: self.cpu.memory.read_long(0x0000_0004).unwrap_or(0),
+        })
+    }
+
+    /// Resets the CPU and every peripheral, recording whether the watchdog
+    /// caused the reset
+    ///
+    /// RAM is cleared with `clear_ram`, and keeps its contents otherwise. A
+    /// watchdog reset keeps the console: bytes still in the TX FIFO are
+    /// moved to the output buffer, and unread output and pending input stay.
+    fn hardware_reset(&mut self, by_watchdog: bool, clear_ram: bool) {
         // Copy ROM to CPU memory at $000000
         self.sync_rom_to_memory();
-
-        // Read reset vectors
-        let ssp = self.cpu.memory.read_long(0x0000_0000).unwrap_or(0);
-        let pc = self.cpu.memory.read_long(0x0000_0004).unwrap_or(0);
+        let vectors = self.reset_vectors();
 
         // Reset CPU
-        self.cpu.reset();
+        if clear_ram {
+            self.cpu.reset();
+        } else {
+            self.cpu.reset_keep_memory();
+        }
+        self.instructions = 0;
+        self.last_stop = None;
 
         // Restore ROM (reset clears memory)
         self.sync_rom_to_memory();
 
         // Set up registers
-        self.cpu.registers.set_sp(ssp);
-        self.cpu.set_pc(pc);
         self.cpu.set_sr(0x2700); // Supervisor mode, all interrupts masked
+        self.cpu.registers.set_sp(vectors.ssp);
+        self.cpu.set_pc(vectors.pc);
 
         // Reset UART
+        if by_watchdog {
+            self.drain_uart_tx();
+        } else {
+            self.uart_output.clear();
+            self.uart_input.clear();
+        }
         self.uart.lock().unwrap().reset();
-        self.uart_output.clear();
+        self.app_stubs = false;
+
+        // The RTC keeps time across resets; only its registers reset
+        self.rtc.lock().unwrap().reset();
+        self.gpio.lock().unwrap().reset();
+        self.timer.lock().unwrap().reset();
+        self.watchdog.lock().unwrap().reset(by_watchdog);
+        self.leds.lock().unwrap().reset();
+        self.buzzer.lock().unwrap().reset();
+        self.spi.lock().unwrap().reset();
+        self.i2c.lock().unwrap().reset();
+        self.cfcard.lock().unwrap().reset();
+
+        // Reset masks every interrupt source
+        let mut intc = self.intc.lock().unwrap();
+        intc.reset();
+        intc.set_priority(intc::sources::CF, self.config.cf.irq_level);
+    }
+
+    /// Overrides the reset vectors, or restores fetching them from ROM with
+    /// `None`
+    ///
+    /// Takes effect on the next reset.
+    pub const fn set_reset_vectors(&mut self, vectors: Option<ResetVectors>) {
+        self.config.reset_vectors = vectors;
     }
 
     /// Syncs ROM data to CPU memory
@@ -346,36 +1123,13 @@ fn sync_rom_to_memory(&mut self) {
                 let _ = self.cpu.memory.load_binary(addr, &self.rom_data);
             }
         }
-        // Fix TRAP vectors: the embedded ROM binary has handler addresses that
-        // are off by $12 (18 bytes) from the actual handler code. This is due
-        // to a mismatch between the vector table and handler positions in the
-        // assembled binary. Patch all 16 TRAP vectors to correct the offset.
-        // NOTE: This fixup is for ROM boot mode. For app mode (run_app),
-        // custom stubs are installed that bypass the ROM handlers entirely.
-        self.fixup_trap_vectors();
-    }
-
-    /// Patches TRAP vector table to correct handler address offset
-    fn fixup_trap_vectors(&mut self) {
-        const TRAP_VECTOR_OFFSET: u32 = 0x12; // All handlers are +$12 from vectors
-        for i in 0..16u32 {
-            let vec_addr = 0x80 + i * 4; // TRAP #0 is vector 32 = offset $80
-            if let Ok(old_handler) = self.cpu.memory.read_long(vec_addr) {
-                // Only fix vectors that point into ROM (< $100000) and aren't $FFFFFFFF
-                if old_handler < 0x0010_0000 && old_handler != 0xFFFF_FFFF {
-                    let _ = self
-                        .cpu
-                        .memory
-                        .write_long(vec_addr, old_handler + TRAP_VECTOR_OFFSET);
-                }
-            }
-        }
     }
 
     /// Loads ROM from a single binary file
     ///
     /// The ROM should be 64KB or less. If smaller, it's padded with 0xFF.
     pub fn load_rom(&mut self, data: &[u8]) {
+        self.embedded_rom = false;
         self.rom_data.fill(0xFF);
         let len = data.len().min(ROM_SIZE);
         self.rom_data[..len].copy_from_slice(&data[..len]);
@@ -395,6 +1149,7 @@ pub fn load_rom_file(&mut self, path: &Path) -> io::Result<()> {
     /// - rom-u.bin: Upper bytes (D8-D15, even addresses)
     /// - rom-l.bin: Lower bytes (D0-D7, odd addresses)
     pub fn load_rom_split(&mut self, rom_l: &[u8], rom_u: &[u8]) {
+        self.embedded_rom = false;
         self.rom_data.fill(0xFF);
         let len = rom_l.len().min(rom_u.len()).min(ROM_SIZE / 2);
         for i in 0..len {
@@ -414,17 +1169,111 @@ pub fn load_rom_split_files(&mut self, path_l: &Path, path_u: &Path) -> io::Resu
 
     /// Loads a `CompactFlash` disk image
     pub fn load_cf_image(&mut self, path: &Path) -> io::Result<()> {
-        self.cfcard.lock().unwrap().load_image(path)
+        let result = self.cfcard.lock().unwrap().load_image(path);
+        if result.is_ok() {
+            self.config.cf.image = Some(path.display().to_string());
+        }
+        self.update_card_detect();
+        result
     }
 
     /// Loads a `CompactFlash` disk image from bytes
     pub fn load_cf_bytes(&mut self, data: &[u8]) {
         self.cfcard.lock().unwrap().load_bytes(data);
+        self.config.cf.image = None;
+        self.update_card_detect();
+    }
+
+    /// Inserts a `CompactFlash` card (hot-swap)
+    pub fn insert_cf(&mut self, image: CfImage) -> io::Result<()> {
+        let path = match &image {
+            CfImage::File(path) => Some(path.display().to_string()),
+            CfImage::Bytes(_) => None,
+        };
+        let result = self.cfcard.lock().unwrap().insert(image);
+        if result.is_ok() {
+            self.config.cf.image = path;
+        }
+        self.update_card_detect();
+        result
+    }
+
+    /// Ejects the `CompactFlash` card, flushing any cached writes
+    pub fn eject_cf(&mut self) -> io::Result<()> {
+        let result = self.cfcard.lock().unwrap().eject();
+        self.config.cf.image = None;
+        self.update_card_detect();
+        result
+    }
+
+    /// Wires (or disconnects) the CF card-detect signal to UART CTS
+    ///
+    /// When wired, MSR bit 4 reads 1 while a card is inserted, and inserting
+    /// or removing the card sets DCTS and raises a modem status interrupt if
+    /// the guest enabled it.
+    pub fn set_card_detect_enabled(&mut self, enabled: bool) {
+        self.config.uart.card_detect = enabled;
+        self.update_card_detect();
+    }
+
+    /// Sets the priority of the CF INTRQ line (0 disconnects it)
+    ///
+    /// The priority is programmed into the interrupt controller now and
+    /// after every reset; the guest can still change it.
+    pub fn set_cf_irq_level(&mut self, level: u8) {
+        self.config.cf.irq_level = level.min(7);
+        self.intc
+            .lock()
+            .unwrap()
+            .set_priority(intc::sources::CF, self.config.cf.irq_level);
+    }
+
+    /// Updates the card-detect input from the current CF card state
+    fn update_card_detect(&mut self) {
+        let present = self
+            .config
+            .uart
+            .card_detect
+            .then(|| self.cfcard.lock().unwrap().is_inserted());
+        self.uart.lock().unwrap().set_card_detect(present);
+    }
+
+    /// Writes all cached `CompactFlash` sectors to the backing image
+    pub fn flush_cf(&mut self) -> io::Result<()> {
+        self.cfcard.lock().unwrap().flush()
+    }
+
+    /// Enables or disables the `CompactFlash` copy-on-write overlay
+    pub fn set_cf_overlay_enabled(&mut self, enabled: bool) -> io::Result<()> {
+        self.cfcard.lock().unwrap().set_overlay_enabled(enabled)?;
+        self.config.cf.overlay = enabled;
+        Ok(())
+    }
+
+    /// Discards all `CompactFlash` writes held in the overlay
+    pub fn discard_cf_overlay(&mut self) {
+        self.cfcard.lock().unwrap().discard_overlay();
+    }
+
+    /// Commits the `CompactFlash` overlay into the base image
+    pub fn commit_cf_overlay(&mut self) -> io::Result<()> {
+        self.cfcard.lock().unwrap().commit_overlay()
+    }
+
+    /// Exports the `CompactFlash` overlay as a delta file
+    pub fn export_cf_overlay(&mut self, path: &Path) -> io::Result<()> {
+        self.cfcard.lock().unwrap().export_overlay(path)
     }
 
-    /// Ejects the `CompactFlash` card
-    pub fn eject_cf(&mut self) {
-        self.cfcard.lock().unwrap().eject();
+    /// Returns `CompactFlash` sector cache statistics
+    #[must_use]
+    pub fn cf_cache_stats(&self) -> CacheStats {
+        self.cfcard.lock().unwrap().cache_stats()
+    }
+
+    /// Sets the `CompactFlash` command latency
+    pub fn set_cf_timing(&mut self, timing: CfTiming) {
+        self.cfcard.lock().unwrap().set_timing(timing);
     }
 
     /// Returns true if a CF card is inserted
@@ -433,62 +1282,439 @@ pub fn cf_inserted(&self) -> bool {
         self.cfcard.lock().unwrap().is_inserted()
     }
 
-    /// Loads an application binary into RAM at $E00100
+    /// Loads an application binary into RAM at `addr` ($E00100 by default)
     ///
     /// This is how programs are loaded for execution on the target board.
-    pub fn load_app(&mut self, data: &[u8]) {
-        let _ = self.cpu.memory.load_binary(APP_START, data);
+    /// The binary must fit in populated RAM (either window) above the
+    /// 256-byte system area, where `run_app` installs its TRAP stubs.
+    pub fn load_app(&mut self, data: &[u8], addr: Option<u32>) -> Result<(), AppLoadError> {
+        let addr = addr.unwrap_or(APP_START);
+        let ram_size = self.config.ram_size;
+        let offset = [RAM_BASE, RAM_MIRROR]
+            .into_iter()
+            .find(|&base| (base..base + ram_size).contains(&addr))
+            .map(|base| (addr - base) as usize)
+            .ok_or(AppLoadError::NotRam { addr })?;
+        if offset < (APP_START - RAM_MIRROR) as usize {
+            return Err(AppLoadError::SystemArea { addr });
+        }
+        let available = ram_size as usize - offset;
+        if data.len() > available {
+            return Err(AppLoadError::TooLarge {
+                addr,
+                size: data.len(),
+                available,
+            });
+        }
+        let _ = self.cpu.memory.load_binary(addr, data);
+        Ok(())
     }
 
-    /// Executes the loaded application
+    /// Writes a block of bytes through the bus, as a debugger does
     ///
-    /// Sets up registers as the ROM would:
-    /// - SP = end of RAM ($F00000)
-    /// - PC = $E00100 (app start)
-    /// - D0-D7/A0-A6 = 0
-    /// - Supervisor mode, interrupts enabled (IPL=0)
+    /// Device registers see the writes like guest writes. Addresses that
+    /// ignore writes (ROM, unpopulated RAM, open bus and conflicting
+    /// decodes) are write-protected: writing stops at the first one, which
+    /// `failed_at` reports. With `override_rom`, ROM bytes are patched into
+    /// the ROM image (and all its mirrors) instead.
+    pub fn write_memory(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        override_rom: bool,
+    ) -> MemoryWriteResult {
+        let mut written = 0;
+        let mut rom_patched = false;
+        let failed_at = loop {
+            if written == data.len() {
+                break None;
+            }
+            let addr = address.wrapping_add(written as u32);
+            match decode_address(addr) {
+                SbcAddressRegion::Rom(offset) if override_rom => {
+                    self.rom_data[offset as usize] = data[written];
+                    rom_patched = true;
+                    written += 1;
+                }
+                _ if self.host_writable(addr) => {
+                    let run = (written..data.len())
+                        .take_while(|&i| self.host_writable(address.wrapping_add(i as u32)))
+                        .count();
+                    match self
+                        .cpu
+                        .memory
+                        .write_bytes(addr, &data[written..written + run])
+                    {
+                        Ok(count) => written += count,
+                        Err(MemoryError::AddressOutOfRange { address: at, .. }) => {
+                            written += at.wrapping_sub(addr) as usize;
+                            break Some(at);
+                        }
+                    }
+                }
+                _ => break Some(addr & ADDR_MASK),
+            }
+        };
+
+        if rom_patched {
+            self.sync_rom_to_memory();
+        }
+        MemoryWriteResult { written, failed_at }
+    }
+
+    /// Fills `length` bytes from `address` with `pattern` repeated, through
+    /// the bus like `write_memory`, stopping at the first write-protected
+    /// address
     ///
-    /// Instead of relying on ROM TRAP handlers (which have address mismatches
-    /// in the current ROM binary), this installs small handler stubs directly
-    /// in RAM at $E00080. These stubs handle the core syscalls (Exit, `OutChar`,
-    /// `OutStr`, `InChar`) by directly accessing the UART hardware.
-    pub fn run_app(&mut self) {
-        // Install TRAP handler stubs in RAM at $E00080 (within the 256-byte
-        // system area, below the app load address at $E00100)
-        self.install_trap_stubs();
+    /// With `dry_run` nothing is written; the result reports what the fill
+    /// would write. An empty pattern writes nothing.
+    pub fn fill_memory(
+        &mut self,
+        address: u32,
+        length: usize,
+        pattern: &[u8],
+        dry_run: bool,
+    ) -> MemoryWriteResult {
+        let length = if pattern.is_empty() { 0 } else { length };
+        if dry_run {
+            let written = (0..length)
+                .take_while(|&i| self.host_writable(address.wrapping_add(i as u32)))
+                .count();
+            let failed_at =
+                (written < length).then(|| address.wrapping_add(written as u32) & ADDR_MASK);
+            return MemoryWriteResult { written, failed_at };
+        }
+        let data: Vec<u8> = pattern.iter().copied().cycle().take(length).collect();
+        self.write_memory(address, &data, false)
+    }
 
-        // Don't call cpu.reset() as it clears memory!
-        // Just set up registers for app execution
-        self.cpu.registers = crate::registers::RegisterFile::new();
-        // Set supervisor mode FIRST (so set_sp sets SSP)
-        self.cpu.set_sr(0x2000); // Supervisor mode, interrupts enabled
-                                 // Now set stack pointer (will set SSP since we're in supervisor mode)
-        self.cpu.registers.set_sp(INITIAL_SP);
-        self.cpu.set_pc(APP_START);
-        self.cpu.resume();
+    /// Returns true if a host read of `addr` reaches memory or a device
+    ///
+    /// Unpopulated RAM, open bus and conflicting decodes answer with the
+    /// open bus value instead.
+    pub fn host_readable(&self, addr: u32) -> bool {
+        match decode_address(addr) {
+            SbcAddressRegion::Ram(offset) => !self.bus.ram_unpopulated(offset),
+            SbcAddressRegion::Rom(_)
+            | SbcAddressRegion::Uart(_)
+            | SbcAddressRegion::CfCard(_)
+            | SbcAddressRegion::Expansion(_) => true,
+            SbcAddressRegion::OpenBus | SbcAddressRegion::Conflict => false,
+        }
     }
 
-    /// Installs M68K TRAP handler stubs in RAM for app execution.
+    /// Returns true if a host write to `addr` reaches memory or a device
     ///
-    /// These stubs bypass the ROM's TRAP handlers entirely, directly
-    /// implementing the core syscalls. Stubs are placed at $E00080-$E000FF.
-    #[allow(
-        unused_assignments,
-        reason = "addr is incremented sequentially during instruction writing"
-    )]
-    fn install_trap_stubs(&mut self) {
-        // Helper function to write trap vectors
-        fn write_vec(mem: &mut crate::memory::Memory, vec_addr: u32, handler: u32) {
-            let bytes = handler.to_be_bytes();
-            let _ = mem.load_binary(vec_addr, &bytes);
+    /// ROM, unpopulated RAM, open bus and conflicting decodes ignore writes.
+    fn host_writable(&self, addr: u32) -> bool {
+        match decode_address(addr) {
+            SbcAddressRegion::Ram(offset) => !self.bus.ram_unpopulated(offset),
+            SbcAddressRegion::Uart(_)
+            | SbcAddressRegion::CfCard(_)
+            | SbcAddressRegion::Expansion(_) => true,
+            SbcAddressRegion::Rom(_) | SbcAddressRegion::OpenBus | SbcAddressRegion::Conflict => {
+                false
+            }
         }
+    }
 
-        // Base address for stubs (in system variable area)
-        let stub_base: u32 = 0x00E0_0080;
-        let mut addr = stub_base;
+    /// Loads a raw binary file into memory at `address`, returning its size
+    ///
+    /// The file must fit in the populated RAM window (or, with
+    /// `override_rom`, the ROM image) from `address` on. Unlike `load_app`,
+    /// any RAM address is allowed, including the system area.
+    pub fn load_binary_file(
+        &mut self,
+        path: &Path,
+        address: u32,
+        override_rom: bool,
+    ) -> Result<usize, BinaryLoadError> {
+        let data = std::fs::read(path).map_err(|e| {
+            let path = path.display().to_string();
+            if e.kind() == io::ErrorKind::NotFound {
+                BinaryLoadError::NotFound { path }
+            } else {
+                BinaryLoadError::Io {
+                    path,
+                    kind: e.kind(),
+                    message: e.to_string(),
+                }
+            }
+        })?;
 
-        // Helper to write a word and advance
-        let mem = &mut self.cpu.memory;
+        let addr = address & ADDR_MASK;
+        let available = match decode_address(addr) {
+            SbcAddressRegion::Ram(offset) if !self.bus.ram_unpopulated(offset) => {
+                (self.config.ram_size - offset) as usize
+            }
+            SbcAddressRegion::Rom(offset) if override_rom => ROM_SIZE - offset as usize,
+            _ => return Err(BinaryLoadError::NotWritable { addr }),
+        };
+        if data.len() > available {
+            return Err(BinaryLoadError::TooLarge {
+                addr,
+                size: data.len(),
+                available,
+            });
+        }
+        let result = self.write_memory(addr, &data, override_rom);
+        debug_assert_eq!(result.failed_at, None);
+        Ok(result.written)
+    }
+
+    /// Disassembles `count` instructions starting at `address`
+    ///
+    /// Only ROM and populated RAM are read, so device registers never see
+    /// the accesses. If an instruction runs off mapped memory, the listing
+    /// ends with a `DISASSEMBLY_END` entry at its address.
+    pub fn disassemble(&self, address: u32, count: usize) -> Vec<DisassemblyLine> {
+        let read_byte = |addr: u32| peek_byte(&self.rom_data, &self.cpu.memory, &self.bus, addr);
+        let fetch = |addr: u32| {
+            let high = read_byte(addr)?;
+            let low = read_byte(addr.wrapping_add(1))?;
+            Some(u16::from_be_bytes([high, low]))
+        };
+
+        let pc = self.pc() & ADDR_MASK;
+        let mut addr = address & ADDR_MASK;
+        let mut lines = Vec::with_capacity(count);
+        while lines.len() < count {
+            let symbol = self.debugger.symbol_at(addr).map(str::to_string);
+            let Some(insn) = disasm::decode(addr, fetch) else {
+                lines.push(DisassemblyLine {
+                    addr,
+                    bytes_hex: String::new(),
+                    mnemonic: DISASSEMBLY_END.to_string(),
+                    operands: String::new(),
+                    is_current_pc: addr == pc,
+                    symbol,
+                });
+                break;
+            };
+            let mut bytes_hex = String::new();
+            for byte in (0..insn.length).filter_map(|i| read_byte(addr.wrapping_add(i))) {
+                let _ = write!(bytes_hex, "{byte:02X}");
+            }
+            lines.push(DisassemblyLine {
+                addr,
+                bytes_hex,
+                mnemonic: insn.mnemonic,
+                operands: insn.operands,
+                is_current_pc: addr == pc,
+                symbol,
+            });
+            addr = addr.wrapping_add(insn.length) & ADDR_MASK;
+        }
+        lines
+    }
+
+    /// Reads up to `max_entries` longwords from the active stack pointer
+    /// upward, annotating each one
+    ///
+    /// Like `disassemble`, this only reads ROM and populated RAM, and stops
+    /// at the first longword that isn't there. A6 is taken as the frame
+    /// pointer of the current LINK frame.
+    #[must_use]
+    pub fn stack(&self, max_entries: usize) -> Vec<StackEntry> {
+        let read_byte = |addr: u32| peek_byte(&self.rom_data, &self.cpu.memory, &self.bus, addr);
+
+        let regs = self.registers();
+        let sp = regs.sp() & ADDR_MASK;
+        let fp = regs.a(6) & ADDR_MASK;
+        // Locals, the saved A6 and the return address
+        let frame = if fp >= sp { sp..fp + 8 } else { 0..0 };
+
+        let mut entries = Vec::new();
+        let mut address = sp;
+        while entries.len() < max_entries {
+            let Some(value) = self.peek_long(address) else {
+                break;
+            };
+            let target = value & ADDR_MASK;
+            let symbol = read_byte(target).and_then(|_| self.debugger.describe(target));
+            entries.push(StackEntry {
+                address,
+                value,
+                return_address: self.follows_call(target),
+                symbol,
+                in_frame: frame.contains(&address),
+            });
+            address = (address + 4) & ADDR_MASK;
+        }
+        entries
+    }
+
+    /// Reconstructs up to `max_frames` frames of the call stack, the
+    /// current routine (at the PC) first
+    ///
+    /// Callers are found by following the LINK frames from A6: each holds
+    /// the caller's A6 and, above it, the return address. Where the chain
+    /// ends (a routine without a LINK frame, or none at all), the rest of
+    /// the stack is scanned for longwords that look like return addresses
+    /// (see `stack`), and those frames are marked as guesses.
+    #[must_use]
+    pub fn call_stack(&self, max_frames: usize) -> Vec<CallFrame> {
+        /// Most longwords scanned for return addresses
+        const MAX_SCAN: u32 = 4096;
+
+        let regs = self.registers();
+        let sp = regs.sp() & ADDR_MASK;
+        // The LINK frame at `fp` and the return address it holds, if `fp`
+        // can be one: even, not below `floor`, and holding a return address
+        let linked = |fp: u32, floor: u32| {
+            if fp & 1 != 0 || fp < floor {
+                return None;
+            }
+            let return_address = self.peek_long(fp.wrapping_add(4))? & ADDR_MASK;
+            self.follows_call(return_address)
+                .then_some((fp, return_address))
+        };
+        let frame = |return_address: u32, link: Option<(u32, u32)>, is_link_frame: bool| {
+            let (symbol, offset) = self
+                .debugger
+                .nearest_symbol(return_address)
+                .map(|(name, offset)| (name.to_string(), offset))
+                .unzip();
+            CallFrame {
+                return_address,
+                frame_pointer: link.map(|(fp, _)| fp),
+                symbol,
+                offset,
+                is_link_frame,
+            }
+        };
+
+        let mut frames = Vec::new();
+        if max_frames == 0 {
+            return frames;
+        }
+        let mut link = linked(regs.a(6) & ADDR_MASK, sp);
+        frames.push(frame(self.pc(), link, link.is_some()));
+
+        // Where the scan for return addresses starts once the chain ends
+        let mut scan = sp;
+        while let Some((fp, return_address)) = link {
+            if frames.len() >= max_frames {
+                return frames;
+            }
+            scan = fp.wrapping_add(8) & ADDR_MASK;
+            link = self
+                .peek_long(fp)
+                .and_then(|saved| linked(saved & ADDR_MASK, scan));
+            frames.push(frame(return_address, link, true));
+        }
+
+        let mut address = scan;
+        for _ in 0..MAX_SCAN {
+            if frames.len() >= max_frames {
+                break;
+            }
+            let Some(value) = self.peek_long(address) else {
+                break;
+            };
+            if self.follows_call(value & ADDR_MASK) {
+                frames.push(frame(value & ADDR_MASK, None, false));
+            }
+            address = address.wrapping_add(4) & ADDR_MASK;
+        }
+        frames
+    }
+
+    /// Reads the longword at `address` without side effects, if it is in
+    /// ROM or RAM
+    fn peek_long(&self, address: u32) -> Option<u32> {
+        let mut bytes = [0; 4];
+        for (i, byte) in (0..).zip(bytes.iter_mut()) {
+            *byte = peek_byte(
+                &self.rom_data,
+                &self.cpu.memory,
+                &self.bus,
+                address.wrapping_add(i),
+            )?;
+        }
+        Some(u32::from_be_bytes(bytes))
+    }
+
+    /// Returns true if a JSR, BSR or TRAP ends just before `address`, so
+    /// that it could be the return address the instruction pushed
+    fn follows_call(&self, address: u32) -> bool {
+        if address & 1 != 0
+            || peek_byte(&self.rom_data, &self.cpu.memory, &self.bus, address).is_none()
+        {
+            return false;
+        }
+        // JSR takes 2 to 6 bytes, BSR 2 or 4 and TRAP 2
+        [2, 4, 6].into_iter().any(|length| {
+            let start = address.wrapping_sub(length) & ADDR_MASK;
+            self.peek_call(start)
+                .is_some_and(|call_length| call_length == length)
+        })
+    }
+
+    /// Returns the length of the instruction at `address` if it is a JSR,
+    /// BSR or TRAP in ROM or RAM
+    fn peek_call(&self, address: u32) -> Option<u32> {
+        let read_byte = |addr: u32| peek_byte(&self.rom_data, &self.cpu.memory, &self.bus, addr);
+        let fetch = |addr: u32| {
+            let high = read_byte(addr)?;
+            let low = read_byte(addr.wrapping_add(1))?;
+            Some(u16::from_be_bytes([high, low]))
+        };
+        let insn = disasm::decode(address, fetch)?;
+        let name = insn.mnemonic.split('.').next().unwrap_or_default();
+        matches!(name, "jsr" | "bsr" | "trap").then_some(insn.length)
+    }
+
+    /// Executes the loaded application
+    ///
+    /// Sets up registers as the ROM would:
+    /// - SP = end of populated RAM ($F00000 with 1MB)
+    /// - PC = `entry` ($E00100, the app start, by default)
+    /// - D0-D7/A0-A6 = 0
+    /// - Supervisor mode, interrupts enabled (IPL=0)
+    ///
+    /// Instead of relying on ROM TRAP handlers (which have address mismatches
+    /// in the current ROM binary), this installs small handler stubs directly
+    /// in RAM at $E00080. These stubs handle the core syscalls (Exit, `OutChar`,
+    /// `OutStr`, `InChar`) by directly accessing the UART hardware. Exit
+    /// takes an optional exit code in D0, which `run` reports.
+    pub fn run_app(&mut self, entry: Option<u32>) {
+        // Install TRAP handler stubs in RAM at $E00080 (within the 256-byte
+        // system area, below the app load address at $E00100)
+        self.install_trap_stubs();
+        self.app_stubs = true;
+
+        // Don't call cpu.reset() as it clears memory!
+        // Just set up registers for app execution
+        self.cpu.registers = crate::registers::RegisterFile::new();
+        // Set supervisor mode FIRST (so set_sp sets SSP)
+        self.cpu.set_sr(0x2000); // Supervisor mode, interrupts enabled
+                                 // Now set stack pointer (will set SSP since we're in supervisor mode)
+        self.cpu.registers.set_sp(RAM_MIRROR + self.config.ram_size);
+        self.cpu.set_pc(entry.unwrap_or(APP_START));
+        self.cpu.resume();
+    }
+
+    /// Installs M68K TRAP handler stubs in RAM for app execution.
+    ///
+    /// These stubs bypass the ROM's TRAP handlers entirely, directly
+    /// implementing the core syscalls. Stubs are placed at $E00080-$E000FF.
+    #[allow(
+        unused_assignments,
+        reason = "addr is incremented sequentially during instruction writing"
+    )]
+    fn install_trap_stubs(&mut self) {
+        // Helper function to write trap vectors
+        fn write_vec(mem: &mut crate::memory::Memory, vec_addr: u32, handler: u32) {
+            let bytes = handler.to_be_bytes();
+            let _ = mem.load_binary(vec_addr, &bytes);
+        }
+
+        // Base address for stubs (in system variable area)
+        let mut addr = TRAP_STUB_BASE;
+
+        // Helper to write a word and advance
+        let mem = &mut self.cpu.memory;
 
         // ---- TRAP #0: Exit ----
         // STOP #$2700  (halt CPU, supervisor mode, interrupts masked)
@@ -617,12 +1843,258 @@ pub fn cfcard(&self) -> Arc<Mutex<CfCard>> {
         Arc::clone(&self.cfcard)
     }
 
+    /// Gets a reference to the real-time clock
+    #[must_use]
+    pub fn rtc(&self) -> Arc<Mutex<Rtc>> {
+        Arc::clone(&self.rtc)
+    }
+
     /// Returns true if the CPU is halted
     #[must_use]
     pub const fn is_halted(&self) -> bool {
         self.cpu.is_halted()
     }
 
+    /// Returns the active configuration
+    #[must_use]
+    pub const fn config(&self) -> &SbcConfig {
+        &self.config
+    }
+
+    /// Captures the machine state (see `snapshot` for what isn't included)
+    ///
+    /// Flush the CF card first so the image on disk matches the snapshot.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        let created = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let ram_size = self.config.ram_size as usize;
+        let mut ram = self.cpu.memory.read_range(RAM_BASE, ram_size);
+        ram.extend(self.cpu.memory.read_range(RAM_MIRROR, ram_size));
+
+        Snapshot {
+            info: SnapshotInfo {
+                version: SNAPSHOT_VERSION,
+                created,
+                pc: self.pc(),
+                cycles: self.cycles(),
+                config: self.config.clone(),
+            },
+            state: MachineState {
+                cpu: self.cpu.save_state(),
+                uart: self.uart.lock().unwrap().clone(),
+                cfcard: self.cfcard.lock().unwrap().save_registers(),
+                rtc: self.rtc.lock().unwrap().clone(),
+                gpio: self.gpio.lock().unwrap().clone(),
+                intc: self.intc.lock().unwrap().clone(),
+                timer: self.timer.lock().unwrap().clone(),
+                watchdog: self.watchdog.lock().unwrap().clone(),
+                leds: self.leds.lock().unwrap().clone(),
+                buzzer: self.buzzer.lock().unwrap().clone(),
+                spi: self.spi.lock().unwrap().save_registers(),
+                i2c: self.i2c.lock().unwrap().save_registers(),
+                dipswitch: *self.dipswitch.lock().unwrap(),
+                scheduler: self.scheduler.clone(),
+                uart_output: self.uart_output.clone(),
+                uart_input: self.uart_input.clone(),
+                instructions: self.instructions,
+                embedded_rom: self.embedded_rom,
+                app_stubs: self.app_stubs,
+            },
+            rom: self.rom_data.clone(),
+            ram,
+        }
+    }
+
+    /// Builds a machine from a snapshot
+    ///
+    /// The configuration is checked and the CF image it names is opened
+    /// before the machine is built.
+    pub fn from_snapshot(snapshot: Snapshot) -> Result<Self, SnapshotError> {
+        let Snapshot {
+            info,
+            state,
+            rom,
+            ram,
+        } = snapshot;
+        let config = info.config;
+        config.validate().map_err(SnapshotError::Config)?;
+
+        let mut card = CfCard::new();
+        if let Some(image) = &config.cf.image {
+            card.load_image(Path::new(image)).map_err(|e| {
+                SnapshotError::Config(ConfigError::new("cf.image", format!("{image}: {e}")))
+            })?;
+        }
+        card.set_overlay_enabled(config.cf.overlay)
+            .map_err(|e| SnapshotError::Config(ConfigError::new("cf.overlay", e.to_string())))?;
+        card.restore_registers(state.cfcard);
+
+        let mut sbc = Self::with_config(config);
+        sbc.rom_data = rom;
+        sbc.embedded_rom = state.embedded_rom;
+        sbc.sync_rom_to_memory();
+        let ram_size = ram.len() / 2;
+        let _ = sbc.cpu.memory.load_binary(RAM_BASE, &ram[..ram_size]);
+        let _ = sbc.cpu.memory.load_binary(RAM_MIRROR, &ram[ram_size..]);
+        sbc.cpu.restore_state(state.cpu);
+
+        *sbc.uart.lock().unwrap() = state.uart;
+        *sbc.cfcard.lock().unwrap() = card;
+        *sbc.rtc.lock().unwrap() = state.rtc;
+        *sbc.gpio.lock().unwrap() = state.gpio;
+        *sbc.intc.lock().unwrap() = state.intc;
+        *sbc.timer.lock().unwrap() = state.timer;
+        *sbc.watchdog.lock().unwrap() = state.watchdog;
+        *sbc.leds.lock().unwrap() = state.leds;
+        *sbc.buzzer.lock().unwrap() = state.buzzer;
+        sbc.spi.lock().unwrap().restore_registers(state.spi);
+        sbc.i2c.lock().unwrap().restore_registers(state.i2c);
+        *sbc.dipswitch.lock().unwrap() = state.dipswitch;
+        sbc.scheduler = state.scheduler;
+        sbc.uart_output = state.uart_output;
+        sbc.uart_input = state.uart_input;
+        sbc.instructions = state.instructions;
+        sbc.app_stubs = state.app_stubs;
+        sbc.update_card_detect();
+        Ok(sbc)
+    }
+
+    /// Builds the board panel state (see `panel`)
+    ///
+    /// The UART section also counts the host input waiting for room in the
+    /// RX FIFO.
+    #[must_use]
+    pub fn panel_state(&self) -> serde_json::Value {
+        fn section(device: &Mutex<impl Peripheral>, enabled: bool) -> serde_json::Value {
+            if enabled {
+                device.lock().unwrap().ui_state()
+            } else {
+                serde_json::Value::Null
+            }
+        }
+
+        let enabled = self.config.peripherals;
+        let mut uart = section(&self.uart, true);
+        uart["inputQueued"] = self.uart_input.len().into();
+        serde_json::json!({
+            "leds": section(&self.leds, true),
+            "gpio": section(&self.gpio, enabled.gpio),
+            "uart": uart,
+            "cf": section(&self.cfcard, true),
+            "timer": section(&self.timer, enabled.timer),
+        })
+    }
+
+    /// Returns a peripheral's registers and internal state (see
+    /// `Peripheral::debug_dump`), or `None` for a name not in
+    /// `panel::PERIPHERALS`
+    #[must_use]
+    pub fn dump_peripheral(&self, name: &str) -> Option<serde_json::Value> {
+        let dump = match name {
+            "leds" => self.leds.lock().unwrap().debug_dump(),
+            "gpio" => self.gpio.lock().unwrap().debug_dump(),
+            "uart" => self.uart.lock().unwrap().debug_dump(),
+            "cf" => self.cfcard.lock().unwrap().debug_dump(),
+            "timer" => self.timer.lock().unwrap().debug_dump(),
+            _ => return None,
+        };
+        Some(dump)
+    }
+
+    /// Builds a diagnostics report for bug reports (see `diagnostics`)
+    #[must_use]
+    pub fn diagnostics(&self) -> Diagnostics {
+        let enabled = self.config.peripherals;
+        let peripherals = [
+            ("RTC", slots::RTC, enabled.rtc),
+            ("GPIO", slots::GPIO, enabled.gpio),
+            ("Interrupt controller", slots::INTC, true),
+            ("Timer", slots::TIMER, enabled.timer),
+            ("Watchdog", slots::WATCHDOG, true),
+            ("LED bar", slots::LED, true),
+            ("Buzzer", slots::BUZZER, true),
+            ("SPI", slots::SPI, true),
+            ("I2C", slots::I2C, true),
+            ("DIP switch", slots::DIPSWITCH, true),
+        ]
+        .into_iter()
+        .map(|(name, slot, enabled)| PeripheralInfo {
+            name,
+            base: EXPANSION_BASE + slot * 32,
+            enabled,
+        })
+        .collect();
+
+        let ram_end = self.config.ram_size - 1;
+        let memory_map = vec![
+            MapRegion {
+                name: "ROM",
+                start: 0x0000_0000,
+                end: 0x000F_FFFF,
+            },
+            MapRegion {
+                name: "ROM mirror",
+                start: 0x0020_0000,
+                end: 0x002F_FFFF,
+            },
+            MapRegion {
+                name: "Expansion bus",
+                start: EXPANSION_BASE,
+                end: 0x008F_FFFF,
+            },
+            MapRegion {
+                name: "CompactFlash",
+                start: 0x0090_0000,
+                end: 0x009F_FFFF,
+            },
+            MapRegion {
+                name: "UART",
+                start: 0x00A0_0000,
+                end: 0x00AF_FFFF,
+            },
+            MapRegion {
+                name: "RAM",
+                start: RAM_BASE,
+                end: RAM_BASE + ram_end,
+            },
+            MapRegion {
+                name: "RAM mirror",
+                start: RAM_MIRROR,
+                end: RAM_MIRROR + ram_end,
+            },
+        ];
+
+        Diagnostics {
+            version: env!("CARGO_PKG_VERSION"),
+            cpu: CpuInfo {
+                model: CPU_MODEL,
+                clock_hz: self.config.clock_hz,
+                clr_read: self.config.clr_read,
+                prefetch: self.config.prefetch,
+                halted: self.is_halted(),
+            },
+            ram_size: self.config.ram_size,
+            rom: RomInfo {
+                embedded: self.embedded_rom,
+                path: self.config.rom_path.clone(),
+                size: ROM_SIZE as u32,
+                crc32: crc32(&self.rom_data),
+            },
+            cf_inserted: self.cf_inserted(),
+            memory_map,
+            peripherals,
+            cycles: self.cycles(),
+            master_cycles: self.master_cycles(),
+            faults: FaultInfo {
+                last_bus_fault: *self.bus.fault.lock().unwrap(),
+                watchdog_reset: self.watchdog.lock().unwrap().reset_by_watchdog(),
+            },
+            config: self.config.clone(),
+        }
+    }
+
     /// Returns the current program counter
     #[must_use]
     pub const fn pc(&self) -> u32 {
@@ -635,10 +2107,21 @@ pub const fn sr(&self) -> u16 {
         self.cpu.sr()
     }
 
-    /// Returns the LED state from UART MCR
+    /// Returns the state of LED 0 (the UART RTS status LED)
     #[must_use]
     pub fn led_state(&self) -> bool {
-        self.uart.lock().unwrap().led_state()
+        self.led_bar() & 0x01 != 0
+    }
+
+    /// Returns the LED bar latch
+    #[must_use]
+    pub fn led_bar(&self) -> u8 {
+        self.leds.lock().unwrap().value()
+    }
+
+    /// Returns and clears the queued LED bar change events
+    pub fn take_led_events(&mut self) -> Vec<u8> {
+        self.leds.lock().unwrap().take_events()
     }
 
     /// Returns total cycles executed
@@ -647,9 +2130,258 @@ pub const fn cycles(&self) -> u64 {
         self.cpu.total_cycles()
     }
 
-    /// Sends a character to the UART receive buffer (from terminal)
-    pub fn send_char(&mut self, ch: u8) {
-        self.uart.lock().unwrap().push_rx(ch);
+    /// Returns the number of instructions executed since the last reset
+    #[must_use]
+    pub const fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Returns why the last run or batch of steps stopped, if there was one
+    /// since the last reset
+    #[must_use]
+    pub const fn last_stop(&self) -> Option<StopReason> {
+        self.last_stop
+    }
+
+    /// Starts recording executed instructions, keeping the last `depth`
+    /// (see `trace`)
+    pub fn start_trace(&mut self, depth: usize) {
+        self.trace.start(depth);
+    }
+
+    /// Stops recording executed instructions, keeping the trace
+    pub const fn stop_trace(&mut self) {
+        self.trace.stop();
+    }
+
+    /// Returns the most recent `max_entries` traced instructions, oldest
+    /// first, disassembling them if `disassemble` is set
+    ///
+    /// Disassembly reads memory as it is now, so it can differ from what
+    /// executed if the code has since been overwritten.
+    #[must_use]
+    pub fn trace(&self, max_entries: usize, disassemble: bool) -> Vec<TraceEntry> {
+        let mut entries = self.trace.latest(max_entries);
+        if disassemble {
+            let read_byte =
+                |addr: u32| peek_byte(&self.rom_data, &self.cpu.memory, &self.bus, addr);
+            let fetch = |addr: u32| {
+                let high = read_byte(addr)?;
+                let low = read_byte(addr.wrapping_add(1))?;
+                Some(u16::from_be_bytes([high, low]))
+            };
+            for entry in &mut entries {
+                entry.mnemonic = disasm::decode(entry.pc & ADDR_MASK, fetch).map(|insn| {
+                    format!("{} {}", insn.mnemonic, insn.operands)
+                        .trim_end()
+                        .to_string()
+                });
+            }
+        }
+        entries
+    }
+
+    /// Starts charging executed instructions' cycles to their addresses
+    /// (see `profile_report`)
+    pub const fn start_profile(&mut self) {
+        self.profiler.start();
+    }
+
+    /// Stops profiling, keeping the samples
+    pub const fn stop_profile(&mut self) {
+        self.profiler.stop();
+    }
+
+    /// Discards the profile samples
+    pub fn reset_profile(&mut self) {
+        self.profiler.reset();
+    }
+
+    /// Reports the `top_n` addresses the profiled instructions spent the
+    /// most cycles at, and the cycles spent per mnemonic
+    #[must_use]
+    pub fn profile_report(&self, top_n: usize) -> ProfileReport {
+        self.profiler.report(
+            top_n,
+            |address| self.debugger.describe(address),
+            |address| {
+                self.disassemble(address, 1)
+                    .pop()
+                    .map(|line| {
+                        format!("{} {}", line.mnemonic, line.operands)
+                            .trim_end()
+                            .to_string()
+                    })
+                    .unwrap_or_default()
+            },
+        )
+    }
+
+    /// Adds the instruction at the PC to the trace, if tracing
+    fn record_trace(&mut self) {
+        if !self.trace.is_enabled() {
+            return;
+        }
+        let pc = self.pc();
+        let opcode = self.peek_opcode(pc);
+        let (sr, cycles) = (self.cpu.registers.sr, self.cycles());
+        self.trace.record(pc, opcode, sr, cycles);
+    }
+
+    /// Reads the word at `pc` without side effects (0 outside ROM and RAM)
+    fn peek_opcode(&self, pc: u32) -> u16 {
+        let read_byte = |addr: u32| peek_byte(&self.rom_data, &self.cpu.memory, &self.bus, addr);
+        match (read_byte(pc), read_byte(pc.wrapping_add(1))) {
+            (Some(high), Some(low)) => u16::from_be_bytes([high, low]),
+            _ => 0,
+        }
+    }
+
+    /// Returns the instruction at the PC as `mnemonic operands`, or `None`
+    /// if the PC isn't in ROM or RAM
+    #[must_use]
+    pub fn next_instruction(&self) -> Option<String> {
+        let line = self.disassemble(self.pc(), 1).pop()?;
+        if line.mnemonic == DISASSEMBLY_END {
+            return None;
+        }
+        Some(
+            format!("{} {}", line.mnemonic, line.operands)
+                .trim_end()
+                .to_string(),
+        )
+    }
+
+    /// Queues a character for the UART receive FIFO (from terminal)
+    ///
+    /// Returns false if the input queue is full and the character was
+    /// dropped. Queued input moves into the FIFO as the guest makes room
+    /// (see `feed_uart_rx`). With `set_ctrl_c_nmi`, Ctrl-C raises an NMI
+    /// instead.
+    pub fn send_char(&mut self, ch: u8) -> bool {
+        if ch == CTRL_C && self.config.uart.ctrl_c_nmi {
+            self.raise_nmi();
+            return true;
+        }
+        if self.uart_input.len() >= self.config.uart.input_queue as usize {
+            return false;
+        }
+        self.uart_input.push_back(ch);
+        self.feed_uart_rx();
+        true
+    }
+
+    /// Queues characters for the UART receive FIFO, as a terminal paste
+    ///
+    /// Line endings are translated as `set_uart_line_ending` chose, and a
+    /// translated line ending is queued whole or not at all. Returns how
+    /// many leading bytes of `data` were accepted; the rest didn't fit in
+    /// the input queue and should be sent again later.
+    pub fn send_bytes(&mut self, data: &[u8]) -> usize {
+        let ending = self.config.uart.line_ending.bytes();
+        let mut accepted = 0;
+        while let Some(&ch) = data.get(accepted) {
+            let (bytes, len) = match ending {
+                Some(ending) if ch == b'\r' && data.get(accepted + 1) == Some(&b'\n') => {
+                    (ending, 2)
+                }
+                Some(ending) if ch == b'\r' || ch == b'\n' => (ending, 1),
+                _ => (std::slice::from_ref(&data[accepted]), 1),
+            };
+            if self.uart_input.len() + bytes.len() > self.input_queue_capacity()
+                && !(ch == CTRL_C && self.config.uart.ctrl_c_nmi)
+            {
+                break;
+            }
+            for &byte in bytes {
+                self.send_char(byte);
+            }
+            accepted += len;
+        }
+        accepted
+    }
+
+    /// Returns the number of bytes waiting in the input queue
+    #[must_use]
+    pub fn input_queued(&self) -> usize {
+        self.uart_input.len()
+    }
+
+    /// Returns the input queue size in bytes
+    #[must_use]
+    pub const fn input_queue_capacity(&self) -> usize {
+        self.config.uart.input_queue as usize
+    }
+
+    /// Moves queued input into the UART RX FIFO while it has room
+    ///
+    /// With flow control, nothing moves while the guest deasserts RTS.
+    fn feed_uart_rx(&mut self) {
+        if self.uart_input.is_empty() {
+            return;
+        }
+        let mut uart = self.uart.lock().unwrap();
+        if self.config.uart.flow_control && !uart.rts() {
+            return;
+        }
+        let count = uart.rx_space().min(self.uart_input.len());
+        for ch in self.uart_input.drain(..count) {
+            uart.push_rx(ch);
+        }
+    }
+
+    /// Makes Ctrl-C from the terminal raise an NMI instead of reaching the
+    /// UART
+    pub const fn set_ctrl_c_nmi(&mut self, enabled: bool) {
+        self.config.uart.ctrl_c_nmi = enabled;
+    }
+
+    /// Sets how line endings in pasted text reach the UART (see
+    /// `send_bytes`)
+    pub const fn set_uart_line_ending(&mut self, ending: LineEnding) {
+        self.config.uart.line_ending = ending;
+    }
+
+    /// Sets the DIP switch positions (bit n = switch n + 1, 1 = ON)
+    ///
+    /// The ROM only looks at the switches when it boots, so a change
+    /// usually takes effect at the next reset.
+    pub fn set_dip_switch(&mut self, value: u8) {
+        self.config.dip_switch = value;
+        self.dipswitch.lock().unwrap().set(value);
+    }
+
+    /// Returns the DIP switch positions
+    #[must_use]
+    pub fn dip_switch(&self) -> u8 {
+        self.dipswitch.lock().unwrap().value()
+    }
+
+    /// Raises a non-maskable interrupt, like a monitor's break button
+    ///
+    /// The CPU takes a level 7 autovectored interrupt (vector 31) whatever
+    /// its interrupt mask, waking it from STOP. The stacked PC is where the
+    /// interrupted code resumes.
+    pub fn raise_nmi(&mut self) {
+        self.cpu.service_autovector_interrupt(7);
+    }
+
+    /// Requests an interrupt at `level` (1-7) as a peripheral would
+    ///
+    /// The request goes through the interrupt controller as its external
+    /// source, so the CPU's mask and the other sources' priorities apply as
+    /// usual. It autovectors unless `vector` is given. A deferred request
+    /// stays latched until the CPU takes it; only one can wait at a time.
+    pub fn inject_interrupt(&mut self, level: u8, vector: Option<u8>) -> InterruptOutcome {
+        if !self.intc.lock().unwrap().assert_external(level, vector) {
+            return InterruptOutcome::Dropped;
+        }
+        self.handle_interrupts();
+        if self.intc.lock().unwrap().external_pending() {
+            InterruptOutcome::Deferred
+        } else {
+            InterruptOutcome::Accepted
+        }
     }
 
     /// Receives a character from the UART transmit buffer (to terminal)
@@ -676,16 +2408,106 @@ pub fn set_button(&mut self, pressed: bool) {
     ///
     /// Returns true if an instruction was executed, false if halted.
     pub fn step(&mut self) -> bool {
+        self.step_at().0
+    }
+
+    /// Executes a single instruction and reports what it did
+    ///
+    /// The UART output the instruction produced is taken out of the output
+    /// buffer and returned with the result, so it isn't drained twice.
+    pub fn step_detailed(&mut self) -> StepResult {
+        let pc_before = self.pc();
+        let start_cycles = self.cycles();
+        let start_output = self.uart_output.len();
+        self.cpu.take_last_exception();
+        let (_, pc) = self.step_at();
+        StepResult {
+            pc_before,
+            pc_after: self.pc(),
+            opcode: self.peek_opcode(pc),
+            cycles: self.cycles() - start_cycles,
+            exception_vector: self.cpu.take_last_exception(),
+            halted: self.is_halted(),
+            uart_output: self
+                .uart_output
+                .split_off(start_output.min(self.uart_output.len())),
+            watches: self.watch_values(),
+        }
+    }
+
+    /// Executes a single instruction, returning whether one executed and
+    /// its address (past any interrupt taken first)
+    fn step_at(&mut self) -> (bool, u32) {
+        self.feed_uart_rx();
         self.handle_interrupts();
+        self.record_trace();
+        let pc = self.pc();
+        let start_cycles = self.cycles();
         let result = self.cpu.step();
+        if result {
+            self.instructions += 1;
+        }
+        let elapsed = self.cycles() - start_cycles;
+        self.profiler.record(pc, elapsed);
+        self.tick_peripherals(elapsed);
         // Auto-drain UART TX FIFO so ROM code doesn't hang waiting for THRE
         self.drain_uart_tx();
-        result
+        (result, pc)
+    }
+
+    /// Advances time-dependent peripherals by the given number of cycles
+    fn tick_peripherals(&mut self, cycles: u64) {
+        if cycles > 0 {
+            self.scheduler.advance(cycles);
+            for (_, device) in &self.clocked {
+                device.lock().unwrap().tick(cycles);
+            }
+
+            let action = self.watchdog.lock().unwrap().tick(cycles);
+            match action {
+                Some(WatchdogAction::Nmi) => self.raise_nmi(),
+                Some(WatchdogAction::Reset) => self.hardware_reset(true, false),
+                None => {}
+            }
+        }
+    }
+
+    /// Returns the number of cycles until a peripheral event could end a
+    /// STOP, or `None` if nothing is pending
+    ///
+    /// Each source's event is rescheduled from the device's current state.
+    /// With every interrupt masked only the watchdog can wake the CPU.
+    fn next_wakeup(&mut self) -> Option<u64> {
+        let masked = (self.cpu.sr() >> 8) & 0x7 == 7;
+        for (source, device) in &self.clocked {
+            match device.lock().unwrap().next_event() {
+                Some(delay) if !masked => self.scheduler.schedule(*source, delay),
+                _ => self.scheduler.cancel(*source),
+            }
+        }
+        match self.watchdog.lock().unwrap().cycles_until_timeout() {
+            Some(delay) => self.scheduler.schedule(EventSource::Watchdog, delay),
+            None => self.scheduler.cancel(EventSource::Watchdog),
+        }
+        let at = self.scheduler.next_event()?;
+        Some((at - self.scheduler.now()).max(1))
+    }
+
+    /// Returns the master cycle count
+    ///
+    /// Unlike `cycles`, this keeps counting across resets, and it includes
+    /// the cycles skipped while the CPU was stopped.
+    #[must_use]
+    pub const fn master_cycles(&self) -> u64 {
+        self.scheduler.now()
     }
 
     /// Drains the UART TX FIFO into the output buffer
     fn drain_uart_tx(&mut self) {
         while let Some(byte) = self.uart.lock().unwrap().pop_tx() {
+            if byte == 0x07 && self.config.uart.bell {
+                self.buzzer.lock().unwrap().bell();
+            }
             self.uart_output.push(byte);
         }
     }
@@ -701,38 +2523,434 @@ pub fn peek_output(&self) -> &[u8] {
     }
 
     /// Handles interrupt delivery from peripherals.
+    ///
+    /// Each source's request line is sampled into the interrupt controller,
+    /// which picks the highest-priority enabled source. That source is
+    /// serviced if its priority is above the CPU's IPL. The UART interrupt
+    /// is cleared once acknowledged; the other lines stay asserted until the
+    /// guest clears the condition at the device.
     fn handle_interrupts(&mut self) {
-        let uart_pending = self.uart.lock().unwrap().interrupt_pending();
-        if !uart_pending {
-            return;
+        let mut intc = self.intc.lock().unwrap();
+        if intc.take_acks() & (1 << intc::sources::UART) != 0 {
+            self.uart.lock().unwrap().clear_interrupt();
         }
 
-        // UART uses autovector level 1 on the target board
+        let lines = [
+            (
+                intc::sources::UART,
+                self.uart.lock().unwrap().interrupt_pending(),
+            ),
+            (
+                intc::sources::CF,
+                self.cfcard.lock().unwrap().interrupt_pending(),
+            ),
+            (
+                intc::sources::RTC,
+                self.rtc.lock().unwrap().interrupt_pending(),
+            ),
+            (
+                intc::sources::GPIO,
+                self.gpio.lock().unwrap().interrupt_pending(),
+            ),
+            (
+                intc::sources::TIMER,
+                self.timer.lock().unwrap().interrupt_pending(),
+            ),
+            (
+                intc::sources::SPI,
+                self.spi.lock().unwrap().interrupt_pending(),
+            ),
+            (
+                intc::sources::I2C,
+                self.i2c.lock().unwrap().interrupt_pending(),
+            ),
+        ];
+        let mask = lines.iter().fold(0u8, |mask, &(source, line)| {
+            mask | (u8::from(line) << source)
+        });
+        intc.set_lines(mask);
+
         let current_ipl = ((self.cpu.sr() >> 8) & 0x7) as u8;
-        if 1 > current_ipl {
-            self.cpu.service_autovector_interrupt(1);
+        let Some(request) = intc.request(current_ipl) else {
+            return;
+        };
+        if intc.acknowledge(request.source) {
             self.uart.lock().unwrap().clear_interrupt();
         }
-    }
+        drop(intc);
 
-    /// Runs until halted or for a maximum number of cycles
-    pub fn run(&mut self, max_cycles: u64) -> u64 {
-        let start_cycles = self.cycles();
-        while !self.is_halted() && (self.cycles() - start_cycles) < max_cycles {
-            self.handle_interrupts();
-            self.cpu.step();
-            self.drain_uart_tx();
+        match request.vector {
+            Some(vector) => self.cpu.service_vectored_interrupt(request.level, vector),
+            None => self.cpu.service_autovector_interrupt(request.level),
         }
-        self.cycles() - start_cycles
     }
 
-    /// Provides mutable access to the underlying CPU
-    pub const fn cpu_mut(&mut self) -> &mut Cpu {
-        &mut self.cpu
+    /// Runs until halted, a debugger stop, or for a maximum number of cycles
+    ///
+    /// Cycles are counted per step, as a watchdog reset restarts the CPU's
+    /// cycle counter. See `debugger` for how breakpoints and watchpoints
+    /// stop a run.
+    ///
+    /// While the CPU is stopped, the run skips ahead to the next peripheral
+    /// event (see `scheduler`) until an interrupt wakes it; a stop nothing
+    /// can end returns right away.
+    pub fn run(&mut self, max_cycles: u64) -> RunResult {
+        self.execute(
+            max_cycles,
+            u64::MAX,
+            DebugChecks::SkipFirst,
+            &mut Vec::new(),
+            0,
+        )
     }
 
-    /// Provides access to the underlying CPU
-    pub const fn cpu(&self) -> &Cpu {
+    /// Runs like `run`, as the continuation of an earlier run that used up
+    /// its budget: a breakpoint at the first instruction stops it too
+    ///
+    /// This lets a long run be split into slices without missing a
+    /// breakpoint that falls on a slice boundary.
+    pub fn run_continued(&mut self, max_cycles: u64) -> RunResult {
+        self.execute(max_cycles, u64::MAX, DebugChecks::All, &mut Vec::new(), 0)
+    }
+
+    /// Executes up to `count` instructions in one batch, stopping early on
+    /// a halt or (with `stop_on_breakpoint`) a breakpoint or watchpoint
+    ///
+    /// A CPU that is already stopped waits for the interrupt that wakes it,
+    /// as in `run`. The PCs of the first `trace_limit` instructions are
+    /// recorded, and the UART output the batch produced is drained.
+    pub fn step_n(
+        &mut self,
+        count: u64,
+        stop_on_breakpoint: bool,
+        trace_limit: usize,
+    ) -> StepBatch {
+        let start = self.uart_output.len();
+        let mut pcs = Vec::new();
+        let checks = if stop_on_breakpoint {
+            DebugChecks::SkipFirst
+        } else {
+            DebugChecks::None
+        };
+        let run = self.execute(u64::MAX, count, checks, &mut pcs, trace_limit);
+        StepBatch {
+            run,
+            pcs,
+            output: self
+                .uart_output
+                .split_off(start.min(self.uart_output.len())),
+        }
+    }
+
+    /// Executes the instruction at the PC, running a JSR, BSR or TRAP until
+    /// it returns to the next instruction
+    ///
+    /// A call has returned once execution reaches the instruction after it
+    /// with the stack pointer back at (or above) where it was, so a
+    /// recursive call to the same routine doesn't end it early. Breakpoints
+    /// and watchpoints in the callee stop it, as do halts, and
+    /// `max_cycles` stops it with `BudgetExhausted`; it reports
+    /// `StepsCompleted` when the call returns. The UART output produced is
+    /// drained, as in `step_n`.
+    pub fn step_over(&mut self, max_cycles: u64) -> StepBatch {
+        let pc = self.pc();
+        let Some(length) = self.peek_call(pc) else {
+            return self.run_until(max_cycles, |_, _| true);
+        };
+        let return_address = pc.wrapping_add(length) & ADDR_MASK;
+        let sp = self.registers().a(7);
+        self.run_until(max_cycles, |sbc, _| {
+            sbc.pc() == return_address && sbc.registers().a(7) >= sp
+        })
+    }
+
+    /// Runs until the current subroutine returns to its caller, stopping
+    /// like `step_over`
+    ///
+    /// The routine has returned once an RTS, RTR or RTE leaves the stack
+    /// pointer above where it was when the step began; returns from
+    /// routines it calls (and from interrupt handlers) leave it no higher.
+    pub fn step_out(&mut self, max_cycles: u64) -> StepBatch {
+        /// RTE, RTS and RTR
+        const RETURNS: [u16; 3] = [0x4E73, 0x4E75, 0x4E77];
+        let sp = self.registers().a(7);
+        self.run_until(max_cycles, |sbc, opcode| {
+            RETURNS.contains(&opcode) && sbc.registers().a(7) > sp
+        })
+    }
+
+    /// Executes one instruction at a time until `done` holds after one
+    ///
+    /// `done` gets the machine and the opcode at the PC before the
+    /// instruction. Breakpoints count from the second instruction, as in
+    /// `run`.
+    fn run_until(
+        &mut self,
+        max_cycles: u64,
+        mut done: impl FnMut(&Self, u16) -> bool,
+    ) -> StepBatch {
+        let start = self.uart_output.len();
+        let mut total = RunResult {
+            instructions: 0,
+            cycles: 0,
+            stop: StopReason::BudgetExhausted,
+            pc: self.pc(),
+        };
+        let mut checks = DebugChecks::SkipFirst;
+        while total.cycles < max_cycles {
+            let opcode = self.peek_opcode(self.pc());
+            let run = self.execute(max_cycles - total.cycles, 1, checks, &mut Vec::new(), 0);
+            checks = DebugChecks::All;
+            total.instructions += run.instructions;
+            total.cycles += run.cycles;
+            total.stop = run.stop;
+            total.pc = run.pc;
+            if run.stop != StopReason::StepsCompleted || done(self, opcode) {
+                break;
+            }
+            total.stop = StopReason::BudgetExhausted;
+        }
+        StepBatch {
+            run: total,
+            pcs: Vec::new(),
+            output: self
+                .uart_output
+                .split_off(start.min(self.uart_output.len())),
+        }
+    }
+
+    /// Shared loop behind `run` and `step_n`
+    ///
+    /// Stops after `max_cycles` or `max_instructions`, checking breakpoints
+    /// and watchpoints as `checks` says. Instructions are counted
+    /// toward `max_instructions` only when they execute, and a limited
+    /// batch also stops as soon as an instruction halts the CPU.
+    fn execute(
+        &mut self,
+        max_cycles: u64,
+        max_instructions: u64,
+        checks: DebugChecks,
+        pcs: &mut Vec<u32>,
+        trace_limit: usize,
+    ) -> RunResult {
+        let watching = checks != DebugChecks::None && self.debugger.watching();
+        self.bus.watch_active.store(watching, Ordering::Relaxed);
+        let mut instructions = 0;
+        let mut executed = 0;
+
+        let stop = loop {
+            self.feed_uart_rx();
+            if self.is_halted() {
+                // An interrupt the CPU accepts ends a STOP
+                self.cpu.take_last_exception();
+                let pc = self.pc();
+                self.handle_interrupts();
+                if let Some(stop) = self.check_uninitialized_vector(pc) {
+                    break stop;
+                }
+            }
+            if self.is_halted() {
+                // Skip ahead to the next peripheral event instead of idling
+                let Some(delay) = self.next_wakeup() else {
+                    break self.halt_reason();
+                };
+                if executed >= max_cycles {
+                    break StopReason::BudgetExhausted;
+                }
+                let delay = delay.min(max_cycles - executed);
+                executed += delay;
+                self.tick_peripherals(delay);
+                continue;
+            }
+            if executed >= max_cycles {
+                break StopReason::BudgetExhausted;
+            }
+            if instructions >= max_instructions {
+                break StopReason::StepsCompleted;
+            }
+            if watching {
+                self.bus.access_log.lock().unwrap().clear();
+            }
+
+            let pc = self.pc();
+            self.cpu.take_last_exception();
+            self.handle_interrupts();
+            if let Some(stop) = self.check_uninitialized_vector(pc) {
+                break stop;
+            }
+            // Resuming from a breakpoint must not stop on it again
+            let check_breakpoints = match checks {
+                DebugChecks::None => false,
+                DebugChecks::SkipFirst => instructions > 0,
+                DebugChecks::All => true,
+            };
+            if check_breakpoints {
+                let peek = |addr| peek_byte(&self.rom_data, &self.cpu.memory, &self.bus, addr);
+                let registers = &self.cpu.registers;
+                if let Some(stop) = self
+                    .debugger
+                    .check_breakpoint(registers.pc, registers, &peek)
+                {
+                    break stop;
+                }
+            }
+
+            let pc = self.pc();
+            if pcs.len() < trace_limit {
+                pcs.push(pc);
+            }
+            self.record_trace();
+            let step_start = self.cycles();
+            self.cpu.step();
+            instructions += 1;
+            let elapsed = self.cycles() - step_start;
+            executed += elapsed;
+            self.profiler.record(pc, elapsed);
+            self.tick_peripherals(elapsed);
+            self.drain_uart_tx();
+
+            if let Some(stop) = self.check_uninitialized_vector(pc) {
+                break stop;
+            }
+            if watching {
+                let accesses = std::mem::take(&mut *self.bus.access_log.lock().unwrap());
+                if let Some(stop) = self.debugger.check_watchpoints(&accesses) {
+                    break stop;
+                }
+            }
+            if max_instructions != u64::MAX && self.is_halted() {
+                break self.halt_reason();
+            }
+        };
+
+        self.bus.watch_active.store(false, Ordering::Relaxed);
+        self.instructions += instructions;
+        self.last_stop = Some(stop);
+        RunResult {
+            instructions,
+            cycles: executed,
+            stop,
+            pc: self.pc(),
+        }
+    }
+
+    /// Tells a STOP in the Exit stub apart from any other halt
+    const fn halt_reason(&self) -> StopReason {
+        // STOP #$2700 is the whole Exit stub, so the PC ends up right after it
+        if self.app_stubs && self.pc() == TRAP_STUB_BASE + 4 {
+            StopReason::Exit {
+                code: self.cpu.registers.d[0],
+            }
+        } else {
+            StopReason::Halted
+        }
+    }
+
+    /// Checks whether the exception taken since the last check (if any)
+    /// went through an uninitialized vector
+    fn check_uninitialized_vector(&mut self, pc: u32) -> Option<StopReason> {
+        let vector = self.cpu.take_last_exception()?;
+        // Bypasses the bus so the lookup doesn't count as a watched access
+        let handler = self.cpu.memory.read_long_unchecked(u32::from(vector) * 4);
+        (handler == 0 || handler == 0xFFFF_FFFF)
+            .then_some(StopReason::UninitializedVector { vector, pc })
+    }
+
+    /// Returns the breakpoint and watchpoint tables
+    #[must_use]
+    pub const fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Evaluates the debugger's watch expressions against the machine
+    #[must_use]
+    pub fn watch_values(&self) -> Vec<WatchValue> {
+        let peek = |addr| peek_byte(&self.rom_data, &self.cpu.memory, &self.bus, addr);
+        self.debugger.watch_values(&self.cpu.registers, &peek)
+    }
+
+    /// Evaluates an expression against the machine (see `expression`)
+    ///
+    /// # Errors
+    /// Fails like `Expression::evaluate`.
+    pub fn evaluate(&self, expression: &Expression) -> Result<Evaluation, String> {
+        let peek = |addr| peek_byte(&self.rom_data, &self.cpu.memory, &self.bus, addr);
+        self.debugger
+            .evaluate(expression, &self.cpu.registers, &peek)
+    }
+
+    /// Returns the breakpoint and watchpoint tables for editing
+    pub const fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Enables or disables the terminal bell (BEL sent to the UART beeps)
+    pub const fn set_uart_bell(&mut self, enabled: bool) {
+        self.config.uart.bell = enabled;
+    }
+
+    /// Returns and clears the queued buzzer tone events
+    pub fn take_buzzer_events(&mut self) -> Vec<ToneEvent> {
+        self.buzzer.lock().unwrap().take_events()
+    }
+
+    /// Renders `ms` milliseconds of the buzzer's current output
+    ///
+    /// The samples are a square wave at [`crate::buzzer::SAMPLE_RATE`].
+    #[must_use]
+    pub fn render_audio(&self, ms: u32) -> Vec<i16> {
+        self.buzzer.lock().unwrap().render(ms)
+    }
+
+    /// Attaches a device to an SPI chip-select line (0-7)
+    pub fn attach_spi_device(&mut self, cs: usize, device: Box<dyn SpiDevice>) {
+        self.spi.lock().unwrap().attach(cs, device);
+    }
+
+    /// Attaches a device to the I2C bus at a 7-bit address
+    pub fn attach_i2c_device(&mut self, address: u8, device: Box<dyn I2cDevice>) {
+        self.i2c.lock().unwrap().attach(address, device);
+    }
+
+    /// Returns a reference to the interrupt controller
+    pub fn intc(&self) -> Arc<Mutex<InterruptController>> {
+        Arc::clone(&self.intc)
+    }
+
+    /// Replaces the RTC's host clock source (e.g. with a manual clock)
+    pub fn set_rtc_clock(&mut self, clock: Arc<dyn ClockSource>) {
+        self.rtc.lock().unwrap().set_clock(clock);
+    }
+
+    /// Drives a GPIO input pin from the host
+    pub fn gpio_set_input(&mut self, pin: u8, level: bool) {
+        self.gpio.lock().unwrap().set_input(pin, level);
+    }
+
+    /// Returns the levels of the GPIO output pins
+    #[must_use]
+    pub fn gpio_output_state(&self) -> u8 {
+        self.gpio.lock().unwrap().output_state()
+    }
+
+    /// Returns the full GPIO port state
+    #[must_use]
+    pub fn gpio_state(&self) -> GpioState {
+        self.gpio.lock().unwrap().state()
+    }
+
+    /// Returns and clears the queued GPIO output change events
+    pub fn take_gpio_events(&mut self) -> Vec<GpioState> {
+        self.gpio.lock().unwrap().take_events()
+    }
+
+    /// Provides mutable access to the underlying CPU
+    pub const fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    /// Provides access to the underlying CPU
+    pub const fn cpu(&self) -> &Cpu {
         &self.cpu
     }
 
@@ -747,6 +2965,15 @@ pub const fn registers_mut(&mut self) -> &mut crate::registers::RegisterFile {
     }
 }
 
+impl Drop for Sbc {
+    fn drop(&mut self) {
+        // Don't lose guest writes still sitting in the CF sector cache
+        if let Ok(mut cf) = self.cfcard.lock() {
+            let _ = cf.flush();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -774,6 +3001,169 @@ fn test_sbc_reset() {
         assert_eq!(sbc.pc(), 0x00000008);
     }
 
+    #[test]
+    fn test_sbc_reset_executes_from_vector_table() {
+        // Power-on starts from the embedded ROM's vectors
+        let sbc = Sbc::new();
+        let pc = u32::from_be_bytes(EMBEDDED_ROM[4..8].try_into().unwrap());
+        assert_eq!(sbc.pc(), pc);
+
+        // SSP = $00E80000, PC = $00000200: MOVEQ #1,D0
+        let mut sbc = Sbc::new();
+        let mut rom = vec![0u8; 0x204];
+        rom[0..4].copy_from_slice(&0x00E8_0000u32.to_be_bytes());
+        rom[4..8].copy_from_slice(&0x0000_0200u32.to_be_bytes());
+        rom[0x200..0x202].copy_from_slice(&[0x70, 0x01]);
+        sbc.load_rom(&rom);
+        sbc.reset();
+
+        assert_eq!(sbc.sr(), 0x2700);
+        assert_eq!(sbc.cpu.registers.get_ssp(), 0x00E8_0000);
+        assert!(sbc.step());
+        assert_eq!(sbc.cpu.registers.d[0], 1);
+        assert_eq!(sbc.pc(), 0x0000_0202);
+    }
+
+    #[test]
+    fn test_sbc_reset_vector_override() {
+        let mut sbc = Sbc::new();
+        sbc.set_reset_vectors(Some(ResetVectors {
+            ssp: 0x00C8_0000,
+            pc: APP_START,
+        }));
+        sbc.reset();
+        assert_eq!(sbc.cpu.registers.sp(), 0x00C8_0000);
+        assert_eq!(sbc.pc(), APP_START);
+
+        sbc.set_reset_vectors(None);
+        sbc.reset();
+        let pc = u32::from_be_bytes(EMBEDDED_ROM[4..8].try_into().unwrap());
+        assert_eq!(sbc.pc(), pc);
+    }
+
+    #[test]
+    fn test_sbc_reset_modes() {
+        let ssp = u32::from_be_bytes(EMBEDDED_ROM[0..4].try_into().unwrap());
+        let pc = u32::from_be_bytes(EMBEDDED_ROM[4..8].try_into().unwrap());
+        let setup = |sbc: &mut Sbc| {
+            start_program(sbc, "        moveq   #5,d0\n        nop\n");
+            sbc.step();
+            sbc.write_memory(0x00E0_2000, b"kept", false);
+            sbc.debugger_mut().add_breakpoint(APP_START + 2);
+            sbc.load_cf_bytes(&[0; 512]);
+        };
+        let vectors_loaded = |sbc: &Sbc| (sbc.pc(), sbc.registers().sp(), sbc.sr());
+        let ram = |sbc: &Sbc| sbc.cpu.memory.read_long(0x00E0_2000).unwrap().to_be_bytes();
+
+        // CPU only: the vectors are fetched again and nothing else changes
+        let mut sbc = Sbc::new();
+        setup(&mut sbc);
+        sbc.reset_with(ResetMode::CpuOnly).unwrap();
+        assert_eq!(vectors_loaded(&sbc), (pc, ssp, 0x2700));
+        assert_eq!(sbc.registers().d(0), 5);
+        assert_eq!(&ram(&sbc), b"kept");
+        assert_eq!(sbc.debugger().breakpoints().len(), 1);
+
+        // Warm: the CPU and peripherals reset, RAM and attachments stay
+        let mut sbc = Sbc::new();
+        setup(&mut sbc);
+        sbc.reset_with(ResetMode::Warm).unwrap();
+        assert_eq!(vectors_loaded(&sbc), (pc, ssp, 0x2700));
+        assert_eq!(sbc.registers().d(0), 0);
+        assert_eq!(&ram(&sbc), b"kept");
+        assert_eq!(sbc.debugger().breakpoints().len(), 1);
+        assert!(sbc.cf_inserted());
+
+        // Cold: a power cycle
+        let mut sbc = Sbc::new();
+        setup(&mut sbc);
+        sbc.reset_with(ResetMode::Cold).unwrap();
+        assert_eq!(vectors_loaded(&sbc), (pc, ssp, 0x2700));
+        assert_eq!(ram(&sbc), [0; 4]);
+        assert!(sbc.debugger().breakpoints().is_empty());
+        assert!(!sbc.cf_inserted());
+    }
+
+    #[test]
+    fn test_sbc_boots_rom_from_file() {
+        // SSP = $00F00000, PC = $00000400:
+        //   MOVE.B #'A',$A00000
+        //   STOP   #$2700
+        let mut rom = vec![0u8; 0x40C];
+        rom[0..4].copy_from_slice(&0x00F0_0000u32.to_be_bytes());
+        rom[4..8].copy_from_slice(&0x0000_0400u32.to_be_bytes());
+        rom[0x400..].copy_from_slice(&[
+            0x13, 0xFC, 0x00, 0x41, 0x00, 0xA0, 0x00, 0x00, 0x4E, 0x72, 0x27, 0x00,
+        ]);
+        let path = std::env::temp_dir().join(format!("flux32-sbc-rom-{}.bin", std::process::id()));
+        std::fs::write(&path, &rom).unwrap();
+
+        let mut sbc = Sbc::new_with_rom(&path).unwrap();
+        assert_eq!(sbc.cpu.registers.sp(), 0x00F0_0000);
+        assert_eq!(sbc.pc(), 0x0000_0400);
+        sbc.run(1000);
+        assert!(sbc.is_halted());
+        assert_eq!(sbc.drain_output(), b"A");
+
+        std::fs::write(&path, vec![0u8; ROM_SIZE + 1]).unwrap();
+        assert_eq!(
+            Sbc::new_with_rom(&path).err(),
+            Some(RomError::TooLarge {
+                size: ROM_SIZE + 1,
+                max: ROM_SIZE,
+            })
+        );
+        std::fs::write(&path, [0u8; 6]).unwrap();
+        assert_eq!(
+            Sbc::new_with_rom(&path).err(),
+            Some(RomError::TooSmall { size: 6 })
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(Sbc::new_with_rom(&path), Err(RomError::Io { .. })));
+    }
+
+    #[test]
+    fn test_sbc_rom_reads_dip_switch_at_boot() {
+        // Switch 1 selects the self-test boot path
+        const ROM: &str = r#"
+        dc.l    $00F00000
+        dc.l    start
+normal: dc.b    "BOOT",0
+test:   dc.b    "SELF-TEST",0
+        even
+paths:  dc.l    normal,test
+send:   move.b  d1,$A00000
+print:  move.b  (a0)+,d1
+        bne     send
+        rts
+start:  moveq   #0,d0
+        move.b  $800120,d0
+        andi.b  #1,d0
+        lsl.w   #2,d0
+        lea.l   paths,a1
+        movea.l (a1,d0.w),a0
+        bsr     print
+        stop    #$2700
+"#;
+        let mut asm = crate::assembler::Assembler::new();
+        let rom = asm.assemble_source(ROM, Path::new("<test>")).unwrap();
+
+        let mut sbc = Sbc::new();
+        sbc.load_rom(&rom);
+        for (switches, output) in [(0x00, &b"BOOT"[..]), (0xA5, b"SELF-TEST")] {
+            sbc.set_dip_switch(switches);
+            sbc.reset();
+            sbc.run(10_000);
+            assert_eq!(sbc.drain_output(), output);
+        }
+
+        // Reads have no side effects and writes are ignored
+        let _ = sbc.cpu.memory.write_byte(0x0080_0120, 0x00);
+        assert_eq!(sbc.cpu.memory.read_byte(0x0080_0120).unwrap(), 0xA5);
+        assert_eq!(sbc.cpu.memory.read_byte(0x0080_0120).unwrap(), 0xA5);
+        assert_eq!(sbc.config().dip_switch, 0xA5);
+    }
+
     #[test]
     fn test_sbc_rom_mirroring() {
         let mut sbc = Sbc::new();
@@ -820,6 +3210,169 @@ fn test_sbc_uart_rx() {
         assert!(lsr & 0x01 != 0); // Data ready bit
     }
 
+    /// Echoes every received byte after a delay, much slower than the host
+    /// sends
+    const SLOW_ECHO: &str = "
+        lea.l   $A00000,a0
+        move.b  #$02,8(a0)
+loop:   btst    #0,10(a0)
+        beq     loop
+        move.b  (a0),d0
+        move.w  #40,d1
+.delay: dbra    d1,.delay
+        move.b  d0,(a0)
+        bra     loop
+";
+
+    #[test]
+    fn test_sbc_write_stack_and_sr_then_run() {
+        use crate::registers::Register;
+
+        let mut sbc = Sbc::new();
+        start_program(
+            &mut sbc,
+            "
+        move.l  #$CAFEF00D,-(sp)
+        move.w  sr,d1
+        moveq   #0,d0
+        trap    #0
+",
+        );
+        // Drop to user mode on a fresh user stack
+        let regs = sbc.registers_mut();
+        regs.write(Register::Usp, 0x00E4_0000).unwrap();
+        regs.write(Register::Sr, 0x0000).unwrap();
+        assert_eq!(regs.sp(), 0x00E4_0000);
+
+        let result = sbc.run(10_000);
+        assert_eq!(result.stop, StopReason::Exit { code: 0 });
+        assert_eq!(sbc.registers().d(1) & 0x2000, 0);
+        assert_eq!(sbc.registers().usp(), 0x00E3_FFFC);
+        assert_eq!(sbc.cpu.memory.read_long(0x00E3_FFFC).unwrap(), 0xCAFE_F00D);
+    }
+
+    #[test]
+    fn test_sbc_paste_into_slow_echo() {
+        let mut sbc = Sbc::new();
+        start_program(&mut sbc, SLOW_ECHO);
+        let paste: Vec<u8> = (0..4096u32).map(|i| b' ' + (i % 95) as u8).collect();
+        assert_eq!(sbc.send_bytes(&paste), paste.len());
+        assert!(sbc.input_queued() > 0);
+
+        let mut echoed = Vec::new();
+        for _ in 0..100 {
+            sbc.run(100_000);
+            echoed.extend(sbc.drain_output());
+        }
+        assert_eq!(sbc.input_queued(), 0);
+        assert_eq!(echoed, paste);
+    }
+
+    #[test]
+    fn test_sbc_input_queue_limit_and_flow_control() {
+        let mut config = SbcConfig::default();
+        config.uart.input_queue = 32;
+        config.uart.flow_control = true;
+        let mut sbc = Sbc::new_with_config(config).unwrap();
+
+        // RTS is deasserted after reset, so nothing reaches the FIFO
+        assert_eq!(sbc.send_bytes(&[b'x'; 40]), 32);
+        assert!(!sbc.send_char(b'y'));
+        assert_eq!(sbc.input_queued(), 32);
+        assert_eq!(sbc.cpu.memory.read_byte(0x00A0_000A).unwrap() & 0x01, 0);
+
+        // Asserting RTS lets a FIFO's worth through
+        let _ = sbc.cpu.memory.write_byte(0x00A0_0008, 0x02);
+        sbc.step();
+        assert_eq!(sbc.input_queued(), 16);
+        assert!(sbc.send_char(b'y'));
+    }
+
+    /// Collects a line until CR, ignoring LF, then prints it back after a
+    /// `>` prompt
+    const LINE_EDITOR: &str = "
+        lea.l   $A00000,a0
+        move.b  #$02,8(a0)
+line:   lea.l   $E02000,a1
+loop:   btst    #0,10(a0)
+        beq     loop
+        move.b  (a0),d0
+        cmp.b   #13,d0
+        beq     enter
+        cmp.b   #10,d0
+        beq     loop
+        move.b  d0,(a1)+
+        bra     loop
+enter:  move.l  a1,d2
+        lea.l   $E02000,a1
+        moveq   #62,d0
+        bsr     putc
+print:  cmp.l   a1,d2
+        beq     done
+        move.b  (a1)+,d0
+        bsr     putc
+        bra     print
+done:   moveq   #13,d0
+        bsr     putc
+        moveq   #10,d0
+        bsr     putc
+        bra     line
+putc:   btst    #5,10(a0)
+        beq     putc
+        move.b  d0,(a0)
+        rts
+";
+
+    #[test]
+    fn test_sbc_paste_line_endings() {
+        let script = "10 PRINT \"HI\"\n20 GOTO 10\r\nLIST\rRUN\n";
+
+        // Every line reaches the line editor as one Enter press
+        let mut sbc = Sbc::new();
+        sbc.set_uart_line_ending(LineEnding::Cr);
+        start_program(&mut sbc, LINE_EDITOR);
+        assert_eq!(sbc.send_bytes(script.as_bytes()), script.len());
+        let mut output = Vec::new();
+        for _ in 0..20 {
+            sbc.run(100_000);
+            output.extend(sbc.drain_output());
+        }
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            ">10 PRINT \"HI\"\r\n>20 GOTO 10\r\n>LIST\r\n>RUN\r\n"
+        );
+
+        // The guest sees exactly the configured line endings
+        let mut sbc = Sbc::new();
+        sbc.set_uart_line_ending(LineEnding::CrLf);
+        start_program(&mut sbc, SLOW_ECHO);
+        assert_eq!(sbc.send_bytes(script.as_bytes()), script.len());
+        let mut echoed = Vec::new();
+        for _ in 0..20 {
+            sbc.run(100_000);
+            echoed.extend(sbc.drain_output());
+        }
+        assert_eq!(echoed, b"10 PRINT \"HI\"\r\n20 GOTO 10\r\nLIST\r\nRUN\r\n");
+    }
+
+    #[test]
+    fn test_sbc_paste_queues_line_endings_whole() {
+        let mut config = SbcConfig::default();
+        config.uart.input_queue = 4;
+        config.uart.flow_control = true;
+        config.uart.line_ending = LineEnding::CrLf;
+        let mut sbc = Sbc::new_with_config(config.clone()).unwrap();
+
+        // "ab" and CR LF fill the queue, so "c" is the first byte refused
+        assert_eq!(sbc.send_bytes(b"ab\ncd"), 3);
+        assert_eq!(sbc.input_queued(), 4);
+
+        // A CR LF that doesn't fit is refused whole
+        let mut sbc = Sbc::new_with_config(config).unwrap();
+        assert_eq!(sbc.send_bytes(b"abc\r\nd"), 3);
+        assert_eq!(sbc.input_queued(), 3);
+    }
+
     #[test]
     fn test_sbc_cf_card() {
         let mut sbc = Sbc::new();
@@ -853,84 +3406,2280 @@ fn test_sbc_load_app() {
 
         // Load a simple app
         let app = [0x70, 0x2A]; // MOVEQ #42, D0
-        sbc.load_app(&app);
+        sbc.load_app(&app, None).unwrap();
 
         // Verify it's in RAM at $E00100
         assert_eq!(sbc.cpu.memory.read_word(APP_START).unwrap(), 0x702A);
     }
 
     #[test]
-    fn test_sbc_forbidden_region_reads_open_bus() {
-        let mut sbc = Sbc::new();
-        // $100000-$1FFFFF is ROM + CF overlap (forbidden).
-        let _ = sbc.cpu.memory.write_byte(0x100000, 0xAA);
-        assert_eq!(sbc.cpu.memory.read_byte(0x100000).unwrap(), 0xFF);
+    fn test_sbc_load_app_at_address() {
+        // MOVEQ #42,D0 / STOP #$2700
+        let app = [0x70, 0x2A, 0x4E, 0x72, 0x27, 0x00];
+        for addr in [APP_START, 0x00C0_1000] {
+            let mut sbc = Sbc::new();
+            sbc.load_app(&app, Some(addr)).unwrap();
+            sbc.run_app(Some(addr));
+            sbc.run(100);
+            assert!(sbc.is_halted());
+            assert_eq!(sbc.pc(), addr + 6);
+            assert_eq!(sbc.cpu.registers.d[0], 42);
+        }
     }
 
     #[test]
-    fn test_sbc_button_msr_polarity() {
+    fn test_sbc_pc_relative_app_runs_anywhere() {
+        let source = "
+        org     $E00100
+        move.w  value,d0
+        lea     table,a0
+        add.w   (a0),d0
+        jsr     double
+        stop    #$2700
+double: add.w   d0,d0
+        rts
+value:  dc.w    40
+table:  dc.w    2
+";
+        let shifted = 0x00C0_1000;
+        let run_at = |pc_relative: bool, addr: u32| {
+            let mut asm = crate::assembler::Assembler::new();
+            asm.pc_relative = pc_relative;
+            let app = asm.assemble_checked(source, Path::new("<test>")).unwrap();
+            let mut sbc = Sbc::new();
+            sbc.load_app(&app, Some(addr)).unwrap();
+            sbc.run_app(Some(addr));
+            sbc.run(10_000);
+            sbc.is_halted() && sbc.cpu.registers.d[0] as u16 == 84
+        };
+        assert!(run_at(false, APP_START));
+        assert!(run_at(true, APP_START));
+        assert!(run_at(true, shifted));
+        // The absolute version reads and calls where it was assembled for
+        assert!(!run_at(false, shifted));
+    }
+
+    /// Assembles a program and starts it as an app without running it
+    fn start_program(sbc: &mut Sbc, source: &str) {
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(source, Path::new("<test>")).unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+    }
+
+    #[test]
+    fn test_sbc_run_reports_exit_code() {
         let mut sbc = Sbc::new();
-        sbc.set_button(true);
-        let msr = sbc.cpu.memory.read_byte(0xA0000C).unwrap();
-        assert!(msr & 0x40 != 0);
+        start_program(&mut sbc, "\n        moveq   #3,d0\n        trap    #0\n");
+        let result = sbc.run(1_000_000);
+        assert_eq!(result.stop, StopReason::Exit { code: 3 });
+        // MOVEQ, TRAP and the STOP in the Exit stub
+        assert_eq!(result.instructions, 3);
+        assert_eq!(result.pc, TRAP_STUB_BASE + 4);
+
+        // A STOP elsewhere is a plain halt; running again stops right away
+        start_program(&mut sbc, "\n        stop    #$2700\n");
+        assert_eq!(sbc.run(1_000_000).stop, StopReason::Halted);
+        let result = sbc.run(1_000_000);
+        assert_eq!((result.stop, result.instructions), (StopReason::Halted, 0));
+
+        start_program(&mut sbc, "\nloop:   bra     loop\n");
+        let result = sbc.run(100);
+        assert_eq!(result.stop, StopReason::BudgetExhausted);
+        assert!(result.cycles >= 100);
     }
 
     #[test]
-    fn test_sbc_break_inserts_zero_and_sets_lsr() {
+    fn test_sbc_run_stops_at_breakpoint() {
         let mut sbc = Sbc::new();
-        sbc.send_break();
-        let lsr = sbc.cpu.memory.read_byte(0xA0000A).unwrap();
-        assert!(lsr & 0x10 != 0);
-        let byte = sbc.cpu.memory.read_byte(0xA00000).unwrap();
-        assert_eq!(byte, 0);
+        start_program(
+            &mut sbc,
+            "
+        moveq   #0,d0
+.loop:  addq.l  #1,d0
+        cmp.l   #5,d0
+        bne     .loop
+        stop    #$2700
+",
+        );
+        let id = sbc.debugger_mut().add_breakpoint(APP_START + 2);
+        let stop = StopReason::Breakpoint {
+            id,
+            address: APP_START + 2,
+        };
+
+        let result = sbc.run(1_000_000);
+        assert_eq!(result.stop, stop);
+        assert_eq!((result.instructions, result.pc), (1, APP_START + 2));
+        assert_eq!(sbc.cpu.registers.d[0], 0);
+
+        // Running again executes the instruction at the breakpoint
+        assert_eq!(sbc.run(1_000_000).stop, stop);
+        assert_eq!(sbc.cpu.registers.d[0], 1);
+        assert_eq!(sbc.debugger().breakpoints()[0].hits, 2);
+
+        sbc.debugger_mut().set_enabled(id, false);
+        assert_eq!(sbc.run(1_000_000).stop, StopReason::Halted);
+        assert_eq!(sbc.cpu.registers.d[0], 5);
     }
 
     #[test]
-    fn test_sbc_rom_compatibility() {
+    fn test_sbc_run_continued_checks_first_instruction() {
         let mut sbc = Sbc::new();
+        start_program(&mut sbc, "\nloop:   addq.l  #1,d0\n        bra     loop\n");
+        sbc.run(100);
+        let address = sbc.pc();
+        let id = sbc.debugger_mut().add_breakpoint(address);
+        let stop = StopReason::Breakpoint { id, address };
 
-        // Simulate loading a compatible ROM with proper reset vectors
-        // Initial SSP = 0x00F00000 (end of RAM)
-        // Initial PC = 0x000000C0 (ROM start)
-        let mut rom = vec![0u8; ROM_SIZE];
-        rom[0..4].copy_from_slice(&[0x00, 0xF0, 0x00, 0x00]); // Initial SSP
-        rom[4..8].copy_from_slice(&[0x00, 0x00, 0x00, 0xC0]); // Initial PC
+        // A continued run stops before executing anything
+        let result = sbc.run_continued(100);
+        assert_eq!((result.stop, result.instructions), (stop, 0));
+        // A fresh run resumes past the breakpoint, coming back to it
+        let result = sbc.run(100);
+        assert_eq!((result.stop, result.instructions), (stop, 2));
+    }
 
-        // Write a simple program at 0xC0 that loads D0 and halts
-        // MOVEQ #42,D0 (quick move, single instruction)
-        rom[0xC0] = 0x70; // MOVEQ
-        rom[0xC1] = 0x2A; // #42
+    #[test]
+    fn test_sbc_conditional_breakpoints() {
+        let mut sbc = Sbc::new();
+        start_program(
+            &mut sbc,
+            "
+        moveq   #0,d0
+loop:   addq.l  #1,d0
+        move.w  d0,$E02000
+        cmp.l   #10,d0
+        bne     loop
+        stop    #$2700
+",
+        );
+        sbc.debugger_mut()
+            .set_symbols([("count".to_string(), 0x00E0_2000)]);
+        let check = APP_START + 10;
+        let stop = |id| StopReason::Breakpoint { id, address: check };
 
-        // STOP #$2700 (halt CPU with interrupts masked)
-        rom[0xC2] = 0x4E; // STOP
-        rom[0xC3] = 0x72;
-        rom[0xC4] = 0x27; // SR value
-        rom[0xC5] = 0x00;
+        // Stops only where the condition holds, reading memory via a label
+        let condition = crate::expression::Expression::parse("word(count) = 4").unwrap();
+        let id = sbc
+            .debugger_mut()
+            .add_conditional_breakpoint(check, Some(condition), 0);
+        assert_eq!(sbc.run(1_000_000).stop, stop(id));
+        assert_eq!(sbc.cpu.registers.d[0], 4);
+        assert_eq!(sbc.debugger().breakpoints()[0].hits, 1);
+        assert!(sbc.debugger_mut().remove(id));
 
-        sbc.load_rom(&rom);
-        sbc.reset();
+        // A skip count lets hits pass first
+        let condition = crate::expression::Expression::parse("d0 >= 6").unwrap();
+        let id = sbc
+            .debugger_mut()
+            .add_conditional_breakpoint(check, Some(condition), 2);
+        assert_eq!(sbc.run(1_000_000).stop, stop(id));
+        assert_eq!(sbc.cpu.registers.d[0], 8);
+        assert_eq!(sbc.run(1_000_000).stop, stop(id));
+        assert_eq!(sbc.cpu.registers.d[0], 9);
+        assert_eq!(sbc.debugger().breakpoints()[0].hits, 4);
 
-        // Verify reset vectors loaded correctly
-        assert_eq!(sbc.pc(), 0x000000C0);
-        assert_eq!(sbc.registers().sp(), 0x00F00000);
-        assert_eq!(sbc.sr(), 0x2700); // Supervisor mode, interrupts masked
+        sbc.debugger_mut().set_enabled(id, false);
+        assert_eq!(sbc.run(1_000_000).stop, StopReason::Halted);
+        assert_eq!(sbc.cpu.registers.d[0], 10);
+    }
 
-        // Execute instructions
-        let mut executed = 0;
-        for _ in 0..10 {
-            if !sbc.step() {
-                break;
+    #[test]
+    fn test_sbc_run_stops_on_watchpoint() {
+        let mut sbc = Sbc::new();
+        start_program(
+            &mut sbc,
+            "
+        move.l  $E02000,d1
+        move.w  #1,$E02002
+        stop    #$2700
+",
+        );
+        let id =
+            sbc.debugger_mut()
+                .add_watchpoint(0x00E0_2000, 4, crate::debugger::WatchAccess::Write);
+
+        let result = sbc.run(1_000_000);
+        assert_eq!(
+            result.stop,
+            StopReason::Watchpoint {
+                id,
+                address: 0x00E0_2002,
+                access: crate::debugger::WatchAccess::Write,
             }
-            executed += 1;
-        }
+        );
+        assert_eq!(result.instructions, 2);
+        assert_eq!(sbc.run(1_000_000).stop, StopReason::Halted);
+    }
 
-        // Should have executed at least MOVEQ
-        assert!(executed >= 1);
+    #[test]
+    fn test_sbc_status_tracks_next_instruction() {
+        let mut sbc = Sbc::new();
+        start_program(
+            &mut sbc,
+            "
+        moveq   #1,d0
+        addq.l  #2,d0
+        move.l  d0,d1
+        stop    #$2700
+",
+        );
+        assert_eq!(sbc.next_instruction().as_deref(), Some("moveq #1,d0"));
+        sbc.step();
+        sbc.step();
+        assert_eq!(sbc.next_instruction().as_deref(), Some("move.l d0,d1"));
+        assert_eq!((sbc.instructions(), sbc.last_stop()), (2, None));
 
-        // Verify D0 contains the value we loaded
-        assert_eq!(sbc.registers().d(0), 42);
+        sbc.step_n(1, true, 0);
+        assert_eq!(sbc.next_instruction().as_deref(), Some("stop #$2700"));
+        assert_eq!(sbc.instructions(), 3);
+        assert_eq!(sbc.last_stop(), Some(StopReason::StepsCompleted));
+        sbc.run(1000);
+        assert_eq!(sbc.last_stop(), Some(StopReason::Halted));
 
-        // CPU should be halted by STOP
-        assert!(sbc.is_halted());
+        sbc.reset();
+        assert_eq!((sbc.instructions(), sbc.last_stop()), (0, None));
+        sbc.cpu.set_pc(0x00B0_0000);
+        assert_eq!(sbc.next_instruction(), None);
+    }
+
+    #[test]
+    fn test_sbc_step_n_batches_output() {
+        const PROGRAM: &str = "
+        moveq   #49,d1
+.line:  moveq   #'0',d0
+.digit: move.b  d0,$A00000
+        addq.b  #1,d0
+        cmp.b   #'9'+1,d0
+        bne     .digit
+        move.b  #10,$A00000
+        dbra    d1,.line
+        moveq   #0,d0
+        trap    #0
+";
+        let mut full = Sbc::new();
+        start_program(&mut full, PROGRAM);
+        assert_eq!(full.run(10_000_000).stop, StopReason::Exit { code: 0 });
+        let expected = full.drain_output();
+        assert_eq!(expected.len(), 550);
+
+        let mut sbc = Sbc::new();
+        start_program(&mut sbc, PROGRAM);
+        let mut output = Vec::new();
+        let batch = sbc.step_n(1000, true, 16);
+        assert_eq!(batch.run.stop, StopReason::StepsCompleted);
+        assert_eq!(batch.run.instructions, 1000);
+        assert_eq!(batch.pcs.len(), 16);
+        assert_eq!(&batch.pcs[..3], &[APP_START, APP_START + 2, APP_START + 4]);
+        output.extend(batch.output);
+        let batch = loop {
+            let batch = sbc.step_n(1000, true, 0);
+            output.extend(&batch.output);
+            if batch.run.stop != StopReason::StepsCompleted {
+                break batch;
+            }
+        };
+        assert_eq!(batch.run.stop, StopReason::Exit { code: 0 });
+        assert_eq!(output, expected);
+        assert!(sbc.peek_output().is_empty());
+        assert_eq!(sbc.registers(), full.registers());
+
+        // Breakpoints stop a batch only when asked to
+        start_program(&mut sbc, PROGRAM);
+        sbc.debugger_mut().add_breakpoint(APP_START + 4);
+        let batch = sbc.step_n(1000, true, 0);
+        assert_eq!(batch.run.instructions, 2);
+        assert_eq!(batch.output, Vec::<u8>::new());
+        let batch = sbc.step_n(1000, false, 0);
+        assert_eq!(batch.run.instructions, 1000);
+    }
+
+    #[test]
+    fn test_sbc_run_stops_on_uninitialized_vector() {
+        let mut sbc = Sbc::new();
+        start_program(&mut sbc, "\n        nop\n        trap    #7\n");
+        // TRAP #7 is vector 39 at $9C
+        let _ = sbc.cpu.memory.load_binary(0x9C, &[0xFF; 4]);
+
+        let result = sbc.run(1_000_000);
+        assert_eq!(
+            result.stop,
+            StopReason::UninitializedVector {
+                vector: 39,
+                pc: APP_START + 2,
+            }
+        );
+        assert_eq!(result.pc, 0xFFFF_FFFF);
+    }
+
+    #[test]
+    fn test_sbc_load_app_rejects_bad_ranges() {
+        let mut sbc = Sbc::new();
+        assert_eq!(
+            sbc.load_app(&[0; 2], Some(0x1000)),
+            Err(AppLoadError::NotRam { addr: 0x1000 })
+        );
+        assert_eq!(
+            sbc.load_app(&[0; 2], Some(RAM_BASE + 0x80)),
+            Err(AppLoadError::SystemArea {
+                addr: RAM_BASE + 0x80
+            })
+        );
+        assert_eq!(
+            sbc.load_app(&[0; 0x20], Some(0x00EF_FFF0)),
+            Err(AppLoadError::TooLarge {
+                addr: 0x00EF_FFF0,
+                size: 0x20,
+                available: 0x10,
+            })
+        );
+        assert!(sbc.load_app(&[0; 0x10], Some(0x00EF_FFF0)).is_ok());
+    }
+
+    #[test]
+    fn test_sbc_config_ram_sizes() {
+        let config = SbcConfig {
+            ram_size: 256 * 1024,
+            ..SbcConfig::default()
+        };
+        let mut sbc = Sbc::new_with_config(config.clone()).unwrap();
+        assert_eq!(sbc.config(), &config);
+        let memory = &mut sbc.cpu.memory;
+        let _ = memory.write_byte(RAM_MIRROR + 0x3_FFFF, 0x55);
+        let _ = memory.write_byte(RAM_MIRROR + 0x4_0000, 0x55);
+        assert_eq!(memory.read_byte(RAM_MIRROR + 0x3_FFFF).unwrap(), 0x55);
+        assert_eq!(memory.read_byte(RAM_MIRROR + 0x4_0000).unwrap(), 0xFF);
+        assert_eq!(
+            sbc.load_app(&[0; 0x20], Some(RAM_MIRROR + 0x3_FFF0)),
+            Err(AppLoadError::TooLarge {
+                addr: RAM_MIRROR + 0x3_FFF0,
+                size: 0x20,
+                available: 0x10,
+            })
+        );
+        sbc.run_app(None);
+        assert_eq!(sbc.registers().get_ssp(), RAM_MIRROR + 0x4_0000);
+
+        let mut sbc = Sbc::new_with_config(SbcConfig::default()).unwrap();
+        let _ = sbc.cpu.memory.write_byte(RAM_MIRROR + 0x4_0000, 0x55);
+        assert_eq!(
+            sbc.cpu.memory.read_byte(RAM_MIRROR + 0x4_0000).unwrap(),
+            0x55
+        );
+
+        let mut config = SbcConfig::default();
+        config.cf.image = Some("/nonexistent/disk.img".to_string());
+        assert_eq!(
+            Sbc::new_with_config(config).err().unwrap().field,
+            "cf.image"
+        );
+    }
+
+    #[test]
+    fn test_sbc_config_bus_and_cpu_toggles() {
+        let mut config = SbcConfig {
+            open_bus: crate::config::OpenBusPolicy::Low,
+            ..SbcConfig::default()
+        };
+        config.peripherals.timer = false;
+        let mut sbc = Sbc::new_with_config(config).unwrap();
+        assert_eq!(sbc.cpu.memory.read_byte(0x0010_0000).unwrap(), 0x00);
+        // The disabled timer leaves its slot open
+        let _ = sbc.cpu.memory.write_byte(0x0080_0066, 0x12);
+        assert_eq!(sbc.cpu.memory.read_byte(0x0080_0066).unwrap(), 0x00);
+
+        // The instruction after the MOVE.W is overwritten with MOVEQ #5,D0,
+        // but with prefetch on the old MOVEQ #1,D0 has already been fetched
+        const PATCH: &str = "
+        lea.l   $E0010A,a0
+        move.w  #$7005,(a0)
+        moveq   #1,d0
+        stop    #$2700
+";
+        // CLR.B on the UART's receive buffer pops a byte when CLR reads first
+        const CLEAR: &str = "
+        clr.b   $A00000
+        move.b  $A00000,d0
+        stop    #$2700
+";
+        for enabled in [false, true] {
+            let config = SbcConfig {
+                clr_read: enabled,
+                prefetch: enabled,
+                ..SbcConfig::default()
+            };
+            let mut sbc = Sbc::new_with_config(config).unwrap();
+            start_program(&mut sbc, PATCH);
+            sbc.run(1_000);
+            assert_eq!(sbc.registers().d(0), if enabled { 1 } else { 5 });
+
+            start_program(&mut sbc, CLEAR);
+            sbc.send_char(b'A');
+            sbc.send_char(b'B');
+            sbc.run(1_000);
+            let expected = if enabled { b'B' } else { b'A' };
+            assert_eq!(sbc.registers().d(0), u32::from(expected));
+        }
+    }
+
+    #[test]
+    fn test_sbc_forbidden_region_reads_open_bus() {
+        let mut sbc = Sbc::new();
+        // $100000-$1FFFFF is ROM + CF overlap (forbidden).
+        let _ = sbc.cpu.memory.write_byte(0x100000, 0xAA);
+        assert_eq!(sbc.cpu.memory.read_byte(0x100000).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn test_sbc_write_memory_block() {
+        let mut sbc = Sbc::new();
+        let block: Vec<u8> = (0..0x1_0000u32).map(|i| (i * 7 + (i >> 8)) as u8).collect();
+        let result = sbc.write_memory(0x00E1_0000, &block, false);
+        assert_eq!(
+            result,
+            MemoryWriteResult {
+                written: block.len(),
+                failed_at: None,
+            }
+        );
+        let mut readback = Vec::new();
+        for chunk in 0..16u32 {
+            readback.extend(
+                sbc.cpu
+                    .memory
+                    .read_range(0x00E1_0000 + chunk * 0x1000, 0x1000),
+            );
+        }
+        assert_eq!(readback, block);
+
+        // ROM is write-protected unless overridden
+        let result = sbc.write_memory(0x0000_1000, &[0x4E, 0x71], false);
+        assert_eq!(result.written, 0);
+        assert_eq!(result.failed_at, Some(0x0000_1000));
+        let result = sbc.write_memory(0x0000_1000, &[0x4E, 0x71], true);
+        assert_eq!(result.failed_at, None);
+        assert_eq!(sbc.cpu.memory.read_word(0x0000_1000).unwrap(), 0x4E71);
+        assert_eq!(sbc.cpu.memory.read_word(0x0021_1000).unwrap(), 0x4E71);
+        sbc.reset();
+        assert_eq!(sbc.cpu.memory.read_word(0x0000_1000).unwrap(), 0x4E71);
+
+        // A block running off the end of RAM stops at the first missing byte
+        let result = sbc.write_memory(0x00EF_FFFE, &[1, 2, 3, 4], false);
+        assert_eq!(
+            result,
+            MemoryWriteResult {
+                written: 2,
+                failed_at: Some(0x00F0_0000),
+            }
+        );
+    }
+
+    #[test]
+    fn test_sbc_fill_memory_pattern() {
+        let mut sbc = Sbc::new();
+        let pattern = [0xDE, 0xAD, 0xBE, 0xEF, 0x55];
+        let result = sbc.fill_memory(0x00E2_0000, 0x1000, &pattern, false);
+        assert_eq!(
+            result,
+            MemoryWriteResult {
+                written: 0x1000,
+                failed_at: None,
+            }
+        );
+        let readback = sbc.cpu.memory.read_range(0x00E2_0000, 0x1001);
+        assert!(readback[..0x1000]
+            .iter()
+            .zip(pattern.iter().cycle())
+            .all(|(a, b)| a == b));
+        assert_eq!(readback[0x1000], 0);
+
+        // A dry run reports the same without writing
+        let result = sbc.fill_memory(0x00E3_0000, 0x100, &[0xAA], true);
+        assert_eq!(result.written, 0x100);
+        assert_eq!(sbc.cpu.memory.read_byte(0x00E3_0000).unwrap(), 0);
+
+        // Running off the end of RAM stops at the first missing byte
+        let result = sbc.fill_memory(0x00EF_FFFC, 8, &[1, 2], true);
+        assert_eq!(
+            result,
+            MemoryWriteResult {
+                written: 4,
+                failed_at: Some(0x00F0_0000),
+            }
+        );
+        assert_eq!(sbc.fill_memory(0x00EF_FFFC, 8, &[1, 2], false), result);
+        assert_eq!(sbc.cpu.memory.read_word(0x00EF_FFFE).unwrap(), 0x0102);
+
+        // ROM is write-protected
+        let rom = sbc.cpu.memory.read_range(0x0000_0100, 16);
+        let result = sbc.fill_memory(0x0000_0100, 16, &[0], false);
+        assert_eq!((result.written, result.failed_at), (0, Some(0x0000_0100)));
+        assert_eq!(sbc.cpu.memory.read_range(0x0000_0100, 16), rom);
+    }
+
+    #[test]
+    fn test_sbc_diagnostics_report() {
+        let config = SbcConfig {
+            ram_size: 512 * 1024,
+            ..SbcConfig::default()
+        };
+        let mut rom = vec![0xFF; ROM_SIZE];
+        rom[..8].copy_from_slice(&[0x00, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00]);
+        let mut sbc = Sbc::new_with_config(config).unwrap();
+        sbc.load_rom(&rom);
+        sbc.reset();
+
+        let report = sbc.diagnostics();
+        assert_eq!(report.ram_size, 512 * 1024);
+        assert_eq!(report.rom.crc32, crc32(&rom));
+        assert!(!report.rom.embedded);
+        let ram = report.memory_map.iter().find(|r| r.name == "RAM").unwrap();
+        assert_eq!(ram.end, RAM_BASE + 0x7_FFFF);
+        assert_eq!(report.faults.last_bus_fault, None);
+        assert!(!report.faults.watchdog_reset);
+
+        // Reads from the ROM + CF overlap latch the address
+        let _ = sbc.cpu.memory.read_byte(0x0010_0042);
+        assert_eq!(sbc.diagnostics().faults.last_bus_fault, Some(0x0010_0042));
+
+        let sbc = Sbc::new();
+        let json = serde_json::to_value(sbc.diagnostics()).unwrap();
+        assert_eq!(json["ramSize"], RAM_SIZE);
+        assert_eq!(json["rom"]["crc32"], crc32(&sbc.rom_data));
+        assert_eq!(json["rom"]["embedded"], true);
+    }
+
+    #[test]
+    fn test_sbc_button_msr_polarity() {
+        let mut sbc = Sbc::new();
+        sbc.set_button(true);
+        let msr = sbc.cpu.memory.read_byte(0xA0000C).unwrap();
+        assert!(msr & 0x40 != 0);
+    }
+
+    #[test]
+    fn test_sbc_break_inserts_zero_and_sets_lsr() {
+        let mut sbc = Sbc::new();
+        sbc.send_break();
+        let lsr = sbc.cpu.memory.read_byte(0xA0000A).unwrap();
+        assert!(lsr & 0x10 != 0);
+        let byte = sbc.cpu.memory.read_byte(0xA00000).unwrap();
+        assert_eq!(byte, 0);
+    }
+
+    #[test]
+    fn test_sbc_rom_compatibility() {
+        let mut sbc = Sbc::new();
+
+        // Simulate loading a compatible ROM with proper reset vectors
+        // Initial SSP = 0x00F00000 (end of RAM)
+        // Initial PC = 0x000000C0 (ROM start)
+        let mut rom = vec![0u8; ROM_SIZE];
+        rom[0..4].copy_from_slice(&[0x00, 0xF0, 0x00, 0x00]); // Initial SSP
+        rom[4..8].copy_from_slice(&[0x00, 0x00, 0x00, 0xC0]); // Initial PC
+
+        // Write a simple program at 0xC0 that loads D0 and halts
+        // MOVEQ #42,D0 (quick move, single instruction)
+        rom[0xC0] = 0x70; // MOVEQ
+        rom[0xC1] = 0x2A; // #42
+
+        // STOP #$2700 (halt CPU with interrupts masked)
+        rom[0xC2] = 0x4E; // STOP
+        rom[0xC3] = 0x72;
+        rom[0xC4] = 0x27; // SR value
+        rom[0xC5] = 0x00;
+
+        sbc.load_rom(&rom);
+        sbc.reset();
+
+        // Verify reset vectors loaded correctly
+        assert_eq!(sbc.pc(), 0x000000C0);
+        assert_eq!(sbc.registers().sp(), 0x00F00000);
+        assert_eq!(sbc.sr(), 0x2700); // Supervisor mode, interrupts masked
+
+        // Execute instructions
+        let mut executed = 0;
+        for _ in 0..10 {
+            if !sbc.step() {
+                break;
+            }
+            executed += 1;
+        }
+
+        // Should have executed at least MOVEQ
+        assert!(executed >= 1);
+
+        // Verify D0 contains the value we loaded
+        assert_eq!(sbc.registers().d(0), 42);
+
+        // CPU should be halted by STOP
+        assert!(sbc.is_halted());
+    }
+
+    /// Guest routine mirroring the ROM's `_cfcard_sendcmd`: issues READ SECTOR
+    /// for LBA 0, polls BSY with a 64K iteration timeout, then waits for DRQ.
+    /// Leaves the ROM error code in D0 (0 = success, 32 = `FSERR_TIMEOUT`).
+    const CF_READ_PROGRAM: &str = "
+        lea.l   $900000,a1
+        move.b  #$E0,13(a1)
+        clr.b   7(a1)
+        move.b  #1,5(a1)
+        move.b  #$20,15(a1)
+        moveq   #-1,d0
+.busy:  btst.b  #7,15(a1)
+        beq     .notbusy
+        dbra    d0,.busy
+        moveq   #32,d0
+        bra     .done
+.notbusy:
+        moveq   #-1,d0
+.nodata:
+        btst.b  #3,15(a1)
+        bne     .gotdata
+        dbra    d0,.nodata
+        moveq   #32,d0
+        bra     .done
+.gotdata:
+        move.w  (a1),d1
+        moveq   #0,d0
+.done:  stop    #$2700
+";
+
+    /// Minimal guest FAT16 mount, following the ROM's `fs_mount`: finds the
+    /// partition in the MBR, checks the BPB, and reads the root directory.
+    /// Returns D0 = 0 on success with D2 = root directory LBA.
+    const FAT16_MOUNT_PROGRAM: &str = "
+        lea.l   $900000,a1
+        lea.l   $E10000,a0
+        moveq   #0,d0
+        bsr     readsec
+        bne     nomount
+        cmp.w   #$55AA,510(a0)
+        bne     nomount
+        move.b  $1C2(a0),d0
+        cmp.b   #$04,d0
+        beq     .isfat16
+        cmp.b   #$06,d0
+        bne     nomount
+.isfat16:
+        move.b  $1C9(a0),d0
+        lsl.l   #8,d0
+        move.b  $1C8(a0),d0
+        lsl.l   #8,d0
+        move.b  $1C7(a0),d0
+        lsl.l   #8,d0
+        move.b  $1C6(a0),d0
+        move.l  d0,d2
+        bsr     readsec
+        bne     nomount
+        move.b  12(a0),d0
+        lsl.w   #8,d0
+        move.b  11(a0),d0
+        cmp.w   #512,d0
+        bne     nomount
+        moveq   #0,d3
+        move.b  15(a0),d3
+        lsl.w   #8,d3
+        move.b  14(a0),d3
+        moveq   #0,d4
+        move.b  23(a0),d4
+        lsl.w   #8,d4
+        move.b  22(a0),d4
+        moveq   #0,d5
+        move.b  16(a0),d5
+        mulu.w  d5,d4
+        add.l   d3,d2
+        add.l   d4,d2
+        move.l  d2,d0
+        bsr     readsec
+        bne     nomount
+        cmp.b   #$08,11(a0)
+        bne     nomount
+        moveq   #0,d0
+        stop    #$2700
+nomount: moveq  #-1,d0
+        stop    #$2700
+
+readsec:
+        move.l  d0,d1
+        move.b  d1,7(a1)
+        lsr.l   #8,d1
+        move.b  d1,9(a1)
+        lsr.l   #8,d1
+        move.b  d1,11(a1)
+        lsr.l   #8,d1
+        and.b   #$0F,d1
+        or.b    #$E0,d1
+        move.b  d1,13(a1)
+        move.b  #1,5(a1)
+        move.b  #$20,15(a1)
+.wait:  btst.b  #7,15(a1)
+        bne     .wait
+        btst.b  #0,15(a1)
+        bne     .err
+        move.w  #255,d1
+.copy:  move.w  (a1),(a0)+
+        dbra    d1,.copy
+        lea.l   -512(a0),a0
+        moveq   #0,d1
+        rts
+.err:   moveq   #1,d1
+        rts
+";
+
+    fn run_cf_read_program(sbc: &mut Sbc) -> u32 {
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm
+            .assemble_source(CF_READ_PROGRAM, Path::new("<test>"))
+            .unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        sbc.run(50_000_000);
+        assert!(sbc.is_halted());
+        sbc.registers().d(0)
+    }
+
+    #[test]
+    fn test_sbc_cf_read_waits_for_busy() {
+        let mut sbc = Sbc::new();
+        let mut image = vec![0u8; 512 * 4];
+        image[0] = 0x5A;
+        image[1] = 0xA5;
+        sbc.load_cf_bytes(&image);
+
+        assert_eq!(run_cf_read_program(&mut sbc), 0);
+        assert_eq!(sbc.registers().d(1) & 0xFFFF, 0x5AA5);
+    }
+
+    #[test]
+    fn test_sbc_cf_overlay_discard() {
+        let path =
+            std::env::temp_dir().join(format!("flux32-sbc-overlay-{}.img", std::process::id()));
+        let mut image = vec![0u8; 512 * 4];
+        image[0] = 0x12;
+        image[1] = 0x34;
+        std::fs::write(&path, &image).unwrap();
+
+        let mut sbc = Sbc::new();
+        sbc.set_cf_timing(CfTiming::INSTANT);
+        sbc.load_cf_image(&path).unwrap();
+        sbc.set_cf_overlay_enabled(true).unwrap();
+
+        {
+            let cf = sbc.cfcard();
+            let mut cf = cf.lock().unwrap();
+            cf.write(crate::cfcard::regs::SECTOR_COUNT, 1);
+            cf.write(crate::cfcard::regs::LBA0, 0);
+            cf.write(crate::cfcard::regs::LBA1, 0);
+            cf.write(crate::cfcard::regs::LBA2, 0);
+            cf.write(crate::cfcard::regs::DRIVE_HEAD, 0xE0);
+            cf.write(
+                crate::cfcard::regs::STATUS_COMMAND,
+                crate::cfcard::commands::WRITE_SECTORS,
+            );
+            for _ in 0..256 {
+                cf.write(crate::cfcard::regs::DATA, 0xBE);
+                cf.write(crate::cfcard::regs::DATA, 0xEF);
+            }
+        }
+        sbc.flush_cf().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), image);
+
+        assert_eq!(run_cf_read_program(&mut sbc), 0);
+        assert_eq!(sbc.registers().d(1) & 0xFFFF, 0xBEEF);
+
+        sbc.discard_cf_overlay();
+        assert_eq!(run_cf_read_program(&mut sbc), 0);
+        assert_eq!(sbc.registers().d(1) & 0xFFFF, 0x1234);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sbc_guest_mounts_created_fat16_image() {
+        let path =
+            std::env::temp_dir().join(format!("flux32-sbc-fat16-{}.img", std::process::id()));
+        crate::cfimage::create_image(&path, 8, true).unwrap();
+
+        let mut sbc = Sbc::new();
+        sbc.load_cf_image(&path).unwrap();
+        assert_eq!(sbc.cfcard().lock().unwrap().volume_label(), "FLUX32     ");
+
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm
+            .assemble_source(FAT16_MOUNT_PROGRAM, Path::new("<test>"))
+            .unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        sbc.run(50_000_000);
+        assert!(sbc.is_halted());
+        assert_eq!(sbc.registers().d(0), 0);
+
+        let geometry = crate::cfimage::Fat16Geometry::new(8 * 2048 - 63).unwrap();
+        assert_eq!(sbc.registers().d(2), 63 + geometry.root_dir_sector());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn run_program(sbc: &mut Sbc, source: &str) {
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(source, Path::new("<test>")).unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        sbc.run(1_000_000);
+        assert!(sbc.is_halted());
+    }
+
+    #[test]
+    fn test_sbc_rtc_set_and_read_from_guest() {
+        let clock = Arc::new(crate::rtc::ManualClock::new(1_700_000_000));
+        let mut sbc = Sbc::new();
+        sbc.set_rtc_clock(clock.clone());
+
+        run_program(
+            &mut sbc,
+            "
+        lea.l   $800000,a1
+        move.b  #$01,14(a1)
+        move.b  #$30,12(a1)
+        move.b  #$06,10(a1)
+        move.b  #$15,8(a1)
+        move.b  #$12,4(a1)
+        move.b  #$34,2(a1)
+        move.b  #$56,0(a1)
+        clr.b   14(a1)
+        stop    #$2700
+",
+        );
+        clock.advance(10);
+
+        run_program(
+            &mut sbc,
+            "
+        lea.l   $800000,a1
+        move.b  #$01,14(a1)
+        moveq   #0,d0
+        move.b  4(a1),d0
+        lsl.l   #8,d0
+        move.b  2(a1),d0
+        lsl.l   #8,d0
+        move.b  0(a1),d0
+        moveq   #0,d1
+        move.b  12(a1),d1
+        lsl.w   #8,d1
+        move.b  10(a1),d1
+        lsl.l   #8,d1
+        move.b  8(a1),d1
+        clr.b   14(a1)
+        stop    #$2700
+",
+        );
+        assert_eq!(sbc.registers().d(0), 0x12_35_06);
+        assert_eq!(sbc.registers().d(1), 0x30_06_15);
+    }
+
+    #[test]
+    fn test_sbc_gpio_guest_mirrors_input() {
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm
+            .assemble_source(
+                "
+        lea.l   $800020,a1
+        move.b  #$80,(a1)
+.loop:  btst.b  #0,2(a1)
+        beq     .low
+        move.b  #$80,2(a1)
+        bra     .loop
+.low:   clr.b   2(a1)
+        bra     .loop
+",
+                Path::new("<test>"),
+            )
+            .unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+
+        sbc.run(1000);
+        assert_eq!(sbc.gpio_output_state(), 0x80);
+
+        sbc.gpio_set_input(0, false);
+        sbc.run(1000);
+        assert_eq!(sbc.gpio_output_state(), 0x00);
+
+        sbc.gpio_set_input(0, true);
+        sbc.run(1000);
+        assert_eq!(sbc.gpio_output_state(), 0x80);
+
+        let events: Vec<u8> = sbc.take_gpio_events().iter().map(|e| e.output).collect();
+        assert_eq!(events, [0x80, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn test_sbc_intc_timer_preempts_uart() {
+        // UART at priority 2 and the timer at priority 5, with vector base 64.
+        // The UART handler starts the timer and spins until the timer
+        // handler has run, so the timer must preempt it. Both handlers log
+        // to $E02000.
+        const PROGRAM: &str = "
+        bra     main
+uart_isr:
+        move.b  #1,(a0)+
+        move.b  $800040,(a0)+
+        move.b  $A00000,d0
+        move.b  #$11,$800060
+.spin:  tst.b   d6
+        beq     .spin
+        move.b  #2,(a0)+
+        moveq   #1,d7
+        rte
+timer_isr:
+        move.b  #3,(a0)+
+        move.b  $800044,(a0)+
+        move.b  $800048,(a0)+
+        clr.b   $800060
+        move.b  #1,$800062
+        move.b  $800040,(a0)+
+        moveq   #1,d6
+        rte
+main:
+        lea.l   $E02000,a0
+        moveq   #0,d6
+        moveq   #0,d7
+        move.b  #64,$800046
+        move.b  #2,$800050
+        move.b  #5,$800058
+        move.b  #$11,$800042
+        move.b  #50,$800066
+        move.b  #$01,$A00002
+.wait:  tst.b   d7
+        beq     .wait
+        stop    #$2700
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(PROGRAM, Path::new("<test>")).unwrap();
+        let uart_isr = APP_START + asm.symbols.get("uart_isr").unwrap() as u32;
+        let timer_isr = APP_START + asm.symbols.get("timer_isr").unwrap() as u32;
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        // Vector 64 (UART, source 0) and vector 68 (timer, source 4)
+        let _ = sbc.cpu.memory.load_binary(0x100, &uart_isr.to_be_bytes());
+        let _ = sbc.cpu.memory.load_binary(0x110, &timer_isr.to_be_bytes());
+
+        sbc.run(2_000);
+        assert!(!sbc.is_halted());
+        sbc.send_char(b'A');
+        sbc.run(100_000);
+        assert!(sbc.is_halted());
+
+        // UART entry (pending cleared by IACK), timer entry with status,
+        // current source and pending after clearing IF, then UART exit
+        let mut log = [0u8; 7];
+        for (i, byte) in log.iter_mut().enumerate() {
+            *byte = sbc.cpu.memory.read_byte(0x00E0_2000 + i as u32).unwrap();
+        }
+        assert_eq!(log, [1, 0x00, 3, 0x10, 4, 0x00, 2]);
+    }
+
+    #[test]
+    fn test_sbc_nmi_breaks_into_loop() {
+        // The handler records the stacked PC; the loop runs with every
+        // interrupt masked
+        const PROGRAM: &str = "
+        bra     main
+nmi:
+        move.l  2(sp),d6
+        addq.l  #1,d7
+        stop    #$2700
+main:
+        moveq   #0,d7
+        move.w  #$2700,sr
+loop:   addq.l  #1,d5
+        bra     loop
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(PROGRAM, Path::new("<test>")).unwrap();
+        let nmi = APP_START + asm.symbols.get("nmi").unwrap() as u32;
+        let loop_addr = APP_START + asm.symbols.get("loop").unwrap() as u32;
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        // Autovector 7
+        let _ = sbc.cpu.memory.load_binary(0x7C, &nmi.to_be_bytes());
+
+        sbc.run(1_000);
+        assert_eq!(sbc.registers().d(7), 0);
+        sbc.raise_nmi();
+        sbc.run(1_000);
+        assert_eq!(sbc.registers().d(7), 1);
+        assert!((loop_addr..loop_addr + 4).contains(&sbc.registers().d(6)));
+
+        // Ctrl-C reaches the UART until it is mapped to NMI
+        sbc.run_app(None);
+        sbc.run(1_000);
+        sbc.send_char(0x03);
+        sbc.run(1_000);
+        assert_eq!(sbc.registers().d(7), 0);
+        sbc.set_ctrl_c_nmi(true);
+        sbc.send_char(0x03);
+        sbc.run(1_000);
+        assert_eq!(sbc.registers().d(7), 1);
+        assert_eq!(sbc.recv_char(), None);
+        // Only the first Ctrl-C was received
+        assert_eq!(sbc.cpu.memory.read_byte(0x00A0_0000).unwrap(), 0x03);
+        assert_eq!(sbc.cpu.memory.read_byte(0x00A0_000A).unwrap() & 0x01, 0);
+    }
+
+    #[test]
+    fn test_sbc_inject_interrupt_waits_for_the_mask() {
+        // The handler counts in D7; the main code waits with the mask at 5
+        // until D0 is set, then lowers it
+        const PROGRAM: &str = "
+        bra     main
+handler:
+        addq.l  #1,d7
+        rte
+main:
+        moveq   #0,d7
+        move.w  #$2500,sr
+wait:   tst.l   d0
+        beq     wait
+        move.w  #$2000,sr
+done:   bra     done
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(PROGRAM, Path::new("<test>")).unwrap();
+        let handler = APP_START + asm.symbols.get("handler").unwrap() as u32;
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        // Autovector 3 and vector 100
+        let _ = sbc.cpu.memory.load_binary(0x6C, &handler.to_be_bytes());
+        let _ = sbc.cpu.memory.load_binary(400, &handler.to_be_bytes());
+        sbc.run(1_000);
+
+        assert_eq!(sbc.inject_interrupt(3, None), InterruptOutcome::Deferred);
+        assert_eq!(sbc.inject_interrupt(3, None), InterruptOutcome::Dropped);
+        sbc.run(1_000);
+        assert_eq!(sbc.registers().d(7), 0);
+
+        sbc.cpu.registers.d[0] = 1;
+        sbc.run(1_000);
+        assert_eq!(sbc.registers().d(7), 1);
+        assert_eq!(
+            sbc.intc.lock().unwrap().read(intc::regs::CURRENT),
+            intc::EXTERNAL
+        );
+
+        // With the mask down a request is taken at once, on its own vector
+        assert_eq!(
+            sbc.inject_interrupt(3, Some(100)),
+            InterruptOutcome::Accepted
+        );
+        sbc.run(1_000);
+        assert_eq!(sbc.registers().d(7), 2);
+    }
+
+    #[test]
+    fn test_sbc_step_over_and_out() {
+        const PROGRAM: &str = "
+start:  moveq   #0,d0
+        bsr     outer
+after:  moveq   #1,d1
+done:   bra     done
+outer:  addq.l  #1,d0
+        bsr     inner
+back:   addq.l  #1,d0
+        rts
+inner:  addq.l  #1,d2
+print:  move.b  #'x',$A00000
+        rts
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(PROGRAM, Path::new("<test>")).unwrap();
+        let label = |name: &str| APP_START + asm.symbols.get(name).unwrap() as u32;
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+
+        // Outside a call, stepping over is a single step
+        let batch = sbc.step_over(1_000);
+        assert_eq!(batch.run.stop, StopReason::StepsCompleted);
+        assert_eq!((batch.run.instructions, sbc.pc()), (1, label("start") + 2));
+
+        let batch = sbc.step_over(1_000);
+        assert_eq!(batch.run.stop, StopReason::StepsCompleted);
+        assert_eq!(sbc.pc(), label("after"));
+        assert_eq!(sbc.registers().d(0), 2);
+        assert_eq!(batch.output, b"x");
+
+        // A breakpoint in the callee stops the step
+        sbc.run_app(None);
+        let id = sbc.debugger_mut().add_breakpoint(label("print"));
+        sbc.step();
+        let batch = sbc.step_over(1_000);
+        assert_eq!(
+            batch.run.stop,
+            StopReason::Breakpoint {
+                id,
+                address: label("print"),
+            }
+        );
+        assert_eq!(sbc.pc(), label("print"));
+
+        // Stepping out returns through one level at a time
+        let batch = sbc.step_out(1_000);
+        assert_eq!(batch.run.stop, StopReason::StepsCompleted);
+        assert_eq!(sbc.pc(), label("back"));
+        assert_eq!(batch.output, b"x");
+        sbc.step_out(1_000);
+        assert_eq!(sbc.pc(), label("after"));
+        assert_eq!(sbc.registers().d(0), 2);
+
+        // Nothing returns from the final loop
+        let batch = sbc.step_out(1_000);
+        assert_eq!(batch.run.stop, StopReason::BudgetExhausted);
+        assert!(batch.run.cycles >= 1_000);
+        assert!((label("after")..=label("done")).contains(&sbc.pc()));
+    }
+
+    #[test]
+    fn test_sbc_stop_skips_ahead_to_timer() {
+        // The timer fires every 1000 ticks (12000 cycles) at priority 5 and
+        // the program STOPs between interrupts until three have been counted
+        const PROGRAM: &str = "
+        bra     main
+timer_isr:
+        move.b  #1,$800062
+        addq.l  #1,d7
+        rte
+main:
+        moveq   #0,d7
+        move.b  #64,$800046
+        move.b  #5,$800058
+        move.b  #$10,$800042
+        move.b  #$03,$800064
+        move.b  #$E8,$800066
+        move.b  #$11,$800060
+.wait:  stop    #$2000
+        cmpi.l  #3,d7
+        bne     .wait
+        stop    #$2700
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(PROGRAM, Path::new("<test>")).unwrap();
+        let timer_isr = APP_START + asm.symbols.get("timer_isr").unwrap() as u32;
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        let _ = sbc.cpu.memory.load_binary(0x110, &timer_isr.to_be_bytes());
+
+        // The budget caps a skip while STOPped
+        let result = sbc.run(5_000);
+        assert_eq!(result.stop, StopReason::BudgetExhausted);
+        assert!(sbc.is_halted());
+        assert_eq!(result.cycles, 5_000);
+        let start = sbc.master_cycles() - result.cycles;
+
+        let result = sbc.run(1_000_000);
+        assert_eq!(result.stop, StopReason::Halted);
+        assert_eq!(sbc.registers().d(7), 3);
+        // Three whole periods plus the few instructions run in between
+        let elapsed = sbc.master_cycles() - start;
+        assert!((36_000..37_000).contains(&elapsed), "{elapsed}");
+        assert!(result.instructions < 30);
+    }
+
+    #[test]
+    fn test_sbc_intc_masks_sources_after_reset() {
+        let mut sbc = Sbc::new();
+        sbc.reset();
+        run_program(
+            &mut sbc,
+            "
+        move.b  #$01,$A00002
+        moveq   #0,d0
+        move.b  $800042,d0
+        stop    #$2000
+",
+        );
+        sbc.send_char(b'A');
+        sbc.step();
+        // The UART requests an interrupt, but the masked source can't wake
+        // the CPU from STOP
+        assert_eq!(sbc.registers().d(0), 0);
+        assert_eq!(sbc.intc().lock().unwrap().read(intc::regs::PENDING), 0x01);
+        assert!(sbc.is_halted());
+    }
+
+    #[test]
+    fn test_sbc_rom_break_reaches_serial_loader() {
+        // The embedded ROM unmasks the UART at the interrupt controller, so
+        // a break at the prompt enters its serial loader, which answers 'U'
+        let mut sbc = Sbc::new();
+        let mut output = Vec::new();
+        for _ in 0..200 {
+            sbc.run(100_000);
+            output.extend(sbc.drain_output());
+            if output.ends_with(b"> ") {
+                break;
+            }
+        }
+        assert!(
+            output.ends_with(b"> "),
+            "{}",
+            String::from_utf8_lossy(&output)
+        );
+        assert_eq!(
+            sbc.intc().lock().unwrap().read(intc::regs::ENABLE),
+            1 << intc::sources::UART
+        );
+
+        sbc.send_break();
+        sbc.run(100_000);
+        assert_eq!(sbc.drain_output(), b"U");
+    }
+
+    #[test]
+    fn test_sbc_watchdog_kicked_in_time() {
+        // Arms a 20000-cycle reset-mode watchdog, then kicks it 50 times
+        // about 1000 cycles apart before disabling it
+        let mut sbc = Sbc::new();
+        run_program(
+            &mut sbc,
+            "
+        lea.l   $800080,a1
+        clr.b   4(a1)
+        move.b  #$4E,6(a1)
+        move.b  #$20,8(a1)
+        move.b  #$03,0(a1)
+        moveq   #49,d1
+.kick:  move.w  #100,d2
+.delay: dbra    d2,.delay
+        move.b  #$A5,10(a1)
+        dbra    d1,.kick
+        clr.b   0(a1)
+        moveq   #0,d0
+        move.b  12(a1),d0
+        stop    #$2700
+",
+        );
+        assert!(sbc.cycles() > 50_000);
+        assert_eq!(sbc.registers().d(0), 0);
+        assert_eq!(sbc.registers().d(1) & 0xFFFF, 0xFFFF);
+        assert!(sbc.pc() >= APP_START);
+    }
+
+    #[test]
+    fn test_sbc_watchdog_timeout_resets_machine() {
+        // The firmware lives in ROM: the first boot arms the watchdog and
+        // hangs; the second sees WDRF
+        const ROM: &str = "
+        dc.l    $00F00000
+        dc.l    start
+start:
+        lea.l   $800080,a1
+        moveq   #0,d0
+        move.b  12(a1),d0
+        btst    #0,d0
+        bne     .done
+        clr.b   4(a1)
+        move.b  #$4E,6(a1)
+        move.b  #$20,8(a1)
+        move.b  #$03,0(a1)
+        move.b  #$01,$800042
+.hang:  bra     .hang
+.done:  moveq   #0,d1
+        move.b  0(a1),d1
+        move.b  $800042,d2
+        stop    #$2700
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let rom = asm.assemble_source(ROM, Path::new("<test>")).unwrap();
+        sbc.load_rom(&rom);
+        sbc.reset();
+
+        sbc.run(100_000);
+        assert!(sbc.is_halted());
+        // Caused by the watchdog, which is now disabled, and the interrupt
+        // controller was reset along with it
+        assert_eq!(sbc.registers().d(0), 0x01);
+        assert_eq!(sbc.registers().d(1), 0);
+        assert_eq!(sbc.registers().d(2) & 0xFF, 0);
+
+        // A host reset clears the cause
+        sbc.reset();
+        assert_eq!(
+            sbc.watchdog
+                .lock()
+                .unwrap()
+                .read(crate::watchdog::regs::STATUS),
+            0
+        );
+    }
+
+    #[test]
+    fn test_sbc_watchdog_reset_keeps_console_and_ram() {
+        // Each boot counts itself in RAM and prints a letter; the first one
+        // then arms the watchdog and hangs
+        const ROM: &str = "
+        dc.l    $00F00000
+        dc.l    start
+start:
+        lea.l   $800080,a1
+        addq.b  #1,$C01000
+        btst    #0,12(a1)
+        bne     .done
+        move.b  #'A',$A00000
+        clr.b   4(a1)
+        move.b  #$4E,6(a1)
+        move.b  #$20,8(a1)
+        move.b  #$03,0(a1)
+.hang:  bra     .hang
+.done:  move.b  #'B',$A00000
+        moveq   #0,d0
+        move.b  $C01000,d0
+        stop    #$2700
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let rom = asm.assemble_source(ROM, Path::new("<test>")).unwrap();
+        sbc.load_rom(&rom);
+        sbc.reset();
+
+        sbc.run(100_000);
+        assert!(sbc.is_halted());
+        assert_eq!(sbc.registers().d(0), 2);
+        assert_eq!(sbc.drain_output(), b"AB");
+    }
+
+    #[test]
+    fn test_sbc_watchdog_nmi_mode() {
+        const PROGRAM: &str = "
+        bra     main
+nmi:
+        clr.b   0(a1)
+        moveq   #0,d0
+        move.b  12(a1),d0
+        moveq   #1,d6
+        rte
+main:
+        lea.l   $800080,a1
+        moveq   #0,d6
+        clr.b   4(a1)
+        move.b  #$4E,6(a1)
+        move.b  #$20,8(a1)
+        move.b  #$01,0(a1)
+.wait:  tst.b   d6
+        beq     .wait
+        stop    #$2700
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(PROGRAM, Path::new("<test>")).unwrap();
+        let nmi = APP_START + asm.symbols.get("nmi").unwrap() as u32;
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        // Level 7 autovector (vector 31); the watchdog bypasses the
+        // interrupt controller, which still masks everything
+        let _ = sbc.cpu.memory.load_binary(0x7C, &nmi.to_be_bytes());
+
+        sbc.run(100_000);
+        assert!(sbc.is_halted());
+        assert_eq!(sbc.registers().d(0), 0x02);
+        assert!(sbc.pc() >= APP_START);
+    }
+
+    #[test]
+    fn test_sbc_led_bar_walking_bit() {
+        let mut sbc = Sbc::new();
+        run_program(
+            &mut sbc,
+            "
+        lea.l   $8000A0,a1
+        moveq   #1,d0
+        moveq   #7,d1
+.walk:  move.b  d0,(a1)
+        lsl.b   #1,d0
+        dbra    d1,.walk
+        move.b  #$02,$A00008
+        moveq   #0,d2
+        move.b  (a1),d2
+        clr.b   (a1)
+        stop    #$2700
+",
+        );
+        // RTS turns on LED 0 without disturbing the others
+        assert_eq!(sbc.registers().d(2), 0x81);
+        assert_eq!(sbc.led_bar(), 0);
+        assert!(!sbc.led_state());
+        assert_eq!(
+            sbc.take_led_events(),
+            vec![0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x81, 0x00]
+        );
+        assert!(sbc.take_led_events().is_empty());
+    }
+
+    #[test]
+    fn test_sbc_panel_state() {
+        let mut sbc = Sbc::new();
+        run_program(
+            &mut sbc,
+            "
+        move.b  #$A5,$8000A0
+        move.b  #'O',$A00000
+        move.b  #'K',$A00000
+        lea.l   $800060,a1
+        move.b  #$03,4(a1)
+        move.b  #$E8,6(a1)
+        move.b  #$01,(a1)
+        stop    #$2700
+",
+        );
+        sbc.send_bytes(&[b'x'; 40]);
+
+        let panel = sbc.panel_state();
+        let sections: Vec<_> = panel.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(sections, ["cf", "gpio", "leds", "timer", "uart"]);
+        assert_eq!(panel["leds"]["value"], 0xA5);
+        assert_eq!(panel["gpio"]["direction"], 0);
+        assert_eq!(panel["uart"]["txBusy"], false);
+        let rx_fifo = panel["uart"]["rxFifo"].as_u64().unwrap();
+        assert!(rx_fifo > 0);
+        assert_eq!(panel["uart"]["inputQueued"].as_u64().unwrap(), 40 - rx_fifo);
+        assert_eq!(panel["cf"]["inserted"], false);
+        assert_eq!(panel["cf"]["busy"], false);
+        assert_eq!(panel["timer"]["running"], true);
+        assert_eq!(panel["timer"]["period"], 1000);
+        assert_eq!(sbc.drain_output(), b"OK");
+
+        // Devices left off the bus have no section
+        let mut config = SbcConfig::default();
+        config.peripherals.gpio = false;
+        let sbc = Sbc::new_with_config(config).unwrap();
+        assert!(sbc.panel_state()["gpio"].is_null());
+    }
+
+    #[test]
+    fn test_sbc_dump_peripheral_reads_without_side_effects() {
+        // The guest reads one byte of the input and stops
+        let mut sbc = Sbc::new();
+        run_program(
+            &mut sbc,
+            "
+        move.b  #'>',$A00000
+        stop    #$2700
+",
+        );
+        assert_eq!(sbc.send_bytes(b"hello"), 5);
+
+        let dump = sbc.dump_peripheral("uart").unwrap();
+        assert_eq!(dump["rxFifo"], serde_json::json!(b"hello"));
+        assert_eq!(dump["txFifo"], serde_json::json!([]));
+        assert_eq!(
+            dump["lsr"].as_u64().unwrap() & u64::from(crate::uart::lsr::DR),
+            1
+        );
+        // Dumping again finds the same FIFO, which the guest still reads
+        assert_eq!(sbc.dump_peripheral("uart").unwrap(), dump);
+        assert_eq!(sbc.cpu.memory.read_byte(0x00A0_0000).unwrap(), b'h');
+        assert_eq!(
+            sbc.dump_peripheral("uart").unwrap()["rxFifo"]
+                .as_array()
+                .unwrap()
+                .len(),
+            4
+        );
+
+        assert_eq!(sbc.dump_peripheral("cf").unwrap()["cache"]["hits"], 0);
+        assert!(sbc.dump_peripheral("timer").unwrap()["reload"].is_u64());
+        assert_eq!(sbc.dump_peripheral("dma"), None);
+    }
+
+    #[test]
+    fn test_sbc_buzzer_tone_from_guest() {
+        // 1 kHz tone (period 1000 us), left sounding
+        let mut sbc = Sbc::new();
+        run_program(
+            &mut sbc,
+            "
+        lea.l   $8000C0,a1
+        move.b  #$03,0(a1)
+        move.b  #$E8,2(a1)
+        move.b  #$01,4(a1)
+        stop    #$2700
+",
+        );
+
+        // 48 samples per period at 48 kHz, half high and half low
+        let samples = sbc.render_audio(10);
+        assert_eq!(samples.len(), 480);
+        let edges: Vec<usize> = (1..samples.len())
+            .filter(|&i| samples[i] != samples[i - 1])
+            .collect();
+        assert_eq!(edges.len(), 19);
+        assert!(edges.windows(2).all(|w| w[1] - w[0] == 24));
+
+        run_program(
+            &mut sbc,
+            "
+        move.w  #3000,d0
+.delay: dbra    d0,.delay
+        clr.b   $8000C4
+        stop    #$2700
+",
+        );
+        assert!(sbc.render_audio(1).iter().all(|&s| s == 0));
+        let events = sbc.take_buzzer_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].frequency, 1000);
+        assert!(events[0].duration_ms >= 3);
+        assert!(events[0].end_cycle > events[0].start_cycle);
+    }
+
+    #[test]
+    fn test_sbc_uart_bell() {
+        const PROGRAM: &str = "
+        move.b  #$07,$A00000
+        stop    #$2700
+";
+        let mut sbc = Sbc::new();
+        run_program(&mut sbc, PROGRAM);
+        assert!(sbc.take_buzzer_events().is_empty());
+
+        sbc.set_uart_bell(true);
+        run_program(&mut sbc, PROGRAM);
+        let events = sbc.take_buzzer_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].frequency, crate::buzzer::BELL_FREQUENCY);
+        assert_eq!(events[0].duration_ms, crate::buzzer::BELL_DURATION_MS);
+        assert_eq!(sbc.drain_output(), vec![0x07, 0x07]);
+    }
+
+    #[test]
+    fn test_sbc_spi_eeprom_from_guest() {
+        // Writes "HELLO" to $0100 of an EEPROM on CS0 and reads it back to
+        // $E02000, polling DONE after each byte
+        const PROGRAM: &str = "
+main:
+        lea.l   $8000E0,a1
+        move.b  #1,6(a1)
+        move.b  #$01,8(a1)
+        moveq   #$06,d0
+        bsr     xfer
+        clr.b   8(a1)
+        move.b  #$01,8(a1)
+        moveq   #$02,d0
+        bsr     xfer
+        moveq   #$01,d0
+        bsr     xfer
+        moveq   #$00,d0
+        bsr     xfer
+        lea     msg(pc),a0
+        moveq   #4,d2
+.wr:    move.b  (a0)+,d0
+        bsr     xfer
+        dbra    d2,.wr
+        clr.b   8(a1)
+        move.b  #$01,8(a1)
+        moveq   #$03,d0
+        bsr     xfer
+        moveq   #$01,d0
+        bsr     xfer
+        moveq   #$00,d0
+        bsr     xfer
+        lea.l   $E02000,a0
+        moveq   #4,d2
+.rd:    moveq   #0,d0
+        bsr     xfer
+        move.b  d0,(a0)+
+        dbra    d2,.rd
+        clr.b   8(a1)
+        stop    #$2700
+xfer:
+        move.b  d0,(a1)
+.busy:  btst    #1,4(a1)
+        beq     .busy
+        move.b  #$02,4(a1)
+        move.b  (a1),d0
+        rts
+msg:    dc.b    'HELLO'
+";
+        let eeprom = Arc::new(Mutex::new(crate::spi::SpiEeprom::new_25lc256()));
+        let mut sbc = Sbc::new();
+        sbc.attach_spi_device(0, Box::new(Arc::clone(&eeprom)));
+        run_program(&mut sbc, PROGRAM);
+
+        assert_eq!(&eeprom.lock().unwrap().data()[0x100..0x105], b"HELLO");
+        let mut readback = [0u8; 5];
+        for (i, byte) in readback.iter_mut().enumerate() {
+            *byte = sbc.cpu.memory.read_byte(0x00E0_2000 + i as u32).unwrap();
+        }
+        assert_eq!(&readback, b"HELLO");
+    }
+
+    #[test]
+    fn test_sbc_i2c_eeprom_from_guest() {
+        // Scans the bus, writes a page at $10 of the EEPROM it finds, polls
+        // for the end of the write cycle and reads the page back to $E02000.
+        // The address found goes to $E02010 and the poll count to $E02014.
+        // `wait` comes first so every call to it is a backward branch.
+        const PROGRAM: &str = "
+        bra     main
+wait:   btst    #0,6(a1)
+        bne     wait
+        rts
+main:
+        lea.l   $800100,a1
+        moveq   #0,d6
+        moveq   #0,d7
+        moveq   #$08,d1
+.scan:  move.b  d1,(a1)
+        move.b  #$11,4(a1)
+        bsr     wait
+        btst    #2,6(a1)
+        bne     .next
+        move.b  d1,d7
+        addq.b  #1,d6
+.next:  addq.b  #1,d1
+        cmp.b   #$78,d1
+        bne     .scan
+        move.b  d7,$E02010
+        move.b  d6,$E02011
+        move.b  d7,(a1)
+        move.b  #$01,4(a1)
+        bsr     wait
+        move.b  #$10,2(a1)
+        move.b  #$02,4(a1)
+        bsr     wait
+        lea     msg(pc),a0
+        moveq   #7,d2
+.wr:    move.b  (a0)+,2(a1)
+        move.b  #$02,4(a1)
+        bsr     wait
+        dbra    d2,.wr
+        move.b  #$10,4(a1)
+        bsr     wait
+        moveq   #0,d5
+.poll:  addq.l  #1,d5
+        move.b  #$11,4(a1)
+        bsr     wait
+        btst    #2,6(a1)
+        bne     .poll
+        move.l  d5,$E02014
+        move.b  #$01,4(a1)
+        bsr     wait
+        move.b  #$10,2(a1)
+        move.b  #$02,4(a1)
+        bsr     wait
+        move.b  #$05,4(a1)
+        bsr     wait
+        lea.l   $E02000,a0
+        moveq   #6,d2
+.rd:    move.b  #$04,4(a1)
+        bsr     wait
+        move.b  2(a1),(a0)+
+        dbra    d2,.rd
+        move.b  #$1C,4(a1)
+        bsr     wait
+        move.b  2(a1),(a0)+
+        stop    #$2700
+msg:    dc.b    'ABCDEFGH'
+";
+        let eeprom = Arc::new(Mutex::new(crate::i2c::I2cEeprom::new()));
+        let mut sbc = Sbc::new();
+        sbc.attach_i2c_device(0x50, Box::new(Arc::clone(&eeprom)));
+        run_program(&mut sbc, PROGRAM);
+
+        let read = |address: u32| sbc.cpu.memory.read_byte(address).unwrap();
+        assert_eq!(read(0x00E0_2010), 0x50);
+        assert_eq!(read(0x00E0_2011), 1);
+        assert_eq!(&eeprom.lock().unwrap().data()[0x10..0x18], b"ABCDEFGH");
+        // The EEPROM NACKed at least once during its write cycle
+        assert!(sbc.cpu.memory.read_long(0x00E0_2014).unwrap() > 1);
+        let readback: Vec<u8> = (0..8).map(|i| read(0x00E0_2000 + i)).collect();
+        assert_eq!(readback, b"ABCDEFGH");
+    }
+
+    #[test]
+    fn test_sbc_expansion_unused_slot_is_open_bus() {
+        let mut sbc = Sbc::new();
+        let _ = sbc.cpu.memory.write_byte(0x008F_0000, 0x12);
+        assert_eq!(sbc.cpu.memory.read_byte(0x008F_0000).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn test_sbc_cf_timeout_with_high_latency() {
+        // Calls the ROM's own command routine, found by assembling its source
+        let path = Path::new("rom/rom.asm");
+        let mut rom = crate::assembler::Assembler::new();
+        rom.include_paths.push(path.parent().unwrap().to_path_buf());
+        let image = rom
+            .assemble_source(&std::fs::read_to_string(path).unwrap(), path)
+            .unwrap();
+        assert_eq!(image, EMBEDDED_ROM[..image.len()]);
+        let sendcmd = rom.symbols.get("cfcard_sendcmd").unwrap();
+        let timeout = rom.symbols.get("FSERR_TIMEOUT").unwrap();
+
+        let program = format!(
+            "
+        lea.l   $900000,a1
+        move.b  #$E0,13(a1)
+        clr.b   7(a1)
+        move.b  #1,5(a1)
+        moveq   #$20,d0
+        jsr     ${sendcmd:X}
+        stop    #$2700
+"
+        );
+        let mut sbc = Sbc::new();
+        sbc.load_cf_bytes(&vec![0u8; 512 * 4]);
+        sbc.set_cf_timing(CfTiming {
+            command_cycles: u64::MAX / 2,
+            sector_cycles: 0,
+        });
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(&program, Path::new("<test>")).unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        sbc.run(50_000_000);
+        assert!(sbc.is_halted());
+        assert_eq!(i64::from(sbc.registers().d(0)), timeout);
+        assert!(sbc.cfcard().lock().unwrap().is_busy());
+    }
+
+    #[test]
+    fn test_sbc_cf_hot_swap_card_detect() {
+        // Waits for removal and re-insertion via MSR CTS, then re-runs the
+        // ROM-style init (status == $50) and IDENTIFY. D2 records progress,
+        // D3 receives the first IDENTIFY word (or -1 if no card was found).
+        const PROGRAM: &str = "
+        lea.l   $A00000,a2
+.out:   btst.b  #4,12(a2)
+        bne     .out
+        moveq   #1,d2
+.in:    btst.b  #4,12(a2)
+        beq     .in
+        moveq   #2,d2
+        lea.l   $900000,a1
+        moveq   #-1,d0
+.find:  cmp.b   #$50,15(a1)
+        beq     .found
+        dbra    d0,.find
+        moveq   #-1,d3
+        bra     .done
+.found: move.b  #$EC,15(a1)
+.busy:  btst.b  #7,15(a1)
+        bne     .busy
+        move.w  (a1),d3
+.done:  stop    #$2700
+";
+        let mut sbc = Sbc::new();
+        sbc.load_cf_bytes(&vec![0u8; 512 * 16]);
+        sbc.set_card_detect_enabled(true);
+
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(PROGRAM, Path::new("<test>")).unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+
+        sbc.run(10_000);
+        assert_eq!(sbc.registers().d(2), 0);
+
+        sbc.eject_cf().unwrap();
+        sbc.run(10_000);
+        assert_eq!(sbc.registers().d(2), 1);
+        assert!(!sbc.is_halted());
+
+        sbc.insert_cf(CfImage::Bytes(vec![0u8; 512 * 16])).unwrap();
+        sbc.run(1_000_000);
+        assert!(sbc.is_halted());
+        assert_eq!(sbc.registers().d(2), 2);
+        assert_eq!(sbc.registers().d(3) & 0xFFFF, 0x848A);
+    }
+
+    #[test]
+    fn test_sbc_cf_interrupt_driven_read() {
+        // The main loop enables the CF source in the interrupt controller and
+        // never touches the CF after issuing the command; the level 2
+        // handler acknowledges INTRQ and copies one sector per interrupt
+        // into $E02000, counting blocks in D5.
+        const PROGRAM: &str = "
+        bra     main
+handler:
+        move.b  15(a1),d0
+        btst    #3,d0
+        beq     .none
+        move.w  #255,d1
+.copy:  move.w  (a1),(a0)+
+        dbra    d1,.copy
+        addq.w  #1,d5
+.none:  rte
+main:
+        lea.l   $900000,a1
+        lea.l   $E02000,a0
+        moveq   #0,d5
+        move.b  #$02,$800042
+        move.b  #0,29(a1)
+        move.b  #$E0,13(a1)
+        move.b  #0,7(a1)
+        move.b  #4,5(a1)
+        move.b  #$20,15(a1)
+.wait:  cmp.w   #4,d5
+        bne     .wait
+        stop    #$2700
+";
+        let mut sbc = Sbc::new();
+        let mut image = vec![0u8; 512 * 8];
+        for sector in 0..4 {
+            image[sector * 512..(sector + 1) * 512].fill(0x10 + sector as u8);
+        }
+        sbc.load_cf_bytes(&image);
+
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(PROGRAM, Path::new("<test>")).unwrap();
+        let handler = APP_START + asm.symbols.get("handler").unwrap() as u32;
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        // Level 2 autovector (vector 26)
+        let _ = sbc.cpu.memory.load_binary(0x68, &handler.to_be_bytes());
+
+        sbc.run(1_000_000);
+        assert!(sbc.is_halted());
+        assert_eq!(sbc.registers().d(5) & 0xFFFF, 4);
+        for sector in 0..4u32 {
+            let addr = 0x00E0_2000 + sector * 512;
+            assert_eq!(sbc.cpu.memory.read_byte(addr).unwrap(), 0x10 + sector as u8);
+            assert_eq!(
+                sbc.cpu.memory.read_byte(addr + 511).unwrap(),
+                0x10 + sector as u8
+            );
+        }
+    }
+
+    #[test]
+    fn test_sbc_trace_records_executed_instructions() {
+        let mut sbc = Sbc::new();
+        start_program(
+            &mut sbc,
+            "
+        moveq   #3,d0
+loop:   addq.l  #1,d1
+        subq.l  #1,d0
+        bne.s   loop
+        moveq   #0,d0
+        trap    #0
+",
+        );
+        sbc.start_trace(64);
+        assert_eq!(sbc.step_n(10, false, 0).run.instructions, 10);
+        sbc.stop_trace();
+        sbc.run(1_000_000);
+
+        let trace = sbc.trace(100, true);
+        let pcs: Vec<_> = trace.iter().map(|entry| entry.pc - APP_START).collect();
+        assert_eq!(pcs, [0, 2, 4, 6, 2, 4, 6, 2, 4, 6]);
+        let indices: Vec<_> = trace.iter().map(|entry| entry.index).collect();
+        assert_eq!(indices, (0..10).collect::<Vec<_>>());
+        assert_eq!(trace[0].opcode, 0x7003);
+        assert_eq!(trace[0].mnemonic.as_deref(), Some("moveq #3,d0"));
+        assert_eq!(trace[3].mnemonic.as_deref(), Some("bne.s $E00102"));
+        assert!(trace.windows(2).all(|pair| pair[0].cycles < pair[1].cycles));
+        assert!(sbc.trace(100, false).iter().all(|e| e.mnemonic.is_none()));
+
+        // A shallow trace keeps only the latest instructions
+        sbc.start_trace(4);
+        start_program(
+            &mut sbc,
+            "        nop\n        nop\n        moveq   #0,d0\n        trap    #0\n",
+        );
+        sbc.step_n(3, false, 0);
+        sbc.step();
+        let trace = sbc.trace(3, false);
+        let indices: Vec<_> = trace.iter().map(|entry| entry.index).collect();
+        assert_eq!(indices, [1, 2, 3]);
+        assert_eq!(trace[2].opcode, 0x4E40);
+    }
+
+    #[test]
+    fn test_sbc_profile_finds_hot_loop() {
+        const SOURCE: &str = "
+        org     $E00100
+start:  move.w  #2000,d0
+        moveq   #0,d1
+loop:   addq.l  #1,d1
+        dbra    d0,loop
+        bsr     tail
+        moveq   #0,d0
+        trap    #0
+tail:   nop
+        rts
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(SOURCE, Path::new("<test>")).unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        sbc.debugger_mut().set_symbols(asm.labels.iter().cloned());
+
+        // Profiling joins a run in progress, as it would a background run
+        sbc.run(100);
+        sbc.start_profile();
+        let run = sbc.run(1_000_000);
+        assert_eq!(run.stop, StopReason::Exit { code: 0 });
+        sbc.stop_profile();
+
+        let report = sbc.profile_report(3);
+        assert_eq!(report.total_cycles, run.cycles);
+        assert_eq!(report.instructions, run.instructions);
+        assert_eq!(report.hotspots.len(), 3);
+        let hottest: Vec<_> = report.hotspots[..2]
+            .iter()
+            .map(|h| h.symbol.as_deref().unwrap())
+            .collect();
+        assert!(
+            hottest.iter().all(|name| name.starts_with("loop")),
+            "{hottest:?}"
+        );
+        assert!(report.hotspots[0].percent + report.hotspots[1].percent > 90.0);
+        assert!(report.hotspots[0].instruction.starts_with("dbra"));
+        let mnemonics: Vec<_> = report
+            .mnemonics
+            .iter()
+            .map(|m| m.mnemonic.as_str())
+            .collect();
+        assert_eq!(mnemonics[..2], ["dbra", "addq.l"]);
+
+        // Samples stop with the profiler and go with a reset
+        sbc.run_app(None);
+        sbc.run(1_000_000);
+        assert_eq!(sbc.profile_report(3).total_cycles, run.cycles);
+        sbc.reset_profile();
+        assert_eq!(sbc.profile_report(3).total_cycles, 0);
+    }
+
+    #[test]
+    fn test_sbc_step_detailed_reports_trap_and_output() {
+        let mut sbc = Sbc::new();
+        start_program(
+            &mut sbc,
+            "
+        org     $E00100
+start:  moveq   #$41,d0
+        trap    #15
+        moveq   #0,d0
+        trap    #0
+",
+        );
+        // Route TRAP #15 to the OutChar stub
+        let putchar = sbc.cpu().memory.read_long(0x88).unwrap();
+        sbc.cpu_mut()
+            .memory
+            .load_binary(0xBC, &putchar.to_be_bytes())
+            .unwrap();
+
+        let step = sbc.step_detailed();
+        assert_eq!((step.pc_before, step.pc_after), (0x00E0_0100, 0x00E0_0102));
+        assert_eq!((step.opcode, step.exception_vector), (0x7041, None));
+        assert!(step.cycles > 0 && !step.halted);
+
+        let step = sbc.step_detailed();
+        assert_eq!(step.opcode, 0x4E4F);
+        assert_eq!(step.exception_vector, Some(47));
+        assert_eq!(step.pc_after, putchar);
+
+        // Step through the handler until it returns
+        let mut output = Vec::new();
+        while sbc.pc() != 0x00E0_0104 {
+            let step = sbc.step_detailed();
+            assert!(!step.halted);
+            output.extend(step.uart_output);
+        }
+        assert_eq!(output, b"A");
+        // The output went out with the steps, not through the buffer
+        assert!(sbc.drain_output().is_empty());
+    }
+
+    #[test]
+    fn test_sbc_stack_flags_return_addresses() {
+        const SOURCE: &str = "
+        org     $E00100
+start:  bsr     outer
+ret:    moveq   #0,d0
+        trap    #0
+outer:  link    a6,#-8
+        move.l  #inner+2,-8(a6)
+        move.l  #$12345678,-4(a6)
+        jsr     inner
+back:   unlk    a6
+        rts
+inner:  nop
+        rts
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(SOURCE, Path::new("<test>")).unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        let label = |name: &str| {
+            let (_, addr) = asm.labels.iter().find(|(label, _)| label == name).unwrap();
+            *addr
+        };
+        sbc.debugger_mut().set_symbols(asm.labels.iter().cloned());
+        sbc.debugger_mut().add_breakpoint(label("inner"));
+        let stop = sbc.run(1_000_000).stop;
+        assert!(matches!(stop, StopReason::Breakpoint { .. }), "{stop:?}");
+
+        let sp = sbc.registers().sp();
+        let stack = sbc.stack(16);
+        let values: Vec<_> = stack.iter().map(|entry| entry.value).collect();
+        assert_eq!(
+            values[..5],
+            [
+                label("back"),
+                label("inner") + 2,
+                0x1234_5678,
+                0,
+                label("ret")
+            ]
+        );
+        for (i, entry) in stack.iter().enumerate() {
+            assert_eq!(entry.address, sp + 4 * i as u32);
+            assert_eq!(entry.return_address, i == 0 || i == 4, "entry {i}");
+            assert_eq!(entry.in_frame, i < 5, "entry {i}");
+        }
+        assert_eq!(stack[0].symbol.as_deref(), Some("back"));
+        // Entry 1 points just past a NOP, so it only gets a symbol
+        assert_eq!(stack[1].symbol.as_deref(), Some("inner+$2"));
+        assert_eq!(stack[2].symbol, None);
+        assert_eq!(stack[4].symbol.as_deref(), Some("ret"));
+        assert_eq!(sbc.stack(2).len(), 2);
+    }
+
+    #[test]
+    fn test_sbc_call_stack_follows_link_frames() {
+        const SOURCE: &str = "
+        org     $E00100
+first:  link    a6,#-4
+        bsr     second
+ret1:   unlk    a6
+        moveq   #0,d0
+        trap    #0
+second: link    a6,#0
+        move.l  #$E00100,-(sp)
+        bsr     third
+ret2:   addq.l  #4,sp
+        unlk    a6
+        rts
+third:  link    a6,#-8
+        nop
+        unlk    a6
+        rts
+leaf:   bsr     inner
+ret3:   rts
+inner:  nop
+        rts
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(SOURCE, Path::new("<test>")).unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        let label = |name: &str| {
+            let (_, addr) = asm.labels.iter().find(|(label, _)| label == name).unwrap();
+            *addr
+        };
+        sbc.debugger_mut().set_symbols(asm.labels.iter().cloned());
+        sbc.debugger_mut().add_breakpoint(label("third") + 4);
+        let stop = sbc.run(1_000_000).stop;
+        assert!(matches!(stop, StopReason::Breakpoint { .. }), "{stop:?}");
+
+        let frames = sbc.call_stack(16);
+        let addresses: Vec<_> = frames.iter().map(|frame| frame.return_address).collect();
+        assert_eq!(
+            addresses,
+            [label("third") + 4, label("ret2"), label("ret1")]
+        );
+        assert!(frames.iter().all(|frame| frame.is_link_frame));
+        let symbols: Vec<_> = frames.iter().map(|frame| frame.symbol.as_deref()).collect();
+        assert_eq!(symbols, [Some("third"), Some("ret2"), Some("ret1")]);
+        assert_eq!(frames[0].offset, Some(4));
+        assert_eq!(frames[0].frame_pointer, Some(sbc.registers().a(6)));
+        // The outermost routine's saved A6 (zero) isn't a frame
+        assert!(frames[1].frame_pointer.is_some());
+        assert_eq!(frames[2].frame_pointer, None);
+        assert_eq!(sbc.call_stack(2).len(), 2);
+        assert!(sbc.call_stack(0).is_empty());
+
+        // Without LINK frames, return addresses are found by scanning
+        sbc.registers_mut().set_a(6, 0);
+        sbc.registers_mut().set_pc(label("leaf"));
+        sbc.debugger_mut().add_breakpoint(label("inner"));
+        let stop = sbc.run(1_000_000).stop;
+        assert!(matches!(stop, StopReason::Breakpoint { .. }), "{stop:?}");
+        let frames = sbc.call_stack(16);
+        assert_eq!(frames[0].return_address, label("inner"));
+        assert!(!frames[0].is_link_frame);
+        assert_eq!(frames[1].return_address, label("ret3"));
+        assert_eq!(frames[1].symbol.as_deref(), Some("ret3"));
+        assert!(frames[1..].iter().all(|frame| !frame.is_link_frame));
+    }
+
+    #[test]
+    fn test_sbc_disassemble_program() {
+        const SOURCE: &str = "
+start:  lea     msg,a0
+        moveq   #0,d0
+loop:   move.b  (a0)+,d1
+        beq     done
+        addq.l  #1,d0
+        bra     loop
+done:   trap    #0
+msg:    dc.b    \"hi\",0
+";
+        let mut sbc = Sbc::new();
+        let mut asm = crate::assembler::Assembler::new();
+        let app = asm.assemble_source(SOURCE, Path::new("<test>")).unwrap();
+        sbc.load_app(&app, None).unwrap();
+        sbc.run_app(None);
+        sbc.debugger_mut().set_symbols(
+            asm.labels
+                .iter()
+                .map(|(name, addr)| (name.clone(), APP_START + addr)),
+        );
+
+        let lines = sbc.disassemble(APP_START, 7);
+        let source: Vec<&str> = SOURCE
+            .lines()
+            .filter_map(|line| line.split_whitespace().find(|word| !word.ends_with(':')))
+            .collect();
+        for (line, mnemonic) in lines.iter().zip(&source) {
+            assert!(
+                line.mnemonic == *mnemonic || line.mnemonic.starts_with(&format!("{mnemonic}.")),
+                "{} vs {mnemonic}",
+                line.mnemonic
+            );
+        }
+        assert_eq!(lines[0].symbol.as_deref(), Some("start"));
+        assert!(lines[0].is_current_pc && !lines[1].is_current_pc);
+        assert_eq!(lines[1].bytes_hex, "7000");
+        assert_eq!(lines[1].operands, "#0,d0");
+        assert_eq!(lines[2].symbol.as_deref(), Some("loop"));
+        assert_eq!(lines[6].symbol.as_deref(), Some("done"));
+        assert_eq!(lines[5].operands, format!("${:X}", lines[2].addr));
+
+        // Running off the end of RAM ends the listing
+        sbc.cpu.memory.write_word(0x00EF_FFFE, 0x4E71).unwrap();
+        let lines = sbc.disassemble(0x00EF_FFFE, 4);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].mnemonic, "nop");
+        assert_eq!(lines[1].addr, 0x00F0_0000);
+        assert_eq!(lines[1].mnemonic, DISASSEMBLY_END);
+    }
+
+    #[test]
+    fn test_sbc_load_binary_file() {
+        let mut asm = crate::assembler::Assembler::new();
+        let binary = asm
+            .assemble_source(
+                "\n        move.b  #'B',$A00000\n        stop    #$2700\n",
+                Path::new("<test>"),
+            )
+            .unwrap();
+        let path =
+            std::env::temp_dir().join(format!("flux32-sbc-binary-{}.bin", std::process::id()));
+        std::fs::write(&path, &binary).unwrap();
+
+        let mut sbc = Sbc::new();
+        sbc.run(100_000);
+        let _ = sbc.drain_output();
+        assert_eq!(
+            sbc.load_binary_file(&path, 0x00C1_0000, false),
+            Ok(binary.len())
+        );
+        sbc.cpu.set_pc(0x00C1_0000);
+        sbc.cpu.resume();
+        sbc.run(1000);
+        assert!(sbc.is_halted());
+        assert_eq!(sbc.drain_output(), b"B");
+
+        // ROM only with the override; the region must hold the whole file
+        assert_eq!(
+            sbc.load_binary_file(&path, 0x0000_8000, false),
+            Err(BinaryLoadError::NotWritable { addr: 0x0000_8000 })
+        );
+        assert_eq!(
+            sbc.load_binary_file(&path, 0x0000_8000, true),
+            Ok(binary.len())
+        );
+        assert_eq!(sbc.cpu.memory.read_word(0x0000_8000).unwrap(), 0x13FC);
+        assert_eq!(
+            sbc.load_binary_file(&path, 0x00EF_FFFC, false),
+            Err(BinaryLoadError::TooLarge {
+                addr: 0x00EF_FFFC,
+                size: binary.len(),
+                available: 4,
+            })
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            sbc.load_binary_file(&path, 0x00C1_0000, false),
+            Err(BinaryLoadError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sbc_snapshot_round_trip() {
+        let mut sbc = Sbc::new();
+        start_program(&mut sbc, SLOW_ECHO);
+        let input: Vec<u8> = (0..200u32).map(|i| b'a' + (i % 26) as u8).collect();
+        sbc.send_bytes(&input);
+        sbc.run(20_000);
+
+        let path =
+            std::env::temp_dir().join(format!("flux32-sbc-snapshot-{}.bin", std::process::id()));
+        sbc.snapshot().save(&path).unwrap();
+        let registers = sbc.registers().clone();
+        let output = sbc.peek_output().to_vec();
+        let queued = sbc.input_queued();
+        assert!(!output.is_empty() && queued > 0);
+
+        // Where the original goes from here
+        sbc.run(2_000_000);
+        let expected_registers = sbc.registers().clone();
+        let expected_output = sbc.drain_output();
+
+        let info = Snapshot::load_info(&path).unwrap();
+        assert_eq!(info.version, SNAPSHOT_VERSION);
+        assert_eq!(info.pc, registers.pc);
+        assert_eq!(&info.config, sbc.config());
+
+        let mut restored = Sbc::from_snapshot(Snapshot::load(&path).unwrap()).unwrap();
+        assert_eq!(restored.registers(), &registers);
+        assert_eq!(restored.peek_output(), output.as_slice());
+        assert_eq!(restored.input_queued(), queued);
+        restored.run(2_000_000);
+        assert_eq!(restored.registers(), &expected_registers);
+        assert_eq!(restored.drain_output(), expected_output);
+
+        // Other versions and other files are rejected
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[11] = 99;
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            Snapshot::load(&path).err(),
+            Some(SnapshotError::Version {
+                found: 99,
+                expected: SNAPSHOT_VERSION,
+            })
+        );
+        std::fs::write(&path, b"not a snapshot").unwrap();
+        assert_eq!(
+            Snapshot::load_info(&path).err(),
+            Some(SnapshotError::NotSnapshot)
+        );
+        std::fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,152 @@
+//! Master Cycle Scheduler
+//!
+//! This module keeps the machine's master cycle count and a queue of
+//! future peripheral events ordered by cycle. Every instruction's cycles are
+//! handed to each clocked peripheral so devices stay in lockstep with the
+//! CPU; the queue tells the run loop when the next device will change state
+//! on its own (a timer expiring, a CF command completing, ...), so a
+//! stopped CPU can skip straight to it instead of idling cycle by cycle.
+//!
+//! Each source has at most one pending event; scheduling it again moves
+//! the event. Events at the same cycle fire in source order.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A peripheral driven by the master clock
+pub trait Clocked: Send {
+    /// Advances the device by the given number of CPU cycles
+    fn tick(&mut self, cycles: u64);
+
+    /// Returns the number of cycles until the device next changes state on
+    /// its own, or `None` while it is idle
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Peripheral an event belongs to
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum EventSource {
+    /// `CompactFlash` command completion
+    Cf,
+    /// RTC host clock poll
+    Rtc,
+    /// Timer expiry
+    Timer,
+    /// Buzzer
+    Buzzer,
+    /// SPI transfer completion
+    Spi,
+    /// I2C command completion
+    I2c,
+    /// Watchdog timeout
+    Watchdog,
+}
+
+/// Master cycle count and event queue
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Scheduler {
+    /// Cycles elapsed since the scheduler was created
+    now: u64,
+    /// Pending events ordered by cycle
+    queue: BTreeSet<(u64, EventSource)>,
+    /// Cycle of each source's pending event
+    pending: BTreeMap<EventSource, u64>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler at cycle 0 with no events
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            now: 0,
+            queue: BTreeSet::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the master cycle count
+    #[must_use]
+    pub const fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Advances the master cycle count
+    pub const fn advance(&mut self, cycles: u64) {
+        self.now += cycles;
+    }
+
+    /// Schedules an event `delay` cycles from now, replacing the source's
+    /// pending event
+    pub fn schedule(&mut self, source: EventSource, delay: u64) {
+        self.cancel(source);
+        let at = self.now + delay;
+        self.queue.insert((at, source));
+        self.pending.insert(source, at);
+    }
+
+    /// Drops the source's pending event, if any
+    pub fn cancel(&mut self, source: EventSource) {
+        if let Some(at) = self.pending.remove(&source) {
+            self.queue.remove(&(at, source));
+        }
+    }
+
+    /// Returns the cycle of the earliest pending event
+    #[must_use]
+    pub fn next_event(&self) -> Option<u64> {
+        self.queue.first().map(|&(at, _)| at)
+    }
+
+    /// Removes and returns the earliest event that is due (at or before
+    /// now), with the cycle it was scheduled for
+    #[cfg(test)]
+    pub fn pop_due(&mut self) -> Option<(u64, EventSource)> {
+        let &(at, source) = self.queue.first()?;
+        if at > self.now {
+            return None;
+        }
+        self.queue.remove(&(at, source));
+        self.pending.remove(&source);
+        Some((at, source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scheduler_fires_in_cycle_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(EventSource::Timer, 300);
+        scheduler.schedule(EventSource::Cf, 100);
+        scheduler.schedule(EventSource::Spi, 500);
+        // Rescheduling moves the event
+        scheduler.schedule(EventSource::Spi, 200);
+        assert_eq!(scheduler.next_event(), Some(100));
+
+        let mut fired = Vec::new();
+        while let Some(at) = scheduler.next_event() {
+            scheduler.advance(at - scheduler.now());
+            while let Some(event) = scheduler.pop_due() {
+                fired.push(event);
+            }
+        }
+        assert_eq!(
+            fired,
+            vec![
+                (100, EventSource::Cf),
+                (200, EventSource::Spi),
+                (300, EventSource::Timer),
+            ]
+        );
+        assert_eq!(scheduler.now(), 300);
+
+        scheduler.schedule(EventSource::I2c, 10);
+        scheduler.cancel(EventSource::I2c);
+        assert_eq!(scheduler.next_event(), None);
+    }
+}
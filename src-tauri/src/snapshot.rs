@@ -0,0 +1,271 @@
+//! Machine Snapshots
+//!
+//! This module defines the snapshot `Sbc::snapshot` captures and
+//! `Sbc::from_snapshot` restores, and its file format.
+//!
+//! ## File Format
+//!
+//! ```text
+//! magic    8 bytes   "F32SNAP\0"
+//! version  u32       SNAPSHOT_VERSION
+//! info     u32 length, then `SnapshotInfo` as JSON
+//! state    u32 length, then `MachineState` as JSON
+//! rom      ROM_SIZE bytes
+//! ram      ramSize bytes at $C00000, then ramSize bytes at $E00000
+//! ```
+//!
+//! Integers are big-endian. The info block comes first so a snapshot can
+//! be described without reading the rest of the file.
+//!
+//! ## What Isn't Saved
+//!
+//! Host-side state stays out of snapshots: the `CompactFlash` medium (the
+//! image the configuration names is inserted as it is on disk, and overlay
+//! writes are dropped), devices attached to the SPI and I2C buses, the
+//! RTC's host clock, and the debugger's breakpoints and symbols.
+
+use crate::buzzer::Buzzer;
+use crate::cfcard::CfCard;
+use crate::config::{ConfigError, SbcConfig};
+use crate::cpu::CpuSnapshot;
+use crate::dipswitch::DipSwitch;
+use crate::gpio::Gpio;
+use crate::i2c::I2cController;
+use crate::intc::InterruptController;
+use crate::led::LedBar;
+use crate::rtc::Rtc;
+use crate::sbc::ROM_SIZE;
+use crate::scheduler::Scheduler;
+use crate::spi::SpiController;
+use crate::timer::Timer;
+use crate::uart::Uart16550;
+use crate::watchdog::Watchdog;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// Magic bytes at the start of a snapshot file
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"F32SNAP\0";
+
+/// Snapshot format version; files with another version are rejected
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Errors that can occur while saving or loading a snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// Reading or writing the file failed
+    Io {
+        path: String,
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// The file doesn't start with the snapshot magic
+    NotSnapshot,
+    /// The file has another format version
+    Version { found: u32, expected: u32 },
+    /// The file is truncated or its contents don't parse
+    Corrupt { message: String },
+    /// The saved configuration can't be applied
+    Config(ConfigError),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, message, .. } => write!(f, "Snapshot {path}: {message}"),
+            Self::NotSnapshot => write!(f, "Not a Flux32 snapshot file"),
+            Self::Version { found, expected } => write!(
+                f,
+                "Snapshot version {found} is not supported (expected {expected})"
+            ),
+            Self::Corrupt { message } => write!(f, "Corrupt snapshot: {message}"),
+            Self::Config(e) => write!(f, "Snapshot configuration: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// Snapshot metadata, readable without loading the snapshot
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    /// Format version
+    pub version: u32,
+    /// Creation time in seconds since the Unix epoch
+    pub created: u64,
+    /// Program counter
+    pub pc: u32,
+    /// CPU cycles since the last reset
+    pub cycles: u64,
+    /// Configuration the machine was built with
+    pub config: SbcConfig,
+}
+
+/// CPU and peripheral state
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct MachineState {
+    /// CPU registers and execution state
+    pub cpu: CpuSnapshot,
+    /// UART
+    pub uart: Uart16550,
+    /// `CompactFlash` registers (without the medium)
+    pub cfcard: CfCard,
+    /// Real-time clock
+    pub rtc: Rtc,
+    /// GPIO port
+    pub gpio: Gpio,
+    /// Interrupt controller
+    pub intc: InterruptController,
+    /// Interval timer
+    pub timer: Timer,
+    /// Watchdog timer
+    pub watchdog: Watchdog,
+    /// LED bar
+    pub leds: LedBar,
+    /// Buzzer
+    pub buzzer: Buzzer,
+    /// SPI controller (without attached devices)
+    pub spi: SpiController,
+    /// I2C controller (without attached devices)
+    pub i2c: I2cController,
+    /// DIP switch
+    pub dipswitch: DipSwitch,
+    /// Master cycle count and pending events
+    pub scheduler: Scheduler,
+    /// UART output not yet drained by the host
+    pub uart_output: Vec<u8>,
+    /// Host input waiting for room in the RX FIFO
+    pub uart_input: VecDeque<u8>,
+    /// Instructions executed since the last reset
+    #[serde(default)]
+    pub instructions: u64,
+    /// The embedded ROM is loaded
+    pub embedded_rom: bool,
+    /// The app-mode TRAP stubs are installed
+    pub app_stubs: bool,
+}
+
+/// A complete machine snapshot
+pub struct Snapshot {
+    /// Metadata
+    pub info: SnapshotInfo,
+    /// CPU and peripheral state
+    pub state: MachineState,
+    /// ROM image (`ROM_SIZE` bytes)
+    pub rom: Vec<u8>,
+    /// Both RAM windows, $C00000 first (`ramSize` bytes each)
+    pub ram: Vec<u8>,
+}
+
+/// Maps a read or parse error to `SnapshotError::Corrupt`
+fn corrupt(e: impl fmt::Display) -> SnapshotError {
+    SnapshotError::Corrupt {
+        message: e.to_string(),
+    }
+}
+
+/// Reads a length-prefixed block
+fn read_block(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let mut block = vec![0; u32::from_be_bytes(len) as usize];
+    reader.read_exact(&mut block)?;
+    Ok(block)
+}
+
+/// Reads the magic, version and info block
+fn read_header(reader: &mut impl Read) -> Result<SnapshotInfo, SnapshotError> {
+    let mut magic = [0; 8];
+    let mut version = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|_| SnapshotError::NotSnapshot)?;
+    if &magic != SNAPSHOT_MAGIC {
+        return Err(SnapshotError::NotSnapshot);
+    }
+    reader.read_exact(&mut version).map_err(corrupt)?;
+    let version = u32::from_be_bytes(version);
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::Version {
+            found: version,
+            expected: SNAPSHOT_VERSION,
+        });
+    }
+    let info = read_block(reader).map_err(corrupt)?;
+    serde_json::from_slice(&info).map_err(corrupt)
+}
+
+impl Snapshot {
+    /// Serializes the snapshot in the file format
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let info = serde_json::to_vec(&self.info).expect("snapshot info serializes");
+        let state = serde_json::to_vec(&self.state).expect("machine state serializes");
+
+        let mut out =
+            Vec::with_capacity(20 + info.len() + state.len() + self.rom.len() + self.ram.len());
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.extend_from_slice(&SNAPSHOT_VERSION.to_be_bytes());
+        for block in [&info, &state] {
+            out.extend_from_slice(&(block.len() as u32).to_be_bytes());
+            out.extend_from_slice(block);
+        }
+        out.extend_from_slice(&self.rom);
+        out.extend_from_slice(&self.ram);
+        out
+    }
+
+    /// Parses a snapshot, checking the memory images against its
+    /// configuration
+    pub fn read(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        let info = read_header(reader)?;
+        let state = read_block(reader).map_err(corrupt)?;
+        let state = serde_json::from_slice(&state).map_err(corrupt)?;
+
+        let mut rom = vec![0; ROM_SIZE];
+        reader.read_exact(&mut rom).map_err(corrupt)?;
+        let mut ram = vec![0; 2 * info.config.ram_size as usize];
+        reader.read_exact(&mut ram).map_err(corrupt)?;
+        if reader.read(&mut [0]).map_err(corrupt)? != 0 {
+            return Err(corrupt("trailing data"));
+        }
+
+        Ok(Self {
+            info,
+            state,
+            rom,
+            ram,
+        })
+    }
+
+    /// Writes the snapshot to a file
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        std::fs::write(path, self.to_bytes()).map_err(|e| SnapshotError::Io {
+            path: path.display().to_string(),
+            kind: e.kind(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Reads a snapshot file
+    pub fn load(path: &Path) -> Result<Self, SnapshotError> {
+        Self::read(&mut BufReader::new(open(path)?))
+    }
+
+    /// Reads a snapshot file's metadata, without the rest of the file
+    pub fn load_info(path: &Path) -> Result<SnapshotInfo, SnapshotError> {
+        read_header(&mut BufReader::new(open(path)?))
+    }
+}
+
+/// Opens a snapshot file for reading
+fn open(path: &Path) -> Result<File, SnapshotError> {
+    File::open(path).map_err(|e| SnapshotError::Io {
+        path: path.display().to_string(),
+        kind: e.kind(),
+        message: e.to_string(),
+    })
+}
@@ -0,0 +1,508 @@
+//! SPI Controller Emulation
+//!
+//! This module emulates an SPI master on the expansion bus. Firmware selects
+//! a device with the chip-select register and writes a byte to the data
+//! register; the controller shifts it out to the selected devices and shifts
+//! their reply in. Devices implement [`SpiDevice`] and are attached by the
+//! host to one of eight chip-select lines.
+//!
+//! ## Register Map (offsets from $8000E0)
+//!
+//! | Offset | Read            | Write                        |
+//! |--------|-----------------|------------------------------|
+//! | 0      | Received byte   | Transmit byte (starts xfer)  |
+//! | 2      | Control         | Control (IE)                 |
+//! | 4      | Status          | Clear DONE (write 1s)        |
+//! | 6      | Clock divisor   | Clock divisor                |
+//! | 8      | Chip selects    | Chip selects (bit N = CS N)  |
+//!
+//! A transfer takes 16 × (divisor + 1) CPU cycles (SCK = 12 MHz / (2 ×
+//! (divisor + 1))). BUSY is set while it runs; when it finishes the received
+//! byte appears in the data register and DONE is set. Writes to the data
+//! register while BUSY are ignored. With no device selected, the received
+//! byte is $FF.
+//!
+//! ## Interrupts
+//!
+//! With IE set in the control register, the controller asserts its
+//! interrupt line while DONE is set.
+
+use std::sync::{Arc, Mutex};
+
+use crate::scheduler::Clocked;
+
+/// Number of chip-select lines
+pub const CS_LINES: usize = 8;
+
+/// SPI controller register offsets
+pub mod regs {
+    /// Data register
+    pub const DATA: u32 = 0;
+    /// Control register
+    pub const CONTROL: u32 = 2;
+    /// Status register
+    pub const STATUS: u32 = 4;
+    /// Clock divisor
+    pub const DIVISOR: u32 = 6;
+    /// Chip-select lines
+    pub const CS: u32 = 8;
+}
+
+/// Control register bits
+pub mod control {
+    /// Enable the transfer-done interrupt
+    pub const IE: u8 = 0x10;
+}
+
+/// Status register bits
+pub mod status {
+    /// A transfer is in progress
+    pub const BUSY: u8 = 0x01;
+    /// A transfer finished (write 1 to clear)
+    pub const DONE: u8 = 0x02;
+}
+
+/// A device on the SPI bus
+pub trait SpiDevice: Send {
+    /// Exchanges one byte while the device is selected
+    fn transfer(&mut self, mosi: u8) -> u8;
+
+    /// Called when the device's chip select is asserted
+    fn select(&mut self) {}
+
+    /// Called when the device's chip select is released
+    fn deselect(&mut self) {}
+}
+
+/// Lets the host keep a handle on a device it attached
+impl<T: SpiDevice> SpiDevice for Arc<Mutex<T>> {
+    fn transfer(&mut self, mosi: u8) -> u8 {
+        self.lock().unwrap().transfer(mosi)
+    }
+
+    fn select(&mut self) {
+        self.lock().unwrap().select();
+    }
+
+    fn deselect(&mut self) {
+        self.lock().unwrap().deselect();
+    }
+}
+
+/// SPI master controller peripheral
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct SpiController {
+    /// Control register
+    control: u8,
+    /// Status register
+    status: u8,
+    /// Clock divisor
+    divisor: u8,
+    /// Chip-select register
+    cs: u8,
+    /// Last received byte
+    rx: u8,
+    /// Byte being shifted in by the current transfer
+    shift_in: u8,
+    /// Cycles left in the current transfer
+    busy_cycles: u64,
+    /// Attached devices by chip-select line (host-side, not saved in
+    /// snapshots)
+    #[serde(skip)]
+    devices: [Option<Box<dyn SpiDevice>>; CS_LINES],
+}
+
+impl SpiController {
+    /// Creates an idle controller with no devices attached
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            rx: 0xFF,
+            ..Self::default()
+        }
+    }
+
+    /// Copies the controller registers for a snapshot, without the attached
+    /// devices
+    #[must_use]
+    pub fn save_registers(&self) -> Self {
+        Self {
+            devices: Default::default(),
+            ..*self
+        }
+    }
+
+    /// Takes the registers from a snapshot's controller, keeping the
+    /// attached devices
+    pub fn restore_registers(&mut self, saved: Self) {
+        let devices = std::mem::take(&mut self.devices);
+        *self = Self { devices, ..saved };
+    }
+
+    /// Resets the controller registers, releasing every chip select
+    ///
+    /// Attached devices stay attached.
+    pub fn reset(&mut self) {
+        self.set_cs(0);
+        self.control = 0;
+        self.status = 0;
+        self.divisor = 0;
+        self.rx = 0xFF;
+        self.busy_cycles = 0;
+    }
+
+    /// Attaches a device to a chip-select line, returning the old one
+    pub fn attach(&mut self, cs: usize, device: Box<dyn SpiDevice>) -> Option<Box<dyn SpiDevice>> {
+        self.devices[cs].replace(device)
+    }
+
+    /// Reads a controller register
+    #[must_use]
+    pub const fn read(&self, offset: u32) -> u8 {
+        match offset {
+            regs::DATA => self.rx,
+            regs::CONTROL => self.control,
+            regs::STATUS => self.status,
+            regs::DIVISOR => self.divisor,
+            regs::CS => self.cs,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes a controller register
+    pub fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            regs::DATA => self.start_transfer(value),
+            regs::CONTROL => self.control = value & control::IE,
+            regs::STATUS => self.status &= !(value & status::DONE),
+            regs::DIVISOR => self.divisor = value,
+            regs::CS => self.set_cs(value),
+            _ => {}
+        }
+    }
+
+    /// Returns the length of one transfer in CPU cycles
+    #[must_use]
+    pub const fn transfer_cycles(&self) -> u64 {
+        16 * (self.divisor as u64 + 1)
+    }
+
+    /// Advances the current transfer by the given number of CPU cycles
+    pub const fn tick(&mut self, cycles: u64) {
+        if self.busy_cycles == 0 {
+            return;
+        }
+        self.busy_cycles = self.busy_cycles.saturating_sub(cycles);
+        if self.busy_cycles == 0 {
+            self.rx = self.shift_in;
+            self.status = (self.status & !status::BUSY) | status::DONE;
+        }
+    }
+
+    /// Returns true if the controller is asserting its interrupt line
+    #[must_use]
+    pub const fn interrupt_pending(&self) -> bool {
+        self.control & control::IE != 0 && self.status & status::DONE != 0
+    }
+
+    /// Starts a transfer; the selected devices see the byte immediately
+    fn start_transfer(&mut self, mosi: u8) {
+        if self.status & status::BUSY != 0 {
+            return;
+        }
+        let mut miso = 0xFF;
+        for (line, device) in self.devices.iter_mut().enumerate() {
+            if self.cs & (1 << line) != 0 {
+                if let Some(device) = device {
+                    // Unselected outputs float high, so the bus ANDs replies
+                    miso &= device.transfer(mosi);
+                }
+            }
+        }
+        self.shift_in = miso;
+        self.busy_cycles = self.transfer_cycles();
+        self.status = (self.status & !status::DONE) | status::BUSY;
+    }
+
+    /// Updates the chip selects, notifying devices on each edge
+    fn set_cs(&mut self, cs: u8) {
+        let changed = self.cs ^ cs;
+        self.cs = cs;
+        for (line, device) in self.devices.iter_mut().enumerate() {
+            let mask = 1 << line;
+            if changed & mask == 0 {
+                continue;
+            }
+            if let Some(device) = device {
+                if cs & mask != 0 {
+                    device.select();
+                } else {
+                    device.deselect();
+                }
+            }
+        }
+    }
+}
+
+/// 25xx-series SPI EEPROM commands
+pub mod eeprom_commands {
+    /// Read data from memory
+    pub const READ: u8 = 0x03;
+    /// Write data to memory
+    pub const WRITE: u8 = 0x02;
+    /// Reset the write enable latch
+    pub const WRDI: u8 = 0x04;
+    /// Read the status register
+    pub const RDSR: u8 = 0x05;
+    /// Set the write enable latch
+    pub const WREN: u8 = 0x06;
+}
+
+/// EEPROM status register bit: write enable latch
+pub const EEPROM_WEL: u8 = 0x02;
+
+/// Where the EEPROM is in a command sequence
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EepromState {
+    /// Waiting for a command byte
+    Command,
+    /// Receiving the address for READ or WRITE
+    Address { command: u8, remaining: u8 },
+    /// Streaming data out
+    Read,
+    /// Collecting data for a page write
+    Write,
+    /// Streaming the status register out
+    Status,
+    /// Ignoring bytes until deselected
+    Ignore,
+}
+
+/// 25xx-series SPI EEPROM (e.g. 25LC256) backed by a byte array
+///
+/// Uses 16-bit addresses. Sequential reads wrap at the end of the array;
+/// writes wrap within a page and are committed when the chip is deselected,
+/// provided WREN was issued first. The write cycle is not modeled.
+// Allow dead code: only the tests attach one; the app has no way to yet.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct SpiEeprom {
+    /// Memory contents
+    data: Vec<u8>,
+    /// Page size in bytes
+    page_size: usize,
+    /// Write enable latch
+    wel: bool,
+    /// Command state
+    state: EepromState,
+    /// Current address
+    address: usize,
+    /// Bytes of the pending page write (address, value)
+    pending: Vec<(usize, u8)>,
+}
+
+// Allow dead code: only the tests attach one; the app has no way to yet.
+#[allow(dead_code)]
+impl SpiEeprom {
+    /// Creates an erased ($FF) EEPROM of the given size and page size
+    #[must_use]
+    pub fn new(size: usize, page_size: usize) -> Self {
+        Self {
+            data: vec![0xFF; size],
+            page_size,
+            wel: false,
+            state: EepromState::Command,
+            address: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Creates a 32 KB EEPROM with 64-byte pages, like a 25LC256
+    #[must_use]
+    pub fn new_25lc256() -> Self {
+        Self::new(32 * 1024, 64)
+    }
+
+    /// Returns the memory contents
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the memory contents for modification
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Returns the status register
+    const fn status(&self) -> u8 {
+        if self.wel {
+            EEPROM_WEL
+        } else {
+            0
+        }
+    }
+}
+
+impl SpiDevice for SpiEeprom {
+    fn transfer(&mut self, mosi: u8) -> u8 {
+        match self.state {
+            EepromState::Command => {
+                self.state = match mosi {
+                    eeprom_commands::READ | eeprom_commands::WRITE => {
+                        self.address = 0;
+                        EepromState::Address {
+                            command: mosi,
+                            remaining: 2,
+                        }
+                    }
+                    eeprom_commands::WREN => {
+                        self.wel = true;
+                        EepromState::Ignore
+                    }
+                    eeprom_commands::WRDI => {
+                        self.wel = false;
+                        EepromState::Ignore
+                    }
+                    eeprom_commands::RDSR => EepromState::Status,
+                    _ => EepromState::Ignore,
+                };
+                0xFF
+            }
+            EepromState::Address { command, remaining } => {
+                self.address = ((self.address << 8) | usize::from(mosi)) % self.data.len();
+                self.state = if remaining > 1 {
+                    EepromState::Address {
+                        command,
+                        remaining: remaining - 1,
+                    }
+                } else if command == eeprom_commands::READ {
+                    EepromState::Read
+                } else {
+                    EepromState::Write
+                };
+                0xFF
+            }
+            EepromState::Read => {
+                let value = self.data[self.address];
+                self.address = (self.address + 1) % self.data.len();
+                value
+            }
+            EepromState::Write => {
+                self.pending.push((self.address, mosi));
+                let page = self.address - self.address % self.page_size;
+                self.address = page + (self.address + 1) % self.page_size;
+                0xFF
+            }
+            EepromState::Status => self.status(),
+            EepromState::Ignore => 0xFF,
+        }
+    }
+
+    fn select(&mut self) {
+        self.state = EepromState::Command;
+        self.pending.clear();
+    }
+
+    fn deselect(&mut self) {
+        if self.state == EepromState::Write && self.wel {
+            for &(address, value) in &self.pending {
+                self.data[address] = value;
+            }
+            self.wel = false;
+        }
+        self.pending.clear();
+        self.state = EepromState::Command;
+    }
+}
+
+impl Clocked for SpiController {
+    fn tick(&mut self, cycles: u64) {
+        Self::tick(self, cycles);
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        (self.busy_cycles > 0).then_some(self.busy_cycles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs one byte through the controller and returns the reply
+    fn xfer(spi: &mut SpiController, mosi: u8) -> u8 {
+        spi.write(regs::DATA, mosi);
+        spi.tick(spi.transfer_cycles());
+        spi.write(regs::STATUS, status::DONE);
+        spi.read(regs::DATA)
+    }
+
+    #[test]
+    fn test_spi_transfer_timing_and_interrupt() {
+        let mut spi = SpiController::new();
+        spi.write(regs::CONTROL, control::IE);
+        spi.write(regs::DIVISOR, 3);
+        spi.write(regs::DATA, 0x55);
+        assert_eq!(spi.read(regs::STATUS), status::BUSY);
+
+        spi.tick(63);
+        assert_eq!(spi.read(regs::STATUS), status::BUSY);
+        spi.tick(1);
+        assert_eq!(spi.read(regs::STATUS), status::DONE);
+        assert!(spi.interrupt_pending());
+        // Nothing selected: the bus floats high
+        assert_eq!(spi.read(regs::DATA), 0xFF);
+
+        spi.write(regs::STATUS, status::DONE);
+        assert!(!spi.interrupt_pending());
+    }
+
+    #[test]
+    fn test_spi_eeprom_page_write_wraps() {
+        let eeprom = Arc::new(Mutex::new(SpiEeprom::new(256, 16)));
+        let mut spi = SpiController::new();
+        spi.attach(2, Box::new(Arc::clone(&eeprom)));
+
+        // WRITE without WREN is ignored
+        spi.write(regs::CS, 0x04);
+        for byte in [eeprom_commands::WRITE, 0x00, 0x00, 0x11] {
+            xfer(&mut spi, byte);
+        }
+        spi.write(regs::CS, 0);
+        assert_eq!(eeprom.lock().unwrap().data()[0], 0xFF);
+
+        spi.write(regs::CS, 0x04);
+        xfer(&mut spi, eeprom_commands::WREN);
+        spi.write(regs::CS, 0);
+        spi.write(regs::CS, 0x04);
+        xfer(&mut spi, eeprom_commands::RDSR);
+        assert_eq!(xfer(&mut spi, 0), EEPROM_WEL);
+        spi.write(regs::CS, 0);
+
+        // Three bytes from $1E wrap to the start of the page at $10
+        spi.write(regs::CS, 0x04);
+        for byte in [eeprom_commands::WRITE, 0x00, 0x1E, 0xA1, 0xA2, 0xA3] {
+            xfer(&mut spi, byte);
+        }
+        spi.write(regs::CS, 0);
+        {
+            let eeprom = eeprom.lock().unwrap();
+            assert_eq!(&eeprom.data()[0x1E..0x20], &[0xA1, 0xA2]);
+            assert_eq!(eeprom.data()[0x10], 0xA3);
+            assert_eq!(eeprom.data()[0x20], 0xFF);
+        }
+
+        // The write cleared WEL; sequential reads cross the page
+        spi.write(regs::CS, 0x04);
+        for byte in [eeprom_commands::READ, 0x00, 0x1F] {
+            xfer(&mut spi, byte);
+        }
+        assert_eq!(xfer(&mut spi, 0), 0xA2);
+        assert_eq!(xfer(&mut spi, 0), 0xFF);
+        spi.write(regs::CS, 0);
+        spi.write(regs::CS, 0x04);
+        xfer(&mut spi, eeprom_commands::RDSR);
+        assert_eq!(xfer(&mut spi, 0), 0);
+    }
+}
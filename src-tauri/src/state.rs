@@ -0,0 +1,885 @@
+//! Emulator State
+//!
+//! `Flux32State` holds the emulator instances and the logic behind the
+//! Tauri commands. The app registers one with `Builder::manage` and every
+//! command reaches it through `tauri::State`, so nothing here depends on
+//! Tauri: the commands only translate arguments and emit events, and the
+//! tests drive a `Flux32State` directly.
+//!
+//! Commands run on Tauri's worker threads, alongside background runs, so
+//! locks are always taken in this order: the instance table, then an `Sbc`,
+//! and never the table while an `Sbc` is held. Long operations take a
+//! handle from `machine()`, which releases the table right away, and lock
+//! the `Sbc` in bounded slices so other commands are served in between.
+
+use crate::assembler::Assembler;
+use crate::config::SbcConfig;
+use crate::debugger::{Debugger, RunResult, StopReason, WatchExpression, WatchValue};
+use crate::error::EmulatorError;
+use crate::expression::{Evaluation, Expression};
+use crate::instances::{InstanceId, Instances, PRIMARY_INSTANCE};
+use crate::registers::{CcrFlags, CpuState};
+use crate::runner::{self, BackgroundRun, RunStopped};
+use crate::sbc::{ResetMode, Sbc};
+use crate::snapshot::{Snapshot, SnapshotInfo};
+use crate::throttle::{Throttle, ThrottleStats};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// One emulator instance
+pub struct Flux32Emulator {
+    /// The machine, shared with background runs
+    sbc: Arc<Mutex<Sbc>>,
+    /// Background run started by `start` (stopped when dropped)
+    runner: Option<BackgroundRun>,
+    /// Paces `run_throttled` calls
+    throttle: Throttle,
+}
+
+impl Flux32Emulator {
+    fn new() -> Self {
+        Self::with_sbc(Sbc::new())
+    }
+
+    fn with_sbc(sbc: Sbc) -> Self {
+        Self {
+            sbc: Arc::new(Mutex::new(sbc)),
+            runner: None,
+            throttle: Throttle::new(),
+        }
+    }
+
+    /// Returns true while a background run is executing
+    fn running(&self) -> bool {
+        self.runner.as_ref().is_some_and(BackgroundRun::is_running)
+    }
+}
+
+impl Default for Flux32Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Emulator status information
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmulatorStatus {
+    halted: bool,
+    cycles: u64,
+    executed: u64,
+    /// Program counter
+    pc: u32,
+    /// Condition code flags
+    flags: CcrFlags,
+    /// The CPU is in supervisor mode
+    supervisor: bool,
+    /// Instructions executed since the last reset
+    instructions: u64,
+    /// Why the last run or batch of steps stopped
+    stop: Option<StopReason>,
+    /// Instruction at the PC (`None` outside ROM and RAM)
+    next_instruction: Option<String>,
+    /// Details of the run that produced this status (only from `run`)
+    run: Option<RunResult>,
+    /// A background run is executing
+    running: bool,
+    /// Active machine configuration (only from `status`)
+    config: Option<SbcConfig>,
+    /// Pacing of the call (only from `run_throttled`)
+    throttle: Option<ThrottleStats>,
+    /// Values of the watch expressions
+    watches: Vec<WatchValue>,
+}
+
+impl EmulatorStatus {
+    /// Builds the status the run and status commands return
+    fn new(sbc: &Sbc, run: Option<RunResult>, running: bool, config: Option<SbcConfig>) -> Self {
+        let sr = sbc.registers().sr;
+        Self {
+            halted: sbc.is_halted(),
+            cycles: sbc.cycles(),
+            executed: run.map_or(0, |run| run.cycles),
+            pc: sbc.pc(),
+            flags: CcrFlags::from_sr(sr),
+            supervisor: sr & 0x2000 != 0,
+            instructions: sbc.instructions(),
+            stop: sbc.last_stop(),
+            next_instruction: sbc.next_instruction(),
+            run,
+            running,
+            config,
+            throttle: None,
+            watches: sbc.watch_values(),
+        }
+    }
+}
+
+/// Cycles between UART drains while `run` streams output
+pub const OUTPUT_DRAIN_CYCLES: u64 = 50_000;
+
+/// The emulator instances, managed by Tauri
+#[derive(Default)]
+pub struct Flux32State {
+    emulators: Mutex<Instances<Flux32Emulator>>,
+    /// The editor's incremental assembler, kept between assemblies
+    assembler: Mutex<Option<Assembler>>,
+}
+
+impl Flux32State {
+    /// Creates a state with no instances
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` on the editor's incremental assembler, made by `new` the
+    /// first time
+    pub fn with_assembler<T>(
+        &self,
+        new: impl FnOnce() -> Assembler,
+        f: impl FnOnce(&mut Assembler) -> T,
+    ) -> T {
+        f(self.assembler.lock().unwrap().get_or_insert_with(new))
+    }
+
+    /// Returns a handle to an instance's machine, releasing the instance
+    /// table so a long operation doesn't block other commands
+    pub fn machine(&self, instance: Option<InstanceId>) -> Result<Arc<Mutex<Sbc>>, EmulatorError> {
+        self.emulators
+            .lock()
+            .unwrap()
+            .get(instance.unwrap_or(PRIMARY_INSTANCE))
+            .map(|emulator| Arc::clone(&emulator.sbc))
+            .ok_or_else(|| EmulatorError::not_initialized(instance))
+    }
+
+    /// Runs `f` on an instance's machine
+    pub fn with_sbc<T>(
+        &self,
+        instance: Option<InstanceId>,
+        f: impl FnOnce(&mut Sbc) -> T,
+    ) -> Result<T, EmulatorError> {
+        let emulators = self.emulators.lock().unwrap();
+        let emulator = emulators
+            .get(instance.unwrap_or(PRIMARY_INSTANCE))
+            .ok_or_else(|| EmulatorError::not_initialized(instance))?;
+        let mut sbc = emulator.sbc.lock().unwrap();
+        Ok(f(&mut sbc))
+    }
+
+    /// Runs a fallible `f` on an instance's machine
+    pub fn try_with_sbc<T>(
+        &self,
+        instance: Option<InstanceId>,
+        f: impl FnOnce(&mut Sbc) -> Result<T, EmulatorError>,
+    ) -> Result<T, EmulatorError> {
+        self.with_sbc(instance, f)?
+    }
+
+    /// Returns true while a background run of the instance is executing
+    pub fn background_running(&self, instance: Option<InstanceId>) -> bool {
+        self.emulators
+            .lock()
+            .unwrap()
+            .get(instance.unwrap_or(PRIMARY_INSTANCE))
+            .is_some_and(Flux32Emulator::running)
+    }
+
+    /// Initializes an instance, returning the configuration it runs with
+    ///
+    /// With `rom_path` or `config`, the instance is (re)created: `config`
+    /// builds the machine (missing fields take their defaults) and
+    /// `rom_path` overrides its ROM. An existing instance is torn down
+    /// first, stopping its background run and flushing its CF card.
+    pub fn init(
+        &self,
+        rom_path: Option<String>,
+        config: Option<SbcConfig>,
+        instance: Option<InstanceId>,
+    ) -> Result<SbcConfig, EmulatorError> {
+        let instance = instance.unwrap_or(PRIMARY_INSTANCE);
+        let mut emulators = self.emulators.lock().unwrap();
+        if rom_path.is_some() || config.is_some() {
+            let mut config = config.unwrap_or_default();
+            if rom_path.is_some() {
+                config.rom_path = rom_path;
+            }
+            config.validate()?;
+            if let Some(old) = emulators.get_mut(instance) {
+                if let Some(runner) = old.runner.take() {
+                    runner.stop();
+                }
+                // The new machine may open the same CF image
+                old.sbc.lock().unwrap().flush_cf()?;
+            }
+            let sbc = Sbc::new_with_config(config)?;
+            emulators.insert(instance, Flux32Emulator::with_sbc(sbc));
+        }
+        let emulator = emulators.get_or_insert_with(instance, Flux32Emulator::new);
+        let config = emulator.sbc.lock().unwrap().config().clone();
+        Ok(config)
+    }
+
+    /// Creates an instance alongside the existing ones and returns its id
+    pub fn create_instance(&self, config: Option<SbcConfig>) -> Result<InstanceId, EmulatorError> {
+        let sbc = Sbc::new_with_config(config.unwrap_or_default())?;
+        Ok(self
+            .emulators
+            .lock()
+            .unwrap()
+            .create(Flux32Emulator::with_sbc(sbc)))
+    }
+
+    /// Destroys an instance, stopping its background run and flushing its
+    /// CF card
+    pub fn destroy_instance(&self, instance: InstanceId) -> Result<(), EmulatorError> {
+        // Dropped once the lock is released: stopping a run waits for its slice
+        let emulator = self.emulators.lock().unwrap().remove(instance);
+        emulator
+            .map(drop)
+            .ok_or(EmulatorError::NotInitialized { instance })
+    }
+
+    /// Returns the ids of the instances
+    pub fn ids(&self) -> Vec<InstanceId> {
+        self.emulators.lock().unwrap().ids()
+    }
+
+    /// Drops every instance, flushing cached CF card writes
+    pub fn clear(&self) {
+        self.emulators.lock().unwrap().clear();
+    }
+
+    /// Resets an instance (creating it if needed)
+    pub fn reset(
+        &self,
+        mode: Option<ResetMode>,
+        instance: Option<InstanceId>,
+    ) -> Result<(), EmulatorError> {
+        let mut emulators = self.emulators.lock().unwrap();
+        let emulator =
+            emulators.get_or_insert_with(instance.unwrap_or(PRIMARY_INSTANCE), Flux32Emulator::new);
+        let mut sbc = emulator.sbc.lock().unwrap();
+        sbc.reset_with(mode.unwrap_or_default())
+            .map_err(|e| EmulatorError::Io {
+                path: None,
+                kind: e.kind(),
+                message: format!("Reset, but the CF card's writes couldn't be flushed: {e}"),
+            })
+    }
+
+    /// Runs an instance for up to `max_cycles`, locking the machine one
+    /// `runner::SLICE_CYCLES` slice at a time
+    ///
+    /// The UART output is drained to `on_output` every
+    /// `OUTPUT_DRAIN_CYCLES` and once more at the end, so a long run streams
+    /// its output in order and all of it has been passed on before the
+    /// status is built.
+    pub fn run(
+        &self,
+        max_cycles: u64,
+        instance: Option<InstanceId>,
+        mut on_slice: impl FnMut(&mut Sbc),
+        mut on_output: impl FnMut(Vec<u8>),
+    ) -> Result<EmulatorStatus, EmulatorError> {
+        let mut drain = move |sbc: &mut Sbc| {
+            let output = sbc.drain_output();
+            if !output.is_empty() {
+                on_output(output);
+            }
+        };
+        let sbc = self.machine(instance)?;
+        let drain_slices = OUTPUT_DRAIN_CYCLES / runner::SLICE_CYCLES;
+        let mut slices = 0u64;
+        let run = runner::run_sliced(&sbc, max_cycles, |sbc| {
+            on_slice(sbc);
+            slices += 1;
+            if slices.is_multiple_of(drain_slices) {
+                drain(sbc);
+            }
+        });
+        let running = self.background_running(instance);
+        let mut sbc = sbc.lock().unwrap();
+        drain(&mut sbc);
+        Ok(EmulatorStatus::new(&sbc, Some(run), running, None))
+    }
+
+    /// Runs an instance for the wall time since the previous call at
+    /// `target_hz` (the configured clock by default), making up for at most
+    /// `max_elapsed`
+    pub fn run_throttled(
+        &self,
+        target_hz: Option<u32>,
+        max_elapsed: Duration,
+        instance: Option<InstanceId>,
+        on_slice: impl FnMut(&mut Sbc),
+    ) -> Result<EmulatorStatus, EmulatorError> {
+        let id = instance.unwrap_or(PRIMARY_INSTANCE);
+        let (sbc, budget) = {
+            let mut emulators = self.emulators.lock().unwrap();
+            let emulator = emulators
+                .get_mut(id)
+                .ok_or(EmulatorError::NotInitialized { instance: id })?;
+            let target_hz = match target_hz {
+                Some(0) => {
+                    return Err(EmulatorError::invalid(
+                        "targetHz",
+                        "Target frequency must be above 0 Hz",
+                    ))
+                }
+                Some(hz) => hz,
+                None => emulator.sbc.lock().unwrap().config().clock_hz,
+            };
+            let budget = emulator.throttle.budget(target_hz, max_elapsed);
+            (Arc::clone(&emulator.sbc), budget)
+        };
+
+        let run = runner::run_sliced(&sbc, budget, on_slice);
+        let stats = self
+            .emulators
+            .lock()
+            .unwrap()
+            .get_mut(id)
+            .map(|emulator| emulator.throttle.record(budget, run.cycles));
+        let running = self.background_running(instance);
+        let sbc = sbc.lock().unwrap();
+        let mut status = EmulatorStatus::new(&sbc, Some(run), running, None);
+        status.throttle = stats;
+        Ok(status)
+    }
+
+    /// Starts (or resumes) running an instance on a background thread
+    ///
+    /// `on_slice` runs after every slice and `on_stop` whenever the run
+    /// stops (see `BackgroundRun::start`); neither may touch this state.
+    pub fn start(
+        &self,
+        instance: Option<InstanceId>,
+        on_slice: impl FnMut(&mut Sbc) + Send + 'static,
+        on_stop: impl FnMut(RunStopped) + Send + 'static,
+    ) -> Result<(), EmulatorError> {
+        let mut emulators = self.emulators.lock().unwrap();
+        let emulator = emulators
+            .get_mut(instance.unwrap_or(PRIMARY_INSTANCE))
+            .ok_or_else(|| EmulatorError::not_initialized(instance))?;
+        if let Some(runner) = emulator.runner.as_ref() {
+            runner.resume();
+        } else {
+            emulator.runner = Some(BackgroundRun::start(
+                Arc::clone(&emulator.sbc),
+                on_slice,
+                on_stop,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Pauses a background run after the slice in progress
+    pub fn pause(&self, instance: Option<InstanceId>) -> Result<(), EmulatorError> {
+        let emulators = self.emulators.lock().unwrap();
+        let emulator = emulators
+            .get(instance.unwrap_or(PRIMARY_INSTANCE))
+            .ok_or_else(|| EmulatorError::not_initialized(instance))?;
+        if let Some(runner) = emulator.runner.as_ref() {
+            runner.pause();
+        }
+        Ok(())
+    }
+
+    /// Stops a background run and ends its worker thread
+    pub fn stop(&self, instance: Option<InstanceId>) -> Result<(), EmulatorError> {
+        let mut emulators = self.emulators.lock().unwrap();
+        let emulator = emulators
+            .get_mut(instance.unwrap_or(PRIMARY_INSTANCE))
+            .ok_or_else(|| EmulatorError::not_initialized(instance))?;
+        if let Some(runner) = emulator.runner.take() {
+            runner.stop();
+        }
+        Ok(())
+    }
+
+    /// Returns an instance's status, with its configuration
+    pub fn status(&self, instance: Option<InstanceId>) -> Result<EmulatorStatus, EmulatorError> {
+        let emulators = self.emulators.lock().unwrap();
+        let emulator = emulators
+            .get(instance.unwrap_or(PRIMARY_INSTANCE))
+            .ok_or_else(|| EmulatorError::not_initialized(instance))?;
+        let sbc = emulator.sbc.lock().unwrap();
+        Ok(EmulatorStatus::new(
+            &sbc,
+            None,
+            emulator.running(),
+            Some(sbc.config().clone()),
+        ))
+    }
+
+    /// Returns an instance's CPU registers
+    pub fn registers(&self, instance: Option<InstanceId>) -> Result<CpuState, EmulatorError> {
+        self.with_sbc(instance, |sbc| CpuState::from(sbc.registers()))
+    }
+
+    /// Adds a breakpoint and returns its id
+    ///
+    /// `condition` is a debugger expression that must hold for the
+    /// breakpoint to hit (blank means none), and `skip_count` hits pass
+    /// before it stops execution.
+    pub fn add_breakpoint(
+        &self,
+        address: u32,
+        condition: Option<&str>,
+        skip_count: u64,
+        instance: Option<InstanceId>,
+    ) -> Result<u32, EmulatorError> {
+        let condition = condition
+            .filter(|source| !source.trim().is_empty())
+            .map(Expression::parse)
+            .transpose()
+            .map_err(|e| EmulatorError::invalid("condition", format!("Invalid condition: {e}")))?;
+        self.with_sbc(instance, |sbc| {
+            sbc.debugger_mut()
+                .add_conditional_breakpoint(address, condition, skip_count)
+        })
+    }
+
+    /// Adds a watch expression and returns its id
+    ///
+    /// The expression is parsed first; an error names the column where
+    /// parsing stopped.
+    pub fn add_watch(
+        &self,
+        name: &str,
+        expression: &str,
+        instance: Option<InstanceId>,
+    ) -> Result<u32, EmulatorError> {
+        let expression = Expression::parse(expression).map_err(|e| {
+            EmulatorError::invalid("expression", format!("Invalid expression: {e}"))
+        })?;
+        let name = if name.trim().is_empty() {
+            expression.source().to_string()
+        } else {
+            name.trim().to_string()
+        };
+        self.with_sbc(instance, |sbc| {
+            sbc.debugger_mut().add_watch(name, expression)
+        })
+    }
+
+    /// Removes a watch expression
+    pub fn remove_watch(&self, id: u32, instance: Option<InstanceId>) -> Result<(), EmulatorError> {
+        self.try_with_sbc(instance, |sbc| {
+            if sbc.debugger_mut().remove_watch(id) {
+                Ok(())
+            } else {
+                Err(EmulatorError::invalid(
+                    "id",
+                    format!("No watch with id {id}"),
+                ))
+            }
+        })
+    }
+
+    /// Lists an instance's watch expressions
+    pub fn watches(
+        &self,
+        instance: Option<InstanceId>,
+    ) -> Result<Vec<WatchExpression>, EmulatorError> {
+        self.with_sbc(instance, |sbc| sbc.debugger().watches().to_vec())
+    }
+
+    /// Evaluates an expression against an instance's current state
+    ///
+    /// A name that is neither a register nor a known label fails with the
+    /// symbols it might be a typo of.
+    pub fn evaluate(
+        &self,
+        expression: &str,
+        instance: Option<InstanceId>,
+    ) -> Result<Evaluation, EmulatorError> {
+        /// Most similar symbols an undefined one suggests
+        const MAX_SUGGESTIONS: usize = 5;
+
+        let expression = Expression::parse(expression).map_err(|e| {
+            EmulatorError::invalid("expression", format!("Invalid expression: {e}"))
+        })?;
+        self.try_with_sbc(instance, |sbc| {
+            let debugger = sbc.debugger();
+            let undefined = expression
+                .labels()
+                .into_iter()
+                .find(|name| debugger.address_of(name).is_none());
+            if let Some(name) = undefined {
+                return Err(EmulatorError::UndefinedSymbol {
+                    name: name.to_string(),
+                    suggestions: debugger.similar_symbols(name, MAX_SUGGESTIONS),
+                });
+            }
+            sbc.evaluate(&expression)
+                .map_err(|message| EmulatorError::invalid("expression", message))
+        })
+    }
+
+    /// Runs `f` on an instance's debugger if `id` names a breakpoint
+    pub fn with_breakpoint(
+        &self,
+        id: u32,
+        instance: Option<InstanceId>,
+        f: impl FnOnce(&mut Debugger),
+    ) -> Result<(), EmulatorError> {
+        self.try_with_sbc(instance, |sbc| {
+            let debugger = sbc.debugger_mut();
+            if !debugger.breakpoints().iter().any(|bp| bp.id == id) {
+                return Err(EmulatorError::invalid(
+                    "id",
+                    format!("No breakpoint with id {id}"),
+                ));
+            }
+            f(debugger);
+            Ok(())
+        })
+    }
+
+    /// Saves an instance's full state to a snapshot file, returning its
+    /// metadata
+    ///
+    /// Cached CF card writes are flushed first, since the card image itself
+    /// isn't part of the snapshot.
+    pub fn save_snapshot(
+        &self,
+        path: &Path,
+        instance: Option<InstanceId>,
+    ) -> Result<SnapshotInfo, EmulatorError> {
+        let snapshot = self.try_with_sbc(instance, |sbc| {
+            sbc.flush_cf()?;
+            Ok(sbc.snapshot())
+        })?;
+        snapshot.save(path)?;
+        Ok(snapshot.info)
+    }
+
+    /// Replaces an instance's machine (creating the instance if needed)
+    /// with one restored from a snapshot file, returning its metadata
+    ///
+    /// On any error the current machine is left as it was. Breakpoints and
+    /// watchpoints carry over to the restored machine.
+    pub fn load_snapshot(
+        &self,
+        path: &Path,
+        instance: Option<InstanceId>,
+    ) -> Result<SnapshotInfo, EmulatorError> {
+        let snapshot = Snapshot::load(path)?;
+        let info = snapshot.info.clone();
+
+        let mut emulators = self.emulators.lock().unwrap();
+        let emulator =
+            emulators.get_or_insert_with(instance.unwrap_or(PRIMARY_INSTANCE), Flux32Emulator::new);
+        let mut sbc = emulator.sbc.lock().unwrap();
+        // The restored machine reopens the card image, so write back the cache
+        sbc.flush_cf()?;
+        let mut restored = Sbc::from_snapshot(snapshot)?;
+        *restored.debugger_mut() = sbc.debugger().clone();
+        *sbc = restored;
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+    use crate::expression::ValueKind;
+    use crate::sbc::APP_START;
+
+    #[test]
+    fn test_state_commands_need_an_instance() {
+        let state = Flux32State::new();
+        assert_eq!(
+            state.status(None).unwrap_err(),
+            EmulatorError::NotInitialized { instance: 0 }
+        );
+        assert_eq!(
+            state.with_sbc(Some(4), |sbc| sbc.pc()).unwrap_err(),
+            EmulatorError::NotInitialized { instance: 4 }
+        );
+        assert!(state.ids().is_empty());
+
+        // A reset creates the instance, like a first command from the UI
+        state.reset(None, None).unwrap();
+        assert_eq!(state.ids(), [PRIMARY_INSTANCE]);
+        let second = state.create_instance(None).unwrap();
+        assert_eq!(state.ids(), [PRIMARY_INSTANCE, second]);
+        state.destroy_instance(second).unwrap();
+        assert_eq!(
+            state.destroy_instance(second).unwrap_err(),
+            EmulatorError::NotInitialized { instance: second }
+        );
+    }
+
+    #[test]
+    fn test_state_init_and_run() {
+        let state = Flux32State::new();
+        let config = SbcConfig {
+            ram_size: 256 * 1024,
+            ..SbcConfig::default()
+        };
+        assert_eq!(
+            state.init(None, Some(config), None).unwrap().ram_size,
+            256 * 1024
+        );
+        let bad = SbcConfig {
+            ram_size: 1000,
+            ..SbcConfig::default()
+        };
+        assert!(matches!(
+            state.init(None, Some(bad), None),
+            Err(EmulatorError::InvalidArgument { ref field, .. }) if field == "ramSize"
+        ));
+
+        let mut slices = 0;
+        let status = state.run(25_000, None, |_| slices += 1, |_| {}).unwrap();
+        assert!(status.executed >= 25_000);
+        assert_eq!(slices, 3);
+        assert!(!status.running);
+        assert_eq!(state.status(None).unwrap().cycles, status.cycles);
+
+        assert!(matches!(
+            state.run_throttled(Some(0), Duration::from_millis(100), None, |_| {}),
+            Err(EmulatorError::InvalidArgument { ref field, .. }) if field == "targetHz"
+        ));
+    }
+
+    #[test]
+    fn test_state_run_streams_uart_output() {
+        // Prints 100 KB, each byte the low byte of its index
+        const PROGRAM: &str = "
+        moveq   #0,d0
+        move.l  #102400,d1
+loop:   move.b  d0,$A00000
+        addq.b  #1,d0
+        subq.l  #1,d1
+        bne     loop
+        stop    #$2700
+";
+        let state = Flux32State::new();
+        state.init(None, None, None).unwrap();
+        let app = Assembler::new()
+            .assemble_source(PROGRAM, Path::new("<test>"))
+            .unwrap();
+        state
+            .try_with_sbc(None, |sbc| {
+                sbc.load_app(&app, None)?;
+                sbc.run_app(None);
+                Ok(())
+            })
+            .unwrap();
+
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+        let status = state
+            .run(10_000_000, None, |_| {}, |chunk| chunks.push(chunk))
+            .unwrap();
+        assert!(status.halted);
+        assert!(chunks.len() > 10);
+        // Each chunk is at most what a drain interval can print
+        assert!(chunks.iter().all(|chunk| chunk.len() < 4096));
+        let output = chunks.concat();
+        assert_eq!(output.len(), 102_400);
+        assert!(output.iter().zip((0..=255u8).cycle()).all(|(&a, b)| a == b));
+        assert!(state.with_sbc(None, Sbc::drain_output).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_state_registers_answer_during_a_run() {
+        // Counts in D0 until the host sets the flag
+        const PROGRAM: &str = "
+loop:   addq.l  #1,d0
+        tst.b   $E02000
+        beq     loop
+        stop    #$2700
+";
+        let state = Arc::new(Flux32State::new());
+        state.init(None, None, None).unwrap();
+        let app = Assembler::new()
+            .assemble_source(PROGRAM, Path::new("<test>"))
+            .unwrap();
+        state
+            .try_with_sbc(None, |sbc| {
+                sbc.load_app(&app, None)?;
+                sbc.run_app(None);
+                Ok(())
+            })
+            .unwrap();
+
+        let (started, first_slice) = std::sync::mpsc::channel();
+        let run = std::thread::spawn({
+            let state = Arc::clone(&state);
+            move || {
+                state.run(
+                    u64::MAX,
+                    None,
+                    move |_| {
+                        let _ = started.send(());
+                    },
+                    |_| {},
+                )
+            }
+        });
+        first_slice.recv().unwrap();
+
+        // The registers come back while the run is still going
+        let (reply, registers) = std::sync::mpsc::channel();
+        std::thread::spawn({
+            let state = Arc::clone(&state);
+            move || reply.send(state.registers(None))
+        });
+        let registers = registers.recv_timeout(Duration::from_secs(1));
+        let running = !run.is_finished();
+        state
+            .with_sbc(None, |sbc| sbc.write_memory(0x00E0_2000, &[1], false))
+            .unwrap();
+        let status = run.join().unwrap().unwrap();
+
+        assert!(registers.unwrap().unwrap().d[0] > 0);
+        assert!(running);
+        assert!(status.halted);
+    }
+
+    #[test]
+    fn test_state_watches_track_execution() {
+        const PROGRAM: &str = "
+        lea     table(pc),a0
+        moveq   #3,d0
+        addq.l  #2,d0
+        addq.l  #2,a0
+done:   bra     done
+table:  dc.w    $1234,$5678
+";
+        let state = Flux32State::new();
+        state.init(None, None, None).unwrap();
+        let app = Assembler::new()
+            .assemble_source(PROGRAM, Path::new("<test>"))
+            .unwrap();
+        state
+            .try_with_sbc(None, |sbc| {
+                sbc.load_app(&app, None)?;
+                sbc.run_app(None);
+                Ok(())
+            })
+            .unwrap();
+
+        let double = state.add_watch("", "D0*2", None).unwrap();
+        let word = state.add_watch("table word", "word(A0)", None).unwrap();
+        let err = state.add_watch("", "d0 +", None).unwrap_err();
+        assert!(err.to_string().contains("at column"), "{err}");
+        let names: Vec<_> = state
+            .watches(None)
+            .unwrap()
+            .into_iter()
+            .map(|watch| watch.name)
+            .collect();
+        assert_eq!(names, ["D0*2", "table word"]);
+
+        let values = |state: &Flux32State| -> Vec<Option<i64>> {
+            state
+                .status(None)
+                .unwrap()
+                .watches
+                .iter()
+                .map(|w| w.value)
+                .collect()
+        };
+        let step = |state: &Flux32State| {
+            state.with_sbc(None, Sbc::step).unwrap();
+        };
+        // A0 still points at the vector table in ROM
+        assert_eq!(values(&state)[0], Some(0));
+        step(&state);
+        assert_eq!(values(&state), [Some(0), Some(0x1234)]);
+        step(&state);
+        step(&state);
+        assert_eq!(values(&state), [Some(10), Some(0x1234)]);
+        step(&state);
+        assert_eq!(values(&state), [Some(10), Some(0x5678)]);
+
+        // A watch that can't be evaluated reports why
+        state
+            .with_sbc(None, |sbc| sbc.registers_mut().set_a(0, 0x0080_0000))
+            .unwrap();
+        let status = state.status(None).unwrap();
+        assert_eq!(status.watches[1].value, None);
+        assert!(status.watches[1].error.is_some());
+
+        state.remove_watch(double, None).unwrap();
+        assert!(state.remove_watch(double, None).is_err());
+        assert_eq!(state.status(None).unwrap().watches[0].id, word);
+    }
+
+    #[test]
+    fn test_state_evaluates_expressions() {
+        let state = Flux32State::new();
+        state.init(None, None, None).unwrap();
+        state
+            .with_sbc(None, |sbc| {
+                sbc.debugger_mut()
+                    .set_symbols([("start".to_string(), APP_START)]);
+                let registers = sbc.registers_mut();
+                registers.set_d(3, 0x1234);
+                registers.set_a(7, APP_START + 0x100);
+                sbc.write_memory(APP_START + 0x100, &[0xDE, 0xAD, 0xBE, 0xEF], false);
+            })
+            .unwrap();
+
+        let result = state.evaluate("start+$10", None).unwrap();
+        assert_eq!(result.value, i64::from(APP_START + 0x10));
+        assert_eq!(result.hex, "$E00110");
+        assert_eq!(result.kind, ValueKind::Address);
+        let result = state.evaluate("long(A7)", None).unwrap();
+        assert_eq!(result.value, 0xDEAD_BEEF);
+        assert_eq!(result.kind, ValueKind::Number);
+        let result = state.evaluate("D3&$FF", None).unwrap();
+        assert_eq!((result.value, result.hex.as_str()), (0x34, "$34"));
+
+        assert_eq!(
+            state.evaluate("strat+4", None).unwrap_err(),
+            EmulatorError::UndefinedSymbol {
+                name: "strat".to_string(),
+                suggestions: vec!["start".to_string()],
+            }
+        );
+        assert!(matches!(
+            state.evaluate("word($800000)", None),
+            Err(EmulatorError::InvalidArgument { ref field, .. }) if field == "expression"
+        ));
+        assert!(matches!(
+            state.evaluate("d3 +", None),
+            Err(EmulatorError::InvalidArgument { ref field, .. }) if field == "expression"
+        ));
+    }
+
+    #[test]
+    fn test_state_breakpoints() {
+        let state = Flux32State::new();
+        state.init(None, None, None).unwrap();
+        let id = state
+            .add_breakpoint(0x00E0_0100, Some("d0 = 3"), 0, None)
+            .unwrap();
+        assert!(matches!(
+            state.add_breakpoint(0x00E0_0100, Some("d0 = "), 0, None),
+            Err(EmulatorError::InvalidArgument { ref field, .. }) if field == "condition"
+        ));
+
+        state
+            .with_breakpoint(id, None, |debugger| {
+                debugger.set_enabled(id, false);
+            })
+            .unwrap();
+        let enabled = state
+            .with_sbc(None, |sbc| sbc.debugger().breakpoints()[0].enabled)
+            .unwrap();
+        assert!(!enabled);
+        assert!(matches!(
+            state.with_breakpoint(id + 1, None, |_| {}),
+            Err(EmulatorError::InvalidArgument { ref field, .. }) if field == "id"
+        ));
+    }
+}
@@ -0,0 +1,300 @@
+//! Throttled Execution
+//!
+//! The frontend drives a throttled run from its animation frame loop. Each
+//! call runs the cycles the target clock would have executed in the wall
+//! time since the previous call, and the fraction of a cycle left over is
+//! carried to the next call, so over many frames the machine runs at
+//! exactly the target frequency. The last instruction of a call can run
+//! past its budget; the overrun comes out of the next budget. A call makes up for at most `max_elapsed`
+//! of wall time, so a stalled frame loop (a hidden window, a breakpoint in
+//! the frontend) doesn't come back to a burst of catch-up work.
+//!
+//! The achieved speed is measured over windows of `SPEED_WINDOW` from the
+//! cycles the machine actually executed, which fall short of the budget
+//! when it halts, hits a breakpoint or can't keep up with the host.
+//!
+//! Wall time comes from a `Stopwatch`, so tests can step it by hand.
+
+#[cfg(test)]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Wall time the achieved speed is measured over
+pub const SPEED_WINDOW: Duration = Duration::from_millis(500);
+
+/// Nanoseconds per second
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// A monotonic source of wall time
+pub trait Stopwatch: Send + Sync {
+    /// Returns the time since a fixed origin
+    fn elapsed(&self) -> Duration;
+}
+
+/// Host monotonic clock
+#[derive(Clone, Copy, Debug)]
+pub struct HostStopwatch {
+    origin: Instant,
+}
+
+impl HostStopwatch {
+    /// Creates a stopwatch started now
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Default for HostStopwatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stopwatch for HostStopwatch {
+    fn elapsed(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// A stopwatch that only moves when told to, for tests
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct ManualStopwatch {
+    nanos: AtomicU64,
+}
+
+#[cfg(test)]
+impl ManualStopwatch {
+    /// Creates a stopwatch at zero
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Moves the stopwatch forward
+    pub fn advance(&self, by: Duration) {
+        self.nanos
+            .fetch_add(by.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+impl Stopwatch for ManualStopwatch {
+    fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// How a throttled call went
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThrottleStats {
+    /// Clock frequency being emulated, in Hz
+    pub target_hz: u32,
+    /// Cycles the call was allowed to run
+    pub budget: u64,
+    /// Measured emulated clock frequency, in Hz
+    pub achieved_hz: f64,
+    /// Achieved frequency as a fraction of the target (1.0 = full speed)
+    pub speed_ratio: f64,
+}
+
+/// Turns wall time into cycle budgets at a target frequency
+pub struct Throttle {
+    clock: Arc<dyn Stopwatch>,
+    /// Frequency the budgets are for (0 until the first call)
+    target_hz: u32,
+    /// Wall time of the previous budget
+    last: Option<Duration>,
+    /// Fraction of a cycle carried over, in billionths of a cycle
+    remainder: u128,
+    /// Cycles executed past earlier budgets, owed back by the next ones
+    overrun: u64,
+    /// Wall time the current speed window started
+    window_start: Duration,
+    /// Cycles executed in the current speed window
+    window_cycles: u64,
+    /// Speed measured over the last complete window
+    achieved_hz: Option<f64>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Throttle {
+    /// Creates a throttle timed by the host clock
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(HostStopwatch::new()))
+    }
+
+    /// Creates a throttle timed by `clock`
+    #[must_use]
+    pub fn with_clock(clock: Arc<dyn Stopwatch>) -> Self {
+        Self {
+            clock,
+            target_hz: 0,
+            last: None,
+            remainder: 0,
+            overrun: 0,
+            window_start: Duration::ZERO,
+            window_cycles: 0,
+            achieved_hz: None,
+        }
+    }
+
+    /// Returns the cycles to run at `target_hz` for the wall time since the
+    /// previous budget, making up for at most `max_elapsed`
+    ///
+    /// The first call only starts the clock. Changing the frequency drops
+    /// the carried fraction and restarts the speed measurement.
+    pub fn budget(&mut self, target_hz: u32, max_elapsed: Duration) -> u64 {
+        let now = self.clock.elapsed();
+        if target_hz != self.target_hz {
+            self.target_hz = target_hz;
+            self.remainder = 0;
+            self.restart_window(now);
+        }
+        let elapsed = match self.last.replace(now) {
+            Some(last) => now.saturating_sub(last),
+            None => {
+                self.restart_window(now);
+                Duration::ZERO
+            }
+        };
+        if elapsed > max_elapsed {
+            // The caller stalled; don't count the gap against the speed
+            self.restart_window(now.saturating_sub(max_elapsed));
+        }
+
+        let owed = elapsed.min(max_elapsed).as_nanos() * u128::from(target_hz) + self.remainder;
+        self.remainder = owed % NANOS_PER_SEC;
+        let cycles = (owed / NANOS_PER_SEC) as u64;
+        let repaid = cycles.min(self.overrun);
+        self.overrun -= repaid;
+        cycles - repaid
+    }
+
+    /// Records the cycles executed for the latest budget and returns the
+    /// stats for the call
+    #[allow(
+        clippy::cast_precision_loss,
+        reason = "Speed readouts don't need all 64 bits of a cycle count"
+    )]
+    pub fn record(&mut self, budget: u64, executed: u64) -> ThrottleStats {
+        let now = self.last.unwrap_or_default();
+        self.overrun += executed.saturating_sub(budget);
+        self.window_cycles += executed;
+        let span = now.saturating_sub(self.window_start);
+        let window_hz = (!span.is_zero()).then(|| self.window_cycles as f64 / span.as_secs_f64());
+        if span >= SPEED_WINDOW {
+            self.achieved_hz = window_hz;
+            self.restart_window(now);
+        }
+
+        let achieved_hz = self.achieved_hz.or(window_hz).unwrap_or(0.0);
+        ThrottleStats {
+            target_hz: self.target_hz,
+            budget,
+            achieved_hz,
+            speed_ratio: if self.target_hz == 0 {
+                0.0
+            } else {
+                achieved_hz / f64::from(self.target_hz)
+            },
+        }
+    }
+
+    /// Starts a new speed window at `now`, forgetting the last measurement
+    /// unless the window completed
+    fn restart_window(&mut self, now: Duration) {
+        if now.saturating_sub(self.window_start) < SPEED_WINDOW {
+            self.achieved_hz = None;
+        }
+        self.window_start = now;
+        self.window_cycles = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(16);
+    const MAX: Duration = Duration::from_millis(100);
+
+    fn throttle() -> (Throttle, Arc<ManualStopwatch>) {
+        let clock = Arc::new(ManualStopwatch::new());
+        (Throttle::with_clock(clock.clone()), clock)
+    }
+
+    #[test]
+    fn test_throttle_carries_fractional_cycles() {
+        let (mut throttle, clock) = throttle();
+        assert_eq!(throttle.budget(7_372_800, MAX), 0);
+
+        // 16 ms at 7.3728 MHz is 117964.8 cycles
+        let budgets: Vec<_> = (0..5)
+            .map(|_| {
+                clock.advance(FRAME);
+                throttle.budget(7_372_800, MAX)
+            })
+            .collect();
+        assert_eq!(budgets, [117_964, 117_965, 117_965, 117_965, 117_965]);
+        assert_eq!(budgets.iter().sum::<u64>(), 7_372_800 * 80 / 1000);
+
+        // Running past a budget takes the overrun out of the next one
+        throttle.record(117_965, 117_971);
+        clock.advance(FRAME);
+        assert_eq!(throttle.budget(7_372_800, MAX), 117_964 - 6);
+
+        // A stalled caller only makes up for the cap
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(throttle.budget(7_372_800, MAX), 737_280);
+
+        // A new frequency drops the carried fraction
+        clock.advance(Duration::from_nanos(100));
+        assert_eq!(throttle.budget(7_372_800, MAX), 1);
+        clock.advance(Duration::from_nanos(500));
+        assert_eq!(throttle.budget(1_000_000, MAX), 0);
+        clock.advance(Duration::from_nanos(500));
+        assert_eq!(throttle.budget(1_000_000, MAX), 1);
+    }
+
+    #[test]
+    fn test_throttle_measures_achieved_speed() {
+        let (mut throttle, clock) = throttle();
+        throttle.budget(8_000_000, MAX);
+        let mut stats = throttle.record(0, 0);
+        assert!(stats.achieved_hz.abs() < 1e-9);
+
+        // Full speed for a window, then half speed for the next one
+        for _ in 0..32 {
+            clock.advance(FRAME);
+            let budget = throttle.budget(8_000_000, MAX);
+            stats = throttle.record(budget, budget);
+        }
+        assert_eq!(stats.budget, 128_000);
+        assert!((stats.speed_ratio - 1.0).abs() < 1e-9, "{stats:?}");
+        for _ in 0..32 {
+            clock.advance(FRAME);
+            let budget = throttle.budget(8_000_000, MAX);
+            stats = throttle.record(budget, budget / 2);
+            // The last complete window is reported until the next one ends
+            if stats.speed_ratio < 0.9 {
+                break;
+            }
+        }
+        assert!((stats.achieved_hz - 4_000_000.0).abs() < 1.0, "{stats:?}");
+        assert!((stats.speed_ratio - 0.5).abs() < 1e-6);
+    }
+}
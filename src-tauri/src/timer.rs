@@ -0,0 +1,230 @@
+//! Interval Timer Emulation
+//!
+//! This module emulates a 16-bit periodic timer on the expansion bus. The
+//! timer counts microseconds of emulated time (12 CPU cycles at the default
+//! 12 MHz clock) and sets its expiry flag every time the programmed period
+//! elapses.
+//!
+//! ## Register Map (offsets from $800060)
+//!
+//! | Offset | Read            | Write                  |
+//! |--------|-----------------|------------------------|
+//! | 0      | Control         | Control (RUN, IE)      |
+//! | 2      | Status          | Clear flags (write 1s) |
+//! | 4      | Period (high)   | Period (high)          |
+//! | 6      | Period (low)    | Period (low)           |
+//!
+//! The period is in microseconds; a period of 0 means 65536. Writing either
+//! period byte or setting RUN restarts the count.
+//!
+//! ## Interrupts
+//!
+//! With IE set in the control register, the timer asserts its interrupt
+//! line while IF is set in the status register.
+
+use crate::panel::Peripheral;
+use crate::scheduler::Clocked;
+
+/// CPU cycles per timer tick at the default clock (1 µs at 12 MHz)
+pub const CYCLES_PER_TICK: u64 = 12;
+
+/// Timer register offsets
+pub mod regs {
+    /// Control register
+    pub const CONTROL: u32 = 0;
+    /// Status register
+    pub const STATUS: u32 = 2;
+    /// Period, high byte
+    pub const PERIOD_HI: u32 = 4;
+    /// Period, low byte
+    pub const PERIOD_LO: u32 = 6;
+}
+
+/// Control register bits
+pub mod control {
+    /// Count while set
+    pub const RUN: u8 = 0x01;
+    /// Enable the expiry interrupt
+    pub const IE: u8 = 0x10;
+}
+
+/// Status register bits
+pub mod status {
+    /// The period elapsed (write 1 to clear)
+    pub const IF: u8 = 0x01;
+}
+
+/// 16-bit interval timer peripheral
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Timer {
+    /// Control register
+    control: u8,
+    /// Status register
+    status: u8,
+    /// Period in ticks (0 = 65536)
+    period: u16,
+    /// Cycles counted towards the current period
+    elapsed: u64,
+    /// CPU cycles per tick (1 µs of emulated time)
+    cycles_per_tick: u64,
+}
+
+impl Timer {
+    /// Creates a stopped timer
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            control: 0,
+            status: 0,
+            period: 0,
+            elapsed: 0,
+            cycles_per_tick: CYCLES_PER_TICK,
+        }
+    }
+
+    /// Resets the timer registers
+    pub const fn reset(&mut self) {
+        let cycles_per_tick = self.cycles_per_tick;
+        *self = Self::new();
+        self.cycles_per_tick = cycles_per_tick;
+    }
+
+    /// Sets the CPU clock the tick length is derived from
+    pub const fn set_cpu_clock(&mut self, clock_hz: u32) {
+        self.cycles_per_tick = (clock_hz / 1_000_000) as u64;
+    }
+
+    /// Reads a timer register
+    #[must_use]
+    pub const fn read(&self, offset: u32) -> u8 {
+        match offset {
+            regs::CONTROL => self.control,
+            regs::STATUS => self.status,
+            regs::PERIOD_HI => (self.period >> 8) as u8,
+            regs::PERIOD_LO => self.period as u8,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes a timer register
+    pub const fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            regs::CONTROL => {
+                if value & control::RUN != 0 && self.control & control::RUN == 0 {
+                    self.elapsed = 0;
+                }
+                self.control = value;
+            }
+            regs::STATUS => self.status &= !value,
+            regs::PERIOD_HI => {
+                self.period = (self.period & 0x00FF) | ((value as u16) << 8);
+                self.elapsed = 0;
+            }
+            regs::PERIOD_LO => {
+                self.period = (self.period & 0xFF00) | value as u16;
+                self.elapsed = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the period in CPU cycles
+    #[must_use]
+    pub const fn period_cycles(&self) -> u64 {
+        let ticks = if self.period == 0 {
+            0x1_0000
+        } else {
+            self.period as u64
+        };
+        ticks * self.cycles_per_tick
+    }
+
+    /// Advances the timer by the given number of CPU cycles
+    pub const fn tick(&mut self, cycles: u64) {
+        if self.control & control::RUN == 0 {
+            return;
+        }
+        self.elapsed += cycles;
+        let period = self.period_cycles();
+        if self.elapsed >= period {
+            self.elapsed %= period;
+            self.status |= status::IF;
+        }
+    }
+
+    /// Returns true if the timer is asserting its interrupt line
+    #[must_use]
+    pub const fn interrupt_pending(&self) -> bool {
+        self.control & control::IE != 0 && self.status & status::IF != 0
+    }
+}
+
+impl Clocked for Timer {
+    fn tick(&mut self, cycles: u64) {
+        Self::tick(self, cycles);
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        (self.control & control::RUN != 0)
+            .then(|| self.period_cycles().saturating_sub(self.elapsed))
+    }
+}
+
+impl Peripheral for Timer {
+    fn ui_state(&self) -> serde_json::Value {
+        serde_json::json!({
+            "running": self.control & control::RUN != 0,
+            "period": self.period,
+            "elapsedTicks": self.elapsed / self.cycles_per_tick.max(1),
+            "interruptEnabled": self.control & control::IE != 0,
+            "expired": self.status & status::IF != 0,
+        })
+    }
+
+    fn debug_dump(&self) -> serde_json::Value {
+        let ticks = self.elapsed / self.cycles_per_tick.max(1);
+        serde_json::json!({
+            "control": self.control,
+            "status": self.status,
+            "reload": self.period,
+            "count": ticks,
+            "elapsedCycles": self.elapsed,
+            "cyclesPerTick": self.cycles_per_tick,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_expires_after_period() {
+        let mut timer = Timer::new();
+        timer.write(regs::PERIOD_HI, 0);
+        timer.write(regs::PERIOD_LO, 10);
+        timer.write(regs::CONTROL, control::RUN | control::IE);
+
+        timer.tick(119);
+        assert!(!timer.interrupt_pending());
+        timer.tick(1);
+        assert!(timer.interrupt_pending());
+        assert_eq!(timer.read(regs::STATUS), status::IF);
+
+        timer.write(regs::STATUS, status::IF);
+        assert!(!timer.interrupt_pending());
+    }
+
+    #[test]
+    fn test_timer_stopped_and_masked() {
+        let mut timer = Timer::new();
+        timer.write(regs::PERIOD_LO, 1);
+        timer.tick(1000);
+        assert_eq!(timer.read(regs::STATUS), 0);
+
+        timer.write(regs::CONTROL, control::RUN);
+        timer.tick(12);
+        assert_eq!(timer.read(regs::STATUS), status::IF);
+        assert!(!timer.interrupt_pending());
+    }
+}
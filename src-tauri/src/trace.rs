@@ -0,0 +1,137 @@
+//! Execution Trace
+//!
+//! While tracing is on, `Sbc` records every instruction it is about to
+//! execute (by `run`, `step_n` or `step`) in a ring buffer, so the
+//! debugger can show how the CPU reached the current state. The buffer
+//! keeps the most recent `depth` instructions and is kept after tracing
+//! stops, until the next `start`.
+//!
+//! Entries are numbered from 0 in execution order since tracing started,
+//! so a reader can tell how many instructions fell out of the buffer.
+
+use std::collections::VecDeque;
+
+/// Largest supported trace depth in instructions
+pub const MAX_TRACE_DEPTH: usize = 1 << 20;
+
+/// A traced instruction
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceEntry {
+    /// Position in execution order since tracing started
+    pub index: u64,
+    /// Address of the instruction
+    pub pc: u32,
+    /// First word of the instruction
+    pub opcode: u16,
+    /// Status register before the instruction executed
+    pub sr: u16,
+    /// CPU cycle count before the instruction executed
+    pub cycles: u64,
+    /// Disassembly (`mnemonic operands`), when asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mnemonic: Option<String>,
+}
+
+/// Ring buffer of the most recently executed instructions
+#[derive(Clone, Debug, Default)]
+pub struct TraceBuffer {
+    /// Recorded instructions, oldest first
+    entries: VecDeque<TraceEntry>,
+    /// Most entries kept (0 until tracing first starts)
+    depth: usize,
+    /// Index the next entry gets
+    next_index: u64,
+    /// Whether instructions are being recorded
+    enabled: bool,
+}
+
+impl TraceBuffer {
+    /// Creates an empty buffer with tracing off
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            depth: 0,
+            next_index: 0,
+            enabled: false,
+        }
+    }
+
+    /// Clears the buffer and starts recording the last `depth` instructions
+    /// (at most `MAX_TRACE_DEPTH`)
+    pub fn start(&mut self, depth: usize) {
+        self.depth = depth.clamp(1, MAX_TRACE_DEPTH);
+        self.entries = VecDeque::with_capacity(self.depth.min(4096));
+        self.next_index = 0;
+        self.enabled = true;
+    }
+
+    /// Stops recording, keeping the entries
+    pub const fn stop(&mut self) {
+        self.enabled = false;
+    }
+
+    /// Returns true while instructions are being recorded
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Records an instruction about to execute, dropping the oldest entry
+    /// when the buffer is full
+    pub fn record(&mut self, pc: u32, opcode: u16, sr: u16, cycles: u64) {
+        if !self.enabled {
+            return;
+        }
+        if self.entries.len() == self.depth {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TraceEntry {
+            index: self.next_index,
+            pc,
+            opcode,
+            sr,
+            cycles,
+            mnemonic: None,
+        });
+        self.next_index += 1;
+    }
+
+    /// Returns copies of the most recent `max_entries` entries, oldest first
+    #[must_use]
+    pub fn latest(&self, max_entries: usize) -> Vec<TraceEntry> {
+        let skip = self.entries.len().saturating_sub(max_entries);
+        self.entries.iter().skip(skip).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_buffer_keeps_latest() {
+        let mut trace = TraceBuffer::new();
+        trace.record(0x100, 0x4E71, 0x2700, 0);
+        assert!(trace.latest(10).is_empty());
+
+        trace.start(3);
+        for i in 0..5 {
+            trace.record(0x100 + 2 * i, 0x4E71, 0x2700, u64::from(i) * 4);
+        }
+        trace.stop();
+        trace.record(0x200, 0x4E71, 0x2700, 20);
+
+        let indices: Vec<_> = trace.latest(10).iter().map(|e| e.index).collect();
+        assert_eq!(indices, [2, 3, 4]);
+        let latest = trace.latest(2);
+        assert_eq!(latest.len(), 2);
+        assert_eq!((latest[0].pc, latest[1].pc), (0x106, 0x108));
+        assert_eq!(latest[1].cycles, 16);
+
+        // Starting again clears the buffer
+        trace.start(3);
+        assert!(trace.latest(10).is_empty());
+    }
+}
@@ -22,7 +22,7 @@
 //!
 //! The modem control lines are used for:
 //! - MCR bit 0 (DTR): SPI COPI (Controller Out, Peripheral In)
-//! - MCR bit 1 (RTS): Status LED
+//! - MCR bit 1 (RTS): Status LED (LED 0 of the LED bar, see `led`)
 //! - MCR bit 2 (OUT1): SPI clock
 //! - MCR bit 3 (OUT2): SPI chip select (/SS, directly directly active low)
 //!
@@ -30,12 +30,15 @@
 //! - MSR bit 7 (DCD): SPI CIPO (Controller In, Peripheral Out)
 //! - MSR bit 6 (RI): Button input
 //! - MSR bit 5 (DSR): RTC square wave output
+//! - MSR bit 4 (CTS): `CompactFlash` card detect (when wired, 1 = card present)
 
 // Allow dead code - this module is exercised through the CLI
 #![allow(dead_code)]
 
 use std::collections::VecDeque;
 
+use crate::panel::Peripheral;
+
 /// Base address of the UART in the system memory map
 pub const UART_BASE: u32 = 0x00A0_0000;
 
@@ -103,7 +106,7 @@ pub mod msr {
     pub const TERI: u8 = 0x04;
     /// Delta DCD
     pub const DDCD: u8 = 0x08;
-    /// CTS
+    /// CTS - `CompactFlash` card detect (1 = card present, floats high if not wired)
     pub const CTS: u8 = 0x10;
     /// DSR - RTC square wave output
     pub const SQW: u8 = 0x20;
@@ -151,7 +154,7 @@ pub mod lcr {
 /// Number of LSR reads to keep a break pulse asserted.
 const BREAK_PULSE_READS: u8 = 3;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 enum SpiMode {
     Idle,
     Read,
@@ -159,7 +162,7 @@ enum SpiMode {
 }
 
 /// Minimal DS3234 RTC SPI emulation used by the ROM.
-#[derive(Clone)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 struct RtcSpi {
     regs: [u8; 0x20],
     active: bool,
@@ -264,7 +267,7 @@ const fn finish_byte(&mut self) {
 }
 
 /// 16550 UART emulation state
-#[derive(Clone)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Uart16550 {
     /// Receive FIFO
     rx_fifo: VecDeque<u8>,
@@ -299,6 +302,11 @@ pub struct Uart16550 {
     /// LED state (derived from MCR)
     led_on: bool,
 
+    /// Card-detect input on CTS (None = not wired)
+    card_detect: Option<bool>,
+    /// CTS change latch (DCTS, cleared on MSR read)
+    cts_changed: bool,
+
     /// Interrupt pending flag
     interrupt_pending: bool,
 
@@ -333,6 +341,8 @@ pub fn new() -> Self {
             button_pressed: false,
             button_edge: false,
             led_on: false,
+            card_detect: None,
+            cts_changed: false,
             interrupt_pending: false,
             spi: RtcSpi::new(),
             spi_cipo_inverted: true,
@@ -353,6 +363,7 @@ pub fn reset(&mut self) {
         self.break_active = false;
         self.break_reads_remaining = 0;
         self.button_edge = false;
+        self.cts_changed = false;
         self.interrupt_pending = false;
         self.spi = RtcSpi::new();
         self.spi_cipo_inverted = true;
@@ -405,6 +416,21 @@ pub fn push_rx(&mut self, byte: u8) {
         // If FIFO full, character is dropped (overrun)
     }
 
+    /// Returns the number of bytes the receive FIFO can still take
+    #[must_use]
+    pub fn rx_space(&self) -> usize {
+        FIFO_SIZE - self.rx_fifo.len()
+    }
+
+    /// Returns true while the guest asserts RTS (MCR bit 1)
+    ///
+    /// With hardware flow control the host only sends while RTS is
+    /// asserted. On the target board RTS also drives the status LED.
+    #[must_use]
+    pub const fn rts(&self) -> bool {
+        self.mcr & mcr::LED != 0
+    }
+
     /// Sends a break condition (enters the serial loader)
     pub fn send_break(&mut self) {
         self.break_active = true;
@@ -434,6 +460,29 @@ pub const fn set_button(&mut self, pressed: bool) {
         self.button_pressed = pressed;
     }
 
+    /// Drives the card-detect input wired to CTS
+    ///
+    /// `None` leaves CTS unconnected (reads high). A level change sets DCTS
+    /// and raises a modem status interrupt if enabled.
+    pub const fn set_card_detect(&mut self, present: Option<bool>) {
+        let old_cts = self.cts_level();
+        self.card_detect = present;
+        if self.cts_level() != old_cts {
+            self.cts_changed = true;
+            if self.ier & ier::EDSSI != 0 {
+                self.interrupt_pending = true;
+            }
+        }
+    }
+
+    /// Returns the CTS input level (card present, or high if not wired)
+    const fn cts_level(&self) -> bool {
+        match self.card_detect {
+            Some(present) => present,
+            None => true,
+        }
+    }
+
     /// Reads from a UART register
     ///
     /// `offset` is the byte offset from the UART base address.
@@ -536,7 +585,21 @@ pub fn write(&mut self, offset: u32, value: u8) {
     }
 
     /// Reads the Line Status Register
+    ///
+    /// A break shows for a few reads and then deasserts.
     fn read_lsr(&mut self) -> u8 {
+        let lsr = self.line_status();
+        if self.break_active && self.break_reads_remaining > 0 {
+            self.break_reads_remaining -= 1;
+            if self.break_reads_remaining == 0 {
+                self.break_active = false;
+            }
+        }
+        lsr
+    }
+
+    /// Returns the Line Status Register without counting down a break
+    fn line_status(&self) -> u8 {
         let mut lsr = 0u8;
 
         // THRE (bit 5): TX Holding Register Empty - set if FIFO has space
@@ -554,12 +617,6 @@ fn read_lsr(&mut self) -> u8 {
 
         if self.break_active {
             lsr |= lsr::BI; // Break interrupt
-            if self.break_reads_remaining > 0 {
-                self.break_reads_remaining -= 1;
-                if self.break_reads_remaining == 0 {
-                    self.break_active = false;
-                }
-            }
         }
 
         lsr
@@ -580,8 +637,14 @@ const fn read_msr(&mut self) -> u8 {
             self.button_edge = false; // Clear on read
         }
 
-        // CTS/DSR/DCD - not connected, default high
-        msr |= msr::CTS;
+        // CTS carries card detect (floats high when not wired)
+        if self.cts_level() {
+            msr |= msr::CTS;
+        }
+        if self.cts_changed {
+            msr |= msr::DCTS;
+            self.cts_changed = false; // Clear on read
+        }
         if self.spi_cipo_inverted {
             msr |= msr::CIPO;
         }
@@ -664,6 +727,35 @@ pub fn baud_rate(&self, clock_hz: u32) -> u32 {
     }
 }
 
+impl Peripheral for Uart16550 {
+    fn ui_state(&self) -> serde_json::Value {
+        serde_json::json!({
+            "rxFifo": self.rx_fifo.len(),
+            "txBusy": self.has_tx_data(),
+            "divisor": self.divisor(),
+            "rts": self.rts(),
+            "interruptPending": self.interrupt_pending,
+        })
+    }
+
+    fn debug_dump(&self) -> serde_json::Value {
+        serde_json::json!({
+            "rxFifo": self.rx_fifo,
+            "txFifo": self.tx_fifo,
+            "ier": self.ier,
+            "fcr": self.fcr,
+            "lcr": self.lcr,
+            "mcr": self.mcr,
+            "lsr": self.line_status(),
+            "spr": self.spr,
+            "divisor": self.divisor(),
+            "breakActive": self.break_active,
+            "cardDetect": self.card_detect,
+            "interruptPending": self.interrupt_pending,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -804,4 +896,27 @@ fn test_uart_break_pulse() {
         let lsr = uart.read(regs::LSR);
         assert!(lsr & lsr::BI == 0);
     }
+
+    #[test]
+    fn test_uart_card_detect() {
+        let mut uart = Uart16550::new();
+
+        // Not wired: CTS floats high, no delta
+        let msr = uart.read(regs::MSR);
+        assert!(msr & msr::CTS != 0);
+        assert!(msr & msr::DCTS == 0);
+
+        uart.write(regs::IER_DLM, ier::EDSSI);
+        uart.set_card_detect(Some(false));
+        assert!(uart.interrupt_pending());
+        let msr = uart.read(regs::MSR);
+        assert!(msr & msr::CTS == 0);
+        assert!(msr & msr::DCTS != 0);
+
+        // Delta is cleared on read
+        assert!(uart.read(regs::MSR) & msr::DCTS == 0);
+
+        uart.set_card_detect(Some(true));
+        assert!(uart.read(regs::MSR) & msr::CTS != 0);
+    }
 }
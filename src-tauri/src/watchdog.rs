@@ -0,0 +1,233 @@
+//! Watchdog Timer Emulation
+//!
+//! This module emulates a watchdog timer on the expansion bus. Once enabled,
+//! firmware must kick it before the timeout (in emulated CPU cycles)
+//! elapses; otherwise the watchdog raises a level 7 interrupt (NMI) or
+//! resets the whole machine, depending on the mode bit.
+//!
+//! ## Register Map (offsets from $800080)
+//!
+//! | Offset | Read                 | Write                       |
+//! |--------|----------------------|-----------------------------|
+//! | 0      | Control              | Control (EN, RESET)         |
+//! | 2-8    | Timeout (big-endian) | Timeout (big-endian)        |
+//! | 10     | -                    | Kick (write $A5)            |
+//! | 12     | Status               | Clear flags (write 1s)      |
+//!
+//! The timeout is a 32-bit cycle count spread over four byte registers,
+//! most significant byte first. Writing any timeout byte, enabling the
+//! watchdog, or kicking it restarts the count.
+//!
+//! ## Timeout
+//!
+//! With RESET clear, a timeout sets NMI in the status register, raises a
+//! level 7 interrupt and restarts the count. With RESET set, a timeout
+//! resets the CPU and every peripheral like the host's reset, which disables
+//! the watchdog, but keeps RAM and the console output; WDRF in the status
+//! register then tells firmware the watchdog caused the reset. Any other
+//! reset clears WDRF.
+
+/// Timeout after reset (1 second at 12 MHz)
+pub const DEFAULT_TIMEOUT: u32 = 12_000_000;
+
+/// Value that must be written to the kick register
+pub const KICK_VALUE: u8 = 0xA5;
+
+/// Watchdog register offsets
+pub mod regs {
+    /// Control register
+    pub const CONTROL: u32 = 0;
+    /// Timeout, most significant byte (bytes at 2, 4, 6, 8)
+    pub const TIMEOUT: u32 = 2;
+    /// Kick register
+    pub const KICK: u32 = 10;
+    /// Status register
+    pub const STATUS: u32 = 12;
+}
+
+/// Control register bits
+pub mod control {
+    /// Enable the watchdog
+    pub const EN: u8 = 0x01;
+    /// Reset the machine on timeout (clear = raise NMI)
+    pub const RESET: u8 = 0x02;
+}
+
+/// Status register bits
+pub mod status {
+    /// The last reset was caused by the watchdog
+    pub const WDRF: u8 = 0x01;
+    /// A timeout raised an NMI
+    pub const NMI: u8 = 0x02;
+}
+
+/// What the machine must do after a watchdog timeout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// Raise a level 7 interrupt
+    Nmi,
+    /// Perform a full machine reset
+    Reset,
+}
+
+/// Watchdog timer peripheral
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Watchdog {
+    /// Control register
+    control: u8,
+    /// Status register
+    status: u8,
+    /// Timeout in cycles
+    timeout: u32,
+    /// Cycles since the last kick
+    elapsed: u64,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchdog {
+    /// Creates a disabled watchdog
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            control: 0,
+            status: 0,
+            timeout: DEFAULT_TIMEOUT,
+            elapsed: 0,
+        }
+    }
+
+    /// Resets the watchdog registers, disabling it
+    ///
+    /// `by_watchdog` sets WDRF so firmware can tell why it restarted.
+    pub const fn reset(&mut self, by_watchdog: bool) {
+        *self = Self::new();
+        if by_watchdog {
+            self.status = status::WDRF;
+        }
+    }
+
+    /// Reads a watchdog register
+    #[must_use]
+    pub const fn read(&self, offset: u32) -> u8 {
+        match offset {
+            regs::CONTROL => self.control,
+            regs::TIMEOUT..regs::KICK if offset & 1 == 0 => {
+                let shift = (regs::KICK - 2 - offset) * 4;
+                (self.timeout >> shift) as u8
+            }
+            regs::STATUS => self.status,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes a watchdog register
+    pub const fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            regs::CONTROL => {
+                if value & control::EN != 0 && self.control & control::EN == 0 {
+                    self.elapsed = 0;
+                }
+                self.control = value & (control::EN | control::RESET);
+            }
+            regs::TIMEOUT..regs::KICK if offset & 1 == 0 => {
+                let shift = (regs::KICK - 2 - offset) * 4;
+                self.timeout = (self.timeout & !(0xFF << shift)) | ((value as u32) << shift);
+                self.elapsed = 0;
+            }
+            regs::KICK if value == KICK_VALUE => self.elapsed = 0,
+            regs::STATUS => self.status &= !value,
+            _ => {}
+        }
+    }
+
+    /// Returns true if the watchdog caused the last reset (WDRF)
+    #[must_use]
+    pub const fn reset_by_watchdog(&self) -> bool {
+        self.status & status::WDRF != 0
+    }
+
+    /// Returns true if the watchdog is counting
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.control & control::EN != 0
+    }
+
+    /// Returns the number of cycles until the watchdog times out, or `None`
+    /// while it is disabled
+    #[must_use]
+    pub const fn cycles_until_timeout(&self) -> Option<u64> {
+        if self.enabled() {
+            Some((self.timeout as u64).saturating_sub(self.elapsed))
+        } else {
+            None
+        }
+    }
+
+    /// Advances the watchdog by the given number of CPU cycles
+    ///
+    /// Returns the action the machine must take if the timeout elapsed.
+    pub const fn tick(&mut self, cycles: u64) -> Option<WatchdogAction> {
+        if !self.enabled() {
+            return None;
+        }
+        self.elapsed += cycles;
+        if self.elapsed < self.timeout as u64 {
+            return None;
+        }
+
+        self.elapsed = 0;
+        if self.control & control::RESET != 0 {
+            Some(WatchdogAction::Reset)
+        } else {
+            self.status |= status::NMI;
+            Some(WatchdogAction::Nmi)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_kick_restarts_count() {
+        let mut wd = Watchdog::new();
+        for (i, byte) in 100u32.to_be_bytes().into_iter().enumerate() {
+            wd.write(regs::TIMEOUT + 2 * i as u32, byte);
+        }
+        assert_eq!(wd.read(regs::TIMEOUT + 6), 100);
+        assert_eq!(wd.read(regs::TIMEOUT), 0);
+        wd.write(regs::CONTROL, control::EN);
+
+        for _ in 0..10 {
+            assert_eq!(wd.tick(90), None);
+            wd.write(regs::KICK, KICK_VALUE);
+        }
+        // Other values don't kick
+        wd.write(regs::KICK, 0);
+        assert_eq!(wd.tick(90), None);
+        assert_eq!(wd.tick(10), Some(WatchdogAction::Nmi));
+        assert_eq!(wd.read(regs::STATUS), status::NMI);
+    }
+
+    #[test]
+    fn test_watchdog_reset_mode() {
+        let mut wd = Watchdog::new();
+        wd.write(regs::CONTROL, control::EN | control::RESET);
+        assert_eq!(
+            wd.tick(u64::from(DEFAULT_TIMEOUT)),
+            Some(WatchdogAction::Reset)
+        );
+
+        wd.reset(true);
+        assert!(!wd.enabled());
+        assert_eq!(wd.read(regs::STATUS), status::WDRF);
+        wd.reset(false);
+        assert_eq!(wd.read(regs::STATUS), 0);
+    }
+}
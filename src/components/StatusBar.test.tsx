@@ -31,13 +31,25 @@ function mockStatus(
 
 // Helper to create mock CPU state with all required fields
 function mockCpuState(overrides: Partial<{ pc: number; sr: number }> = {}) {
+  const sr = overrides.sr ?? 0;
   return {
     pc: overrides.pc ?? 0,
-    sr: overrides.sr ?? 0,
+    sr,
     d: [0, 0, 0, 0, 0, 0, 0, 0],
     a: [0, 0, 0, 0, 0, 0, 0, 0],
+    a7: 0,
     usp: 0,
     ssp: 0,
+    flags: {
+      c: (sr & 0x01) !== 0,
+      v: (sr & 0x02) !== 0,
+      z: (sr & 0x04) !== 0,
+      n: (sr & 0x08) !== 0,
+      x: (sr & 0x10) !== 0,
+    },
+    trace: (sr & 0x8000) !== 0,
+    supervisor: (sr & 0x2000) !== 0,
+    interruptMask: (sr >> 8) & 7,
   };
 }
 
@@ -75,6 +87,26 @@ describe("StatusBar", () => {
     expect(screen.getByText("Halted")).toBeInTheDocument();
   });
 
+  it("shows why the last run stopped", () => {
+    render(
+      <StatusBar
+        {...defaultProps}
+        initialized={true}
+        status={{
+          ...mockStatus({ halted: false }),
+          run: {
+            instructions: 1,
+            cycles: 4,
+            stop: { kind: "breakpoint", id: 2, address: 0xe00102 },
+            pc: 0xe00102,
+          },
+        }}
+      />,
+    );
+
+    expect(screen.getByText("Breakpoint 2 at $e00102")).toBeInTheDocument();
+  });
+
   it("shows Running when loading", () => {
     render(
       <StatusBar
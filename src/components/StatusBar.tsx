@@ -5,7 +5,7 @@
  * and version info. Modeled after VS Code / desktop IDE status bars.
  */
 
-import type { EmulatorStatus } from "../lib/emulator-types";
+import type { EmulatorStatus, StopReason } from "../lib/emulator-types";
 import type { CpuState } from "../lib/emulator-types";
 import { cn } from "../lib/utils";
 import { Separator } from "./ui/separator";
@@ -19,6 +19,31 @@ interface StatusBarProps {
   ledState?: boolean;
 }
 
+/** Formats an address as $xxxxxx */
+function hex(value: number): string {
+  return "$" + value.toString(16).toLowerCase().padStart(6, "0");
+}
+
+/** Describes why the last run stopped, or null if there is nothing to tell */
+function describeStop(stop: StopReason): string | null {
+  switch (stop.kind) {
+    case "budgetExhausted":
+    case "stepsCompleted":
+    case "halted":
+      return null;
+    case "paused":
+      return "Paused";
+    case "breakpoint":
+      return `Breakpoint ${stop.id} at ${hex(stop.address)}`;
+    case "watchpoint":
+      return `Watchpoint ${stop.id}: ${stop.access} at ${hex(stop.address)}`;
+    case "uninitializedVector":
+      return `Uninitialized vector ${stop.vector} at ${hex(stop.pc)}`;
+    case "exit":
+      return `Exited with code ${stop.code}`;
+  }
+}
+
 /** Desktop-style status bar pinned to the bottom of the window */
 export function StatusBar({
   status,
@@ -29,6 +54,7 @@ export function StatusBar({
 }: StatusBarProps) {
   const halted = status?.halted ?? true;
   const cycles = status?.cycles ?? 0;
+  const stopMessage = status?.run ? describeStop(status.run.stop) : null;
   const isRunning = loading ?? false;
 
   return (
@@ -91,6 +117,14 @@ export function StatusBar({
           </>
         )}
 
+        {/* Why the last run stopped */}
+        {stopMessage && (
+          <>
+            <Separator orientation="vertical" className="bg-border/60 h-3.5" />
+            <span className="truncate px-1.5 text-[10px]">{stopMessage}</span>
+          </>
+        )}
+
         {/* Error message */}
         {error && (
           <>
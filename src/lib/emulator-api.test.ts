@@ -13,12 +13,34 @@ describe("EmulatorAPI", () => {
   });
 
   it("init calls invoke with correct command", async () => {
-    (invoke as unknown as Mock).mockResolvedValue("Emulator initialized");
+    const config = { clockHz: 12000000, ramSize: 1048576 };
+    (invoke as unknown as Mock).mockResolvedValue(config);
 
     const result = await EmulatorAPI.init();
 
     expect(invoke).toHaveBeenCalledWith("emulator_init");
-    expect(result).toEqual({ status: "success", data: "Emulator initialized" });
+    expect(result).toEqual({ status: "success", data: config });
+  });
+
+  it("init passes the ROM path when given", async () => {
+    (invoke as unknown as Mock).mockResolvedValue({});
+
+    await EmulatorAPI.init("/roms/boot.bin");
+
+    expect(invoke).toHaveBeenCalledWith("emulator_init", {
+      romPath: "/roms/boot.bin",
+    });
+  });
+
+  it("init passes the machine config when given", async () => {
+    (invoke as unknown as Mock).mockResolvedValue({});
+
+    await EmulatorAPI.init(undefined, { ramSize: 262144, openBus: "low" });
+
+    expect(invoke).toHaveBeenCalledWith("emulator_init", {
+      romPath: undefined,
+      config: { ramSize: 262144, openBus: "low" },
+    });
   });
 
   it("handles errors gracefully", async () => {
@@ -29,4 +51,47 @@ describe("EmulatorAPI", () => {
     expect(invoke).toHaveBeenCalledWith("emulator_init");
     expect(result).toEqual({ status: "error", error: "Failed to init" });
   });
+
+  it("keeps structured errors from the backend", async () => {
+    const details = {
+      code: "invalidArgument",
+      field: "ramSize",
+      message: "Invalid ramSize: 1000 bytes is not a power of two",
+    };
+    (invoke as unknown as Mock).mockRejectedValue(details);
+
+    const result = await EmulatorAPI.init(undefined, { ramSize: 1000 });
+
+    expect(result).toEqual({
+      status: "error",
+      error: details.message,
+      details,
+    });
+  });
+
+  it("fillMemory sends a value or a pattern", async () => {
+    const written = { written: 16, failedAt: null };
+    (invoke as unknown as Mock).mockResolvedValue(written);
+
+    await EmulatorAPI.fillMemory(0xe02000, 16, 0xaa);
+    const result = await EmulatorAPI.fillMemory(0xe02000, 16, [1, 2], true);
+
+    expect(invoke).toHaveBeenNthCalledWith(1, "emulator_fill_memory", {
+      address: 0xe02000,
+      length: 16,
+      value: 0xaa,
+      pattern: undefined,
+      dryRun: false,
+      instance: undefined,
+    });
+    expect(invoke).toHaveBeenNthCalledWith(2, "emulator_fill_memory", {
+      address: 0xe02000,
+      length: 16,
+      value: undefined,
+      pattern: [1, 2],
+      dryRun: true,
+      instance: undefined,
+    });
+    expect(result).toEqual({ status: "success", data: written });
+  });
 });
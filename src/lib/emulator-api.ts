@@ -6,127 +6,542 @@
  */
 
 import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import type {
+  AssembledSection,
+  Breakpoint,
+  CallFrame,
+  CheckedAssembly,
+  CheckedAssemblyResult,
   CpuState,
+  Diagnostics,
+  DisassemblyLine,
+  EmulatorError,
+  EmulatorFailure,
   EmulatorResult,
   EmulatorStatus,
+  Evaluation,
+  GpioState,
+  HexLoadResult,
+  IncrementalAssembly,
+  IncrementalAssemblyResult,
+  InstanceEvent,
+  InterruptOutcome,
+  LinkedProgram,
+  LoadedProgram,
   MemoryViewOptions,
+  MemoryWriteResult,
+  PeripheralsState,
+  ProfileReport,
+  ProjectAssembly,
+  ResetMode,
+  RomImage,
+  RunStopped,
+  SbcConfig,
+  SnapshotInfo,
+  StackEntry,
+  StepNResult,
+  StepResult,
+  SymbolInfo,
+  SymbolMapFormat,
+  SymbolMatch,
+  ToneEvent,
+  TraceEntry,
+  UartLineEnding,
+  UartQueueStatus,
+  WatchExpression,
 } from "./emulator-types";
 
+/** Id of the emulator instance calls use by default */
+export const PRIMARY_INSTANCE = 0;
+
+/**
+ * Returns true for the structured errors commands reject with
+ */
+function isEmulatorError(error: unknown): error is EmulatorError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    typeof (error as { code?: unknown }).code === "string" &&
+    typeof (error as { message?: unknown }).message === "string"
+  );
+}
+
+/**
+ * Turns a rejected command into a failed result, keeping the backend's
+ * structured error as `details`
+ */
+function failure(error: unknown): EmulatorFailure {
+  if (isEmulatorError(error)) {
+    return { status: "error", error: error.message, details: error };
+  }
+  return {
+    status: "error",
+    error: error instanceof Error ? error.message : String(error),
+  };
+}
+
+/**
+ * Listens for an event from one emulator instance
+ */
+function listenInstance<T>(
+  event: string,
+  instance: number,
+  callback: (payload: T) => void,
+): Promise<UnlistenFn> {
+  return listen<InstanceEvent<T>>(event, (e) => {
+    if (e.payload.instance === instance) {
+      callback(e.payload.payload);
+    }
+  });
+}
+
 /**
  * Flux32 Emulator API class
  *
  * Provides methods to interact with the M68K emulator backend.
  * All methods return promises that resolve to the operation result.
+ * Methods that act on a machine take an optional `instance` id last and
+ * default to the primary instance.
  */
 export class EmulatorAPI {
   /**
-   * Initialize a new emulator instance
+   * Initialize an emulator instance (the primary one by default)
+   *
+   * With `romPath` or `config`, the instance is (re)created: `config` builds
+   * the machine and `romPath` boots from that ROM image instead of the
+   * embedded one. Invalid configurations are rejected with an error naming
+   * the field.
+   * @returns The configuration the instance runs with
    */
-  static async init(): Promise<EmulatorResult<string>> {
+  static async init(
+    romPath?: string,
+    config?: SbcConfig,
+    instance?: number,
+  ): Promise<EmulatorResult<SbcConfig>> {
     try {
-      const result = await invoke<string>("emulator_init");
+      const result =
+        romPath === undefined && config === undefined && instance === undefined
+          ? await invoke<SbcConfig>("emulator_init")
+          : await invoke<SbcConfig>("emulator_init", {
+              romPath,
+              config,
+              instance,
+            });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
+    }
+  }
+
+  /**
+   * Create an emulator instance alongside the existing ones
+   * @param config Machine configuration (defaults to the stock board)
+   * @returns The new instance's id
+   */
+  static async createInstance(
+    config?: SbcConfig,
+  ): Promise<EmulatorResult<number>> {
+    try {
+      const id = await invoke<number>("emulator_create_instance", { config });
+      return { status: "success", data: id };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Destroy an emulator instance, stopping its background run
+   */
+  static async destroyInstance(
+    instance: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_destroy_instance", { instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * List the ids of the emulator instances
+   */
+  static async listInstances(): Promise<EmulatorResult<number[]>> {
+    try {
+      const ids = await invoke<number[]>("emulator_list_instances");
+      return { status: "success", data: ids };
+    } catch (error) {
+      return failure(error);
     }
   }
 
   /**
    * Execute a single instruction step
    */
-  static async step(): Promise<EmulatorResult<string>> {
+  static async step(instance?: number): Promise<EmulatorResult<string>> {
+    try {
+      const result = await invoke<string>("emulator_step", { instance });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Execute a single instruction step and report what it did, including the
+   * UART output it produced
+   */
+  static async stepEx(
+    instance?: number,
+  ): Promise<EmulatorResult<StepResult>> {
+    try {
+      const result = await invoke<StepResult>("emulator_step_ex", {
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Execute up to `count` instructions in one call, stopping early when the
+   * CPU halts
+   * @param count Most instructions to execute
+   * @param stopOnBreakpoint Stop on breakpoints and watchpoints
+   * @param trace Return the PCs of the executed instructions
+   */
+  static async stepN(
+    count: number,
+    stopOnBreakpoint = true,
+    trace = false,
+    instance?: number,
+  ): Promise<EmulatorResult<StepNResult>> {
+    try {
+      const result = await invoke<StepNResult>("emulator_step_n", {
+        count,
+        stopOnBreakpoint,
+        trace,
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Execute the instruction at the PC, running a JSR, BSR or TRAP until it
+   * returns; breakpoints in the callee stop it
+   * @param maxCycles Most cycles to run (default: 10000000); the stop reason
+   *   is `budgetExhausted` when they run out
+   */
+  static async stepOver(
+    maxCycles?: number,
+    instance?: number,
+  ): Promise<EmulatorResult<StepNResult>> {
+    try {
+      const result = await invoke<StepNResult>("emulator_step_over", {
+        maxCycles,
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Run until the current subroutine returns to its caller, stopping like
+   * `stepOver`
+   * @param maxCycles Most cycles to run (default: 10000000)
+   */
+  static async stepOut(
+    maxCycles?: number,
+    instance?: number,
+  ): Promise<EmulatorResult<StepNResult>> {
     try {
-      const result = await invoke<string>("emulator_step");
+      const result = await invoke<StepNResult>("emulator_step_out", {
+        maxCycles,
+        instance,
+      });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
   /**
-   * Reset the emulator to initial state
+   * Reset the emulator
+   * @param mode "cold" (default) power-cycles the machine, "warm" keeps RAM,
+   *   the CF card and breakpoints, "cpu_only" just reloads SSP and PC
    */
-  static async reset(): Promise<EmulatorResult<string>> {
+  static async reset(
+    mode?: ResetMode,
+    instance?: number,
+  ): Promise<EmulatorResult<string>> {
     try {
-      const result = await invoke<string>("emulator_reset");
+      const result = await invoke<string>("emulator_reset", {
+        mode,
+        instance,
+      });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
   /**
-   * Run the emulator continuously
+   * Run the emulator continuously; UART output streams to `onUartOutput`
+   * listeners while it runs
    * @param maxCycles Maximum number of cycles to execute (default: 100000)
    */
   static async run(
     maxCycles?: number,
+    instance?: number,
   ): Promise<EmulatorResult<EmulatorStatus>> {
     try {
       const result = await invoke<EmulatorStatus>("emulator_run", {
         maxCycles,
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Run for the wall time since the previous call at a target clock
+   * frequency; call it from an animation frame loop
+   * @param targetHz Clock frequency to emulate (default: the configured clock)
+   * @param durationMs Most wall time one call makes up for (default: 100)
+   * @returns Status, with the achieved speed in `throttle`
+   */
+  static async runThrottled(
+    targetHz?: number,
+    durationMs?: number,
+    instance?: number,
+  ): Promise<EmulatorResult<EmulatorStatus>> {
+    try {
+      const result = await invoke<EmulatorStatus>("emulator_run_throttled", {
+        targetHz,
+        durationMs,
+        instance,
       });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
+    }
+  }
+
+  /**
+   * Start (or resume) running the emulator in the background; other calls
+   * keep working while it runs
+   */
+  static async start(instance?: number): Promise<EmulatorResult<string>> {
+    try {
+      const result = await invoke<string>("emulator_start", { instance });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Pause a background run (resume it with `start`)
+   */
+  static async pause(instance?: number): Promise<EmulatorResult<string>> {
+    try {
+      const result = await invoke<string>("emulator_pause", { instance });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Stop a background run
+   */
+  static async stop(instance?: number): Promise<EmulatorResult<string>> {
+    try {
+      const result = await invoke<string>("emulator_stop", { instance });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
     }
   }
 
+  /**
+   * Subscribe to UART output streamed during `run`
+   * @param callback Called with each chunk of bytes, in order; every chunk
+   *   arrives before `run` returns
+   * @returns Function that removes the listener
+   */
+  static async onUartOutput(
+    callback: (bytes: number[]) => void,
+    instance = PRIMARY_INSTANCE,
+  ): Promise<UnlistenFn> {
+    return listenInstance("uart-output", instance, callback);
+  }
+
+  /**
+   * Subscribe to background run stops (breakpoints, halts, pauses)
+   * @param callback Called with the run's totals and stop reason
+   * @returns Function that removes the listener
+   */
+  static async onRunStopped(
+    callback: (stopped: RunStopped) => void,
+    instance = PRIMARY_INSTANCE,
+  ): Promise<UnlistenFn> {
+    return listenInstance("run-stopped", instance, callback);
+  }
+
   /**
    * Get emulator status (halted state, cycle count)
    */
-  static async getStatus(): Promise<EmulatorResult<EmulatorStatus>> {
+  static async getStatus(
+    instance?: number,
+  ): Promise<EmulatorResult<EmulatorStatus>> {
+    try {
+      const result = await invoke<EmulatorStatus>("emulator_get_status", {
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Get a diagnostics report (memory map, peripherals, ROM CRC, latched
+   * faults) to attach to bug reports
+   */
+  static async getDiagnostics(
+    instance?: number,
+  ): Promise<EmulatorResult<Diagnostics>> {
     try {
-      const result = await invoke<EmulatorStatus>("emulator_get_status");
+      const result = await invoke<Diagnostics>("emulator_get_diagnostics", {
+        instance,
+      });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
+    }
+  }
+
+  /**
+   * Save the full machine state to a snapshot file
+   * @param path Snapshot file to write
+   * @returns The snapshot's metadata
+   */
+  static async saveSnapshot(
+    path: string,
+    instance?: number,
+  ): Promise<EmulatorResult<SnapshotInfo>> {
+    try {
+      const info = await invoke<SnapshotInfo>("emulator_save_snapshot", {
+        path,
+        instance,
+      });
+      return { status: "success", data: info };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Replace the machine with one restored from a snapshot file (the current
+   * machine is kept if loading fails)
+   * @param path Snapshot file to read
+   * @returns The snapshot's metadata
+   */
+  static async loadSnapshot(
+    path: string,
+    instance?: number,
+  ): Promise<EmulatorResult<SnapshotInfo>> {
+    try {
+      const info = await invoke<SnapshotInfo>("emulator_load_snapshot", {
+        path,
+        instance,
+      });
+      return { status: "success", data: info };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Read a snapshot file's metadata without loading it
+   * @param path Snapshot file to read
+   */
+  static async snapshotInfo(
+    path: string,
+  ): Promise<EmulatorResult<SnapshotInfo>> {
+    try {
+      const info = await invoke<SnapshotInfo>("emulator_snapshot_info", {
+        path,
+      });
+      return { status: "success", data: info };
+    } catch (error) {
+      return failure(error);
     }
   }
 
   /**
    * Get the current CPU register state
    */
-  static async getRegisters(): Promise<EmulatorResult<CpuState>> {
+  static async getRegisters(
+    instance?: number,
+  ): Promise<EmulatorResult<CpuState>> {
+    try {
+      const result = await invoke<CpuState>("emulator_get_registers", {
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Write a CPU register and return the updated register state
+   * @param name D0-D7, A0-A7 (or SP), PC, SR, USP or SSP
+   * @param value New value (PC must be even; SR is 16 bits)
+   */
+  static async writeRegister(
+    name: string,
+    value: number,
+    instance?: number,
+  ): Promise<EmulatorResult<CpuState>> {
     try {
-      const result = await invoke<CpuState>("emulator_get_registers");
+      const result = await invoke<CpuState>("emulator_write_register", {
+        name,
+        value,
+        instance,
+      });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
   /**
    * Read a byte from memory at the given address
    */
-  static async readByte(address: number): Promise<EmulatorResult<number>> {
+  static async readByte(
+    address: number,
+    instance?: number,
+  ): Promise<EmulatorResult<number>> {
     try {
-      const result = await invoke<number>("emulator_read_byte", { address });
+      const result = await invoke<number>("emulator_read_byte", {
+        address,
+        instance,
+      });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
@@ -136,18 +551,17 @@ export class EmulatorAPI {
   static async readMemory(
     address: number,
     length: number,
+    instance?: number,
   ): Promise<EmulatorResult<number[]>> {
     try {
       const result = await invoke<number[]>("emulator_read_memory", {
         address,
         length,
+        instance,
       });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
@@ -157,95 +571,1213 @@ export class EmulatorAPI {
   static async writeByte(
     address: number,
     value: number,
+    instance?: number,
   ): Promise<EmulatorResult<null>> {
     try {
-      await invoke("emulator_write_byte", { address, value });
+      await invoke("emulator_write_byte", { address, value, instance });
       return { status: "success", data: null };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
   /**
-   * Assemble M68K assembly code
+   * Write a block of bytes to memory
+   * @param address First address to write
+   * @param data Bytes to write
+   * @param overrideRom Patch ROM bytes instead of treating ROM as protected
+   * @returns Bytes written and the first address that couldn't be written
    */
-  static async assemble(code: string): Promise<EmulatorResult<number[]>> {
+  static async writeMemory(
+    address: number,
+    data: number[],
+    overrideRom = false,
+    instance?: number,
+  ): Promise<EmulatorResult<MemoryWriteResult>> {
     try {
-      const result = await invoke<number[]>("emulator_assemble", { code });
+      const result = await invoke<MemoryWriteResult>("emulator_write_memory", {
+        address,
+        data,
+        overrideRom,
+        instance,
+      });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
   /**
-   * Assemble code, load into RAM, and start execution
+   * Fill a block of memory with a byte or a repeating pattern
+   * @param address First address to fill
+   * @param length Number of bytes to fill
+   * @param fill Byte value, or a pattern of bytes to repeat
+   * @param dryRun Report what would be written without writing
+   * @returns Bytes written and the first address that couldn't be written
    */
-  static async assembleAndLoad(code: string): Promise<EmulatorResult<string>> {
+  static async fillMemory(
+    address: number,
+    length: number,
+    fill: number | number[],
+    dryRun = false,
+    instance?: number,
+  ): Promise<EmulatorResult<MemoryWriteResult>> {
     try {
-      const result = await invoke<string>("emulator_assemble_and_load", {
-        code,
+      const [value, pattern] = Array.isArray(fill)
+        ? [undefined, fill]
+        : [fill, undefined];
+      const result = await invoke<MemoryWriteResult>("emulator_fill_memory", {
+        address,
+        length,
+        value,
+        pattern,
+        dryRun,
+        instance,
       });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
   /**
-   * Read UART output (drain TX buffer)
+   * Load a raw binary file into memory
+   * @param path Binary file on disk
+   * @param address Load address (RAM, or ROM with `overrideRom`)
+   * @param start Set the PC to `address` and resume a halted CPU
+   * @param overrideRom Allow loading into the ROM image
+   * @returns Number of bytes loaded
+   */
+  static async loadBinary(
+    path: string,
+    address: number,
+    start = false,
+    overrideRom = false,
+    instance?: number,
+  ): Promise<EmulatorResult<number>> {
+    try {
+      const size = await invoke<number>("emulator_load_binary", {
+        path,
+        address,
+        start,
+        overrideRom,
+        instance,
+      });
+      return { status: "success", data: size };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Load a Motorola S-record or Intel HEX file into memory
+   * @param pathOrText Path of the file, or its text
+   * @param start Set the PC to the file's start address and resume a halted CPU
+   * @returns Segments written and the file's start address
    */
-  static async readUart(): Promise<EmulatorResult<number[]>> {
+  static async loadHex(
+    pathOrText: string,
+    start = false,
+    instance?: number,
+  ): Promise<EmulatorResult<HexLoadResult>> {
     try {
-      const result = await invoke<number[]>("emulator_read_uart");
+      const result = await invoke<HexLoadResult>("emulator_load_hex", {
+        pathOrText,
+        start,
+        instance,
+      });
       return { status: "success", data: result };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
   /**
-   * Write a character to UART RX (simulate keyboard input)
+   * Disassemble instructions
+   * @param address Start address
+   * @param count Number of instructions
+   * @param followPc Start at the program counter instead of `address`
+   * @returns The instructions, ending early with an "(unmapped)" entry if
+   *   the listing runs off ROM and RAM
+   */
+  static async disassemble(
+    address: number,
+    count: number,
+    followPc = false,
+    instance?: number,
+  ): Promise<EmulatorResult<DisassemblyLine[]>> {
+    try {
+      const lines = await invoke<DisassemblyLine[]>("emulator_disassemble", {
+        address,
+        count,
+        followPc,
+        instance,
+      });
+      return { status: "success", data: lines };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Reconstruct the call stack, the current routine first
+   * @param maxFrames Most frames to return
+   * @returns The frames; those found by scanning the stack rather than
+   *   following LINK frames have `isLinkFrame` false and may be stale
+   */
+  static async getCallStack(
+    maxFrames: number,
+    instance?: number,
+  ): Promise<EmulatorResult<CallFrame[]>> {
+    try {
+      const frames = await invoke<CallFrame[]>("emulator_get_callstack", {
+        maxFrames,
+        instance,
+      });
+      return { status: "success", data: frames };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Read the stack from the stack pointer upward
+   * @param maxEntries Number of longwords to read
+   * @returns The longwords with their annotations, ending early if the
+   *   stack runs off ROM and RAM
+   */
+  static async getStack(
+    maxEntries: number,
+    instance?: number,
+  ): Promise<EmulatorResult<StackEntry[]>> {
+    try {
+      const entries = await invoke<StackEntry[]>("emulator_get_stack", {
+        maxEntries,
+        instance,
+      });
+      return { status: "success", data: entries };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Start recording executed instructions (clears any earlier trace)
+   * @param depth Number of most recent instructions to keep
    */
-  static async writeUart(byte: number): Promise<EmulatorResult<null>> {
+  static async traceStart(
+    depth: number,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
     try {
-      await invoke("emulator_write_uart", { byte });
+      await invoke("emulator_trace_start", { depth, instance });
       return { status: "success", data: null };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
   /**
-   * Get LED state
+   * Stop recording executed instructions, keeping the trace
    */
-  static async getLed(): Promise<EmulatorResult<boolean>> {
+  static async traceStop(instance?: number): Promise<EmulatorResult<null>> {
     try {
-      const result = await invoke<boolean>("emulator_get_led");
-      return { status: "success", data: result };
+      await invoke("emulator_trace_stop", { instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Get the most recently executed instructions, oldest first
+   * @param maxEntries Number of entries to return
+   * @param disassemble Include each instruction's disassembly
+   */
+  static async getTrace(
+    maxEntries: number,
+    disassemble = false,
+    instance?: number,
+  ): Promise<EmulatorResult<TraceEntry[]>> {
+    try {
+      const entries = await invoke<TraceEntry[]>("emulator_get_trace", {
+        maxEntries,
+        disassemble,
+        instance,
+      });
+      return { status: "success", data: entries };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Start profiling (can join a background run)
+   */
+  static async profileStart(instance?: number): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_profile_start", { instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Stop profiling, keeping the samples
+   */
+  static async profileStop(instance?: number): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_profile_stop", { instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Discard the profile samples
+   */
+  static async profileReset(instance?: number): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_profile_reset", { instance });
+      return { status: "success", data: null };
     } catch (error) {
-      return {
-        status: "error",
-        error: error instanceof Error ? error.message : String(error),
-      };
+      return failure(error);
     }
   }
 
+  /**
+   * Get the hottest addresses and the cycles spent per mnemonic
+   * @param topN Number of addresses to list
+   */
+  static async profileReport(
+    topN: number,
+    instance?: number,
+  ): Promise<EmulatorResult<ProfileReport>> {
+    try {
+      const report = await invoke<ProfileReport>("emulator_profile_report", {
+        topN,
+        instance,
+      });
+      return { status: "success", data: report };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Add a breakpoint
+   * @param address Address of the instruction to stop at
+   * @param condition Expression that must hold for the breakpoint to hit
+   * (e.g. `d0 = 3` or `word(a0) <> 0`)
+   * @param skipCount Number of hits to let pass before stopping
+   * @returns The breakpoint's id
+   */
+  static async addBreakpoint(
+    address: number,
+    condition?: string,
+    skipCount?: number,
+    instance?: number,
+  ): Promise<EmulatorResult<number>> {
+    try {
+      const id = await invoke<number>("emulator_breakpoint_add", {
+        address,
+        condition,
+        skipCount,
+        instance,
+      });
+      return { status: "success", data: id };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Remove a breakpoint
+   */
+  static async removeBreakpoint(
+    id: number,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_breakpoint_remove", { id, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Enable or disable a breakpoint
+   */
+  static async setBreakpointEnabled(
+    id: number,
+    enabled: boolean,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_breakpoint_set_enabled", {
+        id,
+        enabled,
+        instance,
+      });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * List the breakpoints with their hit counts
+   */
+  static async listBreakpoints(
+    instance?: number,
+  ): Promise<EmulatorResult<Breakpoint[]>> {
+    try {
+      const list = await invoke<Breakpoint[]>("emulator_breakpoint_list", {
+        instance,
+      });
+      return { status: "success", data: list };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Assemble M68K assembly code
+   * @param rom Pad the binary to a ROM image (overrides a ROMSIZE directive)
+   */
+  static async assemble(
+    code: string,
+    rom?: RomImage,
+  ): Promise<EmulatorResult<number[]>> {
+    try {
+      const result = await invoke<number[]>("emulator_assemble", {
+        code,
+        rom,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Assemble M68K assembly code with a project's own include files
+   * @param files Include files by name, tried before the disk
+   * @param includeDirs Extra directories to search for includes
+   * @returns The binary and where each include was read from
+   */
+  static async assembleProject(
+    code: string,
+    files?: Record<string, string>,
+    includeDirs?: string[],
+  ): Promise<EmulatorResult<ProjectAssembly>> {
+    try {
+      const result = await invoke<ProjectAssembly>(
+        "emulator_assemble_project",
+        { code, files, includeDirs },
+      );
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Assemble M68K assembly code, reporting errors and warnings with their
+   * positions
+   * @param optimize Use MOVEQ/ADDQ/SUBQ for eligible immediates (off by
+   *   default)
+   * @param pcRelative Address nearby labels PC-relative in source operands,
+   *   unless written with .L or .W (off by default)
+   * @param ignoreCase Treat symbol names as case-insensitive (off by
+   *   default; symbols differing only in case are warned about)
+   * @param rom Pad the binary to a ROM image (overrides a ROMSIZE directive)
+   * @param strictAlignment Make word data and branch targets at odd
+   *   addresses errors (off by default; they're warned about)
+   * @returns The binary and any warnings, or every error found
+   */
+  static async assembleChecked(
+    code: string,
+    optimize?: boolean,
+    pcRelative?: boolean,
+    ignoreCase?: boolean,
+    rom?: RomImage,
+    strictAlignment?: boolean,
+  ): Promise<CheckedAssemblyResult> {
+    try {
+      const result = await invoke<CheckedAssembly>(
+        "emulator_assemble_checked",
+        { code, optimize, pcRelative, ignoreCase, rom, strictAlignment },
+      );
+      return { status: "success", data: result };
+    } catch (error) {
+      const result = failure(error);
+      const diagnostics =
+        result.details?.code === "assemblyFailed"
+          ? result.details.diagnostics
+          : [];
+      return { ...result, diagnostics };
+    }
+  }
+
+  /**
+   * Assemble editor code, reusing what the last call learned about the
+   * includes, lines and label addresses that haven't changed. Meant for
+   * assembling as the user types; the result is the same as
+   * `assembleChecked` with default options.
+   * @returns The binary, warnings and timings, or every error found
+   */
+  static async assembleIncremental(
+    code: string,
+  ): Promise<IncrementalAssemblyResult> {
+    try {
+      const result = await invoke<IncrementalAssembly>(
+        "emulator_assemble_incremental",
+        { code },
+      );
+      return { status: "success", data: result };
+    } catch (error) {
+      const result = failure(error);
+      const diagnostics =
+        result.details?.code === "assemblyFailed"
+          ? result.details.diagnostics
+          : [];
+      return { ...result, diagnostics };
+    }
+  }
+
+  /**
+   * Assemble M68K assembly code into Motorola S-records
+   * @param recordLength Data bytes per record (16 by default, at most 250)
+   * @returns The S-record text, one record per line
+   */
+  static async assembleSRecords(
+    code: string,
+    recordLength?: number,
+  ): Promise<EmulatorResult<string>> {
+    try {
+      const result = await invoke<string>("emulator_assemble_srecords", {
+        code,
+        recordLength,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Assemble M68K assembly code into a listing of addresses, bytes and
+   * source lines
+   * @param expand Show the lines macro calls and REPT blocks expand to
+   * @returns The listing text, one line per source line
+   */
+  static async assembleListing(
+    code: string,
+    expand?: boolean,
+  ): Promise<EmulatorResult<string>> {
+    try {
+      const result = await invoke<string>("emulator_assemble_listing", {
+        code,
+        expand,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Assemble M68K assembly code into its sections, placed code first, then
+   * data, then BSS
+   * @returns Each section's origin and bytes (a BSS section has only a size)
+   */
+  static async assembleSections(
+    code: string,
+  ): Promise<EmulatorResult<AssembledSection[]>> {
+    try {
+      const result = await invoke<AssembledSection[]>(
+        "emulator_assemble_sections",
+        { code },
+      );
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Assemble M68K assembly code into a relocatable object file
+   * @param path Where to write the object; link errors name it after the file
+   */
+  static async assembleObject(
+    code: string,
+    path: string,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_assemble_object", { code, path });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Link object files into one program
+   * @param origin Address of the first byte (APP_START by default)
+   * @returns The program's bytes and exported symbols
+   */
+  static async link(
+    objects: string[],
+    origin?: number,
+  ): Promise<EmulatorResult<LinkedProgram>> {
+    try {
+      const result = await invoke<LinkedProgram>("emulator_link", {
+        objects,
+        origin,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Assemble code, load into RAM, and start execution
+   * @param includeConstants List EQU and RS constants with the labels
+   * @returns Where the program went and its labels' addresses there
+   */
+  static async assembleAndLoad(
+    code: string,
+    includeConstants?: boolean,
+    instance?: number,
+  ): Promise<EmulatorResult<LoadedProgram>> {
+    try {
+      const result = await invoke<LoadedProgram>("emulator_assemble_and_load", {
+        code,
+        includeConstants,
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Assemble code, load it at `loadAddr` and start execution at `entry`
+   *
+   * `loadAddr` defaults to the program's ORG address (or $E00100) and
+   * `entry` to the load address.
+   * @param includeConstants List EQU and RS constants with the labels
+   * @returns Where the program went and its labels' addresses there
+   */
+  static async loadAndRun(
+    code: string,
+    loadAddr?: number,
+    entry?: number,
+    includeConstants?: boolean,
+    instance?: number,
+  ): Promise<EmulatorResult<LoadedProgram>> {
+    try {
+      const result = await invoke<LoadedProgram>("emulator_load_and_run", {
+        code,
+        loadAddr,
+        entry,
+        includeConstants,
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Add a watch expression; its value is reported in every status and step
+   * result
+   * @param name Name to show the value under (empty for the expression)
+   * @param expression Expression in the breakpoint condition syntax, e.g.
+   *   `d0*2` or `word(a0)`
+   * @returns The watch id
+   */
+  static async addWatch(
+    name: string,
+    expression: string,
+    instance?: number,
+  ): Promise<EmulatorResult<number>> {
+    try {
+      const id = await invoke<number>("emulator_watch_add", {
+        name,
+        expression,
+        instance,
+      });
+      return { status: "success", data: id };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Remove a watch expression
+   */
+  static async removeWatch(
+    id: number,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_watch_remove", { id, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * List the watch expressions
+   */
+  static async listWatches(
+    instance?: number,
+  ): Promise<EmulatorResult<WatchExpression[]>> {
+    try {
+      const list = await invoke<WatchExpression[]>("emulator_watch_list", {
+        instance,
+      });
+      return { status: "success", data: list };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Evaluate an expression against the current machine state
+   * @param expression Assembler expression with registers, labels and
+   *   byte()/word()/long() memory reads (e.g. "long(a7)")
+   * @returns The value; a name that isn't a register or label fails with
+   *   an "undefinedSymbol" error listing similar symbols
+   */
+  static async evaluate(
+    expression: string,
+    instance?: number,
+  ): Promise<EmulatorResult<Evaluation>> {
+    try {
+      const evaluation = await invoke<Evaluation>("emulator_eval", {
+        expression,
+        instance,
+      });
+      return { status: "success", data: evaluation };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * List the symbol table (the program's labels and map file symbols)
+   * @param filter Keep only names containing this text (ignoring case)
+   */
+  static async getSymbols(
+    filter?: string,
+    instance?: number,
+  ): Promise<EmulatorResult<SymbolInfo[]>> {
+    try {
+      const data = await invoke<SymbolInfo[]>("emulator_get_symbols", {
+        filter,
+        instance,
+      });
+      return { status: "success", data };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Find the closest symbol at or below an address
+   * @returns The symbol and offset, or null below every symbol
+   */
+  static async symbolAt(
+    address: number,
+    instance?: number,
+  ): Promise<EmulatorResult<SymbolMatch | null>> {
+    try {
+      const data = await invoke<SymbolMatch | null>("emulator_symbol_at", {
+        address,
+        instance,
+      });
+      return { status: "success", data };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Merge a map file's symbols into the symbol table; each line is
+   * `name value`, `name = value` or `name EQU value`
+   * @returns How many symbols were added
+   */
+  static async loadSymbolMap(
+    path: string,
+    instance?: number,
+  ): Promise<EmulatorResult<number>> {
+    try {
+      const data = await invoke<number>("emulator_load_symbol_map", {
+        path,
+        instance,
+      });
+      return { status: "success", data };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Assemble code and write its symbol map to a file
+   * @param format "listing" (every symbol with its value and kind, the
+   *   default) or "plain" (labels as `name $value`, which loadSymbolMap
+   *   reads)
+   */
+  static async saveSymbolMap(
+    code: string,
+    path: string,
+    format?: SymbolMapFormat,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_save_symbol_map", { code, path, format });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Read UART output (drain TX buffer)
+   */
+  static async readUart(instance?: number): Promise<EmulatorResult<number[]>> {
+    try {
+      const result = await invoke<number[]>("emulator_read_uart", { instance });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Write a character to UART RX (simulate keyboard input)
+   * @returns false if the input queue was full and the character dropped
+   */
+  static async writeUart(
+    byte: number,
+    instance?: number,
+  ): Promise<EmulatorResult<boolean>> {
+    try {
+      const result = await invoke<boolean>("emulator_write_uart", {
+        byte,
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Queue bytes for UART RX (simulate a terminal paste)
+   *
+   * Line endings are translated as `setUartLineEnding` chose.
+   * @param data Bytes to send
+   * @returns How many leading bytes were accepted; resend the rest once
+   * `getUartQueue` shows room
+   */
+  static async writeUartBytes(
+    data: number[],
+    instance?: number,
+  ): Promise<EmulatorResult<number>> {
+    try {
+      const result = await invoke<number>("emulator_write_uart_bytes", {
+        data,
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Queue text for UART RX as UTF-8 (simulate a terminal paste)
+   * @param text Text to send
+   * @returns How many leading bytes of the UTF-8 encoding were accepted;
+   * resend the rest once `getUartQueue` shows room
+   */
+  static async writeUartString(
+    text: string,
+    instance?: number,
+  ): Promise<EmulatorResult<number>> {
+    try {
+      const result = await invoke<number>("emulator_write_uart_string", {
+        text,
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Set how line endings in pasted text reach the UART
+   * @param ending Line ending pastes are translated to
+   */
+  static async setUartLineEnding(
+    ending: UartLineEnding,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_set_uart_line_ending", { ending, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Get the UART input queue occupancy (to throttle pastes)
+   */
+  static async getUartQueue(
+    instance?: number,
+  ): Promise<EmulatorResult<UartQueueStatus>> {
+    try {
+      const result = await invoke<UartQueueStatus>("emulator_get_uart_queue", {
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Get the state of LED 0 (the original status LED)
+   */
+  static async getLed(instance?: number): Promise<EmulatorResult<boolean>> {
+    try {
+      const result = await invoke<boolean>("emulator_get_led", { instance });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Get the LED bar state (bit N = LED N)
+   */
+  static async getLeds(instance?: number): Promise<EmulatorResult<number>> {
+    try {
+      const result = await invoke<number>("emulator_get_leds", { instance });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Get the state of every board panel peripheral in one call
+   */
+  static async getPeripherals(
+    instance?: number,
+  ): Promise<EmulatorResult<PeripheralsState>> {
+    try {
+      const result = await invoke<PeripheralsState>(
+        "emulator_get_peripherals",
+        { instance },
+      );
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Get one peripheral's registers and internal state, read without the
+   * side effects a guest read has (FIFOs aren't popped)
+   * @param name One of `leds`, `gpio`, `uart`, `cf` or `timer`
+   */
+  static async dumpPeripheral(
+    name: string,
+    instance?: number,
+  ): Promise<EmulatorResult<Record<string, unknown>>> {
+    try {
+      const data = await invoke<Record<string, unknown>>(
+        "emulator_dump_peripheral",
+        { name, instance },
+      );
+      return { status: "success", data };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Subscribe to LED bar changes
+   * @param callback Called with the new LED bar value after each change
+   * @returns Function that removes the listener
+   */
+  static async onLedChange(
+    callback: (value: number) => void,
+    instance = PRIMARY_INSTANCE,
+  ): Promise<UnlistenFn> {
+    return listenInstance("led-change", instance, callback);
+  }
+
+  /**
+   * Eject the CompactFlash card (flushes cached writes to the image file)
+   */
+  static async cfEject(instance?: number): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_cf_eject", { instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Insert a CompactFlash card backed by an image file
+   * @param path Path to a raw disk image
+   */
+  static async cfInsert(
+    path: string,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_cf_insert", { path, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Enable or disable the CompactFlash copy-on-write overlay
+   * @param enabled Whether guest writes go to the overlay
+   */
+  static async cfSetOverlay(
+    enabled: boolean,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_cf_set_overlay", { enabled, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Discard all CompactFlash writes held in the overlay
+   */
+  static async cfDiscardOverlay(
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_cf_discard_overlay", { instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Commit the CompactFlash overlay into the base image
+   */
+  static async cfCommitOverlay(
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_cf_commit_overlay", { instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Export the CompactFlash overlay as a delta file
+   * @param path Destination file path
+   */
+  static async cfExportOverlay(
+    path: string,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_cf_export_overlay", { path, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Create a blank CompactFlash image
+   * @param path Destination file path
+   * @param sizeMb Image size in megabytes
+   * @param format Whether to add a FAT16 partition
+   */
+  static async createCfImage(
+    path: string,
+    sizeMb: number,
+    format: boolean,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_create_cf_image", { path, sizeMb, format });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Get the GPIO port state
+   */
+  static async gpioRead(instance?: number): Promise<EmulatorResult<GpioState>> {
+    try {
+      const result = await invoke<GpioState>("emulator_gpio_read", {
+        instance,
+      });
+      return { status: "success", data: result };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Drive a GPIO input pin from the host
+   * @param pin Pin number (0-7)
+   * @param level Input level
+   */
+  static async gpioWriteInput(
+    pin: number,
+    level: boolean,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_gpio_write_input", { pin, level, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Subscribe to GPIO output changes
+   * @param callback Called with the port state after each output change
+   * @returns Function that removes the listener
+   */
+  static async onGpioOutput(
+    callback: (state: GpioState) => void,
+    instance = PRIMARY_INSTANCE,
+  ): Promise<UnlistenFn> {
+    return listenInstance("gpio-output", instance, callback);
+  }
+
+  /**
+   * Raise a non-maskable interrupt (level 7), like a monitor's break button
+   */
+  static async nmi(instance?: number): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_nmi", { instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Request an interrupt through the interrupt controller, as a peripheral
+   * would, so the CPU's mask and source priorities apply
+   * @param level Interrupt level (1-7)
+   * @param vector Vector to supply; autovectors when omitted
+   */
+  static async interrupt(
+    level: number,
+    vector?: number,
+    instance?: number,
+  ): Promise<EmulatorResult<InterruptOutcome>> {
+    try {
+      const data = await invoke<InterruptOutcome>("emulator_interrupt", {
+        level,
+        vector,
+        instance,
+      });
+      return { status: "success", data };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Make Ctrl-C written to the UART raise an NMI instead
+   * @param enabled Whether Ctrl-C raises an NMI
+   */
+  static async setCtrlCNmi(
+    enabled: boolean,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_set_ctrl_c_nmi", { enabled, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Set the DIP switch positions; the ROM reads them at boot
+   * @param value Bit n is switch n + 1 (1 = ON)
+   */
+  static async setDipSwitch(
+    value: number,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_set_dipswitch", { value, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Enable or disable the terminal bell (BEL sent to the UART beeps)
+   * @param enabled Whether BEL sounds the buzzer
+   */
+  static async setUartBell(
+    enabled: boolean,
+    instance?: number,
+  ): Promise<EmulatorResult<null>> {
+    try {
+      await invoke("emulator_set_uart_bell", { enabled, instance });
+      return { status: "success", data: null };
+    } catch (error) {
+      return failure(error);
+    }
+  }
+
+  /**
+   * Subscribe to buzzer tones
+   * @param callback Called with each tone once it has finished (or, for the
+   * bell, as soon as it starts)
+   * @returns Function that removes the listener
+   */
+  static async onBuzzer(
+    callback: (tone: ToneEvent) => void,
+    instance = PRIMARY_INSTANCE,
+  ): Promise<UnlistenFn> {
+    return listenInstance("buzzer", instance, callback);
+  }
+
   /**
    * Format a memory view for display
    */
@@ -14,6 +14,7 @@ vi.mock("./emulator-api", () => ({
     getStatus: vi.fn(),
     getLed: vi.fn(),
     readUart: vi.fn(),
+    onUartOutput: vi.fn(),
     writeUart: vi.fn(),
     assembleAndLoad: vi.fn(),
   },
@@ -21,13 +22,25 @@ vi.mock("./emulator-api", () => ({
 
 // Helper to create a mock CpuState with all required fields
 function mockCpuState(overrides: Partial<{ pc: number; sr: number }> = {}) {
+  const sr = overrides.sr ?? 0;
   return {
     pc: overrides.pc ?? 0,
-    sr: overrides.sr ?? 0,
+    sr,
     d: [0, 0, 0, 0, 0, 0, 0, 0],
     a: [0, 0, 0, 0, 0, 0, 0, 0],
+    a7: 0,
     usp: 0,
     ssp: 0,
+    flags: {
+      c: (sr & 0x01) !== 0,
+      v: (sr & 0x02) !== 0,
+      z: (sr & 0x04) !== 0,
+      n: (sr & 0x08) !== 0,
+      x: (sr & 0x10) !== 0,
+    },
+    trace: (sr & 0x8000) !== 0,
+    supervisor: (sr & 0x2000) !== 0,
+    interruptMask: (sr >> 8) & 7,
   };
 }
 
@@ -81,7 +94,7 @@ describe("useEmulatorStore", () => {
     it("initializes successfully", async () => {
       vi.mocked(EmulatorAPI.init).mockResolvedValue({
         status: "success",
-        data: "initialized",
+        data: {},
       });
       vi.mocked(EmulatorAPI.getRegisters).mockResolvedValue({
         status: "success",
@@ -106,6 +119,38 @@ describe("useEmulatorStore", () => {
       expect(result.current.error).toBeNull();
     });
 
+    it("appends UART output streamed during runs", async () => {
+      vi.mocked(EmulatorAPI.init).mockResolvedValue({
+        status: "success",
+        data: {},
+      });
+      vi.mocked(EmulatorAPI.getRegisters).mockResolvedValue({
+        status: "success",
+        data: mockCpuState(),
+      });
+      vi.mocked(EmulatorAPI.getStatus).mockResolvedValue({
+        status: "success",
+        data: mockStatus(),
+      });
+      vi.mocked(EmulatorAPI.getLed).mockResolvedValue({
+        status: "success",
+        data: false,
+      });
+
+      const { result } = renderHook(() => useEmulatorStore());
+
+      await act(async () => {
+        await result.current.init();
+      });
+      const [onOutput] = vi.mocked(EmulatorAPI.onUartOutput).mock.calls[0];
+      act(() => {
+        onOutput([79, 75]);
+        onOutput([33]);
+      });
+
+      expect(result.current.uartOutput).toBe("OK!");
+    });
+
     it("handles initialization error", async () => {
       vi.mocked(EmulatorAPI.init).mockResolvedValue({
         status: "error",
@@ -291,7 +336,12 @@ describe("useEmulatorStore", () => {
       });
       vi.mocked(EmulatorAPI.assembleAndLoad).mockResolvedValue({
         status: "success",
-        data: "loaded",
+        data: {
+          loadAddress: 0xe00100,
+          size: 2,
+          entry: 0xe00100,
+          symbols: {},
+        },
       });
       vi.mocked(EmulatorAPI.run).mockResolvedValue({
         status: "success",
@@ -6,9 +6,20 @@
  */
 
 import { create } from "zustand";
+import type { UnlistenFn } from "@tauri-apps/api/event";
 import type { CpuState, EmulatorStatus } from "./emulator-types";
 import { EmulatorAPI } from "./emulator-api";
 
+/** Removes the listener for UART output streamed during runs */
+let stopUartListener: UnlistenFn | undefined;
+
+/**
+ * Decode UART bytes as text
+ */
+function uartText(bytes: number[]): string {
+  return bytes.map((b) => String.fromCharCode(b)).join("");
+}
+
 /**
  * Emulator store state
  */
@@ -93,6 +104,11 @@ export const useEmulatorStore = create<EmulatorStore>((set, get) => ({
       const result = await EmulatorAPI.init();
       if (result.status === "success") {
         set({ initialized: true });
+        // Append the output runs stream
+        stopUartListener?.();
+        stopUartListener = await EmulatorAPI.onUartOutput((bytes) => {
+          set((state) => ({ uartOutput: state.uartOutput + uartText(bytes) }));
+        });
         // Load initial CPU state
         await get().refresh();
       } else {
@@ -201,7 +217,7 @@ export const useEmulatorStore = create<EmulatorStore>((set, get) => ({
     try {
       const result = await EmulatorAPI.readUart();
       if (result.status === "success" && result.data.length > 0) {
-        const text = result.data.map((b) => String.fromCharCode(b)).join("");
+        const text = uartText(result.data);
         set((state) => ({ uartOutput: state.uartOutput + text }));
       }
     } catch {
@@ -13,6 +13,8 @@ export interface CpuState {
   d: number[];
   /** Address registers A0-A6 (A7 is SP) */
   a: number[];
+  /** Active stack pointer (USP in user mode, SSP in supervisor mode) */
+  a7: number;
   /** Program Counter */
   pc: number;
   /** Status Register */
@@ -21,6 +23,30 @@ export interface CpuState {
   usp: number;
   /** Supervisor Stack Pointer */
   ssp: number;
+  /** Condition code flags, decoded from SR */
+  flags: CcrFlags;
+  /** Trace bit */
+  trace: boolean;
+  /** Supervisor bit */
+  supervisor: boolean;
+  /** Interrupt mask (0-7) */
+  interruptMask: number;
+}
+
+/**
+ * Condition code flags
+ */
+export interface CcrFlags {
+  /** Carry */
+  c: boolean;
+  /** Overflow */
+  v: boolean;
+  /** Zero */
+  z: boolean;
+  /** Negative */
+  n: boolean;
+  /** Extend */
+  x: boolean;
 }
 
 /**
@@ -33,6 +59,442 @@ export interface EmulatorStatus {
   cycles: number;
   /** Cycles executed in last run */
   executed: number;
+  /** Program counter */
+  pc: number;
+  /** Condition code flags */
+  flags: CcrFlags;
+  /** Whether the CPU is in supervisor mode */
+  supervisor: boolean;
+  /** Instructions executed since the last reset */
+  instructions: number;
+  /** Why the last run or batch of steps stopped */
+  stop: StopReason | null;
+  /** Instruction at the PC (null outside ROM and RAM) */
+  nextInstruction: string | null;
+  /** Details of the run that produced this status (only from `run`) */
+  run?: RunResult | null;
+  /** Whether a background run is executing */
+  running: boolean;
+  /** Active machine configuration (only from `getStatus`) */
+  config?: SbcConfig | null;
+  /** Pacing of the call (only from `runThrottled`) */
+  throttle?: ThrottleStats | null;
+  /** Values of the watch expressions */
+  watches: WatchValue[];
+}
+
+/**
+ * How a throttled run call went
+ */
+export interface ThrottleStats {
+  /** Clock frequency being emulated, in Hz */
+  targetHz: number;
+  /** Cycles the call was allowed to run */
+  budget: number;
+  /** Measured emulated clock frequency, in Hz */
+  achievedHz: number;
+  /** Achieved frequency as a fraction of the target (1 = full speed) */
+  speedRatio: number;
+}
+
+/**
+ * How much of the machine `emulator_reset` reinitializes
+ *
+ * - `cold`: power cycle (RAM cleared, CF card ejected, breakpoints dropped)
+ * - `warm`: CPU and peripherals reset, RAM and attachments kept
+ * - `cpu_only`: SSP and PC reloaded from the reset vectors
+ */
+export type ResetMode = "cold" | "warm" | "cpu_only";
+
+/**
+ * How line endings in pasted text reach the UART
+ *
+ * CR, LF and CR LF in the paste each count as one line ending.
+ */
+export type UartLineEnding = "unchanged" | "cr" | "lf" | "crlf";
+
+/**
+ * Machine configuration for `init`; missing fields take their defaults
+ */
+export interface SbcConfig {
+  /** CPU clock in Hz (whole MHz from 1 to 50; default 12 MHz) */
+  clockHz?: number;
+  /** Populated RAM in bytes (power of two from 64 KB to 1 MB; default 1 MB) */
+  ramSize?: number;
+  /** ROM image to boot instead of the embedded one */
+  romPath?: string | null;
+  /** Reset vectors used instead of the ROM's vector table */
+  resetVectors?: { ssp: number; pc: number } | null;
+  /** UART wiring */
+  uart?: {
+    /** BEL sent to the UART sounds the buzzer's bell */
+    bell?: boolean;
+    /** CF card detect is wired to the UART's CTS input */
+    cardDetect?: boolean;
+    /** Ctrl-C raises an NMI instead of reaching the UART */
+    ctrlCNmi?: boolean;
+    /** Host input queue size in bytes (default 4096) */
+    inputQueue?: number;
+    /** Queued input only reaches the UART while the guest asserts RTS */
+    flowControl?: boolean;
+    /** How line endings in pasted text are sent (default "unchanged") */
+    lineEnding?: UartLineEnding;
+  };
+  /** Expansion peripheral enables (all enabled by default) */
+  peripherals?: { timer?: boolean; rtc?: boolean; gpio?: boolean };
+  /** CompactFlash card */
+  cf?: {
+    /** Disk image inserted at power-on */
+    image?: string | null;
+    /** Writes go to a copy-on-write overlay */
+    overlay?: boolean;
+    /** Priority of the CF interrupt (0 disconnects it) */
+    irqLevel?: number;
+  };
+  /** DIP switch positions (bit n = switch n + 1, 1 = ON) */
+  dipSwitch?: number;
+  /** CLR reads its destination before clearing it */
+  clrRead?: boolean;
+  /** Emulate the 68000 prefetch */
+  prefetch?: boolean;
+  /** Value unmapped reads return */
+  openBus?: "high" | "low";
+}
+
+/**
+ * Why a run stopped
+ */
+export type StopReason =
+  | { kind: "budgetExhausted" }
+  | { kind: "stepsCompleted" }
+  | { kind: "paused" }
+  | { kind: "halted" }
+  | { kind: "breakpoint"; id: number; address: number }
+  | {
+      kind: "watchpoint";
+      id: number;
+      address: number;
+      access: "read" | "write";
+    }
+  | { kind: "uninitializedVector"; vector: number; pc: number }
+  | { kind: "exit"; code: number };
+
+/**
+ * Outcome of a run
+ */
+export interface RunResult {
+  /** Instructions executed */
+  instructions: number;
+  /** Cycles executed */
+  cycles: number;
+  /** Why execution stopped */
+  stop: StopReason;
+  /** Program counter after the run */
+  pc: number;
+}
+
+/**
+ * An event payload tagged with the emulator instance that sent it
+ */
+export interface InstanceEvent<T> {
+  /** Instance id (0 is the primary instance) */
+  instance: number;
+  /** The event itself */
+  payload: T;
+}
+
+/**
+ * Payload of the `run-stopped` event sent when a background run stops
+ */
+export interface RunStopped {
+  /** Instructions executed since the run started or resumed */
+  instructions: number;
+  /** Cycles executed since the run started or resumed */
+  cycles: number;
+  /** Why execution stopped (`paused` when asked to) */
+  stop: StopReason;
+  /** Program counter after the run */
+  pc: number;
+}
+
+/**
+ * Result of a batch of steps, a step over or a step out
+ */
+export interface StepNResult {
+  /** Register state after the batch */
+  registers: CpuState;
+  /** Instructions and cycles executed, and why the batch stopped */
+  run: RunResult;
+  /** PCs of the executed instructions (only when traced) */
+  pcs: number[];
+  /** UART output produced by the batch */
+  output: number[];
+  /** Values of the watch expressions after the batch */
+  watches: WatchValue[];
+}
+
+/**
+ * A watch expression, from `emulator_watch_list`
+ */
+export interface WatchExpression {
+  /** Watch id */
+  id: number;
+  /** Name to show the value under */
+  name: string;
+  /** Expression text */
+  expression: string;
+}
+
+/**
+ * The current value of a watch expression
+ */
+export interface WatchValue {
+  /** Watch id */
+  id: number;
+  /** Name to show the value under */
+  name: string;
+  /** Value of the expression, or null if it couldn't be evaluated */
+  value: number | null;
+  /** Why it couldn't be evaluated */
+  error: string | null;
+}
+
+/**
+ * What an evaluated value stands for: an address when the expression names
+ * a label, else a plain number
+ */
+export type ValueKind = "address" | "number";
+
+/**
+ * An expression's value, from `emulator_eval`
+ */
+export interface Evaluation {
+  /** The value */
+  value: number;
+  /** The value in hex ("$FF"), 32-bit two's complement when it fits */
+  hex: string;
+  /** What the value stands for */
+  kind: ValueKind;
+}
+
+/**
+ * Snapshot file metadata
+ */
+export interface SnapshotInfo {
+  /** Snapshot format version */
+  version: number;
+  /** Creation time in seconds since the Unix epoch */
+  created: number;
+  /** Program counter */
+  pc: number;
+  /** CPU cycles since the last reset */
+  cycles: number;
+  /** Configuration the machine was built with */
+  config: SbcConfig;
+}
+
+/**
+ * Machine diagnostics report for bug reports
+ */
+export interface Diagnostics {
+  /** Emulator version */
+  version: string;
+  /** CPU model and options */
+  cpu: {
+    model: string;
+    clockHz: number;
+    clrRead: boolean;
+    prefetch: boolean;
+    halted: boolean;
+  };
+  /** Populated RAM in bytes */
+  ramSize: number;
+  /** ROM image */
+  rom: {
+    /** The embedded ROM is loaded */
+    embedded: boolean;
+    /** Image file the configuration names */
+    path: string | null;
+    /** ROM size in bytes */
+    size: number;
+    /** CRC-32 of the whole ROM */
+    crc32: number;
+  };
+  /** CompactFlash card inserted */
+  cfInserted: boolean;
+  /** Memory map, in address order (`end` is inclusive) */
+  memoryMap: { name: string; start: number; end: number }[];
+  /** Expansion bus peripherals, in slot order */
+  peripherals: { name: string; base: number; enabled: boolean }[];
+  /** CPU cycles since the last reset */
+  cycles: number;
+  /** Master cycles since power-on */
+  masterCycles: number;
+  /** Latched faults */
+  faults: {
+    /** Last address that selected more than one device */
+    lastBusFault: number | null;
+    /** The last reset was caused by the watchdog */
+    watchdogReset: boolean;
+  };
+  /** Active configuration */
+  config: SbcConfig;
+}
+
+/**
+ * Outcome of a bulk memory write
+ */
+export interface MemoryWriteResult {
+  /** Bytes written before the first failure */
+  written: number;
+  /** First address that couldn't be written (null if all were) */
+  failedAt: number | null;
+}
+
+/**
+ * UART host input queue occupancy
+ */
+export interface UartQueueStatus {
+  /** Bytes waiting for room in the UART's receive FIFO */
+  queued: number;
+  /** Queue size in bytes */
+  capacity: number;
+}
+
+/**
+ * GPIO port state (also the payload of "gpio-output" events)
+ */
+export interface GpioState {
+  /** Direction register (1 = output) */
+  direction: number;
+  /** Levels of the output pins (input pins read as 0) */
+  output: number;
+  /** Host-driven input levels */
+  input: number;
+  /** Pin levels as the guest reads them */
+  pins: number;
+}
+
+/**
+ * GPIO section of the board panel
+ */
+export interface GpioPanelState extends GpioState {
+  /** Whether an edge flag is raising the port's interrupt */
+  interruptPending: boolean;
+}
+
+/**
+ * Everything the board panel shows, from `emulator_get_peripherals`
+ *
+ * Devices the configuration leaves off the bus are `null`.
+ */
+export interface PeripheralsState {
+  leds: {
+    /** LED latch (bit N = LED N) */
+    value: number;
+  };
+  gpio: GpioPanelState | null;
+  uart: {
+    /** Bytes in the RX FIFO */
+    rxFifo: number;
+    /** Host input waiting for room in the RX FIFO */
+    inputQueued: number;
+    /** Whether bytes are waiting in the TX FIFO */
+    txBusy: boolean;
+    /** Baud rate divisor */
+    divisor: number;
+    /** Whether the guest asserts RTS */
+    rts: boolean;
+    /** Whether the UART is raising its interrupt */
+    interruptPending: boolean;
+  };
+  cf: {
+    /** Whether a card is inserted */
+    inserted: boolean;
+    /** Image file the card is streamed from */
+    image: string | null;
+    /** FAT16 volume label of the inserted card */
+    label: string | null;
+    /** Whether a command is in progress */
+    busy: boolean;
+    /** Error register from the last command */
+    error: number;
+  };
+  timer: {
+    /** Whether the timer is counting */
+    running: boolean;
+    /** Period in ticks (0 = 65536) */
+    period: number;
+    /** Ticks counted towards the current period */
+    elapsedTicks: number;
+    /** Whether expiry raises an interrupt */
+    interruptEnabled: boolean;
+    /** Whether the period elapsed since the flag was last cleared */
+    expired: boolean;
+  } | null;
+}
+
+/**
+ * A tone played by the buzzer (payload of "buzzer" events)
+ */
+export interface ToneEvent {
+  /** Frequency in Hz */
+  frequency: number;
+  /** Duration in milliseconds */
+  durationMs: number;
+  /** Emulated cycle count when the tone started */
+  startCycle: number;
+  /** Emulated cycle count when the tone ended */
+  endCycle: number;
+}
+
+/**
+ * Why a command failed, as the backend reports it
+ *
+ * `code` says what kind of failure it was and `message` is readable text
+ * for it; the other fields depend on the code.
+ */
+export type EmulatorError =
+  | { code: "notInitialized"; instance: number; message: string }
+  | { code: "unmapped"; address: number; message: string }
+  | { code: "writeProtected"; address: number; message: string }
+  | {
+      code: "assemblyFailed";
+      diagnostics: AssemblerDiagnostic[];
+      message: string;
+    }
+  | {
+      code: "io";
+      /** File the error is about, when known */
+      path: string | null;
+      /** I/O error kind in camelCase (e.g. "notFound") */
+      kind: string;
+      message: string;
+    }
+  | {
+      code: "invalidArgument";
+      /** Argument or config field at fault (e.g. "pin" or "ramSize") */
+      field: string;
+      message: string;
+    }
+  | {
+      code: "undefinedSymbol";
+      /** Name that is neither a register nor a known label */
+      name: string;
+      /** Known symbols with similar names, closest first */
+      suggestions: string[];
+      message: string;
+    }
+  | { code: "failed"; message: string };
+
+/**
+ * A failed emulator operation; `details` holds the backend's structured
+ * error when it sent one
+ */
+export interface EmulatorFailure {
+  status: "error";
+  error: string;
+  details?: EmulatorError;
 }
 
 /**
@@ -40,7 +502,7 @@ export interface EmulatorStatus {
  */
 export type EmulatorResult<T> =
   | { status: "success"; data: T }
-  | { status: "error"; error: string };
+  | EmulatorFailure;
 
 /**
  * Emulator command responses
@@ -86,18 +548,384 @@ export interface MemoryViewOptions {
   format: "hex" | "decimal" | "both";
 }
 
+/**
+ * Execution breakpoint
+ */
+export interface Breakpoint {
+  /** Breakpoint id */
+  id: number;
+  /** Address of the instruction to stop at */
+  address: number;
+  /** Condition that must hold for the breakpoint to hit */
+  condition: string | null;
+  /** Number of hits to let pass before stopping */
+  skipCount: number;
+  /** Whether the breakpoint is armed */
+  enabled: boolean;
+  /** Number of times execution reached it with its condition holding */
+  hits: number;
+}
+
+/**
+ * Where a symbol came from: the loaded program's labels or a map file
+ */
+export type SymbolSource = "program" | "mapFile";
+
+/**
+ * Symbol table entry, from `emulator_get_symbols`
+ */
+export interface SymbolInfo {
+  /** Label name */
+  name: string;
+  /** Address the label names */
+  address: number;
+  /** Where the symbol came from */
+  source: SymbolSource;
+}
+
+/**
+ * Format of a symbol map written by `emulator_save_symbol_map`
+ */
+export type SymbolMapFormat = "listing" | "plain";
+
+/**
+ * Closest symbol at or below an address, from `emulator_symbol_at`
+ */
+export interface SymbolMatch extends SymbolInfo {
+  /** How far past the symbol the address is */
+  offset: number;
+  /** The address as `label` or `label+$offset` */
+  label: string;
+}
+
 /**
  * Disassembly result
  */
 export interface DisassemblyLine {
   /** Address of the instruction */
+  addr: number;
+  /** Instruction bytes in hex (empty for the "(unmapped)" terminator) */
+  bytesHex: string;
+  /** Mnemonic with size suffix, or "(unmapped)" past mapped memory */
+  mnemonic: string;
+  /** Comma-separated operands */
+  operands: string;
+  /** Whether the instruction is at the program counter */
+  isCurrentPc: boolean;
+  /** Label at this address in the loaded program */
+  symbol: string | null;
+}
+
+/**
+ * What one instruction step did, from `emulator_step_ex`
+ */
+export interface StepResult {
+  /** Program counter before the step */
+  pcBefore: number;
+  /** Program counter after the step */
+  pcAfter: number;
+  /** First word of the executed instruction */
+  opcode: number;
+  /** Cycles the step took */
+  cycles: number;
+  /** Vector of the exception or interrupt the step took, if any */
+  exceptionVector: number | null;
+  /** Whether the CPU is halted after the step */
+  halted: boolean;
+  /** UART output the instruction produced */
+  uartOutput: number[];
+  /** Values of the watch expressions after the step */
+  watches: WatchValue[];
+}
+
+/**
+ * What became of an interrupt requested with `emulator_interrupt`
+ * - `accepted`: the CPU took it right away
+ * - `deferred`: the CPU's mask is at or above the level; it waits latched
+ * - `dropped`: an earlier request is still waiting, so this one was lost
+ */
+export type InterruptOutcome = "accepted" | "deferred" | "dropped";
+
+/**
+ * A longword on the stack, from `emulator_get_stack`
+ */
+export interface StackEntry {
+  /** Address of the longword */
+  address: number;
+  /** Value of the longword */
+  value: number;
+  /** Whether the value points just after a JSR, BSR or TRAP */
+  returnAddress: boolean;
+  /** Closest label at or below the value ("label" or "label+$offset") */
+  symbol: string | null;
+  /** Whether the longword lies in the current LINK frame */
+  inFrame: boolean;
+}
+
+/**
+ * A routine on the call stack, from `emulator_get_callstack`
+ */
+export interface CallFrame {
+  /** Where execution continues: the PC for frame 0, else the return address */
+  returnAddress: number;
+  /** The A6 the routine's LINK set up, if it has one */
+  framePointer: number | null;
+  /** Closest label at or below the return address */
+  symbol: string | null;
+  /** How far past the label the return address is */
+  offset: number | null;
+  /**
+   * Whether the frame was found by following LINK frames rather than
+   * guessed by scanning the stack (for frame 0: whether A6 heads a frame)
+   */
+  isLinkFrame: boolean;
+}
+
+/**
+ * An executed instruction, from `emulator_get_trace`
+ */
+export interface TraceEntry {
+  /** Position in execution order since tracing started */
+  index: number;
+  /** Address of the instruction */
+  pc: number;
+  /** First word of the instruction */
+  opcode: number;
+  /** Status register before the instruction executed */
+  sr: number;
+  /** CPU cycle count before the instruction executed */
+  cycles: number;
+  /** Disassembly ("mnemonic operands"), when asked for */
+  mnemonic?: string;
+}
+
+/**
+ * An address in a profile report
+ */
+export interface ProfileHotspot {
+  /** Instruction address */
   address: number;
-  /** Opcode bytes in hex */
-  bytes: string;
-  /** Instruction mnemonic and operands */
+  /** Closest label at or below the address ("label" or "label+$offset") */
+  symbol: string | null;
+  /** Instruction at the address ("mnemonic operands") */
   instruction: string;
+  /** Cycles spent executing it */
+  cycles: number;
+  /** Times it executed */
+  count: number;
+  /** Share of the profiled cycles, in percent */
+  percent: number;
+}
+
+/**
+ * Cycles spent on one mnemonic in a profile report
+ */
+export interface ProfileMnemonicTotal {
+  /** Mnemonic with size suffix (e.g. "move.l") */
+  mnemonic: string;
+  /** Cycles spent on instructions with this mnemonic */
+  cycles: number;
+  /** Instructions executed with this mnemonic */
+  count: number;
+  /** Share of the profiled cycles, in percent */
+  percent: number;
 }
 
+/**
+ * Where the profiled cycles went, from `emulator_profile_report`
+ */
+export interface ProfileReport {
+  /** Whether samples are being collected */
+  running: boolean;
+  /** Cycles executed while profiling */
+  totalCycles: number;
+  /** Instructions executed while profiling */
+  instructions: number;
+  /** Hottest addresses, most cycles first */
+  hotspots: ProfileHotspot[];
+  /** Cycles by mnemonic, most cycles first */
+  mnemonics: ProfileMnemonicTotal[];
+}
+
+/**
+ * A run of bytes written by `emulator_load_hex`
+ */
+export interface LoadedSegment {
+  /** First address written */
+  address: number;
+  /** Bytes written */
+  length: number;
+}
+
+/**
+ * Result of loading an S-record or Intel HEX file
+ */
+export interface HexLoadResult {
+  /** The regions written, in address order */
+  segments: LoadedSegment[];
+  /** Start address from the file, if it has one */
+  entry: number | null;
+}
+
+/**
+ * An assembler error or warning
+ */
+export interface AssemblerDiagnostic {
+  /** Source file ("<editor>" for editor code) */
+  file: string;
+  /** Line number (1-based) */
+  line: number;
+  /** Column where the span starts (1-based, a tab counts as one) */
+  column: number;
+  /** Length of the span in columns */
+  length: number;
+  /** Whether assembly failed because of it */
+  severity: "error" | "warning";
+  /** Description without the position */
+  message: string;
+}
+
+/**
+ * Code assembled by `emulator_assemble_checked`
+ */
+export interface CheckedAssembly {
+  /** Machine code */
+  binary: number[];
+  /** Warnings assembling it produced */
+  warnings: AssemblerDiagnostic[];
+}
+
+/**
+ * How long an assembly's stages took, in microseconds, and what it reused
+ * from the one before
+ */
+export interface AssemblyTimings {
+  preprocessUs: number;
+  lexUs: number;
+  /** Both passes, including each time pass 1 ran again */
+  passesUs: number;
+  totalUs: number;
+  /**
+   * Times pass 1 ran: none when the lines that changed only hold
+   * instructions that kept their size
+   */
+  sizingPasses: number;
+  /** Includes whose expansion was reused */
+  cachedIncludes: number;
+  /**
+   * The source's expansion was patched where it changed rather than
+   * preprocessed again
+   */
+  patchedSource: boolean;
+  /** Lines whose tokens were reused */
+  cachedLines: number;
+  /**
+   * Pass 1 was skipped, or started from the last assembly's sizes and
+   * addresses, and they held
+   */
+  reusedSizes: boolean;
+}
+
+/**
+ * Code assembled by `emulator_assemble_incremental`
+ */
+export interface IncrementalAssembly extends CheckedAssembly {
+  /** Time each stage took and what was reused from the last call */
+  timings: AssemblyTimings;
+}
+
+/**
+ * Result of incremental assembly; a failure lists every error found
+ */
+export type IncrementalAssemblyResult =
+  | { status: "success"; data: IncrementalAssembly }
+  | (EmulatorFailure & { diagnostics: AssemblerDiagnostic[] });
+
+/**
+ * An INCLUDE the assembler resolved
+ */
+export interface ResolvedInclude {
+  /** File containing the INCLUDE line */
+  from: string;
+  /** File name as written on the INCLUDE line */
+  name: string;
+  /** Virtual file name or disk path the include was read from */
+  path: string;
+  /** Whether it was read from the passed files or from disk */
+  origin: "virtual" | "disk";
+}
+
+/**
+ * Code assembled by `emulator_assemble_project`
+ */
+export interface ProjectAssembly {
+  /** Machine code */
+  binary: number[];
+  /** Includes in the order they were read */
+  includes: ResolvedInclude[];
+}
+
+/**
+ * A ROM image an assembled binary is padded to
+ */
+export interface RomImage {
+  /** Image size in bytes */
+  size: number;
+  /** Byte the space after the code is filled with ($FF by default) */
+  fill?: number;
+  /** Store the 16-bit sum of the other bytes in the last two, big-endian */
+  checksum?: boolean;
+}
+
+/**
+ * A section `emulator_assemble_sections` returns
+ */
+export interface AssembledSection {
+  /** Name it was declared with */
+  name: string;
+  /** What it holds; BSS is space that's cleared when the program loads */
+  kind: "code" | "data" | "bss";
+  /** Address of its first byte */
+  origin: number;
+  /** Its bytes (none for BSS) */
+  data: number[];
+  /** Size in bytes */
+  size: number;
+}
+
+/**
+ * Objects `emulator_link` linked into one program
+ */
+export interface LinkedProgram {
+  /** Machine code and data, from `origin` on */
+  binary: number[];
+  /** Address of the first byte */
+  origin: number;
+  /** Exported symbols and their addresses (or values), by name */
+  symbols: Record<string, number>;
+}
+
+/**
+ * A program `emulator_assemble_and_load` placed in RAM
+ */
+export interface LoadedProgram {
+  /** Address of the first byte */
+  loadAddress: number;
+  /** Size in bytes */
+  size: number;
+  /** Address execution started at */
+  entry: number;
+  /** Labels at their loaded addresses (plus constants when requested) */
+  symbols: Record<string, number>;
+}
+
+/**
+ * Result of checked assembly; a failure lists every error found
+ */
+export type CheckedAssemblyResult =
+  | { status: "success"; data: CheckedAssembly }
+  | (EmulatorFailure & { diagnostics: AssemblerDiagnostic[] });
+
 /**
  * Breakpoint information
  */
@@ -154,12 +154,15 @@ const DIRECTIVES = new Set([
   "DC",
   "DS",
   "EQU",
+  "EQUR",
   "SET",
   "EVEN",
   "ALIGN",
   "INCLUDE",
   "INCBIN",
   "SECTION",
+  "XDEF",
+  "XREF",
   "END",
   "MACRO",
   "ENDM",
@@ -170,9 +173,15 @@ const DIRECTIVES = new Set([
   "ENDIF",
   "IFND",
   "IFD",
+  "IFDEF",
+  "IFNDEF",
   "IFC",
   "IFNC",
   "CNOP",
+  "ROMSIZE",
+  "OPT",
+  "ASSERT",
+  "FAIL",
   "RS",
   "RSRESET",
   "RSSET",
@@ -207,6 +216,7 @@ const REGISTERS = new Set([
   "A6",
   "A7",
   "SP",
+  "FP",
   "USP",
   "SSP",
   "SR",
@@ -235,15 +245,16 @@ const m68kLanguage = StreamLanguage.define<M68kState>({
     if (stream.match(/^"[^"]*"/)) return "string";
     if (stream.match(/^'[^']*'/)) return "string";
 
-    // Hex numbers: $xxxx or 0xXXXX
-    if (stream.match(/^\$[0-9A-Fa-f]+/)) return "number";
-    if (stream.match(/^0x[0-9A-Fa-f]+/i)) return "number";
+    // Hex numbers: $xxxx or 0xXXXX, with _ separating digits
+    if (stream.match(/^\$[0-9A-Fa-f]+(_[0-9A-Fa-f]+)*/)) return "number";
+    if (stream.match(/^0x[0-9A-Fa-f]+(_[0-9A-Fa-f]+)*/i)) return "number";
 
-    // Binary numbers: %xxxx
-    if (stream.match(/^%[01]+/)) return "number";
+    // Binary numbers: %xxxx or 0bxxxx
+    if (stream.match(/^%[01]+(_[01]+)*/)) return "number";
+    if (stream.match(/^0b[01]+(_[01]+)*/i)) return "number";
 
     // Decimal numbers
-    if (stream.match(/^[0-9]+/)) return "number";
+    if (stream.match(/^[0-9]+(_[0-9]+)*/)) return "number";
 
     // Hash for immediate values
     if (stream.eat("#")) return "operator";